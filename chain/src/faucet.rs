@@ -0,0 +1,63 @@
+use penumbra_crypto::Address;
+use penumbra_proto::{chain as pb, Protobuf};
+
+/// Tokens minted by the faucet in response to a claim.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaucetDisbursement {
+    pub address: Address,
+    /// The amount of the staking token minted.
+    pub amount: u64,
+    /// The height of the block which included the claim.
+    pub height: u64,
+}
+
+impl Protobuf<pb::FaucetDisbursement> for FaucetDisbursement {}
+
+impl TryFrom<pb::FaucetDisbursement> for FaucetDisbursement {
+    type Error = anyhow::Error;
+    fn try_from(msg: pb::FaucetDisbursement) -> anyhow::Result<Self> {
+        Ok(FaucetDisbursement {
+            address: msg
+                .address
+                .ok_or_else(|| anyhow::anyhow!("missing disbursement address"))?
+                .try_into()?,
+            amount: msg.amount,
+            height: msg.height,
+        })
+    }
+}
+
+impl From<FaucetDisbursement> for pb::FaucetDisbursement {
+    fn from(disbursement: FaucetDisbursement) -> Self {
+        Self {
+            address: Some(disbursement.address.into()),
+            amount: disbursement.amount,
+            height: disbursement.height,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FaucetDisbursements(pub Vec<FaucetDisbursement>);
+
+impl Protobuf<pb::FaucetDisbursements> for FaucetDisbursements {}
+
+impl TryFrom<pb::FaucetDisbursements> for FaucetDisbursements {
+    type Error = anyhow::Error;
+    fn try_from(msg: pb::FaucetDisbursements) -> anyhow::Result<Self> {
+        Ok(FaucetDisbursements(
+            msg.disbursements
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<anyhow::Result<_>>()?,
+        ))
+    }
+}
+
+impl From<FaucetDisbursements> for pb::FaucetDisbursements {
+    fn from(disbursements: FaucetDisbursements) -> Self {
+        Self {
+            disbursements: disbursements.0.into_iter().map(Into::into).collect(),
+        }
+    }
+}
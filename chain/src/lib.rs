@@ -1,8 +1,10 @@
+mod faucet;
 mod known_assets;
 mod note_source;
 
 pub mod params;
 pub mod sync;
 
+pub use faucet::{FaucetDisbursement, FaucetDisbursements};
 pub use known_assets::KnownAssets;
 pub use note_source::NoteSource;
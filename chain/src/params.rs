@@ -40,20 +40,71 @@ impl From<AssetInfo> for pb::AssetInfo {
 #[serde(try_from = "pb::ChainParams", into = "pb::ChainParams")]
 pub struct ChainParams {
     pub chain_id: String,
+    /// The duration of each epoch, in number of blocks.
     pub epoch_duration: u64,
+    /// If nonzero, the duration of each epoch in seconds of block time, which
+    /// takes precedence over `epoch_duration`.
+    pub epoch_duration_seconds: u64,
     pub unbonding_epochs: u64,
+    /// The minimum number of epochs a validator must remain in the consensus
+    /// set after entering it, so that validators cannot cycle in and out of it.
+    pub min_validator_bond_epochs: u64,
     /// The number of validators allowed in the consensus set (Active state).
     pub active_validator_limit: u64,
     /// Slashing penalty in basis points
     pub slashing_penalty: u64,
     /// The base reward rate, expressed in basis points of basis points
     pub base_reward_rate: u64,
+    /// The fraction by which the base reward rate declines each epoch from
+    /// its value of `base_reward_rate` at epoch 0, expressed in basis points
+    /// of basis points, or 0 for a constant base reward rate.
+    pub base_reward_rate_decay: u64,
+    /// The base reward rate below which it does not decline, expressed in
+    /// basis points of basis points.
+    pub min_base_reward_rate: u64,
     /// Whether IBC (forming connections, processing IBC packets) is enabled.
     pub ibc_enabled: bool,
     /// Whether inbound ICS-20 transfers are enabled
     pub inbound_ics20_transfers_enabled: bool,
     /// Whether outbound ICS-20 transfers are enabled
     pub outbound_ics20_transfers_enabled: bool,
+    /// The experimental features enabled on the chain; see [`Self::feature_enabled`].
+    pub features: Vec<String>,
+    /// The most a single faucet claim may mint, if the `faucet` feature is enabled.
+    pub faucet_max_claim: u64,
+    /// The number of blocks an address must wait after a faucet claim before claiming again.
+    pub faucet_claim_interval: u64,
+    /// The maximum number of faucet claims included in a single block.
+    pub faucet_claims_per_block: u64,
+    /// The most stake a single validator may hold, in basis points of the
+    /// total stake, beyond which delegations to it are rejected, or 0 for no
+    /// maximum.
+    pub max_validator_stake_share: u64,
+}
+
+impl ChainParams {
+    /// Whether the experimental feature `name` is enabled at block `height`.
+    ///
+    /// A feature is enabled by listing its name in `features`, optionally
+    /// suffixed with `@<height>` to enable it only from that height on, so that
+    /// a testnet can switch it on at a coordinated height without a binary
+    /// swap. For compatibility, `ibc_enabled` also enables the `ibc` feature.
+    pub fn feature_enabled(&self, name: &str, height: u64) -> bool {
+        if name == "ibc" && self.ibc_enabled {
+            return true;
+        }
+        self.features
+            .iter()
+            .any(|feature| match feature.split_once('@') {
+                Some((feature, activation_height)) => {
+                    feature == name
+                        && activation_height
+                            .parse::<u64>()
+                            .map_or(false, |activation_height| height >= activation_height)
+                }
+                None => feature == name,
+            })
+    }
 }
 
 impl Protobuf<pb::ChainParams> for ChainParams {}
@@ -63,13 +114,22 @@ impl From<pb::ChainParams> for ChainParams {
         ChainParams {
             chain_id: msg.chain_id,
             epoch_duration: msg.epoch_duration,
+            epoch_duration_seconds: msg.epoch_duration_seconds,
             unbonding_epochs: msg.unbonding_epochs,
+            min_validator_bond_epochs: msg.min_validator_bond_epochs,
             active_validator_limit: msg.active_validator_limit,
             slashing_penalty: msg.slashing_penalty,
             base_reward_rate: msg.base_reward_rate,
+            base_reward_rate_decay: msg.base_reward_rate_decay,
+            min_base_reward_rate: msg.min_base_reward_rate,
             ibc_enabled: msg.ibc_enabled,
             inbound_ics20_transfers_enabled: msg.inbound_ics20_transfers_enabled,
             outbound_ics20_transfers_enabled: msg.outbound_ics20_transfers_enabled,
+            features: msg.features,
+            faucet_max_claim: msg.faucet_max_claim,
+            faucet_claim_interval: msg.faucet_claim_interval,
+            faucet_claims_per_block: msg.faucet_claims_per_block,
+            max_validator_stake_share: msg.max_validator_stake_share,
         }
     }
 }
@@ -79,13 +139,22 @@ impl From<ChainParams> for pb::ChainParams {
         pb::ChainParams {
             chain_id: params.chain_id,
             epoch_duration: params.epoch_duration,
+            epoch_duration_seconds: params.epoch_duration_seconds,
             unbonding_epochs: params.unbonding_epochs,
+            min_validator_bond_epochs: params.min_validator_bond_epochs,
             active_validator_limit: params.active_validator_limit,
             slashing_penalty: params.slashing_penalty,
             base_reward_rate: params.base_reward_rate,
+            base_reward_rate_decay: params.base_reward_rate_decay,
+            min_base_reward_rate: params.min_base_reward_rate,
             ibc_enabled: params.ibc_enabled,
             inbound_ics20_transfers_enabled: params.inbound_ics20_transfers_enabled,
             outbound_ics20_transfers_enabled: params.outbound_ics20_transfers_enabled,
+            features: params.features,
+            faucet_max_claim: params.faucet_max_claim,
+            faucet_claim_interval: params.faucet_claim_interval,
+            faucet_claims_per_block: params.faucet_claims_per_block,
+            max_validator_stake_share: params.max_validator_stake_share,
         }
     }
 }
@@ -97,15 +166,75 @@ impl Default for ChainParams {
         Self {
             chain_id: String::new(),
             epoch_duration: 8640,
+            epoch_duration_seconds: 0,
             unbonding_epochs: 30,
+            min_validator_bond_epochs: 0,
             active_validator_limit: 10,
             // 1000 basis points = 10%
             slashing_penalty: 1000,
             // 3bps -> 11% return over 365 epochs
             base_reward_rate: 3_0000,
+            base_reward_rate_decay: 0,
+            min_base_reward_rate: 0,
             ibc_enabled: false,
             inbound_ics20_transfers_enabled: false,
             outbound_ics20_transfers_enabled: false,
+            features: Vec::new(),
+            // 100 penumbra per claim, once a day at 10 second blocks.
+            faucet_max_claim: 100_000_000,
+            faucet_claim_interval: 8640,
+            faucet_claims_per_block: 10,
+            max_validator_stake_share: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_features(features: &[&str]) -> ChainParams {
+        ChainParams {
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+            ..Default::default()
         }
     }
+
+    #[test]
+    fn features_are_enabled_by_name() {
+        let params = with_features(&["ibc"]);
+        assert!(params.feature_enabled("ibc", 0));
+        assert!(params.feature_enabled("ibc", 1_000));
+        assert!(!params.feature_enabled("other", 0));
+        assert!(!ChainParams::default().feature_enabled("ibc", 0));
+    }
+
+    #[test]
+    fn features_are_enabled_from_their_activation_height() {
+        let params = with_features(&["ibc@100"]);
+        assert!(!params.feature_enabled("ibc", 0));
+        assert!(!params.feature_enabled("ibc", 99));
+        assert!(params.feature_enabled("ibc", 100));
+        assert!(params.feature_enabled("ibc", 101));
+
+        // A malformed activation height never enables the feature.
+        assert!(!with_features(&["ibc@soon"]).feature_enabled("ibc", u64::MAX));
+    }
+
+    #[test]
+    fn ibc_enabled_implies_the_ibc_feature() {
+        let params = ChainParams {
+            ibc_enabled: true,
+            ..Default::default()
+        };
+        assert!(params.feature_enabled("ibc", 0));
+        assert!(!params.feature_enabled("other", 0));
+    }
+
+    #[test]
+    fn features_survive_the_proto_round_trip() {
+        let params = with_features(&["ibc@100"]);
+        let proto: pb::ChainParams = params.clone().into();
+        assert_eq!(ChainParams::from(proto).features, params.features);
+    }
 }
@@ -17,6 +17,10 @@ pub struct CompactBlock {
     pub outputs: Vec<output::Body>,
     // Nullifiers identifying spent notes.
     pub nullifiers: Vec<Nullifier>,
+    // The root of the block in the tiered commitment tree.
+    pub block_root: Option<penumbra_tct::block::Root>,
+    // The root of the epoch in the tiered commitment tree, on the last block of each epoch.
+    pub epoch_root: Option<penumbra_tct::epoch::Root>,
 }
 
 impl Protobuf<pb::CompactBlock> for CompactBlock {}
@@ -31,6 +35,8 @@ impl From<CompactBlock> for pb::CompactBlock {
                 .into_iter()
                 .map(|v| Bytes::copy_from_slice(&v.0.to_bytes()))
                 .collect(),
+            block_root: cb.block_root.map(Into::into),
+            epoch_root: cb.epoch_root.map(Into::into),
         }
     }
 }
@@ -51,6 +57,8 @@ impl TryFrom<pb::CompactBlock> for CompactBlock {
                 .into_iter()
                 .map(|v| Nullifier::try_from(&*v))
                 .collect::<Result<Vec<Nullifier>>>()?,
+            block_root: value.block_root.map(TryInto::try_into).transpose()?,
+            epoch_root: value.epoch_root.map(TryInto::try_into).transpose()?,
         })
     }
 }
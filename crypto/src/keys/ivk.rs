@@ -1,3 +1,6 @@
+use std::convert::{TryFrom, TryInto};
+
+use anyhow::anyhow;
 use ark_ff::PrimeField;
 
 use super::{Diversifier, DiversifierIndex, DiversifierKey};
@@ -46,4 +49,32 @@ impl IncomingViewingKey {
     pub fn index_for_diversifier(&self, diversifier: &Diversifier) -> DiversifierIndex {
         self.dk.index_for_diversifier(diversifier)
     }
+
+    /// Encode this key as the bytes of its key agreement secret followed by
+    /// its diversifier key.
+    pub fn to_bytes(&self) -> [u8; IVK_LEN_BYTES] {
+        let mut bytes = [0u8; IVK_LEN_BYTES];
+        bytes[0..32].copy_from_slice(&self.ivk.to_bytes());
+        bytes[32..64].copy_from_slice(&self.dk.0);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for IncomingViewingKey {
+    type Error = anyhow::Error;
+
+    fn try_from(slice: &[u8]) -> Result<IncomingViewingKey, Self::Error> {
+        if slice.len() != IVK_LEN_BYTES {
+            return Err(anyhow!(
+                "incoming viewing key must be {} bytes, got {:?}",
+                IVK_LEN_BYTES,
+                slice.len()
+            ));
+        }
+
+        let ivk = ka::Secret::try_from(&slice[0..32])
+            .map_err(|_| anyhow!("invalid incoming viewing key"))?;
+        let dk = DiversifierKey(slice[32..64].try_into().expect("slice is 32 bytes"));
+        Ok(IncomingViewingKey { ivk, dk })
+    }
 }
@@ -0,0 +1,53 @@
+use std::io::{self, Write as _};
+
+use anyhow::Result;
+use structopt::StructOpt;
+
+use crate::Wallet;
+
+mod addresses;
+mod balance;
+mod delegate;
+mod history;
+mod send;
+
+pub use addresses::AddressesCmd;
+pub use balance::BalanceCmd;
+pub use delegate::DelegateCmd;
+pub use history::HistoryCmd;
+pub use send::SendCmd;
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Displays the current wallet balance.
+    Balance(BalanceCmd),
+    /// Sends funds to an address.
+    Send(SendCmd),
+    /// Delegates stake to a validator.
+    Delegate(DelegateCmd),
+    /// Exports the wallet's history of balance changes.
+    History(HistoryCmd),
+    /// Lists the wallet's addresses.
+    Addresses(AddressesCmd),
+}
+
+impl Command {
+    pub async fn exec(&self, wallet: &mut Wallet) -> Result<()> {
+        match self {
+            Command::Balance(cmd) => cmd.exec(wallet).await,
+            Command::Send(cmd) => cmd.exec(wallet).await,
+            Command::Delegate(cmd) => cmd.exec(wallet).await,
+            Command::History(cmd) => cmd.exec(wallet).await,
+            Command::Addresses(cmd) => cmd.exec(wallet).await,
+        }
+    }
+}
+
+/// Prompt for the wallet passphrase on the terminal.
+fn read_passphrase() -> Result<String> {
+    print!("Wallet passphrase: ");
+    io::stdout().flush()?;
+    let mut passphrase = String::new();
+    io::stdin().read_line(&mut passphrase)?;
+    Ok(passphrase.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
@@ -0,0 +1,50 @@
+use anyhow::Result;
+use comfy_table::{presets, Table};
+use penumbra_proto::wallet_next::AddressesRequest;
+use structopt::StructOpt;
+
+use super::read_passphrase;
+use crate::Wallet;
+
+#[derive(Debug, StructOpt)]
+pub struct AddressesCmd {
+    /// The index of the first address to list.
+    #[structopt(long, default_value = "0")]
+    pub start: u64,
+    /// The number of addresses to list.
+    #[structopt(long, default_value = "1")]
+    pub count: u64,
+    /// Emit only the addresses, one per line, without their indices.
+    #[structopt(long)]
+    pub addr_only: bool,
+}
+
+impl AddressesCmd {
+    pub async fn exec(&self, wallet: &mut Wallet) -> Result<()> {
+        let addresses = wallet
+            .addresses(AddressesRequest {
+                passphrase: read_passphrase()?,
+                start_index: self.start,
+                count: self.count,
+            })
+            .await?
+            .addresses;
+
+        if self.addr_only {
+            for address in addresses {
+                println!("{}", address.address);
+            }
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table.load_preset(presets::NOTHING);
+        table.set_header(vec!["Index", "Address"]);
+        for address in addresses {
+            table.add_row(vec![address.index.to_string(), address.address]);
+        }
+        println!("{}", table);
+
+        Ok(())
+    }
+}
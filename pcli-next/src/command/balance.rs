@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Result};
+use comfy_table::{presets, Table};
+use penumbra_crypto::asset::REGISTRY;
+use penumbra_proto::wallet_next::BalanceRequest;
+use penumbra_wallet_next::Formatter;
+use structopt::StructOpt;
+
+use crate::Wallet;
+
+#[derive(Debug, StructOpt)]
+pub struct BalanceCmd {
+    /// Show amounts in base units, rather than in each asset's display unit.
+    #[structopt(long)]
+    pub base_units: bool,
+}
+
+impl BalanceCmd {
+    pub async fn exec(&self, wallet: &mut Wallet) -> Result<()> {
+        let balances = wallet.balance(BalanceRequest {}).await?.balances;
+
+        let formatter = Formatter::default();
+        let mut table = Table::new();
+        table.load_preset(presets::NOTHING);
+        table.set_header(vec!["Amount"]);
+        for balance in balances {
+            let denom = REGISTRY
+                .parse_denom(&balance.denom)
+                .ok_or_else(|| anyhow!("pwalletd reported an invalid denom {:?}", balance.denom))?;
+            let unit = if self.base_units {
+                denom.base_unit()
+            } else {
+                denom.default_unit()
+            };
+            table.add_row(vec![formatter.format_in_unit(balance.amount, &unit)]);
+        }
+        println!("{}", table);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use penumbra_proto::wallet_next::SpendRequest;
+use penumbra_stake::{IdentityKey, STAKING_TOKEN_DENOM};
+use penumbra_wallet_next::Formatter;
+use structopt::StructOpt;
+
+use super::send::authorize_and_submit;
+use crate::Wallet;
+
+#[derive(Debug, StructOpt)]
+pub struct DelegateCmd {
+    /// The identity key of the validator to delegate to.
+    #[structopt(long)]
+    pub to: String,
+    /// The amount of stake to delegate, e.g. `10penumbra`.
+    pub amount: String,
+    /// The account to delegate the stake from.
+    #[structopt(long, default_value = "0")]
+    pub account: u64,
+    /// The file holding the encoded, signed transaction making this delegation.
+    #[structopt(long, parse(from_os_str))]
+    pub transaction: PathBuf,
+}
+
+impl DelegateCmd {
+    pub async fn exec(&self, wallet: &mut Wallet) -> Result<()> {
+        self.to
+            .parse::<IdentityKey>()
+            .map_err(|_| anyhow!("invalid validator identity key {:?}", self.to))?;
+        let (amount, denom) = Formatter::default().parse_denominated(&self.amount)?;
+        if denom != *STAKING_TOKEN_DENOM {
+            return Err(anyhow!("staking can only be done with the staking token"));
+        }
+
+        // Delegated stake leaves the wallet's spendable balance, so it is
+        // subject to the same spending policy as a send, to the validator.
+        let spend = SpendRequest {
+            account: self.account,
+            destination: self.to.clone(),
+            denom: denom.to_string(),
+            amount,
+        };
+        authorize_and_submit(wallet, spend, &self.transaction).await
+    }
+}
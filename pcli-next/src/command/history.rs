@@ -0,0 +1,47 @@
+use anyhow::{anyhow, Result};
+use penumbra_proto::wallet_next::{export_history_request::Format, ExportHistoryRequest};
+use structopt::StructOpt;
+
+use crate::Wallet;
+
+#[derive(Debug, StructOpt)]
+pub struct HistoryCmd {
+    /// The format to export in: csv, ofx, or json.
+    #[structopt(long, default_value = "csv", parse(try_from_str = parse_format))]
+    pub format: Format,
+    /// The first block height to export.
+    #[structopt(long, default_value = "0")]
+    pub start_height: u64,
+    /// The last block height to export [default: the latest height]
+    #[structopt(long)]
+    pub end_height: Option<u64>,
+}
+
+fn parse_format(format: &str) -> Result<Format> {
+    match format {
+        "csv" => Ok(Format::Csv),
+        "ofx" => Ok(Format::Ofx),
+        "json" => Ok(Format::Json),
+        _ => Err(anyhow!(
+            "invalid export format {:?}, expected csv, ofx, or json",
+            format
+        )),
+    }
+}
+
+impl HistoryCmd {
+    pub async fn exec(&self, wallet: &mut Wallet) -> Result<()> {
+        let contents = wallet
+            .export_history(ExportHistoryRequest {
+                format: self.format as i32,
+                start_height: self.start_height,
+                // Zero exports through the latest height.
+                end_height: self.end_height.unwrap_or_default(),
+            })
+            .await?
+            .contents;
+        print!("{}", contents);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use penumbra_crypto::Address;
+use penumbra_proto::wallet_next::{
+    authorize_spend_response::Status, AuthorizeSpendRequest, SpendRequest, SubmitTransactionRequest,
+};
+use penumbra_wallet_next::Formatter;
+use structopt::StructOpt;
+
+use crate::Wallet;
+
+#[derive(Debug, StructOpt)]
+pub struct SendCmd {
+    /// The address to send the funds to.
+    #[structopt(long)]
+    pub to: String,
+    /// The amount to send, e.g. `1.5penumbra`.
+    pub amount: String,
+    /// The account to spend the funds from.
+    #[structopt(long, default_value = "0")]
+    pub account: u64,
+    /// The file holding the encoded, signed transaction making this spend.
+    #[structopt(long, parse(from_os_str))]
+    pub transaction: PathBuf,
+}
+
+impl SendCmd {
+    pub async fn exec(&self, wallet: &mut Wallet) -> Result<()> {
+        self.to
+            .parse::<Address>()
+            .map_err(|_| anyhow!("invalid destination address {:?}", self.to))?;
+        let (amount, denom) = Formatter::default().parse_denominated(&self.amount)?;
+
+        let spend = SpendRequest {
+            account: self.account,
+            destination: self.to.clone(),
+            denom: denom.to_string(),
+            amount,
+        };
+        authorize_and_submit(wallet, spend, &self.transaction).await
+    }
+}
+
+/// Check a spend against the wallet's spending policy, and if it may be
+/// signed, queue the transaction making it for broadcast.
+pub(super) async fn authorize_and_submit(
+    wallet: &mut Wallet,
+    spend: SpendRequest,
+    transaction: &Path,
+) -> Result<()> {
+    let transaction = std::fs::read(transaction)?;
+
+    let authorization = wallet
+        .authorize_spend(AuthorizeSpendRequest {
+            spend: Some(spend),
+            ..Default::default()
+        })
+        .await?;
+    match Status::from_i32(authorization.status) {
+        Some(Status::Approved) => {}
+        Some(Status::Pending) => {
+            println!(
+                "The spend is held for approval as #{}: {}",
+                authorization.pending_id, authorization.reason
+            );
+            return Ok(());
+        }
+        Some(Status::Denied) => {
+            return Err(anyhow!(
+                "the spend was denied by the wallet's spending policy: {}",
+                authorization.reason
+            ))
+        }
+        None => return Err(anyhow!("pwalletd returned an unknown authorization status")),
+    }
+
+    let tx_hash = wallet
+        .submit_transaction(SubmitTransactionRequest { transaction })
+        .await?
+        .tx_hash;
+    println!("Queued transaction {} for broadcast.", hex::encode(tx_hash));
+    if wallet.is_in_process() {
+        println!("It will be broadcast the next time pwalletd runs.");
+    }
+
+    Ok(())
+}
@@ -1,7 +1,50 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use structopt::StructOpt;
+
+mod command;
+mod wallet;
+
+use command::Command;
+use wallet::Wallet;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "pcli-next",
+    about = "The Penumbra command-line wallet, a client of the pwalletd wallet daemon.",
+    version = env!("VERGEN_GIT_SEMVER"),
+)]
+pub struct Opt {
+    /// The URL of the pwalletd RPC service.
+    #[structopt(long, default_value = "http://127.0.0.1:8081")]
+    pub wallet: String,
+    /// Open the wallet database directly, rather than connecting to pwalletd,
+    /// for users who do not run the daemon.
+    ///
+    /// Transactions sent in-process are broadcast the next time pwalletd runs.
+    #[structopt(long)]
+    pub in_process: bool,
+    /// The wallet database to open with `--in-process` [default: $DATABASE_URL]
+    #[structopt(long)]
+    pub database_url: Option<String>,
+    #[structopt(subcommand)]
+    pub cmd: Command,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    println!("Hello, pcli!");
-    Ok(())
+    tracing_subscriber::fmt::init();
+    let opt = Opt::from_args();
+
+    let mut wallet = if opt.in_process {
+        let database_url = match opt.database_url {
+            Some(database_url) => database_url,
+            None => std::env::var("DATABASE_URL")
+                .map_err(|_| anyhow!("--in-process requires --database-url or DATABASE_URL"))?,
+        };
+        Wallet::open(&database_url).await?
+    } else {
+        Wallet::connect(opt.wallet).await?
+    };
+
+    opt.cmd.exec(&mut wallet).await
 }
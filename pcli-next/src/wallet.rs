@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use penumbra_proto::wallet_next::{
+    wallet_service_client::WalletServiceClient,
+    wallet_service_server::WalletService as WalletServiceRpc, AddressesRequest, AddressesResponse,
+    AuthorizeSpendRequest, AuthorizeSpendResponse, BalanceRequest, BalanceResponse,
+    ExportHistoryRequest, ExportHistoryResponse, SubmitTransactionRequest,
+    SubmitTransactionResponse,
+};
+use penumbra_wallet_next::{keystore, WalletService, MIGRATOR};
+use sqlx::sqlite::SqlitePool;
+use tonic::{transport::Channel, Request, Status};
+
+/// The wallet the CLI's commands are run against.
+///
+/// Every command is a call to the `pwalletd` RPC service, either over the
+/// network to a running daemon, or directly to the service, opened in this
+/// process over the wallet database, so that the CLI has no wallet logic of
+/// its own.
+pub enum Wallet {
+    Daemon(WalletServiceClient<Channel>),
+    InProcess(WalletService),
+}
+
+impl Wallet {
+    /// Connect to the `pwalletd` serving at `url`.
+    pub async fn connect(url: String) -> Result<Self> {
+        let client = WalletServiceClient::connect(url.clone())
+            .await
+            .map_err(|e| anyhow!("could not connect to pwalletd at {}: {}", url, e))?;
+        Ok(Wallet::Daemon(client))
+    }
+
+    /// Open the wallet database at `database_url` in this process.
+    ///
+    /// Only the RPC service runs in-process: the daemon's background tasks do
+    /// not, so submitted transactions stay queued until `pwalletd` is next
+    /// run over the same database.
+    pub async fn open(database_url: &str) -> Result<Self> {
+        let pool = SqlitePool::connect(database_url).await?;
+        MIGRATOR.run(&pool).await?;
+        // Finish any passphrase rotation interrupted by an earlier process.
+        keystore::complete_rotation(&pool).await?;
+        Ok(Wallet::InProcess(WalletService::new(pool)))
+    }
+
+    pub fn is_in_process(&self) -> bool {
+        matches!(self, Wallet::InProcess(_))
+    }
+}
+
+/// Defines a method of [`Wallet`] for each RPC, dispatching to the daemon or
+/// to the in-process service.
+macro_rules! rpcs {
+    ($($method:ident($request:ty) -> $response:ty;)*) => {
+        impl Wallet {
+            $(
+                pub async fn $method(&mut self, request: $request) -> Result<$response, Status> {
+                    let response = match self {
+                        Wallet::Daemon(client) => client.$method(request).await?,
+                        Wallet::InProcess(service) => {
+                            WalletServiceRpc::$method(service, Request::new(request)).await?
+                        }
+                    };
+                    Ok(response.into_inner())
+                }
+            )*
+        }
+    };
+}
+
+rpcs! {
+    balance(BalanceRequest) -> BalanceResponse;
+    addresses(AddressesRequest) -> AddressesResponse;
+    export_history(ExportHistoryRequest) -> ExportHistoryResponse;
+    authorize_spend(AuthorizeSpendRequest) -> AuthorizeSpendResponse;
+    submit_transaction(SubmitTransactionRequest) -> SubmitTransactionResponse;
+}
@@ -4,7 +4,7 @@ use anyhow::{anyhow, Context, Result};
 use comfy_table::{presets, Table};
 use futures::stream::TryStreamExt;
 use penumbra_crypto::Value;
-use penumbra_proto::client::oblivious::ValidatorInfoRequest;
+use penumbra_proto::client::{oblivious::ValidatorInfoRequest, specific::NextValidatorRateRequest};
 use penumbra_stake::{
     DelegationToken, IdentityKey, RateData, ValidatorInfo, STAKING_TOKEN_ASSET_ID,
     STAKING_TOKEN_DENOM,
@@ -96,7 +96,10 @@ impl StakeCmd {
 
                 let mut client = opt.specific_client().await?;
                 let rate_data: RateData = client
-                    .next_validator_rate(tonic::Request::new(to.into()))
+                    .next_validator_rate(NextValidatorRateRequest {
+                        chain_id: state.chain_id().unwrap_or_default(),
+                        identity_key: Some(to.into()),
+                    })
                     .await?
                     .into_inner()
                     .try_into()?;
@@ -131,7 +134,10 @@ impl StakeCmd {
 
                 let mut client = opt.specific_client().await?;
                 let rate_data: RateData = client
-                    .next_validator_rate(tonic::Request::new(from.into()))
+                    .next_validator_rate(NextValidatorRateRequest {
+                        chain_id: state.chain_id().unwrap_or_default(),
+                        identity_key: Some(from.into()),
+                    })
                     .await?
                     .into_inner()
                     .try_into()?;
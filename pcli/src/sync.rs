@@ -1,34 +1,75 @@
+use std::time::Duration;
+
 use anyhow::Result;
-use penumbra_proto::client::oblivious::CompactBlockRangeRequest;
+use penumbra_proto::{
+    client::oblivious::{CompactBlockRangeInterrupted, CompactBlockRangeRequest},
+    Message,
+};
 use tracing::instrument;
 
 use crate::{ClientStateFile, Opt};
 
+/// How many times to reconnect when the node interrupts the compact block
+/// stream, e.g. because it is draining connections before a restart.
+const MAX_RESUMES: usize = 5;
+
 #[instrument(skip(opt, state), fields(start_height = state.last_block_height()))]
 pub async fn sync(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
     tracing::info!("starting client sync");
-    let mut client = opt.oblivious_client().await?;
-
-    let start_height = state.last_block_height().map(|h| h + 1).unwrap_or(0);
-    let mut stream = client
-        .compact_block_range(tonic::Request::new(CompactBlockRangeRequest {
-            start_height,
-            end_height: 0,
-            chain_id: state
-                .chain_id()
-                .ok_or_else(|| anyhow::anyhow!("missing chain_id"))?,
-        }))
-        .await?
-        .into_inner();
 
     let mut count = 0;
-    while let Some(block) = stream.message().await? {
-        state.scan_block(block.try_into()?)?;
-        // very basic form of intermediate checkpointing
-        count += 1;
-        if count % 1000 == 1 {
-            state.commit()?;
-            tracing::info!(height = ?state.last_block_height().unwrap(), "syncing...");
+    let mut resumes = 0;
+    loop {
+        let mut client = opt.oblivious_client().await?;
+
+        let start_height = state.last_block_height().map(|h| h + 1).unwrap_or(0);
+        let mut stream = client
+            .compact_block_range(tonic::Request::new(CompactBlockRangeRequest {
+                start_height,
+                end_height: 0,
+                chain_id: state
+                    .chain_id()
+                    .ok_or_else(|| anyhow::anyhow!("missing chain_id"))?,
+            }))
+            .await?
+            .into_inner();
+
+        let interrupted = loop {
+            match stream.message().await {
+                Ok(Some(block)) => {
+                    state.scan_block(block.try_into()?)?;
+                    // very basic form of intermediate checkpointing
+                    count += 1;
+                    if count % 1000 == 1 {
+                        state.commit()?;
+                        tracing::info!(height = ?state.last_block_height().unwrap(), "syncing...");
+                    }
+                }
+                Ok(None) => break None,
+                Err(status) => match interruption(&status) {
+                    Some(interrupted) => break Some(interrupted),
+                    None => return Err(status.into()),
+                },
+            }
+        };
+
+        match interrupted {
+            None => break,
+            Some(interrupted) if resumes < MAX_RESUMES => {
+                resumes += 1;
+                state.commit()?;
+                tracing::info!(
+                    resume_height = interrupted.resume_height,
+                    "node interrupted sync, reconnecting"
+                );
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Some(_) => {
+                return Err(anyhow::anyhow!(
+                    "node interrupted sync {} times, giving up",
+                    resumes + 1
+                ))
+            }
         }
     }
 
@@ -37,3 +78,12 @@ pub async fn sync(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
     tracing::info!(end_height = ?state.last_block_height().unwrap(), "finished sync");
     Ok(())
 }
+
+/// Whether `status` ended the stream early but resumably, and if so, where to
+/// resume from.
+fn interruption(status: &tonic::Status) -> Option<CompactBlockRangeInterrupted> {
+    if status.code() != tonic::Code::Unavailable || status.details().is_empty() {
+        return None;
+    }
+    CompactBlockRangeInterrupted::decode(status.details()).ok()
+}
@@ -0,0 +1,277 @@
+//! Serving ABCI on a Unix domain socket.
+//!
+//! tower-abci only listens on TCP, so [`listen`] drives the same services over
+//! a [`UnixListener`] instead. Each connection carries Tendermint's socket
+//! protocol: protobuf-encoded requests and responses, each preceded by its
+//! length as a varint. Requests are answered one at a time, in order, which is
+//! all that protocol requires.
+
+use std::{io::ErrorKind, os::unix::fs::FileTypeExt, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use tendermint::abci::{
+    request::Request, response::Response, ConsensusRequest, ConsensusResponse, InfoRequest,
+    InfoResponse, MempoolRequest, MempoolResponse, MethodKind, SnapshotRequest, SnapshotResponse,
+};
+use tendermint_proto::Protobuf;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::UnixListener,
+};
+use tower::{Service, ServiceExt};
+use tower_abci::BoxError;
+
+/// The largest message accepted from Tendermint, which is well above its
+/// largest block.
+const MAX_MESSAGE_LEN: u64 = 128 * 1024 * 1024;
+
+/// Serves ABCI with the given services on a Unix domain socket at `path`,
+/// until accepting a connection fails.
+///
+/// A socket left behind at `path` by a previous listener is removed, but
+/// anything else there is an error rather than being replaced.
+pub async fn listen<C, M, I, S>(
+    path: impl AsRef<Path>,
+    consensus: C,
+    mempool: M,
+    info: I,
+    snapshot: S,
+) -> Result<()>
+where
+    C: Service<ConsensusRequest, Response = ConsensusResponse, Error = BoxError>
+        + Clone
+        + Send
+        + 'static,
+    C::Future: Send,
+    M: Service<MempoolRequest, Response = MempoolResponse, Error = BoxError>
+        + Clone
+        + Send
+        + 'static,
+    M::Future: Send,
+    I: Service<InfoRequest, Response = InfoResponse, Error = BoxError> + Clone + Send + 'static,
+    I::Future: Send,
+    S: Service<SnapshotRequest, Response = SnapshotResponse, Error = BoxError>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    let path = path.as_ref();
+    remove_stale_socket(path)?;
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("cannot bind to {:?}", path))?;
+    tracing::info!(?path, "serving ABCI on a Unix domain socket");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let connection = Connection {
+            consensus: consensus.clone(),
+            mempool: mempool.clone(),
+            info: info.clone(),
+            snapshot: snapshot.clone(),
+        };
+        tokio::spawn(async move {
+            if let Err(error) = connection.run(stream).await {
+                tracing::warn!(%error, "ABCI connection failed");
+            }
+        });
+    }
+}
+
+/// Removes the socket at `path`, if there is one.
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("cannot inspect {:?}", path)),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(anyhow!(
+            "{:?} already exists and is not a socket; refusing to replace it",
+            path
+        ));
+    }
+    std::fs::remove_file(path).with_context(|| format!("cannot remove stale socket {:?}", path))
+}
+
+/// The services answering the requests of one connection.
+struct Connection<C, M, I, S> {
+    consensus: C,
+    mempool: M,
+    info: I,
+    snapshot: S,
+}
+
+impl<C, M, I, S> Connection<C, M, I, S>
+where
+    C: Service<ConsensusRequest, Response = ConsensusResponse, Error = BoxError>,
+    M: Service<MempoolRequest, Response = MempoolResponse, Error = BoxError>,
+    I: Service<InfoRequest, Response = InfoResponse, Error = BoxError>,
+    S: Service<SnapshotRequest, Response = SnapshotResponse, Error = BoxError>,
+{
+    /// Answers requests until the peer hangs up.
+    async fn run(mut self, stream: impl AsyncRead + AsyncWrite) -> Result<()> {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        while let Some(request) = read_request(&mut reader).await? {
+            let response = self.call(request).await.map_err(|e| anyhow!(e))?;
+            let mut buf = Vec::new();
+            response.encode_length_delimited(&mut buf)?;
+            writer.write_all(&buf).await?;
+            writer.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn call(&mut self, request: Request) -> Result<Response, BoxError> {
+        Ok(match request.kind() {
+            MethodKind::Consensus => {
+                let request = request.try_into().expect("checked kind");
+                let response = self.consensus.ready().await?.call(request).await?;
+                response.into()
+            }
+            MethodKind::Mempool => {
+                let request = request.try_into().expect("checked kind");
+                let response = self.mempool.ready().await?.call(request).await?;
+                response.into()
+            }
+            MethodKind::Info => {
+                let request = request.try_into().expect("checked kind");
+                let response = self.info.ready().await?.call(request).await?;
+                response.into()
+            }
+            MethodKind::Snapshot => {
+                let request = request.try_into().expect("checked kind");
+                let response = self.snapshot.ready().await?.call(request).await?;
+                response.into()
+            }
+            // Every earlier response has already been written.
+            MethodKind::Flush => Response::Flush,
+        })
+    }
+}
+
+/// Reads the next length-prefixed request, or `None` if the peer hung up
+/// between requests.
+async fn read_request(reader: &mut (impl AsyncRead + Unpin)) -> Result<Option<Request>> {
+    let mut len = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = match reader.read_u8().await {
+            Ok(byte) => byte,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof && shift == 0 => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        len |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            if len > MAX_MESSAGE_LEN {
+                return Err(anyhow!("request of {} bytes is too large", len));
+            }
+            let mut buf = vec![0; len as usize];
+            reader.read_exact(&mut buf).await?;
+            return Ok(Some(Request::decode_vec(&buf)?));
+        }
+    }
+    Err(anyhow!("request length is not a valid varint"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{self, Ready},
+        path::PathBuf,
+    };
+
+    use tempfile::TempDir;
+    use tendermint::abci::request;
+    use tokio::net::UnixStream;
+    use tower::util::ServiceFn;
+
+    use super::*;
+    use crate::{testing::Node, Info};
+
+    /// A service failing every request.
+    #[allow(clippy::type_complexity)]
+    fn failing<Req, Rsp>() -> ServiceFn<fn(Req) -> Ready<Result<Rsp, BoxError>>> {
+        let fail: fn(Req) -> Ready<Result<Rsp, BoxError>> =
+            |_| future::ready(Err("unexpected request".into()));
+        tower::service_fn(fail)
+    }
+
+    fn socket_path(dir: &TempDir) -> PathBuf {
+        dir.path().join("abci.sock")
+    }
+
+    async fn send(stream: &mut UnixStream, request: Request) -> Result<()> {
+        let mut buf = Vec::new();
+        request.encode_length_delimited(&mut buf)?;
+        stream.write_all(&buf).await?;
+        Ok(())
+    }
+
+    async fn receive(stream: &mut UnixStream) -> Result<Response> {
+        let len = stream.read_u8().await?;
+        assert!(len < 0x80, "the test's responses are short");
+        let mut buf = vec![0; len as usize];
+        stream.read_exact(&mut buf).await?;
+        Ok(Response::decode_vec(&buf)?)
+    }
+
+    #[tokio::test]
+    async fn serves_abci_over_a_socket() -> Result<()> {
+        let node = Node::start(Default::default()).await?;
+        let dir = tempfile::tempdir()?;
+        let path = socket_path(&dir);
+        // A socket left behind by an earlier listener is replaced.
+        std::os::unix::net::UnixListener::bind(&path)?;
+
+        let server = tokio::spawn(listen(
+            path.clone(),
+            failing::<ConsensusRequest, ConsensusResponse>(),
+            failing::<MempoolRequest, MempoolResponse>(),
+            Info::new(node.storage().clone()),
+            failing::<SnapshotRequest, SnapshotResponse>(),
+        ));
+        let mut stream = loop {
+            match UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+
+        let message = "hello".to_string();
+        send(&mut stream, Request::Echo(request::Echo { message })).await?;
+        send(&mut stream, Request::Flush).await?;
+        match receive(&mut stream).await? {
+            Response::Echo(echo) => assert_eq!(echo.message, "hello"),
+            other => panic!("unexpected response {:?}", other),
+        }
+        assert!(matches!(receive(&mut stream).await?, Response::Flush));
+
+        // A failing service closes the connection, but not the listener.
+        send(&mut stream, Request::Commit).await?;
+        assert_eq!(stream.read(&mut [0; 1]).await?, 0);
+        let mut stream = UnixStream::connect(&path).await?;
+        send(&mut stream, Request::Flush).await?;
+        assert!(matches!(receive(&mut stream).await?, Response::Flush));
+
+        server.abort();
+        Ok(())
+    }
+
+    #[test]
+    fn only_sockets_are_replaced() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = socket_path(&dir);
+        remove_stale_socket(&path)?;
+
+        std::fs::write(&path, "not a socket")?;
+        assert!(remove_stale_socket(&path).is_err());
+        assert_eq!(std::fs::read_to_string(&path)?, "not a socket");
+
+        std::fs::remove_file(&path)?;
+        std::os::unix::net::UnixListener::bind(&path)?;
+        remove_stale_socket(&path)?;
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+//! Export of the current validator set for use by monitoring stacks.
+//!
+//! This is used by `pd validators export`, so that scrape targets and
+//! dashboards can be generated from chain state rather than maintained by hand.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tendermint::account;
+
+use crate::{components::staking::View as _, Storage};
+
+/// The output format of a validator export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A Prometheus `file_sd_configs` target list.
+    PrometheusTargets,
+    /// A JSON array of [`Entry`]s.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "prometheus-targets" => Ok(Format::PrometheusTargets),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow!(
+                "invalid export format {:?}, expected prometheus-targets or json",
+                s
+            )),
+        }
+    }
+}
+
+/// A single validator's entry in the address book.
+#[derive(Clone, Debug, Serialize)]
+pub struct Entry {
+    /// The bech32-encoded identity key.
+    pub identity_key: String,
+    /// The Tendermint address derived from the consensus key, as used to label
+    /// validators in Tendermint's own metrics.
+    pub consensus_address: String,
+    pub name: String,
+    pub website: String,
+    pub description: String,
+    /// The name of the validator's state, e.g. `ACTIVE`.
+    pub state: &'static str,
+    pub voting_power: u64,
+}
+
+/// Reads the address book entries for every known validator from the latest state.
+pub async fn entries(storage: &Storage) -> Result<Vec<Entry>> {
+    let overlay = storage.overlay().await?;
+
+    let mut entries = Vec::new();
+    for identity_key in overlay.validator_list().await? {
+        let info = overlay
+            .validator_info(&identity_key)
+            .await?
+            .ok_or_else(|| anyhow!("validator {} is listed but missing", identity_key))?;
+        let validator = info.validator;
+
+        entries.push(Entry {
+            identity_key: identity_key.to_string(),
+            consensus_address: account::Id::from(validator.consensus_key).to_string(),
+            name: validator.name,
+            website: validator.website,
+            description: validator.description,
+            state: info.status.state.name().to_str(),
+            voting_power: info.status.voting_power,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Renders the entries in the given format.
+///
+/// Prometheus targets are derived from the host of each validator's website,
+/// combined with `target_port`, since the chain does not record validators'
+/// network addresses; validators without a website are omitted from the
+/// target list.
+pub fn render(entries: &[Entry], format: Format, target_port: u16) -> Result<String> {
+    let value = match format {
+        Format::Json => serde_json::to_value(entries)?,
+        Format::PrometheusTargets => Value::Array(
+            entries
+                .iter()
+                .filter_map(|entry| {
+                    let host = website_host(&entry.website)?;
+                    Some(json!({
+                        "targets": [format!("{}:{}", host, target_port)],
+                        "labels": {
+                            "identity_key": entry.identity_key,
+                            "validator_address": entry.consensus_address,
+                            "name": entry.name,
+                            "state": entry.state,
+                        },
+                    }))
+                })
+                .collect(),
+        ),
+    };
+
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+/// Extracts the host from a website URL such as `https://example.com:8080/about`.
+fn website_host(website: &str) -> Option<&str> {
+    let without_scheme = website
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(website);
+    let host = without_scheme
+        .split(|c| c == '/' || c == ':' || c == '?' || c == '#')
+        .next()?;
+
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        genesis,
+        testing::{address, delegations, validator, Node},
+    };
+
+    fn entry(name: &str, website: &str) -> Entry {
+        Entry {
+            identity_key: format!("penumbravalid1{}", name),
+            consensus_address: format!("{}0000", name),
+            name: name.to_string(),
+            website: website.to_string(),
+            description: String::new(),
+            state: "ACTIVE",
+            voting_power: 1,
+        }
+    }
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!(
+            "prometheus-targets".parse::<Format>().unwrap(),
+            Format::PrometheusTargets
+        );
+        assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+        assert!("yaml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn extracts_website_hosts() {
+        assert_eq!(
+            website_host("https://example.com:8080/about"),
+            Some("example.com")
+        );
+        assert_eq!(website_host("example.com/about"), Some("example.com"));
+        assert_eq!(website_host("http://example.com?a=b"), Some("example.com"));
+        assert_eq!(website_host("http://example.com#top"), Some("example.com"));
+        assert_eq!(website_host(""), None);
+        assert_eq!(website_host("https://"), None);
+    }
+
+    #[test]
+    fn prometheus_targets_skip_validators_without_a_website() {
+        let entries = [entry("a", "https://a.example/"), entry("b", "")];
+        let targets: Value =
+            serde_json::from_str(&render(&entries, Format::PrometheusTargets, 26660).unwrap())
+                .unwrap();
+
+        assert_eq!(
+            targets,
+            json!([{
+                "targets": ["a.example:26660"],
+                "labels": {
+                    "identity_key": "penumbravalid1a",
+                    "validator_address": "a0000",
+                    "name": "a",
+                    "state": "ACTIVE",
+                },
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn exports_every_genesis_validator() -> Result<()> {
+        let validators = [validator("a"), validator("b")];
+        let node = Node::start(genesis::AppState {
+            allocations: delegations(&validators, 1_000_000, address()),
+            validators: validators.to_vec(),
+            ..Default::default()
+        })
+        .await?;
+
+        let entries = entries(node.storage()).await?;
+        assert_eq!(entries.len(), 2);
+        for validator in &validators {
+            let entry = entries
+                .iter()
+                .find(|entry| entry.identity_key == validator.identity_key.to_string())
+                .unwrap();
+            assert_eq!(entry.name, validator.name);
+            assert_eq!(
+                entry.consensus_address,
+                account::Id::from(validator.consensus_key).to_string()
+            );
+            assert_eq!(entry.state, "ACTIVE");
+            assert!(entry.voting_power > 0);
+        }
+
+        // Every entry is exported as JSON.
+        let json: Value = serde_json::from_str(&render(&entries, Format::Json, 0)?)?;
+        assert_eq!(json.as_array().unwrap().len(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,391 @@
+use std::{path::PathBuf, sync::Arc, time::UNIX_EPOCH};
+
+use metrics_exporter_prometheus::PrometheusHandle;
+use penumbra_proto::admin::{
+    admin_server::Admin as AdminService, DumpMetricsRequest, DumpMetricsResponse,
+    FlushCachesRequest, FlushCachesResponse, ListPendingTransactionsRequest,
+    ListPendingTransactionsResponse, PendingTransaction, SetLogFilterRequest, SetLogFilterResponse,
+    ShutdownRequest, ShutdownResponse, TriggerSnapshotRequest, TriggerSnapshotResponse,
+};
+use tokio::sync::watch;
+use tonic::{metadata::MetadataValue, Request, Response, Status};
+use tracing::instrument;
+
+use crate::{staking_export::DocumentCache, PendingTx, Storage};
+
+/// A callback that replaces the active tracing filter with new directives.
+pub type LogFilterReloader = Arc<dyn Fn(&str) -> anyhow::Result<()> + Send + Sync>;
+
+/// The admin gRPC service, used to operate a running `pd` without shell access.
+///
+/// This should be served behind [`Admin::interceptor`], so that every request is
+/// checked against the operator's bearer token.
+#[derive(Clone)]
+pub struct Admin {
+    storage: Storage,
+    reload_log_filter: LogFilterReloader,
+    metrics: PrometheusHandle,
+    shutdown_tx: watch::Sender<bool>,
+    pending_rx: watch::Receiver<Vec<PendingTx>>,
+    export_cache: DocumentCache,
+}
+
+impl Admin {
+    /// Creates a new admin service.
+    ///
+    /// A `Shutdown` request sets the value on the other end of `shutdown_tx` to
+    /// `true`; the caller is responsible for watching it and stopping the node.
+    /// `pending_rx` should come from [`Mempool::pending_txs`](crate::Mempool::pending_txs),
+    /// and `export_cache` should be the one the staking export is served with.
+    pub fn new(
+        storage: Storage,
+        reload_log_filter: LogFilterReloader,
+        metrics: PrometheusHandle,
+        shutdown_tx: watch::Sender<bool>,
+        pending_rx: watch::Receiver<Vec<PendingTx>>,
+        export_cache: DocumentCache,
+    ) -> Self {
+        Self {
+            storage,
+            reload_log_filter,
+            metrics,
+            shutdown_tx,
+            pending_rx,
+            export_cache,
+        }
+    }
+
+    /// Returns an interceptor rejecting any request that does not carry
+    /// `authorization: Bearer <token>` metadata.
+    pub fn interceptor(
+        token: String,
+    ) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+        let expected = format!("Bearer {}", token);
+        move |request: Request<()>| match request.metadata().get("authorization") {
+            Some(value) if constant_time_eq(value, &expected) => Ok(request),
+            _ => Err(Status::unauthenticated("invalid admin token")),
+        }
+    }
+}
+
+/// Compares the provided authorization header against the expected value without
+/// short-circuiting on the first differing byte.
+fn constant_time_eq(provided: &MetadataValue<tonic::metadata::Ascii>, expected: &str) -> bool {
+    let provided = provided.as_bytes();
+    let expected = expected.as_bytes();
+    provided.len() == expected.len()
+        && provided
+            .iter()
+            .zip(expected)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[tonic::async_trait]
+impl AdminService for Admin {
+    #[instrument(skip(self, request))]
+    async fn set_log_filter(
+        &self,
+        request: Request<SetLogFilterRequest>,
+    ) -> Result<Response<SetLogFilterResponse>, Status> {
+        let filter = request.into_inner().filter;
+        (self.reload_log_filter)(&filter)
+            .map_err(|e| Status::invalid_argument(format!("invalid log filter: {}", e)))?;
+        tracing::info!(?filter, "updated log filter");
+
+        Ok(Response::new(SetLogFilterResponse {}))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn trigger_snapshot(
+        &self,
+        request: Request<TriggerSnapshotRequest>,
+    ) -> Result<Response<TriggerSnapshotResponse>, Status> {
+        let path = PathBuf::from(request.into_inner().path);
+        if path.as_os_str().is_empty() {
+            return Err(Status::invalid_argument("missing snapshot path"));
+        }
+        if path.exists() {
+            return Err(Status::already_exists("snapshot path already exists"));
+        }
+
+        let height = self
+            .storage
+            .checkpoint(path)
+            .await
+            .map_err(|e| Status::internal(format!("snapshot failed: {}", e)))?
+            .ok_or_else(|| Status::failed_precondition("no state to snapshot"))?;
+
+        Ok(Response::new(TriggerSnapshotResponse { height }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn dump_metrics(
+        &self,
+        _request: Request<DumpMetricsRequest>,
+    ) -> Result<Response<DumpMetricsResponse>, Status> {
+        Ok(Response::new(DumpMetricsResponse {
+            metrics: self.metrics.render(),
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn flush_caches(
+        &self,
+        _request: Request<FlushCachesRequest>,
+    ) -> Result<Response<FlushCachesResponse>, Status> {
+        self.storage
+            .flush()
+            .await
+            .map_err(|e| Status::internal(format!("flush failed: {}", e)))?;
+        let evicted_bytes = self.storage.clear_block_cache() + self.export_cache.clear();
+        tracing::info!(evicted_bytes, "flushed caches");
+
+        Ok(Response::new(FlushCachesResponse {
+            evicted_bytes: evicted_bytes as u64,
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn shutdown(
+        &self,
+        _request: Request<ShutdownRequest>,
+    ) -> Result<Response<ShutdownResponse>, Status> {
+        tracing::info!("shutdown requested via admin service");
+        self.shutdown_tx
+            .send(true)
+            .map_err(|_| Status::unavailable("node is already shutting down"))?;
+
+        Ok(Response::new(ShutdownResponse {}))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn list_pending_transactions(
+        &self,
+        _request: Request<ListPendingTransactionsRequest>,
+    ) -> Result<Response<ListPendingTransactionsResponse>, Status> {
+        let transactions = self
+            .pending_rx
+            .borrow()
+            .iter()
+            .map(|tx| PendingTransaction {
+                id: tx.id.to_vec(),
+                size: tx.size as u64,
+                fee: tx.fee,
+                first_seen_unix_ms: tx
+                    .first_seen
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+                nullifiers: tx
+                    .nullifiers
+                    .iter()
+                    .map(|nf| nf.to_bytes().to_vec())
+                    .collect(),
+            })
+            .collect();
+
+        Ok(Response::new(ListPendingTransactionsResponse {
+            transactions,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use tonic::Code;
+
+    use super::*;
+    use crate::testing::Node;
+
+    /// An admin service for `node`, returning the filters it was asked to set
+    /// and the receiving end of its shutdown signal.
+    fn admin(node: &Node) -> (Admin, Arc<Mutex<Vec<String>>>, watch::Receiver<bool>) {
+        let filters = Arc::new(Mutex::new(Vec::new()));
+        let reload_log_filter: LogFilterReloader = Arc::new({
+            let filters = filters.clone();
+            move |filter: &str| {
+                anyhow::ensure!(!filter.contains('!'), "invalid directive");
+                filters.lock().unwrap().push(filter.to_string());
+                Ok(())
+            }
+        });
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let admin = Admin::new(
+            node.storage().clone(),
+            reload_log_filter,
+            PrometheusBuilder::new().build_recorder().handle(),
+            shutdown_tx,
+            watch::channel(Vec::new()).1,
+            Default::default(),
+        );
+        (admin, filters, shutdown_rx)
+    }
+
+    fn authorized(authorization: Option<&str>) -> Result<Request<()>, Status> {
+        let mut request = Request::new(());
+        if let Some(authorization) = authorization {
+            request
+                .metadata_mut()
+                .insert("authorization", authorization.parse().unwrap());
+        }
+        let mut interceptor = Admin::interceptor("secret".to_string());
+        interceptor(request)
+    }
+
+    #[test]
+    fn interceptor_requires_the_bearer_token() {
+        assert!(authorized(Some("Bearer secret")).is_ok());
+        for authorization in [
+            None,
+            Some("secret"),
+            Some("Bearer"),
+            Some("Bearer secre"),
+            Some("Bearer secret2"),
+            Some("Bearer SECRET"),
+            Some("Basic secret"),
+        ] {
+            assert_eq!(
+                authorized(authorization).unwrap_err().code(),
+                Code::Unauthenticated,
+                "accepted {:?}",
+                authorization
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn trigger_snapshot_reports_the_checkpointed_version() -> anyhow::Result<()> {
+        let node = Node::start(Default::default()).await?;
+        node.append_empty_blocks(2).await?;
+        let (admin, _, _) = admin(&node);
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot");
+
+        let height = admin
+            .trigger_snapshot(Request::new(TriggerSnapshotRequest {
+                path: path.to_string_lossy().into_owned(),
+            }))
+            .await?
+            .into_inner()
+            .height;
+        // Blocks committed afterwards are not in the snapshot.
+        node.append_empty_blocks(1).await?;
+
+        let snapshot = Storage::load_read_only(path.clone()).await?;
+        assert_eq!(snapshot.latest_version().await?, Some(height));
+        assert!(node.storage().latest_version().await? > Some(height));
+
+        // An existing snapshot is never overwritten.
+        let existing = admin
+            .trigger_snapshot(Request::new(TriggerSnapshotRequest {
+                path: path.to_string_lossy().into_owned(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(existing.code(), Code::AlreadyExists);
+
+        let missing = admin
+            .trigger_snapshot(Request::new(TriggerSnapshotRequest::default()))
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), Code::InvalidArgument);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_log_filter_reloads_valid_filters() -> anyhow::Result<()> {
+        let node = Node::start(Default::default()).await?;
+        let (admin, filters, _) = admin(&node);
+
+        admin
+            .set_log_filter(Request::new(SetLogFilterRequest {
+                filter: "pd=debug".to_string(),
+            }))
+            .await?;
+        let invalid = admin
+            .set_log_filter(Request::new(SetLogFilterRequest {
+                filter: "pd=!".to_string(),
+            }))
+            .await
+            .unwrap_err();
+
+        assert_eq!(invalid.code(), Code::InvalidArgument);
+        assert_eq!(*filters.lock().unwrap(), vec!["pd=debug".to_string()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn flush_caches_and_shutdown() -> anyhow::Result<()> {
+        let node = Node::start(Default::default()).await?;
+        let (admin, _, mut shutdown_rx) = admin(&node);
+
+        admin
+            .flush_caches(Request::new(FlushCachesRequest {}))
+            .await?;
+        // Everything was evicted the first time.
+        let flushed = admin
+            .flush_caches(Request::new(FlushCachesRequest {}))
+            .await?
+            .into_inner();
+        assert_eq!(flushed.evicted_bytes, 0);
+        assert!(!*shutdown_rx.borrow());
+
+        admin.shutdown(Request::new(ShutdownRequest {})).await?;
+        shutdown_rx.changed().await?;
+        assert!(*shutdown_rx.borrow());
+
+        // Once the node has stopped watching, shutting down again fails.
+        drop(shutdown_rx);
+        let stopped = admin
+            .shutdown(Request::new(ShutdownRequest {}))
+            .await
+            .unwrap_err();
+        assert_eq!(stopped.code(), Code::Unavailable);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lists_pending_transactions() -> anyhow::Result<()> {
+        let node = Node::start(Default::default()).await?;
+        let pending = PendingTx {
+            id: [1; 32],
+            size: 100,
+            fee: 7,
+            first_seen: UNIX_EPOCH + std::time::Duration::from_millis(1_500),
+            nullifiers: Vec::new(),
+        };
+        let admin = Admin::new(
+            node.storage().clone(),
+            Arc::new(|_: &str| Ok(())),
+            PrometheusBuilder::new().build_recorder().handle(),
+            watch::channel(false).0,
+            watch::channel(vec![pending]).1,
+            Default::default(),
+        );
+
+        let transactions = admin
+            .list_pending_transactions(Request::new(ListPendingTransactionsRequest {}))
+            .await?
+            .into_inner()
+            .transactions;
+        assert_eq!(
+            transactions,
+            vec![PendingTransaction {
+                id: vec![1; 32],
+                size: 100,
+                fee: 7,
+                first_seen_unix_ms: 1_500,
+                nullifiers: Vec::new(),
+            }]
+        );
+
+        Ok(())
+    }
+}
@@ -1,12 +1,21 @@
 mod component;
+#[cfg(not(feature = "ibc"))]
+mod ibc_genesis;
 
 pub mod app;
+pub mod faucet;
+#[cfg(feature = "ibc")]
 pub mod ibc;
+pub mod key_schema;
+pub mod registry;
 pub mod shielded_pool;
 pub mod staking;
 
+#[cfg(feature = "ibc")]
 pub use self::ibc::IBCComponent;
 pub use app::App;
 pub use component::Component;
+pub use faucet::Faucet;
+pub use registry::{Registry, REGISTRY};
 pub use shielded_pool::ShieldedPool;
 pub use staking::Staking;
@@ -1,29 +1,55 @@
-use std::str::FromStr;
+use std::{str::FromStr, time::Duration};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use futures::future::BoxFuture;
 use jmt::{RootHash, Version};
 use penumbra_chain::params::ChainParams;
-use penumbra_stake::Epoch;
-use penumbra_transaction::Transaction;
+use penumbra_stake::{Epoch, ValidatorState};
+use penumbra_transaction::{Action, Transaction};
 use tendermint::abci::{self, types::ValidatorUpdate};
 use tendermint::Time;
 use tracing::instrument;
 
 use crate::{genesis, Overlay, OverlayExt, Storage};
 
-use super::{Component, IBCComponent, ShieldedPool, Staking};
+use super::{
+    key_schema::{KeySchema, StateKey},
+    registry::{self, Components, REGISTRY},
+    shielded_pool::View as _,
+    staking::View as _,
+    Component, Staking,
+};
+
+/// The experimental features which can be enabled by the chain parameters.
+///
+/// The actions they handle are rejected unless the feature is enabled. The
+/// components implementing them are compiled in by default, but some can be
+/// left out of the build, as listed in [`registry::COMPILED_OUT`].
+pub const FEATURES: &[&str] = &["ibc", "faucet"];
+
+/// The feature which must be enabled for the action to be accepted, if any.
+fn required_feature(action: &Action) -> Option<&'static str> {
+    match action {
+        Action::IBCAction(_) => Some("ibc"),
+        Action::FaucetClaim(_) => Some("faucet"),
+        Action::Output(_)
+        | Action::Spend(_)
+        | Action::Delegate(_)
+        | Action::Undelegate(_)
+        | Action::ValidatorDefinition(_) => None,
+    }
+}
 
 /// The Penumbra application, written as a bundle of [`Component`]s.
 ///
 /// The [`App`] is also a [`Component`], but as the top-level component,
-/// it constructs the others and exposes a [`commit`](App::commit) that
-/// commits the changes to the persistent storage and resets its subcomponents.
+/// it constructs the others from the [`REGISTRY`] and exposes a
+/// [`commit`](App::commit) that commits the changes to the persistent storage
+/// and resets its subcomponents.
 pub struct App {
     overlay: Overlay,
-    shielded_pool: ShieldedPool,
-    ibc: IBCComponent,
-    staking: Staking,
+    components: Components,
 }
 
 impl App {
@@ -37,17 +63,60 @@ impl App {
         // Commit the pending writes, clearing the overlay.
         let (root_hash, version) = self.overlay.lock().await.commit(storage).await?;
         tracing::debug!(?root_hash, version, "finished committing overlay");
+        // Record the app hash outside the tree, so that the startup self-check
+        // can detect a tree that has diverged from what was committed.
+        storage.put_app_hash(version, root_hash).await?;
         // Now re-instantiate all of the components:
-        self.staking = Staking::new(self.overlay.clone()).await?;
-        self.ibc = IBCComponent::new(self.overlay.clone()).await?;
-        self.shielded_pool = ShieldedPool::new(self.overlay.clone()).await?;
+        self.components = REGISTRY.instantiate(self.overlay.clone()).await?;
 
         Ok((root_hash, version))
     }
 
-    // TODO: should this just be returned by `commit`? both are called during every `EndBlock`
+    /// The chain's statistics as of the latest committed block.
+    pub async fn chain_stats(&self) -> Result<ChainStats> {
+        self.overlay.chain_stats().await
+    }
+
+    /// Returns the complete validator set, with the voting power of each
+    /// validator, as reported to Tendermint by `InitChain`.
     pub async fn tm_validator_updates(&self) -> Result<Vec<ValidatorUpdate>> {
-        self.staking.tm_validator_updates().await
+        self.staking().tm_validator_updates().await
+    }
+
+    /// Returns the changes to the validator set made by the current block, as
+    /// reported to Tendermint by `EndBlock`.
+    pub async fn validator_set_updates(&self) -> Result<Vec<ValidatorUpdate>> {
+        self.staking().validator_set_updates().await
+    }
+
+    /// The instance of the component of type `C`, if it is part of this build.
+    pub fn component<C: Component + 'static>(&self) -> Option<&C> {
+        self.components.get()
+    }
+
+    fn staking(&self) -> &Staking {
+        self.component()
+            .expect("the staking component is always registered")
+    }
+
+    /// Errors if the chain has enabled a feature whose component was compiled
+    /// out of this build, since executing blocks without it would diverge
+    /// from the rest of the network.
+    async fn check_compiled_in(&self, height: u64) -> Result<()> {
+        let chain_params = self.overlay.get_chain_params().await?;
+        for feature in registry::COMPILED_OUT {
+            if chain_params.feature_enabled(feature, height) {
+                return Err(anyhow!(
+                    "the chain enables the {} feature at height {}, but this build of pd was \
+                     compiled without the {} component; rebuild pd with `--features {}`",
+                    feature,
+                    height,
+                    feature,
+                    feature
+                ));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -55,95 +124,162 @@ impl App {
 impl Component for App {
     #[instrument(skip(overlay))]
     async fn new(overlay: Overlay) -> Result<Self> {
-        let staking = Staking::new(overlay.clone()).await?;
-        let ibc = IBCComponent::new(overlay.clone()).await?;
-        let shielded_pool = ShieldedPool::new(overlay.clone()).await?;
+        let components = REGISTRY.instantiate(overlay.clone()).await?;
 
         Ok(Self {
             overlay,
-            shielded_pool,
-            staking,
-            ibc,
+            components,
         })
     }
 
     #[instrument(skip(self, app_state))]
     async fn init_chain(&mut self, app_state: &genesis::AppState) -> Result<()> {
+        for feature in &app_state.chain_params.features {
+            let (name, activation_height) = match feature.split_once('@') {
+                Some((name, activation_height)) => (name, Some(activation_height)),
+                None => (feature.as_str(), None),
+            };
+            if !FEATURES.contains(&name) {
+                return Err(anyhow!(
+                    "unknown feature {:?}; known features are {:?}",
+                    name,
+                    FEATURES
+                ));
+            }
+            if let Some(activation_height) = activation_height {
+                activation_height
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("invalid activation height in feature {:?}", feature))?;
+            }
+        }
+
+        // The genesis block height is 0, and begins the first epoch.
+        self.overlay.put_block_height(0).await;
         self.overlay
             .put_chain_params(app_state.chain_params.clone())
-            .await;
+            .await?;
+        self.check_compiled_in(0).await?;
         // TODO: do we actually need to store the app state here?
         self.overlay
             .put_domain(b"genesis/app_state".into(), app_state.clone())
             .await;
-        // The genesis block height is 0
-        self.overlay.put_block_height(0).await;
-
-        self.staking.init_chain(app_state).await?;
-        self.ibc.init_chain(app_state).await?;
+        self.overlay
+            .put_current_epoch(Epoch {
+                index: 0,
+                start_height: 0,
+            })
+            .await;
 
-        // Shielded pool always executes last.
-        self.shielded_pool.init_chain(app_state).await?;
+        // Components execute in registration order, so the shielded pool is last.
+        for (_, component) in self.components.iter_mut() {
+            component.init_chain(app_state).await?;
+        }
         Ok(())
     }
 
     #[instrument(skip(self, begin_block))]
     async fn begin_block(&mut self, begin_block: &abci::request::BeginBlock) -> Result<()> {
         // store the block height
-        self.overlay
-            .put_block_height(begin_block.header.height.into())
-            .await;
+        let height = begin_block.header.height.into();
+        self.overlay.put_block_height(height).await;
+        self.check_compiled_in(height).await?;
         // store the block time
         self.overlay
             .put_block_timestamp(begin_block.header.time)
             .await;
+        // The first block of each epoch records its start time, for
+        // chains whose epochs are defined by block time.
+        let epoch = self.overlay.get_current_epoch().await?;
+        if self
+            .overlay
+            .get_epoch_start_time(epoch.index)
+            .await?
+            .is_none()
+        {
+            self.overlay
+                .put_epoch_start_time(epoch.index, begin_block.header.time)
+                .await;
+        }
 
-        self.staking.begin_block(begin_block).await?;
-        self.ibc.begin_block(begin_block).await?;
-
-        // Shielded pool always executes last.
-        self.shielded_pool.begin_block(begin_block).await?;
+        // Components execute in registration order, so the shielded pool is last.
+        for (_, component) in self.components.iter_mut() {
+            component.begin_block(begin_block).await?;
+        }
 
         Ok(())
     }
 
     #[instrument(skip(tx))]
     fn check_tx_stateless(tx: &Transaction) -> Result<()> {
-        Staking::check_tx_stateless(tx)?;
-        IBCComponent::check_tx_stateless(tx)?;
-        ShieldedPool::check_tx_stateless(tx)?;
+        for registration in REGISTRY.registrations() {
+            registration.check_tx_stateless(tx)?;
+        }
         Ok(())
     }
 
     #[instrument(skip(self, tx))]
     async fn check_tx_stateful(&self, tx: &Transaction) -> Result<()> {
-        self.staking.check_tx_stateful(tx).await?;
-        self.ibc.check_tx_stateful(tx).await?;
+        // Actions of experimental components are only routed to them once
+        // their feature is enabled.
+        let chain_params = self.overlay.get_chain_params().await?;
+        let height = self.overlay.get_block_height().await?;
+        for action in &tx.transaction_body.actions {
+            if let Some(feature) = required_feature(action) {
+                if !chain_params.feature_enabled(feature, height) {
+                    return Err(anyhow!(
+                        "{} actions are not enabled at height {}",
+                        feature,
+                        height
+                    ));
+                }
+            }
+        }
 
-        // Shielded pool always executes last.
-        self.shielded_pool.check_tx_stateful(tx).await?;
+        // Components execute in registration order, so the shielded pool is last.
+        for (_, component) in self.components.iter() {
+            component.check_tx_stateful(tx).await?;
+        }
         Ok(())
     }
 
     #[instrument(skip(self, tx))]
     async fn execute_tx(&mut self, tx: &Transaction) -> Result<()> {
-        self.staking.execute_tx(tx).await?;
-        self.ibc.execute_tx(tx).await?;
+        // Components execute in registration order, so the shielded pool is last.
+        for (_, component) in self.components.iter_mut() {
+            component.execute_tx(tx).await?;
+        }
 
-        // Shielded pool always executes last.
-        self.shielded_pool.execute_tx(tx).await?;
+        let count = self.overlay.transaction_count().await?;
+        self.overlay.put_transaction_count(count + 1).await;
         Ok(())
     }
 
     #[instrument(skip(self, end_block))]
     async fn end_block(&mut self, end_block: &abci::request::EndBlock) -> Result<()> {
-        self.staking.end_block(end_block).await?;
-        self.ibc.end_block(end_block).await?;
+        // Components execute in registration order, so the shielded pool is last.
+        for (_, component) in self.components.iter_mut() {
+            component.end_block(end_block).await?;
+        }
+
+        // Once every component has processed the end of the epoch, start the next one.
+        if self.overlay.is_end_of_epoch().await? {
+            let epoch = self.overlay.get_current_epoch().await?;
+            let next_epoch = epoch.next(end_block.height as u64 + 1);
+            tracing::debug!(?next_epoch, "starting next epoch");
+            self.overlay.put_current_epoch(next_epoch).await;
+        }
 
-        // Shielded pool always executes last.
-        self.shielded_pool.end_block(end_block).await?;
         Ok(())
     }
+
+    /// Takes the events the components accumulated since the last call, in
+    /// execution order.
+    fn take_events(&mut self) -> Vec<abci::Event> {
+        self.components
+            .iter_mut()
+            .flat_map(|(_, component)| component.take_events())
+            .collect()
+    }
 }
 
 /// This trait provides read and write access to common parts of the Penumbra
@@ -160,18 +296,129 @@ pub trait View: OverlayExt {
             .ok_or_else(|| anyhow!("Missing ChainParams"))
     }
 
-    /// Writes the provided chain parameters to the JMT.
-    async fn put_chain_params(&self, params: ChainParams) {
-        self.put_domain(b"chain_params".into(), params).await
+    /// Writes the provided chain parameters to the JMT, recording them as the
+    /// parameters in force from the current block height on.
+    async fn put_chain_params(&self, params: ChainParams) -> Result<()> {
+        let height = self.get_block_height().await?;
+
+        // The heights at which the parameters changed form a list, linked
+        // from the latest change backwards.
+        let latest: Option<u64> = self
+            .get_proto(b"chain_params/history/latest".into())
+            .await?;
+        if let Some(previous) = latest.filter(|&previous| previous != height) {
+            self.put_proto(
+                format!("chain_params/history/{}/previous", height).into(),
+                previous,
+            )
+            .await;
+        }
+        self.put_proto(b"chain_params/history/latest".into(), height)
+            .await;
+        self.put_domain(
+            format!("chain_params/history/{}", height).into(),
+            params.clone(),
+        )
+        .await;
+
+        self.put_domain(b"chain_params".into(), params).await;
+        Ok(())
+    }
+
+    /// Gets the chain parameters that were in force at the given height.
+    ///
+    /// Returns `None` for future heights, and for heights before the
+    /// parameters were first recorded.
+    async fn chain_params_at(&self, height: u64) -> Result<Option<ChainParams>> {
+        if height > self.get_block_height().await? {
+            return Ok(None);
+        }
+
+        let mut change: Option<u64> = self
+            .get_proto(b"chain_params/history/latest".into())
+            .await?;
+        while let Some(change_height) = change {
+            if change_height <= height {
+                return self
+                    .get_domain(format!("chain_params/history/{}", change_height).into())
+                    .await;
+            }
+            change = self
+                .get_proto(format!("chain_params/history/{}/previous", change_height).into())
+                .await?;
+        }
+        Ok(None)
     }
 
     /// Gets the current epoch for the chain.
     async fn get_current_epoch(&self) -> Result<Epoch> {
-        let block_height = self.get_block_height().await?;
-        Ok(Epoch::from_height(
-            block_height,
-            self.get_epoch_duration().await?,
-        ))
+        let index: Option<u64> = self.get_proto(b"epoch/index".into()).await?;
+        let start_height: Option<u64> = self.get_proto(b"epoch/start_height".into()).await?;
+        match (index, start_height) {
+            (Some(index), Some(start_height)) => Ok(Epoch {
+                index,
+                start_height,
+            }),
+            // Chains initialized before the current epoch was recorded in the
+            // state can only have block-count epochs.
+            _ => Ok(Epoch::from_height(
+                self.get_block_height().await?,
+                self.get_epoch_duration().await?,
+            )),
+        }
+    }
+
+    /// Writes the current epoch to the JMT.
+    async fn put_current_epoch(&self, epoch: Epoch) {
+        self.put_proto(b"epoch/index".into(), epoch.index).await;
+        self.put_proto(b"epoch/start_height".into(), epoch.start_height)
+            .await;
+    }
+
+    /// Gets the timestamp of the first block of the given epoch, if it has started.
+    async fn get_epoch_start_time(&self, index: u64) -> Result<Option<Time>> {
+        let timestamp: Option<String> = self
+            .get_proto(format!("epoch/{}/start_time", index).into())
+            .await?;
+        timestamp
+            .map(|timestamp| Ok(Time::from_str(&timestamp)?))
+            .transpose()
+    }
+
+    /// Writes the timestamp of the first block of the given epoch to the JMT.
+    async fn put_epoch_start_time(&self, index: u64, timestamp: Time) {
+        self.put_proto(
+            format!("epoch/{}/start_time", index).into(),
+            timestamp.to_rfc3339(),
+        )
+        .await
+    }
+
+    /// Indicates whether the current block is the last block of the current epoch.
+    ///
+    /// With block-time epochs, this is the first block at least the epoch
+    /// duration after the epoch's first block. Since block timestamps are
+    /// agreed on by consensus, this is deterministic.
+    async fn is_end_of_epoch(&self) -> Result<bool> {
+        let params = self.get_chain_params().await?;
+        let epoch = self.get_current_epoch().await?;
+        let height = self.get_block_height().await?;
+
+        if params.epoch_duration_seconds == 0 {
+            return Ok(epoch.is_epoch_end(height, params.epoch_duration));
+        }
+
+        let start_time = match self.get_epoch_start_time(epoch.index).await? {
+            Some(start_time) => start_time,
+            // Genesis is part of the first epoch, but has no timestamp.
+            None => return Ok(false),
+        };
+        let elapsed = self
+            .get_block_timestamp()
+            .await?
+            .duration_since(start_time)
+            .unwrap_or_default();
+        Ok(elapsed >= Duration::from_secs(params.epoch_duration_seconds))
     }
 
     /// Gets the epoch duration for the chain.
@@ -223,10 +470,42 @@ pub trait View: OverlayExt {
             .await
     }
 
+    /// The number of transactions executed since genesis.
+    async fn transaction_count(&self) -> Result<u64> {
+        Ok(self
+            .get_proto(b"transaction_count".into())
+            .await?
+            .unwrap_or_default())
+    }
+
+    async fn put_transaction_count(&self, count: u64) {
+        self.put_proto(b"transaction_count".into(), count).await
+    }
+
+    /// Gathers the chain's running totals and its active validator count.
+    async fn chain_stats(&self) -> Result<ChainStats> {
+        let mut active_validators = 0;
+        for v in self.validator_list().await? {
+            if self.validator_state(&v).await? == Some(ValidatorState::Active) {
+                active_validators += 1;
+            }
+        }
+
+        Ok(ChainStats {
+            height: self.get_block_height().await?,
+            commitments: self.commitment_count().await?,
+            nullifiers: self.nullifier_count().await?,
+            transactions: self.transaction_count().await?,
+            active_validators,
+        })
+    }
+
     /// Checks a provided chain_id against the chain state.
     ///
     /// Passes through if the provided chain_id is empty or matches, and
-    /// otherwise errors.
+    /// otherwise errors with `FAILED_PRECONDITION`, carrying an encoded
+    /// [`ChainIdMismatch`](penumbra_proto::chain::ChainIdMismatch) as the
+    /// status details so that clients can tell which chain the node serves.
     async fn check_chain_id(&self, provided: &str) -> Result<(), tonic::Status> {
         let chain_id = self
             .get_chain_id()
@@ -235,12 +514,228 @@ pub trait View: OverlayExt {
         if provided.is_empty() || provided == chain_id {
             Ok(())
         } else {
-            Err(tonic::Status::failed_precondition(format!(
-                "provided chain_id {} does not match chain_id {}",
-                provided, chain_id
-            )))
+            let details = penumbra_proto::chain::ChainIdMismatch {
+                server_chain_id: chain_id.clone(),
+                requested_chain_id: provided.to_string(),
+            };
+            Err(tonic::Status::with_details(
+                tonic::Code::FailedPrecondition,
+                format!(
+                    "provided chain_id {} does not match chain_id {}",
+                    provided, chain_id
+                ),
+                penumbra_proto::Message::encode_to_vec(&details).into(),
+            ))
         }
     }
 }
 
 impl<T: OverlayExt> View for T {}
+
+/// Basic measures of the chain's use, kept as running totals in the state so
+/// that they are available without replaying the chain.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChainStats {
+    /// The height of the latest block.
+    pub height: u64,
+    /// The note commitments added since genesis.
+    pub commitments: u64,
+    /// The nullifiers spent since genesis.
+    pub nullifiers: u64,
+    /// The transactions executed since genesis.
+    pub transactions: u64,
+    /// The validators currently in the active set.
+    pub active_validators: u64,
+}
+
+impl ChainStats {
+    /// The average number of transactions in each block since genesis.
+    pub fn transactions_per_block(&self) -> f64 {
+        if self.height == 0 {
+            0.0
+        } else {
+            self.transactions as f64 / self.height as f64
+        }
+    }
+}
+
+impl From<ChainStats> for penumbra_proto::client::oblivious::ChainStats {
+    fn from(stats: ChainStats) -> Self {
+        Self {
+            height: stats.height,
+            total_commitments: stats.commitments,
+            total_nullifiers: stats.nullifiers,
+            total_transactions: stats.transactions,
+            transactions_per_block: stats.transactions_per_block(),
+            active_validators: stats.active_validators,
+        }
+    }
+}
+
+/// The keys the app writes, for the [`key_schema`](super::key_schema) registry.
+pub(crate) const KEY_SCHEMA: KeySchema = KeySchema {
+    component: "app",
+    keys: state_keys,
+};
+
+fn state_keys(overlay: &Overlay) -> BoxFuture<'_, Result<Vec<StateKey>>> {
+    Box::pin(async move {
+        let mut keys: Vec<_> = [
+            "genesis/app_state",
+            "chain_params",
+            "chain_params/history/latest",
+            "epoch/index",
+            "epoch/start_height",
+            "block_height",
+            "block_timestamp",
+            "transaction_count",
+        ]
+        .into_iter()
+        .map(|key| StateKey::new(key, key))
+        .collect();
+
+        let mut change: Option<u64> = overlay
+            .get_proto(b"chain_params/history/latest".into())
+            .await?;
+        while let Some(height) = change {
+            keys.push(StateKey::new(
+                "chain_params/history/{height}",
+                format!("chain_params/history/{}", height),
+            ));
+            let previous = format!("chain_params/history/{}/previous", height);
+            change = overlay.get_proto(previous.clone().into()).await?;
+            keys.push(StateKey::new(
+                "chain_params/history/{height}/previous",
+                previous,
+            ));
+        }
+
+        let epoch_index: Option<u64> = overlay.get_proto(b"epoch/index".into()).await?;
+        for index in 0..=epoch_index.unwrap_or_default() {
+            keys.push(StateKey::new(
+                "epoch/{index}/start_time",
+                format!("epoch/{}/start_time", index),
+            ));
+        }
+
+        Ok(keys)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{merkle, rdsa::Signature};
+    use penumbra_transaction::{action::FaucetClaim, Fee, TransactionBody};
+
+    use super::*;
+    use crate::testing::address;
+
+    /// Runs genesis with the given features enabled.
+    async fn app_with(features: &[&str]) -> Result<App> {
+        let overlay = Storage::in_memory().await?.overlay().await?;
+        let mut app = App::new(overlay).await?;
+        app.init_chain(&genesis::AppState {
+            chain_params: ChainParams {
+                features: features.iter().map(|feature| feature.to_string()).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await?;
+        Ok(app)
+    }
+
+    /// An (unsigned) transaction claiming from the faucet.
+    fn faucet_claim() -> Transaction {
+        Transaction {
+            transaction_body: TransactionBody {
+                actions: vec![Action::FaucetClaim(FaucetClaim {
+                    address: address(),
+                    amount: 1,
+                })],
+                merkle_root: merkle::Root(Default::default()),
+                expiry_height: 0,
+                chain_id: String::new(),
+                fee: Fee(0),
+            },
+            binding_sig: Signature::from([0; 64]),
+        }
+    }
+
+    #[tokio::test]
+    async fn genesis_rejects_unknown_features() -> Result<()> {
+        for features in [&["faucet", "teleport"][..], &["faucet@soon"]] {
+            assert!(app_with(features).await.is_err(), "{:?}", features);
+        }
+        app_with(&["faucet", "ibc@100"]).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn gated_actions_are_rejected_until_enabled() -> Result<()> {
+        let not_enabled = "faucet actions are not enabled at height 0";
+        for features in [&[][..], &["faucet@1"]] {
+            let app = app_with(features).await?;
+            let error = app.check_tx_stateful(&faucet_claim()).await.unwrap_err();
+            assert_eq!(error.to_string(), not_enabled, "{:?}", features);
+        }
+
+        // Once enabled, the action gets past the gate, whether or not the
+        // faucet component then accepts it.
+        for features in [&["faucet"][..], &["faucet@0"]] {
+            let app = app_with(features).await?;
+            if let Err(error) = app.check_tx_stateful(&faucet_claim()).await {
+                assert_ne!(error.to_string(), not_enabled, "{:?}", features);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chain_params_are_recorded_by_height() -> Result<()> {
+        let overlay = Storage::in_memory().await?.overlay().await?;
+        let params = |epoch_duration| ChainParams {
+            epoch_duration,
+            ..Default::default()
+        };
+
+        // The first parameters are recorded at height 5, and changed at 10
+        // (twice, the second replacing the first) and at 20.
+        for (height, epoch_duration) in [(5, 1), (10, 2), (10, 3), (20, 4)] {
+            overlay.put_block_height(height).await;
+            overlay.put_chain_params(params(epoch_duration)).await?;
+        }
+
+        for (height, epoch_duration) in [
+            (0, None),
+            (4, None),
+            (5, Some(1)),
+            (9, Some(1)),
+            (10, Some(3)),
+            (19, Some(3)),
+            (20, Some(4)),
+            (21, None),
+        ] {
+            assert_eq!(
+                overlay
+                    .chain_params_at(height)
+                    .await?
+                    .map(|params| params.epoch_duration),
+                epoch_duration,
+                "height {}",
+                height
+            );
+        }
+        assert_eq!(overlay.get_chain_params().await?.epoch_duration, 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_experimental_actions_require_features() {
+        let action = faucet_claim().transaction_body.actions.remove(0);
+        assert_eq!(required_feature(&action), Some("faucet"));
+        assert!(FEATURES.contains(&"faucet"));
+    }
+}
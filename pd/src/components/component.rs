@@ -124,4 +124,13 @@ pub trait Component: Sized {
     /// This method should only be called after [`Component::begin_block`].
     /// No methods should be called following this method.
     async fn end_block(&mut self, end_block: &abci::request::EndBlock) -> Result<()>;
+
+    /// Takes the events accumulated since the last call, to be reported to
+    /// Tendermint in the response to the current request.
+    ///
+    /// Components which emit no events can rely on the default, which returns
+    /// none.
+    fn take_events(&mut self) -> Vec<abci::Event> {
+        Vec::new()
+    }
 }
@@ -0,0 +1,212 @@
+//! A rate-limited public faucet.
+//!
+//! Testnet faucets have been frontends sending ordinary shielded transactions,
+//! so the chain could neither see nor limit what they gave out. With the
+//! `faucet` feature enabled, a [`FaucetClaim`] action instead asks the chain
+//! itself to mint the staking token to an address, within quotas set by the
+//! chain parameters: a claim mints at most `faucet_max_claim`, an address must
+//! wait `faucet_claim_interval` blocks between claims, and a block includes at
+//! most `faucet_claims_per_block` claims. Every disbursement is recorded by
+//! height, for the specific query service's `FaucetDisbursements`.
+//!
+//! Addresses are cheap to generate, so the per-address interval only slows a
+//! determined claimant down; the per-block limit is what bounds the total
+//! minted. The shielded pool mints the claimed notes.
+
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use penumbra_chain::{FaucetDisbursement, FaucetDisbursements};
+use penumbra_crypto::Address;
+use penumbra_transaction::{action::FaucetClaim, Transaction};
+use tendermint::abci;
+use tracing::instrument;
+
+use super::{
+    app::View as _,
+    key_schema::{KeySchema, StateKey},
+    Component,
+};
+use crate::{genesis, Overlay, OverlayExt};
+
+pub struct Faucet {
+    overlay: Overlay,
+}
+
+#[async_trait]
+impl Component for Faucet {
+    #[instrument(name = "faucet", skip(overlay))]
+    async fn new(overlay: Overlay) -> Result<Self> {
+        Ok(Self { overlay })
+    }
+
+    #[instrument(name = "faucet", skip(self, _app_state))]
+    async fn init_chain(&mut self, _app_state: &genesis::AppState) -> Result<()> {
+        Ok(())
+    }
+
+    #[instrument(name = "faucet", skip(self, _begin_block))]
+    async fn begin_block(&mut self, _begin_block: &abci::request::BeginBlock) -> Result<()> {
+        Ok(())
+    }
+
+    #[instrument(name = "faucet", skip(tx))]
+    fn check_tx_stateless(tx: &Transaction) -> Result<()> {
+        let mut addresses = BTreeSet::new();
+        for claim in tx.faucet_claims() {
+            if claim.amount == 0 {
+                return Err(anyhow!("faucet claims must be for a nonzero amount"));
+            }
+            if !addresses.insert(claim.address.to_string()) {
+                return Err(anyhow!(
+                    "address {} claims from the faucet more than once",
+                    claim.address
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    #[instrument(name = "faucet", skip(self, tx))]
+    async fn check_tx_stateful(&self, tx: &Transaction) -> Result<()> {
+        let claims: Vec<&FaucetClaim> = tx.faucet_claims().collect();
+        if claims.is_empty() {
+            return Ok(());
+        }
+
+        let chain_params = self.overlay.get_chain_params().await?;
+        let height = self.overlay.get_block_height().await?;
+
+        let disbursed = self.overlay.faucet_disbursements(height).await?.0.len() as u64;
+        if disbursed + claims.len() as u64 > chain_params.faucet_claims_per_block {
+            return Err(anyhow!(
+                "the faucet has reached its limit of {} claims in block {}",
+                chain_params.faucet_claims_per_block,
+                height
+            ));
+        }
+
+        for claim in claims {
+            if claim.amount > chain_params.faucet_max_claim {
+                return Err(anyhow!(
+                    "faucet claim of {} exceeds the maximum of {}",
+                    claim.amount,
+                    chain_params.faucet_max_claim
+                ));
+            }
+            if let Some(last_claim) = self.overlay.faucet_last_claim(&claim.address).await? {
+                let next_claim = last_claim + chain_params.faucet_claim_interval;
+                if height < next_claim {
+                    return Err(anyhow!(
+                        "address {} claimed from the faucet at height {}, and may not claim \
+                         again until height {}",
+                        claim.address,
+                        last_claim,
+                        next_claim
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[instrument(name = "faucet", skip(self, tx))]
+    async fn execute_tx(&mut self, tx: &Transaction) -> Result<()> {
+        let claims: Vec<&FaucetClaim> = tx.faucet_claims().collect();
+        if claims.is_empty() {
+            return Ok(());
+        }
+
+        let height = self.overlay.get_block_height().await?;
+        let mut disbursements = self.overlay.faucet_disbursements(height).await?;
+        for claim in claims {
+            tracing::debug!(address = %claim.address, amount = claim.amount, "faucet claim");
+            disbursements.0.push(FaucetDisbursement {
+                address: claim.address,
+                amount: claim.amount,
+                height,
+            });
+            self.overlay
+                .put_faucet_last_claim(&claim.address, height)
+                .await;
+        }
+        self.overlay
+            .put_faucet_disbursements(height, disbursements)
+            .await;
+
+        Ok(())
+    }
+
+    #[instrument(name = "faucet", skip(self, _end_block))]
+    async fn end_block(&mut self, _end_block: &abci::request::EndBlock) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Extension trait providing read/write access to faucet data.
+#[async_trait]
+pub trait View: OverlayExt {
+    /// The faucet's disbursements in the block at `height`.
+    async fn faucet_disbursements(&self, height: u64) -> Result<FaucetDisbursements> {
+        Ok(self
+            .get_domain(format!("faucet/disbursements/{}", height).into())
+            .await?
+            .unwrap_or_default())
+    }
+
+    async fn put_faucet_disbursements(&self, height: u64, disbursements: FaucetDisbursements) {
+        self.put_domain(
+            format!("faucet/disbursements/{}", height).into(),
+            disbursements,
+        )
+        .await
+    }
+
+    /// The height of the last faucet claim to `address`, if any.
+    async fn faucet_last_claim(&self, address: &Address) -> Result<Option<u64>> {
+        self.get_proto(format!("faucet/last_claim/{}", address).into())
+            .await
+    }
+
+    async fn put_faucet_last_claim(&self, address: &Address, height: u64) {
+        self.put_proto(format!("faucet/last_claim/{}", address).into(), height)
+            .await
+    }
+}
+
+impl<T: OverlayExt> View for T {}
+
+/// The keys the faucet writes, for the [`key_schema`](super::key_schema)
+/// registry.
+pub(crate) const KEY_SCHEMA: KeySchema = KeySchema {
+    component: "faucet",
+    keys: state_keys,
+};
+
+fn state_keys(overlay: &Overlay) -> BoxFuture<'_, Result<Vec<StateKey>>> {
+    Box::pin(async move {
+        let mut keys = Vec::new();
+
+        for height in 0..=overlay.get_block_height().await? {
+            let disbursements = overlay.faucet_disbursements(height).await?;
+            if disbursements.0.is_empty() {
+                continue;
+            }
+            keys.push(StateKey::new(
+                "faucet/disbursements/{height}",
+                format!("faucet/disbursements/{}", height),
+            ));
+            for disbursement in disbursements.0 {
+                keys.push(StateKey::new(
+                    "faucet/last_claim/{address}",
+                    format!("faucet/last_claim/{}", disbursement.address),
+                ));
+            }
+        }
+
+        Ok(keys)
+    })
+}
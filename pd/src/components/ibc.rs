@@ -1,5 +1,6 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::future::BoxFuture;
 use penumbra_ibc::{ClientCounter, ClientData, ConsensusState, IBCAction};
 use penumbra_transaction::{Action, Transaction};
 use std::convert::TryFrom;
@@ -7,12 +8,18 @@ use tendermint::abci;
 use tracing::instrument;
 
 use ibc::core::{
-    ics02_client::{height::Height, msgs::create_client::MsgCreateAnyClient},
+    ics02_client::{
+        client_type::ClientType, height::Height, msgs::create_client::MsgCreateAnyClient,
+    },
     ics24_host::identifier::ClientId,
 };
 use penumbra_proto::ibc::ibc_action::Action::CreateClient;
 
-use super::{app::View as _, Component};
+use super::{
+    app::View as _,
+    key_schema::{KeySchema, StateKey},
+    Component,
+};
 use crate::{genesis, Overlay, OverlayExt};
 
 pub struct IBCComponent {
@@ -190,3 +197,42 @@ pub trait View: OverlayExt + Send + Sync {
 }
 
 impl<T: OverlayExt + Send + Sync> View for T {}
+
+/// The keys the IBC component writes, for the [`key_schema`](super::key_schema)
+/// registry.
+///
+/// Only Tendermint clients can be created, so every client ID is derived from
+/// the client counter, and each client has the one consensus state it was
+/// created with.
+pub(crate) const KEY_SCHEMA: KeySchema = KeySchema {
+    component: "ibc",
+    keys: state_keys,
+};
+
+fn state_keys(overlay: &Overlay) -> BoxFuture<'_, Result<Vec<StateKey>>> {
+    Box::pin(async move {
+        let mut keys = vec![StateKey::new(
+            "ibc/ics02-client/client_counter",
+            "ibc/ics02-client/client_counter",
+        )];
+
+        for counter in 0..overlay.client_counter().await?.0 {
+            let client_id = hex::encode(ClientId::new(ClientType::Tendermint, counter)?.as_bytes());
+            let key = format!("ibc/ics02-client/clients/{}", client_id);
+            let data: Option<ClientData> = overlay.get_domain(key.clone().into()).await?;
+            keys.push(StateKey::new("ibc/ics02-client/clients/{client_id}", key));
+            if let Some(data) = data {
+                keys.push(StateKey::new(
+                    "ibc/ics02-client/clients/{client_id}/consensus_state/{height}",
+                    format!(
+                        "ibc/ics02-client/clients/{}/consensus_state/{}",
+                        client_id,
+                        data.client_state.0.latest_height()
+                    ),
+                ));
+            }
+        }
+
+        Ok(keys)
+    })
+}
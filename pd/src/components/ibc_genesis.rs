@@ -0,0 +1,79 @@
+//! The stand-in for the IBC component in builds without the `ibc` feature.
+//!
+//! The full component records an empty client counter at genesis, so this
+//! does the same, keeping the genesis app hash identical across builds. It
+//! writes the counter's protobuf directly, since these builds leave out
+//! `penumbra-ibc` and its domain types. It
+//! handles nothing else: the [`App`](super::App) refuses to go on once the
+//! chain enables IBC, rather than ignoring actions the full build executes.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use penumbra_proto::ibc::ClientCounter;
+use penumbra_transaction::Transaction;
+use tendermint::abci;
+use tracing::instrument;
+
+use super::{
+    key_schema::{KeySchema, StateKey},
+    Component,
+};
+use crate::{genesis, Overlay, OverlayExt};
+
+/// The key the IBC component keeps its client counter under.
+const CLIENT_COUNTER: &str = "ibc/ics02-client/client_counter";
+
+pub struct IbcGenesis {
+    overlay: Overlay,
+}
+
+#[async_trait]
+impl Component for IbcGenesis {
+    #[instrument(name = "ibc_genesis", skip(overlay))]
+    async fn new(overlay: Overlay) -> Result<Self> {
+        Ok(Self { overlay })
+    }
+
+    #[instrument(name = "ibc_genesis", skip(self, _app_state))]
+    async fn init_chain(&mut self, _app_state: &genesis::AppState) -> Result<()> {
+        self.overlay
+            .put_proto(CLIENT_COUNTER.into(), ClientCounter { counter: 0 })
+            .await;
+
+        Ok(())
+    }
+
+    async fn begin_block(&mut self, _begin_block: &abci::request::BeginBlock) -> Result<()> {
+        Ok(())
+    }
+
+    fn check_tx_stateless(_tx: &Transaction) -> Result<()> {
+        Ok(())
+    }
+
+    async fn check_tx_stateful(&self, _tx: &Transaction) -> Result<()> {
+        Ok(())
+    }
+
+    async fn execute_tx(&mut self, _tx: &Transaction) -> Result<()> {
+        Ok(())
+    }
+
+    async fn end_block(&mut self, _end_block: &abci::request::EndBlock) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The keys the stand-in writes, for the [`key_schema`](super::key_schema)
+/// registry.
+///
+/// No clients can be created without IBC, so this is just the counter.
+pub(crate) const KEY_SCHEMA: KeySchema = KeySchema {
+    component: "ibc",
+    keys: state_keys,
+};
+
+fn state_keys(_overlay: &Overlay) -> BoxFuture<'_, Result<Vec<StateKey>>> {
+    Box::pin(async move { Ok(vec![StateKey::new(CLIENT_COUNTER, CLIENT_COUNTER)]) })
+}
@@ -0,0 +1,267 @@
+//! The registry of the state keys each component writes.
+//!
+//! The JMT is keyed by the hashes of keys, so the keys themselves cannot be
+//! read back out of the tree. Instead, each component contributes a
+//! [`KeySchema`] to the [`REGISTRY`], which enumerates the keys it may have
+//! written, derived from the state itself: the per-validator keys for every
+//! validator in the list, the per-height keys for every height, and so on.
+//! Hashing the enumerated keys attributes the tree's leaves to components, and
+//! [`report`] counts every leaf no schema accounts for as unknown, which is
+//! itself a sign that a schema is out of date.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use jmt::{KeyHash, Version};
+
+#[cfg(feature = "ibc")]
+use super::ibc;
+#[cfg(not(feature = "ibc"))]
+use super::ibc_genesis;
+use super::{app, faucet, shielded_pool, staking};
+use crate::{Overlay, Storage};
+
+/// The keys one component writes.
+pub struct KeySchema {
+    /// The name of the component.
+    pub component: &'static str,
+    /// Enumerates every key the component may have written to the state.
+    ///
+    /// Enumerated keys which are absent from the state are ignored, so this
+    /// may be generous.
+    pub keys: for<'a> fn(&'a Overlay) -> BoxFuture<'a, Result<Vec<StateKey>>>,
+}
+
+/// A decoded state key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateKey {
+    /// The pattern of the family of keys this belongs to, e.g.
+    /// `staking/validators/{identity_key}/power`.
+    pub family: &'static str,
+    pub key: String,
+}
+
+impl StateKey {
+    pub fn new(family: &'static str, key: impl Into<String>) -> Self {
+        Self {
+            family,
+            key: key.into(),
+        }
+    }
+}
+
+/// Every component's key schema.
+pub const REGISTRY: &[KeySchema] = &[
+    app::KEY_SCHEMA,
+    shielded_pool::KEY_SCHEMA,
+    staking::KEY_SCHEMA,
+    #[cfg(feature = "ibc")]
+    ibc::KEY_SCHEMA,
+    // Builds without IBC still write its genesis state, under the same name.
+    #[cfg(not(feature = "ibc"))]
+    ibc_genesis::KEY_SCHEMA,
+    faucet::KEY_SCHEMA,
+    crate::upgrade::KEY_SCHEMA,
+];
+
+/// The keys of one component present in the state.
+#[derive(Clone, Debug)]
+pub struct ComponentKeys {
+    pub component: &'static str,
+    /// The keys present, with the sizes of their values in bytes, by family.
+    pub families: BTreeMap<&'static str, Vec<(String, usize)>>,
+}
+
+/// The state at some version, attributed to components.
+#[derive(Clone, Debug)]
+pub struct KeyReport {
+    pub version: Version,
+    pub components: Vec<ComponentKeys>,
+    /// The sizes of the values of the leaves no schema accounts for.
+    pub unknown: Vec<usize>,
+}
+
+/// Attributes every leaf of the tree at `version` to the component which wrote
+/// it, keeping only the keys which belong to a component named `prefix`, or
+/// which start with it.
+///
+/// Unknown leaves are only reported when there is no prefix.
+pub async fn report(
+    storage: &Storage,
+    version: Version,
+    prefix: Option<&str>,
+) -> Result<KeyReport> {
+    let mut leaves = storage.leaf_sizes(version).await?;
+    let overlay = storage.overlay_at(version).await?;
+
+    let mut components = Vec::new();
+    for schema in REGISTRY {
+        let mut families = BTreeMap::<_, Vec<_>>::new();
+        for StateKey { family, key } in (schema.keys)(&overlay).await? {
+            // Removing the leaf means a key enumerated twice is counted once.
+            let size = match leaves.remove(&KeyHash::from(key.as_bytes()).0) {
+                Some(size) => size,
+                None => continue,
+            };
+            let selected = prefix.map_or(true, |prefix| {
+                schema.component == prefix || key.starts_with(prefix)
+            });
+            if selected {
+                families.entry(family).or_default().push((key, size));
+            }
+        }
+        if !families.is_empty() {
+            components.push(ComponentKeys {
+                component: schema.component,
+                families,
+            });
+        }
+    }
+
+    let unknown = match prefix {
+        Some(_) => Vec::new(),
+        None => leaves.into_values().collect(),
+    };
+    Ok(KeyReport {
+        version,
+        components,
+        unknown,
+    })
+}
+
+/// Renders a report as text: the count and total size of each family of keys,
+/// by component, followed by the keys themselves if `list` is set.
+pub fn render(report: &KeyReport, list: bool) -> String {
+    let mut out = format!("state keys at height {}\n", report.version);
+    for component in &report.components {
+        let sizes = component.families.values().flatten().map(|(_, size)| size);
+        out += &format!(
+            "\n{}: {} keys, {} bytes\n",
+            component.component,
+            sizes.clone().count(),
+            sizes.sum::<usize>()
+        );
+        for (family, keys) in &component.families {
+            out += &format!(
+                "  {}: {} keys, {} bytes\n",
+                family,
+                keys.len(),
+                keys.iter().map(|(_, size)| size).sum::<usize>()
+            );
+            if list {
+                for (key, size) in keys {
+                    out += &format!("    {} ({} bytes)\n", key, size);
+                }
+            }
+        }
+    }
+    if !report.unknown.is_empty() {
+        out += &format!(
+            "\nunknown: {} keys, {} bytes\n",
+            report.unknown.len(),
+            report.unknown.iter().sum::<usize>()
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        genesis,
+        testing::{address, delegations, validator, Node},
+        OverlayExt,
+    };
+
+    /// Starts a node from a genesis state with one delegated validator.
+    async fn node() -> Result<Node> {
+        let validators = vec![validator("a")];
+        Node::start(genesis::AppState {
+            allocations: delegations(&validators, 1_000, address()),
+            validators,
+            ..Default::default()
+        })
+        .await
+    }
+
+    fn component<'a>(report: &'a KeyReport, name: &str) -> Option<&'a ComponentKeys> {
+        report.components.iter().find(|keys| keys.component == name)
+    }
+
+    #[tokio::test]
+    async fn every_key_written_at_genesis_is_attributed() -> Result<()> {
+        let node = node().await?;
+        let storage = node.storage();
+
+        let report = report(storage, storage.latest_version().await?.unwrap(), None).await?;
+        assert!(report.unknown.is_empty(), "{}", render(&report, true));
+        for name in ["app", "shielded_pool", "staking"] {
+            assert!(component(&report, name).is_some(), "no {} keys", name);
+        }
+        let app = component(&report, "app").unwrap();
+        assert_eq!(app.families["block_height"][0].0, "block_height");
+
+        // Every leaf is counted once, whichever schemas enumerate it.
+        let attributed: usize = report
+            .components
+            .iter()
+            .flat_map(|keys| keys.families.values())
+            .map(Vec::len)
+            .sum();
+        assert_eq!(attributed, storage.leaf_sizes(report.version).await?.len());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prefixes_select_components_or_keys() -> Result<()> {
+        let node = node().await?;
+        let storage = node.storage();
+        let version = storage.latest_version().await?.unwrap();
+
+        let staking = report(storage, version, Some("staking")).await?;
+        assert_eq!(staking.components.len(), 1);
+        assert_eq!(staking.components[0].component, "staking");
+
+        let keys = report(storage, version, Some("block_")).await?;
+        assert_eq!(keys.components.len(), 1);
+        let families: Vec<_> = keys.components[0].families.keys().copied().collect();
+        assert_eq!(families, vec!["block_height", "block_timestamp"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unattributed_keys_are_reported_as_unknown() -> Result<()> {
+        let node = node().await?;
+        let storage = node.storage();
+        let before = storage.latest_version().await?.unwrap();
+
+        let overlay = storage.overlay().await?;
+        overlay.put_proto(b"not/in/any/schema".into(), 7u64).await;
+        let (_, after) = overlay.lock().await.commit(storage.clone()).await?;
+
+        let report = report(storage, after, None).await?;
+        assert_eq!(report.unknown.len(), 1);
+        assert!(render(&report, false).ends_with("\nunknown: 1 keys, 2 bytes\n"));
+        // Unknown keys are only reported without a prefix.
+        assert!(report_unknown(storage, after, Some("app"))
+            .await?
+            .is_empty());
+
+        // The key was written after the earlier version.
+        assert!(report_unknown(storage, before, None).await?.is_empty());
+
+        Ok(())
+    }
+
+    async fn report_unknown(
+        storage: &Storage,
+        version: Version,
+        prefix: Option<&str>,
+    ) -> Result<Vec<usize>> {
+        Ok(report(storage, version, prefix).await?.unknown)
+    }
+}
@@ -0,0 +1,228 @@
+//! The registry of the components making up the [`App`](super::App).
+//!
+//! Rather than naming each component in a field, the [`App`](super::App)
+//! drives whichever components were registered in the [`REGISTRY`] when the
+//! binary was built, in the order they were registered. The heavier optional
+//! components are behind cargo features of the same name as the chain feature
+//! enabling them, so conservative operators can build a validator without
+//! them, e.g. with `cargo build --release -p pd --no-default-features`.
+//!
+//! A component compiled out of the build must still leave the state exactly
+//! as the full build would until its feature is enabled, or the two builds
+//! would disagree on the app hash from genesis. So the registry puts a
+//! stand-in in its place, which only performs the component's genesis writes;
+//! once the chain enables the feature, the [`App`](super::App) halts with an
+//! error naming the missing component instead of diverging.
+
+use std::any::Any;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use once_cell::sync::Lazy;
+use penumbra_transaction::Transaction;
+use tendermint::abci;
+
+use super::{Component, Faucet, ShieldedPool, Staking};
+use crate::{genesis, profile, Overlay};
+
+/// The chain features whose components were compiled out of this build.
+#[cfg(feature = "ibc")]
+pub const COMPILED_OUT: &[&str] = &[];
+/// The chain features whose components were compiled out of this build.
+#[cfg(not(feature = "ibc"))]
+pub const COMPILED_OUT: &[&str] = &["ibc"];
+
+/// The components of this build, populated on first use.
+///
+/// The shielded pool is registered last, so that it always executes last.
+pub static REGISTRY: Lazy<Registry> = Lazy::new(|| {
+    let registry = Registry::default().register::<Staking>("staking");
+    #[cfg(feature = "ibc")]
+    let registry = registry.register::<super::IBCComponent>("ibc");
+    #[cfg(not(feature = "ibc"))]
+    let registry = registry.register::<super::ibc_genesis::IbcGenesis>("ibc");
+    registry
+        .register::<Faucet>("faucet")
+        .register::<ShieldedPool>("shielded_pool")
+});
+
+/// An object-safe view of a [`Component`], so that components of different
+/// types can be held together.
+#[async_trait]
+pub trait DynComponent: Send + Sync + 'static {
+    async fn init_chain(&mut self, app_state: &genesis::AppState) -> Result<()>;
+    async fn begin_block(&mut self, begin_block: &abci::request::BeginBlock) -> Result<()>;
+    async fn check_tx_stateful(&self, tx: &Transaction) -> Result<()>;
+    async fn execute_tx(&mut self, tx: &Transaction) -> Result<()>;
+    async fn end_block(&mut self, end_block: &abci::request::EndBlock) -> Result<()>;
+    fn take_events(&mut self) -> Vec<abci::Event>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+#[async_trait]
+impl<C: Component + Send + Sync + 'static> DynComponent for C {
+    async fn init_chain(&mut self, app_state: &genesis::AppState) -> Result<()> {
+        Component::init_chain(self, app_state).await
+    }
+
+    async fn begin_block(&mut self, begin_block: &abci::request::BeginBlock) -> Result<()> {
+        Component::begin_block(self, begin_block).await
+    }
+
+    async fn check_tx_stateful(&self, tx: &Transaction) -> Result<()> {
+        Component::check_tx_stateful(self, tx).await
+    }
+
+    async fn execute_tx(&mut self, tx: &Transaction) -> Result<()> {
+        Component::execute_tx(self, tx).await
+    }
+
+    async fn end_block(&mut self, end_block: &abci::request::EndBlock) -> Result<()> {
+        Component::end_block(self, end_block).await
+    }
+
+    fn take_events(&mut self) -> Vec<abci::Event> {
+        Component::take_events(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// How to construct one registered component, and check transactions
+/// against it without an instance.
+#[derive(Clone, Copy)]
+pub struct Registration {
+    /// The name the component is profiled and reported under.
+    pub name: &'static str,
+    new: fn(Overlay) -> BoxFuture<'static, Result<Box<dyn DynComponent>>>,
+    check_tx_stateless: fn(&Transaction) -> Result<()>,
+}
+
+impl Registration {
+    pub fn check_tx_stateless(&self, tx: &Transaction) -> Result<()> {
+        let _timer = profile::timer(self.name, "check_tx_stateless");
+        (self.check_tx_stateless)(tx)
+    }
+}
+
+fn new_boxed<C: Component + Send + Sync + 'static>(
+    overlay: Overlay,
+) -> BoxFuture<'static, Result<Box<dyn DynComponent>>> {
+    Box::pin(async move { Ok(Box::new(C::new(overlay).await?) as Box<dyn DynComponent>) })
+}
+
+/// The components of the application, in execution order.
+#[derive(Clone, Default)]
+pub struct Registry {
+    registrations: Vec<Registration>,
+}
+
+impl Registry {
+    /// Adds a component to be executed after those already registered.
+    pub fn register<C: Component + Send + Sync + 'static>(mut self, name: &'static str) -> Self {
+        self.registrations.push(Registration {
+            name,
+            new: new_boxed::<C>,
+            check_tx_stateless: C::check_tx_stateless,
+        });
+        self
+    }
+
+    pub fn registrations(&self) -> &[Registration] {
+        &self.registrations
+    }
+
+    /// The names of the registered components, in execution order.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.registrations
+            .iter()
+            .map(|registration| registration.name)
+    }
+
+    /// Constructs every registered component over the given overlay.
+    pub async fn instantiate(&self, overlay: Overlay) -> Result<Components> {
+        let mut components = Vec::with_capacity(self.registrations.len());
+        for registration in &self.registrations {
+            let component = Profiled {
+                name: registration.name,
+                component: (registration.new)(overlay.clone()).await?,
+            };
+            components.push((
+                registration.name,
+                Box::new(component) as Box<dyn DynComponent>,
+            ));
+        }
+        Ok(Components { components })
+    }
+}
+
+/// A component whose every phase is profiled under its registered name.
+struct Profiled {
+    name: &'static str,
+    component: Box<dyn DynComponent>,
+}
+
+#[async_trait]
+impl DynComponent for Profiled {
+    async fn init_chain(&mut self, app_state: &genesis::AppState) -> Result<()> {
+        let _timer = profile::timer(self.name, "init_chain");
+        self.component.init_chain(app_state).await
+    }
+
+    async fn begin_block(&mut self, begin_block: &abci::request::BeginBlock) -> Result<()> {
+        let _timer = profile::timer(self.name, "begin_block");
+        self.component.begin_block(begin_block).await
+    }
+
+    async fn check_tx_stateful(&self, tx: &Transaction) -> Result<()> {
+        let _timer = profile::timer(self.name, "check_tx_stateful");
+        self.component.check_tx_stateful(tx).await
+    }
+
+    async fn execute_tx(&mut self, tx: &Transaction) -> Result<()> {
+        let _timer = profile::timer(self.name, "execute_tx");
+        self.component.execute_tx(tx).await
+    }
+
+    async fn end_block(&mut self, end_block: &abci::request::EndBlock) -> Result<()> {
+        let _timer = profile::timer(self.name, "end_block");
+        self.component.end_block(end_block).await
+    }
+
+    fn take_events(&mut self) -> Vec<abci::Event> {
+        self.component.take_events()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self.component.as_any()
+    }
+}
+
+/// Instances of the registered components, in execution order.
+pub struct Components {
+    components: Vec<(&'static str, Box<dyn DynComponent>)>,
+}
+
+impl Components {
+    /// The instance of the component of type `C`, if one was registered.
+    pub fn get<C: Component + 'static>(&self) -> Option<&C> {
+        self.components
+            .iter()
+            .find_map(|(_, component)| component.as_any().downcast_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &dyn DynComponent)> {
+        self.components
+            .iter()
+            .map(|(name, component)| (*name, component.as_ref()))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&'static str, &mut Box<dyn DynComponent>)> {
+        self.components
+            .iter_mut()
+            .map(|(name, component)| (*name, component))
+    }
+}
@@ -4,6 +4,7 @@ use anyhow::{anyhow, Context, Result};
 use ark_ff::PrimeField;
 use async_trait::async_trait;
 use decaf377::{Fq, Fr};
+use futures::future::BoxFuture;
 use penumbra_chain::{sync::CompactBlock, KnownAssets, NoteSource};
 use penumbra_crypto::{
     asset::{self, Asset, Denom},
@@ -11,20 +12,31 @@ use penumbra_crypto::{
     merkle::{self, Frontier, NoteCommitmentTree, TreeExt},
     note, Address, Note, Nullifier, One, Value,
 };
-use penumbra_stake::{Epoch, STAKING_TOKEN_ASSET_ID};
+use penumbra_stake::STAKING_TOKEN_ASSET_ID;
+use penumbra_tct::{Block, Epoch, Eternity, Forget, Position};
 use penumbra_transaction::{action::output, Action, Transaction};
-use tendermint::abci;
+use tendermint::abci::{self, EventAttributeIndexExt as _};
 use tracing::instrument;
 
-use super::{app::View as _, staking::View as _, Component};
+use super::{
+    app::View as _,
+    key_schema::{KeySchema, StateKey},
+    staking::View as _,
+    Component,
+};
 use crate::{genesis, Overlay, OverlayExt};
 
 // Stub component
 pub struct ShieldedPool {
     overlay: Overlay,
     note_commitment_tree: NoteCommitmentTree,
+    /// The tiered commitment tree, which is only kept for the block and epoch
+    /// roots published in compact blocks, so forgets every commitment.
+    tct: Eternity,
     /// The in-progress CompactBlock representation of the ShieldedPool changes
     compact_block: CompactBlock,
+    /// Events to report to Tendermint for the current request.
+    events: Vec<abci::Event>,
 }
 
 #[async_trait]
@@ -32,16 +44,22 @@ impl Component for ShieldedPool {
     #[instrument(name = "shielded_pool", skip(overlay))]
     async fn new(overlay: Overlay) -> Result<Self> {
         let note_commitment_tree = Self::get_nct(&overlay).await?;
+        let tct = Self::get_tct(&overlay).await?;
 
         Ok(Self {
             overlay,
             note_commitment_tree,
+            tct,
             compact_block: Default::default(),
+            events: Vec::new(),
         })
     }
 
     #[instrument(name = "shielded_pool", skip(self, app_state))]
     async fn init_chain(&mut self, app_state: &genesis::AppState) -> Result<()> {
+        // The genesis notes make up the first block of the first epoch.
+        self.tct.insert_block(Block::new())?;
+
         for allocation in &app_state.allocations {
             tracing::info!(?allocation, "processing allocation");
 
@@ -74,7 +92,7 @@ impl Component for ShieldedPool {
         }
 
         self.compact_block.height = 0;
-        self.write_compactblock_and_nct().await?;
+        self.write_compactblock_and_nct(false).await?;
 
         Ok(())
     }
@@ -153,6 +171,9 @@ impl Component for ShieldedPool {
                 Action::ValidatorDefinition(_validator) => {
                     // Handled in the `Staking` component.
                 }
+                Action::FaucetClaim(_claim) => {
+                    // Handled in the `Faucet` component.
+                }
                 #[allow(unreachable_patterns)]
                 _ => {
                     return Err(anyhow::anyhow!("unsupported action"));
@@ -196,13 +217,29 @@ impl Component for ShieldedPool {
         } else {
          */
         for compact_output in tx.output_bodies() {
-            self.add_note(compact_output, source).await;
+            self.add_note(compact_output, source).await?;
+        }
+        // The faucet has already checked and recorded the claims.
+        for claim in tx.faucet_claims() {
+            self.mint_note(
+                Value {
+                    amount: claim.amount,
+                    asset_id: *STAKING_TOKEN_ASSET_ID,
+                },
+                &claim.address,
+                source,
+            )
+            .await?;
         }
         for spent_nullifier in tx.spent_nullifiers() {
             // We need to record the nullifier as spent in the JMT (to prevent
             // double spends), as well as in the CompactBlock (so that clients
             // can learn that their note was spent).
             self.overlay.spend_nullifier(spent_nullifier, source).await;
+            self.events.push(abci::Event::new(
+                "spend",
+                vec![("nullifier", spent_nullifier.to_string()).index()],
+            ));
             self.compact_block.nullifiers.push(spent_nullifier);
         }
         //}
@@ -225,11 +262,7 @@ impl Component for ShieldedPool {
         // TODO: should we calculate this here or include it directly within the PendingRewardNote
         // to prevent a potential mismatch between Staking and ShieldedPool?
         let source = NoteSource::FundingStreamReward {
-            epoch_index: Epoch::from_height(
-                self.compact_block.height,
-                self.overlay.get_epoch_duration().await?,
-            )
-            .index,
+            epoch_index: self.overlay.get_current_epoch().await?.index,
         };
 
         for note in notes.notes {
@@ -244,9 +277,14 @@ impl Component for ShieldedPool {
             .await?;
         }
 
-        self.write_compactblock_and_nct().await?;
+        let end_of_epoch = self.overlay.is_end_of_epoch().await?;
+        self.write_compactblock_and_nct(end_of_epoch).await?;
         Ok(())
     }
+
+    fn take_events(&mut self) -> Vec<abci::Event> {
+        std::mem::take(&mut self.events)
+    }
 }
 
 impl ShieldedPool {
@@ -327,36 +365,86 @@ impl ShieldedPool {
             },
             source,
         )
-        .await;
-
-        Ok(())
+        .await
     }
 
     #[instrument(skip(self, source, output_body))]
-    async fn add_note(&mut self, output_body: output::Body, source: NoteSource) {
+    async fn add_note(&mut self, output_body: output::Body, source: NoteSource) -> Result<()> {
         tracing::debug!(commitment = ?output_body.note_commitment, "appending to NCT in component");
-        // 1. Insert it into the NCT
+        // 1. Insert it into the NCT, and the TCT
         self.note_commitment_tree
             .append(&output_body.note_commitment);
+        self.tct
+            .insert(Forget, output_body.note_commitment.0)
+            .context("could not insert note commitment into the TCT")?;
         // 2. Record its source in the JMT
         self.overlay
             .set_note_source(&output_body.note_commitment, source)
             .await;
-        // 3. Finally, record it in the pending compact block.
+        // 3. Report it as an event.
+        self.events.push(abci::Event::new(
+            "output",
+            vec![("note_commitment", output_body.note_commitment.to_string()).index()],
+        ));
+        // 4. Finally, record it in the pending compact block.
         self.compact_block.outputs.push(output_body);
+        Ok(())
     }
 
+    /// Writes the compact block and the trees, and starts the next block in
+    /// the TCT, or the next epoch if this block ends the current one.
     #[instrument(skip(self))]
-    async fn write_compactblock_and_nct(&mut self) -> Result<()> {
+    async fn write_compactblock_and_nct(&mut self, end_of_epoch: bool) -> Result<()> {
+        // The finished block must sit where the chain's epochs say it does,
+        // since clients locate it in their own trees the same way:
+        let height = self.compact_block.height;
+        let epoch = self.overlay.get_current_epoch().await?;
+        let expected = Position::start_of_height(height, epoch.start_height, epoch.index)?;
+        let position = self.tct.position();
+        if (position.epoch(), position.block()) != (expected.epoch(), expected.block()) {
+            return Err(anyhow!(
+                "block {} is block {} of epoch {} in the TCT, but block {} of epoch {} on chain",
+                height,
+                position.block(),
+                position.epoch(),
+                expected.block(),
+                expected.epoch(),
+            ));
+        }
+        // Publish the roots of the finished block, and epoch, so that clients
+        // can insert them as they are, rather than recomputing them:
+        self.compact_block.block_root = self.tct.current_block_root();
+        if end_of_epoch {
+            self.compact_block.epoch_root = self.tct.current_epoch_root();
+            self.tct
+                .insert_epoch(Epoch::new())
+                .context("could not start the next epoch in the TCT")?;
+        }
+        self.tct
+            .insert_block(Block::new())
+            .context("could not start the next block in the TCT")?;
+        // Add the block's notes and nullifiers to the running totals:
+        let commitments = self.overlay.commitment_count().await?;
+        self.overlay
+            .put_commitment_count(commitments + self.compact_block.outputs.len() as u64)
+            .await;
+        let nullifiers = self.overlay.nullifier_count().await?;
+        self.overlay
+            .put_nullifier_count(nullifiers + self.compact_block.nullifiers.len() as u64)
+            .await;
         // Write the CompactBlock:
         self.overlay
             .set_compact_block(std::mem::take(&mut self.compact_block))
             .await;
         // and the note commitment tree data and anchor:
         self.overlay
-            .set_nct_anchor(self.compact_block.height, self.note_commitment_tree.root2())
+            .set_nct_anchor(height, self.note_commitment_tree.root2())
             .await;
         self.put_nct().await?;
+        self.overlay
+            .lock()
+            .await
+            .put(b"shielded_pool/tct_data".into(), self.tct.to_bytes());
 
         Ok(())
     }
@@ -389,6 +477,21 @@ impl ShieldedPool {
             Ok(NoteCommitmentTree::new(0))
         }
     }
+
+    /// Like [`get_nct`](Self::get_nct), for the TCT.
+    async fn get_tct(overlay: &Overlay) -> Result<Eternity> {
+        if let Ok(Some(bytes)) = overlay
+            .lock()
+            .await
+            .get(b"shielded_pool/tct_data".into())
+            .await
+        {
+            // The tree was written by this node, so can be trusted.
+            Eternity::from_bytes_trusted(&bytes).map_err(Into::into)
+        } else {
+            Ok(Eternity::new())
+        }
+    }
 }
 
 /// Extension trait providing read/write access to shielded pool data.
@@ -505,6 +608,27 @@ pub trait View: OverlayExt {
             .await
     }
 
+    /// The number of note commitments added to the note commitment tree since
+    /// genesis, including those of genesis allocations.
+    async fn commitment_count(&self) -> Result<u64> {
+        count(self, b"shielded_pool/stats/commitments".into()).await
+    }
+
+    async fn put_commitment_count(&self, count: u64) {
+        self.put_proto(b"shielded_pool/stats/commitments".into(), count)
+            .await
+    }
+
+    /// The number of nullifiers spent since genesis.
+    async fn nullifier_count(&self) -> Result<u64> {
+        count(self, b"shielded_pool/stats/nullifiers".into()).await
+    }
+
+    async fn put_nullifier_count(&self, count: u64) {
+        self.put_proto(b"shielded_pool/stats/nullifiers".into(), count)
+            .await
+    }
+
     async fn set_nct_anchor(&self, height: u64, anchor: merkle::Root) {
         tracing::debug!(?height, ?anchor, "writing anchor");
 
@@ -524,6 +648,12 @@ pub trait View: OverlayExt {
         .await;
     }
 
+    /// Returns the NCT anchor as of the end of the block at `height`, if any.
+    async fn nct_anchor(&self, height: u64) -> Result<Option<merkle::Root>> {
+        self.get_domain(format!("shielded_pool/nct_anchor/{}", height).into())
+            .await
+    }
+
     /// Checks whether a claimed NCT anchor is a previous valid state root.
     async fn check_claimed_anchor(&self, anchor: &merkle::Root) -> Result<()> {
         if let Some(anchor_height) = self
@@ -573,3 +703,85 @@ pub trait View: OverlayExt {
 }
 
 impl<T: OverlayExt> View for T {}
+
+/// Reads a running total, which is zero until it is first written.
+async fn count<T: OverlayExt>(overlay: &T, key: jmt::KeyHash) -> Result<u64> {
+    match overlay.get_proto(key).await {
+        Ok(count) => Ok(count.unwrap_or_default()),
+        // As for token supplies, the totals are first read at genesis, before
+        // the tree has a root.
+        Err(e) if e.downcast_ref::<jmt::MissingRootError>().is_some() => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// The keys the shielded pool writes, for the [`key_schema`](super::key_schema)
+/// registry.
+///
+/// Note sources and spent nullifiers are found from the compact blocks.
+pub(crate) const KEY_SCHEMA: KeySchema = KeySchema {
+    component: "shielded_pool",
+    keys: state_keys,
+};
+
+fn state_keys(overlay: &Overlay) -> BoxFuture<'_, Result<Vec<StateKey>>> {
+    Box::pin(async move {
+        let mut keys = vec![
+            StateKey::new("shielded_pool/nct_data", "shielded_pool/nct_data"),
+            StateKey::new("shielded_pool/tct_data", "shielded_pool/tct_data"),
+            StateKey::new("shielded_pool/known_assets", "shielded_pool/known_assets"),
+            StateKey::new(
+                "shielded_pool/stats/commitments",
+                "shielded_pool/stats/commitments",
+            ),
+            StateKey::new(
+                "shielded_pool/stats/nullifiers",
+                "shielded_pool/stats/nullifiers",
+            ),
+        ];
+
+        for asset in overlay.known_assets().await?.0 {
+            keys.push(StateKey::new(
+                "shielded_pool/assets/{asset_id}/denom",
+                format!("shielded_pool/assets/{}/denom", asset.id),
+            ));
+            keys.push(StateKey::new(
+                "shielded_pool/assets/{asset_id}/token_supply",
+                format!("shielded_pool/assets/{}/token_supply", asset.id),
+            ));
+        }
+
+        for height in 0..=overlay.get_block_height().await? {
+            keys.push(StateKey::new(
+                "shielded_pool/compact_block/{height}",
+                format!("shielded_pool/compact_block/{}", height),
+            ));
+            keys.push(StateKey::new(
+                "shielded_pool/nct_anchor/{height}",
+                format!("shielded_pool/nct_anchor/{}", height),
+            ));
+            if let Some(anchor) = overlay.nct_anchor(height).await? {
+                keys.push(StateKey::new(
+                    "shielded_pool/valid_anchors/{anchor}",
+                    format!("shielded_pool/valid_anchors/{}", anchor),
+                ));
+            }
+            if let Some(block) = overlay.compact_block(height).await? {
+                for output in &block.outputs {
+                    keys.push(StateKey::new(
+                        "shielded_pool/note_source/{note_commitment}",
+                        format!("shielded_pool/note_source/{}", output.note_commitment),
+                    ));
+                }
+                for nullifier in &block.nullifiers {
+                    keys.push(StateKey::new(
+                        "shielded_pool/spent_nullifiers/{nullifier}",
+                        format!("shielded_pool/spent_nullifiers/{}", nullifier),
+                    ));
+                }
+            }
+        }
+
+        Ok(keys)
+    })
+}
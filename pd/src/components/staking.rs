@@ -2,11 +2,13 @@ use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
-use penumbra_proto::Protobuf;
+use futures::future::BoxFuture;
+use penumbra_proto::{self as proto, Protobuf};
 use penumbra_stake::{
-    BaseRateData, Delegate, DelegationChanges, Epoch, IdentityKey, PendingRewardNote, RateData,
-    RewardNotes, Undelegate, Validator, ValidatorInfo, ValidatorList, ValidatorState,
-    ValidatorStatus, STAKING_TOKEN_ASSET_ID,
+    BaseRateData, CommissionPayouts, Delegate, DelegationChanges, Epoch, FundingStreamPayout,
+    IdentityKey, IssuanceSchedule, PendingRewardNote, RateData, RewardNotes, Undelegate, Validator,
+    ValidatorInfo, ValidatorSet, ValidatorSetEntry, ValidatorState, ValidatorStatus,
+    STAKING_TOKEN_ASSET_ID,
 };
 use penumbra_transaction::{Action, Transaction};
 
@@ -14,18 +16,45 @@ use tendermint::{
     abci::{
         self,
         types::{Evidence, ValidatorUpdate},
+        EventAttributeIndexExt as _,
     },
     block, PublicKey,
 };
 use tracing::instrument;
 
-use super::{app::View as _, shielded_pool::View as _, Component};
+use super::{
+    app::View as _,
+    key_schema::{KeySchema, StateKey},
+    shielded_pool::View as _,
+    Component,
+};
 use crate::{genesis, Overlay, OverlayExt};
 
 // Max validator power is 1152921504606846975 (i64::MAX / 8)
 // https://github.com/tendermint/tendermint/blob/master/types/validator_set.go#L25
 const MAX_VOTING_POWER: i64 = 1152921504606846975;
 
+/// The number of epochs of exchange rate history stored under each key.
+const EXCHANGE_RATE_CHUNK_LEN: u64 = 64;
+
+/// The number of validator set changes stored under each key of their index.
+const VALIDATOR_SET_CHANGE_CHUNK_LEN: u64 = 64;
+
+/// The ordered keyspace of every validator's identity key, so that adding a
+/// validator does not rewrite the list of all of them.
+const VALIDATORS_SPACE: &str = "staking/validators";
+
+/// A validator slashed by [`View::slash_validator`].
+pub struct Slashing {
+    pub identity_key: IdentityKey,
+    /// The state of the validator before it was slashed.
+    pub previous_state: ValidatorState,
+    /// The slashing penalty applied, in basis points.
+    pub penalty: u64,
+    /// The amount of stake burned from the validator's pool.
+    pub burned: u64,
+}
+
 // Staking component
 pub struct Staking {
     overlay: Overlay,
@@ -33,16 +62,82 @@ pub struct Staking {
     /// persisted at the end of the block for processing at the end of the next
     /// epoch.
     delegation_changes: DelegationChanges,
+    /// Events to report to Tendermint at the end of this block.
+    events: Vec<abci::Event>,
 }
 
 impl Staking {
+    /// Checks that the (un)delegations of a transaction, by validator, give no
+    /// validator they delegate to more than the chain's maximum share of the
+    /// total stake, once every (un)delegation pending in the epoch is applied.
+    async fn check_max_stake_share(&self, changes: &BTreeMap<IdentityKey, i64>) -> Result<()> {
+        let max_share = self
+            .overlay
+            .get_chain_params()
+            .await?
+            .max_validator_stake_share;
+        if max_share == 0 || changes.values().all(|change| *change <= 0) {
+            return Ok(());
+        }
+
+        // The delegation token supply of each validator at the end of the
+        // epoch: the current supply, changed by the (un)delegations of the
+        // blocks ended so far in the epoch, those of the current block (or,
+        // in the mempool, of the pending transactions), and the transaction's.
+        let mut supplies = BTreeMap::new();
+        for v in self.overlay.validator_list().await? {
+            let supply = self
+                .overlay
+                .token_supply(&v.delegation_token().id())
+                .await?
+                .unwrap_or(0);
+            supplies.insert(v, supply as i64);
+        }
+        let mut apply = |pending: &DelegationChanges| {
+            for d in &pending.delegations {
+                *supplies.entry(d.validator_identity.clone()).or_insert(0) +=
+                    d.delegation_amount as i64;
+            }
+            for u in &pending.undelegations {
+                *supplies.entry(u.validator_identity.clone()).or_insert(0) -=
+                    u.delegation_amount as i64;
+            }
+        };
+        let epoch = self.overlay.get_current_epoch().await?;
+        let height = self.overlay.get_block_height().await?;
+        for height in epoch.start_height..=height {
+            if let Some(pending) = self.overlay.recorded_delegation_changes(height).await? {
+                apply(&pending);
+            }
+        }
+        apply(&self.delegation_changes);
+        for (v, change) in changes {
+            *supplies.entry(v.clone()).or_insert(0) += change;
+        }
+
+        let mut stakes = BTreeMap::new();
+        for (v, supply) in supplies {
+            let rate = self.overlay.next_validator_rate(&v).await?.ok_or_else(|| {
+                anyhow::anyhow!("validator had ID in validator_list but rate not found in JMT")
+            })?;
+            stakes.insert(v, rate.unbonded_amount(supply.max(0) as u64));
+        }
+        let delegated_to = changes
+            .iter()
+            .filter(|(_, change)| **change > 0)
+            .map(|(v, _)| v.clone())
+            .collect::<Vec<_>>();
+        check_stake_share(&stakes, &delegated_to, max_share)
+    }
+
     #[instrument(skip(self, epoch_to_end), fields(index = epoch_to_end.index))]
     async fn end_epoch(&mut self, epoch_to_end: Epoch) -> Result<()> {
         // calculate rate data for next rate, move previous next rate to cur rate,
         // and save the next rate data. ensure that non-Active validators maintain constant rates.
         let mut delegations_by_validator = BTreeMap::<IdentityKey, Vec<Delegate>>::new();
         let mut undelegations_by_validator = BTreeMap::<IdentityKey, Vec<Undelegate>>::new();
-        for height in epoch_to_end.start_height().value()..=epoch_to_end.end_height().value() {
+        let end_height = self.overlay.get_block_height().await?;
+        for height in epoch_to_end.start_height..=end_height {
             let changes = self
                 .overlay
                 .delegation_changes(height.try_into().unwrap())
@@ -80,7 +175,11 @@ impl Staking {
         // update "next_base_rate".
         let current_base_rate = self.overlay.next_base_rate().await?;
 
-        let next_base_rate = current_base_rate.next(chain_params.base_reward_rate);
+        let next_base_rate = current_base_rate.next(&IssuanceSchedule {
+            initial_base_reward_rate: chain_params.base_reward_rate,
+            base_reward_rate_decay: chain_params.base_reward_rate_decay,
+            min_base_reward_rate: chain_params.min_base_reward_rate,
+        });
 
         // rename to curr_rate so it lines up with next_rate (same # chars)
         tracing::debug!(curr_base_rate = ?current_base_rate);
@@ -105,6 +204,20 @@ impl Staking {
             let validator_state = self.overlay.validator_state(v).await?.ok_or_else(|| {
                 anyhow::anyhow!("validator had ID in validator_list but state not found in JMT")
             })?;
+            // The ending epoch's rate and delegation token supply, from which
+            // the validator's pool is revalued.
+            let previous_rate = self
+                .overlay
+                .current_validator_rate(v)
+                .await?
+                .ok_or_else(|| {
+                    anyhow::anyhow!("validator had ID in validator_list but rate not found in JMT")
+                })?;
+            let previous_supply = self
+                .overlay
+                .token_supply(&v.delegation_token().id())
+                .await?
+                .unwrap_or(0);
             tracing::debug!(?validator, "processing validator rate updates");
 
             let funding_streams = validator.funding_streams;
@@ -150,6 +263,16 @@ impl Staking {
             self.overlay
                 .update_token_supply(&STAKING_TOKEN_ASSET_ID, staking_delta)
                 .await?;
+            // the staking tokens removed from the supply are added to the pool
+            self.overlay
+                .update_pool_size(
+                    v,
+                    previous_supply,
+                    &previous_rate,
+                    &current_rate,
+                    -staking_delta,
+                )
+                .await?;
 
             let delegation_token_supply = self
                 .overlay
@@ -166,7 +289,7 @@ impl Staking {
             // with the newly starting epoch's calculated voting rate and power.
             self.overlay
                 .set_validator_rates(v, current_rate.clone(), next_rate.clone())
-                .await;
+                .await?;
             self.overlay.set_validator_power(v, voting_power).await?;
 
             // Only Active validators produce commission rewards
@@ -175,6 +298,7 @@ impl Staking {
             // should still be rewarded.
             if validator_state == ValidatorState::Active {
                 // distribute validator commission
+                let mut payouts = Vec::new();
                 for stream in funding_streams {
                     let commission_reward_amount = stream.reward_amount(
                         delegation_token_supply,
@@ -188,7 +312,19 @@ impl Staking {
                         amount: commission_reward_amount,
                         destination: stream.address,
                     });
+                    payouts.push(FundingStreamPayout {
+                        address: stream.address,
+                        rate_bps: stream.rate_bps,
+                        amount: commission_reward_amount,
+                    });
                 }
+
+                self.record_commission_payouts(CommissionPayouts {
+                    identity_key: v.clone(),
+                    epoch_index: epoch_to_end.index,
+                    payouts,
+                })
+                .await;
             }
 
             // rename to curr_rate so it lines up with next_rate (same # chars)
@@ -202,8 +338,13 @@ impl Staking {
 
         // Now that all the voting power has been calculated for the upcoming epoch,
         // we can determine which validators are Active for the next epoch.
-        self.process_epoch_transitions(epoch_to_end, active_validator_limit, unbonding_epochs)
-            .await?;
+        self.process_epoch_transitions(
+            epoch_to_end,
+            active_validator_limit,
+            unbonding_epochs,
+            chain_params.min_validator_bond_epochs,
+        )
+        .await?;
 
         // The pending delegation changes should be empty at the beginning of the next epoch.
         self.delegation_changes = Default::default();
@@ -222,6 +363,54 @@ impl Staking {
         Ok(())
     }
 
+    /// Records the commission paid out to a validator's funding streams, and
+    /// reports each payout as an event.
+    async fn record_commission_payouts(&mut self, payouts: CommissionPayouts) {
+        for payout in &payouts.payouts {
+            self.events.push(abci::Event::new(
+                "commission_payout",
+                vec![
+                    ("validator", payouts.identity_key.to_string()).index(),
+                    ("epoch", payouts.epoch_index.to_string()).index(),
+                    ("address", payout.address.to_string()).no_index(),
+                    ("rate_bps", payout.rate_bps.to_string()).no_index(),
+                    ("amount", payout.amount.to_string()).no_index(),
+                ],
+            ));
+        }
+        self.overlay.set_commission_payouts(payouts).await;
+    }
+
+    /// Moves a validator from state `from` to state `to`, and reports the
+    /// transition as an event.
+    async fn transition(
+        &mut self,
+        identity_key: &IdentityKey,
+        from: ValidatorState,
+        to: ValidatorState,
+    ) {
+        self.overlay.set_validator_state(identity_key, to).await;
+        self.record_transition(identity_key, from, to);
+    }
+
+    fn record_transition(
+        &mut self,
+        identity_key: &IdentityKey,
+        from: ValidatorState,
+        to: ValidatorState,
+    ) {
+        let mut attributes = vec![
+            ("validator", identity_key.to_string()).index(),
+            ("from", from.name().to_str().to_string()).index(),
+            ("to", to.name().to_str().to_string()).index(),
+        ];
+        if let ValidatorState::Unbonding { unbonding_epoch } = to {
+            attributes.push(("unbonding_epoch", unbonding_epoch.to_string()).no_index());
+        }
+        self.events
+            .push(abci::Event::new("validator_state_change", attributes));
+    }
+
     /// Called during `end_epoch`. Will perform state transitions to validators based
     /// on changes to voting power that occurred in this epoch.
     pub async fn process_epoch_transitions(
@@ -229,6 +418,7 @@ impl Staking {
         epoch_to_end: Epoch,
         active_validator_limit: u64,
         unbonding_epochs: u64,
+        min_validator_bond_epochs: u64,
     ) -> Result<()> {
         // Sort the next validator states by voting power.
         struct VPower {
@@ -256,14 +446,44 @@ impl Staking {
             });
         }
 
-        // Sort by voting power
-        validator_power_list.sort_by(|a, b| a.power.cmp(&b.power));
+        // Sort by voting power, highest first. The sort is stable, so ties are
+        // broken by identity key, the order of the validator list.
+        validator_power_list.sort_by(|a, b| b.power.cmp(&a.power));
+
+        // Active validators which have not yet served the minimum bond
+        // duration keep their place in the active set regardless of voting
+        // power, so that validators cannot cycle in and out of it. Validators
+        // whose delegations were all withdrawn have nothing left bonded, so
+        // they compete for a place like any other.
+        let next_epoch_index = epoch_to_end.index + 1;
+        let mut bonded_validators = Vec::new();
+        for vp in &validator_power_list {
+            if vp.state != ValidatorState::Active || vp.power == 0 {
+                continue;
+            }
+            if let Some(bonded_since) = self
+                .overlay
+                .validator_bonded_since(&vp.identity_key)
+                .await?
+            {
+                if next_epoch_index < bonded_since + min_validator_bond_epochs {
+                    bonded_validators.push(vp.identity_key.clone());
+                }
+            }
+        }
 
-        // Grab the top `active_validator_limit` validators
-        let top_validators = validator_power_list
+        // Grab the top `active_validator_limit` validators, starting with the
+        // ones which must remain bonded.
+        let top_validators = bonded_validators
             .iter()
+            .chain(
+                validator_power_list
+                    .iter()
+                    .map(|v| &v.identity_key)
+                    .filter(|ik| !bonded_validators.contains(ik)),
+            )
             .take(active_validator_limit as usize)
-            .map(|v| v.identity_key.clone())
+            .cloned()
             .collect::<Vec<_>>();
 
         // Iterate every validator and update according to their state and voting power.
@@ -275,8 +495,10 @@ impl Staking {
                 // on voting power and the delegation pool has a nonzero balance (meaning non-zero voting power),
                 // then the validator should be moved to the Active state.
                 if top_validators.contains(&vp.identity_key) && vp.power > 0 {
+                    self.transition(&vp.identity_key, vp.state, ValidatorState::Active)
+                        .await;
                     self.overlay
-                        .set_validator_state(&vp.identity_key, ValidatorState::Active)
+                        .set_validator_bonded_since(&vp.identity_key, next_epoch_index)
                         .await;
                 }
             } else if vp.state == ValidatorState::Active {
@@ -288,14 +510,14 @@ impl Staking {
                     self.overlay
                         .set_validator_power(&vp.identity_key, 0)
                         .await?;
-                    self.overlay
-                        .set_validator_state(
-                            &vp.identity_key,
-                            ValidatorState::Unbonding {
-                                unbonding_epoch: unbonding_epochs,
-                            },
-                        )
-                        .await;
+                    self.transition(
+                        &vp.identity_key,
+                        vp.state,
+                        ValidatorState::Unbonding {
+                            unbonding_epoch: unbonding_epochs,
+                        },
+                    )
+                    .await;
                 }
             }
 
@@ -303,8 +525,7 @@ impl Staking {
             // and the validator is still in Unbonding state
             if let ValidatorState::Unbonding { unbonding_epoch } = vp.state {
                 if unbonding_epoch <= epoch_to_end.index {
-                    self.overlay
-                        .set_validator_state(&vp.identity_key, ValidatorState::Inactive)
+                    self.transition(&vp.identity_key, vp.state, ValidatorState::Inactive)
                         .await;
                 }
             };
@@ -318,7 +539,48 @@ impl Staking {
         // Return the voting power for all known validators.
         // This isn't strictly necessary because tendermint technically expects
         // an update, however it is useful for debugging.
-        let mut updates = Vec::new();
+        self.active_validator_set()
+            .await?
+            .0
+            .into_iter()
+            .map(|entry| {
+                Ok(ValidatorUpdate {
+                    pub_key: entry.consensus_key,
+                    power: entry.power.try_into()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the changes to Tendermint's validator set made by the current
+    /// block, to be reported in the `EndBlock` response.
+    ///
+    /// This compares the validator sets recorded at the end of the previous
+    /// block and of this one, so must be called after `end_block`.
+    pub async fn validator_set_updates(&self) -> Result<Vec<ValidatorUpdate>> {
+        let height = self.overlay.get_block_height().await?;
+        let current = self
+            .overlay
+            .validator_set_at_height(height)
+            .await?
+            .ok_or_else(|| anyhow!("no validator set recorded at height {}", height))?;
+        // Chains which began before validator sets were recorded have no
+        // previous set at first, so report every validator, which leaves
+        // Tendermint's view of the unchanged ones as it was.
+        let previous = match height.checked_sub(1) {
+            Some(previous_height) => self
+                .overlay
+                .validator_set_at_height(previous_height)
+                .await?
+                .unwrap_or_default(),
+            None => Default::default(),
+        };
+        validator_updates(&previous, &current)
+    }
+
+    /// Returns the validators whose voting power is currently reported to Tendermint.
+    async fn active_validator_set(&self) -> Result<ValidatorSet> {
+        let mut entries = Vec::new();
         for v in self.overlay.validator_list().await?.iter() {
             let validator_state = self
                 .overlay
@@ -326,9 +588,9 @@ impl Staking {
                 .await?
                 .ok_or_else(|| anyhow::anyhow!("validator state missing"))?;
 
-            // Only active validators report power to tendermint.
-            // TODO: actually this isn't quite right because Slashed validators
-            // need to report a 0 to Tendermint.
+            // Only active validators report power to tendermint. Validators
+            // leaving the active set, including slashed ones, are reported
+            // with 0 power by `validator_set_updates`.
             if validator_state != ValidatorState::Active {
                 continue;
             }
@@ -344,13 +606,22 @@ impl Staking {
                 .await?
                 .ok_or_else(|| anyhow::anyhow!("validator missing"))?;
 
-            updates.push(ValidatorUpdate {
-                pub_key: validator.consensus_key.clone(),
-                power: power.try_into()?,
+            entries.push(ValidatorSetEntry {
+                identity_key: v.clone(),
+                consensus_key: validator.consensus_key.clone(),
+                power,
             });
         }
 
-        Ok(updates)
+        Ok(ValidatorSet(entries))
+    }
+
+    /// Records the active validator set as of the end of the block at `height`.
+    async fn record_validator_set(&self, height: u64) -> Result<()> {
+        let validator_set = self.active_validator_set().await?;
+        self.overlay
+            .set_validator_set_at_height(height, validator_set)
+            .await
     }
 }
 
@@ -361,28 +632,37 @@ impl Component for Staking {
         Ok(Self {
             overlay,
             delegation_changes: Default::default(),
+            events: Vec::new(),
         })
     }
 
     #[instrument(name = "staking", skip(self, app_state))]
     async fn init_chain(&mut self, app_state: &genesis::AppState) -> Result<()> {
         let starting_height = self.overlay.get_block_height().await?;
-        let starting_epoch =
-            Epoch::from_height(starting_height, self.overlay.get_epoch_duration().await?);
-        let epoch_index = starting_epoch.index;
+        let epoch_index = self.overlay.get_current_epoch().await?.index;
 
         // Delegations require knowing the rates for the next epoch, so
-        // pre-populate with 0 reward => exchange rate 1 for the current
-        // (index 0) and next (index 1) epochs for base rate data.
+        // pre-populate with 0 reward for the current (index 0) and next (index 1)
+        // epochs for base rate data. Unless the genesis state says otherwise
+        // (e.g., when restarting from exported state), the exchange rate is 1.
+        let base_exchange_rate = match &app_state.base_rate_data {
+            Some(base_rate_data) => base_rate_data.base_exchange_rate,
+            None => 1_0000_0000,
+        };
+        if base_exchange_rate == 0 {
+            return Err(anyhow::anyhow!(
+                "genesis base exchange rate must be nonzero"
+            ));
+        }
         let cur_base_rate = BaseRateData {
             epoch_index,
             base_reward_rate: 0,
-            base_exchange_rate: 1_0000_0000,
+            base_exchange_rate,
         };
         let next_base_rate = BaseRateData {
             epoch_index: epoch_index + 1,
             base_reward_rate: 0,
-            base_exchange_rate: 1_0000_0000,
+            base_exchange_rate,
         };
         self.overlay
             .set_base_rates(cur_base_rate.clone(), next_base_rate)
@@ -400,6 +680,35 @@ impl Component for Staking {
             *amount += allocation.amount;
         }
 
+        let mut genesis_exchange_rates = BTreeMap::new();
+        for rate_data in &app_state.validator_rates {
+            if !app_state
+                .validators
+                .iter()
+                .any(|v| v.identity_key == rate_data.identity_key)
+            {
+                return Err(anyhow::anyhow!(
+                    "genesis rate data for unknown validator {}",
+                    rate_data.identity_key
+                ));
+            }
+            if rate_data.validator_exchange_rate == 0 {
+                return Err(anyhow::anyhow!(
+                    "genesis exchange rate for validator {} must be nonzero",
+                    rate_data.identity_key
+                ));
+            }
+            if genesis_exchange_rates
+                .insert(&rate_data.identity_key, rate_data.validator_exchange_rate)
+                .is_some()
+            {
+                return Err(anyhow::anyhow!(
+                    "duplicate genesis rate data for validator {}",
+                    rate_data.identity_key
+                ));
+            }
+        }
+
         // Add initial validators to the JMT
         // Validators are indexed in the JMT by their public key,
         // and there is a separate key containing the list of all validator keys.
@@ -407,19 +716,23 @@ impl Component for Staking {
             let validator_key = validator.identity_key.clone();
 
             // Delegations require knowing the rates for the
-            // next epoch, so pre-populate with 0 reward => exchange rate 1 for
-            // the current and next epochs.
+            // next epoch, so pre-populate with 0 reward for the current and next
+            // epochs, using the genesis exchange rate if there is one, or 1 otherwise.
+            let validator_exchange_rate = genesis_exchange_rates
+                .get(&validator_key)
+                .copied()
+                .unwrap_or(1_0000_0000); // 1 represented as 1e8
             let cur_rate_data = RateData {
                 identity_key: validator_key.clone(),
                 epoch_index,
                 validator_reward_rate: 0,
-                validator_exchange_rate: 1_0000_0000, // 1 represented as 1e8
+                validator_exchange_rate,
             };
             let next_rate_data = RateData {
                 identity_key: validator_key.clone(),
                 epoch_index: epoch_index + 1,
                 validator_reward_rate: 0,
-                validator_exchange_rate: 1_0000_0000, // 1 represented as 1e8
+                validator_exchange_rate,
             };
 
             // The initial allocations to the validator are not available on the JMT yet,
@@ -444,8 +757,13 @@ impl Component for Staking {
                     power,
                 )
                 .await?;
+            self.overlay
+                .set_validator_bonded_since(&validator_key, epoch_index)
+                .await;
         }
 
+        self.record_validator_set(starting_height).await?;
+
         // Finally, record that there were no delegations in this block, so the data
         // isn't missing when we process the first epoch transition.
         self.overlay
@@ -462,7 +780,21 @@ impl Component for Staking {
         // For each validator identified as byzantine by tendermint, update its
         // state to be slashed.
         for evidence in begin_block.byzantine_validators.iter() {
-            self.overlay.slash_validator(evidence).await?;
+            let slashing = self.overlay.slash_validator(evidence).await?;
+            self.events.push(abci::Event::new(
+                "slash",
+                vec![
+                    ("validator", slashing.identity_key.to_string()).index(),
+                    ("height", evidence.height.to_string()).index(),
+                    ("penalty_bps", slashing.penalty.to_string()).no_index(),
+                    ("burned", slashing.burned.to_string()).no_index(),
+                ],
+            ));
+            self.record_transition(
+                &slashing.identity_key,
+                slashing.previous_state,
+                ValidatorState::Slashed,
+            );
         }
 
         Ok(())
@@ -667,6 +999,8 @@ impl Component for Staking {
             // the validator definition has now passed all verification checks
         }
 
+        self.check_max_stake_share(&delegation_changes).await?;
+
         Ok(())
     }
 
@@ -677,10 +1011,28 @@ impl Component for Staking {
             match action {
                 Action::Delegate(d) => {
                     tracing::debug!(?d, "queuing delegation for next epoch");
+                    self.events.push(abci::Event::new(
+                        "delegate",
+                        vec![
+                            ("validator", d.validator_identity.to_string()).index(),
+                            ("epoch", d.epoch_index.to_string()).index(),
+                            ("unbonded_amount", d.unbonded_amount.to_string()).no_index(),
+                            ("delegation_amount", d.delegation_amount.to_string()).no_index(),
+                        ],
+                    ));
                     self.delegation_changes.delegations.push(d.clone());
                 }
                 Action::Undelegate(u) => {
                     tracing::debug!(?u, "queuing undelegation for next epoch");
+                    self.events.push(abci::Event::new(
+                        "undelegate",
+                        vec![
+                            ("validator", u.validator_identity.to_string()).index(),
+                            ("epoch", u.epoch_index.to_string()).index(),
+                            ("unbonded_amount", u.unbonded_amount.to_string()).no_index(),
+                            ("delegation_amount", u.delegation_amount.to_string()).no_index(),
+                        ],
+                    ));
                     self.delegation_changes.undelegations.push(u.clone());
                 }
                 _ => {}
@@ -753,12 +1105,115 @@ impl Component for Staking {
         let cur_epoch = self.overlay.get_current_epoch().await?;
         let cur_height = self.overlay.get_block_height().await?;
 
-        if cur_epoch.is_epoch_end(cur_height) {
+        if self.overlay.is_end_of_epoch().await? {
             self.end_epoch(cur_epoch).await?;
         }
 
+        self.record_validator_set(cur_height).await?;
+
+        for v in self.overlay.validator_list().await? {
+            if let Some(violation) = self.overlay.pool_invariant_violation(&v).await? {
+                report_invariant_violation(&violation);
+            }
+        }
+
         Ok(())
     }
+
+    fn take_events(&mut self) -> Vec<abci::Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// The updates which change Tendermint's validator set from `previous` to
+/// `current`: the voting power of every validator joining the set, or whose
+/// power changed, and a power of 0 for every validator leaving it.
+///
+/// Tendermint identifies validators by consensus key, so a validator whose
+/// consensus key changed leaves the set under its old key and joins it under
+/// its new one. Validators with no voting power are not part of the set, since
+/// Tendermint rejects updates adding a validator with no power.
+pub fn validator_updates(
+    previous: &ValidatorSet,
+    current: &ValidatorSet,
+) -> Result<Vec<ValidatorUpdate>> {
+    // Ordered by consensus key, so that the updates are deterministic.
+    let powers = |set: &ValidatorSet| {
+        set.0
+            .iter()
+            .filter(|entry| entry.power > 0)
+            .map(|entry| {
+                (
+                    entry.consensus_key.to_bytes(),
+                    (entry.consensus_key, entry.power),
+                )
+            })
+            .collect::<BTreeMap<_, _>>()
+    };
+    let (previous, current) = (powers(previous), powers(current));
+
+    let mut updates = Vec::new();
+    for (key, (pub_key, power)) in &current {
+        if previous.get(key).map(|(_, power)| power) != Some(power) {
+            updates.push(ValidatorUpdate {
+                pub_key: *pub_key,
+                power: (*power).try_into()?,
+            });
+        }
+    }
+    for (key, (pub_key, _)) in &previous {
+        if !current.contains_key(key) {
+            updates.push(ValidatorUpdate {
+                pub_key: *pub_key,
+                power: 0u64.try_into()?,
+            });
+        }
+    }
+    Ok(updates)
+}
+
+/// Checks that none of the validators `delegated_to` holds more than
+/// `max_share` basis points of the total stake, given the stake of every
+/// validator.
+fn check_stake_share(
+    stakes: &BTreeMap<IdentityKey, u64>,
+    delegated_to: &[IdentityKey],
+    max_share: u64,
+) -> Result<()> {
+    let total = stakes.values().map(|stake| *stake as u128).sum::<u128>();
+    for v in delegated_to {
+        let stake = stakes.get(v).copied().unwrap_or(0) as u128;
+        if stake * 1_0000 > total * max_share as u128 {
+            return Err(anyhow!(
+                "delegation would give validator {} {:.2}% of the total stake, over the maximum of {:.2}%",
+                v,
+                stake as f64 * 100.0 / total as f64,
+                max_share as f64 / 100.0
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Reports a violated staking invariant: fatally in debug builds, so that
+/// rate-math drift is caught in development, and otherwise as an error and a
+/// metric to alert on, since halting a production node would not undo it.
+fn report_invariant_violation(violation: &str) {
+    tracing::error!(%violation, "staking invariant violated");
+    metrics::increment_counter!("node_stake_invariant_violations_total");
+    debug_assert!(false, "staking invariant violated: {}", violation);
+}
+
+/// The staking tokens bonded to a validator, tracked independently of its
+/// delegation token supply and exchange rate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolSize {
+    /// The amount of staking tokens in the pool.
+    pub amount: u64,
+    /// The number of incremental updates made to the pool since it was first
+    /// recorded, each of which may round differently than the exchange rate
+    /// computation by one unit.
+    pub updates: u64,
 }
 
 /// Extension trait providing read/write access to staking data.
@@ -823,14 +1278,22 @@ pub trait View: OverlayExt {
             .await
     }
 
+    /// Sets the validator's rates for the current and next epochs, recording
+    /// the current exchange rate in the validator's rate history.
     #[instrument(skip(self))]
     async fn set_validator_rates(
         &self,
         identity_key: &IdentityKey,
         current_rates: RateData,
         next_rates: RateData,
-    ) {
+    ) -> Result<()> {
         tracing::debug!("setting validator rates");
+        self.record_exchange_rate(
+            identity_key,
+            current_rates.epoch_index,
+            current_rates.validator_exchange_rate,
+        )
+        .await?;
         self.put_domain(
             format!("staking/validators/{}/rate/current", identity_key).into(),
             current_rates,
@@ -841,6 +1304,163 @@ pub trait View: OverlayExt {
             next_rates,
         )
         .await;
+        Ok(())
+    }
+
+    /// Records the validator's exchange rate in an epoch, replacing any rate
+    /// already recorded for that epoch.
+    ///
+    /// The history is stored in chunks of [`EXCHANGE_RATE_CHUNK_LEN`] epochs, so
+    /// that it takes one key per chunk rather than one key per epoch.
+    async fn record_exchange_rate(
+        &self,
+        identity_key: &IdentityKey,
+        epoch_index: u64,
+        exchange_rate: u64,
+    ) -> Result<()> {
+        let key = exchange_rate_chunk_key(identity_key, epoch_index);
+        let mut chunk: proto::stake::ExchangeRateChunk =
+            self.get_proto(key).await?.unwrap_or_default();
+
+        let offset = (epoch_index % EXCHANGE_RATE_CHUNK_LEN) as usize;
+        if chunk.exchange_rates.len() <= offset {
+            chunk.exchange_rates.resize(offset + 1, 0);
+        }
+        chunk.exchange_rates[offset] = exchange_rate;
+
+        self.put_proto(key, chunk).await;
+        Ok(())
+    }
+
+    /// The validator's exchange rate in the given epoch, if it was defined then
+    /// and the epoch has started.
+    async fn exchange_rate(
+        &self,
+        identity_key: &IdentityKey,
+        epoch_index: u64,
+    ) -> Result<Option<u64>> {
+        let chunk: Option<proto::stake::ExchangeRateChunk> = self
+            .get_proto(exchange_rate_chunk_key(identity_key, epoch_index))
+            .await?;
+
+        let offset = (epoch_index % EXCHANGE_RATE_CHUNK_LEN) as usize;
+        Ok(chunk
+            .and_then(|chunk| chunk.exchange_rates.get(offset).copied())
+            .filter(|&rate| rate != 0))
+    }
+
+    /// The staking tokens bonded to the validator, if they have been recorded.
+    ///
+    /// Pools are first recorded at the end of the first epoch in which the
+    /// validator is known.
+    async fn pool_size(&self, identity_key: &IdentityKey) -> Result<Option<PoolSize>> {
+        let amount: Option<u64> = self
+            .get_proto(format!("staking/validators/{}/pool/amount", identity_key).into())
+            .await?;
+        let updates: Option<u64> = self
+            .get_proto(format!("staking/validators/{}/pool/updates", identity_key).into())
+            .await?;
+        Ok(amount.map(|amount| PoolSize {
+            amount,
+            updates: updates.unwrap_or(0),
+        }))
+    }
+
+    async fn set_pool_size(&self, identity_key: &IdentityKey, pool: PoolSize) {
+        self.put_proto(
+            format!("staking/validators/{}/pool/amount", identity_key).into(),
+            pool.amount,
+        )
+        .await;
+        self.put_proto(
+            format!("staking/validators/{}/pool/updates", identity_key).into(),
+            pool.updates,
+        )
+        .await;
+    }
+
+    /// Updates the validator's pool at an epoch transition: the pool accrues
+    /// the rewards earned by `previous_supply` delegation tokens as the
+    /// exchange rate moved from `previous_rate` to `rate`, and then changes by
+    /// `bonded`, the staking tokens delegated net of those undelegated.
+    ///
+    /// If the pool has not been recorded yet, it starts out as the value of
+    /// `previous_supply` at `previous_rate`.
+    async fn update_pool_size(
+        &self,
+        identity_key: &IdentityKey,
+        previous_supply: u64,
+        previous_rate: &RateData,
+        rate: &RateData,
+        bonded: i64,
+    ) -> Result<()> {
+        let pool = match self.pool_size(identity_key).await? {
+            Some(pool) => pool,
+            None => PoolSize {
+                amount: previous_rate.unbonded_amount(previous_supply),
+                updates: 0,
+            },
+        };
+
+        let rewards = rate.unbonded_amount(previous_supply) as i128
+            - previous_rate.unbonded_amount(previous_supply) as i128;
+        let amount =
+            u64::try_from(pool.amount as i128 + rewards + bonded as i128).map_err(|_| {
+                anyhow!(
+                    "pool of validator {} out of range after adding {} in rewards and {} bonded",
+                    identity_key,
+                    rewards,
+                    bonded
+                )
+            })?;
+
+        self.set_pool_size(
+            identity_key,
+            PoolSize {
+                amount,
+                updates: pool.updates + 1,
+            },
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Checks that the validator's delegation tokens, valued at its current
+    /// exchange rate, are backed by its pool, returning a description of the
+    /// discrepancy if they are not.
+    ///
+    /// The two may differ by one unit for every update of the pool, since each
+    /// update rounds separately.
+    async fn pool_invariant_violation(&self, identity_key: &IdentityKey) -> Result<Option<String>> {
+        let pool = match self.pool_size(identity_key).await? {
+            Some(pool) => pool,
+            None => return Ok(None),
+        };
+        let rate = self
+            .current_validator_rate(identity_key)
+            .await?
+            .ok_or_else(|| anyhow!("validator {} has a pool but no rate", identity_key))?;
+        let supply = self
+            .token_supply(&identity_key.delegation_token().id())
+            .await?
+            .unwrap_or(0);
+
+        let expected = rate.unbonded_amount(supply);
+        let drift = (pool.amount as i128 - expected as i128).abs();
+        if drift > pool.updates as i128 {
+            Ok(Some(format!(
+                "validator {} has {} delegation tokens worth {} staking tokens at exchange rate \
+                 {}, but its pool holds {} after {} updates",
+                identity_key,
+                supply,
+                expected,
+                rate.validator_exchange_rate,
+                pool.amount,
+                pool.updates
+            )))
+        } else {
+            Ok(None)
+        }
     }
 
     #[instrument(skip(self))]
@@ -853,6 +1473,20 @@ pub trait View: OverlayExt {
         .await;
     }
 
+    /// The index of the epoch in which the validator last entered the active set.
+    async fn validator_bonded_since(&self, identity_key: &IdentityKey) -> Result<Option<u64>> {
+        self.get_proto(format!("staking/validators/{}/bonded_since", identity_key).into())
+            .await
+    }
+
+    async fn set_validator_bonded_since(&self, identity_key: &IdentityKey, epoch_index: u64) {
+        self.put_proto(
+            format!("staking/validators/{}/bonded_since", identity_key).into(),
+            epoch_index,
+        )
+        .await;
+    }
+
     async fn validator(&self, identity_key: &IdentityKey) -> Result<Option<Validator>> {
         self.get_domain(format!("staking/validators/{}", identity_key).into())
             .await
@@ -877,7 +1511,7 @@ pub trait View: OverlayExt {
     }
 
     // TODO: move out of view? this seems more like business logic
-    async fn slash_validator(&mut self, evidence: &Evidence) -> Result<()> {
+    async fn slash_validator(&mut self, evidence: &Evidence) -> Result<Slashing> {
         let ck = tendermint::PublicKey::from_raw_ed25519(&evidence.validator.address)
             .ok_or_else(|| anyhow::anyhow!("invalid ed25519 consensus pubkey from tendermint"))
             .unwrap();
@@ -918,8 +1552,21 @@ pub trait View: OverlayExt {
                 anyhow::anyhow!("validator to be slashed did not have current rate in JMT")
             })?;
 
+        let unslashed_rate = cur_rate.clone();
         cur_rate = cur_rate.slash(slashing_penalty);
 
+        // The slashed stake is burned from the validator's pool.
+        let mut burned = 0;
+        if let Some(mut pool) = self.pool_size(&validator.identity_key).await? {
+            let supply = self
+                .token_supply(&validator.identity_key.delegation_token().id())
+                .await?
+                .unwrap_or(0);
+            burned = unslashed_rate.unbonded_amount(supply) - cur_rate.unbonded_amount(supply);
+            pool.amount = pool.amount.saturating_sub(burned);
+            self.set_pool_size(&validator.identity_key, pool).await;
+        }
+
         // TODO: would it be better to call `current_base_rate.next`? the same logic exists
         // within there, but it requires passing in the current base rates & funding streams,
         // which aren't actually used because the rate is held constant. So, doing it this way
@@ -930,9 +1577,14 @@ pub trait View: OverlayExt {
         next_rate.epoch_index += 1;
 
         self.set_validator_rates(&validator.identity_key, cur_rate, next_rate)
-            .await;
+            .await?;
 
-        Ok(())
+        Ok(Slashing {
+            identity_key: validator.identity_key,
+            previous_state: cur_state,
+            penalty: slashing_penalty,
+            burned,
+        })
     }
 
     // Used for updating an existing validator's definition.
@@ -967,16 +1619,12 @@ pub trait View: OverlayExt {
         self.register_denom(&id.delegation_token().denom()).await?;
 
         self.set_validator_rates(&id, current_rates, next_rates)
-            .await;
+            .await?;
         self.set_validator_state(&id, state).await;
         self.set_validator_power(&id, power).await?;
 
-        let mut validator_list = self.validator_list().await?;
-        validator_list.push(id);
-        tracing::debug!(?validator_list);
-        self.set_validator_list(validator_list).await;
-
-        Ok(())
+        self.put_ordered(VALIDATORS_SPACE, id.0.to_bytes().to_vec(), Vec::new())
+            .await
     }
 
     async fn validator_info(&self, identity_key: &IdentityKey) -> Result<Option<ValidatorInfo>> {
@@ -1016,24 +1664,155 @@ pub trait View: OverlayExt {
         }
     }
 
+    /// Every validator ever added, in order of their identity keys.
     async fn validator_list(&self) -> Result<Vec<IdentityKey>> {
+        self.prefix_range(VALIDATORS_SPACE, &[])
+            .await?
+            .into_iter()
+            .map(|(key, _)| proto::stake::IdentityKey { ik: key }.try_into())
+            .collect()
+    }
+
+    /// Records the validator set as of the end of the block at `height`.
+    ///
+    /// The set is only written when it differs from the previously recorded
+    /// one, in which case `height` is appended to an index of the heights at
+    /// which the set changed, so that unchanged blocks write nothing.
+    async fn set_validator_set_at_height(
+        &self,
+        height: u64,
+        validator_set: ValidatorSet,
+    ) -> Result<()> {
+        let count = self.validator_set_change_count().await?;
+        if let Some(last) = count.checked_sub(1) {
+            let changed_at = self.validator_set_change(last).await?;
+            if changed_at > height {
+                return Err(anyhow!(
+                    "validator set already recorded at height {}, after {}",
+                    changed_at,
+                    height
+                ));
+            }
+            let previous_set: Option<ValidatorSet> = self
+                .get_domain(format!("staking/validator_set/{}", changed_at).into())
+                .await?;
+            if previous_set.as_ref() == Some(&validator_set) {
+                return Ok(());
+            }
+            if changed_at == height {
+                self.put_domain(
+                    format!("staking/validator_set/{}", height).into(),
+                    validator_set,
+                )
+                .await;
+                return Ok(());
+            }
+        }
+
+        self.put_domain(
+            format!("staking/validator_set/{}", height).into(),
+            validator_set,
+        )
+        .await;
+        let key = validator_set_change_chunk_key(count);
+        let mut chunk: proto::stake::ValidatorSetChangeChunk =
+            self.get_proto(key).await?.unwrap_or_default();
+        chunk.heights.push(height);
+        self.put_proto(key, chunk).await;
+        self.put_proto("staking/validator_set/changes/count".into(), count + 1)
+            .await;
+
+        Ok(())
+    }
+
+    /// Returns the active validator set as of the end of the block at
+    /// `height`, found by bisecting the heights at which it changed, or `None`
+    /// if it was not recorded then or that block has not been committed.
+    async fn validator_set_at_height(&self, height: u64) -> Result<Option<ValidatorSet>> {
+        if height > self.get_block_height().await? {
+            return Ok(None);
+        }
+
+        // The number of changes at or before `height`.
+        let (mut low, mut high) = (0, self.validator_set_change_count().await?);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.validator_set_change(mid).await? <= height {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        let changed_at = match low.checked_sub(1) {
+            Some(index) => self.validator_set_change(index).await?,
+            None => return Ok(None),
+        };
+        self.get_domain(format!("staking/validator_set/{}", changed_at).into())
+            .await
+    }
+
+    /// The number of times the recorded validator set has changed.
+    async fn validator_set_change_count(&self) -> Result<u64> {
         Ok(self
-            .get_domain("staking/validators/list".into())
+            .get_proto("staking/validator_set/changes/count".into())
             .await?
-            .map(|list: ValidatorList| list.0)
             .unwrap_or_default())
     }
 
-    async fn set_validator_list(&self, validators: Vec<IdentityKey>) {
-        self.put_domain("staking/validators/list".into(), ValidatorList(validators))
-            .await;
+    /// The height of the validator set change with the given index.
+    async fn validator_set_change(&self, index: u64) -> Result<u64> {
+        let chunk: Option<proto::stake::ValidatorSetChangeChunk> = self
+            .get_proto(validator_set_change_chunk_key(index))
+            .await?;
+        chunk
+            .and_then(|chunk| {
+                let offset = (index % VALIDATOR_SET_CHANGE_CHUNK_LEN) as usize;
+                chunk.heights.get(offset).copied()
+            })
+            .ok_or_else(|| anyhow!("missing validator set change {}", index))
+    }
+
+    async fn set_commission_payouts(&self, payouts: CommissionPayouts) {
+        self.put_domain(
+            format!(
+                "staking/commission_payouts/{}/{}",
+                payouts.epoch_index, payouts.identity_key
+            )
+            .into(),
+            payouts,
+        )
+        .await
+    }
+
+    /// Returns the commission paid to the validator's funding streams for the given epoch.
+    ///
+    /// This is `None` if the validator was not active at the end of that epoch.
+    async fn commission_payouts(
+        &self,
+        identity_key: &IdentityKey,
+        epoch_index: u64,
+    ) -> Result<Option<CommissionPayouts>> {
+        self.get_domain(
+            format!(
+                "staking/commission_payouts/{}/{}",
+                epoch_index, identity_key
+            )
+            .into(),
+        )
+        .await
     }
 
     async fn delegation_changes(&self, height: block::Height) -> Result<DelegationChanges> {
-        Ok(self
-            .get_domain(format!("staking/delegation_changes/{}", height.value()).into())
+        self.recorded_delegation_changes(height.value())
             .await?
-            .ok_or_else(|| anyhow!("missing delegation changes for block {}", height))?)
+            .ok_or_else(|| anyhow!("missing delegation changes for block {}", height))
+    }
+
+    /// The delegation changes of the block at `height`, if it has ended.
+    async fn recorded_delegation_changes(&self, height: u64) -> Result<Option<DelegationChanges>> {
+        self.get_domain(format!("staking/delegation_changes/{}", height).into())
+            .await
     }
 
     async fn set_delegation_changes(&self, height: block::Height, changes: DelegationChanges) {
@@ -1056,3 +1835,698 @@ pub trait View: OverlayExt {
 }
 
 impl<T: OverlayExt + Send + Sync> View for T {}
+
+/// The key of the chunk of the validator's exchange rate history containing
+/// the given epoch.
+fn exchange_rate_chunk_key(identity_key: &IdentityKey, epoch_index: u64) -> jmt::KeyHash {
+    format!(
+        "staking/validators/{}/rate_history/{}",
+        identity_key,
+        epoch_index / EXCHANGE_RATE_CHUNK_LEN
+    )
+    .into()
+}
+
+/// The key of the chunk of the index of validator set changes containing the
+/// change with the given index.
+fn validator_set_change_chunk_key(index: u64) -> jmt::KeyHash {
+    format!(
+        "staking/validator_set/changes/{}",
+        index / VALIDATOR_SET_CHANGE_CHUNK_LEN
+    )
+    .into()
+}
+
+/// The keys the staking component writes, for the
+/// [`key_schema`](super::key_schema) registry.
+pub(crate) const KEY_SCHEMA: KeySchema = KeySchema {
+    component: "staking",
+    keys: state_keys,
+};
+
+fn state_keys(overlay: &Overlay) -> BoxFuture<'_, Result<Vec<StateKey>>> {
+    Box::pin(async move {
+        let mut keys: Vec<_> = ["staking/base_rate/current", "staking/base_rate/next"]
+            .into_iter()
+            .map(|key| StateKey::new(key, key))
+            .collect();
+        for (family, key) in overlay.ordered_state_keys(VALIDATORS_SPACE).await? {
+            keys.push(StateKey::new(family, key));
+        }
+
+        let epoch_index = overlay.get_current_epoch().await?.index;
+        for identity_key in overlay.validator_list().await? {
+            keys.push(StateKey::new(
+                "staking/validators/{identity_key}",
+                format!("staking/validators/{}", identity_key),
+            ));
+            for (family, suffix) in [
+                (
+                    "staking/validators/{identity_key}/rate/current",
+                    "rate/current",
+                ),
+                ("staking/validators/{identity_key}/rate/next", "rate/next"),
+                ("staking/validators/{identity_key}/power", "power"),
+                (
+                    "staking/validators/{identity_key}/pool/amount",
+                    "pool/amount",
+                ),
+                (
+                    "staking/validators/{identity_key}/pool/updates",
+                    "pool/updates",
+                ),
+                ("staking/validators/{identity_key}/state", "state"),
+                (
+                    "staking/validators/{identity_key}/bonded_since",
+                    "bonded_since",
+                ),
+            ] {
+                keys.push(StateKey::new(
+                    family,
+                    format!("staking/validators/{}/{}", identity_key, suffix),
+                ));
+            }
+            for chunk in 0..=epoch_index / EXCHANGE_RATE_CHUNK_LEN {
+                keys.push(StateKey::new(
+                    "staking/validators/{identity_key}/rate_history/{chunk}",
+                    format!("staking/validators/{}/rate_history/{}", identity_key, chunk),
+                ));
+            }
+            for epoch in 0..=epoch_index {
+                keys.push(StateKey::new(
+                    "staking/commission_payouts/{epoch_index}/{identity_key}",
+                    format!("staking/commission_payouts/{}/{}", epoch, identity_key),
+                ));
+            }
+            if let Some(validator) = overlay.validator(&identity_key).await? {
+                keys.push(StateKey::new(
+                    "staking/consensus_key/{consensus_key}",
+                    format!("staking/consensus_key/{}", validator.consensus_key.to_hex()),
+                ));
+            }
+        }
+
+        let changes = overlay.validator_set_change_count().await?;
+        keys.push(StateKey::new(
+            "staking/validator_set/changes/count",
+            "staking/validator_set/changes/count",
+        ));
+        for chunk in 0..=changes / VALIDATOR_SET_CHANGE_CHUNK_LEN {
+            keys.push(StateKey::new(
+                "staking/validator_set/changes/{chunk}",
+                format!("staking/validator_set/changes/{}", chunk),
+            ));
+        }
+        for index in 0..changes {
+            keys.push(StateKey::new(
+                "staking/validator_set/{height}",
+                format!(
+                    "staking/validator_set/{}",
+                    overlay.validator_set_change(index).await?
+                ),
+            ));
+        }
+
+        for height in 0..=overlay.get_block_height().await? {
+            for (family, prefix) in [
+                ("staking/delegation_changes/{height}", "delegation_changes"),
+                ("staking/reward_notes/{height}", "reward_notes"),
+            ] {
+                keys.push(StateKey::new(
+                    family,
+                    format!("staking/{}/{}", prefix, height),
+                ));
+            }
+        }
+
+        Ok(keys)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_stake::FundingStream;
+
+    use super::*;
+    use crate::testing::{address, delegations, spend_key, validator, Node};
+
+    fn identity_key() -> IdentityKey {
+        IdentityKey(
+            spend_key()
+                .full_viewing_key()
+                .spend_verification_key()
+                .clone(),
+        )
+    }
+
+    #[test]
+    fn rejects_delegations_past_the_maximum_share() {
+        let (a, b, c) = (identity_key(), identity_key(), identity_key());
+        let stakes = BTreeMap::from([(a.clone(), 500), (b.clone(), 300), (c.clone(), 200)]);
+
+        // At a maximum of 50%, a validator may hold exactly half of the stake...
+        check_stake_share(&stakes, &[a.clone(), b.clone()], 50_00).unwrap();
+        // ... but no more.
+        assert!(check_stake_share(&stakes, &[b.clone(), a.clone()], 49_99).is_err());
+        // Only validators being delegated to are held to the maximum.
+        check_stake_share(&stakes, &[b.clone(), c.clone()], 30_00).unwrap();
+        assert!(check_stake_share(&stakes, &[c.clone()], 19_99).is_err());
+
+        // A lone validator holds all of the stake, so only a maximum of 100%
+        // admits delegations to it.
+        let lone = BTreeMap::from([(a.clone(), 100)]);
+        assert!(check_stake_share(&lone, &[a.clone()], 99_99).is_err());
+        check_stake_share(&lone, &[a], 100_00).unwrap();
+    }
+
+    /// Starts a chain whose genesis validators are all active and bonded
+    /// since epoch 0, returning a staking component that sees them with the
+    /// given voting powers.
+    async fn active_validators(powers: &[u64]) -> Result<(Node, Staking, Vec<IdentityKey>)> {
+        let validators: Vec<_> = (0..powers.len())
+            .map(|i| validator(&i.to_string()))
+            .collect();
+        let node = Node::start(genesis::AppState {
+            allocations: delegations(&validators, 1_000_000, address()),
+            validators: validators.clone(),
+            ..Default::default()
+        })
+        .await?;
+        let overlay = node.storage().overlay().await?;
+        let identity_keys: Vec<_> = validators.into_iter().map(|v| v.identity_key).collect();
+        for (identity_key, &power) in identity_keys.iter().zip(powers) {
+            overlay.set_validator_power(identity_key, power).await?;
+        }
+        let staking = Staking::new(overlay).await?;
+        Ok((node, staking, identity_keys))
+    }
+
+    /// Starts a chain with `validators`, equally delegated, as its genesis
+    /// validators, returning a staking component for it.
+    async fn start_with(
+        validators: &[Validator],
+        app_state: genesis::AppState,
+    ) -> Result<(Node, Staking)> {
+        let node = Node::start(genesis::AppState {
+            allocations: delegations(validators, 1_000_000, address()),
+            validators: validators.to_vec(),
+            ..app_state
+        })
+        .await?;
+        let staking = Staking::new(node.storage().overlay().await?).await?;
+        Ok((node, staking))
+    }
+
+    /// The base exchange rate, and each validator's exchange rate, for the
+    /// current and next epochs.
+    async fn exchange_rates(
+        staking: &Staking,
+        validators: &[Validator],
+    ) -> Result<Vec<(u64, u64)>> {
+        let overlay = &staking.overlay;
+        let mut rates = vec![(
+            overlay.current_base_rate().await?.base_exchange_rate,
+            overlay.next_base_rate().await?.base_exchange_rate,
+        )];
+        for Validator { identity_key, .. } in validators {
+            let current = overlay.current_validator_rate(identity_key).await?.unwrap();
+            let next = overlay.next_validator_rate(identity_key).await?.unwrap();
+            rates.push((
+                current.validator_exchange_rate,
+                next.validator_exchange_rate,
+            ));
+        }
+        Ok(rates)
+    }
+
+    #[tokio::test]
+    async fn genesis_uses_supplied_rates() -> Result<()> {
+        let validators = [validator("a"), validator("b")];
+        let (_node, staking) = start_with(
+            &validators,
+            genesis::AppState {
+                // The epoch indices and reward rates are ignored.
+                base_rate_data: Some(BaseRateData {
+                    epoch_index: 7,
+                    base_reward_rate: 5,
+                    base_exchange_rate: 2_0000_0000,
+                }),
+                validator_rates: vec![RateData {
+                    identity_key: validators[0].identity_key.clone(),
+                    epoch_index: 7,
+                    validator_reward_rate: 3,
+                    validator_exchange_rate: 3_0000_0000,
+                }],
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        assert_eq!(
+            exchange_rates(&staking, &validators).await?,
+            vec![
+                (2_0000_0000, 2_0000_0000),
+                (3_0000_0000, 3_0000_0000),
+                (1_0000_0000, 1_0000_0000),
+            ]
+        );
+        let next_base_rate = staking.overlay.next_base_rate().await?;
+        assert_eq!(
+            (next_base_rate.epoch_index, next_base_rate.base_reward_rate),
+            (1, 0)
+        );
+
+        // Voting power is computed with the supplied rates, so the same number
+        // of delegation tokens is worth more for the validator with the higher rate.
+        let power_a = staking
+            .overlay
+            .validator_power(&validators[0].identity_key)
+            .await?
+            .unwrap();
+        let power_b = staking
+            .overlay
+            .validator_power(&validators[1].identity_key)
+            .await?
+            .unwrap();
+        assert!(power_a > power_b);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn genesis_without_rates_starts_at_the_identity_rate() -> Result<()> {
+        // Genesis files written before the rates could be supplied omit them.
+        let mut json = serde_json::to_value(genesis::AppState::default())?;
+        let fields = json.as_object_mut().unwrap();
+        assert!(fields.remove("base_rate_data").is_some());
+        assert!(fields.remove("validator_rates").is_some());
+        let app_state: genesis::AppState = serde_json::from_value(json)?;
+        assert!(app_state.base_rate_data.is_none());
+        assert!(app_state.validator_rates.is_empty());
+
+        let validators = [validator("a"), validator("b")];
+        let (_node, staking) = start_with(&validators, app_state).await?;
+        assert_eq!(
+            exchange_rates(&staking, &validators).await?,
+            vec![(1_0000_0000, 1_0000_0000); 3]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lists_validators_in_order_of_their_identity_keys() -> Result<()> {
+        let validators: Vec<_> = ["a", "b", "c", "d"].into_iter().map(validator).collect();
+        let (_node, staking) = start_with(&validators, Default::default()).await?;
+
+        let list = staking.overlay.validator_list().await?;
+        let mut expected: Vec<_> = validators.into_iter().map(|v| v.identity_key).collect();
+        expected.sort_by_key(|identity_key| identity_key.0.to_bytes());
+        assert_eq!(list, expected);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn records_the_validator_set_only_when_it_changes() -> Result<()> {
+        let (_node, staking, _) = active_validators(&[10, 20]).await?;
+        let overlay = &staking.overlay;
+        let genesis_set = overlay.validator_set_at_height(0).await?.unwrap();
+        assert_eq!(genesis_set.0.len(), 2);
+
+        // The powers changed since genesis, so the new set is written...
+        let current_set = staking.active_validator_set().await?;
+        let mut powers: Vec<_> = current_set.0.iter().map(|entry| entry.power).collect();
+        powers.sort_unstable();
+        assert_eq!(powers, vec![10, 20]);
+        staking.record_validator_set(1).await?;
+        // ... but an unchanged set writes nothing.
+        staking.record_validator_set(2).await?;
+        assert_eq!(overlay.validator_set_change_count().await?, 2);
+        assert_eq!(
+            overlay
+                .get_domain::<ValidatorSet, proto::stake::ValidatorSet>(
+                    "staking/validator_set/2".into()
+                )
+                .await?,
+            None
+        );
+
+        overlay.put_block_height(2).await;
+        assert_eq!(overlay.validator_set_at_height(0).await?, Some(genesis_set));
+        assert_eq!(
+            overlay.validator_set_at_height(1).await?,
+            Some(current_set.clone())
+        );
+        assert_eq!(overlay.validator_set_at_height(2).await?, Some(current_set));
+        // Later blocks have not been committed.
+        assert_eq!(overlay.validator_set_at_height(3).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn finds_validator_sets_across_chunks_of_changes() -> Result<()> {
+        let (_node, staking, identity_keys) = active_validators(&[10, 20]).await?;
+        let overlay = &staking.overlay;
+        let genesis_set = overlay.validator_set_at_height(0).await?.unwrap();
+
+        // The set changes every third block, from height 3 on.
+        let changes = 2 * VALIDATOR_SET_CHANGE_CHUNK_LEN;
+        for change in 1..=changes {
+            overlay
+                .set_validator_power(&identity_keys[0], change)
+                .await?;
+            staking.record_validator_set(3 * change).await?;
+            staking.record_validator_set(3 * change + 1).await?;
+        }
+        assert_eq!(overlay.validator_set_change_count().await?, changes + 1);
+
+        overlay.put_block_height(3 * changes + 2).await;
+        assert_eq!(overlay.validator_set_at_height(2).await?, Some(genesis_set));
+        for height in 3..=3 * changes + 2 {
+            let set = overlay.validator_set_at_height(height).await?.unwrap();
+            let power = set
+                .0
+                .iter()
+                .find(|entry| entry.identity_key == identity_keys[0])
+                .unwrap()
+                .power;
+            assert_eq!(power, height / 3, "at height {}", height);
+        }
+
+        // The history cannot be rewritten.
+        assert!(staking.record_validator_set(3).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn records_commission_payouts_at_the_end_of_the_epoch() -> Result<()> {
+        let stream = FundingStream {
+            address: address(),
+            rate_bps: 10_00,
+        };
+        let mut paying = validator("paying");
+        paying.funding_streams = vec![stream.clone()].try_into()?;
+        let validators = [paying, validator("unfunded")];
+        let (_node, mut staking) = start_with(&validators, Default::default()).await?;
+
+        staking.end_epoch(epoch(0)).await?;
+
+        let overlay = &staking.overlay;
+        let amount = stream.reward_amount(
+            1_000_000,
+            &overlay.next_base_rate().await?,
+            &overlay.current_base_rate().await?,
+        );
+        assert!(amount > 0);
+        assert_eq!(
+            overlay
+                .commission_payouts(&validators[0].identity_key, 0)
+                .await?,
+            Some(CommissionPayouts {
+                identity_key: validators[0].identity_key.clone(),
+                epoch_index: 0,
+                payouts: vec![FundingStreamPayout {
+                    address: stream.address,
+                    rate_bps: stream.rate_bps,
+                    amount,
+                }],
+            })
+        );
+        // Active validators without funding streams record that they paid nothing.
+        assert_eq!(
+            overlay
+                .commission_payouts(&validators[1].identity_key, 0)
+                .await?
+                .map(|payouts| payouts.payouts),
+            Some(Vec::new())
+        );
+        assert_eq!(
+            overlay
+                .commission_payouts(&validators[0].identity_key, 1)
+                .await?,
+            None
+        );
+
+        // Each payout is reported as an event.
+        let events = staking.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, "commission_payout");
+        let attributes: Vec<_> = events[0]
+            .attributes
+            .iter()
+            .map(|attribute| (attribute.key.as_str(), attribute.value.clone()))
+            .collect();
+        assert_eq!(
+            attributes,
+            vec![
+                ("validator", validators[0].identity_key.to_string()),
+                ("epoch", "0".to_string()),
+                ("address", stream.address.to_string()),
+                ("rate_bps", "1000".to_string()),
+                ("amount", amount.to_string()),
+            ]
+        );
+        assert!(staking.take_events().is_empty());
+
+        Ok(())
+    }
+
+    fn epoch(index: u64) -> Epoch {
+        Epoch {
+            index,
+            start_height: index * 10,
+        }
+    }
+
+    async fn states(staking: &Staking, identity_keys: &[IdentityKey]) -> Vec<ValidatorState> {
+        let mut states = Vec::new();
+        for identity_key in identity_keys {
+            states.push(
+                staking
+                    .overlay
+                    .validator_state(identity_key)
+                    .await
+                    .unwrap()
+                    .unwrap(),
+            );
+        }
+        states
+    }
+
+    #[tokio::test]
+    async fn keeps_the_highest_power_validators_active() -> Result<()> {
+        let (_node, mut staking, v) = active_validators(&[10, 30, 20]).await?;
+
+        staking.process_epoch_transitions(epoch(0), 2, 5, 0).await?;
+        assert_eq!(
+            states(&staking, &v).await,
+            vec![
+                ValidatorState::Unbonding { unbonding_epoch: 5 },
+                ValidatorState::Active,
+                ValidatorState::Active,
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keeps_bonded_validators_active_until_the_bond_ends() -> Result<()> {
+        let (_node, mut staking, v) = active_validators(&[30, 20, 10]).await?;
+        // The lowest-power validator joined the active set at epoch 1, so
+        // with a minimum bond of 2 epochs it is bonded through epoch 2.
+        staking.overlay.set_validator_bonded_since(&v[2], 1).await;
+
+        staking.process_epoch_transitions(epoch(1), 2, 5, 2).await?;
+        assert_eq!(
+            states(&staking, &v).await,
+            vec![
+                ValidatorState::Active,
+                ValidatorState::Unbonding { unbonding_epoch: 5 },
+                ValidatorState::Active,
+            ]
+        );
+
+        // Once the bond ends, it is displaced by a higher-power validator.
+        staking.overlay.set_validator_power(&v[1], 20).await?;
+        staking.process_epoch_transitions(epoch(2), 2, 5, 2).await?;
+        assert_eq!(
+            states(&staking, &v).await,
+            vec![
+                ValidatorState::Active,
+                ValidatorState::Active,
+                ValidatorState::Unbonding { unbonding_epoch: 5 },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn does_not_keep_bonded_validators_without_power() -> Result<()> {
+        let (_node, mut staking, v) = active_validators(&[10, 20, 0]).await?;
+        staking.overlay.set_validator_bonded_since(&v[2], 1).await;
+
+        staking.process_epoch_transitions(epoch(1), 2, 5, 2).await?;
+        assert_eq!(
+            states(&staking, &v).await,
+            vec![
+                ValidatorState::Active,
+                ValidatorState::Active,
+                ValidatorState::Unbonding { unbonding_epoch: 5 },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exchange_rate_history_is_chunked() -> Result<()> {
+        let overlay = crate::Storage::in_memory().await?.overlay().await?;
+        let id = identity_key();
+
+        // The validator is defined from partway through the second chunk.
+        let epochs = 70..200;
+        for epoch_index in epochs.clone() {
+            overlay
+                .record_exchange_rate(&id, epoch_index, 1_0000_0000 + epoch_index)
+                .await?;
+        }
+
+        // Any epoch's rate is a lookup of the one chunk containing it, so the
+        // change in rate between two epochs takes two reads however far apart
+        // they are.
+        for epoch_index in epochs.clone() {
+            assert_eq!(
+                overlay.exchange_rate(&id, epoch_index).await?,
+                Some(1_0000_0000 + epoch_index)
+            );
+        }
+        for epoch_index in [0, 63, 64, 69, 200, 255, 256] {
+            assert_eq!(overlay.exchange_rate(&id, epoch_index).await?, None);
+        }
+        for (epoch_index, len) in [(0, None), (64, Some(64)), (128, Some(64)), (192, Some(8))] {
+            let chunk: Option<proto::stake::ExchangeRateChunk> = overlay
+                .get_proto(exchange_rate_chunk_key(&id, epoch_index))
+                .await?;
+            assert_eq!(chunk.map(|chunk| chunk.exchange_rates.len()), len);
+        }
+
+        // Recording an epoch again replaces its rate, leaving the rest alone.
+        overlay.record_exchange_rate(&id, 100, 5).await?;
+        assert_eq!(overlay.exchange_rate(&id, 100).await?, Some(5));
+        assert_eq!(overlay.exchange_rate(&id, 101).await?, Some(1_0000_0101));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn genesis_rates_start_the_history() -> Result<()> {
+        let validators = [validator("a")];
+        let (_node, staking) = start_with(&validators, Default::default()).await?;
+        let identity_key = &validators[0].identity_key;
+
+        let current = staking
+            .overlay
+            .current_validator_rate(identity_key)
+            .await?
+            .unwrap();
+        assert_eq!(
+            staking.overlay.exchange_rate(identity_key, 0).await?,
+            Some(current.validator_exchange_rate)
+        );
+        assert_eq!(staking.overlay.exchange_rate(identity_key, 1).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pools_back_delegations_across_epochs() -> Result<()> {
+        let stream = FundingStream {
+            address: address(),
+            rate_bps: 10_00,
+        };
+        let mut paying = validator("paying");
+        paying.funding_streams = vec![stream].try_into()?;
+        let validators = [paying, validator("unfunded")];
+        let (_node, mut staking) = start_with(&validators, Default::default()).await?;
+
+        // Pools are first recorded at the end of the first epoch, and then
+        // accrue rewards at every epoch.
+        for Validator { identity_key, .. } in &validators {
+            assert_eq!(staking.overlay.pool_size(identity_key).await?, None);
+        }
+        for index in 0..3 {
+            staking.end_epoch(epoch(index)).await?;
+            for Validator { identity_key, .. } in &validators {
+                let pool = staking.overlay.pool_size(identity_key).await?.unwrap();
+                assert_eq!(pool.updates, index + 1);
+                assert!(pool.amount >= 1_000_000);
+                assert_eq!(
+                    staking
+                        .overlay
+                        .pool_invariant_violation(identity_key)
+                        .await?,
+                    None
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn detects_pools_not_backing_delegations() -> Result<()> {
+        let validators = [validator("a")];
+        let (_node, staking) = start_with(&validators, Default::default()).await?;
+        let identity_key = &validators[0].identity_key;
+        let overlay = &staking.overlay;
+
+        // Genesis delegates 1,000,000 tokens at an exchange rate of 1.
+        let pool = |amount, updates| PoolSize { amount, updates };
+        overlay
+            .set_pool_size(identity_key, pool(1_000_000, 0))
+            .await;
+        assert_eq!(overlay.pool_invariant_violation(identity_key).await?, None);
+
+        // Each update of the pool may round differently, by one unit.
+        overlay
+            .set_pool_size(identity_key, pool(1_000_002, 2))
+            .await;
+        assert_eq!(overlay.pool_invariant_violation(identity_key).await?, None);
+        overlay.set_pool_size(identity_key, pool(999_998, 2)).await;
+        assert_eq!(overlay.pool_invariant_violation(identity_key).await?, None);
+
+        for amount in [1_000_003, 999_997, 0] {
+            overlay.set_pool_size(identity_key, pool(amount, 2)).await;
+            let violation = overlay.pool_invariant_violation(identity_key).await?;
+            assert!(
+                violation.map_or(false, |violation| violation.contains("its pool holds")),
+                "{}",
+                amount
+            );
+        }
+
+        Ok(())
+    }
+
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    #[should_panic(expected = "staking invariant violated")]
+    async fn end_block_fires_on_invariant_violations() {
+        let validators = [validator("a")];
+        let (_node, mut staking) = start_with(&validators, Default::default()).await.unwrap();
+        staking
+            .overlay
+            .set_pool_size(&validators[0].identity_key, PoolSize::default())
+            .await;
+
+        staking
+            .end_block(&abci::request::EndBlock { height: 1 })
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,915 @@
+//! The `pd start` configuration file.
+//!
+//! Every option of `pd start` can also be set in a TOML file passed with
+//! `--config`, under the same name as its flag, e.g. `abci-port = 26658`.
+//! Flags given on the command line take precedence over the file, and options
+//! set in neither take their defaults. `pd generate-config` writes a file
+//! with every option set to its default, and documented.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use structopt::StructOpt;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+use crate::{
+    logging::{self, Logging},
+    pd_metrics::MetricsMode,
+    pruning::{Pruning, Strategy},
+    storage::{CompactionStyle, Tuning},
+};
+
+/// The options of `pd start`, as given on the command line or in a
+/// configuration file.
+///
+/// Unset options are `None`, so that the two sources can be combined with
+/// [`Options::or`] before the defaults are filled in by [`Options::resolve`].
+#[derive(Debug, Default, Deserialize, StructOpt)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Options {
+    /// The path used to store the Rocks database.
+    #[structopt(short, long)]
+    pub rocks_path: Option<PathBuf>,
+    /// Bind the services to this host [default: 127.0.0.1].
+    #[structopt(short, long)]
+    pub host: Option<String>,
+    /// Bind the ABCI server to this port [default: 26658].
+    #[structopt(short, long)]
+    pub abci_port: Option<u16>,
+    /// Serve ABCI on a Unix domain socket at this path, rather than on
+    /// `abci-port`, for a Tendermint node running on the same host.
+    #[structopt(long, parse(from_os_str))]
+    pub abci_uds: Option<PathBuf>,
+    /// Bind the oblivious query service to this port [default: 26666].
+    #[structopt(short, long)]
+    pub oblivious_query_port: Option<u16>,
+    /// Bind the specific query service to this port [default: 26667].
+    #[structopt(short, long)]
+    pub specific_query_port: Option<u16>,
+    /// Serve both query services, and gRPC server reflection, on this single
+    /// port, rather than on `oblivious-query-port` and `specific-query-port`,
+    /// so that clients only need to reach one port and can discover the API
+    /// with tools like `grpcurl`.
+    #[structopt(long)]
+    pub grpc_port: Option<u16>,
+    /// Serve the gRPC services over TLS, with the PEM-encoded certificate
+    /// chain in this file. Requires `tls-key`.
+    #[structopt(long, parse(from_os_str))]
+    pub tls_cert: Option<PathBuf>,
+    /// The PEM-encoded private key for `tls-cert`.
+    #[structopt(long, parse(from_os_str))]
+    pub tls_key: Option<PathBuf>,
+    /// Require gRPC clients to present a certificate signed by one of the
+    /// PEM-encoded CA certificates in this file, for private deployments.
+    /// Requires `tls-cert`.
+    #[structopt(long, parse(from_os_str))]
+    pub tls_client_ca: Option<PathBuf>,
+    /// Whether to serve metrics for Prometheus: `enabled`, which keeps
+    /// running without them if they cannot be served, `strict`, which fails
+    /// to start instead, or `disabled` [default: enabled].
+    #[structopt(long)]
+    pub metrics: Option<MetricsMode>,
+    /// Bind the metrics endpoint to this port [default: 9000].
+    #[structopt(short, long)]
+    pub metrics_port: Option<u16>,
+    /// Bind the admin service to this port [default: 26668].
+    #[structopt(long)]
+    pub admin_port: Option<u16>,
+    /// Path to a file containing the bearer token for the admin service.
+    ///
+    /// The admin service is only started if this is set.
+    #[structopt(long, parse(from_os_str))]
+    pub admin_token_file: Option<PathBuf>,
+    /// Serve the staking state as JSON documents for dashboards, over HTTP
+    /// on this port.
+    ///
+    /// The export is only served if this is set.
+    #[structopt(long)]
+    pub staking_export_port: Option<u16>,
+    /// The URL of the co-located Tendermint node's RPC, e.g.
+    /// `http://127.0.0.1:26657`.
+    ///
+    /// If set, Tendermint's peer count, sync status and mempool size are
+    /// polled and republished on the metrics endpoint.
+    #[structopt(long)]
+    pub tendermint_rpc: Option<String>,
+    /// How often, in seconds, to poll the Tendermint RPC [default: 10].
+    #[structopt(long)]
+    pub tendermint_poll_interval: Option<u64>,
+    /// Periodically fetch the chain's peer list from this URL, as `pd peers
+    /// refresh` does, and write it to the persistent peers of the Tendermint
+    /// config in `tendermint-home`. Requires `peers-signer`.
+    #[structopt(long)]
+    pub peers_url: Option<String>,
+    /// The hex-encoded Ed25519 key the peer list at `peers-url` must be
+    /// signed with.
+    #[structopt(long)]
+    pub peers_signer: Option<String>,
+    /// The home directory of the co-located Tendermint node, whose config
+    /// is rewritten with the peers at `peers-url`.
+    #[structopt(long, parse(from_os_str))]
+    pub tendermint_home: Option<PathBuf>,
+    /// How often, in seconds, to fetch the peer list [default: 3600].
+    #[structopt(long)]
+    pub peers_refresh_interval: Option<u64>,
+    /// Serve liveness and readiness probes over HTTP on this port, at
+    /// `/healthz` and `/readyz`.
+    ///
+    /// The node is ready once it has caught up with the Tendermint node at
+    /// `tendermint-rpc`. The probes are only served if this is set.
+    #[structopt(long)]
+    pub health_port: Option<u16>,
+    /// Record the result of every executed transaction, so that it can be
+    /// looked up by transaction hash on the specific query service.
+    #[structopt(long)]
+    pub persist_tx_results: bool,
+    /// Keep each executed block in memory until it is committed, so that a
+    /// block replayed by Tendermint after it reconnects to this `pd` process
+    /// is committed without executing it again.
+    #[structopt(long)]
+    pub cache_replayed_blocks: bool,
+    /// Append every consensus request, with the response to it, to this
+    /// file, for `pd replay --log` to re-execute against fresh storage when
+    /// the node's app hash diverges.
+    #[structopt(long, parse(from_os_str))]
+    pub consensus_replay_log: Option<PathBuf>,
+    /// Stop the node once it has committed the block at this height, for a
+    /// coordinated upgrade with `pd migrate`.
+    #[structopt(long)]
+    pub halt_height: Option<u64>,
+    /// Which old versions of the stored state to delete: `default`,
+    /// `nothing`, `everything`, or `custom` [default: default].
+    #[structopt(long)]
+    pub pruning: Option<Strategy>,
+    /// With `custom` pruning, how many of the latest versions to keep.
+    #[structopt(long)]
+    pub pruning_keep_recent: Option<u64>,
+    /// With `custom` pruning, how many blocks to commit between prunings.
+    #[structopt(long)]
+    pub pruning_interval: Option<u64>,
+    /// The size of RocksDB's block cache, shared by every column family, in
+    /// MiB [default: 256].
+    #[structopt(long)]
+    pub rocksdb_block_cache_size: Option<usize>,
+    /// How RocksDB compacts the database: `level`, which keeps it smaller, or
+    /// `universal`, which writes less [default: level].
+    #[structopt(long)]
+    pub rocksdb_compaction_style: Option<CompactionStyle>,
+    /// Flush RocksDB's write buffers once its write-ahead log grows to this
+    /// many bytes. Chosen by RocksDB if 0 [default: 0].
+    #[structopt(long)]
+    pub rocksdb_max_total_wal_size: Option<u64>,
+    /// Sync RocksDB's write-ahead log in the background after every this many
+    /// bytes written to it, smoothing out its I/O. Left to the operating
+    /// system if 0 [default: 0].
+    #[structopt(long)]
+    pub rocksdb_wal_bytes_per_sync: Option<u64>,
+    /// Likewise, for RocksDB's other files [default: 0].
+    #[structopt(long)]
+    pub rocksdb_bytes_per_sync: Option<u64>,
+    /// Sync RocksDB's files with `fsync` rather than `fdatasync`, for
+    /// filesystems on which the latter does not make file metadata durable.
+    #[structopt(long)]
+    pub rocksdb_use_fsync: bool,
+    /// End compact block streams after this many seconds, so that clients
+    /// reconnect periodically rather than holding one stream open
+    /// indefinitely. Streams are not limited if this is 0 [default: 0].
+    #[structopt(long)]
+    pub max_stream_duration: Option<u64>,
+    /// The maximum number of requests and streams each client connection may
+    /// have open at once on the query services [default: unlimited].
+    #[structopt(long)]
+    pub max_concurrent_streams: Option<u32>,
+    /// The average number of requests per second each client IP address may
+    /// make to the query services. Requests are not limited if this is 0
+    /// [default: 0].
+    #[structopt(long)]
+    pub query_rate_limit: Option<u32>,
+    /// The number of requests each client IP address may make to the query
+    /// services at once, beyond `query-rate-limit` [default: 100].
+    #[structopt(long)]
+    pub query_rate_burst: Option<u32>,
+    /// Path to a file of API keys which clients may present in the
+    /// `x-api-key` header, to be limited and accounted for by key rather than
+    /// by IP address. Each line is `<name> <key>`, or `<name> <key> <rate>
+    /// <burst>` to override `query-rate-limit` and `query-rate-burst`.
+    ///
+    /// Requests presenting any other key are rejected.
+    #[structopt(long, parse(from_os_str))]
+    pub api_keys_file: Option<PathBuf>,
+    /// Publish the query requests of this many of the busiest clients in
+    /// each minute on the metrics endpoint, or none if 0 [default: 10].
+    #[structopt(long)]
+    pub query_usage_top_clients: Option<usize>,
+    /// Evict transactions from the mempool once they have been pending for
+    /// this many seconds without being included in a block, or never if 0
+    /// [default: 0].
+    #[structopt(long)]
+    pub mempool_ttl: Option<u64>,
+    /// Reject transactions declaring a fee lower than this [default: 0].
+    #[structopt(long)]
+    pub mempool_min_fee: Option<u64>,
+    /// On shutdown, how many seconds to wait for compact block streams to
+    /// end and their clients to disconnect [default: 10].
+    #[structopt(long)]
+    pub drain_grace_period: Option<u64>,
+    /// On shutdown, how many seconds to wait for the block in progress to
+    /// be committed before stopping anyway. An uncommitted block is not
+    /// lost: Tendermint replays it on restart [default: 30].
+    #[structopt(long)]
+    pub shutdown_grace_period: Option<u64>,
+    /// Open the database read-only and serve only the query services, without
+    /// the consensus and mempool services, for inspecting a data directory
+    /// which may be corrupt without risking further damage.
+    ///
+    /// Inconsistent state is reported, but does not stop the node.
+    #[structopt(long)]
+    pub read_only: bool,
+    /// Which spans and events to log, in the syntax of `RUST_LOG`, which this
+    /// overrides, e.g. `info,pd::consensus=debug` for per-target levels.
+    #[structopt(long, alias = "log-level")]
+    #[serde(alias = "log-level")]
+    pub log_filter: Option<String>,
+    /// The format of the logs: `text`, or `json`, with one object per line
+    /// [default: text].
+    #[structopt(long)]
+    pub log_format: Option<logging::Format>,
+    /// Write the logs to this file, rather than to standard output.
+    #[structopt(long, parse(from_os_str))]
+    pub log_file: Option<PathBuf>,
+    /// How often to start a new `log-file`, suffixed with its date:
+    /// `never`, `hourly` or `daily` [default: daily].
+    #[structopt(long)]
+    pub log_rotation: Option<logging::Rotation>,
+}
+
+/// The options of `pd start`, with defaults filled in.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub rocks_path: PathBuf,
+    pub host: String,
+    pub abci_port: u16,
+    pub abci_uds: Option<PathBuf>,
+    pub oblivious_query_port: u16,
+    pub specific_query_port: u16,
+    pub grpc_port: Option<u16>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_client_ca: Option<PathBuf>,
+    pub metrics: MetricsMode,
+    pub metrics_port: u16,
+    pub admin_port: u16,
+    pub admin_token_file: Option<PathBuf>,
+    pub staking_export_port: Option<u16>,
+    pub tendermint_rpc: Option<String>,
+    pub tendermint_poll_interval: u64,
+    pub peers_url: Option<String>,
+    pub peers_signer: Option<String>,
+    pub tendermint_home: Option<PathBuf>,
+    pub peers_refresh_interval: u64,
+    pub health_port: Option<u16>,
+    pub persist_tx_results: bool,
+    pub cache_replayed_blocks: bool,
+    pub consensus_replay_log: Option<PathBuf>,
+    pub halt_height: Option<u64>,
+    /// How to prune the stored state, or `None` to keep every version.
+    pub pruning: Option<Pruning>,
+    pub rocksdb: Tuning,
+    pub max_stream_duration: u64,
+    pub max_concurrent_streams: Option<u32>,
+    pub query_rate_limit: u32,
+    pub query_rate_burst: u32,
+    pub api_keys_file: Option<PathBuf>,
+    pub query_usage_top_clients: usize,
+    pub mempool_ttl: u64,
+    pub mempool_min_fee: u64,
+    pub drain_grace_period: u64,
+    pub shutdown_grace_period: u64,
+    pub read_only: bool,
+    pub log_filter: Option<String>,
+    pub logging: Logging,
+}
+
+// The defaults of the options which have them.
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_ABCI_PORT: u16 = 26658;
+const DEFAULT_OBLIVIOUS_QUERY_PORT: u16 = 26666;
+const DEFAULT_SPECIFIC_QUERY_PORT: u16 = 26667;
+const DEFAULT_METRICS: MetricsMode = MetricsMode::Enabled;
+const DEFAULT_METRICS_PORT: u16 = 9000;
+const DEFAULT_ADMIN_PORT: u16 = 26668;
+const DEFAULT_TENDERMINT_POLL_INTERVAL: u64 = 10;
+const DEFAULT_PEERS_REFRESH_INTERVAL: u64 = 3600;
+const DEFAULT_PRUNING: Strategy = Strategy::Default;
+const DEFAULT_ROCKSDB_BLOCK_CACHE_SIZE: usize = 256;
+const DEFAULT_ROCKSDB_COMPACTION_STYLE: CompactionStyle = CompactionStyle::Level;
+const DEFAULT_ROCKSDB_MAX_TOTAL_WAL_SIZE: u64 = 0;
+const DEFAULT_ROCKSDB_WAL_BYTES_PER_SYNC: u64 = 0;
+const DEFAULT_ROCKSDB_BYTES_PER_SYNC: u64 = 0;
+const DEFAULT_MAX_STREAM_DURATION: u64 = 0;
+const DEFAULT_QUERY_RATE_LIMIT: u32 = 0;
+const DEFAULT_QUERY_RATE_BURST: u32 = 100;
+const DEFAULT_QUERY_USAGE_TOP_CLIENTS: usize = 10;
+const DEFAULT_MEMPOOL_TTL: u64 = 0;
+const DEFAULT_MEMPOOL_MIN_FEE: u64 = 0;
+const DEFAULT_DRAIN_GRACE_PERIOD: u64 = 10;
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: u64 = 30;
+const DEFAULT_LOG_FORMAT: logging::Format = logging::Format::Text;
+const DEFAULT_LOG_ROTATION: logging::Rotation = logging::Rotation::Daily;
+
+impl Options {
+    /// Reads options from a configuration file.
+    ///
+    /// Relative paths in the file are taken to be relative to the directory
+    /// containing it, rather than to the working directory of `pd`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read config file {:?}", path))?;
+        let mut options: Options =
+            toml::from_str(&contents).with_context(|| format!("invalid config file {:?}", path))?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for path in [
+            &mut options.rocks_path,
+            &mut options.abci_uds,
+            &mut options.admin_token_file,
+            &mut options.tendermint_home,
+            &mut options.consensus_replay_log,
+            &mut options.api_keys_file,
+            &mut options.tls_cert,
+            &mut options.tls_key,
+            &mut options.tls_client_ca,
+            &mut options.log_file,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = dir.join(&*path);
+        }
+
+        Ok(options)
+    }
+
+    /// Combines two sets of options, taking each option from `self` if it is
+    /// set there, and from `fallback` otherwise.
+    pub fn or(self, fallback: Options) -> Options {
+        Options {
+            rocks_path: self.rocks_path.or(fallback.rocks_path),
+            host: self.host.or(fallback.host),
+            abci_port: self.abci_port.or(fallback.abci_port),
+            abci_uds: self.abci_uds.or(fallback.abci_uds),
+            oblivious_query_port: self.oblivious_query_port.or(fallback.oblivious_query_port),
+            specific_query_port: self.specific_query_port.or(fallback.specific_query_port),
+            grpc_port: self.grpc_port.or(fallback.grpc_port),
+            tls_cert: self.tls_cert.or(fallback.tls_cert),
+            tls_key: self.tls_key.or(fallback.tls_key),
+            tls_client_ca: self.tls_client_ca.or(fallback.tls_client_ca),
+            metrics: self.metrics.or(fallback.metrics),
+            metrics_port: self.metrics_port.or(fallback.metrics_port),
+            admin_port: self.admin_port.or(fallback.admin_port),
+            admin_token_file: self.admin_token_file.or(fallback.admin_token_file),
+            staking_export_port: self.staking_export_port.or(fallback.staking_export_port),
+            tendermint_rpc: self.tendermint_rpc.or(fallback.tendermint_rpc),
+            tendermint_poll_interval: self
+                .tendermint_poll_interval
+                .or(fallback.tendermint_poll_interval),
+            peers_url: self.peers_url.or(fallback.peers_url),
+            peers_signer: self.peers_signer.or(fallback.peers_signer),
+            tendermint_home: self.tendermint_home.or(fallback.tendermint_home),
+            peers_refresh_interval: self
+                .peers_refresh_interval
+                .or(fallback.peers_refresh_interval),
+            health_port: self.health_port.or(fallback.health_port),
+            persist_tx_results: self.persist_tx_results || fallback.persist_tx_results,
+            cache_replayed_blocks: self.cache_replayed_blocks || fallback.cache_replayed_blocks,
+            consensus_replay_log: self.consensus_replay_log.or(fallback.consensus_replay_log),
+            halt_height: self.halt_height.or(fallback.halt_height),
+            pruning: self.pruning.or(fallback.pruning),
+            pruning_keep_recent: self.pruning_keep_recent.or(fallback.pruning_keep_recent),
+            pruning_interval: self.pruning_interval.or(fallback.pruning_interval),
+            rocksdb_block_cache_size: self
+                .rocksdb_block_cache_size
+                .or(fallback.rocksdb_block_cache_size),
+            rocksdb_compaction_style: self
+                .rocksdb_compaction_style
+                .or(fallback.rocksdb_compaction_style),
+            rocksdb_max_total_wal_size: self
+                .rocksdb_max_total_wal_size
+                .or(fallback.rocksdb_max_total_wal_size),
+            rocksdb_wal_bytes_per_sync: self
+                .rocksdb_wal_bytes_per_sync
+                .or(fallback.rocksdb_wal_bytes_per_sync),
+            rocksdb_bytes_per_sync: self
+                .rocksdb_bytes_per_sync
+                .or(fallback.rocksdb_bytes_per_sync),
+            rocksdb_use_fsync: self.rocksdb_use_fsync || fallback.rocksdb_use_fsync,
+            max_stream_duration: self.max_stream_duration.or(fallback.max_stream_duration),
+            max_concurrent_streams: self
+                .max_concurrent_streams
+                .or(fallback.max_concurrent_streams),
+            query_rate_limit: self.query_rate_limit.or(fallback.query_rate_limit),
+            query_rate_burst: self.query_rate_burst.or(fallback.query_rate_burst),
+            api_keys_file: self.api_keys_file.or(fallback.api_keys_file),
+            query_usage_top_clients: self
+                .query_usage_top_clients
+                .or(fallback.query_usage_top_clients),
+            mempool_ttl: self.mempool_ttl.or(fallback.mempool_ttl),
+            mempool_min_fee: self.mempool_min_fee.or(fallback.mempool_min_fee),
+            drain_grace_period: self.drain_grace_period.or(fallback.drain_grace_period),
+            shutdown_grace_period: self
+                .shutdown_grace_period
+                .or(fallback.shutdown_grace_period),
+            read_only: self.read_only || fallback.read_only,
+            log_filter: self.log_filter.or(fallback.log_filter),
+            log_format: self.log_format.or(fallback.log_format),
+            log_file: self.log_file.or(fallback.log_file),
+            log_rotation: self.log_rotation.or(fallback.log_rotation),
+        }
+    }
+
+    /// Fills in the defaults of unset options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `rocks-path`, which has no default, is unset, or
+    /// if the pruning options are inconsistent.
+    pub fn resolve(self) -> anyhow::Result<Config> {
+        Ok(Config {
+            rocks_path: self.rocks_path.ok_or_else(|| {
+                anyhow!("no RocksDB path: pass --rocks-path, or set rocks-path in the config file")
+            })?,
+            host: self.host.unwrap_or_else(|| DEFAULT_HOST.to_string()),
+            abci_port: self.abci_port.unwrap_or(DEFAULT_ABCI_PORT),
+            abci_uds: self.abci_uds,
+            oblivious_query_port: self
+                .oblivious_query_port
+                .unwrap_or(DEFAULT_OBLIVIOUS_QUERY_PORT),
+            specific_query_port: self
+                .specific_query_port
+                .unwrap_or(DEFAULT_SPECIFIC_QUERY_PORT),
+            grpc_port: self.grpc_port,
+            tls_cert: self.tls_cert,
+            tls_key: self.tls_key,
+            tls_client_ca: self.tls_client_ca,
+            metrics: self.metrics.unwrap_or(DEFAULT_METRICS),
+            metrics_port: self.metrics_port.unwrap_or(DEFAULT_METRICS_PORT),
+            admin_port: self.admin_port.unwrap_or(DEFAULT_ADMIN_PORT),
+            admin_token_file: self.admin_token_file,
+            staking_export_port: self.staking_export_port,
+            tendermint_rpc: self.tendermint_rpc,
+            tendermint_poll_interval: self
+                .tendermint_poll_interval
+                .unwrap_or(DEFAULT_TENDERMINT_POLL_INTERVAL),
+            peers_url: self.peers_url,
+            peers_signer: self.peers_signer,
+            tendermint_home: self.tendermint_home,
+            peers_refresh_interval: self
+                .peers_refresh_interval
+                .unwrap_or(DEFAULT_PEERS_REFRESH_INTERVAL),
+            health_port: self.health_port,
+            persist_tx_results: self.persist_tx_results,
+            cache_replayed_blocks: self.cache_replayed_blocks,
+            consensus_replay_log: self.consensus_replay_log,
+            halt_height: self.halt_height,
+            pruning: Pruning::new(
+                self.pruning.unwrap_or(DEFAULT_PRUNING),
+                self.pruning_keep_recent,
+                self.pruning_interval,
+            )?,
+            rocksdb: Tuning {
+                block_cache_size: self
+                    .rocksdb_block_cache_size
+                    .unwrap_or(DEFAULT_ROCKSDB_BLOCK_CACHE_SIZE)
+                    * 1024
+                    * 1024,
+                compaction_style: self
+                    .rocksdb_compaction_style
+                    .unwrap_or(DEFAULT_ROCKSDB_COMPACTION_STYLE),
+                max_total_wal_size: self
+                    .rocksdb_max_total_wal_size
+                    .unwrap_or(DEFAULT_ROCKSDB_MAX_TOTAL_WAL_SIZE),
+                wal_bytes_per_sync: self
+                    .rocksdb_wal_bytes_per_sync
+                    .unwrap_or(DEFAULT_ROCKSDB_WAL_BYTES_PER_SYNC),
+                bytes_per_sync: self
+                    .rocksdb_bytes_per_sync
+                    .unwrap_or(DEFAULT_ROCKSDB_BYTES_PER_SYNC),
+                use_fsync: self.rocksdb_use_fsync,
+            },
+            max_stream_duration: self
+                .max_stream_duration
+                .unwrap_or(DEFAULT_MAX_STREAM_DURATION),
+            max_concurrent_streams: self.max_concurrent_streams,
+            query_rate_limit: self.query_rate_limit.unwrap_or(DEFAULT_QUERY_RATE_LIMIT),
+            query_rate_burst: self.query_rate_burst.unwrap_or(DEFAULT_QUERY_RATE_BURST),
+            api_keys_file: self.api_keys_file,
+            query_usage_top_clients: self
+                .query_usage_top_clients
+                .unwrap_or(DEFAULT_QUERY_USAGE_TOP_CLIENTS),
+            mempool_ttl: self.mempool_ttl.unwrap_or(DEFAULT_MEMPOOL_TTL),
+            mempool_min_fee: self.mempool_min_fee.unwrap_or(DEFAULT_MEMPOOL_MIN_FEE),
+            drain_grace_period: self
+                .drain_grace_period
+                .unwrap_or(DEFAULT_DRAIN_GRACE_PERIOD),
+            shutdown_grace_period: self
+                .shutdown_grace_period
+                .unwrap_or(DEFAULT_SHUTDOWN_GRACE_PERIOD),
+            read_only: self.read_only,
+            log_filter: self.log_filter,
+            logging: Logging {
+                format: self.log_format.unwrap_or(DEFAULT_LOG_FORMAT),
+                file: self.log_file,
+                rotation: self.log_rotation.unwrap_or(DEFAULT_LOG_ROTATION),
+            },
+        })
+    }
+}
+
+impl Config {
+    /// The TLS configuration of the gRPC services, or `None` if they are
+    /// served in plaintext.
+    pub fn tls_config(&self) -> anyhow::Result<Option<ServerTlsConfig>> {
+        let read =
+            |path: &PathBuf| std::fs::read(path).with_context(|| format!("cannot read {:?}", path));
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                let mut tls =
+                    ServerTlsConfig::new().identity(Identity::from_pem(read(cert)?, read(key)?));
+                if let Some(client_ca) = &self.tls_client_ca {
+                    tls = tls.client_ca_root(Certificate::from_pem(read(client_ca)?));
+                }
+                Ok(Some(tls))
+            }
+            (None, None) if self.tls_client_ca.is_some() => Err(anyhow!(
+                "client certificates can only be required with tls-cert and tls-key"
+            )),
+            (None, None) => Ok(None),
+            _ => Err(anyhow!("tls-cert and tls-key must be given together")),
+        }
+    }
+}
+
+/// A configuration file setting every option to its default, with options
+/// that have no default commented out, and each option documented.
+pub fn template() -> String {
+    format!(
+        r#"# Configuration for `pd start --config <this file>`.
+#
+# Every option can also be given as a flag of the same name, which takes
+# precedence over this file. Relative paths are relative to the directory
+# containing this file.
+
+## Storage
+
+# The path used to store the Rocks database. Required, here or as a flag.
+#rocks-path = "rocksdb"
+
+# Record the result of every executed transaction, so that it can be looked up
+# by transaction hash on the specific query service.
+persist-tx-results = false
+
+# Keep each executed block in memory until it is committed, so that if the
+# Tendermint node restarts and replays the block, it is committed without
+# verifying and executing its transactions again. This shortens recovery from
+# a Tendermint crash on large blocks, but not from a crash of `pd` itself.
+cache-replayed-blocks = false
+
+# Append every consensus request, with the response to it, to this file, so
+# that if the node's app hash diverges from the rest of the network, the
+# divergence can be reproduced offline with `pd replay --log`. The file grows
+# with every block, and is never pruned.
+#consensus-replay-log = "consensus.log"
+
+# Stop the node once it has committed the block at this height, so that every
+# validator can install a new version of `pd` and run `pd migrate` at the same
+# point in the chain. See the guide's section on upgrades.
+#halt-height = 100000
+
+# Which old versions of the stored state to delete, in the background:
+# - "default" keeps the last 362880 versions, pruning every 100 blocks;
+# - "nothing" keeps every version;
+# - "everything" keeps the last 2 versions, pruning every 10 blocks;
+# - "custom" keeps `pruning-keep-recent` versions, pruning every
+#   `pruning-interval` blocks.
+# Pruned versions can no longer be queried, exported, or rolled back to.
+pruning = "{pruning}"
+#pruning-keep-recent = 1000
+#pruning-interval = 10
+
+# Tuning of RocksDB, which only affects performance and durability, so can be
+# changed between restarts. The block cache, in MiB, is shared by every column
+# family. Level compaction keeps the database smaller, universal compaction
+# writes less. The WAL size, in bytes, after which write buffers are flushed,
+# and the number of bytes written between background syncs of the WAL and of
+# other files, are left to RocksDB and the operating system if 0. `fsync` is
+# only needed on filesystems where `fdatasync` does not make file metadata
+# durable.
+rocksdb-block-cache-size = {rocksdb_block_cache_size}
+rocksdb-compaction-style = "{rocksdb_compaction_style}"
+rocksdb-max-total-wal-size = {rocksdb_max_total_wal_size}
+rocksdb-wal-bytes-per-sync = {rocksdb_wal_bytes_per_sync}
+rocksdb-bytes-per-sync = {rocksdb_bytes_per_sync}
+rocksdb-use-fsync = false
+
+## Network
+
+# Bind the services to this host.
+host = "{host}"
+
+# Bind the ABCI server to this port.
+abci-port = {abci_port}
+
+# Serve ABCI on a Unix domain socket at this path, rather than on `abci-port`,
+# for a Tendermint node running on the same host.
+#abci-uds = "abci.sock"
+
+# Bind the oblivious query service to this port.
+oblivious-query-port = {oblivious_query_port}
+
+# Bind the specific query service to this port.
+specific-query-port = {specific_query_port}
+
+# Serve both query services, and gRPC server reflection, on this single port,
+# rather than on `oblivious-query-port` and `specific-query-port`, so that
+# clients only need to reach one port and can discover the API with tools like
+# `grpcurl`.
+#grpc-port = 26669
+
+# Serve the gRPC services over TLS, with the PEM-encoded certificate chain and
+# private key in these files.
+#tls-cert = "tls/cert.pem"
+#tls-key = "tls/key.pem"
+
+# Require gRPC clients to present a certificate signed by one of the
+# PEM-encoded CA certificates in this file, for private deployments.
+#tls-client-ca = "tls/client-ca.pem"
+
+# Whether to serve metrics for Prometheus: "enabled", which keeps running
+# without serving them if they cannot be served, e.g. because the port is
+# taken, "strict", which fails to start instead, or "disabled".
+metrics = "{metrics}"
+
+# Bind the metrics endpoint to this port.
+metrics-port = {metrics_port}
+
+# Bind the admin service to this port.
+admin-port = {admin_port}
+
+# Path to a file containing the bearer token for the admin service. The admin
+# service is only started if this is set.
+#admin-token-file = "admin_token"
+
+# Serve the staking state as JSON documents for dashboards, over HTTP on this
+# port. The export is only served if this is set.
+#staking-export-port = 8080
+
+# The URL of the co-located Tendermint node's RPC. If set, Tendermint's peer
+# count, sync status and mempool size are polled and republished on the
+# metrics endpoint.
+#tendermint-rpc = "http://127.0.0.1:26657"
+
+# How often, in seconds, to poll the Tendermint RPC.
+tendermint-poll-interval = {tendermint_poll_interval}
+
+# Periodically fetch the chain's peer list from this URL, as `pd peers refresh`
+# does, and write it to the persistent peers of the Tendermint config in
+# tendermint-home, which Tendermint reads when it is next restarted. The list
+# must be signed with the hex-encoded Ed25519 key peers-signer.
+#peers-url = "https://example.com/peers.json"
+#peers-signer = ""
+#tendermint-home = "../tendermint"
+
+# How often, in seconds, to fetch the peer list.
+peers-refresh-interval = {peers_refresh_interval}
+
+# Serve liveness and readiness probes over HTTP on this port, at /healthz and
+# /readyz. The node is ready once it has caught up with the Tendermint node at
+# tendermint-rpc. The probes are only served if this is set.
+#health-port = 8081
+
+## Mempool
+
+# Evict transactions from the mempool once they have been pending for this
+# many seconds without being included in a block, or never if 0. Transactions
+# which are no longer valid against the latest state are always evicted, once
+# Tendermint rechecks them, which it does unless its `recheck` is disabled.
+mempool-ttl = {mempool_ttl}
+
+# Reject transactions declaring a fee lower than this. Accepted transactions
+# are given their fee as their priority, so that block proposers include the
+# highest-paying first, which requires Tendermint's `mempool.version = "v1"`.
+mempool-min-fee = {mempool_min_fee}
+
+## Query streams, limits and shutdown
+
+# End compact block streams after this many seconds, so that clients reconnect
+# periodically rather than holding one stream open indefinitely. Streams are
+# not limited if this is 0.
+max-stream-duration = {max_stream_duration}
+
+# The maximum number of requests and streams each client connection may have
+# open at once on the query services. Unlimited if unset.
+#max-concurrent-streams = 100
+
+# The average number of requests per second each client IP address may make to
+# the query services, and how many it may make at once beyond that. Requests
+# are not limited if the rate is 0.
+query-rate-limit = {query_rate_limit}
+query-rate-burst = {query_rate_burst}
+
+# Path to a file of API keys which clients may present in the `x-api-key`
+# header, to be limited and accounted for by key rather than by IP address.
+# Each line is `<name> <key>`, or `<name> <key> <rate> <burst>` to override the
+# rate limit above. Requests presenting any other key are rejected.
+#api-keys-file = "api_keys"
+
+# Publish the query requests of this many of the busiest clients in each
+# minute on the metrics endpoint, or none if 0.
+query-usage-top-clients = {query_usage_top_clients}
+
+# On shutdown, how many seconds to wait for compact block streams to end and
+# their clients to disconnect.
+drain-grace-period = {drain_grace_period}
+
+# On shutdown, how many seconds to wait for the block in progress to be
+# committed before stopping anyway. An uncommitted block is not lost:
+# Tendermint replays it on restart.
+shutdown-grace-period = {shutdown_grace_period}
+
+# Open the database read-only and serve only the query services, without the
+# consensus and mempool services, for inspecting a data directory which may be
+# corrupt without risking further damage. Inconsistent state is reported, but
+# does not stop the node.
+read-only = false
+
+## Tracing
+
+# Which spans and events to log, in the syntax of `RUST_LOG`, which this
+# overrides, with a level for each target.
+#log-filter = "info,pd=debug"
+
+# The format of the logs: "text", or "json", with one object per line, for log
+# shippers to ingest without parsing lines.
+log-format = "{log_format}"
+
+# Write the logs to this file, rather than to standard output, starting a new
+# file, suffixed with its date, "never", "hourly" or "daily". Rotated files are
+# not deleted.
+#log-file = "pd.log"
+log-rotation = "{log_rotation}"
+"#,
+        host = DEFAULT_HOST,
+        abci_port = DEFAULT_ABCI_PORT,
+        oblivious_query_port = DEFAULT_OBLIVIOUS_QUERY_PORT,
+        specific_query_port = DEFAULT_SPECIFIC_QUERY_PORT,
+        metrics = DEFAULT_METRICS,
+        metrics_port = DEFAULT_METRICS_PORT,
+        admin_port = DEFAULT_ADMIN_PORT,
+        tendermint_poll_interval = DEFAULT_TENDERMINT_POLL_INTERVAL,
+        peers_refresh_interval = DEFAULT_PEERS_REFRESH_INTERVAL,
+        pruning = DEFAULT_PRUNING,
+        rocksdb_block_cache_size = DEFAULT_ROCKSDB_BLOCK_CACHE_SIZE,
+        rocksdb_compaction_style = DEFAULT_ROCKSDB_COMPACTION_STYLE,
+        rocksdb_max_total_wal_size = DEFAULT_ROCKSDB_MAX_TOTAL_WAL_SIZE,
+        rocksdb_wal_bytes_per_sync = DEFAULT_ROCKSDB_WAL_BYTES_PER_SYNC,
+        rocksdb_bytes_per_sync = DEFAULT_ROCKSDB_BYTES_PER_SYNC,
+        max_stream_duration = DEFAULT_MAX_STREAM_DURATION,
+        query_rate_limit = DEFAULT_QUERY_RATE_LIMIT,
+        query_rate_burst = DEFAULT_QUERY_RATE_BURST,
+        query_usage_top_clients = DEFAULT_QUERY_USAGE_TOP_CLIENTS,
+        mempool_ttl = DEFAULT_MEMPOOL_TTL,
+        mempool_min_fee = DEFAULT_MEMPOOL_MIN_FEE,
+        drain_grace_period = DEFAULT_DRAIN_GRACE_PERIOD,
+        shutdown_grace_period = DEFAULT_SHUTDOWN_GRACE_PERIOD,
+        log_format = DEFAULT_LOG_FORMAT,
+        log_rotation = DEFAULT_LOG_ROTATION,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_rocks_path(options: Options) -> Options {
+        Options {
+            rocks_path: Some("rocksdb".into()),
+            ..options
+        }
+    }
+
+    #[test]
+    fn template_sets_the_defaults() -> anyhow::Result<()> {
+        let template: Options = toml::from_str(&template())?;
+        let template = with_rocks_path(template).resolve()?;
+        let defaults = with_rocks_path(Options::default()).resolve()?;
+        assert_eq!(format!("{:?}", template), format!("{:?}", defaults));
+        Ok(())
+    }
+
+    #[test]
+    fn rocks_path_is_required() {
+        let error = Options::default().resolve().unwrap_err();
+        assert!(error.to_string().contains("--rocks-path"), "{}", error);
+    }
+
+    #[test]
+    fn flags_take_precedence_over_the_file() -> anyhow::Result<()> {
+        let file: Options = toml::from_str(
+            r#"
+            abci-port = 1
+            admin-port = 2
+            persist-tx-results = true
+            "#,
+        )?;
+        let flags = Options {
+            abci_port: Some(3),
+            ..Default::default()
+        };
+
+        let config = with_rocks_path(flags.or(file)).resolve()?;
+        assert_eq!(config.abci_port, 3);
+        assert_eq!(config.admin_port, 2);
+        assert_eq!(config.specific_query_port, DEFAULT_SPECIFIC_QUERY_PORT);
+        // Switches can only be turned on, by either source.
+        assert!(config.persist_tx_results);
+        assert!(!config.cache_replayed_blocks);
+
+        Ok(())
+    }
+
+    #[test]
+    fn file_paths_are_relative_to_the_file() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("pd.toml");
+        std::fs::write(
+            &path,
+            r#"
+            rocks-path = "rocksdb"
+            admin-token-file = "/etc/pd/admin-token"
+            "#,
+        )?;
+
+        let options = Options::load(&path)?;
+        assert_eq!(options.rocks_path, Some(dir.path().join("rocksdb")));
+        assert_eq!(
+            options.admin_token_file,
+            Some(PathBuf::from("/etc/pd/admin-token"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("pd.toml");
+        std::fs::write(&path, "abci-prot = 26658\n")?;
+
+        let error = Options::load(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains("abci-prot"), "{:#}", error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tls_needs_a_certificate_and_key() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = |name: &str| -> anyhow::Result<Option<PathBuf>> {
+            let path = dir.path().join(name);
+            std::fs::write(&path, "-----BEGIN CERTIFICATE-----\n")?;
+            Ok(Some(path))
+        };
+        let tls = |tls_cert, tls_key, tls_client_ca| {
+            with_rocks_path(Options {
+                tls_cert,
+                tls_key,
+                tls_client_ca,
+                ..Default::default()
+            })
+            .resolve()
+            .and_then(|config| config.tls_config())
+        };
+
+        assert!(tls(None, None, None)?.is_none());
+        assert!(tls(file("cert")?, file("key")?, None)?.is_some());
+        assert!(tls(file("cert")?, file("key")?, file("ca")?)?.is_some());
+
+        for (cert, key, client_ca, problem) in [
+            (file("cert")?, None, None, "together"),
+            (None, file("key")?, None, "together"),
+            (None, None, file("ca")?, "tls-cert and tls-key"),
+            (
+                file("cert")?,
+                Some(dir.path().join("missing")),
+                None,
+                "cannot read",
+            ),
+        ] {
+            let error = tls(cert, key, client_ca).unwrap_err();
+            assert!(error.to_string().contains(problem), "{}", error);
+        }
+
+        Ok(())
+    }
+}
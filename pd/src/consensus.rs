@@ -1,7 +1,12 @@
+mod execution;
 mod message;
+pub mod proposal;
 mod service;
+mod tx_error;
 mod worker;
 
+use execution::{Execution, Replay};
 use message::Message;
 pub use service::Consensus;
+pub use tx_error::{TxCode, TxError};
 use worker::Worker;
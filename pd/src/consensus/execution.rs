@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use tendermint::{abci, Hash};
+
+/// A record of the responses to the requests executing a block, kept in
+/// memory until the block is committed, so that if Tendermint reconnects to
+/// the same `pd` process and replays the block, its already executed changes
+/// can be committed without executing it again.
+///
+/// Nothing is recovered if `pd` itself restarts: the uncommitted changes are
+/// lost with the record, and Tendermint's replay executes the block anew.
+#[derive(Debug)]
+pub struct Execution {
+    height: u64,
+    hash: Hash,
+    begin_block: abci::response::BeginBlock,
+    /// The response to each `DeliverTx`, with the hash of its transaction.
+    deliver_txs: Vec<([u8; 32], abci::response::DeliverTx)>,
+    end_block: Option<abci::response::EndBlock>,
+}
+
+impl Execution {
+    pub fn new(height: u64, hash: Hash, begin_block: abci::response::BeginBlock) -> Self {
+        Self {
+            height,
+            hash,
+            begin_block,
+            deliver_txs: Vec::new(),
+            end_block: None,
+        }
+    }
+
+    pub fn record_deliver_tx(&mut self, tx_hash: [u8; 32], rsp: &abci::response::DeliverTx) {
+        self.deliver_txs.push((tx_hash, rsp.clone()));
+    }
+
+    pub fn record_end_block(&mut self, rsp: &abci::response::EndBlock) {
+        self.end_block = Some(rsp.clone());
+    }
+
+    /// Whether this is a complete execution of the block at `height` with
+    /// the given hash, which can be replayed in place of executing it again.
+    ///
+    /// The block hash commits to the block's transactions, so a replayed
+    /// block with the same hash has the same transactions, in the same order.
+    pub fn replays(&self, height: u64, hash: &Hash) -> bool {
+        self.end_block.is_some() && self.height == height && &self.hash == hash
+    }
+
+    pub fn begin_block(&self) -> abci::response::BeginBlock {
+        self.begin_block.clone()
+    }
+}
+
+/// A block being replayed from its recorded [`Execution`].
+///
+/// The requests replayed so far are kept, so that if the block turns out to
+/// differ from the recorded one, it can be executed from the start instead.
+#[derive(Debug)]
+pub struct Replay {
+    execution: Execution,
+    begin_block: abci::request::BeginBlock,
+    /// The transactions replayed so far.
+    delivered: Vec<abci::request::DeliverTx>,
+}
+
+impl Replay {
+    pub fn new(execution: Execution, begin_block: abci::request::BeginBlock) -> Self {
+        Self {
+            execution,
+            begin_block,
+            delivered: Vec::new(),
+        }
+    }
+
+    /// The recorded response to the next transaction, if it is the one with
+    /// the given hash.
+    pub fn deliver_tx(
+        &mut self,
+        deliver_tx: abci::request::DeliverTx,
+        tx_hash: &[u8; 32],
+    ) -> Result<abci::response::DeliverTx> {
+        let (recorded_hash, rsp) = self
+            .execution
+            .deliver_txs
+            .get(self.delivered.len())
+            .ok_or_else(|| anyhow!("replayed block has more transactions than were executed"))?;
+        if recorded_hash != tx_hash {
+            return Err(anyhow!(
+                "replayed block has different transactions than were executed"
+            ));
+        }
+        let rsp = rsp.clone();
+        self.delivered.push(deliver_tx);
+        Ok(rsp)
+    }
+
+    /// The recorded response to `EndBlock`, if every transaction was replayed.
+    pub fn end_block(&self) -> Result<abci::response::EndBlock> {
+        if self.delivered.len() != self.execution.deliver_txs.len() {
+            return Err(anyhow!(
+                "replayed block has fewer transactions than were executed"
+            ));
+        }
+        self.execution
+            .end_block
+            .clone()
+            .ok_or_else(|| anyhow!("replayed block was not executed in full"))
+    }
+
+    /// Restarts the replay, giving back the recorded execution.
+    pub fn into_execution(self) -> Execution {
+        self.execution
+    }
+
+    /// Abandons the replay, giving back the requests replayed so far.
+    pub fn into_requests(self) -> (abci::request::BeginBlock, Vec<abci::request::DeliverTx>) {
+        (self.begin_block, self.delivered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::begin_block;
+
+    fn executed_block() -> Execution {
+        let mut execution = Execution::new(1, Hash::None, Default::default());
+        execution.record_deliver_tx([1; 32], &Default::default());
+        execution.record_end_block(&Default::default());
+        execution
+    }
+
+    fn tx(byte: u8) -> abci::request::DeliverTx {
+        abci::request::DeliverTx {
+            tx: vec![byte].into(),
+        }
+    }
+
+    #[test]
+    fn replays_only_the_executed_transactions() {
+        let mut replay = Replay::new(executed_block(), begin_block(1, 1));
+        // The block must end after the executed transactions...
+        assert!(replay.end_block().is_err());
+        // ...which must be replayed in order...
+        assert!(replay.deliver_tx(tx(2), &[2; 32]).is_err());
+        replay.deliver_tx(tx(1), &[1; 32]).unwrap();
+        // ...with no more after them.
+        assert!(replay.deliver_tx(tx(3), &[3; 32]).is_err());
+        replay.end_block().unwrap();
+
+        // An abandoned replay gives back only the requests it accepted.
+        let (begin_block, delivered) = replay.into_requests();
+        assert_eq!(begin_block.header.height.value(), 1);
+        assert_eq!(delivered.len(), 1);
+    }
+}
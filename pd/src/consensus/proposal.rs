@@ -0,0 +1,309 @@
+//! Application-side block proposal logic.
+//!
+//! Tendermint currently decides which transactions go into a block, and in what
+//! order. The functions here are shaped after ABCI++'s `PrepareProposal` and
+//! `ProcessProposal`, so that once tower-abci exposes those requests, the
+//! application can take over transaction selection and ordering by wiring them
+//! into the worker. Until then, only [`validate_tx`] is used on the consensus path.
+
+use std::{cmp::Ordering, collections::BTreeSet};
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use penumbra_proto::Protobuf;
+use penumbra_transaction::Transaction;
+
+use super::{TxCode, TxError};
+use crate::{App, Component};
+
+/// Decodes a transaction and performs all stateless and stateful checks on it
+/// against the current state of the `app`.
+///
+/// This does not execute the transaction.
+pub async fn validate_tx(app: &App, tx: Bytes) -> Result<Transaction, TxError> {
+    // Verify the transaction is well-formed...
+    let transaction = Transaction::decode(tx).map_err(|e| TxError::new(TxCode::Malformed, e))?;
+    // ... and statelessly valid...
+    App::check_tx_stateless(&transaction)
+        .map_err(|e| TxError::new(TxCode::StatelessCheckFailed, e))?;
+    // ... and statefully valid.
+    app.check_tx_stateful(&transaction)
+        .await
+        .map_err(|e| TxError::new(TxCode::StatefulCheckFailed, e))?;
+    Ok(transaction)
+}
+
+/// Selects and orders transactions for a block this node is proposing.
+///
+/// Invalid transactions, transactions which would double-spend a nullifier
+/// already spent by an earlier transaction in the block, and transactions which
+/// would push the block over `max_bytes` are dropped. The remaining
+/// transactions are returned in [`block_order`].
+pub async fn prepare_block(app: &App, candidates: Vec<Bytes>, max_bytes: usize) -> Vec<Bytes> {
+    let mut valid = Vec::with_capacity(candidates.len());
+    for tx_bytes in candidates {
+        match validate_tx(app, tx_bytes.clone()).await {
+            Ok(transaction) => valid.push((transaction, tx_bytes)),
+            Err(e) => tracing::debug!(?e, "dropping invalid transaction from proposal"),
+        }
+    }
+
+    select(valid, max_bytes)
+}
+
+/// Orders already validated transactions with [`block_order`], dropping those
+/// which would double-spend a nullifier or push the block over `max_bytes`.
+fn select(mut valid: Vec<(Transaction, Bytes)>, max_bytes: usize) -> Vec<Bytes> {
+    valid.sort_by(|(a, _), (b, _)| block_order(a, b));
+
+    let mut spent = BTreeSet::new();
+    let mut size = 0;
+    let mut selected = Vec::with_capacity(valid.len());
+    for (transaction, tx_bytes) in valid {
+        if size + tx_bytes.len() > max_bytes {
+            continue;
+        }
+        let nullifiers = transaction.spent_nullifiers();
+        if nullifiers.iter().any(|nf| spent.contains(nf)) {
+            tracing::debug!("dropping double-spending transaction from proposal");
+            continue;
+        }
+        spent.extend(nullifiers);
+        size += tx_bytes.len();
+        selected.push(tx_bytes);
+    }
+
+    selected
+}
+
+/// Checks that a block proposed by another validator is one this node could
+/// have produced with [`prepare_block`].
+///
+/// # Errors
+///
+/// Returns an error if any transaction is invalid, if two transactions spend
+/// the same nullifier, or if the transactions are not in [`block_order`].
+pub async fn process_block(app: &App, txs: &[Bytes]) -> Result<()> {
+    let mut transactions = Vec::with_capacity(txs.len());
+    for (i, tx_bytes) in txs.iter().enumerate() {
+        let transaction = validate_tx(app, tx_bytes.clone())
+            .await
+            .map_err(|e| anyhow!("transaction {} is invalid: {}", i, e))?;
+        transactions.push(transaction);
+    }
+
+    check_block(&transactions)
+}
+
+/// Checks that already validated transactions neither double-spend a nullifier
+/// nor break [`block_order`].
+fn check_block(transactions: &[Transaction]) -> Result<()> {
+    let mut spent = BTreeSet::new();
+    let mut previous: Option<&Transaction> = None;
+    for (i, transaction) in transactions.iter().enumerate() {
+        for nf in transaction.spent_nullifiers() {
+            if !spent.insert(nf) {
+                return Err(anyhow!("transaction {} double-spends a nullifier", i));
+            }
+        }
+
+        if let Some(previous) = previous {
+            if block_order(previous, transaction) == Ordering::Greater {
+                return Err(anyhow!("transaction {} is out of order", i));
+            }
+        }
+        previous = Some(transaction);
+    }
+
+    Ok(())
+}
+
+/// The order in which transactions are included in a block.
+///
+/// Transactions which change the validator set (validator definitions,
+/// delegations and undelegations) come first, so that they take effect before
+/// any epoch transition at the end of the block; ties are broken by fee, highest
+/// first.
+pub fn block_order(a: &Transaction, b: &Transaction) -> Ordering {
+    fn changes_validators(tx: &Transaction) -> bool {
+        tx.validator_definitions().next().is_some()
+            || tx.delegations().next().is_some()
+            || tx.undelegations().next().is_some()
+    }
+
+    changes_validators(b)
+        .cmp(&changes_validators(a))
+        .then_with(|| b.transaction_body.fee.0.cmp(&a.transaction_body.fee.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{
+        keys::SpendKey,
+        merkle::{self, Frontier, NoteCommitmentTree, Tree, TreeExt},
+        rdsa::Signature,
+        Note, Value,
+    };
+    use penumbra_stake::{Delegate, STAKING_TOKEN_ASSET_ID};
+    use penumbra_transaction::{Action, Fee, TransactionBody};
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::testing::{spend_key, validator, Node};
+
+    /// Notes held by one spend key, witnessed in a local note commitment tree
+    /// so that they can be spent.
+    struct Notes {
+        spend_key: SpendKey,
+        nct: NoteCommitmentTree,
+    }
+
+    impl Notes {
+        fn new() -> Self {
+            Self {
+                spend_key: spend_key(),
+                nct: NoteCommitmentTree::new(0),
+            }
+        }
+
+        fn note(&mut self, amount: u64) -> Note {
+            let address = self
+                .spend_key
+                .incoming_viewing_key()
+                .payment_address(0u64.into())
+                .0;
+            let note = Note::generate(
+                &mut OsRng,
+                &address,
+                Value {
+                    amount,
+                    asset_id: *STAKING_TOKEN_ASSET_ID,
+                },
+            );
+            self.nct.append(&note.commit());
+            self.nct.witness();
+            note
+        }
+
+        /// A transaction spending all of `note` on its fee.
+        fn spend(&self, note: &Note) -> Transaction {
+            Transaction::build_with_root(self.nct.root2())
+                .set_fee(note.amount())
+                .set_chain_id("penumbra".to_string())
+                .add_spend(&mut OsRng, &self.nct, &self.spend_key, note.clone())
+                .unwrap()
+                .finalize(&mut OsRng)
+                .unwrap()
+        }
+    }
+
+    /// An (unsigned) transaction delegating to a validator.
+    fn delegation(fee: u64) -> Transaction {
+        Transaction {
+            transaction_body: TransactionBody {
+                actions: vec![Action::Delegate(Delegate {
+                    validator_identity: validator("a").identity_key,
+                    epoch_index: 0,
+                    unbonded_amount: 1,
+                    delegation_amount: 1,
+                })],
+                merkle_root: merkle::Root(Default::default()),
+                expiry_height: 0,
+                chain_id: String::new(),
+                fee: Fee(fee),
+            },
+            binding_sig: Signature::from([0; 64]),
+        }
+    }
+
+    fn with_bytes(txs: &[&Transaction]) -> Vec<(Transaction, Bytes)> {
+        txs.iter()
+            .map(|tx| ((*tx).clone(), tx.encode_to_vec().into()))
+            .collect()
+    }
+
+    fn encoded(txs: &[&Transaction]) -> Vec<Bytes> {
+        with_bytes(txs)
+            .into_iter()
+            .map(|(_, bytes)| bytes)
+            .collect()
+    }
+
+    #[test]
+    fn validator_changes_come_first_then_highest_fee() {
+        let mut notes = Notes::new();
+        let (cheap, dear) = (notes.note(1), notes.note(5));
+        let (cheap, dear) = (notes.spend(&cheap), notes.spend(&dear));
+        let delegation = delegation(0);
+
+        let selected = select(with_bytes(&[&cheap, &dear, &delegation]), usize::MAX);
+        assert_eq!(selected, encoded(&[&delegation, &dear, &cheap]));
+
+        check_block(&[delegation.clone(), dear.clone(), cheap.clone()]).unwrap();
+        let error = check_block(&[dear, delegation, cheap]).unwrap_err();
+        assert_eq!(error.to_string(), "transaction 1 is out of order");
+    }
+
+    #[test]
+    fn double_spends_keep_the_highest_fee() {
+        let mut notes = Notes::new();
+        let note = notes.note(5);
+        let first = notes.spend(&note);
+        // The same note, spent again with a different proof and signature.
+        let second = notes.spend(&note);
+        assert_eq!(first.spent_nullifiers(), second.spent_nullifiers());
+        let dearer = delegation(10);
+
+        let selected = select(with_bytes(&[&first, &dearer, &second]), usize::MAX);
+        assert_eq!(selected.len(), 2);
+        assert_eq!(selected[0], encoded(&[&dearer])[0]);
+
+        let error = check_block(&[first, second]).unwrap_err();
+        assert_eq!(error.to_string(), "transaction 1 double-spends a nullifier");
+    }
+
+    #[test]
+    fn transactions_over_the_size_limit_are_skipped() {
+        let delegation = delegation(0);
+        let mut empty = delegation.clone();
+        empty.transaction_body.actions.clear();
+        let [large, small]: [Bytes; 2] = encoded(&[&delegation, &empty]).try_into().unwrap();
+        assert!(small.len() < large.len());
+
+        let txs = with_bytes(&[&empty, &delegation]);
+        let both = large.len() + small.len();
+        assert_eq!(
+            select(txs.clone(), both),
+            vec![large.clone(), small.clone()]
+        );
+        assert_eq!(select(txs.clone(), both - 1), vec![large]);
+        // A transaction which does not fit is skipped, not the end of the block.
+        assert_eq!(select(txs.clone(), small.len()), vec![small]);
+        assert!(select(txs, small.len() - 1).is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_transactions_are_dropped_or_rejected() -> Result<()> {
+        let node = Node::start(Default::default()).await?;
+        let app = App::new(node.storage().overlay().await?).await?;
+
+        // The spend's anchor is the root of a tree the chain has never seen.
+        let mut notes = Notes::new();
+        let note = notes.note(1);
+        let spend = notes.spend(&note);
+        let garbage = Bytes::from_static(b"not a transaction");
+
+        let candidates = vec![garbage, encoded(&[&spend])[0].clone()];
+        assert!(prepare_block(&app, candidates.clone(), usize::MAX)
+            .await
+            .is_empty());
+
+        let error = process_block(&app, &candidates).await.unwrap_err();
+        assert!(error.to_string().starts_with("transaction 0 is invalid"));
+        let error = process_block(&app, &candidates[1..]).await.unwrap_err();
+        assert!(error.to_string().starts_with("transaction 0 is invalid"));
+        process_block(&app, &[]).await?;
+
+        Ok(())
+    }
+}
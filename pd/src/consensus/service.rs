@@ -9,12 +9,15 @@ use tendermint::{
     abci::{ConsensusRequest, ConsensusResponse},
     block,
 };
-use tokio::sync::{mpsc, oneshot, watch};
-use tokio_util::sync::PollSender;
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    task::JoinHandle,
+};
+use tokio_util::sync::{CancellationToken, PollSender};
 use tower_abci::BoxError;
 
 use super::{Message, Worker};
-use crate::{RequestExt, Storage};
+use crate::{replay::ReplayLog, NullifierCache, RequestExt, Storage};
 
 #[derive(Clone)]
 pub struct Consensus {
@@ -22,7 +25,40 @@ pub struct Consensus {
 }
 
 impl Consensus {
-    pub async fn new(storage: Storage) -> anyhow::Result<(Self, watch::Receiver<block::Height>)> {
+    /// Starts the consensus worker.
+    ///
+    /// If `persist_tx_results` is set, the result of every `DeliverTx` is
+    /// recorded in local storage, so that it can be queried by transaction hash.
+    ///
+    /// If `cache_replayed_blocks` is set, each executed block is kept until it
+    /// is committed, so that if Tendermint reconnects and replays the block,
+    /// it is committed without verifying and executing its transactions again.
+    ///
+    /// The nullifiers spent by each delivered transaction are claimed in
+    /// `nullifiers` until its block is committed.
+    ///
+    /// If `replay_log` is set, every request is recorded in it with its
+    /// response, for `pd replay --log`.
+    ///
+    /// If `halt_height` is set, the worker stops, as if `stop` were cancelled,
+    /// once the block at that height is committed, and cancels `stop` itself.
+    ///
+    /// Once `stop` is cancelled, the worker finishes the block in progress, if
+    /// any, through its `Commit`, and then stops processing requests. The
+    /// returned task completes when the worker has stopped.
+    pub async fn new(
+        storage: Storage,
+        persist_tx_results: bool,
+        cache_replayed_blocks: bool,
+        nullifiers: NullifierCache,
+        stop: CancellationToken,
+        replay_log: Option<ReplayLog>,
+        halt_height: Option<u64>,
+    ) -> anyhow::Result<(
+        Self,
+        watch::Receiver<block::Height>,
+        JoinHandle<anyhow::Result<()>>,
+    )> {
         let (queue_tx, queue_rx) = mpsc::channel(10);
         let initial_height = match storage.latest_version().await? {
             Some(version) => version.try_into().unwrap(),
@@ -30,13 +66,28 @@ impl Consensus {
         };
         let (height_tx, height_rx) = watch::channel(initial_height);
 
-        tokio::spawn(Worker::new(storage, queue_rx, height_tx).await?.run());
+        let worker = tokio::spawn(
+            Worker::new(
+                storage,
+                queue_rx,
+                height_tx,
+                persist_tx_results,
+                cache_replayed_blocks,
+                nullifiers,
+                stop,
+                replay_log,
+                halt_height,
+            )
+            .await?
+            .run(),
+        );
 
         Ok((
             Self {
                 queue: PollSender::new(queue_tx),
             },
             height_rx,
+            worker,
         ))
     }
 }
@@ -66,3 +117,160 @@ impl tower::Service<ConsensusRequest> for Consensus {
         async move { Ok(rx.await.expect("worker error??")) }.boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sha2::{Digest, Sha256};
+
+    use tendermint::abci;
+    use tower::{Service, ServiceExt};
+
+    use super::*;
+    use crate::{
+        genesis,
+        pd_metrics::{histogram_count, test_handle},
+        testing::{begin_block, Node},
+    };
+
+    async fn start(
+        node: &Node,
+        persist_tx_results: bool,
+        stop: CancellationToken,
+    ) -> anyhow::Result<(Consensus, JoinHandle<anyhow::Result<()>>)> {
+        let (consensus, _, worker) = Consensus::new(
+            node.storage().clone(),
+            persist_tx_results,
+            false,
+            NullifierCache::default(),
+            stop,
+            None,
+            None,
+        )
+        .await?;
+        Ok((consensus, worker))
+    }
+
+    async fn send(consensus: &mut Consensus, req: ConsensusRequest) -> anyhow::Result<()> {
+        consensus
+            .ready()
+            .await
+            .map_err(|error| anyhow::anyhow!(error))?
+            .call(req)
+            .await
+            .map_err(|error| anyhow::anyhow!(error))?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stops_between_blocks_at_once() -> anyhow::Result<()> {
+        let node = Node::start(genesis::AppState::default()).await?;
+        let stop = CancellationToken::new();
+        let (_consensus, worker) = start(&node, false, stop.clone()).await?;
+
+        stop.cancel();
+        tokio::time::timeout(Duration::from_secs(10), worker).await???;
+        assert_eq!(node.storage().latest_version().await?, Some(0));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn commits_the_block_in_progress_before_stopping() -> anyhow::Result<()> {
+        let node = Node::start(genesis::AppState::default()).await?;
+        let stop = CancellationToken::new();
+        let (mut consensus, mut worker) = start(&node, false, stop.clone()).await?;
+
+        send(
+            &mut consensus,
+            ConsensusRequest::BeginBlock(begin_block(1, 1)),
+        )
+        .await?;
+        stop.cancel();
+
+        // The worker keeps going until the block is committed...
+        send(
+            &mut consensus,
+            ConsensusRequest::EndBlock(abci::request::EndBlock { height: 1 }),
+        )
+        .await?;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), &mut worker)
+                .await
+                .is_err()
+        );
+        send(&mut consensus, ConsensusRequest::Commit).await?;
+
+        // ... and then stops.
+        tokio::time::timeout(Duration::from_secs(10), worker).await???;
+        assert_eq!(node.storage().latest_version().await?, Some(1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn discards_the_block_in_progress_when_tendermint_replays_it() -> anyhow::Result<()> {
+        let node = Node::start(genesis::AppState::default()).await?;
+        let (mut consensus, _worker) = start(&node, true, CancellationToken::new()).await?;
+
+        // Tendermint delivers a transaction, then restarts and begins the
+        // block again without it.
+        let tx = b"not a transaction".to_vec();
+        send(
+            &mut consensus,
+            ConsensusRequest::BeginBlock(begin_block(1, 1)),
+        )
+        .await?;
+        send(
+            &mut consensus,
+            ConsensusRequest::DeliverTx(abci::request::DeliverTx {
+                tx: tx.clone().into(),
+            }),
+        )
+        .await?;
+        for req in [
+            ConsensusRequest::BeginBlock(begin_block(1, 1)),
+            ConsensusRequest::EndBlock(abci::request::EndBlock { height: 1 }),
+            ConsensusRequest::Commit,
+        ] {
+            send(&mut consensus, req).await?;
+        }
+
+        assert_eq!(node.storage().latest_version().await?, Some(1));
+        let tx_hash: [u8; 32] = Sha256::digest(&tx).into();
+        assert_eq!(node.storage().tx_result(tx_hash).await?, None);
+        let report = crate::verify::check(node.storage()).await?;
+        assert!(report.is_consistent(), "{:?}", report.problems);
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn records_how_long_blocks_take() -> anyhow::Result<()> {
+        let handle = test_handle();
+        let node = Node::start(genesis::AppState::default()).await?;
+        let (mut consensus, _worker) = start(&node, false, CancellationToken::new()).await?;
+
+        // Other tests execute blocks too, so only an increase can be checked.
+        let counts = || {
+            [
+                "node_block_execution_duration_seconds",
+                "node_block_commit_duration_seconds",
+            ]
+            .map(|name| histogram_count(&handle, name))
+        };
+        let before = counts();
+        for req in [
+            ConsensusRequest::BeginBlock(begin_block(1, 1)),
+            ConsensusRequest::EndBlock(abci::request::EndBlock { height: 1 }),
+            ConsensusRequest::Commit,
+        ] {
+            send(&mut consensus, req).await?;
+        }
+        let after = counts();
+        assert!(after[0] > before[0], "{:?} {:?}", before, after);
+        assert!(after[1] > before[1], "{:?} {:?}", before, after);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+/// A stable, public classification of why a transaction was rejected.
+///
+/// The numeric value is returned as the ABCI result `code`, and
+/// [`TxCode::public_message`] as the result `log`. Both end up in public
+/// consensus results, so they must not depend on internal error details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TxCode {
+    /// The transaction could not be decoded.
+    Malformed = 1,
+    /// The transaction failed a stateless validity check.
+    StatelessCheckFailed = 2,
+    /// The transaction failed a check against the current chain state.
+    StatefulCheckFailed = 3,
+}
+
+impl TxCode {
+    /// The minimal message to report publicly for this code.
+    pub fn public_message(&self) -> &'static str {
+        match self {
+            TxCode::Malformed => "malformed transaction",
+            TxCode::StatelessCheckFailed => "transaction failed stateless validation",
+            TxCode::StatefulCheckFailed => "transaction failed stateful validation",
+        }
+    }
+}
+
+impl From<TxCode> for u32 {
+    fn from(code: TxCode) -> u32 {
+        code as u32
+    }
+}
+
+/// A transaction validation failure, pairing a public [`TxCode`] with the full
+/// internal error, which should only be logged locally.
+#[derive(Debug)]
+pub struct TxError {
+    pub code: TxCode,
+    pub source: anyhow::Error,
+}
+
+impl TxError {
+    pub fn new(code: TxCode, source: anyhow::Error) -> Self {
+        Self { code, source }
+    }
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code.public_message(), self.source)
+    }
+}
+
+impl std::error::Error for TxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{consensus::proposal, testing::Node, App};
+
+    #[test]
+    fn codes_are_stable() {
+        // These are returned to clients as ABCI result codes, so changing
+        // them breaks anything matching on them.
+        assert_eq!(u32::from(TxCode::Malformed), 1);
+        assert_eq!(u32::from(TxCode::StatelessCheckFailed), 2);
+        assert_eq!(u32::from(TxCode::StatefulCheckFailed), 3);
+    }
+
+    #[test]
+    fn public_message_omits_the_source() {
+        let error = TxError::new(
+            TxCode::StatefulCheckFailed,
+            anyhow::anyhow!("nullifier 0x1234 was already spent"),
+        );
+        assert_eq!(
+            error.code.public_message(),
+            "transaction failed stateful validation"
+        );
+        assert_eq!(
+            error.to_string(),
+            "transaction failed stateful validation: nullifier 0x1234 was already spent"
+        );
+    }
+
+    #[tokio::test]
+    async fn undecodable_transactions_are_malformed() -> anyhow::Result<()> {
+        let node = Node::start(Default::default()).await?;
+        let app = App::new(node.storage().overlay().await?).await?;
+
+        let error = proposal::validate_tx(&app, Bytes::from_static(b"not a transaction"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, TxCode::Malformed);
+
+        Ok(())
+    }
+}
@@ -1,23 +1,49 @@
-use anyhow::{anyhow, Result};
-
-use penumbra_proto::Protobuf;
+use std::time::Instant;
 
-use penumbra_transaction::Transaction;
+use anyhow::{anyhow, Result};
+use penumbra_proto::chain::{self as pb, TxResult};
+use sha2::{Digest, Sha256};
 use tendermint::{
     abci::{self, ConsensusRequest as Request, ConsensusResponse as Response},
     block,
 };
 use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
 use tracing::Instrument;
 
-use super::Message;
-use crate::{genesis, App, Component, Storage};
+use super::{proposal, Execution, Message, Replay, TxError};
+use crate::{genesis, replay::ReplayLog, App, Component, NullifierCache, Storage};
 
 pub struct Worker {
     queue: mpsc::Receiver<Message>,
     height_tx: watch::Sender<block::Height>,
     storage: Storage,
     app: App,
+    /// The height of the block being executed.
+    height: u64,
+    /// If set, the results of the current block's transactions, to be
+    /// persisted when it is committed.
+    tx_results: Option<Vec<([u8; 32], TxResult)>>,
+    /// Claims the nullifiers of the current block's transactions, for the
+    /// mempool to check against.
+    nullifiers: NullifierCache,
+    /// Cancelled to stop the worker once the block in progress is committed.
+    stop: CancellationToken,
+    /// Whether a block has begun and not yet been committed.
+    in_block: bool,
+    /// Whether to record the execution of each block until it is committed,
+    /// so that a replay of the block can reuse it.
+    cache_replays: bool,
+    /// The record of the current block's execution, if it is being recorded.
+    execution: Option<Execution>,
+    /// The current block's replay of a recorded execution, if any.
+    replay: Option<Replay>,
+    /// When the current block began executing, unless it is being replayed.
+    block_started: Option<Instant>,
+    /// The log to record each request and its response in, if any.
+    replay_log: Option<ReplayLog>,
+    /// The height after whose commit to stop, if any.
+    halt_height: Option<u64>,
 }
 
 impl Worker {
@@ -25,6 +51,12 @@ impl Worker {
         storage: Storage,
         queue: mpsc::Receiver<Message>,
         height_tx: watch::Sender<block::Height>,
+        persist_tx_results: bool,
+        cache_replays: bool,
+        nullifiers: NullifierCache,
+        stop: CancellationToken,
+        replay_log: Option<ReplayLog>,
+        halt_height: Option<u64>,
     ) -> Result<Self> {
         let app = App::new(storage.overlay().await?).await?;
 
@@ -33,20 +65,48 @@ impl Worker {
             height_tx,
             storage,
             app,
+            height: 0,
+            tx_results: if persist_tx_results {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            nullifiers,
+            stop,
+            in_block: false,
+            cache_replays,
+            execution: None,
+            replay: None,
+            block_started: None,
+            replay_log,
+            halt_height,
         })
     }
 
     pub async fn run(mut self) -> Result<()> {
-        while let Some(Message {
-            req,
-            rsp_sender,
-            span,
-        }) = self.queue.recv().await
-        {
-            // The send only fails if the receiver was dropped, which happens
-            // if the caller didn't propagate the message back to tendermint
-            // for some reason -- but that's not our problem.
-            let _ = rsp_sender.send(match req {
+        loop {
+            // Stopping between blocks means nothing is lost: the state of a
+            // block which has begun but not been committed only lives in
+            // memory, and Tendermint replays that block on restart anyway.
+            if self.stop.is_cancelled() && !self.in_block {
+                tracing::info!(height = self.height, "consensus worker stopped");
+                break;
+            }
+            let message = tokio::select! {
+                message = self.queue.recv() => message,
+                _ = self.stop.cancelled(), if !self.stop.is_cancelled() => continue,
+            };
+            let Message {
+                req,
+                rsp_sender,
+                span,
+            } = match message {
+                Some(message) => message,
+                None => break,
+            };
+
+            let logged_req = self.replay_log.is_some().then(|| req.clone());
+            let rsp = match req {
                 Request::InitChain(init_chain) => Response::InitChain(
                     self.init_chain(init_chain)
                         .instrument(span)
@@ -59,16 +119,12 @@ impl Worker {
                         .await
                         .expect("begin_block must succeed"),
                 ),
-                Request::DeliverTx(deliver_tx) => {
-                    Response::DeliverTx(match self.deliver_tx(deliver_tx).instrument(span).await {
-                        Ok(()) => abci::response::DeliverTx::default(),
-                        Err(e) => abci::response::DeliverTx {
-                            code: 1,
-                            log: e.to_string(),
-                            ..Default::default()
-                        },
-                    })
-                }
+                Request::DeliverTx(deliver_tx) => Response::DeliverTx(
+                    self.replay_or_deliver_tx(deliver_tx)
+                        .instrument(span)
+                        .await
+                        .expect("deliver_tx must succeed, up to internal errors"),
+                ),
                 Request::EndBlock(end_block) => Response::EndBlock(
                     self.end_block(end_block)
                         .instrument(span)
@@ -81,7 +137,19 @@ impl Worker {
                         .await
                         .expect("commit must succeed"),
                 ),
-            });
+            };
+            if let (Some(log), Some(req)) = (&mut self.replay_log, logged_req) {
+                // A gap would make the log useless for replay, so recording
+                // stops at the first failure, but consensus carries on.
+                if let Err(error) = log.record(req, rsp.clone()) {
+                    tracing::error!(?error, "could not write replay log, no longer recording");
+                    self.replay_log = None;
+                }
+            }
+            // The send only fails if the receiver was dropped, which happens
+            // if the caller didn't propagate the message back to tendermint
+            // for some reason -- but that's not our problem.
+            let _ = rsp_sender.send(rsp);
         }
         Ok(())
     }
@@ -98,6 +166,10 @@ impl Worker {
         // Note that errors cannot be handled in InitChain, the application must crash.
         let app_state: genesis::AppState = serde_json::from_slice(&init_chain.app_state_bytes)
             .expect("can parse app_state in genesis file");
+        tracing::info!(
+            genesis_hash = %hex::encode(app_state.hash()),
+            "loaded genesis app state; compare with `pd genesis hash` on other validators"
+        );
 
         // Check that we haven't got a duplicated InitChain message for some reason:
         if self.storage.latest_version().await?.is_some() {
@@ -135,9 +207,138 @@ impl Worker {
         &mut self,
         begin_block: abci::request::BeginBlock,
     ) -> Result<abci::response::BeginBlock> {
+        let height = begin_block.header.height.value();
+        if self.in_block {
+            // If the uncommitted block was executed in full, and is the block
+            // being begun again, its changes can be committed as they are.
+            let execution = match self.replay.take() {
+                Some(replay) => Some(replay.into_execution()),
+                None => self.execution.take(),
+            };
+            if let Some(execution) =
+                execution.filter(|execution| execution.replays(height, &begin_block.hash))
+            {
+                tracing::info!(
+                    height,
+                    "replaying the already executed block after Tendermint reconnected"
+                );
+                metrics::counter!("node_consensus_cached_replays_total", 1);
+                // The results are recorded again as the transactions are
+                // replayed, and the block's nullifiers are still claimed.
+                if let Some(tx_results) = &mut self.tx_results {
+                    tx_results.clear();
+                }
+                let rsp = execution.begin_block();
+                self.replay = Some(Replay::new(execution, begin_block));
+                self.block_started = None;
+                return Ok(rsp);
+            }
+
+            // Tendermint only begins a block before committing the previous
+            // one if it restarted mid-block and is replaying it on a new
+            // connection, so otherwise the uncommitted changes have to be
+            // thrown away.
+            tracing::warn!(
+                abandoned_height = self.height,
+                height,
+                "discarding uncommitted block after Tendermint reconnected"
+            );
+            self.app = App::new(self.storage.overlay().await?).await?;
+            if let Some(tx_results) = &mut self.tx_results {
+                tx_results.clear();
+            }
+            self.nullifiers.release_block();
+        }
+        self.height = height;
+        self.in_block = true;
+        self.block_started = Some(Instant::now());
         self.app.begin_block(&begin_block).await?;
-        // TODO(events): consider creating + returning Events to Tendermint here.
-        Ok(Default::default())
+        let rsp = abci::response::BeginBlock {
+            events: self.app.take_events(),
+        };
+        if self.cache_replays {
+            self.execution = Some(Execution::new(height, begin_block.hash, rsp.clone()));
+        }
+        Ok(rsp)
+    }
+
+    /// Replays the recorded response to a transaction if the block is being
+    /// replayed, and otherwise executes it, recording its result.
+    async fn replay_or_deliver_tx(
+        &mut self,
+        deliver_tx: abci::request::DeliverTx,
+    ) -> Result<abci::response::DeliverTx> {
+        let tx_hash: [u8; 32] = Sha256::digest(&deliver_tx.tx).into();
+        if let Some(replay) = &mut self.replay {
+            let tx = deliver_tx.tx.clone();
+            match replay.deliver_tx(deliver_tx.clone(), &tx_hash) {
+                Ok(rsp) => {
+                    self.record_tx_result(tx_hash, &tx, &rsp);
+                    return Ok(rsp);
+                }
+                Err(error) => self.abandon_replay(error).await?,
+            }
+        }
+        Ok(self.execute_tx(deliver_tx, tx_hash).await)
+    }
+
+    /// Executes a transaction, recording its result.
+    async fn execute_tx(
+        &mut self,
+        deliver_tx: abci::request::DeliverTx,
+        tx_hash: [u8; 32],
+    ) -> abci::response::DeliverTx {
+        let tx = deliver_tx.tx.clone();
+        let rsp = match self.deliver_tx(deliver_tx).await {
+            Ok(events) => abci::response::DeliverTx {
+                events,
+                ..Default::default()
+            },
+            Err(e) => {
+                // Only the stable public message goes into the consensus
+                // result; the full error stays in the local logs.
+                tracing::debug!(code = ?e.code, error = ?e.source, "deliver_tx failed");
+                abci::response::DeliverTx {
+                    code: e.code.into(),
+                    log: e.code.public_message().to_string(),
+                    ..Default::default()
+                }
+            }
+        };
+        if let Some(execution) = &mut self.execution {
+            execution.record_deliver_tx(tx_hash, &rsp);
+        }
+        self.record_tx_result(tx_hash, &tx, &rsp);
+        rsp
+    }
+
+    /// Stops replaying the current block after it turned out to differ from
+    /// the recorded execution, and executes it from the start instead, up to
+    /// the transactions already replayed.
+    ///
+    /// Execution is deterministic, so the responses already sent for the
+    /// replayed requests are the ones executing them produces.
+    async fn abandon_replay(&mut self, error: anyhow::Error) -> Result<()> {
+        let replay = match self.replay.take() {
+            Some(replay) => replay,
+            None => return Ok(()),
+        };
+        tracing::warn!(
+            height = self.height,
+            %error,
+            "replayed block differs from its cached execution, executing it instead"
+        );
+        metrics::counter!("node_consensus_abandoned_replays_total", 1);
+
+        // With no replay or execution recorded, beginning the block again
+        // discards its uncommitted changes.
+        let (begin_block, delivered) = replay.into_requests();
+        self.begin_block(begin_block).await?;
+        for deliver_tx in delivered {
+            let tx_hash: [u8; 32] = Sha256::digest(&deliver_tx.tx).into();
+            self.execute_tx(deliver_tx, tx_hash).await;
+        }
+        Ok(())
     }
 
     /// Perform full transaction validation via `DeliverTx`.
@@ -147,13 +348,19 @@ impl Worker {
     /// We must perform all checks again here even though they are performed in `CheckTx`, as a
     /// Byzantine node may propose a block containing double spends or other disallowed behavior,
     /// so it is not safe to assume all checks performed in `CheckTx` were done.
-    async fn deliver_tx(&mut self, deliver_tx: abci::request::DeliverTx) -> Result<()> {
-        // Verify the transaction is well-formed...
-        let transaction = Transaction::decode(deliver_tx.tx)?;
-        // ... and statelessly valid...
-        App::check_tx_stateless(&transaction)?;
-        // ... and statefully valid.
-        self.app.check_tx_stateful(&transaction).await?;
+    ///
+    /// Returns the events describing the transaction's effects.
+    async fn deliver_tx(
+        &mut self,
+        deliver_tx: abci::request::DeliverTx,
+    ) -> Result<Vec<abci::Event>, TxError> {
+        let started = Instant::now();
+        let transaction = proposal::validate_tx(&self.app, deliver_tx.tx).await?;
+        metrics::histogram!(
+            "node_tx_verification_duration_seconds",
+            started.elapsed(),
+            "path" => "deliver_tx"
+        );
         // Now execute the transaction. It's important to panic on error here, since if
         // we fail to execute the transaction here, it's because of an internal
         // error and we may have left the chain in an inconsistent state.
@@ -161,39 +368,114 @@ impl Worker {
             .execute_tx(&transaction)
             .await
             .expect("execution of valid tx must succeed, up to internal errors");
-        Ok(())
+        self.nullifiers
+            .claim_in_block(transaction.id(), &transaction.spent_nullifiers());
+        Ok(self.app.take_events())
+    }
+
+    /// Records the result of a delivered transaction, if results are persisted.
+    fn record_tx_result(&mut self, tx_hash: [u8; 32], tx: &[u8], rsp: &abci::response::DeliverTx) {
+        if let Some(tx_results) = &mut self.tx_results {
+            let events = rsp
+                .events
+                .iter()
+                .map(|event| pb::Event {
+                    kind: event.kind.clone(),
+                    attributes: event
+                        .attributes
+                        .iter()
+                        .map(|attribute| pb::EventAttribute {
+                            key: attribute.key.clone(),
+                            value: attribute.value.clone(),
+                            index: attribute.index,
+                        })
+                        .collect(),
+                })
+                .collect();
+
+            tx_results.push((
+                tx_hash,
+                TxResult {
+                    height: self.height,
+                    tx: tx.to_vec(),
+                    code: rsp.code,
+                    log: rsp.log.clone(),
+                    gas_wanted: rsp.gas_wanted,
+                    gas_used: rsp.gas_used,
+                    events,
+                },
+            ));
+        }
     }
 
     async fn end_block(
         &mut self,
         end_block: abci::request::EndBlock,
     ) -> Result<abci::response::EndBlock> {
+        if let Some(replay) = &self.replay {
+            match replay.end_block() {
+                Ok(rsp) => return Ok(rsp),
+                Err(error) => self.abandon_replay(error).await?,
+            }
+        }
         self.app.end_block(&end_block).await?;
 
-        // Set `tm_validator_updates` to the complete set of
-        // validators and voting power. This must be the last step performed,
-        // after all voting power calculations and validator state transitions have
-        // been completed.
-        let validator_updates = self.app.tm_validator_updates().await?;
-
-        tracing::debug!(
-            ?validator_updates,
-            "SKIPPING sending validator updates to tendermint"
-        );
+        // Report the changes to the validator set. This must be the last step
+        // performed, after all voting power calculations and validator state
+        // transitions have been completed.
+        let validator_updates = self.app.validator_set_updates().await?;
+        if !validator_updates.is_empty() {
+            tracing::info!(?validator_updates, "updating tendermint validator set");
+        }
 
-        Ok(abci::response::EndBlock {
-            validator_updates: Vec::new(),
+        let rsp = abci::response::EndBlock {
+            validator_updates,
             consensus_param_updates: None,
-            events: Vec::new(),
-        })
+            events: self.app.take_events(),
+        };
+        if let Some(execution) = &mut self.execution {
+            execution.record_end_block(&rsp);
+        }
+        if let Some(started) = self.block_started.take() {
+            metrics::histogram!("node_block_execution_duration_seconds", started.elapsed());
+        }
+        Ok(rsp)
     }
 
     async fn commit(&mut self) -> Result<abci::response::Commit> {
         // Begin sidecar code
 
         // Note: App::commit resets internal components, so we don't need to do that ourselves.
+        let started = Instant::now();
         let (jmt_root, _) = self.app.commit(self.storage.clone()).await?;
+        metrics::histogram!("node_block_commit_duration_seconds", started.elapsed());
         let app_hash = jmt_root.0.to_vec();
+
+        let stats = self.app.chain_stats().await?;
+        metrics::gauge!("node_chain_commitments", stats.commitments as f64);
+        metrics::gauge!("node_chain_nullifiers", stats.nullifiers as f64);
+        metrics::gauge!("node_chain_transactions", stats.transactions as f64);
+        metrics::gauge!(
+            "node_chain_transactions_per_block",
+            stats.transactions_per_block()
+        );
+        metrics::gauge!(
+            "node_chain_active_validators",
+            stats.active_validators as f64
+        );
+
+        // Only persist transaction results once their block is committed.
+        if let Some(tx_results) = &mut self.tx_results {
+            self.storage
+                .put_tx_results(std::mem::take(tx_results))
+                .await?;
+        }
+        // The block's nullifiers are now spent in the committed state, but
+        // stay claimed until the mempool rechecks against it.
+        self.nullifiers.commit_block();
+        self.execution = None;
+        self.replay = None;
+
         let _ = self.height_tx.send(
             self.storage
                 .latest_version()
@@ -204,6 +486,15 @@ impl Worker {
         );
 
         tracing::info!(app_hash = ?hex::encode(&app_hash), "finished block commit");
+        self.in_block = false;
+
+        if self.halt_height == Some(self.height) {
+            tracing::info!(
+                height = self.height,
+                "reached the halt height; stopping for the upgrade"
+            );
+            self.stop.cancel();
+        }
 
         Ok(abci::response::Commit {
             data: app_hash.into(),
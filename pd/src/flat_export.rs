@@ -0,0 +1,379 @@
+//! Exports of decoded state tables to flat files, for analytics, as written by
+//! `pd export-state --format csv|parquet`.
+//!
+//! The state is a Merkle tree of encoded values under hashed keys, which
+//! standard data tooling cannot read. [`read`] decodes one [`Table`] of the
+//! state at some height into rows of plain columns, and [`write`] writes them
+//! to a CSV file with a header row, or to a Parquet file with a matching
+//! schema, in which integers are unsigned 64-bit and text is UTF-8.
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Result};
+use parquet::{
+    column::writer::ColumnWriter,
+    data_type::ByteArray,
+    file::{
+        properties::WriterProperties,
+        writer::{FileWriter, RowGroupWriter, SerializedFileWriter},
+    },
+    schema::parser::parse_message_type,
+};
+use penumbra_stake::DelegationToken;
+use tendermint::account;
+
+use crate::{
+    components::{app::View as _, shielded_pool::View as _, staking::View as _},
+    genesis, OverlayExt, Storage,
+};
+
+/// A table of decoded state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Table {
+    /// Every validator, with its state, voting power, and rates for the next
+    /// epoch.
+    Validators,
+    /// The genesis allocations.
+    Allocations,
+    /// Every spent nullifier, with the height of the block which spent it.
+    Nullifiers,
+}
+
+impl Table {
+    pub const ALL: [Table; 3] = [Table::Validators, Table::Allocations, Table::Nullifiers];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Table::Validators => "validators",
+            Table::Allocations => "allocations",
+            Table::Nullifiers => "nullifiers",
+        }
+    }
+
+    /// The table's columns, in order.
+    pub fn columns(&self) -> &'static [Column] {
+        use Kind::*;
+        match self {
+            Table::Validators => &[
+                Column::new("identity_key", Text),
+                Column::new("consensus_address", Text),
+                Column::new("name", Text),
+                Column::new("website", Text),
+                Column::new("state", Text),
+                Column::new("voting_power", UInt),
+                Column::nullable("bonded_since_epoch", UInt),
+                Column::new("reward_rate", UInt),
+                Column::new("exchange_rate", UInt),
+                Column::nullable("pool_size", UInt),
+                Column::new("delegation_denom", Text),
+            ],
+            Table::Allocations => &[
+                Column::new("address", Text),
+                Column::new("denom", Text),
+                Column::new("amount", UInt),
+            ],
+            Table::Nullifiers => &[Column::new("height", UInt), Column::new("nullifier", Text)],
+        }
+    }
+}
+
+impl FromStr for Table {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Table::ALL
+            .into_iter()
+            .find(|table| table.name() == s)
+            .ok_or_else(|| {
+                anyhow!(
+                    "unknown table {:?}, expected validators, allocations, or nullifiers",
+                    s
+                )
+            })
+    }
+}
+
+/// The format of the exported files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Parquet,
+}
+
+impl Format {
+    /// The extension of files in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err(anyhow!("invalid format {:?}, expected csv or parquet", s)),
+        }
+    }
+}
+
+/// A column of a [`Table`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub kind: Kind,
+    /// Whether the column may hold [`Value::Null`].
+    pub nullable: bool,
+}
+
+impl Column {
+    const fn new(name: &'static str, kind: Kind) -> Self {
+        Column {
+            name,
+            kind,
+            nullable: false,
+        }
+    }
+
+    const fn nullable(name: &'static str, kind: Kind) -> Self {
+        Column {
+            name,
+            kind,
+            nullable: true,
+        }
+    }
+}
+
+/// The type of a [`Column`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    UInt,
+    Text,
+}
+
+/// A value in a row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    UInt(u64),
+    Text(String),
+    Null,
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::UInt(n)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Text(s)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// The rows of a table, each with a value for each of its columns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rows {
+    pub table: Table,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// A table written by [`export`].
+#[derive(Clone, Debug)]
+pub struct Exported {
+    pub table: Table,
+    pub path: PathBuf,
+    /// The number of rows written.
+    pub rows: usize,
+}
+
+/// Decodes a table of the state at `version`.
+pub async fn read(storage: &Storage, version: u64, table: Table) -> Result<Rows> {
+    let overlay = storage.overlay_at(version).await?;
+    let mut rows = Vec::new();
+    match table {
+        Table::Validators => {
+            for identity_key in overlay.validator_list().await? {
+                let info = overlay
+                    .validator_info(&identity_key)
+                    .await?
+                    .ok_or_else(|| anyhow!("validator {} is listed but missing", identity_key))?;
+                let pool_size = overlay.pool_size(&identity_key).await?;
+                let bonded_since = overlay.validator_bonded_since(&identity_key).await?;
+                let validator = info.validator;
+                rows.push(vec![
+                    identity_key.to_string().into(),
+                    account::Id::from(validator.consensus_key)
+                        .to_string()
+                        .into(),
+                    validator.name.into(),
+                    validator.website.into(),
+                    info.status.state.name().to_str().to_string().into(),
+                    info.status.voting_power.into(),
+                    bonded_since.into(),
+                    info.rate_data.validator_reward_rate.into(),
+                    info.rate_data.validator_exchange_rate.into(),
+                    pool_size.map(|pool| pool.amount).into(),
+                    DelegationToken::new(identity_key)
+                        .denom()
+                        .to_string()
+                        .into(),
+                ]);
+            }
+        }
+        Table::Allocations => {
+            let app_state: genesis::AppState = overlay
+                .get_domain(b"genesis/app_state".into())
+                .await?
+                .ok_or_else(|| anyhow!("the state has no genesis app state"))?;
+            for allocation in app_state.allocations {
+                rows.push(vec![
+                    allocation.address.to_string().into(),
+                    allocation.denom.into(),
+                    allocation.amount.into(),
+                ]);
+            }
+        }
+        Table::Nullifiers => {
+            for height in 0..=overlay.get_block_height().await? {
+                let block = match overlay.compact_block(height).await? {
+                    Some(block) => block,
+                    None => continue,
+                };
+                for nullifier in block.nullifiers {
+                    rows.push(vec![height.into(), nullifier.to_string().into()]);
+                }
+            }
+        }
+    }
+    Ok(Rows { table, rows })
+}
+
+/// Writes the rows to a file at `path`, in the given format.
+pub fn write(rows: &Rows, format: Format, path: &Path) -> Result<()> {
+    match format {
+        Format::Csv => write_csv(rows, path),
+        Format::Parquet => write_parquet(rows, path),
+    }
+    .with_context(|| format!("could not write {}", path.display()))
+}
+
+/// Decodes each of `tables` from the state at `version`, writing each to a
+/// file named after it in `dir`, which is created if it does not exist.
+pub async fn export(
+    storage: &Storage,
+    version: u64,
+    tables: &[Table],
+    format: Format,
+    dir: &Path,
+) -> Result<Vec<Exported>> {
+    fs::create_dir_all(dir).with_context(|| format!("could not create {}", dir.display()))?;
+    let mut exported = Vec::new();
+    for &table in tables {
+        let rows = read(storage, version, table).await?;
+        let path = dir.join(format!("{}.{}", table.name(), format.extension()));
+        write(&rows, format, &path)?;
+        exported.push(Exported {
+            table,
+            path,
+            rows: rows.rows.len(),
+        });
+    }
+    Ok(exported)
+}
+
+fn write_csv(rows: &Rows, path: &Path) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(rows.table.columns().iter().map(|column| column.name))?;
+    for row in &rows.rows {
+        writer.write_record(row.iter().map(|value| match value {
+            Value::UInt(n) => n.to_string(),
+            Value::Text(s) => s.clone(),
+            Value::Null => String::new(),
+        }))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_parquet(rows: &Rows, path: &Path) -> Result<()> {
+    let columns = rows.table.columns();
+    let fields: String = columns
+        .iter()
+        .map(|column| {
+            let repetition = if column.nullable {
+                "OPTIONAL"
+            } else {
+                "REQUIRED"
+            };
+            let (physical, logical) = match column.kind {
+                Kind::UInt => ("INT64", "UINT_64"),
+                Kind::Text => ("BYTE_ARRAY", "UTF8"),
+            };
+            format!(
+                "  {} {} {} ({});\n",
+                repetition, physical, column.name, logical
+            )
+        })
+        .collect();
+    let schema = parse_message_type(&format!("message {} {{\n{}}}", rows.table.name(), fields))?;
+    let properties = WriterProperties::builder().build();
+    let mut writer =
+        SerializedFileWriter::new(File::create(path)?, Arc::new(schema), Arc::new(properties))?;
+
+    // The whole table is written as a single row group.
+    let mut row_group = writer.next_row_group()?;
+    let mut index = 0;
+    while let Some(mut column_writer) = row_group.next_column()? {
+        let column = &columns[index];
+        let values = rows.rows.iter().map(|row| &row[index]);
+        let definition_levels = values
+            .clone()
+            .map(|value| (*value != Value::Null) as i16)
+            .collect::<Vec<_>>();
+        let definition_levels = column.nullable.then(|| definition_levels.as_slice());
+        match &mut column_writer {
+            ColumnWriter::Int64ColumnWriter(typed) => {
+                // Unsigned integers are stored in their two's complement form.
+                let data = values
+                    .filter_map(|value| match value {
+                        Value::UInt(n) => Some(*n as i64),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                typed.write_batch(&data, definition_levels, None)?;
+            }
+            ColumnWriter::ByteArrayColumnWriter(typed) => {
+                let data = values
+                    .filter_map(|value| match value {
+                        Value::Text(s) => Some(ByteArray::from(s.as_str())),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                typed.write_batch(&data, definition_levels, None)?;
+            }
+            _ => unreachable!("tables only have INT64 and BYTE_ARRAY columns"),
+        }
+        row_group.close_column(column_writer)?;
+        index += 1;
+    }
+    writer.close_row_group(row_group)?;
+    writer.close()?;
+    Ok(())
+}
@@ -1,5 +1,9 @@
 mod allocation;
 mod app_state;
+mod diff;
+mod dry_run;
 
 pub use allocation::Allocation;
 pub use app_state::AppState;
+pub use diff::{diff, AllocationChange, EntryChange, FieldChange, GenesisDiff};
+pub use dry_run::{dry_run, parse, DryRun};
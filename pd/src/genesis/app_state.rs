@@ -1,7 +1,8 @@
 use penumbra_chain::params::ChainParams;
 use penumbra_proto::{genesis as pb, Protobuf};
-use penumbra_stake::Validator;
+use penumbra_stake::{BaseRateData, RateData, Validator};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use super::Allocation;
 
@@ -15,6 +16,35 @@ pub struct AppState {
     pub validators: Vec<Validator>,
     /// The initial token allocations.
     pub allocations: Vec<Allocation>,
+    /// The base rate data for the first epoch.
+    ///
+    /// If unset, the chain starts with a base exchange rate of 1. This only
+    /// needs to be set when restarting a chain from exported state, where
+    /// rewards have already accrued. The epoch index is ignored.
+    pub base_rate_data: Option<BaseRateData>,
+    /// Rate data for the first epoch for any validators whose exchange rate is
+    /// not 1. The epoch index is ignored.
+    pub validator_rates: Vec<RateData>,
+}
+
+impl AppState {
+    /// The canonical encoding of this app state.
+    ///
+    /// This is the protobuf encoding of the parsed app state, so it depends
+    /// only on the genesis content, not on how the genesis file happens to be
+    /// formatted (whitespace, field order, or number formatting).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+
+    /// The SHA-256 digest of the [canonical encoding](AppState::canonical_bytes)
+    /// of this app state.
+    ///
+    /// Validators can compare this out of band before the chain starts, to
+    /// check that they all loaded the same genesis.
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(&self.canonical_bytes()).into()
+    }
 }
 
 impl From<AppState> for pb::GenesisAppState {
@@ -23,6 +53,8 @@ impl From<AppState> for pb::GenesisAppState {
             validators: a.validators.into_iter().map(Into::into).collect(),
             allocations: a.allocations.into_iter().map(Into::into).collect(),
             chain_params: Some(a.chain_params.into()),
+            base_rate_data: a.base_rate_data.map(Into::into),
+            validator_rates: a.validator_rates.into_iter().map(Into::into).collect(),
         }
     }
 }
@@ -44,8 +76,71 @@ impl TryFrom<pb::GenesisAppState> for AppState {
                 .into_iter()
                 .map(TryInto::try_into)
                 .collect::<Result<_, _>>()?,
+
+            base_rate_data: msg.base_rate_data.map(TryInto::try_into).transpose()?,
+
+            validator_rates: msg
+                .validator_rates
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
         })
     }
 }
 
 impl Protobuf<pb::GenesisAppState> for AppState {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{address, delegations, validator};
+
+    fn app_state() -> AppState {
+        let validators = vec![validator("a"), validator("b")];
+        AppState {
+            chain_params: ChainParams {
+                chain_id: "hash-test".to_string(),
+                ..Default::default()
+            },
+            allocations: delegations(&validators, 1_000, address()),
+            validators,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hash_ignores_the_formatting_of_the_genesis_file() -> anyhow::Result<()> {
+        let app_state = app_state();
+        let compact = serde_json::to_string(&app_state)?;
+        // Going through a `Value` sorts the keys, unlike the struct order of
+        // the compact encoding.
+        let value: serde_json::Value = serde_json::from_str(&compact)?;
+        let pretty = serde_json::to_string_pretty(&value)?;
+        assert_ne!(compact, pretty);
+
+        for json in [&compact, &pretty] {
+            let parsed: AppState = serde_json::from_str(json)?;
+            assert_eq!(parsed.canonical_bytes(), app_state.canonical_bytes());
+            assert_eq!(parsed.hash(), app_state.hash());
+        }
+        assert_eq!(
+            app_state.hash(),
+            <[u8; 32]>::from(Sha256::digest(&app_state.canonical_bytes()))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn hash_depends_on_the_content() {
+        let app_state = app_state();
+
+        let mut other_chain = app_state.clone();
+        other_chain.chain_params.chain_id = "other".to_string();
+        assert_ne!(other_chain.hash(), app_state.hash());
+
+        let mut fewer_allocations = app_state.clone();
+        fewer_allocations.allocations.pop();
+        assert_ne!(fewer_allocations.hash(), app_state.hash());
+    }
+}
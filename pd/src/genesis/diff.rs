@@ -0,0 +1,258 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::AppState;
+
+/// The semantic differences between two genesis app states, as printed by
+/// `pd genesis diff`.
+///
+/// Unlike a textual diff of the genesis files, this ignores formatting and the
+/// order of validators and allocations: validators and their rates are matched
+/// by identity key, allocations by address and denomination (summing any that
+/// share both), and the chain parameters and base rate data field by field.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GenesisDiff {
+    pub chain_params: Vec<FieldChange>,
+    pub validators: Vec<EntryChange>,
+    pub allocations: Vec<AllocationChange>,
+    pub base_rate_data: Vec<FieldChange>,
+    pub validator_rates: Vec<EntryChange>,
+}
+
+/// A field whose value differs, with each value as compact JSON, or `None`
+/// where the field is absent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// A validator, or a validator's rate data, which differs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryChange {
+    Added {
+        label: String,
+    },
+    Removed {
+        label: String,
+    },
+    Changed {
+        label: String,
+        fields: Vec<FieldChange>,
+    },
+}
+
+/// The total amount of a denomination allocated to an address, which differs.
+/// An amount of 0 means there is no such allocation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AllocationChange {
+    pub address: String,
+    pub denom: String,
+    pub old: u64,
+    pub new: u64,
+}
+
+impl GenesisDiff {
+    /// Whether the app states are the same.
+    pub fn is_empty(&self) -> bool {
+        *self == GenesisDiff::default()
+    }
+}
+
+/// Compares two genesis app states.
+pub fn diff(old: &AppState, new: &AppState) -> Result<GenesisDiff> {
+    let validators = |app_state: &AppState| -> Result<_> {
+        app_state
+            .validators
+            .iter()
+            .map(|v| {
+                let label = format!("{} ({})", v.name, v.identity_key);
+                Ok((v.identity_key.to_string(), (label, to_json(v)?)))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()
+    };
+    let validator_rates = |app_state: &AppState| -> Result<_> {
+        app_state
+            .validator_rates
+            .iter()
+            .map(|r| {
+                let key = r.identity_key.to_string();
+                Ok((key.clone(), (key, to_json(r)?)))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()
+    };
+
+    Ok(GenesisDiff {
+        chain_params: field_changes(&to_json(&old.chain_params)?, &to_json(&new.chain_params)?),
+        validators: entry_changes(validators(old)?, validators(new)?),
+        allocations: allocation_changes(old, new),
+        base_rate_data: field_changes(
+            &to_json(&old.base_rate_data)?,
+            &to_json(&new.base_rate_data)?,
+        ),
+        validator_rates: entry_changes(validator_rates(old)?, validator_rates(new)?),
+    })
+}
+
+fn to_json(value: &impl Serialize) -> Result<Value> {
+    Ok(serde_json::to_value(value)?)
+}
+
+/// The fields of two JSON objects whose values differ. A value which is not an
+/// object, such as a missing `base_rate_data`, is compared as a whole.
+fn field_changes(old: &Value, new: &Value) -> Vec<FieldChange> {
+    let (old, new) = match (old, new) {
+        (Value::Object(old), Value::Object(new)) => (old, new),
+        _ if old == new => return Vec::new(),
+        _ => {
+            let present = |value: &Value| (!value.is_null()).then(|| value.to_string());
+            return vec![FieldChange {
+                field: String::new(),
+                old: present(old),
+                new: present(new),
+            }];
+        }
+    };
+
+    let mut fields = old.keys().chain(new.keys()).collect::<Vec<_>>();
+    fields.sort();
+    fields.dedup();
+    fields
+        .into_iter()
+        .filter(|field| old.get(*field) != new.get(*field))
+        .map(|field| FieldChange {
+            field: field.clone(),
+            old: old.get(field).map(Value::to_string),
+            new: new.get(field).map(Value::to_string),
+        })
+        .collect()
+}
+
+/// The entries added, removed, or changed between two maps of entries, each
+/// with a label and its JSON encoding.
+fn entry_changes(
+    mut old: BTreeMap<String, (String, Value)>,
+    new: BTreeMap<String, (String, Value)>,
+) -> Vec<EntryChange> {
+    let mut changes = Vec::new();
+    for (key, (label, new_value)) in new {
+        match old.remove(&key) {
+            None => changes.push(EntryChange::Added { label }),
+            Some((_, old_value)) => {
+                let fields = field_changes(&old_value, &new_value);
+                if !fields.is_empty() {
+                    changes.push(EntryChange::Changed { label, fields });
+                }
+            }
+        }
+    }
+    changes.extend(
+        old.into_values()
+            .map(|(label, _)| EntryChange::Removed { label }),
+    );
+    changes
+}
+
+fn allocation_changes(old: &AppState, new: &AppState) -> Vec<AllocationChange> {
+    let totals = |app_state: &AppState| {
+        let mut totals = BTreeMap::<(String, String), u64>::new();
+        for allocation in &app_state.allocations {
+            let total = totals
+                .entry((allocation.address.to_string(), allocation.denom.clone()))
+                .or_default();
+            *total = total.saturating_add(allocation.amount);
+        }
+        totals
+    };
+    let (old, new) = (totals(old), totals(new));
+
+    let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter_map(|key| {
+            let old = old.get(key).copied().unwrap_or_default();
+            let new = new.get(key).copied().unwrap_or_default();
+            (old != new).then(|| AllocationChange {
+                address: key.0.clone(),
+                denom: key.1.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = if self.field.is_empty() {
+            String::new()
+        } else {
+            format!("{}: ", self.field)
+        };
+        let absent = "(none)".to_string();
+        write!(
+            f,
+            "{}{} -> {}",
+            field,
+            self.old.as_ref().unwrap_or(&absent),
+            self.new.as_ref().unwrap_or(&absent)
+        )
+    }
+}
+
+impl fmt::Display for EntryChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryChange::Added { label } => write!(f, "+ {}", label),
+            EntryChange::Removed { label } => write!(f, "- {}", label),
+            EntryChange::Changed { label, fields } => {
+                write!(f, "~ {}", label)?;
+                for field in fields {
+                    write!(f, "\n    {}", field)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for AllocationChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.old, self.new) {
+            (0, new) => write!(f, "+ {} {} to {}", new, self.denom, self.address),
+            (old, 0) => write!(f, "- {} {} to {}", old, self.denom, self.address),
+            (old, new) => write!(f, "~ {} -> {} {} to {}", old, new, self.denom, self.address),
+        }
+    }
+}
+
+/// Prints the differences section by section, omitting sections without any.
+impl fmt::Display for GenesisDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn section<T: fmt::Display>(
+            f: &mut fmt::Formatter<'_>,
+            title: &str,
+            changes: &[T],
+        ) -> fmt::Result {
+            if changes.is_empty() {
+                return Ok(());
+            }
+            writeln!(f, "{}:", title)?;
+            for change in changes {
+                writeln!(f, "  {}", change)?;
+            }
+            Ok(())
+        }
+
+        section(f, "chain parameters", &self.chain_params)?;
+        section(f, "validators", &self.validators)?;
+        section(f, "allocations", &self.allocations)?;
+        section(f, "base rate data", &self.base_rate_data)?;
+        section(f, "validator rates", &self.validator_rates)
+    }
+}
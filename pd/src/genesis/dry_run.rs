@@ -0,0 +1,145 @@
+use anyhow::{anyhow, Context, Result};
+use tendermint::{abci::types::ValidatorUpdate, Genesis};
+
+use super::AppState;
+use crate::{App, Component, Storage};
+
+/// The outcome of executing a genesis file without starting a chain.
+#[derive(Debug)]
+pub struct DryRun {
+    pub chain_id: String,
+    /// The app hash `InitChain` would report to Tendermint.
+    pub app_hash: Vec<u8>,
+    /// The initial validator set `InitChain` would report to Tendermint.
+    pub validators: Vec<ValidatorUpdate>,
+}
+
+/// Parses a Tendermint genesis file, returning its chain ID and app state.
+///
+/// This checks that the chain ID of the app state matches that of the
+/// genesis file, but does not otherwise validate the app state.
+pub fn parse(genesis_json: &[u8]) -> Result<(String, AppState)> {
+    let genesis: Genesis<serde_json::Value> =
+        serde_json::from_slice(genesis_json).context("could not parse genesis file")?;
+    let app_state: AppState =
+        serde_json::from_value(genesis.app_state).context("could not parse app state")?;
+
+    let chain_id = genesis.chain_id.to_string();
+    if app_state.chain_params.chain_id != chain_id {
+        return Err(anyhow!(
+            "app state chain ID {:?} does not match genesis chain ID {:?}",
+            app_state.chain_params.chain_id,
+            chain_id
+        ));
+    }
+
+    Ok((chain_id, app_state))
+}
+
+/// Parses a Tendermint genesis file and runs the `InitChain` code path on its
+/// app state against an in-memory database, so that a malformed genesis is
+/// caught before any validator boots with it.
+pub async fn dry_run(genesis_json: &[u8]) -> Result<DryRun> {
+    let (chain_id, app_state) = parse(genesis_json)?;
+
+    let storage = Storage::in_memory().await?;
+    let mut app = App::new(storage.overlay().await?).await?;
+    app.init_chain(&app_state)
+        .await
+        .context("InitChain failed")?;
+    let (jmt_root, _) = app.commit(storage).await?;
+
+    let validators = app.tm_validator_updates().await?;
+    if validators.is_empty() {
+        return Err(anyhow!("genesis has no active validators"));
+    }
+
+    Ok(DryRun {
+        chain_id,
+        app_hash: jmt_root.0.to_vec(),
+        validators,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_chain::params::ChainParams;
+    use penumbra_stake::BaseRateData;
+
+    use super::*;
+    use crate::testing::{address, delegations, tendermint_genesis, validator};
+
+    const CHAIN_ID: &str = "dry-run-test";
+
+    /// A genesis app state with two delegated validators.
+    fn app_state() -> AppState {
+        let validators = vec![validator("a"), validator("b")];
+        AppState {
+            chain_params: ChainParams {
+                chain_id: CHAIN_ID.to_string(),
+                ..Default::default()
+            },
+            allocations: delegations(&validators, 1_000_000, address()),
+            validators,
+            ..Default::default()
+        }
+    }
+
+    fn genesis_json(app_state: AppState) -> Vec<u8> {
+        serde_json::to_vec(&tendermint_genesis(app_state)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reports_the_genesis_app_hash_and_validators() -> Result<()> {
+        let genesis = genesis_json(app_state());
+
+        let dry_run = super::dry_run(&genesis).await?;
+        assert_eq!(dry_run.chain_id, CHAIN_ID);
+        assert_eq!(dry_run.validators.len(), 2);
+        assert!(dry_run.validators.iter().all(|v| v.power.value() > 0));
+        // Every validator computes the same app hash from the same genesis.
+        assert_eq!(super::dry_run(&genesis).await?.app_hash, dry_run.app_hash);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_mismatched_chain_ids() {
+        let mut genesis = tendermint_genesis(app_state());
+        genesis.chain_id = "other-chain".parse().unwrap();
+
+        let error = parse(&serde_json::to_vec(&genesis).unwrap()).unwrap_err();
+        assert!(error.to_string().contains("does not match"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn rejects_genesis_without_validators() {
+        let genesis = genesis_json(AppState {
+            validators: Vec::new(),
+            allocations: Vec::new(),
+            ..app_state()
+        });
+
+        let error = super::dry_run(&genesis).await.unwrap_err();
+        assert!(
+            error.to_string().contains("no active validators"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_init_chain_failures() {
+        let genesis = genesis_json(AppState {
+            base_rate_data: Some(BaseRateData {
+                epoch_index: 0,
+                base_reward_rate: 0,
+                base_exchange_rate: 0,
+            }),
+            ..app_state()
+        });
+
+        let error = super::dry_run(&genesis).await.unwrap_err();
+        assert!(error.to_string().contains("InitChain failed"), "{}", error);
+    }
+}
@@ -0,0 +1,148 @@
+//! Request duration metrics for the query services.
+//!
+//! [`RequestDurationLayer`] records how long each query took to answer in
+//! `node_grpc_request_duration_seconds`, labeled by its gRPC method, e.g.
+//! `/penumbra.client.oblivious.ObliviousQuery/ChainParams`. For a streaming
+//! method, this is the time until the stream is returned, not until it ends.
+//!
+//! Requests for methods which are not served are not recorded, so that
+//! clients cannot grow the set of labels without bound. For the same reason,
+//! the layer should sit inside any layer rejecting requests before they are
+//! routed, like the [`RateLimitLayer`](crate::rate_limit::RateLimitLayer).
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::ready;
+use pin_project::pin_project;
+use tower::{Layer, Service};
+
+/// The `grpc-status` of a response to a request for a method which is not
+/// served.
+const UNIMPLEMENTED: &str = "12";
+
+/// Records the duration of each request to the services it wraps.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestDurationLayer;
+
+impl<S> Layer<S> for RequestDurationLayer {
+    type Service = RequestDuration<S>;
+
+    fn layer(&self, inner: S) -> RequestDuration<S> {
+        RequestDuration { inner }
+    }
+}
+
+/// A service whose requests are timed by a [`RequestDurationLayer`].
+#[derive(Clone, Debug)]
+pub struct RequestDuration<S> {
+    inner: S,
+}
+
+impl<S, B, C> Service<http::Request<B>> for RequestDuration<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<C>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        ResponseFuture {
+            method: request.uri().path().to_string(),
+            started: Instant::now(),
+            inner: self.inner.call(request),
+        }
+    }
+}
+
+/// The response future of a [`RequestDuration`] service.
+#[pin_project]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    method: String,
+    started: Instant,
+}
+
+impl<F, C, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<http::Response<C>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let response = ready!(this.inner.poll(cx))?;
+        let unimplemented = response
+            .headers()
+            .get("grpc-status")
+            .map_or(false, |status| status == UNIMPLEMENTED);
+        if !unimplemented {
+            metrics::histogram!(
+                "node_grpc_request_duration_seconds",
+                this.started.elapsed(),
+                "method" => std::mem::take(this.method)
+            );
+        }
+        Poll::Ready(Ok(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::pd_metrics::test_handle;
+
+    #[tokio::test]
+    async fn records_only_served_methods() -> anyhow::Result<()> {
+        let handle = test_handle();
+        let service = RequestDurationLayer.layer(tower::service_fn(|request: http::Request<()>| {
+            let status = match request.uri().path() {
+                "/penumbra.test.TestQuery/Served" => "0",
+                _ => UNIMPLEMENTED,
+            };
+            async move {
+                Ok::<_, Infallible>(
+                    http::Response::builder()
+                        .header("grpc-status", status)
+                        .body(())
+                        .unwrap(),
+                )
+            }
+        }));
+
+        for path in [
+            "/penumbra.test.TestQuery/Served",
+            "/penumbra.test.TestQuery/Served",
+            "/penumbra.test.TestQuery/MadeUp",
+        ] {
+            let request = http::Request::builder().uri(path).body(()).unwrap();
+            service.clone().oneshot(request).await?;
+        }
+
+        let rendered = handle.render();
+        assert!(
+            rendered.lines().any(|line| line
+                == "node_grpc_request_duration_seconds_count\
+                    {method=\"/penumbra.test.TestQuery/Served\"} 2"),
+            "{}",
+            rendered
+        );
+        assert!(!rendered.contains("MadeUp"), "{}", rendered);
+
+        Ok(())
+    }
+}
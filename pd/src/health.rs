@@ -0,0 +1,199 @@
+//! Liveness and readiness of the node, for load balancers and orchestrators.
+//!
+//! A node which is still catching up serves stale state, so traffic should
+//! only be routed to it once it has caught up with the chain. [`run`]
+//! periodically compares the height `pd` has committed against the latest
+//! height of the co-located Tendermint node, and publishes the result both as
+//! the status of the query services in the standard gRPC health service, and
+//! to [`serve`], which answers HTTP probes:
+//!
+//! - `GET /healthz` succeeds as long as `pd` is running.
+//! - `GET /readyz` succeeds only while `pd` is ready, and otherwise responds
+//!   `503 Service Unavailable` with the reason it is not.
+
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use penumbra_proto::client::{
+    oblivious::oblivious_query_server::ObliviousQueryServer,
+    specific::specific_query_server::SpecificQueryServer,
+};
+use tendermint::block;
+use tokio::sync::watch;
+use tonic::transport::NamedService;
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+use crate::{tendermint_health, Oblivious, Storage};
+
+/// How often to check whether the node is ready.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many blocks `pd` may be behind Tendermint and still be ready, since
+/// Tendermint reports a block as committed slightly before `pd` has.
+const MAX_LAG: u64 = 1;
+
+/// Whether the node should be sent traffic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Readiness {
+    Ready,
+    /// Not ready, for the given reason.
+    NotReady(String),
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Readiness::Ready)
+    }
+}
+
+/// Decides whether the node is ready.
+#[derive(Clone, Debug)]
+pub struct Checker {
+    block_heights: Option<watch::Receiver<block::Height>>,
+    tendermint_rpc: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Checker {
+    /// Compares the heights from `block_heights`, which is `None` when the
+    /// node is not following the chain, against the Tendermint RPC at
+    /// `tendermint_rpc`.
+    ///
+    /// A node which is not following the chain serves fixed state, and so is
+    /// always ready. Without a Tendermint RPC, there is nothing to compare
+    /// against, and the node is ready as soon as it is running.
+    pub fn new(
+        block_heights: Option<watch::Receiver<block::Height>>,
+        tendermint_rpc: Option<String>,
+    ) -> Self {
+        Self {
+            block_heights,
+            tendermint_rpc,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn check(&self) -> Readiness {
+        let (block_heights, rpc_url) = match (&self.block_heights, &self.tendermint_rpc) {
+            (Some(block_heights), Some(rpc_url)) => (block_heights, rpc_url),
+            _ => return Readiness::Ready,
+        };
+        let height = block_heights.borrow().value();
+
+        let (latest_height, catching_up) = match self.tendermint_status(rpc_url).await {
+            Ok(status) => status,
+            Err(e) => return Readiness::NotReady(format!("cannot reach tendermint: {}", e)),
+        };
+        if catching_up {
+            Readiness::NotReady(format!(
+                "tendermint is catching up, at height {}",
+                latest_height
+            ))
+        } else if height + MAX_LAG < latest_height {
+            Readiness::NotReady(format!(
+                "pd is at height {}, behind tendermint at height {}",
+                height, latest_height
+            ))
+        } else {
+            Readiness::Ready
+        }
+    }
+
+    /// Tendermint's latest block height, and whether it is catching up.
+    async fn tendermint_status(&self, rpc_url: &str) -> Result<(u64, bool)> {
+        let status = tendermint_health::get(&self.client, rpc_url, "status").await?;
+        let sync_info = &status["sync_info"];
+        let latest_height = tendermint_health::number(&sync_info["latest_block_height"])? as u64;
+        let catching_up = sync_info["catching_up"]
+            .as_bool()
+            .ok_or_else(|| anyhow!("could not parse catching_up"))?;
+        Ok((latest_height, catching_up))
+    }
+}
+
+/// Checks readiness every `interval`, publishing it on `readiness` and in
+/// the gRPC health service through `reporter`, until `drain` is raised, when
+/// the node reports that it is shutting down.
+pub async fn run(
+    checker: Checker,
+    interval: Duration,
+    mut reporter: HealthReporter,
+    readiness: watch::Sender<Readiness>,
+    mut drain: watch::Receiver<bool>,
+) {
+    let services = [
+        "",
+        <ObliviousQueryServer<Oblivious> as NamedService>::NAME,
+        <SpecificQueryServer<Storage> as NamedService>::NAME,
+    ];
+    loop {
+        let current = if *drain.borrow() {
+            Readiness::NotReady("shutting down".to_string())
+        } else {
+            checker.check().await
+        };
+
+        if current != *readiness.borrow() {
+            match &current {
+                Readiness::Ready => tracing::info!("node is ready"),
+                Readiness::NotReady(reason) => tracing::info!(%reason, "node is not ready"),
+            }
+        }
+        let status = if current.is_ready() {
+            ServingStatus::Serving
+        } else {
+            ServingStatus::NotServing
+        };
+        for service in services {
+            reporter.set_service_status(service, status).await;
+        }
+        let _ = readiness.send(current);
+
+        if *drain.borrow() {
+            return;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = drain.changed() => {}
+        }
+    }
+}
+
+/// Serves the liveness and readiness probes on `addr` until the server fails.
+pub async fn serve(addr: SocketAddr, readiness: watch::Receiver<Readiness>) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let readiness = readiness.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let readiness = readiness.borrow().clone();
+                async move { Ok::<_, Infallible>(respond(request, readiness)) }
+            }))
+        }
+    });
+
+    tracing::info!(?addr, "serving health probes");
+    hyper::Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+fn respond(request: Request<Body>, readiness: Readiness) -> Response<Body> {
+    let (status, body) = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/healthz") => (StatusCode::OK, "ok\n".to_string()),
+        (&Method::GET, "/readyz") => match readiness {
+            Readiness::Ready => (StatusCode::OK, "ready\n".to_string()),
+            Readiness::NotReady(reason) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("not ready: {}\n", reason),
+            ),
+        },
+        _ => (StatusCode::NOT_FOUND, "not found\n".to_string()),
+    };
+    Response::builder()
+        .status(status)
+        .body(Body::from(body))
+        .expect("response is valid")
+}
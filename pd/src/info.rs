@@ -4,18 +4,91 @@ use std::{
     task::{Context, Poll},
 };
 
+use anyhow::{anyhow, Context as _};
 use futures::FutureExt;
-use tendermint::abci::{self, response::Echo, InfoRequest, InfoResponse};
+use penumbra_proto::Message;
+use tendermint::{
+    abci::{self, response::Echo, InfoRequest, InfoResponse},
+    merkle::proof::{ProofOp, ProofOps},
+};
+use tonic_reflection::server::{ServerReflection, ServerReflectionServer};
 use tower_abci::BoxError;
 use tracing::Instrument;
 
 use crate::{RequestExt, Storage};
 
+mod deadline;
 mod oblivious;
 mod specific;
 
+pub use oblivious::Oblivious;
+
+/// The gRPC server reflection service, describing every service in
+/// [`penumbra_proto::FILE_DESCRIPTOR_SET`], so that clients of the single port
+/// serving both query services can discover the API with tools like `grpcurl`.
+pub fn reflection_service() -> anyhow::Result<ServerReflectionServer<impl ServerReflection>> {
+    tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(penumbra_proto::FILE_DESCRIPTOR_SET)
+        .build()
+        .context("could not build the gRPC reflection service")
+}
+
 const ABCI_INFO_VERSION: &str = env!("VERGEN_GIT_SEMVER");
 
+/// The ABCI query path for reading a raw key from the state.
+pub const KEY_QUERY_PATH: &str = "state/key";
+
+/// The ABCI query path for reading a key of one component's state, as
+/// `store/<component>/key`, where the query data is the key without the
+/// component's `<component>/` prefix. This is the form IBC relayers query,
+/// e.g. `store/ibc/key`.
+pub const STORE_QUERY_PATH: &str = "store/{component}/key";
+
+/// The ABCI query path for the app hash committed at a height.
+pub const APP_HASH_QUERY_PATH: &str = "app/hash";
+
+/// The ABCI query path for the version of `pd` answering the query.
+pub const APP_VERSION_QUERY_PATH: &str = "app/version";
+
+/// The type of the ABCI proof op carrying an ICS23 proof of the state.
+pub const ICS23_PROOF_OP: &str = "jmt:ics23";
+
+/// What an ABCI query asks for.
+enum Route {
+    /// The value of a state key, with a proof if requested.
+    Key(Vec<u8>),
+    AppHash,
+    AppVersion,
+}
+
+impl Route {
+    /// Parses a query path, with or without a leading `/`, and its data.
+    fn parse(path: &str, data: &[u8]) -> anyhow::Result<Self> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        match path {
+            KEY_QUERY_PATH => return Ok(Route::Key(data.to_vec())),
+            APP_HASH_QUERY_PATH => return Ok(Route::AppHash),
+            APP_VERSION_QUERY_PATH => return Ok(Route::AppVersion),
+            _ => {}
+        }
+        match path.split('/').collect::<Vec<_>>().as_slice() {
+            ["store", component, "key"] if !component.is_empty() => {
+                let mut key = format!("{}/", component).into_bytes();
+                key.extend_from_slice(data);
+                Ok(Route::Key(key))
+            }
+            _ => Err(anyhow!(
+                "unknown query path {:?}, expected one of {}, {}, {}, or {}",
+                path,
+                KEY_QUERY_PATH,
+                STORE_QUERY_PATH,
+                APP_HASH_QUERY_PATH,
+                APP_VERSION_QUERY_PATH
+            )),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Info {
     storage: Storage,
@@ -47,13 +120,70 @@ impl Info {
         })
     }
 
+    /// Handles ABCI queries, at the height given, or at the latest height.
+    ///
+    /// The supported paths are:
+    ///
+    /// - [`KEY_QUERY_PATH`], which reads the raw value of the state key given
+    ///   as the query data, and [`STORE_QUERY_PATH`], which does the same
+    ///   within one component's state. If a proof is requested, it is returned
+    ///   as a single [`ICS23_PROOF_OP`] op containing an encoded ICS23
+    ///   `CommitmentProof` of the full key against the app hash at the height,
+    ///   which can be verified with [`penumbra_proto::proofs`].
+    /// - [`APP_HASH_QUERY_PATH`], which returns that app hash, as recorded in
+    ///   the header of the following block.
+    /// - [`APP_VERSION_QUERY_PATH`], which returns the version of `pd`.
     async fn query(
         &self,
         query: abci::request::Query,
     ) -> Result<abci::response::Query, anyhow::Error> {
-        tracing::warn!(?query, "unhandled query");
-        // TODO: implement (#22)
-        Ok(Default::default())
+        let route = Route::parse(&query.path, &query.data)?;
+
+        let latest = self
+            .storage
+            .latest_version()
+            .await?
+            .ok_or_else(|| anyhow!("no state has been committed"))?;
+        let version = match query.height.value() {
+            0 => latest,
+            height if height > latest => {
+                return Err(anyhow!(
+                    "height {} has not been committed yet; the latest height is {}",
+                    height,
+                    latest
+                ))
+            }
+            height => height,
+        };
+
+        let (key, value, proof) = match route {
+            Route::Key(key) => {
+                let (value, proof) = self.storage.get_with_ics23_proof(&key, version).await?;
+                let proof = query.prove.then(|| ProofOps {
+                    ops: vec![ProofOp {
+                        field_type: ICS23_PROOF_OP.to_string(),
+                        key: key.clone(),
+                        data: proof.encode_to_vec(),
+                    }],
+                });
+                (key, value.unwrap_or_default(), proof)
+            }
+            Route::AppHash => {
+                let root = jmt::JellyfishMerkleTree::new(&self.storage)
+                    .get_root_hash(version)
+                    .await?;
+                (Vec::new(), root.0.to_vec(), None)
+            }
+            Route::AppVersion => (Vec::new(), ABCI_INFO_VERSION.as_bytes().to_vec(), None),
+        };
+
+        Ok(abci::response::Query {
+            key: key.into(),
+            value: value.into(),
+            proof,
+            height: version.try_into()?,
+            ..Default::default()
+        })
     }
 }
 
@@ -95,3 +225,63 @@ impl tower::Service<InfoRequest> for Info {
         .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use penumbra_proto::client::{
+        oblivious::ChainParamsRequest, specific::NextValidatorRateRequest,
+    };
+    use tonic::Code;
+
+    use super::*;
+    use crate::{genesis, testing::Node};
+
+    #[test]
+    fn reflection_describes_both_query_services() -> anyhow::Result<()> {
+        reflection_service()?;
+
+        let services: Vec<_> = penumbra_proto::compat::file_descriptor_set()
+            .file
+            .iter()
+            .flat_map(|file| {
+                file.service
+                    .iter()
+                    .map(move |service| format!("{}.{}", file.package(), service.name()))
+            })
+            .collect();
+        for service in [
+            "penumbra.client.oblivious.ObliviousQuery",
+            "penumbra.client.specific.SpecificQuery",
+        ] {
+            assert!(services.iter().any(|s| s == service), "{:?}", services);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn both_query_services_share_one_port() -> anyhow::Result<()> {
+        let node = Node::start(genesis::AppState::default()).await?;
+
+        node.oblivious_client()
+            .await?
+            .chain_params(ChainParamsRequest {
+                chain_id: String::new(),
+            })
+            .await?;
+        // The request reaches the specific query service, which rejects it,
+        // rather than the server not knowing the service.
+        let status = node
+            .specific_client()
+            .await?
+            .next_validator_rate(NextValidatorRateRequest {
+                chain_id: String::new(),
+                identity_key: None,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+//! Request deadlines for the query services.
+//!
+//! gRPC clients send their deadline in the `grpc-timeout` header. Once it
+//! passes, nobody is waiting for the response, so handlers stop reading
+//! storage and streams stop producing items rather than finishing work whose
+//! result would be thrown away.
+
+use std::{future::Future, time::Duration};
+
+use tokio::time::Instant;
+use tonic::Status;
+
+/// The instant after which the client no longer wants a response to
+/// `request`, if it set a deadline.
+pub(crate) fn from_request<T>(request: &tonic::Request<T>) -> Option<Instant> {
+    let timeout = request.metadata().get("grpc-timeout")?.to_str().ok()?;
+    parse_timeout(timeout).map(|timeout| Instant::now() + timeout)
+}
+
+/// Whether `deadline` has passed.
+pub(crate) fn passed(deadline: Option<Instant>) -> bool {
+    deadline.map_or(false, |deadline| Instant::now() >= deadline)
+}
+
+/// The status returned to requests whose deadline passed.
+pub(crate) fn exceeded() -> Status {
+    Status::deadline_exceeded("request deadline exceeded")
+}
+
+/// Runs `future` to completion, unless `deadline` passes first, in which case
+/// the future is dropped and the request fails.
+pub(crate) async fn within<T, F>(deadline: Option<Instant>, future: F) -> Result<T, Status>
+where
+    F: Future<Output = Result<T, Status>>,
+{
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future)
+            .await
+            .unwrap_or_else(|_| Err(exceeded())),
+        None => future.await,
+    }
+}
+
+/// Parses a `grpc-timeout` header value: a positive integer of at most eight
+/// digits, followed by a unit.
+fn parse_timeout(timeout: &str) -> Option<Duration> {
+    if timeout.len() < 2 || timeout.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = timeout.split_at(timeout.len() - 1);
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let value: u64 = digits.parse().ok()?;
+
+    Some(match unit {
+        "H" => Duration::from_secs(value * 60 * 60),
+        "M" => Duration::from_secs(value * 60),
+        "S" => Duration::from_secs(value),
+        "m" => Duration::from_millis(value),
+        "u" => Duration::from_micros(value),
+        "n" => Duration::from_nanos(value),
+        _ => return None,
+    })
+}
@@ -1,16 +1,27 @@
-use std::pin::Pin;
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use async_stream::try_stream;
-use futures::stream::{StreamExt, TryStreamExt};
+use futures::stream::StreamExt;
 use penumbra_proto::{
     chain::{ChainParams, CompactBlock, KnownAssets},
     client::oblivious::{
-        oblivious_query_server::ObliviousQuery, AssetListRequest, ChainParamsRequest,
-        CompactBlockRangeRequest, ValidatorInfoRequest,
+        oblivious_query_server::ObliviousQuery, AssetListRequest, ChainParamsRequest, ChainStats,
+        ChainStatsRequest, CompactBlockRangeInterrupted, CompactBlockRangeRequest,
+        ProtoDescriptorRequest, ProtoDescriptorResponse, SubscribeCompactBlocksRequest,
+        ValidatorInfoRequest, ValidatorSetAtHeightRequest,
     },
-    stake::ValidatorInfo,
-    Protobuf,
+    stake::{ValidatorInfo, ValidatorSet},
+    Message, Protobuf,
 };
+use tendermint::block;
+use tokio::{sync::watch, time::Instant};
 use tonic::Status;
 use tracing::instrument;
 
@@ -21,14 +32,119 @@ use tracing::instrument;
 // (stable) std types.
 // use tracing_futures::Instrument;
 
+use super::deadline;
 use crate::components::{app::View as _, shielded_pool::View as _, staking::View as _};
 use crate::Storage;
 
+/// The oblivious query service.
+///
+/// Compact block streams can be cut short, so that wallets reconnect rather
+/// than hold a stream open across node maintenance: each stream ends after the
+/// maximum stream duration, if there is one, and every stream ends once the
+/// drain signal is raised. Either way, the stream ends with an `UNAVAILABLE`
+/// status carrying the height to resume from.
+///
+/// Subscriptions to new compact blocks are woken by the heights of committed
+/// blocks, and are unavailable unless the service is given them.
+///
+/// Every request honors the client's deadline, and streams are produced only
+/// as fast as the client reads them, so a stream whose client has gone away
+/// stops reading storage as soon as it is dropped.
+#[derive(Clone, Debug)]
+pub struct Oblivious {
+    storage: Storage,
+    max_stream_duration: Option<Duration>,
+    drain: watch::Receiver<bool>,
+    block_heights: Option<watch::Receiver<block::Height>>,
+    open_streams: Arc<AtomicUsize>,
+}
+
+impl Oblivious {
+    /// Serves queries of `storage`, with no maximum stream duration and no
+    /// drain signal.
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            max_stream_duration: None,
+            drain: watch::channel(false).1,
+            block_heights: None,
+            open_streams: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Ends compact block streams which have been open for `duration`.
+    pub fn with_max_stream_duration(mut self, duration: Duration) -> Self {
+        self.max_stream_duration = Some(duration);
+        self
+    }
+
+    /// Ends all compact block streams once `drain` becomes `true`.
+    pub fn with_drain(mut self, drain: watch::Receiver<bool>) -> Self {
+        self.drain = drain;
+        self
+    }
+
+    /// Serves subscriptions to compact blocks, sending each block once its
+    /// height is reported by `block_heights`.
+    pub fn with_block_heights(mut self, block_heights: watch::Receiver<block::Height>) -> Self {
+        self.block_heights = Some(block_heights);
+        self
+    }
+
+    /// The number of streams which have been returned to clients and not yet
+    /// dropped.
+    pub fn open_streams(&self) -> usize {
+        self.open_streams.load(Ordering::SeqCst)
+    }
+
+    async fn overlay_tonic(&self) -> Result<crate::Overlay, Status> {
+        self.storage.overlay_tonic().await
+    }
+
+    fn stream_guard(&self) -> StreamGuard {
+        self.open_streams.fetch_add(1, Ordering::SeqCst);
+        metrics::increment_gauge!("node_open_query_streams", 1.0);
+        StreamGuard(self.open_streams.clone())
+    }
+}
+
+/// Counts a stream as open until it is dropped.
+struct StreamGuard(Arc<AtomicUsize>);
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+        metrics::decrement_gauge!("node_open_query_streams", 1.0);
+    }
+}
+
+/// The status ending a compact block stream which was interrupted before
+/// sending the block at `resume_height`.
+fn interrupted(resume_height: u64, reason: &str) -> Status {
+    let details = CompactBlockRangeInterrupted { resume_height };
+    Status::with_details(
+        tonic::Code::Unavailable,
+        format!("{}; resume from height {}", reason, resume_height),
+        details.encode_to_vec().into(),
+    )
+}
+
+/// Waits until `deadline`, or forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => futures::future::pending().await,
+    }
+}
+
 #[tonic::async_trait]
-impl ObliviousQuery for Storage {
+impl ObliviousQuery for Oblivious {
     type CompactBlockRangeStream =
         Pin<Box<dyn futures::Stream<Item = Result<CompactBlock, tonic::Status>> + Send>>;
 
+    type SubscribeCompactBlocksStream =
+        Pin<Box<dyn futures::Stream<Item = Result<CompactBlock, tonic::Status>> + Send>>;
+
     type ValidatorInfoStream =
         Pin<Box<dyn futures::Stream<Item = Result<ValidatorInfo, tonic::Status>> + Send>>;
 
@@ -37,15 +153,28 @@ impl ObliviousQuery for Storage {
         &self,
         request: tonic::Request<ChainParamsRequest>,
     ) -> Result<tonic::Response<ChainParams>, Status> {
-        let overlay = self.overlay_tonic().await?;
-        overlay.check_chain_id(&request.get_ref().chain_id).await?;
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
 
-        let chain_params = overlay
-            .get_chain_params()
-            .await
-            .map_err(|_| tonic::Status::unavailable("database error"))?;
+            let chain_params = overlay
+                .get_chain_params()
+                .await
+                .map_err(|_| tonic::Status::unavailable("database error"))?;
 
-        Ok(tonic::Response::new(chain_params.into()))
+            Ok(tonic::Response::new(chain_params.into()))
+        })
+        .await
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn proto_descriptor(
+        &self,
+        _request: tonic::Request<ProtoDescriptorRequest>,
+    ) -> Result<tonic::Response<ProtoDescriptorResponse>, Status> {
+        Ok(tonic::Response::new(ProtoDescriptorResponse {
+            file_descriptor_set: penumbra_proto::FILE_DESCRIPTOR_SET.to_vec(),
+        }))
     }
 
     #[instrument(skip(self, request))]
@@ -53,14 +182,59 @@ impl ObliviousQuery for Storage {
         &self,
         request: tonic::Request<AssetListRequest>,
     ) -> Result<tonic::Response<KnownAssets>, Status> {
-        let overlay = self.overlay_tonic().await?;
-        overlay.check_chain_id(&request.get_ref().chain_id).await?;
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let known_assets = overlay
+                .known_assets()
+                .await
+                .map_err(|_| tonic::Status::unavailable("database error"))?;
+            Ok(tonic::Response::new(known_assets.into()))
+        })
+        .await
+    }
+
+    #[instrument(skip(self, request))]
+    async fn validator_set_at_height(
+        &self,
+        request: tonic::Request<ValidatorSetAtHeightRequest>,
+    ) -> Result<tonic::Response<ValidatorSet>, Status> {
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let height = request.into_inner().height;
+            let validator_set = overlay
+                .validator_set_at_height(height)
+                .await
+                .map_err(|_| tonic::Status::unavailable("database error"))?
+                .ok_or_else(|| {
+                    tonic::Status::not_found(format!("no validator set for height {}", height))
+                })?;
 
-        let known_assets = overlay
-            .known_assets()
-            .await
-            .map_err(|_| tonic::Status::unavailable("database error"))?;
-        Ok(tonic::Response::new(known_assets.into()))
+            Ok(tonic::Response::new(validator_set.into()))
+        })
+        .await
+    }
+
+    #[instrument(skip(self, request))]
+    async fn chain_stats(
+        &self,
+        request: tonic::Request<ChainStatsRequest>,
+    ) -> Result<tonic::Response<ChainStats>, Status> {
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let stats = overlay
+                .chain_stats()
+                .await
+                .map_err(|_| tonic::Status::unavailable("database error"))?;
+
+            Ok(tonic::Response::new(stats.into()))
+        })
+        .await
     }
 
     #[instrument(skip(self, request), fields(show_inactive = request.get_ref().show_inactive))]
@@ -68,27 +242,38 @@ impl ObliviousQuery for Storage {
         &self,
         request: tonic::Request<ValidatorInfoRequest>,
     ) -> Result<tonic::Response<Self::ValidatorInfoStream>, Status> {
-        let overlay = self.overlay_tonic().await?;
-        overlay.check_chain_id(&request.get_ref().chain_id).await?;
+        let deadline = deadline::from_request(&request);
+        let (overlay, validators) = deadline::within(deadline, async {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
 
-        let validators = overlay
-            .validator_list()
-            .await
-            .map_err(|_| tonic::Status::unavailable("database error"))?;
+            let validators = overlay
+                .validator_list()
+                .await
+                .map_err(|_| tonic::Status::unavailable("database error"))?;
+            Ok((overlay, validators))
+        })
+        .await?;
 
+        let guard = self.stream_guard();
         let _show_inactive = request.get_ref().show_inactive;
         let s = try_stream! {
+            let _guard = guard;
             for validator in validators {
-                let info = overlay.validator_info(&validator)
-                    .await?
-                    .expect("known validator must be present");
+                let info = deadline::within(deadline, async {
+                    overlay.validator_info(&validator)
+                        .await
+                        .map_err(|_| tonic::Status::unavailable("database error"))
+                })
+                .await?
+                .expect("known validator must be present");
                 // TODO: filter by show_inactive
                 yield info.to_proto();
             }
         };
 
         Ok(tonic::Response::new(
-            s.map_err(|_: anyhow::Error| tonic::Status::unavailable("database error"))
+            s
                 // TODO: how do we instrument a Stream
                 //.instrument(Span::current())
                 .boxed(),
@@ -106,8 +291,18 @@ impl ObliviousQuery for Storage {
         &self,
         request: tonic::Request<CompactBlockRangeRequest>,
     ) -> Result<tonic::Response<Self::CompactBlockRangeStream>, Status> {
-        let overlay = self.overlay_tonic().await?;
-        overlay.check_chain_id(&request.get_ref().chain_id).await?;
+        let request_deadline = deadline::from_request(&request);
+        let (overlay, current_height) = deadline::within(request_deadline, async {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let current_height = overlay
+                .get_block_height()
+                .await
+                .map_err(|_| tonic::Status::unavailable("database error"))?;
+            Ok((overlay, current_height))
+        })
+        .await?;
 
         let CompactBlockRangeRequest {
             start_height,
@@ -115,11 +310,6 @@ impl ObliviousQuery for Storage {
             ..
         } = request.into_inner();
 
-        let current_height = overlay
-            .get_block_height()
-            .await
-            .map_err(|_| tonic::Status::unavailable("database error"))?;
-
         // Treat end_height = 0 as end_height = current_height so that if the
         // end_height is unspecified in the proto, it will be treated as a
         // request to sync up to the current height.
@@ -129,7 +319,17 @@ impl ObliviousQuery for Storage {
             std::cmp::min(end_height, current_height)
         };
 
+        let stream_deadline = self
+            .max_stream_duration
+            .map(|duration| Instant::now() + duration);
+        let drain = self.drain.clone();
+        let guard = self.stream_guard();
+
+        // The stream is only polled when the client is ready for another
+        // block, and is dropped when the client goes away, so blocks are never
+        // read into a dead connection.
         let block_range = try_stream! {
+            let _guard = guard;
             // It's useful to record the end height since we adjusted it,
             // but the start height is already recorded in the span.
             tracing::info!(
@@ -138,19 +338,286 @@ impl ObliviousQuery for Storage {
                 "starting compact_block_range response"
             );
             for height in start_height..end_height {
-                let block = overlay.compact_block(height)
-                    .await?
-                    .expect("compact block for in-range height must be present");
+                if *drain.borrow() {
+                    tracing::info!(height, "interrupting compact_block_range to drain");
+                    Err(interrupted(height, "node is draining connections"))?;
+                }
+                if deadline::passed(stream_deadline) {
+                    tracing::info!(height, "compact_block_range reached maximum duration");
+                    Err(interrupted(height, "stream reached its maximum duration"))?;
+                }
+                if deadline::passed(request_deadline) {
+                    tracing::debug!(height, "compact_block_range deadline exceeded");
+                    Err(deadline::exceeded())?;
+                }
+                let block = deadline::within(request_deadline, async {
+                    overlay.compact_block(height)
+                        .await
+                        .map_err(|_| tonic::Status::unavailable("database error"))
+                })
+                .await?
+                .expect("compact block for in-range height must be present");
                 yield block.to_proto();
             }
         };
 
         Ok(tonic::Response::new(
             block_range
-                .map_err(|_: anyhow::Error| tonic::Status::unavailable("database error"))
                 // TODO: how to instrument a Stream?
                 //.instrument(Span::current())
                 .boxed(),
         ))
     }
+
+    #[instrument(skip(self, request), fields(start_height = request.get_ref().start_height))]
+    async fn subscribe_compact_blocks(
+        &self,
+        request: tonic::Request<SubscribeCompactBlocksRequest>,
+    ) -> Result<tonic::Response<Self::SubscribeCompactBlocksStream>, Status> {
+        let mut block_heights = self
+            .block_heights
+            .clone()
+            .ok_or_else(|| tonic::Status::unavailable("node is not following the chain"))?;
+        let request_deadline = deadline::from_request(&request);
+        deadline::within(request_deadline, async {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await
+        })
+        .await?;
+
+        let mut next_height = match request.into_inner().start_height {
+            0 => block_heights.borrow().value() + 1,
+            start_height => start_height,
+        };
+
+        let storage = self.storage.clone();
+        let stream_deadline = self
+            .max_stream_duration
+            .map(|duration| Instant::now() + duration);
+        let mut drain = self.drain.clone();
+        let guard = self.stream_guard();
+
+        let blocks = try_stream! {
+            let _guard = guard;
+            tracing::info!(next_height, "starting subscribe_compact_blocks response");
+            loop {
+                if *drain.borrow() {
+                    tracing::info!(next_height, "interrupting subscribe_compact_blocks to drain");
+                    Err(interrupted(next_height, "node is draining connections"))?;
+                }
+                if deadline::passed(stream_deadline) {
+                    tracing::info!(next_height, "subscription reached maximum duration");
+                    Err(interrupted(next_height, "stream reached its maximum duration"))?;
+                }
+                if deadline::passed(request_deadline) {
+                    tracing::debug!(next_height, "subscribe_compact_blocks deadline exceeded");
+                    Err(deadline::exceeded())?;
+                }
+
+                // Send the blocks committed since the last one sent, one per
+                // poll, before waiting for the next commit.
+                if next_height <= block_heights.borrow().value() {
+                    let block = deadline::within(request_deadline, async {
+                        storage
+                            .overlay_tonic()
+                            .await?
+                            .compact_block(next_height)
+                            .await
+                            .map_err(|_| tonic::Status::unavailable("database error"))
+                    })
+                    .await?
+                    .ok_or_else(|| {
+                        tonic::Status::internal(format!(
+                            "missing compact block for committed height {}",
+                            next_height
+                        ))
+                    })?;
+                    yield block.to_proto();
+                    next_height += 1;
+                    continue;
+                }
+
+                // Wait for the next commit, or for a reason to end the stream,
+                // which is checked at the top of the loop. A drain signal
+                // which can never be raised is not waited on.
+                let stopped = tokio::select! {
+                    changed = block_heights.changed() => changed.is_err(),
+                    Ok(()) = drain.changed() => false,
+                    _ = sleep_until(stream_deadline) => false,
+                    _ = sleep_until(request_deadline) => false,
+                };
+                if stopped {
+                    tracing::info!(next_height, "interrupting subscription on shutdown");
+                    Err(interrupted(next_height, "node stopped following the chain"))?;
+                }
+            }
+        };
+
+        Ok(tonic::Response::new(blocks.boxed()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::{Code, Request};
+
+    use super::*;
+    use crate::{genesis, testing::Node};
+
+    /// Starts a node with `blocks` empty blocks after genesis.
+    async fn node(blocks: u64) -> anyhow::Result<Node> {
+        let node = Node::start(genesis::AppState::default()).await?;
+        node.append_empty_blocks(blocks).await?;
+        Ok(node)
+    }
+
+    async fn range(
+        oblivious: &Oblivious,
+        start_height: u64,
+        end_height: u64,
+    ) -> Result<<Oblivious as ObliviousQuery>::CompactBlockRangeStream, Status> {
+        let response = oblivious
+            .compact_block_range(Request::new(CompactBlockRangeRequest {
+                chain_id: String::new(),
+                start_height,
+                end_height,
+            }))
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// The height a stream ended by `status` should be resumed from.
+    fn resume_height(status: &Status) -> u64 {
+        assert_eq!(status.code(), Code::Unavailable, "{:?}", status);
+        CompactBlockRangeInterrupted::decode(status.details())
+            .unwrap()
+            .resume_height
+    }
+
+    #[tokio::test]
+    async fn streams_the_requested_range() -> anyhow::Result<()> {
+        let node = node(5).await?;
+        let oblivious = Oblivious::new(node.storage().clone());
+
+        let heights = |stream: <Oblivious as ObliviousQuery>::CompactBlockRangeStream| async move {
+            stream
+                .map(|block| block.unwrap().height)
+                .collect::<Vec<_>>()
+                .await
+        };
+        assert_eq!(heights(range(&oblivious, 1, 3).await?).await, vec![1, 2]);
+        // An end height of 0, or past the current height, means the current
+        // height.
+        assert_eq!(heights(range(&oblivious, 2, 0).await?).await, vec![2, 3, 4]);
+        assert_eq!(
+            heights(range(&oblivious, 2, 99).await?).await,
+            vec![2, 3, 4]
+        );
+
+        // Streams are only counted as open until they are dropped.
+        assert_eq!(oblivious.open_streams(), 0);
+        let stream = range(&oblivious, 0, 0).await?;
+        assert_eq!(oblivious.open_streams(), 1);
+        drop(stream);
+        assert_eq!(oblivious.open_streams(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn draining_interrupts_streams_with_a_resume_height() -> anyhow::Result<()> {
+        let node = node(5).await?;
+        let (drain_tx, drain_rx) = watch::channel(false);
+        let oblivious = Oblivious::new(node.storage().clone()).with_drain(drain_rx);
+
+        let mut stream = range(&oblivious, 1, 0).await?;
+        assert_eq!(stream.next().await.unwrap()?.height, 1);
+        assert_eq!(stream.next().await.unwrap()?.height, 2);
+        drain_tx.send(true)?;
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(resume_height(&status), 3);
+        assert!(stream.next().await.is_none());
+
+        // Streams opened while draining end before their first block.
+        let mut stream = range(&oblivious, 4, 0).await?;
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(resume_height(&status), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streams_end_after_their_maximum_duration() -> anyhow::Result<()> {
+        let node = node(5).await?;
+
+        // A stream which has been open for its maximum duration ends before
+        // its next block.
+        let oblivious =
+            Oblivious::new(node.storage().clone()).with_max_stream_duration(Duration::ZERO);
+        let mut stream = range(&oblivious, 1, 0).await?;
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(resume_height(&status), 1);
+
+        // Streams finishing within it are unaffected.
+        let oblivious = Oblivious::new(node.storage().clone())
+            .with_max_stream_duration(Duration::from_secs(600));
+        let blocks: Vec<_> = range(&oblivious, 1, 0).await?.collect().await;
+        assert_eq!(blocks.len(), 4);
+        assert!(blocks.iter().all(Result::is_ok));
+
+        Ok(())
+    }
+
+    async fn subscribe(
+        oblivious: &Oblivious,
+        start_height: u64,
+    ) -> Result<<Oblivious as ObliviousQuery>::SubscribeCompactBlocksStream, Status> {
+        let response = oblivious
+            .subscribe_compact_blocks(Request::new(SubscribeCompactBlocksRequest {
+                chain_id: String::new(),
+                start_height,
+            }))
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    #[tokio::test]
+    async fn subscriptions_catch_up_then_follow_committed_blocks() -> anyhow::Result<()> {
+        let node = node(5).await?;
+        let (heights_tx, heights_rx) = watch::channel(block::Height::from(5u32));
+        let oblivious = Oblivious::new(node.storage().clone()).with_block_heights(heights_rx);
+
+        let mut from_three = subscribe(&oblivious, 3).await?;
+        let mut from_next = subscribe(&oblivious, 0).await?;
+        for height in 3..=5 {
+            assert_eq!(from_three.next().await.unwrap()?.height, height);
+        }
+
+        node.append_empty_blocks(2).await?;
+        heights_tx.send(block::Height::from(7u32))?;
+        for height in 6..=7 {
+            assert_eq!(from_three.next().await.unwrap()?.height, height);
+            assert_eq!(from_next.next().await.unwrap()?.height, height);
+        }
+
+        // Once the node stops following the chain, subscriptions end with the
+        // height to resume from.
+        drop(heights_tx);
+        let status = from_three.next().await.unwrap().unwrap_err();
+        assert_eq!(resume_height(&status), 8);
+        assert!(from_three.next().await.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn subscriptions_need_committed_block_heights() -> anyhow::Result<()> {
+        let node = node(1).await?;
+        let oblivious = Oblivious::new(node.storage().clone());
+
+        let status = subscribe(&oblivious, 0).await.err().unwrap();
+        assert_eq!(status.code(), Code::Unavailable);
+
+        Ok(())
+    }
 }
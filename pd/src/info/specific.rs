@@ -1,8 +1,15 @@
+use penumbra_chain::FaucetDisbursements;
+use penumbra_crypto::Address;
 use penumbra_proto::{
     self as proto,
-    chain::NoteSource,
-    client::specific::{specific_query_server::SpecificQuery, ValidatorStatusRequest},
-    crypto::NoteCommitment,
+    chain::{NoteSource, TxResult},
+    client::specific::{
+        specific_query_server::SpecificQuery, ChainParamsAtHeightRequest, CommissionPayoutsRequest,
+        ExchangeRateChangeRequest, ExchangeRateChangeResponse, FaucetDisbursementsRequest,
+        KeyValueRequest, KeyValueResponse, NctAnchorRequest, NextValidatorRateRequest,
+        TransactionByHashRequest, TransactionByNoteRequest, ValidatorStatusRequest,
+    },
+    Message,
 };
 
 use tonic::Status;
@@ -14,29 +21,42 @@ use tracing::instrument;
 // (stable) std types.
 //use tracing_futures::Instrument;
 
-use crate::components::{app::View as _, shielded_pool::View as _, staking::View as _};
+use super::deadline;
+use crate::components::{
+    app::View as _, faucet::View as _, shielded_pool::View as _, staking::View as _,
+};
 use crate::Storage;
 
+/// The longest range of heights a `FaucetDisbursements` request may cover.
+const MAX_FAUCET_QUERY_BLOCKS: u64 = 10_000;
+
 #[tonic::async_trait]
 impl SpecificQuery for Storage {
     #[instrument(skip(self, request))]
     async fn transaction_by_note(
         &self,
-        request: tonic::Request<NoteCommitment>,
+        request: tonic::Request<TransactionByNoteRequest>,
     ) -> Result<tonic::Response<NoteSource>, Status> {
-        let overlay = self.overlay_tonic().await?;
-        let cm = request
-            .into_inner()
-            .try_into()
-            .map_err(|_| Status::invalid_argument("invalid commitment"))?;
-        let source = overlay
-            .note_source(&cm)
-            .await
-            .map_err(|_| Status::unavailable("database error"))?
-            .ok_or_else(|| Status::not_found("note commitment not found"))?;
-        tracing::debug!(?cm, ?source);
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
 
-        Ok(tonic::Response::new(source.into()))
+            let cm = request
+                .into_inner()
+                .note_commitment
+                .ok_or_else(|| Status::invalid_argument("missing commitment"))?
+                .try_into()
+                .map_err(|_| Status::invalid_argument("invalid commitment"))?;
+            let source = overlay
+                .note_source(&cm)
+                .await
+                .map_err(|_| Status::unavailable("database error"))?
+                .ok_or_else(|| Status::not_found("note commitment not found"))?;
+            tracing::debug!(?cm, ?source);
+
+            Ok(tonic::Response::new(source.into()))
+        })
+        .await
     }
 
     #[instrument(skip(self, request))]
@@ -44,42 +64,462 @@ impl SpecificQuery for Storage {
         &self,
         request: tonic::Request<ValidatorStatusRequest>,
     ) -> Result<tonic::Response<proto::stake::ValidatorStatus>, Status> {
-        let overlay = self.overlay_tonic().await?;
-        overlay.check_chain_id(&request.get_ref().chain_id).await?;
-
-        let id = request
-            .into_inner()
-            .identity_key
-            .ok_or_else(|| Status::invalid_argument("missing identity key"))?
-            .try_into()
-            .map_err(|_| Status::invalid_argument("invalid identity key"))?;
-
-        let status = overlay
-            .validator_status(&id)
-            .await
-            .map_err(|_| Status::unavailable("database error"))?
-            .ok_or_else(|| Status::not_found("validator not found"))?;
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let id = request
+                .into_inner()
+                .identity_key
+                .ok_or_else(|| Status::invalid_argument("missing identity key"))?
+                .try_into()
+                .map_err(|_| Status::invalid_argument("invalid identity key"))?;
 
-        Ok(tonic::Response::new(status.into()))
+            let status = overlay
+                .validator_status(&id)
+                .await
+                .map_err(|_| Status::unavailable("database error"))?
+                .ok_or_else(|| Status::not_found("validator not found"))?;
+
+            Ok(tonic::Response::new(status.into()))
+        })
+        .await
     }
 
     #[instrument(skip(self, request))]
     async fn next_validator_rate(
         &self,
-        request: tonic::Request<proto::stake::IdentityKey>,
+        request: tonic::Request<NextValidatorRateRequest>,
     ) -> Result<tonic::Response<proto::stake::RateData>, Status> {
-        let overlay = self.overlay_tonic().await?;
-        let identity_key = request
-            .into_inner()
-            .try_into()
-            .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
-
-        let rate_data = overlay
-            .next_validator_rate(&identity_key)
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let identity_key = request
+                .into_inner()
+                .identity_key
+                .ok_or_else(|| tonic::Status::invalid_argument("missing identity key"))?
+                .try_into()
+                .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
+
+            let rate_data = overlay
+                .next_validator_rate(&identity_key)
+                .await
+                .map_err(|e| tonic::Status::internal(e.to_string()))?
+                .unwrap();
+
+            Ok(tonic::Response::new(rate_data.into()))
+        })
+        .await
+    }
+
+    #[instrument(skip(self, request))]
+    async fn nct_anchor(
+        &self,
+        request: tonic::Request<NctAnchorRequest>,
+    ) -> Result<tonic::Response<proto::crypto::MerkleRoot>, Status> {
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let height = request.into_inner().height;
+            let anchor = overlay
+                .nct_anchor(height)
+                .await
+                .map_err(|_| Status::unavailable("database error"))?
+                .ok_or_else(|| Status::not_found(format!("no anchor for height {}", height)))?;
+
+            Ok(tonic::Response::new(anchor.into()))
+        })
+        .await
+    }
+
+    #[instrument(skip(self, request))]
+    async fn commission_payouts(
+        &self,
+        request: tonic::Request<CommissionPayoutsRequest>,
+    ) -> Result<tonic::Response<proto::stake::CommissionPayouts>, Status> {
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let CommissionPayoutsRequest {
+                identity_key,
+                epoch_index,
+                ..
+            } = request.into_inner();
+            let identity_key = identity_key
+                .ok_or_else(|| Status::invalid_argument("missing identity key"))?
+                .try_into()
+                .map_err(|_| Status::invalid_argument("invalid identity key"))?;
+
+            let payouts = overlay
+                .commission_payouts(&identity_key, epoch_index)
+                .await
+                .map_err(|_| Status::unavailable("database error"))?
+                .ok_or_else(|| {
+                    Status::not_found("no commission payouts for that validator and epoch")
+                })?;
+
+            Ok(tonic::Response::new(payouts.into()))
+        })
+        .await
+    }
+    #[instrument(skip(self, request))]
+    async fn key_value(
+        &self,
+        request: tonic::Request<KeyValueRequest>,
+    ) -> Result<tonic::Response<KeyValueResponse>, Status> {
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let KeyValueRequest { key, proof, .. } = request.into_inner();
+            let height = self
+                .latest_version()
+                .await
+                .map_err(|_| Status::unavailable("database error"))?
+                .ok_or_else(|| Status::unavailable("no state has been committed"))?;
+            let (value, commitment_proof) = self
+                .get_with_ics23_proof(&key, height)
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?;
+
+            Ok(tonic::Response::new(KeyValueResponse {
+                value: value.unwrap_or_default(),
+                proof: if proof {
+                    commitment_proof.encode_to_vec()
+                } else {
+                    Vec::new()
+                },
+                height,
+            }))
+        })
+        .await
+    }
+    #[instrument(skip(self, request))]
+    async fn transaction_by_hash(
+        &self,
+        request: tonic::Request<TransactionByHashRequest>,
+    ) -> Result<tonic::Response<TxResult>, Status> {
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let tx_hash: [u8; 32] = request
+                .into_inner()
+                .tx_hash
+                .try_into()
+                .map_err(|_| Status::invalid_argument("transaction hash must be 32 bytes"))?;
+            let result = self
+                .tx_result(tx_hash)
+                .await
+                .map_err(|_| Status::unavailable("database error"))?
+                .ok_or_else(|| {
+                    Status::not_found(
+                        "no result for that transaction; it may not have been included yet, \
+                         or this node may not persist transaction results",
+                    )
+                })?;
+
+            Ok(tonic::Response::new(result))
+        })
+        .await
+    }
+    #[instrument(skip(self, request))]
+    async fn exchange_rate_change(
+        &self,
+        request: tonic::Request<ExchangeRateChangeRequest>,
+    ) -> Result<tonic::Response<ExchangeRateChangeResponse>, Status> {
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let ExchangeRateChangeRequest {
+                identity_key,
+                start_epoch,
+                end_epoch,
+                ..
+            } = request.into_inner();
+            let identity_key = identity_key
+                .ok_or_else(|| Status::invalid_argument("missing identity key"))?
+                .try_into()
+                .map_err(|_| Status::invalid_argument("invalid identity key"))?;
+
+            let mut rates = [0; 2];
+            for (rate, epoch_index) in rates.iter_mut().zip([start_epoch, end_epoch]) {
+                *rate = overlay
+                    .exchange_rate(&identity_key, epoch_index)
+                    .await
+                    .map_err(|_| Status::unavailable("database error"))?
+                    .ok_or_else(|| {
+                        Status::not_found(format!(
+                            "no exchange rate for that validator in epoch {}",
+                            epoch_index
+                        ))
+                    })?;
+            }
+
+            Ok(tonic::Response::new(ExchangeRateChangeResponse {
+                start_exchange_rate: rates[0],
+                end_exchange_rate: rates[1],
+            }))
+        })
+        .await
+    }
+
+    #[instrument(skip(self, request))]
+    async fn chain_params_at_height(
+        &self,
+        request: tonic::Request<ChainParamsAtHeightRequest>,
+    ) -> Result<tonic::Response<proto::chain::ChainParams>, Status> {
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let height = request.into_inner().height;
+            let params = overlay
+                .chain_params_at(height)
+                .await
+                .map_err(|_| Status::unavailable("database error"))?
+                .ok_or_else(|| {
+                    Status::not_found(format!("no chain parameters for height {}", height))
+                })?;
+
+            Ok(tonic::Response::new(params.into()))
+        })
+        .await
+    }
+
+    #[instrument(skip(self, request))]
+    async fn faucet_disbursements(
+        &self,
+        request: tonic::Request<FaucetDisbursementsRequest>,
+    ) -> Result<tonic::Response<proto::chain::FaucetDisbursements>, Status> {
+        deadline::within(deadline::from_request(&request), async move {
+            let overlay = self.overlay_tonic().await?;
+            overlay.check_chain_id(&request.get_ref().chain_id).await?;
+
+            let FaucetDisbursementsRequest {
+                start_height,
+                end_height,
+                address,
+                ..
+            } = request.into_inner();
+            if end_height < start_height {
+                return Err(Status::invalid_argument(
+                    "end height is before start height",
+                ));
+            }
+            if end_height - start_height >= MAX_FAUCET_QUERY_BLOCKS {
+                return Err(Status::invalid_argument(format!(
+                    "at most {} blocks may be queried at once",
+                    MAX_FAUCET_QUERY_BLOCKS
+                )));
+            }
+            let address: Option<Address> = address
+                .map(TryInto::try_into)
+                .transpose()
+                .map_err(|_| Status::invalid_argument("invalid address"))?;
+
+            let mut disbursements = FaucetDisbursements::default();
+            for height in start_height..=end_height {
+                let at_height = overlay
+                    .faucet_disbursements(height)
+                    .await
+                    .map_err(|_| Status::unavailable("database error"))?;
+                disbursements
+                    .0
+                    .extend(at_height.0.into_iter().filter(|disbursement| {
+                        address.map_or(true, |address| disbursement.address == address)
+                    }));
+            }
+
+            Ok(tonic::Response::new(disbursements.into()))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_chain::params::ChainParams;
+    use penumbra_proto::chain::ChainIdMismatch;
+    use tonic::{Code, Request};
+
+    use super::*;
+    use crate::{genesis, testing::Node};
+
+    const CHAIN_ID: &str = "specific-test";
+
+    async fn node() -> anyhow::Result<Node> {
+        Node::start(genesis::AppState {
+            chain_params: ChainParams {
+                chain_id: CHAIN_ID.to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await
+    }
+
+    fn next_validator_rate(chain_id: &str) -> Request<NextValidatorRateRequest> {
+        Request::new(NextValidatorRateRequest {
+            chain_id: chain_id.to_string(),
+            identity_key: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_for_another_chain() -> anyhow::Result<()> {
+        let node = node().await?;
+
+        let status = node
+            .storage()
+            .next_validator_rate(next_validator_rate("other-chain"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(
+            ChainIdMismatch::decode(status.details())?,
+            ChainIdMismatch {
+                server_chain_id: CHAIN_ID.to_string(),
+                requested_chain_id: "other-chain".to_string(),
+            }
+        );
+
+        let status = node
+            .storage()
+            .transaction_by_note(Request::new(TransactionByNoteRequest {
+                chain_id: "other-chain".to_string(),
+                note_commitment: None,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn accepts_requests_for_this_or_any_chain() -> anyhow::Result<()> {
+        let node = node().await?;
+
+        // Both requests get past the chain ID check, and fail on the missing
+        // identity key instead.
+        for chain_id in [CHAIN_ID, ""] {
+            let status = node
+                .storage()
+                .next_validator_rate(next_validator_rate(chain_id))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument, "{:?}", chain_id);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serves_recorded_transaction_results() -> anyhow::Result<()> {
+        let node = node().await?;
+        let result = TxResult {
+            height: 1,
+            tx: vec![1, 2, 3],
+            ..Default::default()
+        };
+        node.storage()
+            .put_tx_results(vec![([1; 32], result.clone())])
+            .await?;
+
+        let request = |tx_hash: Vec<u8>| {
+            Request::new(TransactionByHashRequest {
+                chain_id: CHAIN_ID.to_string(),
+                tx_hash,
+            })
+        };
+        let response = node
+            .storage()
+            .transaction_by_hash(request(vec![1; 32]))
+            .await?;
+        assert_eq!(response.into_inner(), result);
+
+        let status = node
+            .storage()
+            .transaction_by_hash(request(vec![2; 32]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        let status = node
+            .storage()
+            .transaction_by_hash(request(vec![1; 31]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serves_exchange_rate_changes() -> anyhow::Result<()> {
+        let node = node().await?;
+        let identity_key = crate::testing::validator("a").identity_key;
+        let overlay = node.storage().overlay().await?;
+        overlay
+            .record_exchange_rate(&identity_key, 3, 1_0000_0000)
+            .await?;
+        overlay
+            .record_exchange_rate(&identity_key, 300, 1_5000_0000)
+            .await?;
+        overlay.lock().await.commit(node.storage().clone()).await?;
+
+        let request = |start_epoch, end_epoch| {
+            Request::new(ExchangeRateChangeRequest {
+                chain_id: CHAIN_ID.to_string(),
+                identity_key: Some(identity_key.clone().into()),
+                start_epoch,
+                end_epoch,
+            })
+        };
+        let response = node.storage().exchange_rate_change(request(3, 300)).await?;
+        assert_eq!(
+            response.into_inner(),
+            ExchangeRateChangeResponse {
+                start_exchange_rate: 1_0000_0000,
+                end_exchange_rate: 1_5000_0000,
+            }
+        );
+
+        let status = node
+            .storage()
+            .exchange_rate_change(request(3, 4))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serves_chain_params_by_height() -> anyhow::Result<()> {
+        let node = node().await?;
+        let request = |height| {
+            Request::new(ChainParamsAtHeightRequest {
+                chain_id: CHAIN_ID.to_string(),
+                height,
+            })
+        };
+
+        let params = node.storage().chain_params_at_height(request(0)).await?;
+        assert_eq!(params.into_inner().chain_id, CHAIN_ID);
+
+        let status = node
+            .storage()
+            .chain_params_at_height(request(1))
             .await
-            .map_err(|e| tonic::Status::internal(e.to_string()))?
-            .unwrap();
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
 
-        Ok(tonic::Response::new(rate_data.into()))
+        Ok(())
     }
 }
@@ -0,0 +1,168 @@
+//! Derivation of all of a validator node's keys from a single seed phrase.
+//!
+//! This lets operators back up one mnemonic instead of every key file written
+//! by `pd generate-testnet`. The derivation is:
+//!
+//! 1. The seed phrase and an index are stretched into a [`SpendSeed`] exactly
+//!    as `pcli` does it, so the identity key belongs to the wallet account with
+//!    the same seed phrase and index.
+//! 2. The validator's identity (spend authorization) key is derived from the
+//!    [`SpendSeed`] as for any other [`SpendKey`].
+//! 3. The Tendermint consensus and node (P2P) Ed25519 keys are derived from the
+//!    [`SpendSeed`] with BLAKE2b-256, personalized with
+//!    [`CONSENSUS_KEY_PERSONALIZATION`] and [`NODE_KEY_PERSONALIZATION`]
+//!    respectively.
+//!
+//! Changing any of these steps changes the keys restored from existing backups,
+//! so they must never change.
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use penumbra_crypto::keys::{SeedPhrase, SpendKey, SpendSeed};
+use penumbra_stake::IdentityKey;
+
+/// The BLAKE2b personalization used to derive the Tendermint consensus key.
+pub const CONSENSUS_KEY_PERSONALIZATION: &[u8; 16] = b"Penumbra_ValCons";
+/// The BLAKE2b personalization used to derive the Tendermint node key.
+pub const NODE_KEY_PERSONALIZATION: &[u8; 16] = b"Penumbra_NodeKey";
+
+/// Which of a validator node's keys to derive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The Tendermint consensus key, as written to `priv_validator_key.json`.
+    Consensus,
+    /// The validator's identity, as written to `validator_spendseed.json`.
+    Identity,
+    /// The Tendermint P2P key, as written to `node_key.json`.
+    Node,
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "consensus" => Ok(Role::Consensus),
+            "identity" => Ok(Role::Identity),
+            "node" => Ok(Role::Node),
+            _ => Err(anyhow!(
+                "invalid key role {:?}, expected consensus, identity, or node",
+                s
+            )),
+        }
+    }
+}
+
+/// All of the keys of a validator node.
+pub struct NodeKeys {
+    /// The root of the validator's identity key.
+    pub spend_seed: SpendSeed,
+    /// The key Tendermint signs blocks with.
+    pub consensus_key: tendermint::PrivateKey,
+    /// The key Tendermint authenticates P2P connections with.
+    pub node_key: tendermint::PrivateKey,
+}
+
+impl NodeKeys {
+    /// Derives all of a validator node's keys from a seed phrase.
+    pub fn derive(seed_phrase: SeedPhrase, index: u64) -> Self {
+        let spend_seed = SpendSeed::from_seed_phrase(seed_phrase, index);
+        let consensus_key = derive_ed25519(&spend_seed, CONSENSUS_KEY_PERSONALIZATION);
+        let node_key = derive_ed25519(&spend_seed, NODE_KEY_PERSONALIZATION);
+
+        Self {
+            spend_seed,
+            consensus_key,
+            node_key,
+        }
+    }
+
+    /// The validator's identity key.
+    pub fn identity_key(&self) -> IdentityKey {
+        let spend_key = SpendKey::from(self.spend_seed.clone());
+        IdentityKey(
+            spend_key
+                .full_viewing_key()
+                .spend_verification_key()
+                .clone(),
+        )
+    }
+}
+
+fn derive_ed25519(spend_seed: &SpendSeed, personalization: &[u8; 16]) -> tendermint::PrivateKey {
+    let hash = blake2b_simd::Params::new()
+        .hash_length(32)
+        .personal(personalization)
+        .hash(&spend_seed.0);
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(hash.as_bytes());
+
+    tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::from(seed))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn derive(seed_phrase: &str, index: u64) -> NodeKeys {
+        NodeKeys::derive(seed_phrase.parse().unwrap(), index)
+    }
+
+    #[test]
+    fn derivation_is_deterministic() {
+        let seed_phrase = SeedPhrase::generate(OsRng).to_string();
+        let (a, b) = (derive(&seed_phrase, 0), derive(&seed_phrase, 0));
+
+        assert_eq!(a.spend_seed.0, b.spend_seed.0);
+        assert_eq!(a.identity_key(), b.identity_key());
+        assert_eq!(a.consensus_key.public_key(), b.consensus_key.public_key());
+        assert_eq!(a.node_key.public_key(), b.node_key.public_key());
+    }
+
+    #[test]
+    fn keys_are_distinct_per_role_and_index() {
+        let seed_phrase = SeedPhrase::generate(OsRng).to_string();
+        let (first, second) = (derive(&seed_phrase, 0), derive(&seed_phrase, 1));
+
+        assert_ne!(
+            first.consensus_key.public_key(),
+            first.node_key.public_key()
+        );
+        assert_ne!(first.identity_key(), second.identity_key());
+        assert_ne!(
+            first.consensus_key.public_key(),
+            second.consensus_key.public_key()
+        );
+        assert_ne!(first.node_key.public_key(), second.node_key.public_key());
+    }
+
+    #[test]
+    fn identity_key_matches_the_wallet_account() {
+        // `pcli` derives the spend key of the same seed phrase and index the
+        // same way, so the validator's identity belongs to that account.
+        let seed_phrase = SeedPhrase::generate(OsRng).to_string();
+        let spend_key =
+            SpendKey::from(SpendSeed::from_seed_phrase(seed_phrase.parse().unwrap(), 3));
+
+        assert_eq!(
+            derive(&seed_phrase, 3).identity_key(),
+            IdentityKey(
+                spend_key
+                    .full_viewing_key()
+                    .spend_verification_key()
+                    .clone()
+            )
+        );
+    }
+
+    #[test]
+    fn parses_roles() {
+        assert_eq!("consensus".parse::<Role>().unwrap(), Role::Consensus);
+        assert_eq!("identity".parse::<Role>().unwrap(), Role::Identity);
+        assert_eq!("node".parse::<Role>().unwrap(), Role::Node);
+        assert!("spend".parse::<Role>().is_err());
+    }
+}
@@ -7,21 +7,49 @@
 mod consensus;
 mod info;
 mod mempool;
+mod nullifier_cache;
 mod pd_metrics;
 mod request_ext;
 mod snapshot;
 mod storage;
 
+pub mod abci_socket;
+pub mod address_book;
+pub mod admin;
 pub mod components;
+pub mod config;
+pub mod flat_export;
 pub mod genesis;
+pub mod grpc_metrics;
+pub mod health;
+pub mod keys;
+pub mod logging;
+pub mod peers;
+pub mod profile;
+pub mod pruning;
+pub mod rate_limit;
+pub mod replay;
+pub mod staking_export;
+pub mod tendermint_health;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod testnet;
+pub mod upgrade;
+pub mod verify;
 
 use request_ext::RequestExt;
 
 pub use components::{App, Component};
-pub use consensus::Consensus;
-pub use info::Info;
-pub use mempool::Mempool;
-pub use pd_metrics::register_all_metrics;
+pub use consensus::{proposal, Consensus, TxCode, TxError};
+pub use info::{
+    reflection_service, Info, Oblivious, APP_HASH_QUERY_PATH, APP_VERSION_QUERY_PATH,
+    ICS23_PROOF_OP, KEY_QUERY_PATH, STORE_QUERY_PATH,
+};
+pub use mempool::{Mempool, PendingTx};
+pub use nullifier_cache::NullifierCache;
+pub use pd_metrics::{install_metrics, register_all_metrics, MetricsMode};
 pub use snapshot::Snapshot;
-pub use storage::{Overlay, OverlayExt, Storage};
+pub use storage::{
+    CompactionStyle, Overlay, OverlayExt, Pruned, Rollback, StateFile, Storage, Tuning,
+    FORMAT_VERSION,
+};
@@ -0,0 +1,172 @@
+//! Where `pd` writes its logs, and in what format.
+//!
+//! By default, logs are written to standard output as human-readable text.
+//! They can instead be written as JSON, one object per line, so that log
+//! shippers like Promtail or Filebeat can ingest them without parsing lines,
+//! and to a file which is rotated hourly or daily. Rotated files are not
+//! deleted, so they should be cleaned up by the operator.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use tracing_appender::{non_blocking::WorkerGuard, rolling::RollingFileAppender};
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, EnvFilter};
+
+use crate::admin::LogFilterReloader;
+
+/// The format of each logged event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    /// One line of human-readable text.
+    Text,
+    /// One JSON object, with the event's fields and those of its spans.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow!("unknown log format {:?}, expected text or json", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Text => "text",
+            Format::Json => "json",
+        })
+    }
+}
+
+/// How often a new log file is started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl FromStr for Rotation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "never" => Ok(Rotation::Never),
+            "hourly" => Ok(Rotation::Hourly),
+            "daily" => Ok(Rotation::Daily),
+            _ => Err(anyhow!(
+                "unknown log rotation {:?}, expected never, hourly or daily",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Rotation::Never => "never",
+            Rotation::Hourly => "hourly",
+            Rotation::Daily => "daily",
+        })
+    }
+}
+
+impl From<Rotation> for tracing_appender::rolling::Rotation {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Never => tracing_appender::rolling::Rotation::NEVER,
+            Rotation::Hourly => tracing_appender::rolling::Rotation::HOURLY,
+            Rotation::Daily => tracing_appender::rolling::Rotation::DAILY,
+        }
+    }
+}
+
+/// Where, and in what format, logs are written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Logging {
+    pub format: Format,
+    /// Write the logs to this file, suffixed with the date (and hour) it was
+    /// started at if it is rotated, rather than to standard output.
+    pub file: Option<PathBuf>,
+    pub rotation: Rotation,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            format: Format::Text,
+            file: None,
+            rotation: Rotation::Daily,
+        }
+    }
+}
+
+impl Logging {
+    /// Installs the global subscriber, logging the spans and events enabled
+    /// by `RUST_LOG`.
+    ///
+    /// Returns a callback replacing which are logged, and, when logging to a
+    /// file, a guard which must be held until `pd` exits, so that the logs
+    /// still buffered are written.
+    pub fn init(&self) -> Result<(LogFilterReloader, Option<WorkerGuard>)> {
+        let (writer, guard) = match &self.file {
+            Some(path) => {
+                let file_name = path
+                    .file_name()
+                    .ok_or_else(|| anyhow!("log file {:?} has no file name", path))?;
+                let dir = match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir,
+                    _ => Path::new("."),
+                };
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("cannot create log directory {:?}", dir))?;
+                let appender = RollingFileAppender::new(self.rotation.into(), dir, file_name);
+                let (writer, guard) = tracing_appender::non_blocking(appender);
+                (BoxMakeWriter::new(writer), Some(guard))
+            }
+            None => (BoxMakeWriter::new(std::io::stdout), None),
+        };
+
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(EnvFilter::from_default_env())
+            .with_writer(writer)
+            // Terminal colors would only garble a file.
+            .with_ansi(self.file.is_none());
+        let reload_log_filter: LogFilterReloader = match self.format {
+            Format::Text => {
+                let subscriber = builder.with_filter_reloading();
+                let filter_handle = subscriber.reload_handle();
+                subscriber.init();
+                Arc::new(move |filter| {
+                    filter_handle.reload(EnvFilter::try_new(filter)?)?;
+                    Ok(())
+                })
+            }
+            Format::Json => {
+                let subscriber = builder.json().with_filter_reloading();
+                let filter_handle = subscriber.reload_handle();
+                subscriber.init();
+                Arc::new(move |filter| {
+                    filter_handle.reload(EnvFilter::try_new(filter)?)?;
+                    Ok(())
+                })
+            }
+        };
+        Ok((reload_log_filter, guard))
+    }
+}
@@ -1,25 +1,27 @@
 #![allow(clippy::clone_on_copy)]
 use std::{
-    net::{Ipv4Addr, SocketAddr},
+    net::{IpAddr, SocketAddr},
     path::PathBuf,
 };
 
 use anyhow::Context;
-use metrics_exporter_prometheus::PrometheusBuilder;
-use pd::genesis::Allocation;
+use pd::{genesis::Allocation, keys::NodeKeys, rate_limit::remote_addr};
 use penumbra_chain::params::ChainParams;
 use penumbra_crypto::{
-    keys::{SpendKey, SpendSeed},
+    keys::{SeedPhrase, SpendKey, SpendSeed},
     rdsa::{SigningKey, SpendAuth, VerificationKey},
 };
-use penumbra_proto::client::{
-    oblivious::oblivious_query_server::ObliviousQueryServer,
-    specific::specific_query_server::SpecificQueryServer,
+use penumbra_proto::{
+    admin::admin_server::AdminServer,
+    client::{
+        oblivious::oblivious_query_server::ObliviousQueryServer,
+        specific::specific_query_server::SpecificQueryServer,
+    },
 };
 use penumbra_stake::{FundingStream, FundingStreams, Validator};
 use rand_core::OsRng;
 use structopt::StructOpt;
-use tonic::transport::Server;
+use tonic::transport::{Server, ServerTlsConfig};
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -37,24 +39,190 @@ struct Opt {
 enum Command {
     /// Start running the ABCI and wallet services.
     Start {
-        /// The path used to store the Rocks database.
+        /// Read options from this TOML file, as written by `pd generate-config`.
+        /// Options given as flags take precedence over the file.
+        #[structopt(short, long, parse(from_os_str))]
+        config: Option<PathBuf>,
+        #[structopt(flatten)]
+        options: pd::config::Options,
+    },
+
+    /// Writes a configuration file for `pd start`, with every option set to
+    /// its default and documented.
+    GenerateConfig {
+        /// Write the file here, rather than to standard output.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Re-executes the blocks in a log written by `pd start
+    /// --consensus-replay-log`.
+    ///
+    /// By default, the whole log is re-executed from genesis, committing to a
+    /// new, empty database, and every response which differs from the
+    /// recorded one is reported, to reproduce an app hash divergence offline.
+    ///
+    /// With `--from` and `--to`, instead only the logged blocks in that range
+    /// are re-executed against a temporary overlay on top of the stored state,
+    /// without committing anything, and their timings are reported.
+    Replay {
+        /// The path to the Rocks database to replay into, which must be empty,
+        /// or with `--from`, holding the state to replay on top of.
+        #[structopt(short, long)]
+        rocks_path: PathBuf,
+        /// Path to the consensus replay log to re-execute.
+        #[structopt(long, parse(from_os_str))]
+        log: PathBuf,
+        /// The first block height to replay.
+        #[structopt(long, requires = "to")]
+        from: Option<u64>,
+        /// The last block height to replay.
+        #[structopt(long, requires = "from")]
+        to: Option<u64>,
+        /// Report per-transaction and per-component timings and state read/write counts.
+        #[structopt(long, requires = "from")]
+        profile: bool,
+    },
+
+    /// Writes the application state committed at a height to a file, for
+    /// backup, migration, or debugging a consensus failure elsewhere.
+    ///
+    /// With `--format`, writes decoded tables of the state to flat files
+    /// instead, for analysis with standard data tooling.
+    ExportState {
+        /// The path to the Rocks database to export from. It is opened
+        /// read-only, so this can be run alongside `pd start`.
+        #[structopt(short, long)]
+        rocks_path: PathBuf,
+        /// The height to export the state at [default: the latest height].
+        #[structopt(long)]
+        height: Option<u64>,
+        /// The file to write the state to, or with `--format`, the directory
+        /// to write a file for each table to.
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+        /// Write decoded tables as `csv` or `parquet` files, rather than the
+        /// whole state for `pd import-state`.
+        #[structopt(long)]
+        format: Option<pd::flat_export::Format>,
+        /// The tables to write with `--format`, comma-separated, from
+        /// `validators`, `allocations` and `nullifiers` [default: all of them].
+        #[structopt(long, use_delimiter = true)]
+        tables: Vec<pd::flat_export::Table>,
+    },
+
+    /// Restores the application state from a file written by `pd
+    /// export-state` into a new, empty database.
+    ///
+    /// Tendermint must also have the blocks up to the exported height, e.g.
+    /// from a copy of the exporting node's Tendermint data directory, for the
+    /// restored node to join consensus.
+    ImportState {
+        /// The path to the Rocks database to restore into. It must be empty.
+        #[structopt(short, long)]
+        rocks_path: PathBuf,
+        /// The file to read the state from.
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+    },
+
+    /// Rewinds the application state to an earlier height, deleting every
+    /// later block's state, to recover from an app hash mismatch without
+    /// resyncing from genesis.
+    ///
+    /// Tendermint must be rolled back to the same height, with `tendermint
+    /// rollback`, so that it replays the later blocks when the node restarts.
+    /// The node must be stopped first, and since the deleted state cannot be
+    /// recovered, it is worth copying the database before rolling it back.
+    Rollback {
+        /// The path to the Rocks database to roll back.
         #[structopt(short, long)]
         rocks_path: PathBuf,
-        /// Bind the services to this host.
-        #[structopt(short, long, default_value = "127.0.0.1")]
-        host: String,
-        /// Bind the ABCI server to this port.
-        #[structopt(short, long, default_value = "26658")]
-        abci_port: u16,
-        /// Bind the oblivious query service to this port.
-        #[structopt(short, long, default_value = "26666")]
-        oblivious_query_port: u16,
-        /// Bind the specific query service to this port.
-        #[structopt(short, long, default_value = "26667")]
-        specific_query_port: u16,
-        /// Bind the metrics endpoint to this port.
-        #[structopt(short, long, default_value = "9000")]
-        metrics_port: u16,
+        /// The height to roll back to, which becomes the latest height.
+        #[structopt(long)]
+        height: u64,
+    },
+
+    /// Migrates a database written by an older version of `pd` to the
+    /// current storage format.
+    ///
+    /// `pd start` runs quick migrations itself, but refuses to open databases
+    /// needing slower ones, which must be run with this while the node is
+    /// stopped.
+    MigrateStorage {
+        /// The path to the Rocks database to migrate.
+        #[structopt(short, long)]
+        rocks_path: PathBuf,
+    },
+
+    /// Migrates the state of a chain halted for an upgrade with `pd start
+    /// --halt-height`, and writes the Tendermint genesis which resumes the
+    /// chain from the migrated state.
+    ///
+    /// Every validator runs this with the same version of `pd`, which makes
+    /// the same migrations and writes the same genesis. Tendermint's data must
+    /// then be reset, e.g. with `tendermint unsafe-reset-all`, and its genesis
+    /// replaced with the new one, before the chain is started again.
+    Migrate {
+        /// The path to the Rocks database of the halted node.
+        #[structopt(short, long)]
+        rocks_path: PathBuf,
+        /// The chain's current Tendermint genesis file.
+        #[structopt(long, parse(from_os_str))]
+        genesis_file: PathBuf,
+        /// The file to write the new genesis to.
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+    },
+
+    /// Checks that the stored state is internally consistent, as `pd start`
+    /// does before serving anything, and reports any inconsistencies.
+    Verify {
+        /// The path to the Rocks database to check.
+        #[structopt(short, long)]
+        rocks_path: PathBuf,
+    },
+
+    /// Checks that each validator's delegation tokens, valued at its exchange
+    /// rate, are backed by its pool of staking tokens at every height in a
+    /// range, as every block checks as it executes.
+    VerifyStake {
+        /// The path to the Rocks database to check.
+        #[structopt(short, long)]
+        rocks_path: PathBuf,
+        /// The first height to check.
+        #[structopt(long, default_value = "0")]
+        from: u64,
+        /// The last height to check, or the latest height if unset.
+        #[structopt(long)]
+        to: Option<u64>,
+    },
+
+    /// Inspects the validator set recorded in storage.
+    Validators(ValidatorsCommand),
+
+    /// Inspects the application state recorded in storage.
+    State(StateCommand),
+
+    /// Manages validator node keys.
+    Keys(KeysCommand),
+
+    /// Inspects genesis files.
+    Genesis(GenesisCommand),
+
+    /// Manages the co-located Tendermint node's persistent peers.
+    Peers(PeersCommand),
+
+    /// Sets up nodes for networks which are already running.
+    Testnet(TestnetCommand),
+
+    /// Writes the encoded `FileDescriptorSet` of the protocols this node was
+    /// compiled with, which is also served on the oblivious query service, so
+    /// that client compatibility can be checked.
+    ProtoDescriptor {
+        /// Write the descriptors to this file, rather than to standard output.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
     },
 
     /// Generates a directory structure containing necessary files to run a
@@ -63,9 +231,17 @@ enum Command {
         /// Number of blocks per epoch.
         #[structopt(long, default_value = "40")]
         epoch_duration: u64,
+        /// Duration of each epoch in seconds of block time. If nonzero, this
+        /// takes precedence over `epoch-duration`.
+        #[structopt(long, default_value = "0")]
+        epoch_duration_seconds: u64,
         /// Number of epochs before unbonding stake is released.
         #[structopt(long, default_value = "40")]
         unbonding_epochs: u64,
+        /// Minimum number of epochs a validator must remain in the consensus
+        /// set after entering it.
+        #[structopt(long, default_value = "0")]
+        min_validator_bond_epochs: u64,
         /// Maximum number of validators in the consensus set.
         #[structopt(long, default_value = "10")]
         active_validator_limit: u64,
@@ -77,13 +253,23 @@ enum Command {
         /// Expressed in basis points of basis points (1e8 denominator)
         #[structopt(long, default_value = "30000")]
         base_reward_rate: u64,
+        /// Fraction by which the base reward rate declines each epoch, for a
+        /// declining issuance schedule. Expressed in basis points of basis
+        /// points; 0 keeps the base reward rate constant.
+        #[structopt(long, default_value = "0")]
+        base_reward_rate_decay: u64,
+        /// Base reward rate below which it does not decline.
+        /// Expressed in basis points of basis points.
+        #[structopt(long, default_value = "0")]
+        min_base_reward_rate: u64,
         /// Whether to preserve the chain ID (useful for public testnets) or append a random suffix (useful for dev/testing).
         #[structopt(long)]
         preserve_chain_id: bool,
         /// Path to CSV file containing initial allocations [default: latest testnet].
         #[structopt(long, parse(from_os_str))]
         allocations_input_file: Option<PathBuf>,
-        /// Path to JSON file containing initial validator configs [default: latest testnet].
+        /// Path to JSON file containing initial validator configs, each of which may set the
+        /// validator's `self_delegation` [default: latest testnet].
         #[structopt(long, parse(from_os_str))]
         validators_input_file: Option<PathBuf>,
         /// Path to directory to store output in. Must not exist.
@@ -92,39 +278,307 @@ enum Command {
         /// Testnet name [default: latest testnet].
         #[structopt(long)]
         chain_id: Option<String>,
-        /// IP Address to start `tendermint` nodes on. Increments by three to make room for `pd` per node.
+        /// Experimental features to enable, optionally suffixed with
+        /// `@<height>` to enable them only from that block height on.
+        #[structopt(long)]
+        features: Vec<String>,
+        /// The most a single faucet claim may mint, if the `faucet` feature
+        /// is enabled.
+        #[structopt(long, default_value = "100000000")]
+        faucet_max_claim: u64,
+        /// Number of blocks an address must wait between faucet claims.
+        #[structopt(long, default_value = "8640")]
+        faucet_claim_interval: u64,
+        /// Maximum number of faucet claims included in a single block.
+        #[structopt(long, default_value = "10")]
+        faucet_claims_per_block: u64,
+        /// The most stake a single validator may hold, in basis points of the
+        /// total stake, or 0 for no maximum.
+        #[structopt(long, default_value = "0")]
+        max_validator_stake_share: u64,
+        /// Have the generated Tendermint configs reach pd's ABCI server on a
+        /// Unix domain socket at this path, as served by `pd start --abci-uds`.
+        #[structopt(long, parse(from_os_str))]
+        abci_uds: Option<PathBuf>,
+        /// IP address, v4 or v6, to start `tendermint` nodes on. Increments by
+        /// ten per node, to make room for `pd` and other services alongside
+        /// it. Validators with a `listen_address` in the validators file are
+        /// placed there instead.
         #[structopt(long, default_value = "192.167.10.11")]
-        starting_ip: Ipv4Addr,
+        starting_ip: IpAddr,
+        /// Number of full (non-validator) nodes to generate alongside the
+        /// validators, e.g. as sentries, each with the validators as its
+        /// persistent peers. They are placed at the addresses counted after
+        /// the validators'.
+        #[structopt(long, default_value = "0")]
+        fullnodes: usize,
+    },
+}
+
+/// How long to wait before restarting the ABCI listener after it fails.
+const ABCI_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// A builder for a gRPC server, serving TLS if it is configured.
+fn grpc_server(tls: Option<&ServerTlsConfig>) -> anyhow::Result<Server> {
+    Ok(match tls {
+        Some(tls) => Server::builder()
+            .tls_config(tls.clone())
+            .context("invalid TLS configuration")?,
+        None => Server::builder(),
+    })
+}
+
+/// The error to report for a service task which ended, although it should have
+/// run until shutdown.
+fn service_ended(
+    name: &str,
+    result: Result<anyhow::Result<()>, tokio::task::JoinError>,
+) -> anyhow::Error {
+    match result {
+        Ok(Ok(())) => anyhow::anyhow!("{} service stopped unexpectedly", name),
+        Ok(Err(error)) => error.context(format!("{} service failed", name)),
+        Err(error) => anyhow::Error::new(error).context(format!("{} service panicked", name)),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum ValidatorsCommand {
+    /// Exports the current validator set, for configuring monitoring.
+    Export {
+        /// The path to the Rocks database to read the validator set from.
+        #[structopt(short, long)]
+        rocks_path: PathBuf,
+        /// The output format: `prometheus-targets` or `json`.
+        #[structopt(long, default_value = "json")]
+        format: pd::address_book::Format,
+        /// The port to scrape on each validator host, for `prometheus-targets`.
+        #[structopt(long, default_value = "26660")]
+        target_port: u16,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum StateCommand {
+    /// Lists the keys in the state, decoded and grouped by the component which
+    /// owns them, with their counts and sizes.
+    Keys {
+        /// The path to the Rocks database to read. It is opened read-only, so
+        /// this can be run alongside `pd start`.
+        #[structopt(short, long)]
+        rocks_path: PathBuf,
+        /// Only show the keys of this component, or which start with this
+        /// prefix, e.g. `staking` or `staking/validators`.
+        #[structopt(long)]
+        prefix: Option<String>,
+        /// The height to read the state at [default: the latest height].
+        #[structopt(long)]
+        height: Option<u64>,
+        /// List every key, not just the count and size of each family of keys.
+        #[structopt(long)]
+        list: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum GenesisCommand {
+    /// Executes a genesis file's app state against an in-memory database,
+    /// reporting the resulting app hash and initial validator set, or the
+    /// error a validator would crash with at boot.
+    Validate {
+        /// The Tendermint genesis file to validate.
+        #[structopt(parse(from_os_str))]
+        genesis_file: PathBuf,
+    },
+    /// Prints the SHA-256 digest of the canonical encoding of a genesis
+    /// file's app state, which `pd` also logs at `InitChain`.
+    ///
+    /// The digest does not depend on the formatting of the file, so
+    /// validators can compare it out of band to check that they all loaded
+    /// the same genesis before the chain starts.
+    Hash {
+        /// The Tendermint genesis file to hash.
+        #[structopt(parse(from_os_str))]
+        genesis_file: PathBuf,
+    },
+    /// Compares the app states of two genesis files, printing the chain
+    /// parameters, validators, allocations and rates which differ.
+    ///
+    /// Validators are matched by identity key and allocations by address and
+    /// denomination, so reordering either, or reformatting a file, is not a
+    /// difference.
+    Diff {
+        /// The genesis file to compare from, e.g. the previous testnet's.
+        #[structopt(parse(from_os_str))]
+        old_genesis_file: PathBuf,
+        /// The genesis file to compare to.
+        #[structopt(parse(from_os_str))]
+        new_genesis_file: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum PeersCommand {
+    /// Fetches the chain's signed peer list, and writes it to the persistent
+    /// peers of a Tendermint config, which Tendermint reads when it is next
+    /// restarted.
+    Refresh {
+        /// The URL the peer list is published at.
+        #[structopt(long)]
+        from_url: String,
+        /// The hex-encoded Ed25519 key the peer list must be signed with.
+        #[structopt(long)]
+        signer: String,
+        /// The home directory of the Tendermint node, whose
+        /// `config/config.toml` is rewritten, and whose `config/genesis.json`
+        /// names the chain the list must be for.
+        #[structopt(long, parse(from_os_str))]
+        tendermint_home: PathBuf,
+    },
+    /// Signs a peer list for the chain, to be published for `pd peers refresh`.
+    Sign {
+        /// A file holding the hex-encoded Ed25519 signing key.
+        #[structopt(long, parse(from_os_str))]
+        key_file: PathBuf,
+        /// The chain the peers are for.
+        #[structopt(long)]
+        chain_id: String,
+        /// The peers, each as `<node id>@<host>:<port>`.
+        peers: Vec<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum TestnetCommand {
+    /// Writes a ready-to-run directory for a new node joining a running
+    /// network, with the network's genesis, peers to dial, and fresh keys.
+    ///
+    /// The genesis and peers are fetched from a node on the network, unless
+    /// they are published elsewhere.
+    Join {
+        /// The Tendermint RPC endpoint of a node on the network, e.g.
+        /// `http://testnet.penumbra.zone:26657`.
+        #[structopt(long)]
+        node: String,
+        /// Fetch the genesis file published at this URL, rather than the node's.
+        #[structopt(long)]
+        genesis_url: Option<String>,
+        /// Fetch the signed peer list published at this URL, as for `pd peers
+        /// refresh`, rather than dialing the node and its peers. Requires
+        /// `--peers-signer`.
+        #[structopt(long)]
+        peers_url: Option<String>,
+        /// The hex-encoded Ed25519 key the peer list must be signed with.
+        #[structopt(long)]
+        peers_signer: Option<String>,
+        /// A human-readable name for the node.
+        #[structopt(long, default_value = "node0")]
+        moniker: String,
+        /// Have Tendermint reach pd's ABCI server on a Unix domain socket at
+        /// this path, as served by `pd start --abci-uds`.
+        #[structopt(long, parse(from_os_str))]
+        abci_uds: Option<PathBuf>,
+        /// Path to the directory to write the node's files to. Must not exist
+        /// [default: ~/.penumbra/testnet_data/node0].
+        #[structopt(long, parse(from_os_str))]
+        output_dir: Option<PathBuf>,
     },
 }
 
-// Extracted from tonic's remote_addr implementation; we'd like to instrument
-// spans with the remote addr at the server level rather than at the individual
-// request level, but the hook available to do that gives us an http::Request
-// rather than a tonic::Request, so the tonic::Request::remote_addr method isn't
-// available.
-fn remote_addr(req: &http::Request<()>) -> Option<SocketAddr> {
-    use tonic::transport::server::TcpConnectInfo;
-    // NOTE: needs to also check TlsConnectInfo if we use TLS
-    req.extensions()
-        .get::<TcpConnectInfo>()
-        .and_then(|i| i.remote_addr())
+#[derive(Debug, StructOpt)]
+enum KeysCommand {
+    /// Derives one of a validator node's keys from a seed phrase, printing it
+    /// in the format of the corresponding file written by `generate-testnet`.
+    ///
+    /// See the `pd::keys` module for the derivation.
+    Derive {
+        /// The 24-word seed phrase the keys are derived from.
+        #[structopt(long)]
+        mnemonic: String,
+        /// Which key to derive: `consensus` (priv_validator_key.json),
+        /// `identity` (validator_spendseed.json) or `node` (node_key.json).
+        #[structopt(long)]
+        role: pd::keys::Role,
+        /// The index to derive the keys at, if the seed phrase is shared by
+        /// several nodes.
+        #[structopt(long, default_value = "0")]
+        index: u64,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-    let opt = Opt::from_args();
+    let mut opt = Opt::from_args();
+
+    // The options of `pd start` are resolved before anything is logged, since
+    // they configure the logging; the other commands log text to stdout.
+    let start_config = match &mut opt.cmd {
+        Command::Start { config, options } => {
+            let file = config
+                .as_ref()
+                .map(|path| pd::config::Options::load(path))
+                .transpose()?
+                .unwrap_or_default();
+            Some(std::mem::take(options).or(file).resolve()?)
+        }
+        _ => None,
+    };
+    let (reload_log_filter, _log_guard) = start_config
+        .as_ref()
+        .map(|config| config.logging.clone())
+        .unwrap_or_default()
+        .init()?;
 
     match opt.cmd {
-        Command::Start {
-            host,
-            abci_port,
-            oblivious_query_port,
-            specific_query_port,
-            metrics_port,
-            rocks_path,
-        } => {
+        Command::Start { .. } => {
+            let config = start_config.expect("the options of `pd start` are resolved");
+            let tls = config.tls_config()?;
+            if tls.is_some() {
+                tracing::info!("serving gRPC over TLS");
+            }
+            let pd::config::Config {
+                rocks_path,
+                host,
+                abci_port,
+                abci_uds,
+                oblivious_query_port,
+                specific_query_port,
+                grpc_port,
+                metrics,
+                metrics_port,
+                admin_port,
+                admin_token_file,
+                staking_export_port,
+                tendermint_rpc,
+                tendermint_poll_interval,
+                peers_url,
+                peers_signer,
+                tendermint_home,
+                peers_refresh_interval,
+                health_port,
+                persist_tx_results,
+                cache_replayed_blocks,
+                consensus_replay_log,
+                halt_height,
+                pruning,
+                rocksdb,
+                max_stream_duration,
+                max_concurrent_streams,
+                query_rate_limit,
+                query_rate_burst,
+                api_keys_file,
+                query_usage_top_clients,
+                mempool_ttl,
+                mempool_min_fee,
+                drain_grace_period,
+                shutdown_grace_period,
+                read_only,
+                log_filter,
+                ..
+            } = config;
+            if let Some(filter) = &log_filter {
+                reload_log_filter(filter).context("invalid log filter")?;
+            }
+
             tracing::info!(
                 ?host,
                 ?abci_port,
@@ -133,72 +587,923 @@ async fn main() -> anyhow::Result<()> {
                 "starting pd"
             );
 
+            let storage = if read_only {
+                pd::Storage::load_read_only(rocks_path).await
+            } else {
+                pd::Storage::load_tuned(rocks_path, rocksdb).await
+            }
+            .context("Unable to initialize RocksDB storage")?;
+
+            // Refuse to start on inconsistent state, rather than failing
+            // consensus at the next block. In read-only mode, no blocks are
+            // executed, and inconsistent state is what's being inspected.
+            let report = pd::verify::check(&storage).await?;
+            for skipped in &report.skipped {
+                tracing::warn!(%skipped, "skipped state consistency check");
+            }
+            if !report.is_consistent() {
+                for problem in &report.problems {
+                    tracing::error!(%problem, "stored state is inconsistent");
+                }
+                if !read_only {
+                    return Err(anyhow::anyhow!(
+                        "stored state is inconsistent; run `pd verify` for details, and restore \
+                         the database from a checkpoint or roll it back with `pd rollback`"
+                    ));
+                }
+            }
+
+            let stop_consensus = tokio_util::sync::CancellationToken::new();
+            let (mut abci_server, consensus_worker, pending_txs, block_heights) = if read_only {
+                tracing::warn!("read-only mode: not serving consensus or mempool");
+                let (_, pending_txs) = tokio::sync::watch::channel(Vec::new());
+                (
+                    tokio::spawn(futures::future::pending()),
+                    tokio::spawn(async { Ok(()) }),
+                    pending_txs,
+                    None,
+                )
+            } else {
+                // The nullifiers of uncommitted transactions, so that the
+                // mempool rejects conflicting transactions before consensus.
+                let nullifiers = pd::NullifierCache::default();
+                let replay_log = consensus_replay_log
+                    .as_deref()
+                    .map(pd::replay::ReplayLog::open)
+                    .transpose()?;
+                // A node already at its halt height would otherwise go on
+                // executing blocks with the old version of pd.
+                if let (Some(halt_height), Some(latest)) =
+                    (halt_height, storage.latest_version().await?)
+                {
+                    if latest >= halt_height {
+                        return Err(anyhow::anyhow!(
+                            "the stored state is at height {}, at or past the halt height {}; \
+                             run `pd migrate` for the upgrade, or unset the halt height",
+                            latest,
+                            halt_height
+                        ));
+                    }
+                }
+                let (consensus, height_rx, consensus_worker) = pd::Consensus::new(
+                    storage.clone(),
+                    persist_tx_results,
+                    cache_replayed_blocks,
+                    nullifiers.clone(),
+                    stop_consensus.clone(),
+                    replay_log,
+                    halt_height,
+                )
+                .await?;
+                let block_heights = height_rx.clone();
+                let mempool_ttl =
+                    (mempool_ttl > 0).then(|| std::time::Duration::from_secs(mempool_ttl));
+                let mempool = pd::Mempool::new(
+                    storage.clone(),
+                    height_rx,
+                    nullifiers,
+                    mempool_ttl,
+                    mempool_min_fee,
+                )
+                .await?;
+                let pending_txs = mempool.pending_txs();
+                let info = pd::Info::new(storage.clone());
+                let snapshot = pd::Snapshot {};
+
+                // Tendermint restarting only drops its connections, which the
+                // listener survives, but if the listener itself fails, it is
+                // restarted rather than taking the query services down with it.
+                let stop = stop_consensus.clone();
+                let abci_addr = format!("{}:{}", host, abci_port);
+                let abci_server = tokio::spawn(async move {
+                    while !stop.is_cancelled() {
+                        let result = match &abci_uds {
+                            Some(path) => {
+                                pd::abci_socket::listen(
+                                    path,
+                                    consensus.clone(),
+                                    mempool.clone(),
+                                    info.clone(),
+                                    snapshot.clone(),
+                                )
+                                .await
+                            }
+                            None => tower_abci::Server::builder()
+                                .consensus(consensus.clone())
+                                .snapshot(snapshot.clone())
+                                .mempool(mempool.clone())
+                                .info(info.clone())
+                                .finish()
+                                .unwrap()
+                                .listen(abci_addr.clone())
+                                .await
+                                .map_err(|e| anyhow::anyhow!(e)),
+                        };
+                        if let Err(error) = result {
+                            tracing::error!(%error, "ABCI listener failed; restarting it");
+                        }
+                        tokio::time::sleep(ABCI_RESTART_DELAY).await;
+                    }
+                    anyhow::Ok(())
+                });
+                (
+                    abci_server,
+                    consensus_worker,
+                    pending_txs,
+                    Some(block_heights),
+                )
+            };
+
+            // Raised on shutdown, to end compact block streams with a height
+            // to resume from and send GOAWAY to the oblivious query clients.
+            let (drain_tx, drain_rx) = tokio::sync::watch::channel(false);
+            let mut oblivious = pd::Oblivious::new(storage.clone()).with_drain(drain_rx.clone());
+
+            // The query services report themselves as not serving until the
+            // node has caught up, and once it starts shutting down.
+            let (health_reporter, health_service) = tonic_health::server::health_reporter();
+            let (readiness_tx, readiness_rx) = tokio::sync::watch::channel(
+                pd::health::Readiness::NotReady("starting".to_string()),
+            );
+            tokio::spawn(pd::health::run(
+                pd::health::Checker::new(block_heights.clone(), tendermint_rpc.clone()),
+                pd::health::CHECK_INTERVAL,
+                health_reporter,
+                readiness_tx,
+                drain_rx.clone(),
+            ));
+            if let Some(port) = health_port {
+                let addr = format!("{}:{}", host, port)
+                    .parse::<SocketAddr>()
+                    .expect("this is a valid address");
+                tokio::spawn(async move {
+                    if let Err(error) = pd::health::serve(addr, readiness_rx).await {
+                        tracing::error!(?error, "health probes failed");
+                    }
+                });
+            }
+
+            if let (Some(pruning), Some(block_heights)) = (pruning, &block_heights) {
+                tokio::spawn(pd::pruning::run(
+                    storage.clone(),
+                    pruning,
+                    block_heights.clone(),
+                ));
+            }
+
+            if let Some(block_heights) = block_heights {
+                oblivious = oblivious.with_block_heights(block_heights);
+            }
+            if max_stream_duration != 0 {
+                oblivious = oblivious
+                    .with_max_stream_duration(std::time::Duration::from_secs(max_stream_duration));
+            }
+            let drained = {
+                let mut drain_rx = drain_rx;
+                async move {
+                    while !*drain_rx.borrow() {
+                        if drain_rx.changed().await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            };
+            // Shared by the query servers, so that a client's requests to
+            // either service count against the same limit.
+            let mut rate_limit =
+                pd::rate_limit::RateLimitLayer::new(query_rate_limit, query_rate_burst);
+            if let Some(path) = &api_keys_file {
+                rate_limit = rate_limit.with_api_keys(pd::rate_limit::ApiKeys::load(path)?);
+            }
+            // Requests are timed once admitted by the rate limit, so that
+            // rejected requests for made-up methods aren't recorded.
+            let query_layers = tower::ServiceBuilder::new()
+                .layer(rate_limit.clone())
+                .layer(pd::grpc_metrics::RequestDurationLayer)
+                .into_inner();
+            let (mut oblivious_server, mut specific_server) = match grpc_port {
+                Some(grpc_port) => {
+                    let reflection = pd::reflection_service()?;
+                    tracing::info!(?grpc_port, "serving both query services on a single port");
+                    let grpc_server = tokio::spawn(
+                        grpc_server(tls.as_ref())?
+                            .max_concurrent_streams(max_concurrent_streams)
+                            .layer(query_layers.clone())
+                            .trace_fn(|req| match remote_addr(req) {
+                                Some(remote_addr) => tracing::error_span!("query", ?remote_addr),
+                                None => tracing::error_span!("query"),
+                            })
+                            .add_service(ObliviousQueryServer::new(oblivious))
+                            .add_service(SpecificQueryServer::new(storage.clone()))
+                            .add_service(health_service)
+                            .add_service(reflection)
+                            .serve_with_shutdown(
+                                format!("{}:{}", host, grpc_port)
+                                    .parse()
+                                    .expect("this is a valid address"),
+                                drained,
+                            ),
+                    );
+                    // The specific query service is served with the oblivious
+                    // one, and drained along with it.
+                    (grpc_server, tokio::spawn(futures::future::pending()))
+                }
+                None => (
+                    tokio::spawn(
+                        grpc_server(tls.as_ref())?
+                            .max_concurrent_streams(max_concurrent_streams)
+                            .layer(query_layers.clone())
+                            .trace_fn(|req| match remote_addr(req) {
+                                Some(remote_addr) => {
+                                    tracing::error_span!("oblivious_query", ?remote_addr)
+                                }
+                                None => tracing::error_span!("oblivious_query"),
+                            })
+                            .add_service(ObliviousQueryServer::new(oblivious))
+                            .add_service(health_service.clone())
+                            .serve_with_shutdown(
+                                format!("{}:{}", host, oblivious_query_port)
+                                    .parse()
+                                    .expect("this is a valid address"),
+                                drained,
+                            ),
+                    ),
+                    tokio::spawn(
+                        grpc_server(tls.as_ref())?
+                            .max_concurrent_streams(max_concurrent_streams)
+                            .layer(query_layers.clone())
+                            .trace_fn(|req| match remote_addr(req) {
+                                Some(remote_addr) => {
+                                    tracing::error_span!("specific_query", ?remote_addr)
+                                }
+                                None => tracing::error_span!("specific_query"),
+                            })
+                            .add_service(SpecificQueryServer::new(storage.clone()))
+                            .add_service(health_service)
+                            .serve(
+                                format!("{}:{}", host, specific_query_port)
+                                    .parse()
+                                    .expect("this is a valid address"),
+                            ),
+                    ),
+                ),
+            };
+
+            // This service lets Prometheus pull metrics from `pd`
+            let metrics_handle = pd::install_metrics(
+                metrics,
+                format!("{}:{}", host, metrics_port)
+                    .parse::<SocketAddr>()
+                    .expect("this is a valid address"),
+            )?;
+            pd::register_all_metrics();
+
+            if query_usage_top_clients > 0 {
+                tokio::spawn(rate_limit.export_top_clients(query_usage_top_clients));
+            }
+
+            let export_cache = pd::staking_export::DocumentCache::default();
+            if let Some(port) = staking_export_port {
+                let storage = storage.clone();
+                let cache = export_cache.clone();
+                let addr = format!("{}:{}", host, port)
+                    .parse::<SocketAddr>()
+                    .expect("this is a valid address");
+                tokio::spawn(async move {
+                    if let Err(error) = pd::staking_export::serve(storage, addr, cache).await {
+                        tracing::error!(?error, "staking export failed");
+                    }
+                });
+            }
+
+            if let Some(rpc_url) = tendermint_rpc {
+                tokio::spawn(pd::tendermint_health::poll(
+                    rpc_url,
+                    std::time::Duration::from_secs(tendermint_poll_interval),
+                ));
+            }
+
+            if let Some(url) = peers_url {
+                let signer =
+                    pd::peers::parse_signer(peers_signer.as_deref().ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "peers-url requires peers-signer, the key the list is signed with"
+                        )
+                    })?)?;
+                let tendermint_home = tendermint_home.ok_or_else(|| {
+                    anyhow::anyhow!("peers-url requires tendermint-home, whose config to rewrite")
+                })?;
+                tokio::spawn(pd::peers::poll(
+                    url,
+                    signer,
+                    tendermint_home,
+                    std::time::Duration::from_secs(peers_refresh_interval),
+                ));
+            }
+
+            // The admin service is opt-in, since it can stop the node.
+            let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+            let mut admin_server = tokio::spawn({
+                let admin = admin_token_file
+                    .map(|path| {
+                        let token = std::fs::read_to_string(&path)
+                            .with_context(|| format!("cannot read admin token {:?}", path))?
+                            .trim()
+                            .to_string();
+                        anyhow::ensure!(!token.is_empty(), "admin token file {:?} is empty", path);
+                        Ok(AdminServer::with_interceptor(
+                            pd::admin::Admin::new(
+                                storage.clone(),
+                                reload_log_filter,
+                                metrics_handle,
+                                shutdown_tx.clone(),
+                                pending_txs,
+                                export_cache,
+                            ),
+                            pd::admin::Admin::interceptor(token),
+                        ))
+                    })
+                    .transpose()?;
+                let addr = format!("{}:{}", host, admin_port)
+                    .parse::<SocketAddr>()
+                    .expect("this is a valid address");
+                let server = grpc_server(tls.as_ref())?;
+                async move {
+                    match admin {
+                        Some(admin) => {
+                            tracing::info!(?addr, "starting admin service");
+                            server
+                                .trace_fn(|req| match remote_addr(req) {
+                                    Some(remote_addr) => {
+                                        tracing::error_span!("admin", ?remote_addr)
+                                    }
+                                    None => tracing::error_span!("admin"),
+                                })
+                                .add_service(admin)
+                                .serve(addr)
+                                .await
+                        }
+                        None => futures::future::pending().await,
+                    }
+                }
+            });
+
+            let mut terminate =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+            // If a service fails, the others are still shut down cleanly, and
+            // the failure is reported once that's done.
+            let mut oblivious_finished = false;
+            let failure = tokio::select! {
+                x = &mut abci_server => Some(service_ended("ABCI", x)),
+                x = &mut oblivious_server => {
+                    oblivious_finished = true;
+                    Some(service_ended(
+                        "oblivious query",
+                        x.map(|r| r.map_err(|e| anyhow::anyhow!(e))),
+                    ))
+                }
+                x = &mut specific_server => Some(service_ended(
+                    "specific query",
+                    x.map(|r| r.map_err(|e| anyhow::anyhow!(e))),
+                )),
+                x = &mut admin_server => Some(service_ended(
+                    "admin",
+                    x.map(|r| r.map_err(|e| anyhow::anyhow!(e))),
+                )),
+                _ = shutdown_rx.changed() => None,
+                _ = stop_consensus.cancelled() => {
+                    tracing::info!("consensus halted at the halt height");
+                    None
+                }
+                _ = terminate.recv() => {
+                    tracing::info!("received SIGTERM");
+                    None
+                }
+                _ = tokio::signal::ctrl_c() => {
+                    tracing::info!("received SIGINT");
+                    None
+                }
+            };
+            match &failure {
+                Some(error) => tracing::error!(?error, "shutting down after service failure"),
+                None => tracing::info!("shutting down"),
+            }
+
+            // Let the consensus worker commit the block in progress, so that
+            // the stored state is at a block boundary, then stop it.
+            stop_consensus.cancel();
+
+            // Let clients of the oblivious query service reconnect elsewhere,
+            // rather than have their streams cut off mid-block.
+            if !oblivious_finished {
+                tracing::info!("draining compact block streams");
+                let _ = drain_tx.send(true);
+                let grace_period = std::time::Duration::from_secs(drain_grace_period);
+                if tokio::time::timeout(grace_period, oblivious_server)
+                    .await
+                    .is_err()
+                {
+                    tracing::warn!("oblivious query clients still connected after grace period");
+                }
+            }
+
+            let grace_period = std::time::Duration::from_secs(shutdown_grace_period);
+            match tokio::time::timeout(grace_period, consensus_worker).await {
+                Ok(Ok(Ok(()))) => {}
+                Ok(Ok(Err(error))) => tracing::error!(?error, "consensus worker failed"),
+                Ok(Err(error)) => tracing::error!(?error, "consensus worker panicked"),
+                Err(_) => tracing::warn!(
+                    "block in progress was not committed within the shutdown grace period; \
+                     Tendermint will replay it on restart"
+                ),
+            }
+
+            // Stop serving ABCI and the remaining query services.
+            abci_server.abort();
+            specific_server.abort();
+            admin_server.abort();
+
+            // A read-only database has nothing to flush, and refuses to.
+            if !read_only {
+                storage
+                    .flush()
+                    .await
+                    .context("could not flush RocksDB on shutdown")?;
+            }
+
+            match failure {
+                Some(error) => return Err(error),
+                None => tracing::info!("pd shut down cleanly"),
+            }
+        }
+        Command::ExportState {
+            rocks_path,
+            height,
+            output,
+            format,
+            tables,
+        } => {
+            let storage = pd::Storage::load_read_only(rocks_path)
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+            let height = match height {
+                Some(height) => height,
+                None => storage
+                    .latest_version()
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("database is empty"))?,
+            };
+            match format {
+                None => {
+                    if !tables.is_empty() {
+                        return Err(anyhow::anyhow!("--tables requires --format"));
+                    }
+                    let state = storage.export_state(height, output).await?;
+                    println!(
+                        "exported {} nodes and {} app hashes at height {}, with app hash {}",
+                        state.nodes,
+                        state.app_hashes,
+                        state.version,
+                        hex::encode_upper(state.root_hash.0)
+                    );
+                }
+                Some(format) => {
+                    let tables = match tables.is_empty() {
+                        true => pd::flat_export::Table::ALL.to_vec(),
+                        false => tables,
+                    };
+                    let exported =
+                        pd::flat_export::export(&storage, height, &tables, format, &output).await?;
+                    for exported in exported {
+                        println!(
+                            "exported {} rows of {} at height {} to {}",
+                            exported.rows,
+                            exported.table.name(),
+                            height,
+                            exported.path.display()
+                        );
+                    }
+                }
+            }
+        }
+        Command::ImportState { rocks_path, input } => {
+            let storage = pd::Storage::load(rocks_path)
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+            let state = storage.import_state(input).await?;
+            storage.flush().await?;
+            println!(
+                "imported {} nodes and {} app hashes at height {}, with app hash {}",
+                state.nodes,
+                state.app_hashes,
+                state.version,
+                hex::encode_upper(state.root_hash.0)
+            );
+        }
+        Command::Rollback { rocks_path, height } => {
+            let storage = pd::Storage::load(rocks_path)
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+            let rollback = storage.rollback(height).await?;
+            println!(
+                "rolled back from height {} to height {}, deleting {} tree nodes, {} app hashes \
+                 and {} transaction results",
+                rollback.from,
+                rollback.to,
+                rollback.nodes,
+                rollback.app_hashes,
+                rollback.tx_results
+            );
+
+            let report = pd::verify::check(&storage).await?;
+            for problem in &report.problems {
+                println!("inconsistent: {}", problem);
+            }
+            if !report.is_consistent() {
+                return Err(anyhow::anyhow!(
+                    "rolled back state has {} inconsistencies",
+                    report.problems.len()
+                ));
+            }
+        }
+        Command::MigrateStorage { rocks_path } => match pd::Storage::migrate(rocks_path).await? {
+            (None, _) => println!("database is empty"),
+            (Some(from), migrated) if migrated.is_empty() => {
+                println!("database is already in storage format {}", from)
+            }
+            (Some(from), migrated) => {
+                for migration in migrated {
+                    println!("migrated: {}", migration);
+                }
+                println!(
+                    "migrated from storage format {} to {}",
+                    from,
+                    pd::FORMAT_VERSION
+                );
+            }
+        },
+        Command::Migrate {
+            rocks_path,
+            genesis_file,
+            output,
+        } => {
+            let genesis_json = std::fs::read(&genesis_file)
+                .with_context(|| format!("could not read {}", genesis_file.display()))?;
+            let genesis = serde_json::from_slice(&genesis_json)
+                .with_context(|| format!("could not parse {}", genesis_file.display()))?;
             let storage = pd::Storage::load(rocks_path)
                 .await
                 .context("Unable to initialize RocksDB storage")?;
 
-            let (consensus, height_rx) = pd::Consensus::new(storage.clone()).await?;
-            let mempool = pd::Mempool::new(storage.clone(), height_rx).await?;
-            let info = pd::Info::new(storage.clone());
-            let snapshot = pd::Snapshot {};
-
-            let abci_server = tokio::spawn(
-                tower_abci::Server::builder()
-                    .consensus(consensus)
-                    .snapshot(snapshot)
-                    .mempool(mempool)
-                    .info(info)
-                    .finish()
-                    .unwrap()
-                    .listen(format!("{}:{}", host, abci_port)),
+            let migrated = pd::upgrade::migrate(&storage, pd::upgrade::MIGRATIONS).await?;
+            let genesis = pd::upgrade::resume_genesis(genesis, &migrated)?;
+            storage.flush().await?;
+            std::fs::write(&output, serde_json::to_string_pretty(&genesis)?)
+                .with_context(|| format!("could not write {}", output.display()))?;
+
+            for migration in &migrated.migrations {
+                println!("migrated: {}", migration);
+            }
+            println!("migrated state committed as height {}", migrated.height);
+            println!("app hash: {}", hex::encode_upper(migrated.app_hash));
+            println!(
+                "wrote the genesis resuming chain {} at height {} to {}",
+                migrated.chain_id,
+                migrated.version + 1,
+                output.display()
             );
+        }
+        Command::Verify { rocks_path } => {
+            let storage = pd::Storage::load(rocks_path)
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+            let report = pd::verify::check(&storage).await?;
 
-            let oblivious_server = tokio::spawn(
-                Server::builder()
-                    .trace_fn(|req| match remote_addr(req) {
-                        Some(remote_addr) => tracing::error_span!("oblivious_query", ?remote_addr),
-                        None => tracing::error_span!("oblivious_query"),
-                    })
-                    .add_service(ObliviousQueryServer::new(storage.clone()))
-                    .serve(
-                        format!("{}:{}", host, oblivious_query_port)
-                            .parse()
-                            .expect("this is a valid address"),
-                    ),
+            match report.version {
+                Some(version) => println!("checked state at version {}", version),
+                None => println!("database is empty"),
+            }
+            for skipped in &report.skipped {
+                println!("skipped: {}", skipped);
+            }
+            for problem in &report.problems {
+                println!("inconsistent: {}", problem);
+            }
+            if !report.is_consistent() {
+                return Err(anyhow::anyhow!(
+                    "found {} inconsistencies",
+                    report.problems.len()
+                ));
+            }
+            println!("state is consistent");
+        }
+        Command::VerifyStake {
+            rocks_path,
+            from,
+            to,
+        } => {
+            let storage = pd::Storage::load(rocks_path)
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+            let report = pd::verify::check_stake(&storage, from, to).await?;
+
+            match report.version {
+                Some(version) => println!("checked heights {} to {}", from, version),
+                None => println!("database is empty"),
+            }
+            for skipped in &report.skipped {
+                println!("skipped: {}", skipped);
+            }
+            for problem in &report.problems {
+                println!("violated: {}", problem);
+            }
+            if !report.is_consistent() {
+                return Err(anyhow::anyhow!(
+                    "found {} staking invariant violations",
+                    report.problems.len()
+                ));
+            }
+            println!("staking invariants hold");
+        }
+        Command::Replay {
+            rocks_path,
+            log,
+            from: None,
+            ..
+        } => {
+            let storage = pd::Storage::load(rocks_path)
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+            let entries = pd::replay::decode_log(bytes::Bytes::from(
+                std::fs::read(&log).with_context(|| format!("cannot read replay log {:?}", log))?,
+            ))?;
+
+            let replay = pd::replay::replay_log(storage, entries).await?;
+            for mismatch in &replay.mismatches {
+                println!(
+                    "entry {} ({} at height {}) differs:\n  recorded: {}\n  replayed: {}",
+                    mismatch.index,
+                    mismatch.request,
+                    mismatch.height,
+                    mismatch.recorded,
+                    mismatch.replayed
+                );
+            }
+            if let Some(height) = replay.app_hash_divergence() {
+                return Err(anyhow::anyhow!(
+                    "the app hash diverged from the recorded one at height {}",
+                    height
+                ));
+            }
+            println!(
+                "replayed {} requests through height {}: {} responses differ, the app hash never \
+                 does",
+                replay.entries,
+                replay.height,
+                replay.mismatches.len()
             );
-            let specific_server = tokio::spawn(
-                Server::builder()
-                    .trace_fn(|req| match remote_addr(req) {
-                        Some(remote_addr) => tracing::error_span!("specific_query", ?remote_addr),
-                        None => tracing::error_span!("specific_query"),
-                    })
-                    .add_service(SpecificQueryServer::new(storage.clone()))
-                    .serve(
-                        format!("{}:{}", host, specific_query_port)
-                            .parse()
-                            .expect("this is a valid address"),
-                    ),
+        }
+        Command::Replay {
+            rocks_path,
+            log,
+            from: Some(from),
+            to,
+            profile,
+        } => {
+            let to = to.expect("required with --from");
+            let storage = pd::Storage::load(rocks_path)
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+            let requests = pd::replay::decode_log(bytes::Bytes::from(
+                std::fs::read(&log).with_context(|| format!("cannot read replay log {:?}", log))?,
+            ))?
+            .into_iter()
+            .map(|(req, _)| req)
+            .collect();
+
+            if profile {
+                pd::profile::enable();
+            }
+            let blocks = pd::replay::replay(storage, requests, from, to).await?;
+
+            for block in blocks {
+                let valid = block.txs.iter().filter(|tx| tx.valid).count();
+                println!(
+                    "block {}: {} txs ({} valid), begin_block {:?}, end_block {:?}",
+                    block.height,
+                    block.txs.len(),
+                    valid,
+                    block.begin_block,
+                    block.end_block,
+                );
+                if profile {
+                    for tx in &block.txs {
+                        println!(
+                            "  tx {}: {:?}{}",
+                            tx.index,
+                            tx.duration,
+                            if tx.valid { "" } else { " (invalid)" }
+                        );
+                    }
+                    for ((component, phase), timing) in &block.report.timings {
+                        println!(
+                            "  {}::{}: {} calls, {:?} total",
+                            component, phase, timing.count, timing.total
+                        );
+                    }
+                    println!(
+                        "  state: {} reads, {} writes",
+                        block.report.reads, block.report.writes
+                    );
+                }
+            }
+        }
+        Command::Validators(ValidatorsCommand::Export {
+            rocks_path,
+            format,
+            target_port,
+        }) => {
+            let storage = pd::Storage::load(rocks_path)
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+            let entries = pd::address_book::entries(&storage).await?;
+            println!(
+                "{}",
+                pd::address_book::render(&entries, format, target_port)?
             );
+        }
+        Command::State(StateCommand::Keys {
+            rocks_path,
+            prefix,
+            height,
+            list,
+        }) => {
+            let storage = pd::Storage::load_read_only(rocks_path)
+                .await
+                .context("Unable to initialize RocksDB storage")?;
+            let version = match height {
+                Some(height) => height,
+                None => storage
+                    .latest_version()
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("the database has no state"))?,
+            };
+            let report =
+                pd::components::key_schema::report(&storage, version, prefix.as_deref()).await?;
+            print!("{}", pd::components::key_schema::render(&report, list));
+        }
+        Command::Peers(PeersCommand::Refresh {
+            from_url,
+            signer,
+            tendermint_home,
+        }) => {
+            let signer = pd::peers::parse_signer(&signer)?;
+            if pd::peers::refresh(&from_url, &signer, &tendermint_home).await? {
+                println!("rewrote the persistent peers; restart Tendermint to dial them");
+            } else {
+                println!("the persistent peers are already up to date");
+            }
+        }
+        Command::Peers(PeersCommand::Sign {
+            key_file,
+            chain_id,
+            peers,
+        }) => {
+            let key = pd::peers::read_signing_key(&key_file)?;
+            let list = pd::peers::SignedPeerList::sign(&key, chain_id, peers)?;
+            println!("{}", serde_json::to_string_pretty(&list)?);
+        }
+        Command::Testnet(TestnetCommand::Join {
+            node,
+            genesis_url,
+            peers_url,
+            peers_signer,
+            moniker,
+            abci_uds,
+            output_dir,
+        }) => {
+            use pd::testnet::{
+                canonicalize_path,
+                join::{Bootstrap, Sources},
+            };
 
-            // This service lets Prometheus pull metrics from `pd`
-            PrometheusBuilder::new()
-                .with_http_listener(
-                    format!("{}:{}", host, metrics_port)
-                        .parse::<SocketAddr>()
-                        .expect("this is a valid address"),
-                )
-                .install()
-                .expect("metrics service set up");
+            let peer_list = match (peers_url, peers_signer) {
+                (Some(url), Some(signer)) => Some((url, pd::peers::parse_signer(&signer)?)),
+                (Some(_), None) => {
+                    return Err(anyhow::anyhow!("--peers-url requires --peers-signer"))
+                }
+                (None, _) => None,
+            };
+            let bootstrap = Bootstrap::fetch(&Sources {
+                node,
+                genesis_url,
+                peer_list,
+            })
+            .await?;
 
-            pd::register_all_metrics();
+            let output_dir =
+                output_dir.unwrap_or_else(|| canonicalize_path("~/.penumbra/testnet_data/node0"));
+            let proxy_app = pd::testnet::proxy_app(abci_uds.as_deref());
+            bootstrap.write_node_dir(&output_dir, &moniker, &proxy_app)?;
 
-            // TODO: better error reporting
-            // We error out if either service errors, rather than keep running
-            tokio::select! {
-                x = abci_server => x?.map_err(|e| anyhow::anyhow!(e))?,
-                x = oblivious_server => x?.map_err(|e| anyhow::anyhow!(e))?,
-                x = specific_server => x?.map_err(|e| anyhow::anyhow!(e))?,
+            println!(
+                "Wrote a node for chain {} with {} peers to: {}",
+                bootstrap.chain_id,
+                bootstrap.peers.len(),
+                output_dir.display()
+            );
+            println!(
+                "Start it with `pd start --rocks-path {}` and `tendermint start --home {}`",
+                output_dir.join("pd").join("rocksdb").display(),
+                output_dir.join("tendermint").display()
+            );
+        }
+        Command::Keys(KeysCommand::Derive {
+            mnemonic,
+            role,
+            index,
+        }) => {
+            use pd::keys::Role;
+            use tendermint_config::{NodeKey, PrivValidatorKey};
+
+            let seed_phrase = mnemonic.parse::<SeedPhrase>()?;
+            let keys = NodeKeys::derive(seed_phrase, index);
+            let json = match role {
+                Role::Consensus => {
+                    let pub_key = keys.consensus_key.public_key();
+                    serde_json::to_string_pretty(&PrivValidatorKey {
+                        address: pub_key.into(),
+                        pub_key,
+                        priv_key: keys.consensus_key,
+                    })?
+                }
+                Role::Identity => {
+                    tracing::info!(identity_key = %keys.identity_key(), "derived identity");
+                    serde_json::to_string_pretty(&keys.spend_seed)?
+                }
+                Role::Node => serde_json::to_string_pretty(&NodeKey {
+                    priv_key: keys.node_key,
+                })?,
             };
+            println!("{}", json);
+        }
+        Command::GenerateConfig { output } => match output {
+            Some(path) => std::fs::write(&path, pd::config::template())
+                .with_context(|| format!("could not write {}", path.display()))?,
+            None => print!("{}", pd::config::template()),
+        },
+        Command::ProtoDescriptor { output } => match output {
+            Some(path) => std::fs::write(&path, penumbra_proto::FILE_DESCRIPTOR_SET)
+                .with_context(|| format!("could not write {}", path.display()))?,
+            None => {
+                use std::io::Write;
+                std::io::stdout().write_all(penumbra_proto::FILE_DESCRIPTOR_SET)?;
+            }
+        },
+        Command::Genesis(GenesisCommand::Validate { genesis_file }) => {
+            let genesis_json = std::fs::read(&genesis_file)
+                .with_context(|| format!("could not read {}", genesis_file.display()))?;
+            let dry_run = pd::genesis::dry_run(&genesis_json).await?;
+
+            println!("chain ID: {}", dry_run.chain_id);
+            println!("app hash: {}", hex::encode_upper(&dry_run.app_hash));
+            println!("validators:");
+            for validator in &dry_run.validators {
+                println!(
+                    "  {} (power {})",
+                    tendermint::account::Id::from(validator.pub_key),
+                    validator.power.value()
+                );
+            }
+        }
+        Command::Genesis(GenesisCommand::Hash { genesis_file }) => {
+            let genesis_json = std::fs::read(&genesis_file)
+                .with_context(|| format!("could not read {}", genesis_file.display()))?;
+            let (chain_id, app_state) = pd::genesis::parse(&genesis_json)?;
+
+            println!("chain ID: {}", chain_id);
+            println!("genesis hash: {}", hex::encode(app_state.hash()));
+        }
+        Command::Genesis(GenesisCommand::Diff {
+            old_genesis_file,
+            new_genesis_file,
+        }) => {
+            let mut app_states = Vec::new();
+            for genesis_file in [&old_genesis_file, &new_genesis_file] {
+                let genesis_json = std::fs::read(genesis_file)
+                    .with_context(|| format!("could not read {}", genesis_file.display()))?;
+                let (_, app_state) = pd::genesis::parse(&genesis_json)
+                    .with_context(|| format!("could not parse {}", genesis_file.display()))?;
+                app_states.push(app_state);
+            }
+            let diff = pd::genesis::diff(&app_states[0], &app_states[1])?;
+
+            if diff.is_empty() {
+                println!("the genesis app states are the same");
+            } else {
+                print!("{}", diff);
+            }
         }
         Command::GenerateTestnet {
             // TODO this config is gated on a "populate persistent peers"
@@ -207,7 +1512,9 @@ async fn main() -> anyhow::Result<()> {
             // works.
             starting_ip,
             epoch_duration,
+            epoch_duration_seconds,
             unbonding_epochs,
+            min_validator_bond_epochs,
             active_validator_limit,
             allocations_input_file,
             validators_input_file,
@@ -215,7 +1522,16 @@ async fn main() -> anyhow::Result<()> {
             chain_id,
             slashing_penalty,
             base_reward_rate,
+            base_reward_rate_decay,
+            min_base_reward_rate,
             preserve_chain_id,
+            features,
+            faucet_max_claim,
+            faucet_claim_interval,
+            faucet_claims_per_block,
+            max_validator_stake_share,
+            abci_uds,
+            fullnodes,
         } => {
             use std::{
                 fs,
@@ -303,18 +1619,28 @@ async fn main() -> anyhow::Result<()> {
                 })?
             };
 
+            check_validator_stakes(&testnet_validators, active_validator_limit)?;
+            let node_hosts = node_hosts(&testnet_validators, fullnodes, starting_ip)?;
+            let (validator_hosts, fullnode_hosts) = node_hosts.split_at(testnet_validators.len());
+
+            check_imported_keys(&testnet_validators)?;
+
             struct ValidatorKeys {
-                // Penumbra spending key and viewing key for this node.
-                pub validator_id_sk: SigningKey<SpendAuth>,
-                pub validator_id_vk: VerificationKey<SpendAuth>,
-                // Consensus key for tendermint.
-                pub validator_cons_sk: tendermint::PrivateKey,
+                // Penumbra identity key for this node, and its signing key,
+                // unless the validator imported it.
+                pub identity_key: IdentityKey,
+                pub validator_id_sk: Option<SigningKey<SpendAuth>>,
+                // Consensus key for tendermint, and its private key, unless
+                // the validator imported it.
                 pub validator_cons_pk: tendermint::PublicKey,
+                pub validator_cons_sk: Option<tendermint::PrivateKey>,
                 // P2P auth key for tendermint.
                 pub node_key_sk: tendermint::PrivateKey,
                 #[allow(unused_variables, dead_code)]
                 pub node_key_pk: tendermint::PublicKey,
                 pub validator_spendseed: SpendSeed,
+                // The seed phrase all of the generated keys are derived from.
+                pub validator_seed_phrase: String,
             }
             let mut validator_keys = Vec::<ValidatorKeys>::new();
             // Generate a keypair for each validator
@@ -323,69 +1649,76 @@ async fn main() -> anyhow::Result<()> {
                 num_validator_nodes > 0,
                 "must have at least one validator node"
             );
-            for _ in 0..num_validator_nodes {
+            for testnet_validator in &testnet_validators {
+                // Derive all of this node's keys from a fresh seed phrase, so
+                // that they can be restored from it with `pd keys derive`.
+                let seed_phrase = SeedPhrase::generate(OsRng);
+                let validator_seed_phrase = seed_phrase.to_string();
+                let NodeKeys {
+                    spend_seed: seed,
+                    consensus_key: validator_cons_sk,
+                    node_key: node_key_sk,
+                } = NodeKeys::derive(seed_phrase, 0);
+
                 // Create the spend key for this node.
-                let seed = SpendSeed(OsRng.gen());
                 let spend_key = SpendKey::from(seed.clone());
 
-                // Create signing key and verification key for this node.
-                let validator_id_sk = spend_key.spend_auth_key();
-                let validator_id_vk = VerificationKey::from(validator_id_sk);
+                // Use the validator's own identity key, if it has one, or
+                // else the one for this node's spend key.
+                let (identity_key, validator_id_sk) = match &testnet_validator.identity_key {
+                    Some(identity_key) => (identity_key.clone(), None),
+                    None => {
+                        let validator_id_sk = spend_key.spend_auth_key();
+                        (
+                            IdentityKey(VerificationKey::from(validator_id_sk)),
+                            Some(validator_id_sk.clone()),
+                        )
+                    }
+                };
 
-                // generate consensus key for tendermint.
-                let validator_cons_sk =
-                    tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(OsRng));
-                let validator_cons_pk = validator_cons_sk.public_key();
+                // Consensus key for tendermint, likewise.
+                let (validator_cons_pk, validator_cons_sk) = match testnet_validator.consensus_key {
+                    Some(validator_cons_pk) => (validator_cons_pk, None),
+                    None => (validator_cons_sk.public_key(), Some(validator_cons_sk)),
+                };
 
-                // generate P2P auth key for tendermint.
-                let node_key_sk =
-                    tendermint::PrivateKey::Ed25519(ed25519_consensus::SigningKey::new(OsRng));
+                // P2P auth key for tendermint.
                 let node_key_pk = node_key_sk.public_key();
 
-                let vk = ValidatorKeys {
-                    validator_id_sk: validator_id_sk.clone(),
-                    validator_id_vk,
-                    validator_cons_sk,
-                    validator_cons_pk,
-                    node_key_sk,
-                    node_key_pk,
-                    validator_spendseed: seed,
-                };
-
                 let fvk = spend_key.full_viewing_key();
                 let ivk = fvk.incoming();
                 let (dest, _dtk_d) = ivk.payment_address(0u64.into());
 
-                // Add a default 1 upenumbra allocation to the validator.
-                let identity_key: IdentityKey = IdentityKey(fvk.spend_verification_key().clone());
+                // Allocate the validator its self-delegation, in its own
+                // delegation tokens, to this node's wallet.
                 let delegation_denom = identity_key.delegation_token().denom();
                 allocations.push(Allocation {
                     address: dest,
-                    amount: 1_000_000, // 1e6 udelegation tokens
+                    amount: testnet_validator.self_delegation,
                     denom: delegation_denom.to_string(),
                 });
 
+                let vk = ValidatorKeys {
+                    identity_key,
+                    validator_id_sk,
+                    validator_cons_pk,
+                    validator_cons_sk,
+                    node_key_sk,
+                    node_key_pk,
+                    validator_spendseed: seed,
+                    validator_seed_phrase,
+                };
+
                 validator_keys.push(vk);
             }
 
-            let ip_addrs = validator_keys
-                .iter()
-                .enumerate()
-                .map(|(i, _vk)| {
-                    let a = starting_ip.octets();
-                    Ipv4Addr::new(a[0], a[1], a[2], a[3] + (10 * i as u8))
-                })
-                .collect::<Vec<_>>();
             let validators = testnet_validators
                 .iter()
                 .enumerate()
                 .map(|(i, v)| {
                     let vk = &validator_keys[i];
                     Ok(Validator {
-                        // Currently there's no way to set validator keys beyond
-                        // manually editing the genesis.json. Otherwise they
-                        // will be randomly generated keys.
-                        identity_key: IdentityKey(vk.validator_id_vk),
+                        identity_key: vk.identity_key.clone(),
                         consensus_key: vk.validator_cons_pk,
                         name: v.name.clone(),
                         website: v.website.clone(),
@@ -414,24 +1747,73 @@ async fn main() -> anyhow::Result<()> {
                     })
                 })
                 .collect::<Result<Vec<Validator>, anyhow::Error>>()?;
-            for (n, vk) in validator_keys.iter().enumerate() {
-                let node_name = format!("node{}", n);
+            let app_state = genesis::AppState {
+                allocations,
+                chain_params: ChainParams {
+                    chain_id: chain_id.clone(),
+                    epoch_duration,
+                    epoch_duration_seconds,
+                    unbonding_epochs,
+                    min_validator_bond_epochs,
+                    active_validator_limit,
+                    slashing_penalty,
+                    base_reward_rate,
+                    base_reward_rate_decay,
+                    min_base_reward_rate,
+                    ibc_enabled: false,
+                    inbound_ics20_transfers_enabled: false,
+                    outbound_ics20_transfers_enabled: false,
+                    features,
+                    faucet_max_claim,
+                    faucet_claim_interval,
+                    faucet_claims_per_block,
+                    max_validator_stake_share,
+                },
+                validators,
+                base_rate_data: None,
+                validator_rates: Vec::new(),
+            };
 
-                let app_state = genesis::AppState {
-                    allocations: allocations.clone(),
-                    chain_params: ChainParams {
-                        chain_id: chain_id.clone(),
-                        epoch_duration,
-                        unbonding_epochs,
-                        active_validator_limit,
-                        slashing_penalty,
-                        base_reward_rate,
-                        ibc_enabled: false,
-                        inbound_ics20_transfers_enabled: false,
-                        outbound_ics20_transfers_enabled: false,
+            // The tendermint genesis, the same for every node.
+            let testnet_genesis = Genesis {
+                genesis_time,
+                chain_id: chain_id
+                    .parse::<tendermint::chain::Id>()
+                    .expect("able to create chain ID"),
+                initial_height: 0,
+                consensus_params: tendermint::consensus::Params {
+                    block: tendermint::block::Size {
+                        max_bytes: 22020096,
+                        max_gas: -1,
+                        // minimum time increment between consecutive blocks
+                        time_iota_ms: 500,
                     },
-                    validators: validators.clone(),
-                };
+                    // TODO Should these correspond with values used within `pd` for penumbra epochs?
+                    evidence: tendermint::evidence::Params {
+                        max_age_num_blocks: 100000,
+                        // 1 day
+                        max_age_duration: tendermint::evidence::Duration(Duration::new(86400, 0)),
+                        max_bytes: 1048576,
+                    },
+                    validator: tendermint::consensus::params::ValidatorParams {
+                        pub_key_types: vec![Algorithm::Ed25519],
+                    },
+                    version: Some(tendermint::consensus::params::VersionParams { app_version: 0 }),
+                },
+                // always empty in genesis json
+                app_hash: vec![],
+                app_state,
+                // List of initial validators. Note this may be overridden entirely by
+                // the application, and may be left empty to make explicit that the
+                // application will initialize the validator set with ResponseInitChain.
+                // - https://docs.tendermint.com/v0.32/tendermint-core/using-tendermint.html
+                // For penumbra, we can leave this empty since the app_state also contains Validator
+                // configs.
+                validators: vec![],
+            };
+            let proxy_app = pd::testnet::proxy_app(abci_uds.as_deref());
+            for (n, vk) in validator_keys.iter().enumerate() {
+                let node_name = format!("node{}", n);
 
                 // Create the directory for this node
                 let mut node_dir = output_dir.clone();
@@ -454,46 +1836,6 @@ async fn main() -> anyhow::Result<()> {
                 fs::create_dir_all(&pd_dir)?;
 
                 // Write this node's tendermint genesis.json file
-                let validator_genesis = Genesis {
-                    genesis_time,
-                    chain_id: chain_id
-                        .parse::<tendermint::chain::Id>()
-                        .expect("able to create chain ID"),
-                    initial_height: 0,
-                    consensus_params: tendermint::consensus::Params {
-                        block: tendermint::block::Size {
-                            max_bytes: 22020096,
-                            max_gas: -1,
-                            // minimum time increment between consecutive blocks
-                            time_iota_ms: 500,
-                        },
-                        // TODO Should these correspond with values used within `pd` for penumbra epochs?
-                        evidence: tendermint::evidence::Params {
-                            max_age_num_blocks: 100000,
-                            // 1 day
-                            max_age_duration: tendermint::evidence::Duration(Duration::new(
-                                86400, 0,
-                            )),
-                            max_bytes: 1048576,
-                        },
-                        validator: tendermint::consensus::params::ValidatorParams {
-                            pub_key_types: vec![Algorithm::Ed25519],
-                        },
-                        version: Some(tendermint::consensus::params::VersionParams {
-                            app_version: 0,
-                        }),
-                    },
-                    // always empty in genesis json
-                    app_hash: vec![],
-                    app_state,
-                    // List of initial validators. Note this may be overridden entirely by
-                    // the application, and may be left empty to make explicit that the
-                    // application will initialize the validator set with ResponseInitChain.
-                    // - https://docs.tendermint.com/v0.32/tendermint-core/using-tendermint.html
-                    // For penumbra, we can leave this empty since the app_state also contains Validator
-                    // configs.
-                    validators: vec![],
-                };
                 let mut genesis_file_path = node_config_dir.clone();
                 genesis_file_path.push("genesis.json");
                 println!(
@@ -503,27 +1845,26 @@ async fn main() -> anyhow::Result<()> {
                 );
                 let mut genesis_file = File::create(genesis_file_path)?;
                 genesis_file
-                    .write_all(serde_json::to_string_pretty(&validator_genesis)?.as_bytes())?;
+                    .write_all(serde_json::to_string_pretty(&testnet_genesis)?.as_bytes())?;
 
                 // Write this node's config.toml
                 // Note that this isn't a re-implementation of the `Config` type from
                 // Tendermint (https://github.com/tendermint/tendermint/blob/6291d22f46f4c4f9121375af700dbdafa51577e7/config/config.go#L92)
                 // so if they change their defaults or the available fields, that won't be reflected in our template.
                 // TODO: grab all peer pubkeys instead of self pubkey
-                let my_ip = &ip_addrs[n];
-                // Each node should include only the IPs for *other* nodes in their peers list.
-                let ips_minus_mine = ip_addrs
+                // Each node should include only the hosts of *other* nodes in their peers list.
+                let hosts_minus_mine = validator_hosts
                     .iter()
                     .enumerate()
-                    .filter(|(_, p)| *p != my_ip)
-                    .map(|(n, ip)| {
+                    .filter(|(i, _)| *i != n)
+                    .map(|(i, host)| {
                         (
-                            node::Id::from(validator_keys[n].node_key_pk.ed25519().unwrap()),
-                            *ip,
+                            node::Id::from(validator_keys[i].node_key_pk.ed25519().unwrap()),
+                            host.clone(),
                         )
                     })
                     .collect::<Vec<_>>();
-                let tm_config = generate_tm_config(&node_name, &ips_minus_mine);
+                let tm_config = generate_tm_config(&node_name, &proxy_app, &hosts_minus_mine);
                 let mut config_file_path = node_config_dir.clone();
                 config_file_path.push("config.toml");
                 println!(
@@ -550,28 +1891,41 @@ async fn main() -> anyhow::Result<()> {
                 let mut node_key_file = File::create(node_key_file_path)?;
                 node_key_file.write_all(serde_json::to_string_pretty(&node_key)?.as_bytes())?;
 
-                // Write this node's priv_validator_key.json
-                let address: Id = vk.validator_cons_pk.into();
+                // Write this node's priv_validator_key.json, unless its
+                // consensus key was imported, so that its private key is held
+                // elsewhere.
+                match &vk.validator_cons_sk {
+                    Some(validator_cons_sk) => {
+                        let address: Id = vk.validator_cons_pk.into();
 
-                // the underlying type doesn't implement Copy or Clone (for the best)
-                let priv_key = tendermint::PrivateKey::Ed25519(
-                    vk.validator_cons_sk.ed25519_signing_key().unwrap().clone(),
-                );
-                let priv_validator_key = PrivValidatorKey {
-                    address,
-                    pub_key: vk.validator_cons_pk,
-                    priv_key,
-                };
-                let mut priv_validator_key_file_path = node_config_dir.clone();
-                priv_validator_key_file_path.push("priv_validator_key.json");
-                println!(
-                    "Writing {} priv validator key file to: {}",
-                    &node_name,
-                    priv_validator_key_file_path.display()
-                );
-                let mut priv_validator_key_file = File::create(priv_validator_key_file_path)?;
-                priv_validator_key_file
-                    .write_all(serde_json::to_string_pretty(&priv_validator_key)?.as_bytes())?;
+                        // the underlying type doesn't implement Copy or Clone (for the best)
+                        let priv_key = tendermint::PrivateKey::Ed25519(
+                            validator_cons_sk.ed25519_signing_key().unwrap().clone(),
+                        );
+                        let priv_validator_key = PrivValidatorKey {
+                            address,
+                            pub_key: vk.validator_cons_pk,
+                            priv_key,
+                        };
+                        let mut priv_validator_key_file_path = node_config_dir.clone();
+                        priv_validator_key_file_path.push("priv_validator_key.json");
+                        println!(
+                            "Writing {} priv validator key file to: {}",
+                            &node_name,
+                            priv_validator_key_file_path.display()
+                        );
+                        let mut priv_validator_key_file =
+                            File::create(priv_validator_key_file_path)?;
+                        priv_validator_key_file.write_all(
+                            serde_json::to_string_pretty(&priv_validator_key)?.as_bytes(),
+                        )?;
+                    }
+                    None => println!(
+                        "Not writing a {} priv validator key file: its consensus key was \
+                         imported, so configure the node to sign with it",
+                        &node_name
+                    ),
+                }
 
                 // Write the initial validator state:
                 let mut priv_validator_state_file_path = node_data_dir.clone();
@@ -584,29 +1938,107 @@ async fn main() -> anyhow::Result<()> {
                 let mut priv_validator_state_file = File::create(priv_validator_state_file_path)?;
                 priv_validator_state_file.write_all(get_validator_state().as_bytes())?;
 
-                // Write the validator's signing key:
-                let mut validator_signingkey_file_path = node_config_dir.clone();
-                validator_signingkey_file_path.push("validator_signingkey.json");
+                // Write the validator's signing key and spend seed, unless its
+                // identity key was imported, so that they do not match it.
+                if let Some(validator_id_sk) = &vk.validator_id_sk {
+                    let mut validator_signingkey_file_path = node_config_dir.clone();
+                    validator_signingkey_file_path.push("validator_signingkey.json");
+                    println!(
+                        "Writing {} validator signing key file to: {}",
+                        &node_name,
+                        validator_signingkey_file_path.display()
+                    );
+                    let mut validator_signingkey_file =
+                        File::create(validator_signingkey_file_path)?;
+                    validator_signingkey_file
+                        .write_all(serde_json::to_string_pretty(validator_id_sk)?.as_bytes())?;
+
+                    let mut validator_spendseed_file_path = node_config_dir.clone();
+                    validator_spendseed_file_path.push("validator_spendseed.json");
+                    println!(
+                        "Writing {} validator spend seed file to: {}",
+                        &node_name,
+                        validator_spendseed_file_path.display()
+                    );
+                    let mut validator_spendseed_file = File::create(validator_spendseed_file_path)?;
+                    validator_spendseed_file.write_all(
+                        serde_json::to_string_pretty(&vk.validator_spendseed)?.as_bytes(),
+                    )?;
+                } else {
+                    println!(
+                        "Not writing {} validator signing key or spend seed files: its identity \
+                         key was imported",
+                        &node_name
+                    );
+                }
+
+                // Write the seed phrase all of the generated keys are derived from:
+                let mut validator_seed_phrase_file_path = node_config_dir.clone();
+                validator_seed_phrase_file_path.push("validator_seed_phrase.txt");
                 println!(
-                    "Writing {} validator signing key file to: {}",
+                    "Writing {} validator seed phrase file to: {}",
                     &node_name,
-                    validator_signingkey_file_path.display()
+                    validator_seed_phrase_file_path.display()
                 );
-                let mut validator_signingkey_file = File::create(validator_signingkey_file_path)?;
-                validator_signingkey_file
-                    .write_all(serde_json::to_string_pretty(&vk.validator_id_sk)?.as_bytes())?;
+                let mut validator_seed_phrase_file = File::create(validator_seed_phrase_file_path)?;
+                validator_seed_phrase_file.write_all(vk.validator_seed_phrase.as_bytes())?;
 
-                // Write the validator's spend seed:
-                let mut validator_spendseed_file_path = node_config_dir.clone();
-                validator_spendseed_file_path.push("validator_spendseed.json");
+                println!("-------------------------------------");
+            }
+
+            // Full nodes peer with every validator, and have no consensus key:
+            // Tendermint generates a priv_validator_key.json it never signs
+            // with, if none is present.
+            let validator_peers = validator_hosts
+                .iter()
+                .zip(&validator_keys)
+                .map(|(host, vk)| {
+                    (
+                        node::Id::from(vk.node_key_pk.ed25519().unwrap()),
+                        host.clone(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            for (n, host) in fullnode_hosts.iter().enumerate() {
+                let node_name = format!("fullnode{}", n);
+
+                let node_dir = output_dir.join(&node_name);
+                let node_config_dir = node_dir.join("tendermint").join("config");
+                fs::create_dir_all(&node_config_dir)?;
+                fs::create_dir_all(node_dir.join("tendermint").join("data"))?;
+                fs::create_dir_all(node_dir.join("pd"))?;
+
+                let genesis_file_path = node_config_dir.join("genesis.json");
                 println!(
-                    "Writing {} validator spend seed file to: {}",
+                    "Writing {} genesis file to: {}",
                     &node_name,
-                    validator_spendseed_file_path.display()
+                    genesis_file_path.display()
+                );
+                File::create(genesis_file_path)?
+                    .write_all(serde_json::to_string_pretty(&testnet_genesis)?.as_bytes())?;
+
+                let config_file_path = node_config_dir.join("config.toml");
+                println!(
+                    "Writing {} config file to: {} (listening at {})",
+                    &node_name,
+                    config_file_path.display(),
+                    host
+                );
+                File::create(config_file_path)?.write_all(
+                    generate_tm_config(&node_name, &proxy_app, &validator_peers).as_bytes(),
+                )?;
+
+                let node_key = NodeKey {
+                    priv_key: NodeKeys::derive(SeedPhrase::generate(OsRng), 0).node_key,
+                };
+                let node_key_file_path = node_config_dir.join("node_key.json");
+                println!(
+                    "Writing {} node key file to: {}",
+                    &node_name,
+                    node_key_file_path.display()
                 );
-                let mut validator_spendseed_file = File::create(validator_spendseed_file_path)?;
-                validator_spendseed_file
-                    .write_all(serde_json::to_string_pretty(&vk.validator_spendseed)?.as_bytes())?;
+                File::create(node_key_file_path)?
+                    .write_all(serde_json::to_string_pretty(&node_key)?.as_bytes())?;
 
                 println!("-------------------------------------");
             }
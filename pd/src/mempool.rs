@@ -1,7 +1,9 @@
 mod message;
+mod pending;
 mod service;
 mod worker;
 
 use message::Message;
+pub use pending::PendingTx;
 pub use service::Mempool;
 use worker::Worker;
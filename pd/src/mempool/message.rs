@@ -6,6 +6,10 @@ use tracing::Span;
 #[derive(Debug)]
 pub struct Message {
     pub tx_bytes: Bytes,
-    pub rsp_sender: oneshot::Sender<Result<()>>,
+    /// Whether Tendermint is rechecking a transaction it already holds, after
+    /// a block was committed, rather than checking a new one.
+    pub recheck: bool,
+    /// Receives the transaction's priority, if it was accepted.
+    pub rsp_sender: oneshot::Sender<Result<i64>>,
     pub span: Span,
 }
@@ -0,0 +1,170 @@
+use std::{collections::BTreeMap, time::SystemTime};
+
+use bytes::Bytes;
+use penumbra_crypto::Nullifier;
+use penumbra_transaction::Transaction;
+
+/// A transaction accepted by the mempool's `CheckTx`, and still valid against
+/// the latest committed state.
+#[derive(Clone, Debug)]
+pub struct PendingTx {
+    /// The transaction hash, as reported by Tendermint.
+    pub id: [u8; 32],
+    /// The encoded size of the transaction, in bytes.
+    pub size: usize,
+    pub fee: u64,
+    /// When the transaction was first accepted, surviving rechecks after each block.
+    pub first_seen: SystemTime,
+    pub nullifiers: Vec<Nullifier>,
+}
+
+/// Tracks the transactions accepted by the mempool.
+///
+/// After every block, the mempool rechecks the transactions it accepted
+/// against the new state, and evicts those which are no longer valid or have
+/// been pending for too long. Tendermint then rechecks every transaction that
+/// wasn't included, and is told which were evicted, so that it drops them.
+#[derive(Debug, Default)]
+pub(super) struct PendingTxs {
+    current: BTreeMap<[u8; 32], (PendingTx, Bytes)>,
+    /// Why each transaction evicted by the last recheck was evicted.
+    evicted: BTreeMap<[u8; 32], String>,
+}
+
+impl PendingTxs {
+    pub fn accept(&mut self, tx: &Transaction, tx_bytes: Bytes, first_seen: SystemTime) {
+        let id = tx.id();
+        self.current.insert(
+            id,
+            (
+                PendingTx {
+                    id,
+                    size: tx_bytes.len(),
+                    fee: tx.transaction_body.fee.0,
+                    first_seen,
+                    nullifiers: tx.spent_nullifiers(),
+                },
+                tx_bytes,
+            ),
+        );
+    }
+
+    pub fn contains(&self, id: &[u8; 32]) -> bool {
+        self.current.contains_key(id)
+    }
+
+    /// Why the transaction was evicted by the last recheck, if it was.
+    pub fn eviction(&self, id: &[u8; 32]) -> Option<&str> {
+        self.evicted.get(id).map(String::as_str)
+    }
+
+    pub fn evict(&mut self, id: [u8; 32], reason: String) {
+        self.evicted.insert(id, reason);
+    }
+
+    /// Removes every accepted transaction, oldest first, to be rechecked and
+    /// either accepted again or evicted.
+    pub fn take_for_recheck(&mut self) -> Vec<(PendingTx, Bytes)> {
+        self.evicted.clear();
+        let mut txs = std::mem::take(&mut self.current)
+            .into_values()
+            .collect::<Vec<_>>();
+        txs.sort_by_key(|(tx, _)| tx.first_seen);
+        txs
+    }
+
+    /// The currently accepted transactions, oldest first.
+    pub fn snapshot(&self) -> Vec<PendingTx> {
+        let mut txs = self
+            .current
+            .values()
+            .map(|(tx, _)| tx.clone())
+            .collect::<Vec<_>>();
+        txs.sort_by_key(|tx| tx.first_seen);
+        txs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use penumbra_crypto::{merkle, rdsa::Signature};
+    use penumbra_proto::Protobuf;
+    use penumbra_transaction::{Fee, TransactionBody};
+
+    use super::*;
+
+    /// An (invalid) transaction, distinguished by its fee.
+    fn transaction(fee: u64) -> (Transaction, Bytes) {
+        let tx = Transaction {
+            transaction_body: TransactionBody {
+                actions: Vec::new(),
+                merkle_root: merkle::Root(Default::default()),
+                expiry_height: 0,
+                chain_id: String::new(),
+                fee: Fee(fee),
+            },
+            binding_sig: Signature::from([0; 64]),
+        };
+        let bytes = tx.encode_to_vec().into();
+        (tx, bytes)
+    }
+
+    fn seen_at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn fees(txs: &[PendingTx]) -> Vec<u64> {
+        txs.iter().map(|tx| tx.fee).collect()
+    }
+
+    #[test]
+    fn lists_accepted_transactions_oldest_first() {
+        let mut pending = PendingTxs::default();
+        for (fee, seconds) in [(1, 30), (2, 10), (3, 20)] {
+            let (tx, bytes) = transaction(fee);
+            pending.accept(&tx, bytes, seen_at(seconds));
+        }
+
+        let snapshot = pending.snapshot();
+        assert_eq!(fees(&snapshot), vec![2, 3, 1]);
+        let (tx, bytes) = transaction(2);
+        assert_eq!(snapshot[0].id, tx.id());
+        assert_eq!(snapshot[0].size, bytes.len());
+        assert!(pending.contains(&tx.id()));
+    }
+
+    #[test]
+    fn recheck_keeps_first_seen_and_forgets_old_evictions() {
+        let mut pending = PendingTxs::default();
+        let (kept, kept_bytes) = transaction(1);
+        let (evicted, evicted_bytes) = transaction(2);
+        pending.accept(&kept, kept_bytes, seen_at(20));
+        pending.accept(&evicted, evicted_bytes, seen_at(10));
+
+        // Every transaction is taken out to be rechecked, oldest first...
+        let rechecked = pending.take_for_recheck();
+        assert_eq!(
+            rechecked.iter().map(|(tx, _)| tx.fee).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert!(pending.snapshot().is_empty());
+
+        // ... and either accepted again, keeping when it was first seen, or evicted.
+        for (tx, bytes) in rechecked {
+            if tx.id == kept.id() {
+                pending.accept(&kept, bytes, tx.first_seen);
+            } else {
+                pending.evict(tx.id, "expired".to_string());
+            }
+        }
+        assert_eq!(pending.snapshot()[0].first_seen, seen_at(20));
+        assert!(!pending.contains(&evicted.id()));
+        assert_eq!(pending.eviction(&evicted.id()), Some("expired"));
+
+        // The next recheck starts over.
+        pending.take_for_recheck();
+        assert_eq!(pending.eviction(&evicted.id()), None);
+    }
+}
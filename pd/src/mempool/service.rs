@@ -2,13 +2,15 @@ use std::{
     future::Future,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use futures::FutureExt;
 use tendermint::{
     abci::{
-        request::CheckTx as CheckTxReq, response::CheckTx as CheckTxRsp, MempoolRequest,
-        MempoolResponse,
+        request::{CheckTx as CheckTxReq, CheckTxKind},
+        response::CheckTx as CheckTxRsp,
+        MempoolRequest, MempoolResponse,
     },
     block,
 };
@@ -16,27 +18,51 @@ use tokio::sync::{mpsc, oneshot, watch};
 use tokio_util::sync::PollSender;
 use tower_abci::BoxError;
 
-use super::{Message, Worker};
-use crate::{RequestExt, Storage};
+use super::{Message, PendingTx, Worker};
+use crate::{NullifierCache, RequestExt, Storage};
 
 #[derive(Clone)]
 pub struct Mempool {
     queue: PollSender<Message>,
+    pending_rx: watch::Receiver<Vec<PendingTx>>,
 }
 
 impl Mempool {
+    /// Starts the mempool, which rechecks its transactions after each block
+    /// received on `height_rx`, evicting those which have become invalid, or
+    /// have been pending for longer than `ttl`, if set.
+    ///
+    /// Transactions spending nullifiers claimed in `nullifiers` by other
+    /// transactions, or declaring a fee lower than `min_fee`, are rejected.
     pub async fn new(
         storage: Storage,
         height_rx: watch::Receiver<block::Height>,
+        nullifiers: NullifierCache,
+        ttl: Option<Duration>,
+        min_fee: u64,
     ) -> anyhow::Result<Self> {
         let (queue_tx, queue_rx) = mpsc::channel(10);
+        let (pending_tx, pending_rx) = watch::channel(Vec::new());
 
-        tokio::spawn(Worker::new(storage, queue_rx, height_rx).await?.run());
+        tokio::spawn(
+            Worker::new(
+                storage, queue_rx, height_rx, pending_tx, nullifiers, ttl, min_fee,
+            )
+            .await?
+            .run(),
+        );
 
         Ok(Self {
             queue: PollSender::new(queue_tx),
+            pending_rx,
         })
     }
+
+    /// Returns a receiver for the transactions currently accepted by `CheckTx`,
+    /// updated whenever a transaction is accepted or the mempool is rechecked.
+    pub fn pending_txs(&self) -> watch::Receiver<Vec<PendingTx>> {
+        self.pending_rx.clone()
+    }
 }
 
 impl tower::Service<MempoolRequest> for Mempool {
@@ -52,11 +78,12 @@ impl tower::Service<MempoolRequest> for Mempool {
         let span = req.create_span();
         let (tx, rx) = oneshot::channel();
 
-        let MempoolRequest::CheckTx(CheckTxReq { tx: tx_bytes, .. }) = req;
+        let MempoolRequest::CheckTx(CheckTxReq { tx: tx_bytes, kind }) = req;
 
         self.queue
             .send_item(Message {
                 tx_bytes,
+                recheck: matches!(kind, CheckTxKind::Recheck),
                 rsp_sender: tx,
                 span,
             })
@@ -64,7 +91,10 @@ impl tower::Service<MempoolRequest> for Mempool {
 
         async move {
             match rx.await.expect("worker error??") {
-                Ok(()) => Ok(MempoolResponse::CheckTx(CheckTxRsp::default())),
+                Ok(priority) => Ok(MempoolResponse::CheckTx(CheckTxRsp {
+                    priority,
+                    ..Default::default()
+                })),
                 Err(e) => Ok(MempoolResponse::CheckTx(CheckTxRsp {
                     code: 1,
                     log: e.to_string(),
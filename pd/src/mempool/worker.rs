@@ -1,20 +1,32 @@
-use anyhow::Result;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Result};
 use bytes::Bytes;
 
+use metrics::{counter, gauge, histogram};
 use penumbra_proto::Protobuf;
 use penumbra_transaction::Transaction;
 use tendermint::block;
 use tokio::sync::{mpsc, watch};
 use tracing::Instrument;
 
-use super::Message;
-use crate::{App, Component, Storage};
+use super::{pending::PendingTxs, Message, PendingTx};
+use crate::{App, Component, NullifierCache, Storage};
 
 pub struct Worker {
     queue: mpsc::Receiver<Message>,
     storage: Storage,
     app: App,
     height_rx: watch::Receiver<block::Height>,
+    pending: PendingTxs,
+    pending_tx: watch::Sender<Vec<PendingTx>>,
+    /// Claims the nullifiers of the pending transactions, and holds those
+    /// claimed by the block being executed.
+    nullifiers: NullifierCache,
+    /// How long a transaction may stay in the mempool, if limited.
+    ttl: Option<Duration>,
+    /// The lowest fee a transaction may declare.
+    min_fee: u64,
 }
 
 impl Worker {
@@ -22,6 +34,10 @@ impl Worker {
         storage: Storage,
         queue: mpsc::Receiver<Message>,
         height_rx: watch::Receiver<block::Height>,
+        pending_tx: watch::Sender<Vec<PendingTx>>,
+        nullifiers: NullifierCache,
+        ttl: Option<Duration>,
+        min_fee: u64,
     ) -> Result<Self> {
         let app = App::new(storage.overlay().await?).await?;
 
@@ -30,6 +46,11 @@ impl Worker {
             storage,
             app,
             height_rx,
+            pending: Default::default(),
+            pending_tx,
+            nullifiers,
+            ttl,
+            min_fee,
         })
     }
 
@@ -38,26 +59,142 @@ impl Worker {
     /// perform the stateful checks in the worker, and have a frontend service
     /// that performs the stateless checks.  However, this probably isn't
     /// important to do until we know that it's a bottleneck.
-    async fn check_and_execute_tx(&mut self, tx_bytes: Bytes) -> Result<()> {
+    ///
+    /// Returns the priority of the transaction, if it is accepted.
+    async fn check_and_execute_tx(&mut self, tx_bytes: Bytes, recheck: bool) -> Result<i64> {
         let tx = Transaction::decode(tx_bytes.as_ref())?;
-        App::check_tx_stateless(&tx)?;
-        self.app.check_tx_stateful(&tx).await?;
-        self.app.execute_tx(&tx).await?;
+        let priority = priority(&tx);
+
+        // Transactions the mempool has already rechecked against the latest
+        // state have been executed on it, so must not be executed again.
+        let id = tx.id();
+        if self.pending.contains(&id) {
+            return Ok(priority);
+        }
+        if recheck {
+            if let Some(reason) = self.pending.eviction(&id) {
+                tracing::debug!(id = %hex::encode(id), %reason, "evicting transaction");
+                counter!("node_mempool_evicted_txs_total", 1);
+                return Err(anyhow!("evicted from the mempool: {}", reason));
+            }
+        }
+
+        let fee = tx.transaction_body.fee.0;
+        if fee < self.min_fee {
+            counter!("node_mempool_low_fee_txs_total", 1);
+            return Err(anyhow!(
+                "fee of {} is below the mempool's minimum fee of {}",
+                fee,
+                self.min_fee
+            ));
+        }
+
+        // The mempool's state already reflects the pending transactions, but
+        // not those delivered in the block being executed.
+        let nullifiers = tx.spent_nullifiers();
+        if let Some((nullifier, claimant)) = self.nullifiers.conflict(&id, &nullifiers) {
+            counter!("node_mempool_conflicting_txs_total", 1);
+            return Err(anyhow!(
+                "nullifier {} is already spent by uncommitted transaction {}",
+                nullifier,
+                hex::encode(claimant)
+            ));
+        }
+
+        self.execute_tx(&tx).await?;
+        self.nullifiers.claim_pending(id, &nullifiers);
+        self.pending.accept(&tx, tx_bytes, SystemTime::now());
+        self.publish_pending();
+        Ok(priority)
+    }
+
+    /// Publishes the pending transactions, and their number and size as
+    /// metrics.
+    fn publish_pending(&self) {
+        let pending = self.pending.snapshot();
+        gauge!("node_mempool_pending_txs", pending.len() as f64);
+        gauge!(
+            "node_mempool_pending_bytes",
+            pending.iter().map(|tx| tx.size).sum::<usize>() as f64
+        );
+        let _ = self.pending_tx.send(pending);
+    }
+
+    async fn execute_tx(&mut self, tx: &Transaction) -> Result<()> {
+        let started = Instant::now();
+        App::check_tx_stateless(tx)?;
+        self.app.check_tx_stateful(tx).await?;
+        histogram!(
+            "node_tx_verification_duration_seconds",
+            started.elapsed(),
+            "path" => "check_tx"
+        );
+        self.app.execute_tx(tx).await?;
         Ok(())
     }
 
+    /// Throws away the ephemeral mempool state, creates a new one based on the
+    /// latest committed state, and re-executes the pending transactions on
+    /// it, oldest first, evicting those which are no longer valid or have
+    /// outlived the TTL.
+    async fn recheck(&mut self) -> Result<()> {
+        // The nullifiers of the blocks committed so far are spent in the state
+        // loaded next, so their claims are no longer needed.
+        self.nullifiers.release_committed();
+        self.app = App::new(self.storage.overlay().await?).await?;
+        self.nullifiers.release_pending();
+
+        // Transactions which were included in the block fail the recheck too,
+        // but Tendermint drops those itself, and only asks about the rest, so
+        // only those are counted as evicted.
+        let now = SystemTime::now();
+        let (mut kept, mut dropped) = (0, 0);
+        for (pending, tx_bytes) in self.pending.take_for_recheck() {
+            let age = now.duration_since(pending.first_seen).unwrap_or_default();
+            let result = match self.ttl {
+                Some(ttl) if age >= ttl => Err(anyhow!(
+                    "not included within the mempool TTL of {}s",
+                    ttl.as_secs()
+                )),
+                _ => self.recheck_tx(&tx_bytes).await,
+            };
+            match result {
+                Ok(tx) => {
+                    self.nullifiers
+                        .claim_pending(pending.id, &pending.nullifiers);
+                    self.pending.accept(&tx, tx_bytes, pending.first_seen);
+                    kept += 1;
+                }
+                Err(e) => {
+                    self.pending.evict(pending.id, e.to_string());
+                    dropped += 1;
+                }
+            }
+        }
+
+        self.publish_pending();
+        tracing::info!(kept, dropped, "rechecked mempool");
+        Ok(())
+    }
+
+    async fn recheck_tx(&mut self, tx_bytes: &Bytes) -> Result<Transaction> {
+        let tx = Transaction::decode(tx_bytes.as_ref())?;
+        self.execute_tx(&tx).await?;
+        Ok(tx)
+    }
+
     pub async fn run(mut self) -> Result<()> {
         loop {
             tokio::select! {
                 // Use a biased select to poll for height changes *before* polling for messages.
                 biased;
-                // Check whether the height has changed, which requires us to throw away our
-                // ephemeral mempool state, and create a new one based on the new state.
+                // Check whether the height has changed, which requires us to recheck the pending
+                // transactions against the new state, before Tendermint rechecks them with us.
                 change = self.height_rx.changed() => {
                     if let Ok(()) = change {
                         let height = self.height_rx.borrow().value();
-                        tracing::info!(?height, "resetting ephemeral mempool state");
-                        self.app = App::new(self.storage.overlay().await?).await?;
+                        tracing::info!(?height, "rechecking mempool against new state");
+                        self.recheck().await?;
                     } else {
                         tracing::info!("consensus worker shut down, shutting down mempool worker");
                         // The consensus worker shut down, we should too.
@@ -67,12 +204,13 @@ impl Worker {
                 message = self.queue.recv() => {
                     if let Some(Message {
                         tx_bytes,
+                        recheck,
                         rsp_sender,
                         span,
                     }) = message {
                         // ... and then execute it if it was valid.
                         let _ = rsp_sender.send(
-                            self.check_and_execute_tx(tx_bytes)
+                            self.check_and_execute_tx(tx_bytes, recheck)
                                 .instrument(span)
                                 .await
                         );
@@ -85,3 +223,127 @@ impl Worker {
         }
     }
 }
+
+/// The priority Tendermint gives a transaction when choosing which to propose
+/// first, which is the fee it declares, so that the highest-paying
+/// transactions are included first when blocks are full.
+fn priority(tx: &Transaction) -> i64 {
+    i64::try_from(tx.transaction_body.fee.0).unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{merkle, rdsa::Signature};
+    use penumbra_transaction::{Fee, TransactionBody};
+
+    use super::*;
+    use crate::{pd_metrics::test_handle, testing::Node};
+
+    /// An (invalid) transaction declaring `fee`.
+    fn transaction(fee: u64) -> (Transaction, Bytes) {
+        let tx = Transaction {
+            transaction_body: TransactionBody {
+                actions: Vec::new(),
+                merkle_root: merkle::Root(Default::default()),
+                expiry_height: 0,
+                chain_id: String::new(),
+                fee: Fee(fee),
+            },
+            binding_sig: Signature::from([0; 64]),
+        };
+        let bytes = tx.encode_to_vec().into();
+        (tx, bytes)
+    }
+
+    /// A worker, and a receiver of the transactions it publishes as pending.
+    async fn worker(
+        node: &Node,
+        min_fee: u64,
+    ) -> Result<(Worker, watch::Receiver<Vec<PendingTx>>)> {
+        let (_, queue) = mpsc::channel(1);
+        let (_, height_rx) = watch::channel(block::Height::from(0u32));
+        let (pending_tx, pending_rx) = watch::channel(Vec::new());
+        let worker = Worker::new(
+            node.storage().clone(),
+            queue,
+            height_rx,
+            pending_tx,
+            NullifierCache::default(),
+            None,
+            min_fee,
+        )
+        .await?;
+        Ok((worker, pending_rx))
+    }
+
+    #[tokio::test]
+    async fn transactions_below_the_minimum_fee_are_rejected() -> Result<()> {
+        let node = Node::start(Default::default()).await?;
+        let (mut worker, _) = worker(&node, 10).await?;
+
+        let (_, bytes) = transaction(9);
+        let error = worker.check_and_execute_tx(bytes, false).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "fee of 9 is below the mempool's minimum fee of 10"
+        );
+
+        // A transaction paying the minimum gets past the fee check, to be
+        // rejected for being invalid.
+        let (_, bytes) = transaction(10);
+        let error = worker.check_and_execute_tx(bytes, false).await.unwrap_err();
+        assert!(!error.to_string().contains("minimum fee"), "{}", error);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn accepted_transactions_are_prioritized_by_fee() -> Result<()> {
+        let node = Node::start(Default::default()).await?;
+        let (mut worker, _) = worker(&node, 0).await?;
+
+        for fee in [0, 1, 1_000] {
+            let (tx, bytes) = transaction(fee);
+            worker.pending.accept(&tx, bytes.clone(), SystemTime::now());
+            assert_eq!(worker.check_and_execute_tx(bytes, false).await?, fee as i64);
+        }
+
+        // Fees too large for a priority get the highest one.
+        assert_eq!(priority(&transaction(u64::MAX).0), i64::MAX);
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn pending_transactions_are_published_with_their_size() -> Result<()> {
+        let handle = test_handle();
+        let node = Node::start(Default::default()).await?;
+        let (mut worker, pending_rx) = worker(&node, 0).await?;
+
+        let mut size = 0;
+        for fee in [1, 2] {
+            let (tx, bytes) = transaction(fee);
+            size += bytes.len();
+            worker.pending.accept(&tx, bytes, SystemTime::now());
+        }
+        worker.publish_pending();
+
+        let pending = pending_rx.borrow().clone();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending.iter().map(|tx| tx.size).sum::<usize>(), size);
+        // Other tests' mempools publish to the same gauges, so only their
+        // presence can be checked.
+        let rendered = handle.render();
+        assert!(
+            rendered.contains("node_mempool_pending_txs "),
+            "{}",
+            rendered
+        );
+        assert!(
+            rendered.contains("node_mempool_pending_bytes "),
+            "{}",
+            rendered
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use penumbra_crypto::Nullifier;
+
+/// The nullifiers spent by transactions which have not yet been committed,
+/// shared between the mempool and consensus workers.
+///
+/// The mempool checks transactions against the last committed state, so
+/// without this, a transaction conflicting with one delivered in the block
+/// being executed would pass `CheckTx`, only to fail at `DeliverTx` in a
+/// later block. Instead, the consensus worker claims the nullifiers of each
+/// transaction it delivers until the mempool has rechecked against the state
+/// its block was committed to, and the mempool claims those of the
+/// transactions it accepts until they are rechecked, and a new transaction
+/// spending a nullifier claimed by another is rejected.
+#[derive(Clone, Debug, Default)]
+pub struct NullifierCache(Arc<Mutex<Claims>>);
+
+#[derive(Debug, Default)]
+struct Claims {
+    /// The transactions accepted by the mempool spending each nullifier.
+    pending: BTreeMap<Nullifier, [u8; 32]>,
+    /// The transactions delivered in the uncommitted block spending each
+    /// nullifier.
+    in_block: BTreeMap<Nullifier, [u8; 32]>,
+    /// The transactions in committed blocks spending each nullifier, until
+    /// the mempool checks against a state in which they are spent.
+    committed: BTreeMap<Nullifier, [u8; 32]>,
+}
+
+impl NullifierCache {
+    /// The first of the nullifiers which is already claimed by a transaction
+    /// other than `id`, and the ID of that transaction, if any.
+    pub fn conflict(
+        &self,
+        id: &[u8; 32],
+        nullifiers: &[Nullifier],
+    ) -> Option<(Nullifier, [u8; 32])> {
+        let claims = self.0.lock().unwrap();
+        nullifiers.iter().find_map(|nullifier| {
+            [&claims.in_block, &claims.committed, &claims.pending]
+                .into_iter()
+                .filter_map(|claimed| claimed.get(nullifier))
+                .find(|claimant| *claimant != id)
+                .map(|claimant| (*nullifier, *claimant))
+        })
+    }
+
+    /// Claims the nullifiers of a transaction accepted by the mempool.
+    pub fn claim_pending(&self, id: [u8; 32], nullifiers: &[Nullifier]) {
+        let mut claims = self.0.lock().unwrap();
+        for nullifier in nullifiers {
+            claims.pending.insert(*nullifier, id);
+        }
+    }
+
+    /// Releases the nullifiers of every transaction accepted by the mempool,
+    /// before they are rechecked and claimed again.
+    pub fn release_pending(&self) {
+        self.0.lock().unwrap().pending.clear();
+    }
+
+    /// Claims the nullifiers of a transaction delivered in the current block.
+    pub fn claim_in_block(&self, id: [u8; 32], nullifiers: &[Nullifier]) {
+        let mut claims = self.0.lock().unwrap();
+        for nullifier in nullifiers {
+            claims.in_block.insert(*nullifier, id);
+        }
+    }
+
+    /// Releases the nullifiers of the current block's transactions, once the
+    /// block is abandoned.
+    pub fn release_block(&self) {
+        self.0.lock().unwrap().in_block.clear();
+    }
+
+    /// Keeps the nullifiers of the current block's transactions claimed once
+    /// it is committed, until [`release_committed`](Self::release_committed).
+    ///
+    /// The mempool goes on checking transactions against the state before
+    /// the block until it rechecks, so releasing them now would let a
+    /// transaction spending them through in between.
+    pub fn commit_block(&self) {
+        let mut claims = self.0.lock().unwrap();
+        let in_block = std::mem::take(&mut claims.in_block);
+        claims.committed.extend(in_block);
+    }
+
+    /// Releases the nullifiers of the transactions in committed blocks,
+    /// before the mempool starts checking against the latest committed state.
+    ///
+    /// This must be called before that state is loaded, so that the claims of
+    /// a block committed in between are kept until the next recheck.
+    pub fn release_committed(&self) {
+        self.0.lock().unwrap().committed.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::Fq;
+
+    use super::*;
+
+    fn nullifier(n: u64) -> Nullifier {
+        Nullifier(Fq::from(n))
+    }
+
+    #[test]
+    fn rejects_conflicts_until_released() {
+        let cache = NullifierCache::default();
+        let (a, b, c) = ([1; 32], [2; 32], [3; 32]);
+
+        // A transaction delivered in the current block conflicts with others
+        // spending any of its nullifiers, but not with itself.
+        cache.claim_in_block(a, &[nullifier(1), nullifier(2)]);
+        assert_eq!(
+            cache.conflict(&b, &[nullifier(3), nullifier(2)]),
+            Some((nullifier(2), a))
+        );
+        assert_eq!(cache.conflict(&a, &[nullifier(1)]), None);
+
+        // Likewise for transactions pending in the mempool.
+        cache.claim_pending(b, &[nullifier(3)]);
+        assert_eq!(cache.conflict(&c, &[nullifier(3)]), Some((nullifier(3), b)));
+
+        // Abandoning the block releases only its claims...
+        cache.release_block();
+        assert_eq!(cache.conflict(&c, &[nullifier(1), nullifier(2)]), None);
+        assert_eq!(cache.conflict(&c, &[nullifier(3)]), Some((nullifier(3), b)));
+        // ... and rechecking the mempool only the pending ones.
+        cache.release_pending();
+        assert_eq!(cache.conflict(&c, &[nullifier(3)]), None);
+    }
+
+    #[test]
+    fn keeps_committed_claims_until_the_mempool_rechecks() {
+        let cache = NullifierCache::default();
+        let (a, b, c) = ([1; 32], [2; 32], [3; 32]);
+
+        // The block is committed, but the mempool has not yet rechecked, so a
+        // transaction arriving now would be checked against the state before
+        // the block, and must still conflict.
+        cache.claim_in_block(a, &[nullifier(1)]);
+        cache.commit_block();
+        assert_eq!(cache.conflict(&b, &[nullifier(1)]), Some((nullifier(1), a)));
+
+        // The mempool releases the committed claims before loading the latest
+        // state; a block committed after that keeps its claims...
+        cache.release_committed();
+        cache.claim_in_block(c, &[nullifier(2)]);
+        cache.commit_block();
+        assert_eq!(cache.conflict(&b, &[nullifier(1)]), None);
+        assert_eq!(cache.conflict(&b, &[nullifier(2)]), Some((nullifier(2), c)));
+
+        // ... until the mempool rechecks against it in turn.
+        cache.release_committed();
+        assert_eq!(cache.conflict(&b, &[nullifier(2)]), None);
+    }
+}
@@ -1,8 +1,233 @@
-use metrics::register_counter;
+use std::{fmt, net::SocketAddr, str::FromStr};
+
+use anyhow::{anyhow, Context};
+use metrics::{register_counter, register_gauge, register_histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use serde::Deserialize;
+
+/// Whether `pd` serves its metrics for Prometheus to scrape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetricsMode {
+    /// Serve the metrics, but keep running without them if they cannot be
+    /// served, e.g. because the port is taken.
+    Enabled,
+    /// Serve the metrics, and fail to start if they cannot be served.
+    Strict,
+    /// Do not serve the metrics.
+    Disabled,
+}
+
+impl FromStr for MetricsMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "enabled" => Ok(MetricsMode::Enabled),
+            "strict" => Ok(MetricsMode::Strict),
+            "disabled" => Ok(MetricsMode::Disabled),
+            _ => Err(anyhow!(
+                "unknown metrics mode {:?}, expected enabled, strict or disabled",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MetricsMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MetricsMode::Enabled => "enabled",
+            MetricsMode::Strict => "strict",
+            MetricsMode::Disabled => "disabled",
+        })
+    }
+}
+
+/// The upper bounds, in seconds, of the buckets durations are counted in,
+/// from a tenth of a millisecond, for storage reads, to a minute, for the
+/// slowest blocks.
+const DURATION_BUCKETS: &[f64] = &[
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+    5.0, 10.0, 30.0, 60.0,
+];
+
+/// A builder for the recorder, exporting durations as Prometheus histograms,
+/// which can be aggregated across nodes, rather than as summaries.
+fn builder() -> anyhow::Result<PrometheusBuilder> {
+    Ok(PrometheusBuilder::new().set_buckets_for_metric(
+        Matcher::Suffix("_duration_seconds".to_string()),
+        DURATION_BUCKETS,
+    )?)
+}
+
+/// Installs the global metrics recorder, serving its metrics at `addr`
+/// unless `mode` disables them.
+///
+/// The metrics are recorded even when they are not served, so the returned
+/// handle can still render them, e.g. for the admin service.
+pub fn install_metrics(mode: MetricsMode, addr: SocketAddr) -> anyhow::Result<PrometheusHandle> {
+    let recorder = match mode {
+        MetricsMode::Disabled => {
+            tracing::info!("not serving metrics");
+            builder()?.build_recorder()
+        }
+        MetricsMode::Enabled | MetricsMode::Strict => {
+            match builder()?.with_http_listener(addr).build() {
+                Ok((recorder, exporter)) => {
+                    tracing::info!(?addr, "serving metrics");
+                    tokio::spawn(async move {
+                        if let Err(error) = exporter.await {
+                            tracing::error!(?error, "metrics endpoint failed");
+                        }
+                    });
+                    recorder
+                }
+                Err(error) if mode == MetricsMode::Enabled => {
+                    tracing::error!(
+                        ?error,
+                        ?addr,
+                        "could not serve metrics, continuing without serving them"
+                    );
+                    builder()?.build_recorder()
+                }
+                Err(error) => {
+                    return Err(error)
+                        .with_context(|| format!("could not serve metrics on {}", addr))
+                }
+            }
+        }
+    };
+    let handle = recorder.handle();
+    metrics::set_boxed_recorder(Box::new(recorder))
+        .context("metrics recorder already installed")?;
+    Ok(handle)
+}
 
 /// Registers all metrics tracked by `pd`.
 pub fn register_all_metrics() {
     register_counter!("node_spent_nullifiers_total");
     register_counter!("node_notes_total");
     register_counter!("node_transactions_total");
+    register_counter!("node_stake_invariant_violations_total");
+
+    // Transactions evicted from the mempool by the recheck after each block.
+    register_counter!("node_mempool_evicted_txs_total");
+
+    // Transactions rejected by the mempool for declaring less than its minimum fee.
+    register_counter!("node_mempool_low_fee_txs_total");
+
+    // Transactions rejected by the mempool for spending a nullifier already
+    // spent by another uncommitted transaction.
+    register_counter!("node_mempool_conflicting_txs_total");
+
+    // Blocks replayed by Tendermint which were committed from their cached
+    // execution, if `pd start --cache-replayed-blocks` is set.
+    register_counter!("node_consensus_cached_replays_total");
+    // Replayed blocks which turned out to differ from their cached execution,
+    // and were executed instead.
+    register_counter!("node_consensus_abandoned_replays_total");
+
+    // Query streams returned to clients and not yet dropped.
+    register_gauge!("node_open_query_streams");
+    // Query requests rejected by the per-peer rate limit.
+    register_counter!("node_query_requests_rejected_total");
+    // Query requests of the busiest clients in the last minute, labeled by client.
+    register_gauge!("node_query_client_requests");
+    register_gauge!("node_query_client_requests_rejected");
+
+    // Pruning of old versions of the stored state, if enabled.
+    register_counter!("node_storage_prunings_total");
+    register_counter!("node_storage_pruned_nodes_total");
+    register_counter!("node_storage_pruned_bytes_total");
+    register_gauge!("node_storage_earliest_version");
+
+    // Republished from Tendermint's RPC, if `pd start --tendermint-rpc` is set.
+    register_gauge!("node_tendermint_rpc_up");
+    register_gauge!("node_tendermint_peers");
+    register_gauge!("node_tendermint_latest_block_height");
+    register_gauge!("node_tendermint_catching_up");
+    register_gauge!("node_tendermint_mempool_txs");
+    register_gauge!("node_tendermint_mempool_bytes");
+
+    // Time taken to execute each block, from BeginBlock to EndBlock, and to
+    // commit it.
+    register_histogram!("node_block_execution_duration_seconds");
+    register_histogram!("node_block_commit_duration_seconds");
+    // Time taken to check each transaction, labeled by whether it was checked
+    // by the mempool or delivered in a block.
+    register_histogram!("node_tx_verification_duration_seconds");
+    // Time taken by each read and each batch of writes of the state tree.
+    register_histogram!("node_storage_read_duration_seconds");
+    register_histogram!("node_storage_write_duration_seconds");
+    // Time taken to answer each query, labeled by gRPC method.
+    register_histogram!("node_grpc_request_duration_seconds");
+    // Transactions accepted by the mempool and not yet included in a block,
+    // and their total size.
+    register_gauge!("node_mempool_pending_txs");
+    register_gauge!("node_mempool_pending_bytes");
+
+    // The chain's running totals as of the latest committed block, as served
+    // by the `ChainStats` query.
+    register_gauge!("node_chain_commitments");
+    register_gauge!("node_chain_nullifiers");
+    register_gauge!("node_chain_transactions");
+    register_gauge!("node_chain_transactions_per_block");
+    register_gauge!("node_chain_active_validators");
+}
+
+/// The handle of the global recorder shared by this crate's tests, which is
+/// installed by the first test to ask for it.
+///
+/// Tests run concurrently, so metrics without labels unique to a test may be
+/// recorded by others too.
+#[cfg(test)]
+pub(crate) fn test_handle() -> PrometheusHandle {
+    static HANDLE: once_cell::sync::Lazy<PrometheusHandle> = once_cell::sync::Lazy::new(|| {
+        let recorder = builder().unwrap().build_recorder();
+        let handle = recorder.handle();
+        metrics::set_boxed_recorder(Box::new(recorder)).unwrap();
+        handle
+    });
+    HANDLE.clone()
+}
+
+/// The number of observations of the histogram `name` rendered by `handle`,
+/// or 0 if it has none.
+#[cfg(test)]
+pub(crate) fn histogram_count(handle: &PrometheusHandle, name: &str) -> u64 {
+    let prefix = format!("{}_count ", name);
+    handle
+        .render()
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .map_or(0, |count| count.parse().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_exported_as_histograms() {
+        let handle = test_handle();
+        metrics::histogram!("node_test_lookup_duration_seconds", 0.003);
+        metrics::histogram!("node_test_lookup_duration_seconds", 45.0);
+        metrics::histogram!("node_test_lookup_size", 3.0);
+
+        let rendered = handle.render();
+        for line in [
+            "node_test_lookup_duration_seconds_bucket{le=\"0.0025\"} 0",
+            "node_test_lookup_duration_seconds_bucket{le=\"0.005\"} 1",
+            "node_test_lookup_duration_seconds_bucket{le=\"60\"} 2",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "{}", rendered);
+        }
+        assert_eq!(
+            histogram_count(&handle, "node_test_lookup_duration_seconds"),
+            2
+        );
+        // Other histograms are still summaries.
+        assert!(rendered.contains("node_test_lookup_size{quantile="));
+    }
 }
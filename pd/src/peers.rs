@@ -0,0 +1,325 @@
+//! Refreshing the co-located Tendermint node's persistent peers from a peer
+//! list published for the chain.
+//!
+//! On long-running testnets, the persistent peers written into each node's
+//! Tendermint config at genesis go stale as nodes come and go. Instead, the
+//! testnet's coordinator can publish a [`SignedPeerList`] at a URL, signed
+//! with an Ed25519 key, and nodes can fetch it with `pd peers refresh`, or
+//! periodically from `pd start`, and rewrite the `persistent-peers` of their
+//! Tendermint config. Tendermint only reads its config at startup, so the new
+//! peers are dialed once it is next restarted.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use ed25519_consensus::{Signature, SigningKey, VerificationKey};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use tendermint::node;
+
+/// The domain separator of the bytes signed for a peer list.
+const DOMAIN_SEPARATOR: &str = "penumbra-peer-list";
+
+/// A list of peers for a chain, signed by the testnet's coordinator, as
+/// published for `pd peers refresh`.
+///
+/// Each peer is given as Tendermint expects them in `persistent-peers`, as
+/// `<node id>@<host>:<port>`.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedPeerList {
+    pub chain_id: String,
+    pub peers: Vec<String>,
+    /// The hex-encoded Ed25519 signature of the chain ID and the peers.
+    #[serde_as(as = "serde_with::hex::Hex")]
+    pub signature: Vec<u8>,
+}
+
+impl SignedPeerList {
+    /// Signs a peer list for the chain with the coordinator's key.
+    pub fn sign(key: &SigningKey, chain_id: String, peers: Vec<String>) -> Result<Self> {
+        for peer in &peers {
+            check_peer(peer)?;
+        }
+        let signature = key.sign(&signed_bytes(&chain_id, &peers));
+        Ok(SignedPeerList {
+            chain_id,
+            peers,
+            signature: signature.to_bytes().to_vec(),
+        })
+    }
+
+    /// Checks that the list is for the chain, is signed by `signer`, and
+    /// holds only well-formed peers.
+    pub fn verify(&self, signer: &VerificationKey, chain_id: &str) -> Result<()> {
+        if self.chain_id != chain_id {
+            return Err(anyhow!(
+                "the peer list is for chain {:?}, not {:?}",
+                self.chain_id,
+                chain_id
+            ));
+        }
+        let signature = Signature::try_from(self.signature.as_slice())
+            .map_err(|_| anyhow!("malformed peer list signature"))?;
+        signer
+            .verify(&signature, &signed_bytes(&self.chain_id, &self.peers))
+            .map_err(|_| anyhow!("the peer list is not signed by the expected key"))?;
+        if self.peers.is_empty() {
+            return Err(anyhow!("the peer list is empty"));
+        }
+        for peer in &self.peers {
+            check_peer(peer)?;
+        }
+        Ok(())
+    }
+}
+
+/// The bytes signed for a peer list.
+fn signed_bytes(chain_id: &str, peers: &[String]) -> Vec<u8> {
+    let mut bytes = format!("{}\n{}", DOMAIN_SEPARATOR, chain_id);
+    for peer in peers {
+        bytes.push('\n');
+        bytes.push_str(peer);
+    }
+    bytes.into_bytes()
+}
+
+/// Checks that a peer is of the form `<node id>@<host>:<port>`.
+pub(crate) fn check_peer(peer: &str) -> Result<()> {
+    let (id, addr) = peer
+        .split_once('@')
+        .ok_or_else(|| anyhow!("peer {:?} is not of the form <node id>@<host>:<port>", peer))?;
+    node::Id::from_str(id).with_context(|| format!("invalid node id in peer {:?}", peer))?;
+    let (host, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("peer {:?} has no port", peer))?;
+    if host.is_empty() || host.contains(|c: char| c == ',' || c.is_whitespace() || c == '"') {
+        return Err(anyhow!("invalid host in peer {:?}", peer));
+    }
+    port.parse::<u16>()
+        .with_context(|| format!("invalid port in peer {:?}", peer))?;
+    Ok(())
+}
+
+/// Parses the hex-encoded Ed25519 key the peer list must be signed with.
+pub fn parse_signer(signer: &str) -> Result<VerificationKey> {
+    let bytes = hex::decode(signer.trim()).context("the peer list signer is not hex")?;
+    VerificationKey::try_from(bytes.as_slice())
+        .map_err(|_| anyhow!("the peer list signer is not an Ed25519 verification key"))
+}
+
+/// Reads the hex-encoded Ed25519 signing key of a peer list's coordinator.
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("cannot read signing key {:?}", path))?;
+    let bytes = hex::decode(contents.trim())
+        .with_context(|| format!("signing key {:?} is not hex", path))?;
+    SigningKey::try_from(bytes.as_slice())
+        .map_err(|_| anyhow!("signing key {:?} is not an Ed25519 signing key", path))
+}
+
+/// Replaces the `persistent-peers` of a Tendermint config, keeping the rest of
+/// the file, including its comments, as it is.
+pub fn rewrite_config(config: &str, peers: &[String]) -> Result<String> {
+    // Older versions of Tendermint spell the key with an underscore.
+    let re =
+        Regex::new(r#"(?m)^(\s*persistent[-_]peers\s*=\s*)"[^"\n]*""#).expect("the regex is valid");
+    if re.find_iter(config).count() != 1 {
+        return Err(anyhow!(
+            "expected exactly one persistent-peers setting in the Tendermint config"
+        ));
+    }
+    let peers = peers.join(",");
+    Ok(re
+        .replace(config, |captures: &regex::Captures| {
+            format!("{}\"{}\"", &captures[1], peers)
+        })
+        .into_owned())
+}
+
+/// The paths of the config and genesis files in a Tendermint home directory.
+fn tendermint_files(tendermint_home: &Path) -> (PathBuf, PathBuf) {
+    let config = tendermint_home.join("config");
+    (config.join("config.toml"), config.join("genesis.json"))
+}
+
+/// Fetches the peer list at `url`, checks that it is for the chain of the
+/// Tendermint node at `tendermint_home` and signed by `signer`, and writes it
+/// to the node's config.
+///
+/// Returns whether the config was changed.
+pub async fn refresh(url: &str, signer: &VerificationKey, tendermint_home: &Path) -> Result<bool> {
+    let (config_path, genesis_path) = tendermint_files(tendermint_home);
+    let genesis: serde_json::Value = serde_json::from_slice(
+        &std::fs::read(&genesis_path)
+            .with_context(|| format!("cannot read Tendermint genesis {:?}", genesis_path))?,
+    )
+    .with_context(|| format!("invalid Tendermint genesis {:?}", genesis_path))?;
+    let chain_id = genesis["chain_id"]
+        .as_str()
+        .ok_or_else(|| anyhow!("no chain_id in Tendermint genesis {:?}", genesis_path))?;
+
+    let list: SignedPeerList = reqwest::get(url)
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .with_context(|| format!("invalid peer list at {}", url))?;
+    list.verify(signer, chain_id)?;
+
+    let config = std::fs::read_to_string(&config_path)
+        .with_context(|| format!("cannot read Tendermint config {:?}", config_path))?;
+    let rewritten = rewrite_config(&config, &list.peers)?;
+    if rewritten == config {
+        return Ok(false);
+    }
+
+    // Write the new config alongside the old one and move it into place, so
+    // that Tendermint never reads a partially written config.
+    let tmp_path = config_path.with_extension("toml.tmp");
+    std::fs::write(&tmp_path, rewritten)?;
+    std::fs::rename(&tmp_path, &config_path)?;
+    tracing::info!(
+        peers = list.peers.len(),
+        ?config_path,
+        "refreshed persistent peers"
+    );
+    Ok(true)
+}
+
+/// Refreshes the persistent peers every `interval`, forever.
+///
+/// Failures are logged rather than ending the task, keeping the peers as they
+/// were.
+pub async fn poll(
+    url: String,
+    signer: VerificationKey,
+    tendermint_home: PathBuf,
+    interval: Duration,
+) {
+    loop {
+        if let Err(error) = refresh(&url, &signer, &tendermint_home).await {
+            tracing::warn!(?error, %url, "could not refresh persistent peers");
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+
+    const NODE_ID: &str = "1111111111111111111111111111111111111111";
+
+    #[test]
+    fn peers_must_be_node_ids_at_host_ports() {
+        for host in ["node1.example.com", "10.0.0.2", "[::1]"] {
+            let peer = format!("{}@{}:26656", NODE_ID, host);
+            assert!(check_peer(&peer).is_ok(), "{}", peer);
+        }
+        for (peer, problem) in [
+            ("node1.example.com:26656", "not of the form"),
+            ("1111@node1.example.com:26656", "invalid node id"),
+            ("{id}@node1.example.com", "no port"),
+            ("{id}@node1.example.com:http", "invalid port"),
+            ("{id}@:26656", "invalid host"),
+            ("{id}@a,b:26656", "invalid host"),
+            ("{id}@\"a\":26656", "invalid host"),
+        ] {
+            let peer = peer.replace("{id}", NODE_ID);
+            let error = check_peer(&peer).unwrap_err();
+            assert!(error.to_string().contains(problem), "{}: {}", peer, error);
+        }
+    }
+
+    #[test]
+    fn signatures_cover_the_chain_and_every_peer() -> Result<()> {
+        let key = SigningKey::new(OsRng);
+        let signer = key.verification_key();
+        let peers = vec![format!("{}@10.0.0.2:26656", NODE_ID)];
+        let list = SignedPeerList::sign(&key, "penumbra-testnet".to_string(), peers.clone())?;
+
+        // The signature is carried as hex, and survives the round trip.
+        let json = serde_json::to_value(&list)?;
+        assert_eq!(json["signature"], hex::encode(&list.signature));
+        let parsed: SignedPeerList = serde_json::from_value(json)?;
+        parsed.verify(&signer, "penumbra-testnet")?;
+
+        // Signatures of the same chain and peers made for other purposes,
+        // without the domain separator, are not accepted.
+        let mut bytes = b"penumbra-testnet".to_vec();
+        for peer in &peers {
+            bytes.push(b'\n');
+            bytes.extend_from_slice(peer.as_bytes());
+        }
+        let undomained = SignedPeerList {
+            signature: key.sign(&bytes).to_bytes().to_vec(),
+            ..list.clone()
+        };
+        assert!(undomained.verify(&signer, "penumbra-testnet").is_err());
+
+        let truncated = SignedPeerList {
+            signature: list.signature[..32].to_vec(),
+            ..list.clone()
+        };
+        let error = truncated.verify(&signer, "penumbra-testnet").unwrap_err();
+        assert!(error.to_string().contains("malformed"), "{}", error);
+
+        let empty = SignedPeerList::sign(&key, "penumbra-testnet".to_string(), Vec::new())?;
+        let error = empty.verify(&signer, "penumbra-testnet").unwrap_err();
+        assert_eq!(error.to_string(), "the peer list is empty");
+
+        Ok(())
+    }
+
+    #[test]
+    fn rewrites_either_spelling_of_the_setting() -> Result<()> {
+        let peers = vec![format!("{}@10.0.0.2:26656", NODE_ID)];
+        let config = "# peers\npersistent_peers = \"\"\nseeds = \"\"\n";
+        assert_eq!(
+            rewrite_config(config, &peers)?,
+            config.replace(
+                "persistent_peers = \"\"",
+                &format!("persistent_peers = \"{}\"", peers[0])
+            )
+        );
+
+        let twice = "persistent-peers = \"\"\n[other]\npersistent-peers = \"\"\n";
+        assert!(rewrite_config(twice, &peers).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn signing_keys_are_read_as_hex() -> Result<()> {
+        let key = SigningKey::new(OsRng);
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("peers.key");
+        std::fs::write(&path, format!("{}\n", hex::encode(key.as_bytes())))?;
+
+        let read = read_signing_key(&path)?;
+        assert_eq!(
+            read.verification_key().to_bytes(),
+            key.verification_key().to_bytes()
+        );
+        let signer = hex::encode(key.verification_key().as_bytes());
+        assert_eq!(
+            parse_signer(&format!(" {} ", signer))?.to_bytes(),
+            key.verification_key().to_bytes()
+        );
+
+        assert!(parse_signer("not hex").is_err());
+        assert!(parse_signer("00").is_err());
+        std::fs::write(&path, "00")?;
+        assert!(read_signing_key(&path).is_err());
+
+        Ok(())
+    }
+}
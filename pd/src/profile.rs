@@ -0,0 +1,97 @@
+//! Opt-in profiling of the consensus path, used by `pd replay --profile`.
+//!
+//! When profiling is disabled (the default), timers and counters are a single
+//! relaxed atomic load, so they can be left in place on the hot path.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static READS: AtomicU64 = AtomicU64::new(0);
+static WRITES: AtomicU64 = AtomicU64::new(0);
+static TIMINGS: Lazy<Mutex<BTreeMap<(&'static str, &'static str), Timing>>> =
+    Lazy::new(Default::default);
+
+/// Turns on profiling for the rest of the process lifetime.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records a read from the state store.
+pub fn count_read() {
+    if enabled() {
+        READS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Records a write to the state store.
+pub fn count_write() {
+    if enabled() {
+        WRITES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Starts timing one phase of one component, recording the elapsed time when
+/// the returned [`Timer`] is dropped.
+pub fn timer(component: &'static str, phase: &'static str) -> Timer {
+    Timer {
+        component,
+        phase,
+        start: enabled().then(Instant::now),
+    }
+}
+
+/// A running timer, created by [`timer`].
+pub struct Timer {
+    component: &'static str,
+    phase: &'static str,
+    start: Option<Instant>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let mut timings = TIMINGS.lock().unwrap();
+            let timing = timings.entry((self.component, self.phase)).or_default();
+            timing.count += 1;
+            timing.total += start.elapsed();
+        }
+    }
+}
+
+/// The accumulated time spent in one phase of one component.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timing {
+    pub count: u64,
+    pub total: Duration,
+}
+
+/// Everything recorded since the last call to [`take_report`].
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Timings, keyed by `(component, phase)`.
+    pub timings: BTreeMap<(&'static str, &'static str), Timing>,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+/// Returns everything recorded so far, and resets the recorded data.
+pub fn take_report() -> Report {
+    Report {
+        timings: std::mem::take(&mut *TIMINGS.lock().unwrap()),
+        reads: READS.swap(0, Ordering::Relaxed),
+        writes: WRITES.swap(0, Ordering::Relaxed),
+    }
+}
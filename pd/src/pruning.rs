@@ -0,0 +1,157 @@
+//! Pruning of old versions of the stored state.
+//!
+//! Every block writes a new version of the tree, and the nodes it replaces
+//! are kept, so that past versions can still be read, until they are pruned.
+//! [`run`] prunes in the background as the chain advances, keeping the most
+//! recent versions according to a [`Strategy`]:
+//!
+//! - `default` keeps the last 362880 versions, about three weeks of blocks,
+//!   pruning every 100 blocks;
+//! - `nothing` keeps every version, as `pd` always used to;
+//! - `everything` keeps only the last 2 versions, pruning every 10 blocks;
+//! - `custom` keeps `pruning-keep-recent` versions, pruning every
+//!   `pruning-interval` blocks.
+//!
+//! Pruned versions can no longer be queried, exported, replayed on top of or
+//! rolled back to.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use tendermint::block;
+use tokio::sync::watch;
+
+use crate::Storage;
+
+/// Which versions of the stored state to keep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    Default,
+    Nothing,
+    Everything,
+    Custom,
+}
+
+impl FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(Strategy::Default),
+            "nothing" => Ok(Strategy::Nothing),
+            "everything" => Ok(Strategy::Everything),
+            "custom" => Ok(Strategy::Custom),
+            _ => Err(anyhow!(
+                "unknown pruning strategy {:?}, expected default, nothing, everything or custom",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Strategy::Default => "default",
+            Strategy::Nothing => "nothing",
+            Strategy::Everything => "everything",
+            Strategy::Custom => "custom",
+        })
+    }
+}
+
+/// How much of the stored state to keep, and how often to prune the rest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pruning {
+    /// How many of the latest versions to keep.
+    pub keep_recent: u64,
+    /// How many blocks to commit between prunings.
+    pub interval: u64,
+}
+
+impl Pruning {
+    /// The pruning done by `strategy`, or `None` if it keeps everything.
+    ///
+    /// # Errors
+    ///
+    /// `keep_recent` and `interval` must be given for, and only for, the
+    /// custom strategy, and at least the last 2 versions must be kept, since
+    /// requests in flight may still be reading the one before the latest.
+    pub fn new(
+        strategy: Strategy,
+        keep_recent: Option<u64>,
+        interval: Option<u64>,
+    ) -> Result<Option<Self>> {
+        let pruning = match (strategy, keep_recent, interval) {
+            (Strategy::Custom, Some(keep_recent), Some(interval)) => Pruning {
+                keep_recent,
+                interval,
+            },
+            (Strategy::Custom, _, _) => {
+                return Err(anyhow!(
+                    "custom pruning requires pruning-keep-recent and pruning-interval"
+                ))
+            }
+            (_, None, None) => match strategy {
+                Strategy::Nothing => return Ok(None),
+                Strategy::Default => Pruning {
+                    keep_recent: 362880,
+                    interval: 100,
+                },
+                _ => Pruning {
+                    keep_recent: 2,
+                    interval: 10,
+                },
+            },
+            _ => {
+                return Err(anyhow!(
+                    "pruning-keep-recent and pruning-interval can only be set with custom pruning"
+                ))
+            }
+        };
+        if pruning.keep_recent < 2 {
+            return Err(anyhow!("pruning must keep at least the last 2 versions"));
+        }
+        if pruning.interval == 0 {
+            return Err(anyhow!("the pruning interval must be at least 1 block"));
+        }
+        Ok(Some(pruning))
+    }
+}
+
+/// Prunes `storage` as the heights committed on `block_heights` advance,
+/// until the sender is dropped.
+pub async fn run(
+    storage: Storage,
+    pruning: Pruning,
+    mut block_heights: watch::Receiver<block::Height>,
+) {
+    tracing::info!(?pruning, "pruning stored state");
+    let mut pruned_to = 0;
+    while block_heights.changed().await.is_ok() {
+        let height = block_heights.borrow().value();
+        let keep_from = (height + 1).saturating_sub(pruning.keep_recent);
+        if keep_from < pruned_to + pruning.interval {
+            continue;
+        }
+
+        match storage.prune(keep_from).await {
+            Ok(pruned) => {
+                tracing::info!(
+                    keep_from,
+                    nodes = pruned.nodes,
+                    bytes = pruned.bytes,
+                    "pruned"
+                );
+                metrics::increment_counter!("node_storage_prunings_total");
+                metrics::counter!("node_storage_pruned_nodes_total", pruned.nodes as u64);
+                metrics::counter!("node_storage_pruned_bytes_total", pruned.bytes as u64);
+                metrics::gauge!("node_storage_earliest_version", keep_from as f64);
+                pruned_to = keep_from;
+            }
+            Err(error) => tracing::error!(?error, keep_from, "pruning failed"),
+        }
+    }
+}
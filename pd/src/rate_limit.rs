@@ -0,0 +1,625 @@
+//! Per-client request rate limits and usage statistics for the query services.
+//!
+//! Requests are attributed to a client: the name of the API key presented in
+//! the `x-api-key` header, if any, and otherwise the remote IP address. A
+//! request presenting an unknown API key is rejected with `UNAUTHENTICATED`.
+//!
+//! Each client gets a token bucket holding up to `burst` requests, refilled
+//! at `rate` requests per second, which API keys can override. A request
+//! which finds its bucket empty is rejected with `RESOURCE_EXHAUSTED` before
+//! it reaches the service, and counted in `node_query_requests_rejected_total`,
+//! so that one client scanning the chain in a tight loop cannot starve the
+//! others. A stream counts as a single request, however long it runs.
+//!
+//! Responses to limited clients carry their quota in the `x-ratelimit-limit`
+//! and `x-ratelimit-remaining` headers, and rejections say when to retry in
+//! `retry-after`, in seconds. Every client's requests are counted, whether or
+//! not it is limited, and [`RateLimitLayer::export_top_clients`] publishes the
+//! busiest ones as metrics.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context as _};
+use futures::ready;
+use pin_project::pin_project;
+use tokio::time::Instant;
+use tonic::{
+    body::BoxBody,
+    transport::server::{TcpConnectInfo, TlsConnectInfo},
+    Status,
+};
+use tower::{Layer, Service};
+
+/// How many clients to track before forgetting the ones which have refilled,
+/// and so are indistinguishable from new clients, along with their usage.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// The header in which clients present their API key.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// How often [`RateLimitLayer::export_top_clients`] publishes usage.
+const USAGE_WINDOW: Duration = Duration::from_secs(60);
+
+/// The remote address of a request, if it arrived over TCP, with or without
+/// TLS.
+///
+/// This is extracted from tonic's `remote_addr` implementation, so that it
+/// can be used in middleware and `trace_fn` hooks, which see an
+/// `http::Request` rather than a `tonic::Request`.
+pub fn remote_addr<B>(request: &http::Request<B>) -> Option<SocketAddr> {
+    let extensions = request.extensions();
+    extensions
+        .get::<TcpConnectInfo>()
+        .or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<TcpConnectInfo>>()
+                .map(|info| info.get_ref())
+        })
+        .and_then(|info| info.remote_addr())
+}
+
+/// A request rate limit: `rate` requests per second on average, and up to
+/// `burst` at once. Requests are not limited if `rate` is 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Limit {
+    rate: f64,
+    burst: f64,
+}
+
+impl Limit {
+    fn new(rate: u32, burst: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            burst: burst.max(1) as f64,
+        }
+    }
+}
+
+/// The API keys clients may present, so that their requests are limited and
+/// accounted for by key rather than by IP address.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys(HashMap<String, ApiKey>);
+
+#[derive(Clone, Debug)]
+struct ApiKey {
+    name: Arc<str>,
+    /// The key's own limit, rather than the default one.
+    limit: Option<Limit>,
+}
+
+impl ApiKeys {
+    /// Reads API keys from a file, in the format of [`ApiKeys::parse`].
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read API keys file {:?}", path))?;
+        Self::parse(&contents).with_context(|| format!("invalid API keys file {:?}", path))
+    }
+
+    /// Parses API keys, one per line, as `<name> <key>`, or as
+    /// `<name> <key> <rate> <burst>` to give the key its own limit. Blank
+    /// lines, and lines starting with `#`, are ignored.
+    ///
+    /// The name identifies the key's holder in logs and metrics, so should
+    /// not be secret.
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let (name, key, limit) = match fields[..] {
+                [name, key] => (name, key, None),
+                [name, key, rate, burst] => {
+                    let parse = |field: &str| {
+                        field
+                            .parse()
+                            .with_context(|| format!("invalid limit on line {}", number + 1))
+                    };
+                    (name, key, Some(Limit::new(parse(rate)?, parse(burst)?)))
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "line {} is not `<name> <key> [<rate> <burst>]`",
+                        number + 1
+                    ))
+                }
+            };
+            let api_key = ApiKey {
+                name: name.into(),
+                limit,
+            };
+            if keys.insert(key.to_string(), api_key).is_some() {
+                return Err(anyhow!("duplicate key on line {}", number + 1));
+            }
+        }
+        Ok(Self(keys))
+    }
+}
+
+/// The client a request is attributed to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Client {
+    /// A client presenting an API key, identified by the key's name.
+    ApiKey(Arc<str>),
+    /// Any other client, identified by its IP address.
+    Peer(IpAddr),
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Client::ApiKey(name) => write!(f, "key:{}", name),
+            Client::Peer(ip) => ip.fmt(f),
+        }
+    }
+}
+
+/// A client's requests over a usage window, as returned by
+/// [`RateLimitLayer::take_usage`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Usage {
+    /// The API key name, prefixed with `key:`, or the IP address of the client.
+    pub client: String,
+    /// How many requests the client made, including rejected ones.
+    pub requests: u64,
+    /// How many of the client's requests were rejected by its limit.
+    pub rejected: u64,
+}
+
+/// Limits the rate of requests from each client, and records their usage.
+#[derive(Clone, Debug)]
+pub struct RateLimitLayer {
+    limiter: Arc<Limiter>,
+}
+
+impl RateLimitLayer {
+    /// Allows each client `rate` requests per second on average, and up to
+    /// `burst` at once, unless its API key has its own limit. Requests are
+    /// not limited if `rate` is 0.
+    pub fn new(rate: u32, burst: u32) -> Self {
+        Self {
+            limiter: Arc::new(Limiter {
+                default: Limit::new(rate, burst),
+                api_keys: Default::default(),
+                clients: Default::default(),
+            }),
+        }
+    }
+
+    /// Accepts the given API keys, and only those.
+    pub fn with_api_keys(self, api_keys: ApiKeys) -> Self {
+        Self {
+            limiter: Arc::new(Limiter {
+                default: self.limiter.default,
+                api_keys,
+                clients: Default::default(),
+            }),
+        }
+    }
+
+    /// Returns every client's usage since the last call, or since the layer
+    /// was created, and starts counting afresh.
+    ///
+    /// Only the last [`MAX_TRACKED_CLIENTS`] clients are tracked at once, so
+    /// if more made requests, the usage of the least active may be missing.
+    pub fn take_usage(&self) -> Vec<Usage> {
+        let now = Instant::now();
+        let mut clients = self.limiter.clients.lock().unwrap();
+        let usage = clients
+            .iter()
+            .filter(|(_, state)| state.requests > 0)
+            .map(|(client, state)| Usage {
+                client: client.to_string(),
+                requests: state.requests,
+                rejected: state.rejected,
+            })
+            .collect();
+        // Clients whose buckets have refilled need not be tracked any more.
+        clients.retain(|_, state| state.refilled(now) < state.limit.burst);
+        for state in clients.values_mut() {
+            state.requests = 0;
+            state.rejected = 0;
+        }
+        usage
+    }
+
+    /// Publishes the usage of the `count` busiest clients in each minute, as
+    /// the `node_query_client_requests` and
+    /// `node_query_client_requests_rejected` gauges, labeled by client.
+    ///
+    /// A client which drops out of the busiest is reported as 0 rather than
+    /// forgotten, so every client ever among the busiest remains a series.
+    pub async fn export_top_clients(self, count: usize) {
+        let mut exported = HashSet::new();
+        let mut interval = tokio::time::interval(USAGE_WINDOW);
+        // The first tick completes immediately.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let mut usage = self.take_usage();
+            usage.sort_by(|a, b| b.requests.cmp(&a.requests));
+            usage.truncate(count);
+
+            let mut busiest = HashSet::new();
+            for usage in usage {
+                metrics::gauge!(
+                    "node_query_client_requests",
+                    usage.requests as f64,
+                    "client" => usage.client.clone()
+                );
+                metrics::gauge!(
+                    "node_query_client_requests_rejected",
+                    usage.rejected as f64,
+                    "client" => usage.client.clone()
+                );
+                busiest.insert(usage.client);
+            }
+            for client in exported.difference(&busiest) {
+                metrics::gauge!("node_query_client_requests", 0.0, "client" => client.clone());
+                metrics::gauge!(
+                    "node_query_client_requests_rejected",
+                    0.0,
+                    "client" => client.clone()
+                );
+            }
+            exported.extend(busiest);
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> RateLimit<S> {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Limiter {
+    default: Limit,
+    api_keys: ApiKeys,
+    clients: Mutex<HashMap<Client, ClientState>>,
+}
+
+#[derive(Debug)]
+struct ClientState {
+    limit: Limit,
+    tokens: f64,
+    updated: Instant,
+    requests: u64,
+    rejected: u64,
+}
+
+impl ClientState {
+    /// The tokens in the bucket at `now`.
+    fn refilled(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * self.limit.rate).min(self.limit.burst)
+    }
+}
+
+/// A limited client's quota, after a request.
+#[derive(Clone, Copy, Debug)]
+pub struct Quota {
+    limit: u64,
+    remaining: u64,
+    /// If the request was rejected, how many seconds until it could succeed.
+    retry_after: Option<u64>,
+}
+
+impl Quota {
+    fn insert(&self, headers: &mut http::HeaderMap) {
+        headers.insert("x-ratelimit-limit", self.limit.into());
+        headers.insert("x-ratelimit-remaining", self.remaining.into());
+        if let Some(retry_after) = self.retry_after {
+            headers.insert(http::header::RETRY_AFTER, retry_after.into());
+        }
+    }
+}
+
+impl Limiter {
+    /// The client making `request`, and its limit, or `None` if the request
+    /// cannot be attributed to a client.
+    fn identify<B>(&self, request: &http::Request<B>) -> Result<Option<(Client, Limit)>, Status> {
+        if let Some(key) = request.headers().get(API_KEY_HEADER) {
+            return key
+                .to_str()
+                .ok()
+                .and_then(|key| self.api_keys.0.get(key))
+                .map(|key| {
+                    Some((
+                        Client::ApiKey(key.name.clone()),
+                        key.limit.unwrap_or(self.default),
+                    ))
+                })
+                .ok_or_else(|| Status::unauthenticated("unknown API key"));
+        }
+        Ok(remote_addr(request).map(|peer| (Client::Peer(peer.ip()), self.default)))
+    }
+
+    /// Counts a request by `client`, taking a token from its bucket if it is
+    /// limited, and returning its quota, or `None` if it is not limited.
+    fn acquire(&self, client: Client, limit: Limit) -> Option<Quota> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_TRACKED_CLIENTS {
+            clients.retain(|_, state| state.refilled(now) < state.limit.burst);
+        }
+        let state = clients.entry(client).or_insert(ClientState {
+            limit,
+            tokens: limit.burst,
+            updated: now,
+            requests: 0,
+            rejected: 0,
+        });
+        state.requests += 1;
+        if limit.rate == 0.0 {
+            return None;
+        }
+
+        state.tokens = state.refilled(now);
+        state.updated = now;
+        let retry_after = if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            None
+        } else {
+            state.rejected += 1;
+            Some(((1.0 - state.tokens) / limit.rate).ceil() as u64)
+        };
+        Some(Quota {
+            limit: limit.burst as u64,
+            remaining: state.tokens as u64,
+            retry_after,
+        })
+    }
+}
+
+/// A service whose requests are rate limited by a [`RateLimitLayer`].
+#[derive(Clone, Debug)]
+pub struct RateLimit<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+}
+
+impl<S, B> Service<http::Request<B>> for RateLimit<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let quota = match self.limiter.identify(&request) {
+            Ok(Some((client, limit))) => {
+                let quota = self.limiter.acquire(client.clone(), limit);
+                if let Some(
+                    quota @ Quota {
+                        retry_after: Some(_),
+                        ..
+                    },
+                ) = quota
+                {
+                    tracing::debug!(%client, path = %request.uri().path(), "rate limited query");
+                    metrics::increment_counter!("node_query_requests_rejected_total");
+                    let mut response =
+                        Status::resource_exhausted("too many requests; slow down").to_http();
+                    quota.insert(response.headers_mut());
+                    return ResponseFuture::Rejected(Some(response));
+                }
+                quota
+            }
+            Ok(None) => None,
+            Err(status) => {
+                tracing::debug!(path = %request.uri().path(), "query with unknown API key");
+                return ResponseFuture::Rejected(Some(status.to_http()));
+            }
+        };
+        ResponseFuture::Admitted {
+            inner: self.inner.call(request),
+            quota,
+        }
+    }
+}
+
+/// The response future of a [`RateLimit`] service.
+#[pin_project(project = ResponseFutureProj)]
+pub enum ResponseFuture<F> {
+    Rejected(Option<http::Response<BoxBody>>),
+    Admitted {
+        #[pin]
+        inner: F,
+        quota: Option<Quota>,
+    },
+}
+
+impl<F, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<http::Response<BoxBody>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Rejected(response) => {
+                Poll::Ready(Ok(response.take().expect("polled after completion")))
+            }
+            ResponseFutureProj::Admitted { inner, quota } => {
+                let mut response = ready!(inner.poll(cx))?;
+                if let Some(quota) = quota {
+                    quota.insert(response.headers_mut());
+                }
+                Poll::Ready(Ok(response))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn limiter(rate: u32, burst: u32) -> Limiter {
+        Limiter {
+            default: Limit::new(rate, burst),
+            api_keys: Default::default(),
+            clients: Default::default(),
+        }
+    }
+
+    fn peer(ip: &str) -> Client {
+        Client::Peer(ip.parse().unwrap())
+    }
+
+    /// The remaining requests of an admitted request's quota, or the seconds
+    /// to wait if it was rejected.
+    fn acquire(limiter: &Limiter, client: Client) -> Result<u64, u64> {
+        let quota = limiter.acquire(client, limiter.default).unwrap();
+        match quota.retry_after {
+            None => Ok(quota.remaining),
+            Some(retry_after) => Err(retry_after),
+        }
+    }
+
+    #[test]
+    fn buckets_hold_a_burst_and_refill_at_the_rate() {
+        let limiter = limiter(2, 3);
+        let client = peer("10.0.0.1");
+
+        assert_eq!(acquire(&limiter, client.clone()), Ok(2));
+        assert_eq!(acquire(&limiter, client.clone()), Ok(1));
+        assert_eq!(acquire(&limiter, client.clone()), Ok(0));
+        assert_eq!(acquire(&limiter, client.clone()), Err(1));
+        // Each client has its own bucket.
+        assert_eq!(acquire(&limiter, peer("10.0.0.2")), Ok(2));
+
+        // A second later, the bucket has refilled by the rate.
+        let rewind = |limiter: &Limiter| {
+            let mut clients = limiter.clients.lock().unwrap();
+            let state = clients.get_mut(&client).unwrap();
+            state.updated -= Duration::from_secs(1);
+        };
+        rewind(&limiter);
+        assert_eq!(acquire(&limiter, client.clone()), Ok(1));
+        // ... but never beyond the burst.
+        rewind(&limiter);
+        rewind(&limiter);
+        assert_eq!(acquire(&limiter, client.clone()), Ok(2));
+    }
+
+    #[test]
+    fn unlimited_clients_are_only_counted() {
+        let layer = RateLimitLayer::new(0, 1);
+        for _ in 0..10 {
+            assert!(layer
+                .limiter
+                .acquire(peer("10.0.0.1"), Limit::new(0, 1))
+                .is_none());
+        }
+        assert_eq!(
+            layer.take_usage(),
+            vec![Usage {
+                client: "10.0.0.1".to_string(),
+                requests: 10,
+                rejected: 0,
+            }]
+        );
+        // Usage is counted afresh after it is taken.
+        assert!(layer.take_usage().is_empty());
+    }
+
+    #[test]
+    fn api_keys_are_parsed() -> anyhow::Result<()> {
+        let keys = ApiKeys::parse("# name key [rate burst]\n\npartner s3cret\n  bulk k3y 5 10\n")?;
+        assert_eq!(keys.0["s3cret"].name.as_ref(), "partner");
+        assert_eq!(keys.0["s3cret"].limit, None);
+        assert_eq!(keys.0["k3y"].limit, Some(Limit::new(5, 10)));
+
+        for (contents, problem) in [
+            ("partner", "line 1"),
+            ("partner s3cret 5", "line 1"),
+            ("partner s3cret 5 lots", "invalid limit on line 1"),
+            ("a s3cret\nb s3cret", "duplicate key on line 2"),
+        ] {
+            let error = ApiKeys::parse(contents).unwrap_err();
+            assert!(error.to_string().contains(problem), "{}", error);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejected_requests_never_reach_the_service() -> anyhow::Result<()> {
+        let layer = RateLimitLayer::new(1, 1)
+            .with_api_keys(ApiKeys::parse("partner s3cret\nunlimited fr33 0 0")?);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let service = layer.layer(tower::service_fn({
+            let calls = calls.clone();
+            move |_: http::Request<()>| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async { Ok::<_, Infallible>(http::Response::new(tonic::body::empty_body())) }
+            }
+        }));
+        let send = |api_key: &str| {
+            let request = http::Request::builder()
+                .header(API_KEY_HEADER, api_key)
+                .body(())
+                .unwrap();
+            service.clone().oneshot(request)
+        };
+        let header = |response: &http::Response<BoxBody>, name: &str| {
+            response
+                .headers()
+                .get(name)
+                .map(|value| value.to_str().unwrap().to_string())
+        };
+
+        let admitted = send("s3cret").await?;
+        assert_eq!(header(&admitted, "x-ratelimit-remaining").unwrap(), "0");
+        let rejected = send("s3cret").await?;
+        assert_eq!(header(&rejected, "grpc-status").unwrap(), "8");
+        assert_eq!(header(&rejected, "retry-after").unwrap(), "1");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Keys with their own limit are held to it instead.
+        for _ in 0..3 {
+            let admitted = send("fr33").await?;
+            assert_eq!(header(&admitted, "x-ratelimit-limit"), None);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        // Unknown keys are refused outright.
+        let refused = send("guess").await?;
+        assert_eq!(header(&refused, "grpc-status").unwrap(), "16");
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+//! Re-execution of recorded consensus requests.
+//!
+//! `pd start --consensus-replay-log` records every consensus request with the
+//! response to it in a [`ReplayLog`], which `pd replay` uses in two ways. To
+//! reproduce an app hash divergence offline, [`replay_log`] sends the recorded
+//! requests through a fresh consensus service, from genesis, comparing each
+//! response to the recorded one. To make performance work on the consensus
+//! path data-driven, [`replay`] executes recorded blocks against a temporary
+//! overlay, exactly as they were during consensus, but without ever committing
+//! anything to storage.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use bytes::Buf;
+use tendermint::abci::{
+    request::{EndBlock, Request},
+    response::Response,
+    ConsensusRequest, ConsensusResponse,
+};
+use tendermint_proto::Protobuf;
+use tokio_util::sync::CancellationToken;
+use tower::{Service, ServiceExt};
+
+use crate::{profile, proposal, App, Component, Consensus, NullifierCache, Storage};
+
+/// The result of replaying a single transaction.
+#[derive(Clone, Debug)]
+pub struct TxProfile {
+    /// The index of the transaction within its block.
+    pub index: usize,
+    /// The time spent validating and executing the transaction.
+    pub duration: Duration,
+    /// Whether the transaction was valid.
+    pub valid: bool,
+}
+
+/// The result of replaying a single block.
+#[derive(Clone, Debug)]
+pub struct BlockProfile {
+    pub height: u64,
+    pub begin_block: Duration,
+    pub txs: Vec<TxProfile>,
+    pub end_block: Duration,
+    /// Per-component timings and state access counts, if profiling was enabled.
+    pub report: profile::Report,
+}
+
+/// Re-executes the recorded blocks with heights in `from..=to` on top of the
+/// stored state as of height `from - 1`.
+///
+/// Returns one [`BlockProfile`] per replayed block.
+pub async fn replay(
+    storage: Storage,
+    requests: Vec<ConsensusRequest>,
+    from: u64,
+    to: u64,
+) -> Result<Vec<BlockProfile>> {
+    if from == 0 {
+        return Err(anyhow!("cannot replay the genesis block"));
+    }
+    let latest = storage
+        .latest_version()
+        .await?
+        .ok_or_else(|| anyhow!("storage is empty"))?;
+    if from - 1 > latest {
+        return Err(anyhow!(
+            "cannot replay from height {}: storage only contains state up to height {}",
+            from,
+            latest
+        ));
+    }
+
+    let overlay = storage.overlay_at(from - 1).await?;
+    let mut app = App::new(overlay.clone()).await?;
+    let mut profiles = Vec::new();
+    let mut current: Option<BlockProfile> = None;
+
+    for request in requests {
+        match request {
+            ConsensusRequest::BeginBlock(begin_block) => {
+                let height = begin_block.header.height.value();
+                if height < from {
+                    continue;
+                }
+                if height > to {
+                    break;
+                }
+                profile::take_report();
+                let start = Instant::now();
+                app.begin_block(&begin_block).await?;
+                current = Some(BlockProfile {
+                    height,
+                    begin_block: start.elapsed(),
+                    txs: Vec::new(),
+                    end_block: Duration::default(),
+                    report: Default::default(),
+                });
+            }
+            ConsensusRequest::DeliverTx(deliver_tx) => {
+                if let Some(block) = current.as_mut() {
+                    let start = Instant::now();
+                    let valid = match proposal::validate_tx(&app, deliver_tx.tx).await {
+                        Ok(transaction) => {
+                            app.execute_tx(&transaction).await?;
+                            true
+                        }
+                        Err(_) => false,
+                    };
+                    block.txs.push(TxProfile {
+                        index: block.txs.len(),
+                        duration: start.elapsed(),
+                        valid,
+                    });
+                }
+            }
+            ConsensusRequest::EndBlock(EndBlock { height }) => {
+                if let Some(mut block) = current.take() {
+                    let start = Instant::now();
+                    app.end_block(&EndBlock { height }).await?;
+                    block.end_block = start.elapsed();
+                    block.report = profile::take_report();
+                    profiles.push(block);
+
+                    // Instead of committing, start the next block with fresh
+                    // components reading through the same uncommitted overlay.
+                    app = App::new(overlay.clone()).await?;
+                }
+            }
+            ConsensusRequest::InitChain(_) | ConsensusRequest::Commit => {}
+        }
+    }
+
+    Ok(profiles)
+}
+
+/// An append-only file of consensus requests, each followed by the response
+/// to it, both length-delimited.
+#[derive(Debug)]
+pub struct ReplayLog {
+    file: File,
+}
+
+impl ReplayLog {
+    /// Opens the log at `path` for appending, creating it if it does not exist.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("cannot open replay log {:?}", path))?;
+        Ok(Self { file })
+    }
+
+    /// Appends a request and the response to it.
+    ///
+    /// The pair is written at once, so that only a crash can leave a partial
+    /// entry at the end of the log, and the log is synced at each `Commit`,
+    /// so that it holds every committed block.
+    pub fn record(&mut self, req: ConsensusRequest, rsp: ConsensusResponse) -> Result<()> {
+        let commit = matches!(req, ConsensusRequest::Commit);
+        let mut entry = Vec::new();
+        Request::from(req).encode_length_delimited(&mut entry)?;
+        Response::from(rsp).encode_length_delimited(&mut entry)?;
+        self.file.write_all(&entry)?;
+        if commit {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the entries of a [`ReplayLog`].
+pub fn decode_log(mut bytes: impl Buf) -> Result<Vec<(ConsensusRequest, ConsensusResponse)>> {
+    let mut entries = Vec::new();
+    while bytes.has_remaining() {
+        let index = entries.len();
+        let context = || {
+            format!(
+                "could not decode entry {} of the replay log, which may have been cut short by \
+                 a crash",
+                index
+            )
+        };
+        let req = Request::decode_length_delimited(&mut bytes).with_context(context)?;
+        let rsp = Response::decode_length_delimited(&mut bytes).with_context(context)?;
+        entries.push((
+            ConsensusRequest::try_from(req)
+                .map_err(|e| anyhow!(e))
+                .with_context(context)?,
+            ConsensusResponse::try_from(rsp)
+                .map_err(|e| anyhow!(e))
+                .with_context(context)?,
+        ));
+    }
+    Ok(entries)
+}
+
+/// A response to a replayed request which differs from the recorded one.
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    /// The index of the entry in the log.
+    pub index: usize,
+    /// The height of the block the request was part of, or 0 for `InitChain`.
+    pub height: u64,
+    /// The kind of request, e.g. `deliver_tx`.
+    pub request: &'static str,
+    pub recorded: String,
+    pub replayed: String,
+}
+
+/// The result of [`replay_log`].
+#[derive(Clone, Debug, Default)]
+pub struct LogReplay {
+    /// The number of entries replayed.
+    pub entries: usize,
+    /// The height of the last block replayed.
+    pub height: u64,
+    /// Every response which differed from the one recorded, in order.
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl LogReplay {
+    /// The height of the first block whose app hash differed from the one
+    /// recorded, at which the replay stopped.
+    pub fn app_hash_divergence(&self) -> Option<u64> {
+        self.mismatches
+            .iter()
+            .find(|mismatch| mismatch.request == "commit")
+            .map(|mismatch| mismatch.height)
+    }
+}
+
+/// Sends each recorded request through a consensus service on `storage`,
+/// which must be empty, comparing each response to the recorded one.
+///
+/// The replay stops after the first `Commit` whose app hash differs, since
+/// every later block executes on top of different state; the mismatches
+/// before it, e.g. of a transaction's result, point to the cause.
+pub async fn replay_log(
+    storage: Storage,
+    entries: Vec<(ConsensusRequest, ConsensusResponse)>,
+) -> Result<LogReplay> {
+    if let Some(latest) = storage.latest_version().await? {
+        return Err(anyhow!(
+            "the log is replayed from genesis, but storage already contains state up to height {}",
+            latest
+        ));
+    }
+
+    let (mut consensus, _, worker) = Consensus::new(
+        storage,
+        false,
+        false,
+        NullifierCache::default(),
+        CancellationToken::new(),
+        None,
+        None,
+    )
+    .await?;
+    let mut replay = LogReplay::default();
+
+    for (index, (req, recorded)) in entries.into_iter().enumerate() {
+        let request = match &req {
+            ConsensusRequest::InitChain(_) => "init_chain",
+            ConsensusRequest::BeginBlock(begin_block) => {
+                replay.height = begin_block.header.height.value();
+                "begin_block"
+            }
+            ConsensusRequest::DeliverTx(_) => "deliver_tx",
+            ConsensusRequest::EndBlock(_) => "end_block",
+            ConsensusRequest::Commit => "commit",
+        };
+        let replayed = consensus
+            .ready()
+            .await
+            .map_err(|e| anyhow!(e))?
+            .call(req)
+            .await
+            .map_err(|e| anyhow!(e))?;
+        replay.entries += 1;
+
+        let encode = |rsp: &ConsensusResponse| Response::from(rsp.clone()).encode_vec();
+        if encode(&recorded)? != encode(&replayed)? {
+            replay.mismatches.push(Mismatch {
+                index,
+                height: replay.height,
+                request,
+                recorded: format!("{:?}", recorded),
+                replayed: format!("{:?}", replayed),
+            });
+            if request == "commit" {
+                break;
+            }
+        }
+    }
+
+    // Dropping the service closes the worker's queue, which stops it.
+    drop(consensus);
+    worker.await??;
+    Ok(replay)
+}
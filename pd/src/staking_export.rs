@@ -0,0 +1,419 @@
+//! An HTTP export of the staking state, for community dashboards.
+//!
+//! Dashboards want the whole staking state at once: every validator with its
+//! rates, voting power, pool size and funding streams. Rather than have them
+//! stitch it together from many small RPCs every block, this serves it as a
+//! single JSON document per epoch, taken from the state at which the epoch
+//! began. Every node therefore serves byte-identical documents, which are
+//! safe to cache for as long as a CDN likes:
+//!
+//! - `GET /staking/v1/epochs/<index>` is the document for an epoch which has
+//!   begun, and never changes.
+//! - `GET /staking/v1/current` is the document for the current epoch, cached
+//!   only briefly since it changes when the next epoch begins.
+//!
+//! Both carry an `ETag`, so caches can revalidate cheaply. The chain does not
+//! track signing uptime, so the document has none.
+
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use penumbra_stake::{DelegationToken, Epoch};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tendermint::account;
+
+use crate::{
+    components::{app::View as _, staking::View as _},
+    Storage,
+};
+
+/// The version of the document format, which is also part of its path.
+pub const FORMAT_VERSION: u64 = 1;
+
+/// How long caches may serve the current epoch's document without
+/// revalidating it.
+const CURRENT_MAX_AGE_SECS: u64 = 60;
+
+/// How many epochs' documents to keep in memory.
+const CACHED_EPOCHS: usize = 16;
+
+/// A rendered document, with its entity tag.
+struct Document {
+    etag: String,
+    body: Arc<[u8]>,
+}
+
+/// The documents rendered by the export, by epoch index.
+///
+/// Rendering a document reads the whole staking state, so the last few are
+/// kept in memory. Cloning the cache shares it, so that it can be cleared
+/// while the export is serving.
+#[derive(Clone, Default)]
+pub struct DocumentCache(Arc<Mutex<BTreeMap<u64, Arc<Document>>>>);
+
+impl DocumentCache {
+    /// Drops every cached document, returning the number of bytes of their
+    /// bodies.
+    pub fn clear(&self) -> usize {
+        let documents = std::mem::take(&mut *self.0.lock().unwrap());
+        documents.values().map(|document| document.body.len()).sum()
+    }
+}
+
+/// Serves the staking export on `addr` until the server fails, caching
+/// rendered documents in `cache`.
+pub async fn serve(storage: Storage, addr: SocketAddr, cache: DocumentCache) -> Result<()> {
+    let export = Arc::new(Export { storage, cache });
+    let make_service = make_service_fn(move |_| {
+        let export = export.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let export = export.clone();
+                async move { Ok::<_, Infallible>(export.respond(request).await) }
+            }))
+        }
+    });
+
+    tracing::info!(?addr, "serving staking export");
+    hyper::Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+struct Export {
+    storage: Storage,
+    cache: DocumentCache,
+}
+
+impl Export {
+    async fn respond(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::GET {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+
+        let path = request.uri().path().trim_end_matches('/');
+        let (index, cache_control) = if path == "/staking/v1/current" {
+            match self.current_epoch().await {
+                Ok(epoch) => (
+                    epoch.index,
+                    format!("public, max-age={}", CURRENT_MAX_AGE_SECS),
+                ),
+                Err(error) => return internal_error(error),
+            }
+        } else if let Some(index) = path.strip_prefix("/staking/v1/epochs/") {
+            match index.parse() {
+                Ok(index) => (index, "public, max-age=31536000, immutable".to_string()),
+                Err(_) => return status(StatusCode::NOT_FOUND),
+            }
+        } else {
+            return status(StatusCode::NOT_FOUND);
+        };
+
+        let document = match self.document(index).await {
+            Ok(Some(document)) => document,
+            Ok(None) => return status(StatusCode::NOT_FOUND),
+            Err(error) => return internal_error(error),
+        };
+
+        let builder = Response::builder()
+            .header(header::ETAG, &document.etag)
+            .header(header::CACHE_CONTROL, cache_control);
+        let not_modified = request
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .map_or(false, |tag| tag.as_bytes() == document.etag.as_bytes());
+        if not_modified {
+            builder.status(StatusCode::NOT_MODIFIED).body(Body::empty())
+        } else {
+            builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(document.body.to_vec()))
+        }
+        .expect("response headers are valid")
+    }
+
+    async fn current_epoch(&self) -> Result<Epoch> {
+        self.storage.overlay().await?.get_current_epoch().await
+    }
+
+    /// The document for the epoch with the given index, or `None` if that
+    /// epoch has not begun.
+    async fn document(&self, index: u64) -> Result<Option<Arc<Document>>> {
+        if let Some(document) = self.cache.0.lock().unwrap().get(&index) {
+            return Ok(Some(document.clone()));
+        }
+
+        let version = match epoch_version(&self.storage, index).await? {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        let body = serde_json::to_vec(&render(&self.storage, version).await?)?;
+        let document = Arc::new(Document {
+            etag: format!("\"{}\"", hex::encode(&Sha256::digest(&body)[..16])),
+            body: body.into(),
+        });
+
+        let mut cache = self.cache.0.lock().unwrap();
+        cache.insert(index, document.clone());
+        while cache.len() > CACHED_EPOCHS {
+            let oldest = *cache.keys().next().expect("cache is not empty");
+            cache.remove(&oldest);
+        }
+
+        Ok(Some(document))
+    }
+}
+
+/// The first version of the state in the epoch with the given index, or
+/// `None` if that epoch has not begun.
+///
+/// That is the state committed by the block which ended the previous epoch,
+/// holding the new epoch's rates and voting powers. Epochs may be measured in
+/// block time, so their start heights are found by bisection.
+async fn epoch_version(storage: &Storage, index: u64) -> Result<Option<u64>> {
+    let latest = match storage.latest_version().await? {
+        Some(latest) => latest,
+        None => return Ok(None),
+    };
+    let epoch_at = |version| async move {
+        storage
+            .overlay_at(version)
+            .await?
+            .get_current_epoch()
+            .await
+            .map(|epoch| epoch.index)
+    };
+    if epoch_at(latest).await? < index {
+        return Ok(None);
+    }
+
+    // The first version whose epoch is at least `index` lies in `low..=high`.
+    let (mut low, mut high) = (0, latest);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if epoch_at(middle).await? < index {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    Ok(Some(low))
+}
+
+/// Renders the staking state at `version` as a document.
+async fn render(storage: &Storage, version: u64) -> Result<serde_json::Value> {
+    let overlay = storage.overlay_at(version).await?;
+    let epoch = overlay.get_current_epoch().await?;
+    let base_rate = overlay.current_base_rate().await?;
+
+    let mut validators = Vec::new();
+    let mut total_voting_power = 0u64;
+    for identity_key in overlay.validator_list().await? {
+        let info = overlay
+            .validator_info(&identity_key)
+            .await?
+            .ok_or_else(|| anyhow!("validator {} is listed but missing", identity_key))?;
+        let validator = info.validator;
+        let pool_size = overlay.pool_size(&identity_key).await?;
+        let bonded_since = overlay.validator_bonded_since(&identity_key).await?;
+        total_voting_power += info.status.voting_power;
+
+        validators.push(json!({
+            "identity_key": identity_key.to_string(),
+            "consensus_address": account::Id::from(validator.consensus_key).to_string(),
+            "name": validator.name,
+            "website": validator.website,
+            "description": validator.description,
+            "state": info.status.state.name().to_str(),
+            "bonded_since_epoch": bonded_since,
+            "voting_power": info.status.voting_power,
+            "delegation_denom": DelegationToken::new(identity_key.clone()).denom().to_string(),
+            "reward_rate": info.rate_data.validator_reward_rate,
+            "exchange_rate": info.rate_data.validator_exchange_rate,
+            "pool_size": pool_size.map(|pool| pool.amount),
+            "funding_streams": validator
+                .funding_streams
+                .iter()
+                .map(|stream| json!({
+                    "address": stream.address.to_string(),
+                    "rate_bps": stream.rate_bps,
+                }))
+                .collect::<Vec<_>>(),
+        }));
+    }
+
+    Ok(json!({
+        "format_version": FORMAT_VERSION,
+        "chain_id": overlay.get_chain_id().await?,
+        "epoch": {
+            "index": epoch.index,
+            "start_height": epoch.start_height,
+        },
+        "height": version,
+        "base_reward_rate": base_rate.base_reward_rate,
+        "base_exchange_rate": base_rate.base_exchange_rate,
+        "total_voting_power": total_voting_power,
+        "validators": validators,
+    }))
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .expect("response is valid")
+}
+
+fn internal_error(error: anyhow::Error) -> Response<Body> {
+    tracing::warn!(?error, "could not render staking export");
+    status(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        genesis,
+        testing::{address, delegations, validator, Node},
+    };
+
+    async fn export() -> Result<(Node, Export)> {
+        let validators = vec![validator("a")];
+        let node = Node::start(genesis::AppState {
+            allocations: delegations(&validators, 1_000, address()),
+            validators,
+            ..Default::default()
+        })
+        .await?;
+        let export = Export {
+            storage: node.storage().clone(),
+            cache: Default::default(),
+        };
+        Ok((node, export))
+    }
+
+    async fn get(export: &Export, path: &str, etag: Option<&str>) -> Response<Body> {
+        let mut request = Request::get(path);
+        if let Some(etag) = etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        export.respond(request.body(Body::empty()).unwrap()).await
+    }
+
+    fn header_value<'a>(response: &'a Response<Body>, name: header::HeaderName) -> &'a str {
+        response.headers()[name].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_epoch_documents() -> Result<()> {
+        let (_node, export) = export().await?;
+
+        let current = get(&export, "/staking/v1/current", None).await;
+        assert_eq!(current.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&current, header::CACHE_CONTROL),
+            "public, max-age=60"
+        );
+        let etag = header_value(&current, header::ETAG).to_string();
+        let body = hyper::body::to_bytes(current.into_body()).await?;
+        let document: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(document["format_version"], FORMAT_VERSION);
+        assert_eq!(document["epoch"]["index"], 0);
+        assert_eq!(document["validators"].as_array().unwrap().len(), 1);
+
+        // The current epoch's document is the one served under its index.
+        let epoch = get(&export, "/staking/v1/epochs/0/", None).await;
+        assert_eq!(epoch.status(), StatusCode::OK);
+        assert_eq!(header_value(&epoch, header::ETAG), etag);
+        assert!(header_value(&epoch, header::CACHE_CONTROL).contains("immutable"));
+        assert_eq!(hyper::body::to_bytes(epoch.into_body()).await?, body);
+
+        let revalidated = get(&export, "/staking/v1/epochs/0", Some(&etag)).await;
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+        assert!(hyper::body::to_bytes(revalidated.into_body())
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clearing_the_cache_drops_rendered_documents() -> Result<()> {
+        let (_node, export) = export().await?;
+        assert_eq!(export.cache.clear(), 0);
+
+        let response = get(&export, "/staking/v1/epochs/0", None).await;
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let cache = export.cache.clone();
+        assert_eq!(cache.clear(), body.len());
+        assert!(export.cache.0.lock().unwrap().is_empty());
+
+        // The document is rendered again, identically.
+        let response = get(&export, "/staking/v1/epochs/0", None).await;
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await?, body);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_other_requests() -> Result<()> {
+        let (_node, export) = export().await?;
+
+        for path in [
+            "/staking/v1/epochs/1",
+            "/staking/v1/epochs/first",
+            "/staking/v2/current",
+        ] {
+            assert_eq!(
+                get(&export, path, None).await.status(),
+                StatusCode::NOT_FOUND,
+                "{}",
+                path
+            );
+        }
+        let request = Request::post("/staking/v1/current")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            export.respond(request).await.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn finds_the_first_version_of_each_epoch() -> Result<()> {
+        let storage = Storage::in_memory().await?;
+        assert_eq!(epoch_version(&storage, 0).await?, None);
+
+        // Versions 0 to 9, in epochs of three versions each.
+        for version in 0..10 {
+            let overlay = storage.overlay().await?;
+            overlay
+                .put_current_epoch(Epoch {
+                    index: version / 3,
+                    start_height: version / 3 * 3,
+                })
+                .await;
+            overlay.lock().await.commit(storage.clone()).await?;
+        }
+
+        for (index, version) in [(0, Some(0)), (1, Some(3)), (2, Some(6)), (3, Some(9))] {
+            assert_eq!(epoch_version(&storage, index).await?, version);
+        }
+        assert_eq!(epoch_version(&storage, 4).await?, None);
+
+        Ok(())
+    }
+}
@@ -1,31 +1,172 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Instant};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use futures::future::BoxFuture;
 use jmt::{
     storage::{Node, NodeBatch, NodeKey, TreeReader, TreeWriter},
     WriteOverlay,
 };
-use rocksdb::DB;
+use rocksdb::{ColumnFamily, Env, Options, DB};
 use tokio::sync::Mutex;
 use tracing::{instrument, Span};
 
+mod app_hashes;
+mod format;
 mod overlay_ext;
+mod proof;
+mod prune;
+mod rollback;
+mod state_file;
+mod tuning;
+mod tx_results;
 
+pub use format::FORMAT_VERSION;
 pub use overlay_ext::OverlayExt;
+pub use prune::Pruned;
+pub use rollback::Rollback;
+pub use state_file::StateFile;
+use tuning::BlockCache;
+pub use tuning::{CompactionStyle, Tuning};
 
 pub type Overlay = Arc<Mutex<WriteOverlay<Storage>>>;
 
+/// The column family holding the nodes of the JMT, keyed by encoded node key.
+///
+/// Compact blocks and the other indexes kept by the components are written to
+/// the JMT, and so are stored here too: they contribute to the app hash, so
+/// moving them elsewhere would fork the chain.
+const JMT_CF: &str = "jmt";
+
+/// The column families of the database, besides the default one, which is
+/// unused, except by databases in storage formats before 2.
+///
+/// Data outside the JMT column family is local to this node: it is not part of
+/// the JMT, so it does not contribute to the app hash. Adding a column family,
+/// or changing the layout of one, changes the storage format, which needs a
+/// migration (see [`FORMAT_VERSION`]).
+const COLUMN_FAMILIES: [&str; 4] = [
+    JMT_CF,
+    format::META_CF,
+    app_hashes::APP_HASHES_CF,
+    tx_results::TX_RESULTS_CF,
+];
+
+/// The handle of the column family holding the nodes of the JMT.
+fn jmt_cf(db: &DB) -> Result<&ColumnFamily> {
+    db.cf_handle(JMT_CF)
+        .ok_or_else(|| anyhow!("missing {} column family", JMT_CF))
+}
+
+/// Flushes the in-memory write buffers of every column family to disk.
+fn flush_all(db: &DB) -> Result<()> {
+    db.flush()?;
+    for name in COLUMN_FAMILIES {
+        if let Some(cf) = db.cf_handle(name) {
+            db.flush_cf(cf)?;
+        }
+    }
+    Ok(())
+}
+
+/// The database, and its block cache, which read-only databases do without.
 #[derive(Clone, Debug)]
-pub struct Storage(Arc<DB>);
+pub struct Storage(Arc<DB>, Option<BlockCache>);
 
 impl Storage {
+    /// Opens the database at `path`, creating it if it does not exist.
+    ///
+    /// A database in an older storage format is migrated if every migration it
+    /// needs is automatic; otherwise, as for a database in a newer format,
+    /// opening it fails, rather than misreading it.
     pub async fn load(path: PathBuf) -> Result<Self> {
+        Self::load_tuned(path, Tuning::default()).await
+    }
+
+    /// Like [`Self::load`], but tuning the database with `tuning`.
+    pub async fn load_tuned(path: PathBuf, tuning: Tuning) -> Result<Self> {
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                tracing::info!(?path, ?tuning, "opening rocksdb");
+                let (db, cache) = Self::open(path, &tuning)?;
+                let (from, migrated) = format::migrate(&db, false)?;
+                if !migrated.is_empty() {
+                    tracing::info!(?from, to = FORMAT_VERSION, "migrated storage format");
+                }
+                Ok(Self(Arc::new(db), Some(cache)))
+            })
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Migrates the database at `path` to the current storage format, running
+    /// every migration it needs, including those which are not automatic.
+    ///
+    /// Returns the format the database was in, or `None` if it was empty, and
+    /// the descriptions of the migrations run.
+    pub async fn migrate(path: PathBuf) -> Result<(Option<u32>, Vec<&'static str>)> {
         let span = Span::current();
         tokio::task::spawn_blocking(move || {
             span.in_scope(|| {
-                tracing::info!(?path, "opening rocksdb");
-                Ok(Self(Arc::new(DB::open_default(path)?)))
+                tracing::info!(?path, "migrating rocksdb");
+                format::migrate(&Self::open(path, &Tuning::default())?.0, true)
+            })
+        })
+        .await
+        .unwrap()
+    }
+
+    fn open(path: PathBuf, tuning: &Tuning) -> Result<(DB, BlockCache)> {
+        let cache = tuning.block_cache()?;
+        let db =
+            DB::open_cf_descriptors(&tuning.db_options(), path, tuning.column_families(&cache))?;
+        Ok((db, cache))
+    }
+
+    /// Opens an existing database read-only, so that nothing done through the
+    /// returned `Storage` can modify it: every write fails.
+    ///
+    /// This is for inspecting a data directory which may be corrupt, without
+    /// risking further damage. Since it cannot be migrated, the database must
+    /// already be in the current storage format.
+    pub async fn load_read_only(path: PathBuf) -> Result<Self> {
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                tracing::info!(?path, "opening rocksdb read-only");
+                let opts = Options::default();
+                // Column families missing from older formats cannot be
+                // created, so only the existing ones are opened, and the
+                // format check reports the database as needing migration.
+                let existing = DB::list_cf(&opts, &path)?;
+                let column_families = COLUMN_FAMILIES
+                    .into_iter()
+                    .filter(|cf| existing.iter().any(|existing| existing == cf));
+                let db = DB::open_cf_for_read_only(&opts, path, column_families, false)?;
+                format::check(&db)?;
+                Ok(Self(Arc::new(db), None))
+            })
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Opens a fresh database held entirely in memory, which is discarded when
+    /// the last handle to it is dropped.
+    pub async fn in_memory() -> Result<Self> {
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                tracing::debug!("opening in-memory rocksdb");
+                let tuning = Tuning::default();
+                let mut opts = tuning.db_options();
+                opts.set_env(&Env::mem_env()?);
+                let cache = tuning.block_cache()?;
+                let column_families = tuning.column_families(&cache);
+                let db = DB::open_cf_descriptors(&opts, "in-memory", column_families)?;
+                format::migrate(&db, false)?;
+                Ok(Self(Arc::new(db), Some(cache)))
             })
         })
         .await
@@ -57,6 +198,18 @@ impl Storage {
         ))))
     }
 
+    /// Returns a new [`Overlay`] on top of a specific past version of the tree.
+    ///
+    /// Writes to the overlay are never visible to other overlays unless it is
+    /// committed, so this can be used to re-execute past blocks in isolation.
+    pub async fn overlay_at(&self, version: jmt::Version) -> Result<Overlay> {
+        tracing::debug!("creating overlay for version {}", version);
+        Ok(Arc::new(Mutex::new(WriteOverlay::new(
+            self.clone(),
+            version,
+        ))))
+    }
+
     /// Like [`Self::overlay`], but bundles in a [`tonic`] error conversion.
     ///
     /// This is useful for implementing gRPC services that query the storage:
@@ -68,6 +221,87 @@ impl Storage {
             .await
             .map_err(|e| tonic::Status::internal(e.to_string()))
     }
+
+    /// Writes a consistent checkpoint of the database into `path`, which must not exist.
+    ///
+    /// Returns the version (block height) of the checkpointed tree, which is
+    /// read back from the checkpoint, since blocks may be committed while it
+    /// is being written.
+    pub async fn checkpoint(&self, path: PathBuf) -> Result<Option<jmt::Version>> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || {
+                span.in_scope(|| {
+                    tracing::info!(?path, "writing rocksdb checkpoint");
+                    rocksdb::checkpoint::Checkpoint::new(&db)?.create_checkpoint(path)?;
+                    Ok::<_, anyhow::Error>(())
+                })
+            }
+        })
+        .await
+        .unwrap()?;
+
+        let version = Self::load_read_only(path).await?.latest_version().await?;
+        tracing::info!(?version, "wrote rocksdb checkpoint");
+        Ok(version)
+    }
+
+    /// Returns the size of the value of every leaf of the tree at `version`,
+    /// by key hash.
+    ///
+    /// Keys are never deleted from the tree, so the leaves at `version` are
+    /// the latest leaf written for each key up to it. Node keys start with the
+    /// big-endian version, so those are the last ones seen in key order.
+    pub async fn leaf_sizes(&self, version: jmt::Version) -> Result<HashMap<[u8; 32], usize>> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                let mut sizes = HashMap::new();
+                let mut iter = db.raw_iterator_cf(jmt_cf(&db)?);
+                iter.seek_to_first();
+                while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                    if NodeKey::decode(key)?.version() > version {
+                        break;
+                    }
+                    if let Node::Leaf(leaf) = Node::decode(value)? {
+                        sizes.insert(leaf.key_hash().0, leaf.value().len());
+                    }
+                    iter.next();
+                }
+                iter.status()?;
+                Ok(sizes)
+            })
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Flushes the database's memtables (its in-memory write buffers) to disk.
+    pub async fn flush(&self) -> Result<()> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                tracing::info!("flushing rocksdb memtables");
+                flush_all(&db)
+            })
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Evicts every block from the database's block cache which is not in use
+    /// by a reader, returning the number of bytes evicted.
+    ///
+    /// Subsequent reads go to disk until the cache warms up again.
+    pub fn clear_block_cache(&self) -> usize {
+        let evicted = self.1.as_ref().map_or(0, BlockCache::clear);
+        tracing::info!(evicted, "cleared rocksdb block cache");
+        evicted
+    }
 }
 
 impl TreeWriter for Storage {
@@ -89,12 +323,15 @@ impl TreeWriter for Storage {
         Box::pin(async {
             tokio::task::spawn_blocking(move || {
                 span.in_scope(|| {
+                    let started = Instant::now();
+                    let cf = jmt_cf(&db)?;
                     for (node_key, node) in node_batch.clone() {
                         let key_bytes = &node_key.encode()?;
                         let value_bytes = &node.encode()?;
                         tracing::trace!(?key_bytes, value_bytes = ?hex::encode(&value_bytes));
-                        db.put(key_bytes, value_bytes)?;
+                        db.put_cf(cf, key_bytes, value_bytes)?;
                     }
+                    metrics::histogram!("node_storage_write_duration_seconds", started.elapsed());
 
                     Ok(())
                 })
@@ -122,10 +359,12 @@ impl TreeReader for Storage {
         Box::pin(async {
             tokio::task::spawn_blocking(move || {
                 span.in_scope(|| {
+                    let started = Instant::now();
                     let value = db
-                        .get_pinned(&node_key.encode()?)?
+                        .get_pinned_cf(jmt_cf(&db)?, &node_key.encode()?)?
                         .map(|db_slice| Node::decode(&db_slice))
                         .transpose()?;
+                    metrics::histogram!("node_storage_read_duration_seconds", started.elapsed());
 
                     tracing::trace!(?node_key, ?value);
                     Ok(value)
@@ -145,7 +384,7 @@ impl TreeReader for Storage {
         Box::pin(async {
             tokio::task::spawn_blocking(move || {
                 span.in_scope(|| {
-                    let mut iter = db.raw_iterator();
+                    let mut iter = db.raw_iterator_cf(jmt_cf(&db)?);
                     let mut ret = None;
                     iter.seek_to_last();
 
@@ -157,7 +396,7 @@ impl TreeReader for Storage {
                             ret = Some((node_key, leaf_node));
                         }
                     } else {
-                        // There are no keys in the database
+                        // There are no nodes in the database
                     }
                     Ok(ret)
                 })
@@ -167,3 +406,174 @@ impl TreeReader for Storage {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use jmt::RootHash;
+    use penumbra_proto::chain::TxResult;
+
+    use super::*;
+
+    /// Commits `value` under `key`, returning the new version.
+    async fn commit(storage: &Storage, key: &str, value: u64) -> Result<jmt::Version> {
+        let overlay = storage.overlay().await?;
+        overlay.put_proto(key.into(), value).await;
+        let (_, version) = overlay.lock().await.commit(storage.clone()).await?;
+        Ok(version)
+    }
+
+    #[tokio::test]
+    async fn read_only_storage_reads_but_never_writes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("rocksdb");
+        let storage = Storage::load(path.clone()).await?;
+        commit(&storage, "a", 1).await?;
+        let version = commit(&storage, "b", 2).await?;
+        drop(storage);
+
+        let storage = Storage::load_read_only(path).await?;
+        assert_eq!(storage.latest_version().await?, Some(version));
+        let overlay = storage.overlay().await?;
+        assert_eq!(overlay.get_proto::<u64>("a".into()).await?, Some(1));
+        assert_eq!(overlay.get_proto::<u64>("b".into()).await?, Some(2));
+
+        assert!(commit(&storage, "c", 3).await.is_err());
+        assert!(storage
+            .put_tx_results(vec![([0; 32], Default::default())])
+            .await
+            .is_err());
+        assert_eq!(storage.latest_version().await?, Some(version));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_only_storage_is_never_created() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("rocksdb");
+
+        assert!(Storage::load_read_only(path.clone()).await.is_err());
+        assert!(!path.exists());
+
+        Ok(())
+    }
+
+    /// The number of keys in the column family `name`, or in the default one.
+    fn count_keys(storage: &Storage, name: Option<&str>) -> Result<usize> {
+        let db = &storage.0;
+        let mut iter = match name {
+            Some(name) => db.raw_iterator_cf(
+                db.cf_handle(name)
+                    .ok_or_else(|| anyhow!("missing {} column family", name))?,
+            ),
+            None => db.raw_iterator(),
+        };
+        let mut count = 0;
+        iter.seek_to_first();
+        while iter.valid() {
+            count += 1;
+            iter.next();
+        }
+        iter.status()?;
+        Ok(count)
+    }
+
+    #[tokio::test]
+    async fn each_kind_of_data_has_its_own_column_family() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let storage = Storage::load(dir.path().join("rocksdb")).await?;
+        // Only the storage format is recorded until something is written.
+        assert_eq!(count_keys(&storage, Some(format::META_CF))?, 1);
+        assert_eq!(count_keys(&storage, Some(JMT_CF))?, 0);
+
+        let version = commit(&storage, "a", 1).await?;
+        storage.put_app_hash(version, RootHash([1; 32])).await?;
+        storage
+            .put_tx_results(vec![([2; 32], TxResult::default())])
+            .await?;
+
+        assert!(count_keys(&storage, Some(JMT_CF))? > 0);
+        assert_eq!(count_keys(&storage, Some(format::META_CF))?, 1);
+        assert_eq!(count_keys(&storage, Some(app_hashes::APP_HASHES_CF))?, 1);
+        assert_eq!(count_keys(&storage, Some(tx_results::TX_RESULTS_CF))?, 1);
+        assert_eq!(count_keys(&storage, None)?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clearing_the_block_cache_evicts_what_reads_loaded() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let storage = Storage::load(dir.path().join("rocksdb")).await?;
+        let cache = storage
+            .1
+            .clone()
+            .expect("writable storage has a block cache");
+        commit(&storage, "a", 1).await?;
+        storage.flush().await?;
+
+        // Reading from the flushed table files loads their blocks.
+        let overlay = storage.overlay().await?;
+        assert_eq!(overlay.get_proto::<u64>("a".into()).await?, Some(1));
+        let loaded = cache.usage();
+        assert!(loaded > 0);
+
+        assert_eq!(storage.clear_block_cache(), loaded);
+        assert_eq!(cache.usage(), 0);
+        // The data is still there, only no longer cached.
+        let overlay = storage.overlay().await?;
+        assert_eq!(overlay.get_proto::<u64>("a".into()).await?, Some(1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reopening_keeps_the_data_whatever_the_tuning() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("rocksdb");
+        let storage = Storage::load(path.clone()).await?;
+        commit(&storage, "a", 1).await?;
+        let version = commit(&storage, "b", 2).await?;
+        storage.put_app_hash(version, RootHash([1; 32])).await?;
+        storage
+            .put_tx_results(vec![([2; 32], TxResult::default())])
+            .await?;
+        drop(storage);
+
+        let tuning = Tuning {
+            block_cache_size: 1024 * 1024,
+            compaction_style: CompactionStyle::Universal,
+            max_total_wal_size: 1024 * 1024,
+            wal_bytes_per_sync: 1024,
+            bytes_per_sync: 1024,
+            use_fsync: true,
+        };
+        let storage = Storage::load_tuned(path.clone(), tuning).await?;
+        assert_eq!(storage.latest_version().await?, Some(version));
+        let overlay = storage.overlay().await?;
+        assert_eq!(overlay.get_proto::<u64>("a".into()).await?, Some(1));
+        assert_eq!(overlay.get_proto::<u64>("b".into()).await?, Some(2));
+        assert_eq!(storage.app_hash(version).await?, Some(RootHash([1; 32])));
+        assert_eq!(storage.tx_result([2; 32]).await?, Some(TxResult::default()));
+        assert_eq!(commit(&storage, "c", 3).await?, version + 1);
+        drop(overlay);
+        drop(storage);
+
+        let mut column_families = DB::list_cf(&Options::default(), &path)?;
+        column_families.sort();
+        let mut expected = vec!["default".to_string()];
+        expected.extend(COLUMN_FAMILIES.iter().map(|name| name.to_string()));
+        expected.sort();
+        assert_eq!(column_families, expected);
+
+        // Nothing needed migrating, either time.
+        assert_eq!(
+            Storage::migrate(path.clone()).await?,
+            (Some(FORMAT_VERSION), Vec::new())
+        );
+        let storage = Storage::load(path).await?;
+        assert_eq!(storage.latest_version().await?, Some(version + 1));
+
+        Ok(())
+    }
+}
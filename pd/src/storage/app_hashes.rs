@@ -0,0 +1,54 @@
+use anyhow::{anyhow, Result};
+use jmt::{RootHash, Version};
+use tracing::Span;
+
+use super::Storage;
+
+/// The column family holding the app hash committed at each version, keyed by
+/// the big-endian version.
+pub(super) const APP_HASHES_CF: &str = "app_hashes";
+
+impl Storage {
+    /// Records the app hash committed at `version`, so that the tree can later
+    /// be checked against it.
+    pub async fn put_app_hash(&self, version: Version, app_hash: RootHash) -> Result<()> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                let cf = db
+                    .cf_handle(APP_HASHES_CF)
+                    .ok_or_else(|| anyhow!("missing {} column family", APP_HASHES_CF))?;
+                Ok(db.put_cf(cf, version.to_be_bytes(), app_hash.0)?)
+            })
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Reads the app hash recorded as committed at `version`, if any.
+    ///
+    /// Versions committed before app hashes were recorded have none.
+    pub async fn app_hash(&self, version: Version) -> Result<Option<RootHash>> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                let cf = db
+                    .cf_handle(APP_HASHES_CF)
+                    .ok_or_else(|| anyhow!("missing {} column family", APP_HASHES_CF))?;
+                db.get_pinned_cf(cf, version.to_be_bytes())?
+                    .map(|bytes| {
+                        let bytes: [u8; 32] = bytes
+                            .as_ref()
+                            .try_into()
+                            .map_err(|_| anyhow!("malformed app hash for version {}", version))?;
+                        Ok(RootHash(bytes))
+                    })
+                    .transpose()
+            })
+        })
+        .await
+        .unwrap()
+    }
+}
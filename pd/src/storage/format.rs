@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use rocksdb::{WriteBatch, DB};
+
+use super::{flush_all, jmt_cf};
+
+/// The column family holding metadata about the database itself.
+pub(super) const META_CF: &str = "meta";
+
+/// The key of the storage format version in [`META_CF`].
+const FORMAT_KEY: &[u8] = b"format_version";
+
+/// The storage format this version of `pd` reads and writes.
+///
+/// This must be bumped, with a [`Migration`] to the new format, whenever the
+/// column families or the layout of their keys and values change, so that
+/// existing databases are migrated rather than misread, and older versions of
+/// `pd` refuse to open databases they would misread.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// A change from the previous storage format.
+struct Migration {
+    /// The format this migrates to, from the one before it.
+    to: u32,
+    description: &'static str,
+    /// Whether the migration is quick and safe enough to run whenever an older
+    /// database is opened. Others must be run with `pd migrate-storage`.
+    automatic: bool,
+    run: fn(&DB) -> Result<()>,
+}
+
+/// Every migration, in order.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        to: 1,
+        description: "add the app hash and transaction result column families",
+        automatic: true,
+        // Missing column families are created when the database is opened.
+        run: nothing_to_do,
+    },
+    Migration {
+        to: 2,
+        description: "move the tree's nodes from the default column family into their own",
+        automatic: false,
+        run: move_nodes_to_jmt_cf,
+    },
+];
+
+/// How many nodes to move in each write while migrating.
+const MIGRATION_BATCH_SIZE: usize = 10_000;
+
+fn nothing_to_do(_db: &DB) -> Result<()> {
+    Ok(())
+}
+
+fn move_nodes_to_jmt_cf(db: &DB) -> Result<()> {
+    let cf = jmt_cf(db)?;
+    let mut moved = 0;
+    let mut batch = WriteBatch::default();
+    // Each node is moved by a single write, so an interrupted migration can
+    // be run again to move the rest.
+    let mut iter = db.raw_iterator();
+    iter.seek_to_first();
+    while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+        batch.put_cf(cf, key, value);
+        batch.delete(key);
+        moved += 1;
+        if batch.len() >= MIGRATION_BATCH_SIZE {
+            db.write(std::mem::take(&mut batch))?;
+            tracing::debug!(moved, "moving tree nodes");
+        }
+        iter.next();
+    }
+    iter.status()?;
+    db.write(batch)?;
+    db.compact_range(None::<&[u8]>, None::<&[u8]>);
+    tracing::info!(moved, "moved tree nodes");
+    Ok(())
+}
+
+/// The storage format of the database, or `None` if it is empty.
+///
+/// Databases written before the format was recorded are format 0.
+pub(super) fn stored_format(db: &DB) -> Result<Option<u32>> {
+    if let Some(cf) = db.cf_handle(META_CF) {
+        if let Some(bytes) = db.get_pinned_cf(cf, FORMAT_KEY)? {
+            let bytes: [u8; 4] = bytes
+                .as_ref()
+                .try_into()
+                .map_err(|_| anyhow!("malformed storage format version"))?;
+            return Ok(Some(u32::from_be_bytes(bytes)));
+        }
+    }
+
+    let mut iter = db.raw_iterator();
+    iter.seek_to_first();
+    if iter.valid() {
+        Ok(Some(0))
+    } else {
+        iter.status()?;
+        Ok(None)
+    }
+}
+
+/// Checks that this version of `pd` can read the database as it is.
+pub(super) fn check(db: &DB) -> Result<()> {
+    match stored_format(db)? {
+        None => Ok(()),
+        Some(format) if format > FORMAT_VERSION => Err(newer_format(format)),
+        Some(format) if format < FORMAT_VERSION => Err(anyhow!(
+            "the database is in storage format {}, older than format {} of this version of pd; \
+             open it read-write, or run `pd migrate-storage`, to migrate it",
+            format,
+            FORMAT_VERSION
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Migrates the database to [`FORMAT_VERSION`], returning the format it was
+/// in, if it was not empty, and the descriptions of the migrations run.
+///
+/// Unless `manual` is set, only automatic migrations are run, and a database
+/// needing any other migration is left as it is, with an error.
+pub(super) fn migrate(db: &DB, manual: bool) -> Result<(Option<u32>, Vec<&'static str>)> {
+    let cf = db
+        .cf_handle(META_CF)
+        .ok_or_else(|| anyhow!("missing {} column family", META_CF))?;
+    let format = match stored_format(db)? {
+        Some(format) => format,
+        None => {
+            db.put_cf(cf, FORMAT_KEY, FORMAT_VERSION.to_be_bytes())?;
+            return Ok((None, Vec::new()));
+        }
+    };
+    if format > FORMAT_VERSION {
+        return Err(newer_format(format));
+    }
+
+    let pending = MIGRATIONS.iter().filter(|migration| migration.to > format);
+    if !manual {
+        if let Some(migration) = pending.clone().find(|migration| !migration.automatic) {
+            return Err(anyhow!(
+                "the database is in storage format {}, and must be migrated to format {} ({}) \
+                 with `pd migrate-storage` before it can be opened",
+                format,
+                migration.to,
+                migration.description
+            ));
+        }
+    }
+
+    let mut migrated = Vec::new();
+    for migration in pending {
+        tracing::info!(
+            to = migration.to,
+            description = migration.description,
+            "migrating storage format"
+        );
+        (migration.run)(db)?;
+        db.put_cf(cf, FORMAT_KEY, migration.to.to_be_bytes())?;
+        migrated.push(migration.description);
+    }
+    if !migrated.is_empty() {
+        flush_all(db)?;
+    }
+    Ok((Some(format), migrated))
+}
+
+fn newer_format(format: u32) -> anyhow::Error {
+    anyhow!(
+        "the database is in storage format {}, written by a newer version of pd, which only \
+         reads format {}; upgrade pd to open it",
+        format,
+        FORMAT_VERSION
+    )
+}
@@ -1,9 +1,15 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    sync::Arc,
+};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use futures::future::BoxFuture;
 use jmt::{storage::TreeReader, KeyHash, WriteOverlay};
 use penumbra_proto::{Message, Protobuf};
+use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use tracing::instrument;
 
@@ -42,6 +48,320 @@ pub trait OverlayExt: Send + Sync + Sized + Clone + 'static {
     async fn put_proto<P>(&self, key: KeyHash, value: P)
     where
         P: Message + Debug;
+
+    /// Puts `value` under `key` in the ordered keyspace `space`, so that it is
+    /// returned by [`OverlayExt::prefix_range`] scans of the space.
+    ///
+    /// The tree is keyed by the hashes of keys, so it has no order of its own,
+    /// and its keys cannot be scanned. Instead, each ordered keyspace keeps a
+    /// sorted index of its keys in the state, which every node reads back in
+    /// the same order. The index is a trie of buckets of a bounded number of
+    /// keys each, so a write reads and rewrites a few small buckets, however
+    /// large the keyspace.
+    async fn put_ordered(&self, space: &str, key: Vec<u8>, value: Vec<u8>) -> Result<()>;
+
+    /// Removes `key` from the ordered keyspace `space`.
+    async fn delete_ordered(&self, space: &str, key: Vec<u8>) -> Result<()>;
+
+    /// Returns every entry of the ordered keyspace `space` whose key starts
+    /// with `prefix`, in lexicographic order of their keys.
+    ///
+    /// The scan includes the overlay's pending writes and deletions, exactly as
+    /// if they had been committed, so the order never depends on whether, or
+    /// when, they were.
+    async fn prefix_range(&self, space: &str, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// Returns the state keys occupied by the ordered keyspace `space`, with
+    /// the families they belong to, for [`KeySchema`]s.
+    ///
+    /// [`KeySchema`]: crate::components::key_schema::KeySchema
+    async fn ordered_state_keys(&self, space: &str) -> Result<Vec<(&'static str, String)>>;
+}
+
+/// The most keys an ordered keyspace's index keeps in one bucket before
+/// splitting it by the next byte of its keys.
+const ORDERED_BUCKET_LEN: usize = 64;
+
+/// A node of the index of an ordered keyspace: the keys starting with some
+/// prefix.
+///
+/// The index is a trie, whose root bucket has the empty prefix. A bucket
+/// holding more than [`ORDERED_BUCKET_LEN`] keys is split into a child bucket
+/// for each distinct byte following its prefix, so every bucket stays small.
+/// Buckets left empty by deletions are removed, but split buckets are not
+/// merged back together.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Bucket {
+    /// The keys themselves, in order.
+    Leaf(BTreeSet<Vec<u8>>),
+    Split {
+        /// Whether the prefix itself is a key.
+        exact: bool,
+        /// The bytes following the prefix which have non-empty child buckets.
+        children: BTreeSet<u8>,
+    },
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Bucket::Leaf(BTreeSet::new())
+    }
+}
+
+impl Bucket {
+    fn is_empty(&self) -> bool {
+        match self {
+            Bucket::Leaf(keys) => keys.is_empty(),
+            Bucket::Split { exact, children } => !exact && children.is_empty(),
+        }
+    }
+}
+
+/// The state key of the bucket of an ordered keyspace's index with `prefix`.
+fn ordered_bucket_key(space: &str, prefix: &[u8]) -> String {
+    format!("{}/ordered/buckets/{}", space, hex::encode(prefix))
+}
+
+/// The state key of the value of `key` in an ordered keyspace.
+fn ordered_entry_key(space: &str, key: &[u8]) -> String {
+    format!("{}/ordered/entries/{}", space, hex::encode(key))
+}
+
+async fn get_bucket<R: TreeReader + Sync>(
+    overlay: &WriteOverlay<R>,
+    space: &str,
+    prefix: &[u8],
+) -> Result<Bucket> {
+    crate::profile::count_read();
+    match overlay
+        .get(ordered_bucket_key(space, prefix).into())
+        .await?
+    {
+        // The tree cannot delete leaves, so removed buckets are emptied.
+        Some(bytes) if !bytes.is_empty() => Ok(bincode::deserialize(&bytes)?),
+        _ => Ok(Bucket::default()),
+    }
+}
+
+fn put_bucket<R: TreeReader + Sync>(
+    overlay: &mut WriteOverlay<R>,
+    space: &str,
+    prefix: &[u8],
+    bucket: &Bucket,
+) -> Result<()> {
+    crate::profile::count_write();
+    let bytes = if bucket.is_empty() {
+        Vec::new()
+    } else {
+        bincode::serialize(bucket)?
+    };
+    overlay.put(ordered_bucket_key(space, prefix).into(), bytes);
+    Ok(())
+}
+
+/// Writes `keys`, which all start with `prefix`, as the bucket with `prefix`,
+/// splitting it as many times as it takes to keep every bucket small.
+fn put_leaf<R: TreeReader + Sync>(
+    overlay: &mut WriteOverlay<R>,
+    space: &str,
+    prefix: &[u8],
+    keys: BTreeSet<Vec<u8>>,
+) -> Result<()> {
+    if keys.len() <= ORDERED_BUCKET_LEN {
+        return put_bucket(overlay, space, prefix, &Bucket::Leaf(keys));
+    }
+
+    let mut exact = false;
+    let mut groups = BTreeMap::<u8, BTreeSet<Vec<u8>>>::new();
+    for key in keys {
+        match key.get(prefix.len()) {
+            Some(&byte) => {
+                groups.entry(byte).or_default().insert(key);
+            }
+            None => exact = true,
+        }
+    }
+    let children = groups.keys().copied().collect();
+    put_bucket(overlay, space, prefix, &Bucket::Split { exact, children })?;
+    for (byte, keys) in groups {
+        put_leaf(overlay, space, &[prefix, &[byte]].concat(), keys)?;
+    }
+    Ok(())
+}
+
+/// Adds `key` to the index of an ordered keyspace, returning whether it was
+/// absent.
+async fn insert_ordered_key<R: TreeReader + Sync>(
+    overlay: &mut WriteOverlay<R>,
+    space: &str,
+    key: &[u8],
+) -> Result<bool> {
+    let mut depth = 0;
+    loop {
+        let prefix = &key[..depth];
+        match get_bucket(overlay, space, prefix).await? {
+            Bucket::Leaf(mut keys) => {
+                if !keys.insert(key.to_vec()) {
+                    return Ok(false);
+                }
+                put_leaf(overlay, space, prefix, keys)?;
+                return Ok(true);
+            }
+            Bucket::Split { exact, children } if depth == key.len() => {
+                if !exact {
+                    let bucket = Bucket::Split {
+                        exact: true,
+                        children,
+                    };
+                    put_bucket(overlay, space, prefix, &bucket)?;
+                }
+                return Ok(!exact);
+            }
+            Bucket::Split {
+                exact,
+                mut children,
+            } => {
+                if children.insert(key[depth]) {
+                    put_bucket(overlay, space, prefix, &Bucket::Split { exact, children })?;
+                }
+                depth += 1;
+            }
+        }
+    }
+}
+
+/// Removes `key` from the index of an ordered keyspace, returning whether it
+/// was present.
+async fn remove_ordered_key<R: TreeReader + Sync>(
+    overlay: &mut WriteOverlay<R>,
+    space: &str,
+    key: &[u8],
+) -> Result<bool> {
+    // The split buckets on the way down to the key's bucket.
+    let mut path = Vec::new();
+    let mut depth = 0;
+    let mut bucket = loop {
+        match get_bucket(overlay, space, &key[..depth]).await? {
+            Bucket::Leaf(mut keys) => {
+                if !keys.remove(key) {
+                    return Ok(false);
+                }
+                break Bucket::Leaf(keys);
+            }
+            Bucket::Split { exact, children } if depth == key.len() => {
+                if !exact {
+                    return Ok(false);
+                }
+                break Bucket::Split {
+                    exact: false,
+                    children,
+                };
+            }
+            Bucket::Split { exact, children } => {
+                if !children.contains(&key[depth]) {
+                    return Ok(false);
+                }
+                path.push(Bucket::Split { exact, children });
+                depth += 1;
+            }
+        }
+    };
+
+    // Write the bucket back, removing it from its parent if it is now empty,
+    // and so on up the trie.
+    loop {
+        put_bucket(overlay, space, &key[..depth], &bucket)?;
+        if !bucket.is_empty() {
+            break;
+        }
+        match path.pop() {
+            Some(Bucket::Split {
+                exact,
+                mut children,
+            }) => {
+                depth -= 1;
+                children.remove(&key[depth]);
+                bucket = Bucket::Split { exact, children };
+            }
+            Some(Bucket::Leaf(_)) => unreachable!("only split buckets are on the path"),
+            None => break,
+        }
+    }
+    Ok(true)
+}
+
+/// Appends the keys of the ordered keyspace's index starting with `prefix`,
+/// from the bucket with `bucket_prefix` down, to `keys` in order.
+fn collect_ordered_keys<'a, R: TreeReader + Sync>(
+    overlay: &'a WriteOverlay<R>,
+    space: &'a str,
+    bucket_prefix: Vec<u8>,
+    prefix: &'a [u8],
+    keys: &'a mut Vec<Vec<u8>>,
+) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        match get_bucket(overlay, space, &bucket_prefix).await? {
+            Bucket::Leaf(leaf) => keys.extend(
+                leaf.range(prefix.to_vec()..)
+                    .take_while(|key| key.starts_with(prefix))
+                    .cloned(),
+            ),
+            Bucket::Split { exact, children } => {
+                if exact && bucket_prefix.starts_with(prefix) {
+                    keys.push(bucket_prefix.clone());
+                }
+                for byte in children {
+                    let child = [&bucket_prefix[..], &[byte]].concat();
+                    // Only descend into children which can hold keys with the
+                    // prefix, which is all of them once the prefix is covered.
+                    let depth = bucket_prefix.len();
+                    if depth < prefix.len() && prefix[depth] != byte {
+                        continue;
+                    }
+                    collect_ordered_keys(overlay, space, child, prefix, keys).await?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Appends the state keys of the ordered keyspace's index, from the bucket
+/// with `prefix` down, to `keys`.
+fn collect_bucket_keys<'a, R: TreeReader + Sync>(
+    overlay: &'a WriteOverlay<R>,
+    space: &'a str,
+    prefix: Vec<u8>,
+    keys: &'a mut Vec<(&'static str, String)>,
+) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        let bucket = get_bucket(overlay, space, &prefix).await?;
+        keys.push((
+            "{space}/ordered/buckets/{prefix}",
+            ordered_bucket_key(space, &prefix),
+        ));
+        match bucket {
+            Bucket::Leaf(leaf) => keys.extend(leaf.iter().map(|key| {
+                (
+                    "{space}/ordered/entries/{key}",
+                    ordered_entry_key(space, key),
+                )
+            })),
+            Bucket::Split { exact, children } => {
+                if exact {
+                    keys.push((
+                        "{space}/ordered/entries/{key}",
+                        ordered_entry_key(space, &prefix),
+                    ));
+                }
+                for byte in children {
+                    collect_bucket_keys(overlay, space, [&prefix[..], &[byte]].concat(), keys)
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    })
 }
 
 #[async_trait]
@@ -91,6 +411,7 @@ impl<R: TreeReader + Sync + 'static> OverlayExt for Arc<Mutex<WriteOverlay<R>>>
     where
         P: Message + Default + Debug,
     {
+        crate::profile::count_read();
         let bytes = match self.lock().await.get(key).await? {
             None => return Ok(None),
             Some(bytes) => bytes,
@@ -106,6 +427,55 @@ impl<R: TreeReader + Sync + 'static> OverlayExt for Arc<Mutex<WriteOverlay<R>>>
     where
         P: Message + Debug,
     {
+        crate::profile::count_write();
         self.lock().await.put(key, value.encode_to_vec());
     }
+
+    #[instrument(skip(self, key, value))]
+    async fn put_ordered(&self, space: &str, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        crate::profile::count_write();
+        // The index and the entry are updated under one lock, so that scans
+        // never see one without the other.
+        let mut overlay = self.lock().await;
+        insert_ordered_key(&mut *overlay, space, &key).await?;
+        overlay.put(ordered_entry_key(space, &key).into(), value);
+        Ok(())
+    }
+
+    #[instrument(skip(self, key))]
+    async fn delete_ordered(&self, space: &str, key: Vec<u8>) -> Result<()> {
+        crate::profile::count_write();
+        let mut overlay = self.lock().await;
+        if remove_ordered_key(&mut *overlay, space, &key).await? {
+            // The tree cannot delete leaves, so the entry is emptied instead.
+            overlay.put(ordered_entry_key(space, &key).into(), Vec::new());
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self, prefix))]
+    async fn prefix_range(&self, space: &str, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let overlay = self.lock().await;
+        let mut keys = Vec::new();
+        collect_ordered_keys(&*overlay, space, Vec::new(), prefix, &mut keys).await?;
+
+        let mut entries = Vec::new();
+        for key in keys {
+            crate::profile::count_read();
+            let value = overlay
+                .get(ordered_entry_key(space, &key).into())
+                .await?
+                .ok_or_else(|| anyhow!("missing entry {} in {}", hex::encode(&key), space))?;
+            entries.push((key, value));
+        }
+        Ok(entries)
+    }
+
+    #[instrument(skip(self))]
+    async fn ordered_state_keys(&self, space: &str) -> Result<Vec<(&'static str, String)>> {
+        let overlay = self.lock().await;
+        let mut keys = Vec::new();
+        collect_bucket_keys(&*overlay, space, Vec::new(), &mut keys).await?;
+        Ok(keys)
+    }
 }
@@ -0,0 +1,319 @@
+//! ICS23 proofs of the contents of the state tree.
+//!
+//! The JMT's own proofs are converted into ICS23 [`CommitmentProof`]s, which
+//! can be checked with [`penumbra_proto::proofs`] or any other ICS23 verifier.
+//! An ICS23 non-existence proof consists of existence proofs for the keys
+//! immediately before and after the absent key, in key hash order; these are
+//! found by probing the tree with further JMT proofs, since each proof reveals
+//! which of the subtrees along its path are empty.
+
+use anyhow::{anyhow, Result};
+use ics23::{
+    commitment_proof::Proof, CommitmentProof, ExistenceProof, HashOp, InnerOp, NonExistenceProof,
+};
+use jmt::{proof::SparseMerkleProof, JellyfishMerkleTree, KeyHash, Version};
+use penumbra_proto::proofs;
+
+use super::Storage;
+
+/// Which neighbor of a key to look for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+impl Storage {
+    /// Reads the value of `key` as of `version`, together with an ICS23 proof
+    /// of its existence or non-existence against that version's root hash.
+    pub async fn get_with_ics23_proof(
+        &self,
+        key: &[u8],
+        version: Version,
+    ) -> Result<(Option<Vec<u8>>, CommitmentProof)> {
+        let key_hash = KeyHash::from(key);
+        let (value, proof) = self.get_with_jmt_proof(key_hash, version).await?;
+
+        let proof = match &value {
+            Some(value) => Proof::Exist(existence_proof(key_hash.0, value.clone(), &proof)),
+            None => Proof::Nonexist(NonExistenceProof {
+                key: key_hash.0.to_vec(),
+                left: self
+                    .neighbor(key_hash.0, &proof, Side::Left, version)
+                    .await?,
+                right: self
+                    .neighbor(key_hash.0, &proof, Side::Right, version)
+                    .await?,
+            }),
+        };
+
+        Ok((value, CommitmentProof { proof: Some(proof) }))
+    }
+
+    async fn get_with_jmt_proof(
+        &self,
+        key_hash: KeyHash,
+        version: Version,
+    ) -> Result<(Option<Vec<u8>>, SparseMerkleProof)> {
+        JellyfishMerkleTree::new(self)
+            .get_with_proof(key_hash, version)
+            .await
+    }
+
+    /// An existence proof for a key hash known to be present in the tree.
+    async fn existence_proof(
+        &self,
+        key_hash: [u8; 32],
+        version: Version,
+    ) -> Result<ExistenceProof> {
+        let (value, proof) = self.get_with_jmt_proof(KeyHash(key_hash), version).await?;
+        let value = value.ok_or_else(|| anyhow!("leaf vanished from tree version {}", version))?;
+        Ok(existence_proof(key_hash, value, &proof))
+    }
+
+    /// Finds the leaf adjacent to the absent `key_hash` on the given side, given
+    /// the JMT proof of its absence, and proves its existence.
+    async fn neighbor(
+        &self,
+        key_hash: [u8; 32],
+        proof: &SparseMerkleProof,
+        side: Side,
+        version: Version,
+    ) -> Result<Option<ExistenceProof>> {
+        // A leaf occupying the absent key's position is the only leaf in that
+        // subtree, so it is adjacent to the key on one side.
+        if let Some(leaf) = proof.leaf() {
+            let other = leaf.key_hash().0;
+            let adjacent = match side {
+                Side::Left => other < key_hash,
+                Side::Right => other > key_hash,
+            };
+            if adjacent {
+                return Ok(Some(self.existence_proof(other, version).await?));
+            }
+        }
+
+        // Otherwise, the neighbor is the nearest leaf of the deepest non-empty
+        // subtree branching off the key's path on that side.
+        let siblings = proof.siblings();
+        for (i, sibling) in siblings.iter().enumerate() {
+            let level = siblings.len() - 1 - i;
+            let on_side = get_bit(&key_hash, level) == (side == Side::Left);
+            if on_side && sibling.as_ref() != proofs::PLACEHOLDER_HASH {
+                let mut prefix = key_hash;
+                set_bit(&mut prefix, level, side == Side::Right);
+                let nearest = match side {
+                    Side::Left => Side::Right,
+                    Side::Right => Side::Left,
+                };
+                let leaf = self
+                    .outermost_leaf(prefix, level + 1, nearest, version)
+                    .await?;
+                return Ok(Some(self.existence_proof(leaf, version).await?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Finds the leftmost or rightmost leaf of the non-empty subtree at the
+    /// path given by the first `depth` bits of `prefix`.
+    async fn outermost_leaf(
+        &self,
+        mut prefix: [u8; 32],
+        mut depth: usize,
+        side: Side,
+        version: Version,
+    ) -> Result<[u8; 32]> {
+        loop {
+            // Probe the outermost path through the subtree...
+            for bit in depth..256 {
+                set_bit(&mut prefix, bit, side == Side::Right);
+            }
+            let (_, proof) = self.get_with_jmt_proof(KeyHash(prefix), version).await?;
+            if let Some(leaf) = proof.leaf() {
+                return Ok(leaf.key_hash().0);
+            }
+
+            // ... and if it ends in an empty subtree, the outermost leaf is in
+            // the deepest non-empty subtree branching off the probed path.
+            let siblings = proof.siblings();
+            let level = (depth..siblings.len())
+                .rev()
+                .find(|&level| {
+                    siblings[siblings.len() - 1 - level].as_ref() != proofs::PLACEHOLDER_HASH
+                })
+                .ok_or_else(|| anyhow!("subtree of tree version {} is empty", version))?;
+            set_bit(&mut prefix, level, side == Side::Left);
+            depth = level + 1;
+        }
+    }
+}
+
+/// Converts a JMT proof of the existence of a leaf into an ICS23 proof.
+fn existence_proof(
+    key_hash: [u8; 32],
+    value: Vec<u8>,
+    proof: &SparseMerkleProof,
+) -> ExistenceProof {
+    // JMT siblings are ordered from the leaf to the root, as are ICS23 steps.
+    let siblings = proof.siblings();
+    let path = siblings
+        .iter()
+        .enumerate()
+        .map(|(i, sibling)| {
+            let sibling = sibling.as_ref();
+            if get_bit(&key_hash, siblings.len() - 1 - i) {
+                InnerOp {
+                    hash: HashOp::Sha256.into(),
+                    prefix: [proofs::INTERNAL_PREFIX, sibling].concat(),
+                    suffix: Vec::new(),
+                }
+            } else {
+                InnerOp {
+                    hash: HashOp::Sha256.into(),
+                    prefix: proofs::INTERNAL_PREFIX.to_vec(),
+                    suffix: sibling.to_vec(),
+                }
+            }
+        })
+        .collect();
+
+    ExistenceProof {
+        key: key_hash.to_vec(),
+        value,
+        leaf: Some(proofs::leaf_op()),
+        path,
+    }
+}
+
+/// The bit of `key_hash` which selects the child at the given level of the
+/// tree, where the root is level 0.
+fn get_bit(key_hash: &[u8; 32], level: usize) -> bool {
+    key_hash[level / 8] & (0x80 >> (level % 8)) != 0
+}
+
+fn set_bit(key_hash: &mut [u8; 32], level: usize, value: bool) {
+    if value {
+        key_hash[level / 8] |= 0x80 >> (level % 8);
+    } else {
+        key_hash[level / 8] &= !(0x80 >> (level % 8));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jmt::RootHash;
+
+    use super::*;
+    use crate::OverlayExt;
+
+    /// Commits `values` under the keys `key/{i}` to `storage`, returning the
+    /// new root hash and version.
+    async fn commit(storage: &Storage, values: &[u64]) -> Result<(RootHash, Version)> {
+        let overlay = storage.overlay().await?;
+        for (i, value) in values.iter().enumerate() {
+            overlay.put_proto(format!("key/{}", i).into(), *value).await;
+        }
+        let result = overlay.lock().await.commit(storage.clone()).await?;
+        Ok(result)
+    }
+
+    /// Checks the proofs of every key and of as many absent keys against `root`.
+    async fn check_proofs(
+        storage: &Storage,
+        root: RootHash,
+        version: Version,
+        count: usize,
+    ) -> Result<()> {
+        for i in 0..count {
+            let key = format!("key/{}", i);
+            let (value, proof) = storage
+                .get_with_ics23_proof(key.as_bytes(), version)
+                .await?;
+            let value = value.ok_or_else(|| anyhow!("missing {}", key))?;
+            assert!(
+                proofs::verify_membership(&proof, &root.0, key.as_bytes(), &value),
+                "{}",
+                key
+            );
+            assert!(!proofs::verify_non_membership(
+                &proof,
+                &root.0,
+                key.as_bytes()
+            ));
+
+            let absent = format!("absent/{}", i);
+            let (value, proof) = storage
+                .get_with_ics23_proof(absent.as_bytes(), version)
+                .await?;
+            assert_eq!(value, None);
+            assert!(
+                proofs::verify_non_membership(&proof, &root.0, absent.as_bytes()),
+                "{}",
+                absent
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn bits_are_numbered_from_the_root() {
+        let mut key_hash = [0; 32];
+        set_bit(&mut key_hash, 0, true);
+        set_bit(&mut key_hash, 9, true);
+        set_bit(&mut key_hash, 255, true);
+        assert_eq!(key_hash[0], 0x80);
+        assert_eq!(key_hash[1], 0x40);
+        assert_eq!(key_hash[31], 0x01);
+        assert!(get_bit(&key_hash, 0) && get_bit(&key_hash, 9) && get_bit(&key_hash, 255));
+        assert!(!get_bit(&key_hash, 1));
+
+        set_bit(&mut key_hash, 9, false);
+        assert_eq!(key_hash[1], 0);
+    }
+
+    #[tokio::test]
+    async fn proves_membership_and_non_membership() -> Result<()> {
+        // A lone leaf has a neighbor on only one side of any absent key.
+        let storage = Storage::in_memory().await?;
+        let (root, version) = commit(&storage, &[0]).await?;
+        check_proofs(&storage, root, version, 1).await?;
+
+        // With many leaves, the neighbors of absent keys lie in all sorts of
+        // subtrees.
+        let storage = Storage::in_memory().await?;
+        let values: Vec<u64> = (0..64).collect();
+        let (root, version) = commit(&storage, &values).await?;
+        check_proofs(&storage, root, version, values.len()).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proves_past_versions() -> Result<()> {
+        let storage = Storage::in_memory().await?;
+        let (old_root, old_version) = commit(&storage, &[1, 2, 3]).await?;
+        let (new_root, new_version) = commit(&storage, &[4, 5, 6]).await?;
+
+        let (old_value, old_proof) = storage.get_with_ics23_proof(b"key/0", old_version).await?;
+        let (new_value, _) = storage.get_with_ics23_proof(b"key/0", new_version).await?;
+        assert_ne!(old_value, new_value);
+        let old_value = old_value.unwrap();
+        assert!(proofs::verify_membership(
+            &old_proof,
+            &old_root.0,
+            b"key/0",
+            &old_value
+        ));
+        assert!(!proofs::verify_membership(
+            &old_proof,
+            &new_root.0,
+            b"key/0",
+            &old_value
+        ));
+        check_proofs(&storage, old_root, old_version, 3).await?;
+
+        Ok(())
+    }
+}
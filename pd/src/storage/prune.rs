@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use jmt::{
+    storage::{Node, NodeKey},
+    Version,
+};
+use rocksdb::WriteBatch;
+use tracing::Span;
+
+use super::{jmt_cf, Storage};
+
+/// How many deletions to batch into each write while pruning.
+const PRUNE_BATCH_SIZE: usize = 10_000;
+
+/// What [`Storage::prune`] deleted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pruned {
+    /// How many tree nodes were deleted.
+    pub nodes: usize,
+    /// The total size of the deleted nodes' keys and values, in bytes.
+    pub bytes: usize,
+}
+
+impl Storage {
+    /// Deletes every node of the tree which is only needed for versions before
+    /// `keep_from`, so that the versions from `keep_from` onwards are intact,
+    /// but the earlier ones can no longer be read.
+    ///
+    /// The tree never rewrites a node, so a node written before `keep_from` is
+    /// still needed exactly if it is reachable from the root at `keep_from`:
+    /// later versions can only refer to earlier nodes which were unchanged at
+    /// `keep_from`. Finding those nodes means walking the whole tree at
+    /// `keep_from`, which takes time and memory proportional to its size, so
+    /// this should not be done every block. Pruning is safe to run alongside
+    /// commits and queries of the retained versions.
+    pub async fn prune(&self, keep_from: Version) -> Result<Pruned> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                let cf = jmt_cf(&db)?;
+
+                // The nodes from before `keep_from` reachable from its root.
+                let mut needed = HashSet::new();
+                let mut stack = vec![NodeKey::new_empty_path(keep_from)];
+                while let Some(node_key) = stack.pop() {
+                    let key = node_key.encode()?;
+                    let node = db
+                        .get_pinned_cf(cf, &key)?
+                        .map(|bytes| Node::decode(&bytes))
+                        .transpose()?
+                        .ok_or_else(|| anyhow!("missing tree node {:?}", node_key))?;
+                    if node_key.version() < keep_from {
+                        needed.insert(key);
+                    }
+                    if let Node::Internal(internal) = node {
+                        for (nibble, child) in internal.children_sorted() {
+                            stack.push(node_key.gen_child_node_key(child.version, *nibble));
+                        }
+                    }
+                }
+
+                let mut pruned = Pruned::default();
+                let mut batch = WriteBatch::default();
+                let mut iter = db.raw_iterator_cf(cf);
+                iter.seek_to_first();
+                while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                    if NodeKey::decode(key)?.version() >= keep_from {
+                        break;
+                    }
+                    if !needed.contains(key) {
+                        batch.delete_cf(cf, key);
+                        pruned.nodes += 1;
+                        pruned.bytes += key.len() + value.len();
+                    }
+                    if batch.len() >= PRUNE_BATCH_SIZE {
+                        db.write(std::mem::take(&mut batch))?;
+                    }
+                    iter.next();
+                }
+                iter.status()?;
+                db.write(batch)?;
+
+                // Deleted keys only free space once they are compacted away.
+                if pruned.nodes > 0 {
+                    db.compact_range_cf(cf, None::<&[u8]>, Some(&keep_from.to_be_bytes()[..]));
+                }
+
+                tracing::debug!(keep_from, ?pruned, "pruned tree");
+                Ok(pruned)
+            })
+        })
+        .await
+        .unwrap()
+    }
+}
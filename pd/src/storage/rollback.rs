@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Result};
+use jmt::{storage::NodeKey, Version};
+use penumbra_proto::{chain::TxResult, Message};
+use rocksdb::WriteBatch;
+use tracing::Span;
+
+use super::{app_hashes::APP_HASHES_CF, flush_all, jmt_cf, tx_results::TX_RESULTS_CF, Storage};
+
+/// What [`Storage::rollback`] removed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rollback {
+    /// The version of the tree before the rollback.
+    pub from: Version,
+    /// The version of the tree after the rollback.
+    pub to: Version,
+    /// How many tree nodes written after `to` were deleted.
+    pub nodes: usize,
+    /// How many recorded app hashes after `to` were deleted.
+    pub app_hashes: usize,
+    /// How many recorded transaction results after `to` were deleted.
+    pub tx_results: usize,
+}
+
+impl Storage {
+    /// Rewinds the tree to `version`, deleting everything committed after it.
+    ///
+    /// Nodes are never deleted from the tree, so the tree at `version` is
+    /// still intact below the nodes written by later versions, and node keys
+    /// start with the big-endian version, so those are all the keys from the
+    /// first key of `version + 1` onwards. The app hashes and transaction
+    /// results recorded for the later versions are deleted with them, in the
+    /// same atomic write, so an interrupted rollback leaves the database as it
+    /// was.
+    pub async fn rollback(&self, version: Version) -> Result<Rollback> {
+        let from = self
+            .latest_version()
+            .await?
+            .ok_or_else(|| anyhow!("database is empty"))?;
+        if version >= from {
+            return Err(anyhow!(
+                "cannot roll back to version {}, which is not before the latest version {}",
+                version,
+                from
+            ));
+        }
+
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                let mut rollback = Rollback {
+                    from,
+                    to: version,
+                    ..Default::default()
+                };
+                let mut batch = WriteBatch::default();
+                let start = (version + 1).to_be_bytes();
+
+                // The root node has the empty nibble path, so it is the first
+                // node of its version: if the first key at or after `version`
+                // is not at `version`, the tree has no root there.
+                let cf = jmt_cf(&db)?;
+                let mut iter = db.raw_iterator_cf(cf);
+                iter.seek(version.to_be_bytes());
+                match iter.key().map(NodeKey::decode).transpose()? {
+                    Some(node_key) if node_key.version() == version => {}
+                    _ => {
+                        iter.status()?;
+                        return Err(anyhow!("the tree has no root at version {}", version));
+                    }
+                }
+
+                iter.seek(start);
+                while let Some(key) = iter.key() {
+                    batch.delete_cf(cf, key);
+                    rollback.nodes += 1;
+                    iter.next();
+                }
+                iter.status()?;
+
+                let cf = db
+                    .cf_handle(APP_HASHES_CF)
+                    .ok_or_else(|| anyhow!("missing {} column family", APP_HASHES_CF))?;
+                let mut iter = db.raw_iterator_cf(cf);
+                iter.seek(start);
+                while let Some(key) = iter.key() {
+                    batch.delete_cf(cf, key);
+                    rollback.app_hashes += 1;
+                    iter.next();
+                }
+                iter.status()?;
+
+                // Transaction results are keyed by hash, so they must all be
+                // read to find the ones from later blocks.
+                let cf = db
+                    .cf_handle(TX_RESULTS_CF)
+                    .ok_or_else(|| anyhow!("missing {} column family", TX_RESULTS_CF))?;
+                let mut iter = db.raw_iterator_cf(cf);
+                iter.seek_to_first();
+                while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                    if TxResult::decode(value)?.height > version {
+                        batch.delete_cf(cf, key);
+                        rollback.tx_results += 1;
+                    }
+                    iter.next();
+                }
+                iter.status()?;
+
+                tracing::info!(?rollback, "rolling back rocksdb");
+                db.write(batch)?;
+                flush_all(&db)?;
+                Ok(rollback)
+            })
+        })
+        .await
+        .unwrap()
+    }
+}
@@ -0,0 +1,395 @@
+//! Export and import of the application state as a single file.
+//!
+//! A state file holds every JMT node written up to some committed version,
+//! byte for byte, together with the recorded app hashes up to that version.
+//! Importing it into an empty database therefore reproduces the tree exactly,
+//! including its history, so past versions can still be queried and
+//! replayed. Transaction results are local to each node, and not included.
+//!
+//! The format is a header (the magic bytes, a format version, the committed
+//! version and its root hash), then a sequence of records, each a tag byte
+//! and a length-prefixed key and value, and finally the SHA-256 digest of
+//! everything before it.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, Result};
+use jmt::{storage::NodeKey, JellyfishMerkleTree, RootHash, Version};
+use rocksdb::WriteBatch;
+use sha2::{Digest, Sha256};
+use tracing::Span;
+
+use super::{app_hashes::APP_HASHES_CF, jmt_cf, Storage};
+
+/// The bytes every state file starts with.
+const MAGIC: &[u8] = b"PDSTATE";
+
+/// The version of the state file format.
+const FORMAT_VERSION: u8 = 1;
+
+/// A record holding a JMT node.
+const NODE_TAG: u8 = 0;
+
+/// A record holding the app hash recorded at a version.
+const APP_HASH_TAG: u8 = 1;
+
+/// The record tag marking the end of the records.
+const END_TAG: u8 = 0xff;
+
+/// How many records to write to the database at once when importing.
+const IMPORT_BATCH_SIZE: usize = 10_000;
+
+/// What a state file holds.
+#[derive(Clone, Debug)]
+pub struct StateFile {
+    /// The committed version the state was exported at.
+    pub version: Version,
+    /// The root hash of the tree at that version, i.e. the app hash.
+    pub root_hash: RootHash,
+    /// The number of JMT nodes.
+    pub nodes: u64,
+    /// The number of recorded app hashes.
+    pub app_hashes: u64,
+}
+
+impl Storage {
+    /// Writes the state at `version` to a new file at `path`.
+    pub async fn export_state(&self, version: Version, path: PathBuf) -> Result<StateFile> {
+        let root_hash = JellyfishMerkleTree::new(self)
+            .get_root_hash_option(version)
+            .await?
+            .ok_or_else(|| anyhow!("no state committed at version {}", version))?;
+
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                tracing::info!(?path, version, "exporting state");
+                let mut out = DigestWriter::new(BufWriter::new(File::create(&path)?));
+                out.write_all(MAGIC)?;
+                out.write_all(&[FORMAT_VERSION])?;
+                out.write_all(&version.to_be_bytes())?;
+                out.write_all(&root_hash.0)?;
+
+                let mut summary = StateFile {
+                    version,
+                    root_hash,
+                    nodes: 0,
+                    app_hashes: 0,
+                };
+
+                // Node keys start with the big-endian version, so the nodes
+                // written up to `version` come first.
+                let mut iter = db.raw_iterator_cf(jmt_cf(&db)?);
+                iter.seek_to_first();
+                while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                    if NodeKey::decode(key)?.version() > version {
+                        break;
+                    }
+                    write_record(&mut out, NODE_TAG, key, value)?;
+                    summary.nodes += 1;
+                    iter.next();
+                }
+                iter.status()?;
+
+                let cf = db
+                    .cf_handle(APP_HASHES_CF)
+                    .ok_or_else(|| anyhow!("missing {} column family", APP_HASHES_CF))?;
+                let mut iter = db.raw_iterator_cf(cf);
+                iter.seek_to_first();
+                while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                    if key > &version.to_be_bytes()[..] {
+                        break;
+                    }
+                    write_record(&mut out, APP_HASH_TAG, key, value)?;
+                    summary.app_hashes += 1;
+                    iter.next();
+                }
+                iter.status()?;
+
+                out.write_all(&[END_TAG])?;
+                let digest = out.digest.clone().finalize();
+                let mut out = out.inner;
+                out.write_all(&digest)?;
+                out.flush()?;
+
+                tracing::info!(?summary, "exported state");
+                Ok(summary)
+            })
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Restores the state in the file at `path` into this database, which
+    /// must be empty.
+    ///
+    /// The file's digest is checked before anything is written, and the
+    /// restored tree's root hash is checked against the one it was exported
+    /// with.
+    pub async fn import_state(&self, path: PathBuf) -> Result<StateFile> {
+        if let Some(latest) = self.latest_version().await? {
+            return Err(anyhow!(
+                "cannot import state into a database which already has state up to version {}",
+                latest
+            ));
+        }
+
+        // Check the digest first, so that a truncated or corrupted file is
+        // rejected before it is partially imported.
+        let check_path = path.clone();
+        tokio::task::spawn_blocking(move || check_digest(check_path))
+            .await
+            .unwrap()?;
+
+        let db = self.0.clone();
+        let span = Span::current();
+        let summary = tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                tracing::info!(?path, "importing state");
+                let mut input = BufReader::new(File::open(&path)?);
+
+                let mut magic = [0u8; MAGIC.len()];
+                input.read_exact(&mut magic)?;
+                let format_version = read_array::<1>(&mut input)?[0];
+                if magic != MAGIC || format_version != FORMAT_VERSION {
+                    return Err(anyhow!("not a version {} state file", FORMAT_VERSION));
+                }
+                let mut summary = StateFile {
+                    version: u64::from_be_bytes(read_array(&mut input)?),
+                    root_hash: RootHash(read_array(&mut input)?),
+                    nodes: 0,
+                    app_hashes: 0,
+                };
+
+                let nodes = jmt_cf(&db)?;
+                let cf = db
+                    .cf_handle(APP_HASHES_CF)
+                    .ok_or_else(|| anyhow!("missing {} column family", APP_HASHES_CF))?;
+                let mut batch = WriteBatch::default();
+                loop {
+                    let tag = read_array::<1>(&mut input)?[0];
+                    if tag == END_TAG {
+                        break;
+                    }
+                    let key = read_bytes(&mut input)?;
+                    let value = read_bytes(&mut input)?;
+                    match tag {
+                        NODE_TAG => {
+                            batch.put_cf(nodes, key, value);
+                            summary.nodes += 1;
+                        }
+                        APP_HASH_TAG => {
+                            batch.put_cf(cf, key, value);
+                            summary.app_hashes += 1;
+                        }
+                        _ => return Err(anyhow!("unknown record tag {} in state file", tag)),
+                    }
+                    if batch.len() >= IMPORT_BATCH_SIZE {
+                        db.write(std::mem::take(&mut batch))?;
+                    }
+                }
+                db.write(batch)?;
+
+                Ok(summary)
+            })
+        })
+        .await
+        .unwrap()?;
+
+        let root_hash = JellyfishMerkleTree::new(self)
+            .get_root_hash_option(summary.version)
+            .await?;
+        if root_hash.map(|hash| hash.0) != Some(summary.root_hash.0) {
+            return Err(anyhow!(
+                "imported tree has root hash {:?} at version {}, but was exported with {}",
+                root_hash.map(|hash| hex::encode(hash.0)),
+                summary.version,
+                hex::encode(summary.root_hash.0)
+            ));
+        }
+
+        tracing::info!(?summary, "imported state");
+        Ok(summary)
+    }
+}
+
+/// A writer which hashes everything written through it.
+struct DigestWriter<W> {
+    inner: W,
+    digest: Sha256,
+}
+
+impl<W: Write> DigestWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            digest: Sha256::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.digest.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_record(out: &mut impl Write, tag: u8, key: &[u8], value: &[u8]) -> Result<()> {
+    out.write_all(&[tag])?;
+    for bytes in [key, value] {
+        out.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        out.write_all(bytes)?;
+    }
+    Ok(())
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_bytes(input: &mut impl Read) -> Result<Vec<u8>> {
+    let len = u32::from_be_bytes(read_array(input)?) as usize;
+    let mut bytes = vec![0u8; len];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Checks that the file at `path` ends with the digest of its contents.
+fn check_digest(path: PathBuf) -> Result<()> {
+    const DIGEST_LEN: u64 = 32;
+    let len = std::fs::metadata(&path)?.len();
+    if len < DIGEST_LEN {
+        return Err(anyhow!("state file {:?} is truncated", path));
+    }
+
+    let mut input = BufReader::new(File::open(&path)?);
+    let mut digest = Sha256::new();
+    let mut remaining = len - DIGEST_LEN;
+    let mut buf = vec![0u8; 1 << 16];
+    while remaining > 0 {
+        let chunk = &mut buf[..remaining.min(1 << 16) as usize];
+        input.read_exact(chunk)?;
+        digest.update(&*chunk);
+        remaining -= chunk.len() as u64;
+    }
+    let expected = read_array::<32>(&mut input)?;
+    if digest.finalize().as_slice() != expected {
+        return Err(anyhow!(
+            "state file {:?} is corrupt: its digest does not match",
+            path
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OverlayExt;
+
+    /// Commits three versions, each adding a key, with their app hashes.
+    async fn storage() -> Result<Storage> {
+        let storage = Storage::in_memory().await?;
+        for i in 0..3u64 {
+            let overlay = storage.overlay().await?;
+            overlay.put_proto(format!("key/{}", i).into(), i).await;
+            let (root_hash, version) = overlay.lock().await.commit(storage.clone()).await?;
+            storage.put_app_hash(version, root_hash).await?;
+        }
+        Ok(storage)
+    }
+
+    async fn get(storage: &Storage, version: Version, i: u64) -> Result<Option<u64>> {
+        storage
+            .overlay_at(version)
+            .await?
+            .get_proto(format!("key/{}", i).into())
+            .await
+    }
+
+    #[tokio::test]
+    async fn round_trips_the_state_up_to_a_version() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("state");
+        let storage = storage().await?;
+
+        let exported = storage.export_state(1, path.clone()).await?;
+        assert_eq!(exported.version, 1);
+        assert_eq!(exported.app_hashes, 2);
+        assert_eq!(exported.root_hash.0, storage.app_hash(1).await?.unwrap().0);
+
+        let imported_storage = Storage::in_memory().await?;
+        let imported = imported_storage.import_state(path).await?;
+        assert_eq!(imported.nodes, exported.nodes);
+        assert_eq!(imported.app_hashes, exported.app_hashes);
+        assert_eq!(imported.root_hash.0, exported.root_hash.0);
+
+        // The history up to the version is restored, and nothing after it.
+        assert_eq!(imported_storage.latest_version().await?, Some(1));
+        assert_eq!(get(&imported_storage, 0, 0).await?, Some(0));
+        assert_eq!(get(&imported_storage, 0, 1).await?, None);
+        assert_eq!(get(&imported_storage, 1, 1).await?, Some(1));
+        assert_eq!(get(&imported_storage, 1, 2).await?, None);
+        for version in 0..=1 {
+            assert_eq!(
+                imported_storage.app_hash(version).await?.map(|hash| hash.0),
+                storage.app_hash(version).await?.map(|hash| hash.0)
+            );
+        }
+        assert!(imported_storage.app_hash(2).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_uncommitted_versions_and_non_empty_databases() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("state");
+        let storage = storage().await?;
+
+        assert!(storage.export_state(3, path.clone()).await.is_err());
+        storage.export_state(2, path.clone()).await?;
+        let error = storage.import_state(path).await.unwrap_err();
+        assert!(error.to_string().contains("already has state"), "{}", error);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_damaged_files_before_importing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("state");
+        storage().await?.export_state(2, path.clone()).await?;
+        let bytes = std::fs::read(&path)?;
+
+        let mut corrupt = bytes.clone();
+        corrupt[bytes.len() / 2] ^= 1;
+        let truncated = &bytes[..bytes.len() - 1];
+        for (damaged, problem) in [
+            (&corrupt[..], "corrupt"),
+            (truncated, "corrupt"),
+            (&[][..], "truncated"),
+        ] {
+            std::fs::write(&path, damaged)?;
+            let storage = Storage::in_memory().await?;
+            let error = storage.import_state(path.clone()).await.unwrap_err();
+            assert!(error.to_string().contains(problem), "{}", error);
+            assert_eq!(storage.latest_version().await?, None);
+        }
+
+        Ok(())
+    }
+}
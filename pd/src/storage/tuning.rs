@@ -0,0 +1,198 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use rocksdb::{
+    BlockBasedOptions, Cache, ColumnFamilyDescriptor, DBCompactionStyle, DBCompressionType, Options,
+};
+use serde::Deserialize;
+
+use super::{app_hashes::APP_HASHES_CF, format::META_CF, tx_results::TX_RESULTS_CF, JMT_CF};
+
+/// How RocksDB compacts each column family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompactionStyle {
+    /// Leveled compaction, RocksDB's default, which keeps space amplification
+    /// low.
+    Level,
+    /// Universal compaction, which writes less, at the cost of temporarily
+    /// needing up to twice the space.
+    Universal,
+}
+
+impl FromStr for CompactionStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "level" => Ok(CompactionStyle::Level),
+            "universal" => Ok(CompactionStyle::Universal),
+            _ => Err(anyhow!(
+                "unknown compaction style {:?}, expected level or universal",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CompactionStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CompactionStyle::Level => "level",
+            CompactionStyle::Universal => "universal",
+        })
+    }
+}
+
+/// The block cache shared by every column family of a database.
+///
+/// RocksDB keeps its own handle to the cache, so this one is only needed to
+/// inspect and clear it.
+#[derive(Clone)]
+pub(super) struct BlockCache {
+    cache: Arc<Mutex<Cache>>,
+    capacity: usize,
+}
+
+impl BlockCache {
+    fn new(capacity: usize) -> Result<Self> {
+        Ok(Self {
+            cache: Arc::new(Mutex::new(Cache::new_lru_cache(capacity)?)),
+            capacity,
+        })
+    }
+
+    /// The number of bytes of blocks held in the cache.
+    pub(super) fn usage(&self) -> usize {
+        self.cache.lock().unwrap().get_usage()
+    }
+
+    /// Evicts every block not currently pinned by a reader, returning the
+    /// number of bytes evicted.
+    ///
+    /// An LRU cache evicts down to its capacity whenever that shrinks, so the
+    /// capacity is briefly set to zero.
+    pub(super) fn clear(&self) -> usize {
+        let mut cache = self.cache.lock().unwrap();
+        let before = cache.get_usage();
+        cache.set_capacity(0);
+        cache.set_capacity(self.capacity);
+        before.saturating_sub(cache.get_usage())
+    }
+}
+
+impl fmt::Debug for BlockCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockCache")
+            .field("capacity", &self.capacity)
+            .field("usage", &self.usage())
+            .finish()
+    }
+}
+
+/// Tuning of the RocksDB database.
+///
+/// Tuning only affects performance and durability, not what is stored or
+/// where, so a database can be reopened with different tuning, or none.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tuning {
+    /// The size of the block cache shared by every column family, in bytes.
+    pub block_cache_size: usize,
+    pub compaction_style: CompactionStyle,
+    /// Flush the column families once the write-ahead log grows to this many
+    /// bytes, or let RocksDB choose if 0.
+    pub max_total_wal_size: u64,
+    /// Sync the write-ahead log in the background after every this many
+    /// bytes written to it, smoothing out its I/O, or only when the operating
+    /// system chooses if 0.
+    pub wal_bytes_per_sync: u64,
+    /// Likewise, for the database's other files.
+    pub bytes_per_sync: u64,
+    /// Sync files with `fsync` rather than `fdatasync`, for filesystems on
+    /// which the latter does not make file metadata durable.
+    pub use_fsync: bool,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self {
+            block_cache_size: 256 * 1024 * 1024,
+            compaction_style: CompactionStyle::Level,
+            max_total_wal_size: 0,
+            wal_bytes_per_sync: 0,
+            bytes_per_sync: 0,
+            use_fsync: false,
+        }
+    }
+}
+
+impl Tuning {
+    /// The options of the database as a whole.
+    pub(super) fn db_options(&self) -> Options {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        opts.set_max_total_wal_size(self.max_total_wal_size);
+        opts.set_wal_bytes_per_sync(self.wal_bytes_per_sync);
+        opts.set_bytes_per_sync(self.bytes_per_sync);
+        opts.set_use_fsync(self.use_fsync);
+        opts
+    }
+
+    /// A new, empty block cache of the configured size.
+    pub(super) fn block_cache(&self) -> Result<BlockCache> {
+        BlockCache::new(self.block_cache_size)
+    }
+
+    /// The descriptors of every column family, with options suited to what
+    /// each holds, all sharing `cache`.
+    pub(super) fn column_families(&self, cache: &BlockCache) -> Vec<ColumnFamilyDescriptor> {
+        let cache = cache.cache.lock().unwrap();
+        let options = |compression, bloom_filter| {
+            let mut table = BlockBasedOptions::default();
+            table.set_block_cache(&cache);
+            if bloom_filter {
+                table.set_bloom_filter(10.0, false);
+            }
+            let mut opts = Options::default();
+            opts.set_block_based_table_factory(&table);
+            opts.set_compaction_style(match self.compaction_style {
+                CompactionStyle::Level => DBCompactionStyle::Level,
+                CompactionStyle::Universal => DBCompactionStyle::Universal,
+            });
+            opts.set_compression_type(compression);
+            opts
+        };
+
+        vec![
+            // Tree nodes are read by key on every state access, so are worth
+            // filtering. Internal nodes are mostly hashes, which do not
+            // compress, but leaves hold the state's values, which do.
+            ColumnFamilyDescriptor::new(JMT_CF, options(DBCompressionType::Lz4, true)),
+            ColumnFamilyDescriptor::new(META_CF, options(DBCompressionType::None, false)),
+            // App hashes are incompressible, and few.
+            ColumnFamilyDescriptor::new(APP_HASHES_CF, options(DBCompressionType::None, false)),
+            // Transaction results are rarely read, and compress well.
+            ColumnFamilyDescriptor::new(TX_RESULTS_CF, options(DBCompressionType::Zstd, false)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compaction_styles_round_trip_through_their_names() -> Result<()> {
+        for style in [CompactionStyle::Level, CompactionStyle::Universal] {
+            assert_eq!(style.to_string().parse::<CompactionStyle>()?, style);
+        }
+        let error = "fifo".parse::<CompactionStyle>().unwrap_err();
+        assert!(error.to_string().contains("expected level or universal"));
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Result};
+use penumbra_proto::{chain::TxResult, Message};
+use rocksdb::WriteBatch;
+use tracing::Span;
+
+use super::Storage;
+
+/// The column family holding the results of executed transactions, keyed by
+/// transaction hash.
+pub(super) const TX_RESULTS_CF: &str = "tx_results";
+
+impl Storage {
+    /// Records the results of executed transactions, keyed by the SHA-256 hash
+    /// of each encoded transaction.
+    ///
+    /// Recording a result for a transaction hash which already has one
+    /// replaces it, so re-executing a block after a crash is harmless.
+    pub async fn put_tx_results(&self, results: Vec<([u8; 32], TxResult)>) -> Result<()> {
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                let cf = db
+                    .cf_handle(TX_RESULTS_CF)
+                    .ok_or_else(|| anyhow!("missing {} column family", TX_RESULTS_CF))?;
+                let mut batch = WriteBatch::default();
+                for (tx_hash, result) in &results {
+                    batch.put_cf(cf, tx_hash, result.encode_to_vec());
+                }
+                tracing::debug!(count = results.len(), "writing tx results");
+                Ok(db.write(batch)?)
+            })
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Reads the recorded result of the transaction with the given hash, if any.
+    pub async fn tx_result(&self, tx_hash: [u8; 32]) -> Result<Option<TxResult>> {
+        let db = self.0.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| {
+                let cf = db
+                    .cf_handle(TX_RESULTS_CF)
+                    .ok_or_else(|| anyhow!("missing {} column family", TX_RESULTS_CF))?;
+                Ok(db
+                    .get_pinned_cf(cf, tx_hash)?
+                    .map(|bytes| TxResult::decode(&*bytes))
+                    .transpose()?)
+            })
+        })
+        .await
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_proto::chain::{Event, EventAttribute};
+
+    use super::*;
+
+    fn tx_result(height: u64, code: u32) -> TxResult {
+        TxResult {
+            height,
+            tx: vec![1, 2, 3],
+            code,
+            log: "log".to_string(),
+            gas_wanted: 10,
+            gas_used: 5,
+            events: vec![Event {
+                kind: "kind".to_string(),
+                attributes: vec![EventAttribute {
+                    key: "key".to_string(),
+                    value: "value".to_string(),
+                    index: true,
+                }],
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn results_are_read_back_by_hash() -> Result<()> {
+        let storage = Storage::in_memory().await?;
+        storage
+            .put_tx_results(vec![([1; 32], tx_result(1, 0)), ([2; 32], tx_result(1, 7))])
+            .await?;
+
+        assert_eq!(storage.tx_result([1; 32]).await?, Some(tx_result(1, 0)));
+        assert_eq!(storage.tx_result([2; 32]).await?, Some(tx_result(1, 7)));
+        assert_eq!(storage.tx_result([3; 32]).await?, None);
+
+        // Nothing to write is not an error.
+        storage.put_tx_results(Vec::new()).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recording_a_result_again_replaces_it() -> Result<()> {
+        let storage = Storage::in_memory().await?;
+        storage
+            .put_tx_results(vec![([1; 32], tx_result(1, 7))])
+            .await?;
+        storage
+            .put_tx_results(vec![([1; 32], tx_result(2, 0))])
+            .await?;
+
+        assert_eq!(storage.tx_result([1; 32]).await?, Some(tx_result(2, 0)));
+
+        Ok(())
+    }
+}
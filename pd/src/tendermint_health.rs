@@ -0,0 +1,187 @@
+//! Republishing of the co-located Tendermint node's health as `pd` metrics.
+//!
+//! Tendermint serves its own Prometheus endpoint, but operators then need two
+//! scrape targets per node. Instead, [`poll`] periodically queries Tendermint's
+//! RPC for its peer count, sync status, and mempool size, and records them as
+//! gauges on `pd`'s metrics endpoint.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use metrics::gauge;
+use serde_json::Value;
+
+/// Polls the Tendermint RPC at `rpc_url` every `interval`, forever.
+///
+/// Failures to reach Tendermint are logged and recorded in the
+/// `node_tendermint_rpc_up` gauge, rather than ending the task.
+pub async fn poll(rpc_url: String, interval: Duration) {
+    let client = reqwest::Client::new();
+    loop {
+        match record(&client, &rpc_url).await {
+            Ok(()) => gauge!("node_tendermint_rpc_up", 1.0),
+            Err(e) => {
+                tracing::debug!(?e, "could not poll tendermint rpc");
+                gauge!("node_tendermint_rpc_up", 0.0);
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+async fn record(client: &reqwest::Client, rpc_url: &str) -> Result<()> {
+    let net_info = get(client, rpc_url, "net_info").await?;
+    gauge!("node_tendermint_peers", number(&net_info["n_peers"])?);
+
+    let status = get(client, rpc_url, "status").await?;
+    let sync_info = &status["sync_info"];
+    gauge!(
+        "node_tendermint_latest_block_height",
+        number(&sync_info["latest_block_height"])?
+    );
+    let catching_up = sync_info["catching_up"]
+        .as_bool()
+        .ok_or_else(|| anyhow!("could not parse catching_up"))?;
+    gauge!(
+        "node_tendermint_catching_up",
+        if catching_up { 1.0 } else { 0.0 }
+    );
+
+    let mempool = get(client, rpc_url, "num_unconfirmed_txs").await?;
+    gauge!("node_tendermint_mempool_txs", number(&mempool["n_txs"])?);
+    gauge!(
+        "node_tendermint_mempool_bytes",
+        number(&mempool["total_bytes"])?
+    );
+
+    Ok(())
+}
+
+/// Fetches the `result` of a Tendermint RPC method.
+pub(crate) async fn get(client: &reqwest::Client, rpc_url: &str, method: &str) -> Result<Value> {
+    let mut rsp: Value = client
+        .get(format!("{}/{}", rpc_url.trim_end_matches('/'), method))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    match rsp.get_mut("result") {
+        Some(result) => Ok(result.take()),
+        None => Err(anyhow!(
+            "tendermint returned no result for {}: {}",
+            method,
+            rsp
+        )),
+    }
+}
+
+/// Parses a Tendermint RPC number, which is usually encoded as a string.
+pub(crate) fn number(value: &Value) -> Result<f64> {
+    match value {
+        Value::String(s) => Ok(s.parse()?),
+        Value::Number(n) => n.as_f64().ok_or_else(|| anyhow!("invalid number {}", n)),
+        _ => Err(anyhow!("expected a number, found {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Response, Server,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    /// Serves a Tendermint RPC answering each method with the given response
+    /// body, or 404 for any other method, returning its URL.
+    fn serve(responses: Vec<(&'static str, Value)>) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let make_service = make_service_fn(move |_| {
+            let responses = responses.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                    let response = match responses
+                        .iter()
+                        .find(|(method, _)| request.uri().path() == format!("/{}", method))
+                    {
+                        Some((_, body)) => Response::new(Body::from(body.to_string())),
+                        None => Response::builder().status(404).body(Body::empty()).unwrap(),
+                    };
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(make_service));
+        url
+    }
+
+    fn healthy() -> Vec<(&'static str, Value)> {
+        vec![
+            ("net_info", json!({ "result": { "n_peers": "3" } })),
+            (
+                "status",
+                json!({
+                    "result": {
+                        "sync_info": { "latest_block_height": "42", "catching_up": false }
+                    }
+                }),
+            ),
+            (
+                "num_unconfirmed_txs",
+                json!({ "result": { "n_txs": "2", "total_bytes": "512" } }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn parses_rpc_numbers() {
+        assert_eq!(number(&json!("42")).unwrap(), 42.0);
+        assert_eq!(number(&json!(7)).unwrap(), 7.0);
+        assert!(number(&json!("many")).is_err());
+        assert!(number(&json!(null)).is_err());
+    }
+
+    #[tokio::test]
+    async fn gets_the_result_of_a_method() -> Result<()> {
+        let url = serve(vec![
+            ("net_info", json!({ "result": { "n_peers": "3" } })),
+            ("status", json!({ "error": { "message": "unavailable" } })),
+        ]);
+        let client = reqwest::Client::new();
+
+        assert_eq!(
+            get(&client, &url, "net_info").await?,
+            json!({ "n_peers": "3" })
+        );
+        // Errors reported by Tendermint, or by the server, are errors.
+        assert!(get(&client, &url, "status").await.is_err());
+        assert!(get(&client, &url, "genesis").await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn records_every_gauge_or_fails() -> Result<()> {
+        let client = reqwest::Client::new();
+        record(&client, &serve(healthy())).await?;
+
+        // A response missing any of the values fails the whole poll, so that
+        // `node_tendermint_rpc_up` reports it.
+        let mut missing_sync_status = healthy();
+        missing_sync_status[1].1 = json!({ "result": { "sync_info": {} } });
+        assert!(record(&client, &serve(missing_sync_status)).await.is_err());
+        let mut missing_method = healthy();
+        missing_method.pop();
+        assert!(record(&client, &serve(missing_method)).await.is_err());
+
+        Ok(())
+    }
+}
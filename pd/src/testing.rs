@@ -0,0 +1,363 @@
+//! Helpers for testing against an in-process node.
+//!
+//! A [`Node`] runs genesis for the given app state on a fresh temporary
+//! database and serves the oblivious and specific query services on an
+//! ephemeral local port, so tests can exercise real chain state without a
+//! running testnet. It also stands in for Tendermint's RPC, checking the
+//! transactions broadcast to it with pd's mempool. It is used by pd's own
+//! tests, and by the wallet's, which enable this module with the `testing`
+//! feature.
+
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
+
+use anyhow::{anyhow, Context};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, StatusCode,
+};
+use penumbra_chain::sync::CompactBlock;
+use penumbra_crypto::{
+    keys::{SeedPhrase, SpendKey, SpendSeed},
+    Address,
+};
+use penumbra_proto::client::{
+    oblivious::{
+        oblivious_query_client::ObliviousQueryClient, oblivious_query_server::ObliviousQueryServer,
+    },
+    specific::{
+        specific_query_client::SpecificQueryClient, specific_query_server::SpecificQueryServer,
+    },
+};
+use penumbra_stake::{FundingStreams, IdentityKey, Validator};
+use rand_core::OsRng;
+use tempfile::TempDir;
+use tendermint::{
+    abci::{
+        self,
+        request::{CheckTx, CheckTxKind},
+        types::LastCommitInfo,
+        MempoolRequest, MempoolResponse,
+    },
+    account, block,
+    hash::AppHash,
+    public_key::Algorithm,
+    Genesis, Hash, PublicKey, Time,
+};
+use tokio::{net::TcpListener, sync::watch, task::JoinHandle};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tower::ServiceExt;
+
+use crate::{
+    components::{app::View as _, shielded_pool::View as _},
+    genesis::{self, Allocation},
+    reflection_service, App, Component, Mempool, NullifierCache, Oblivious, PendingTx, Storage,
+};
+
+/// An in-process node serving its query services, with gRPC reflection, on
+/// one local port as `pd start --grpc-port` does, and a stand-in for
+/// Tendermint's RPC on another.
+///
+/// The node's database and servers are torn down when it is dropped.
+pub struct Node {
+    storage: Storage,
+    oblivious: Oblivious,
+    mempool: Mempool,
+    block_heights: watch::Sender<block::Height>,
+    addr: SocketAddr,
+    server: JoinHandle<Result<(), tonic::transport::Error>>,
+    rpc_addr: SocketAddr,
+    rpc_server: JoinHandle<hyper::Result<()>>,
+    // Held so that the database outlives the node.
+    _dir: TempDir,
+}
+
+impl Node {
+    /// Starts a node whose chain has just executed genesis with the given app state.
+    pub async fn start(app_state: genesis::AppState) -> anyhow::Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let storage = Storage::load(dir.path().join("rocksdb")).await?;
+
+        let mut app = App::new(storage.overlay().await?).await?;
+        app.init_chain(&app_state).await.context("genesis failed")?;
+        app.commit(storage.clone()).await?;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (block_heights, block_heights_rx) = watch::channel(0u32.into());
+        let oblivious =
+            Oblivious::new(storage.clone()).with_block_heights(block_heights_rx.clone());
+        let mempool = Mempool::new(
+            storage.clone(),
+            block_heights_rx,
+            NullifierCache::default(),
+            None,
+            0,
+        )
+        .await?;
+        let server = tokio::spawn(
+            Server::builder()
+                .add_service(ObliviousQueryServer::new(oblivious.clone()))
+                .add_service(SpecificQueryServer::new(storage.clone()))
+                .add_service(reflection_service()?)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let rpc_listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        rpc_listener.set_nonblocking(true)?;
+        let rpc_addr = rpc_listener.local_addr()?;
+        let rpc_server = tokio::spawn(serve_rpc(rpc_listener, mempool.clone()));
+
+        Ok(Self {
+            storage,
+            oblivious,
+            mempool,
+            block_heights,
+            addr,
+            server,
+            rpc_addr,
+            rpc_server,
+            _dir: dir,
+        })
+    }
+
+    /// The node's storage, for inspecting or modifying chain state directly.
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Commits `count` empty blocks on top of the current chain, returning the
+    /// new block height.
+    ///
+    /// Only the block height, compact blocks and note commitment tree
+    /// anchors are written, which is enough for clients syncing the chain.
+    /// Subscribers to compact blocks are notified of the new blocks.
+    pub async fn append_empty_blocks(&self, count: u64) -> anyhow::Result<u64> {
+        let overlay = self.storage.overlay().await?;
+        let height = overlay.get_block_height().await?;
+        // Empty blocks leave the note commitment tree as it was.
+        let anchor = overlay
+            .nct_anchor(height)
+            .await?
+            .context("missing anchor for the latest block")?;
+        for height in height + 1..=height + count {
+            overlay
+                .set_compact_block(CompactBlock {
+                    height,
+                    ..Default::default()
+                })
+                .await;
+            overlay.set_nct_anchor(height, anchor.clone()).await;
+        }
+        overlay.put_block_height(height + count).await;
+        overlay.lock().await.commit(self.storage.clone()).await?;
+        let _ = self
+            .block_heights
+            .send(block::Height::try_from(height + count)?);
+        Ok(height + count)
+    }
+
+    /// The number of oblivious query streams the node is still producing.
+    pub fn open_streams(&self) -> usize {
+        self.oblivious.open_streams()
+    }
+
+    /// The URL the node's query services are served at.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The URL of the node's stand-in for Tendermint's RPC, which only
+    /// answers `broadcast_tx_sync`.
+    pub fn rpc_url(&self) -> String {
+        format!("http://{}", self.rpc_addr)
+    }
+
+    /// The transactions broadcast to the node and accepted by its mempool.
+    pub fn pending_txs(&self) -> Vec<PendingTx> {
+        self.mempool.pending_txs().borrow().clone()
+    }
+
+    /// Connects a client to the node's oblivious query service.
+    pub async fn oblivious_client(&self) -> anyhow::Result<ObliviousQueryClient<Channel>> {
+        Ok(ObliviousQueryClient::connect(self.url()).await?)
+    }
+
+    /// Connects a client to the node's specific query service.
+    pub async fn specific_client(&self) -> anyhow::Result<SpecificQueryClient<Channel>> {
+        Ok(SpecificQueryClient::connect(self.url()).await?)
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.server.abort();
+        self.rpc_server.abort();
+    }
+}
+
+/// Serves a stand-in for Tendermint's RPC on `listener`, which answers
+/// `broadcast_tx_sync` by checking the transaction with `mempool`, as
+/// Tendermint would before gossiping it.
+async fn serve_rpc(listener: std::net::TcpListener, mempool: Mempool) -> hyper::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let mempool = mempool.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let mempool = mempool.clone();
+                async move {
+                    Ok::<_, Infallible>(match broadcast_tx_sync(request, mempool).await {
+                        Ok(result) => Response::new(Body::from(result.to_string())),
+                        Err(e) => Response::builder()
+                            .status(StatusCode::BAD_REQUEST)
+                            .body(Body::from(e.to_string()))
+                            .expect("valid response"),
+                    })
+                }
+            }))
+        }
+    });
+    hyper::Server::from_tcp(listener)?.serve(make_service).await
+}
+
+/// Answers a JSON-RPC `broadcast_tx_sync` request with the mempool's verdict.
+async fn broadcast_tx_sync(
+    request: Request<Body>,
+    mempool: Mempool,
+) -> anyhow::Result<serde_json::Value> {
+    let body = hyper::body::to_bytes(request.into_body()).await?;
+    let request: serde_json::Value = serde_json::from_slice(&body)?;
+    if request["method"] != "broadcast_tx_sync" {
+        return Err(anyhow!("unsupported method {}", request["method"]));
+    }
+    let tx: Vec<u8> = serde_json::from_value(request["params"][0].clone())?;
+
+    let MempoolResponse::CheckTx(rsp) = mempool
+        .oneshot(MempoolRequest::CheckTx(CheckTx {
+            tx: tx.into(),
+            kind: CheckTxKind::New,
+        }))
+        .await
+        .map_err(|e| anyhow!(e))?;
+    Ok(serde_json::json!({
+        "result": {
+            "code": rsp.code,
+            "log": rsp.log,
+        }
+    }))
+}
+
+/// A fresh spend key.
+pub fn spend_key() -> SpendKey {
+    SpendKey::new(SpendSeed::from_seed_phrase(SeedPhrase::generate(OsRng), 0))
+}
+
+/// The first address of a fresh spend key.
+pub fn address() -> Address {
+    spend_key()
+        .incoming_viewing_key()
+        .payment_address(0u64.into())
+        .0
+}
+
+/// A validator with fresh identity and consensus keys.
+pub fn validator(name: &str) -> Validator {
+    let consensus_key = ed25519_consensus::SigningKey::new(OsRng).verification_key();
+    Validator {
+        identity_key: IdentityKey(
+            spend_key()
+                .full_viewing_key()
+                .spend_verification_key()
+                .clone(),
+        ),
+        consensus_key: PublicKey::from_raw_ed25519(consensus_key.as_bytes())
+            .expect("verification keys are valid ed25519 keys"),
+        name: name.to_string(),
+        website: String::new(),
+        description: String::new(),
+        funding_streams: FundingStreams::new(),
+        sequence_number: 0,
+    }
+}
+
+/// Genesis allocations of `amount` of each validator's delegation token to
+/// `address`, giving the validators voting power from genesis.
+pub fn delegations(validators: &[Validator], amount: u64, address: Address) -> Vec<Allocation> {
+    validators
+        .iter()
+        .map(|validator| Allocation {
+            amount,
+            denom: validator
+                .identity_key
+                .delegation_token()
+                .denom()
+                .to_string(),
+            address,
+        })
+        .collect()
+}
+
+/// A `BeginBlock` request for the block at `height`, made `seconds` after the
+/// Unix epoch, with no evidence of misbehavior.
+///
+/// Only the height and time of the header are read by the application, so
+/// the hashes in it are left empty.
+pub fn begin_block(height: u64, seconds: i64) -> abci::request::BeginBlock {
+    abci::request::BeginBlock {
+        hash: Hash::None,
+        header: block::Header {
+            version: block::header::Version { block: 11, app: 0 },
+            chain_id: "penumbra-test".parse().expect("valid chain ID"),
+            height: height.try_into().expect("valid height"),
+            time: Time::from_unix_timestamp(seconds, 0).expect("valid time"),
+            last_block_id: None,
+            last_commit_hash: None,
+            data_hash: None,
+            validators_hash: Hash::None,
+            next_validators_hash: Hash::None,
+            consensus_hash: Hash::None,
+            app_hash: AppHash::try_from(Vec::new()).expect("empty app hash is valid"),
+            last_results_hash: None,
+            evidence_hash: None,
+            proposer_address: account::Id::new([0; 20]),
+        },
+        last_commit_info: LastCommitInfo {
+            round: Default::default(),
+            votes: Vec::new(),
+        },
+        byzantine_validators: Vec::new(),
+    }
+}
+
+/// A Tendermint genesis file for a chain with the given app state, named
+/// after the chain ID of its chain parameters.
+pub fn tendermint_genesis(app_state: genesis::AppState) -> Genesis<genesis::AppState> {
+    Genesis {
+        genesis_time: Time::from_unix_timestamp(0, 0).expect("valid time"),
+        chain_id: app_state
+            .chain_params
+            .chain_id
+            .parse()
+            .expect("valid chain ID"),
+        initial_height: 0,
+        consensus_params: tendermint::consensus::Params {
+            block: block::Size {
+                max_bytes: 22020096,
+                max_gas: -1,
+                time_iota_ms: 500,
+            },
+            evidence: tendermint::evidence::Params {
+                max_age_num_blocks: 100000,
+                max_age_duration: tendermint::evidence::Duration(Duration::new(86400, 0)),
+                max_bytes: 1048576,
+            },
+            validator: tendermint::consensus::params::ValidatorParams {
+                pub_key_types: vec![Algorithm::Ed25519],
+            },
+            version: None,
+        },
+        app_hash: vec![],
+        app_state,
+        validators: vec![],
+    }
+}
@@ -1,14 +1,25 @@
-use std::{env::current_dir, fmt, io::Read, path::PathBuf, str::FromStr};
+use std::{
+    env::current_dir,
+    fmt,
+    io::Read,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use directories::UserDirs;
 use penumbra_crypto::Address;
+use penumbra_proto::serializers::base64str;
+use penumbra_stake::IdentityKey;
 use regex::{Captures, Regex};
 use serde::{de, Deserialize};
 use tendermint::{node::Id, PrivateKey};
 
 use crate::genesis;
 
+pub mod join;
+
 /// Methods and types used for generating testnet configurations.
 
 pub fn parse_allocations(input: impl Read) -> Result<Vec<genesis::Allocation>> {
@@ -64,10 +75,14 @@ where
 /// Hardcoded Tendermint config template. Should produce tendermint config similar to
 /// https://github.com/tendermint/tendermint/blob/6291d22f46f4c4f9121375af700dbdafa51577e7/cmd/tendermint/commands/init.go#L45
 /// There exists https://github.com/informalsystems/tendermint-rs/blob/a12118978f2ffea4042d6d38ebfb290d12611314/config/src/config.rs#L23 but
-/// this seemed more straightforward as only the moniker is changed right now.
+/// this seemed more straightforward as only a few fields are changed right now.
+///
+/// `proxy_app` is the address at which Tendermint reaches pd's ABCI server, e.g.
+/// `tcp://127.0.0.1:26658` or `unix:///run/pd/abci.sock`.
 pub fn generate_tm_config(
     node_name: &str,
-    persistent_peers: &[(Id, std::net::Ipv4Addr)],
+    proxy_app: &str,
+    persistent_peers: &[(Id, NodeHost)],
 ) -> String {
     let peers_string = persistent_peers
         .iter()
@@ -78,15 +93,25 @@ pub fn generate_tm_config(
         // crypto package.
         // the peer addresses need to match this impl: https://github.com/tendermint/tendermint/blob/f2a8f5e054cf99ebe246818bb6d71f41f9a30faa/internal/p2p/address.go#L43
         // The ID is for the node being connected to, *not* the connecting node's ID.
-        .map(|(id, ip)| format!("{}@{}:26656", id, ip))
+        .map(|(id, host)| format!("{}@{}:{}", id, host, P2P_PORT))
         .collect::<Vec<String>>()
         .join(",");
     format!(
         include_str!("../../testnets/tm_config_template.toml"),
-        node_name, peers_string,
+        proxy_app, node_name, peers_string,
     )
 }
 
+/// The address at which Tendermint reaches pd's ABCI server, for the
+/// `proxy_app` of [`generate_tm_config`]: the Unix domain socket `abci_uds`, as
+/// served by `pd start --abci-uds`, or else pd's default ABCI port.
+pub fn proxy_app(abci_uds: Option<&Path>) -> String {
+    match abci_uds {
+        Some(path) => format!("unix://{}", path.display()),
+        None => "tcp://127.0.0.1:26658".to_string(),
+    }
+}
+
 /// Represents initial allocations to the testnet.
 #[derive(Debug, Deserialize)]
 pub struct TestnetAllocation {
@@ -111,6 +136,245 @@ pub struct TestnetValidator {
     pub description: String,
     pub funding_streams: Vec<TestnetFundingStream>,
     pub sequence_number: u32,
+    /// The delegation tokens allocated to the validator at genesis, which
+    /// determine its initial voting power.
+    #[serde(default = "default_self_delegation", deserialize_with = "string_u64")]
+    pub self_delegation: u64,
+    /// The host the validator's node listens for peers at, which the other
+    /// generated nodes dial: an IPv4 or IPv6 address, or a DNS name. If
+    /// unset, the node is given the next address from `--starting-ip`.
+    #[serde(default)]
+    pub listen_address: Option<String>,
+    /// The validator's existing identity key, as written in the genesis file,
+    /// to use instead of generating one; the generated node then has no
+    /// signing key for it.
+    #[serde(default)]
+    pub identity_key: Option<IdentityKey>,
+    /// The validator's existing Ed25519 consensus public key, base64-encoded
+    /// as in the genesis file, to use instead of generating one; the
+    /// generated node then has no `priv_validator_key.json`, and must sign
+    /// with the key wherever it is held, e.g. through a remote signer.
+    #[serde(default, deserialize_with = "base64_consensus_key")]
+    pub consensus_key: Option<tendermint::PublicKey>,
+}
+
+fn base64_consensus_key<'de, D>(deserializer: D) -> Result<Option<tendermint::PublicKey>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let bytes = base64str::deserialize(deserializer)?;
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    tendermint::PublicKey::from_raw_ed25519(&bytes)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom("consensus key is not an Ed25519 public key"))
+}
+
+/// The delegation tokens allocated to each validator at genesis, unless its
+/// entry in the validators file says otherwise.
+pub const DEFAULT_SELF_DELEGATION: u64 = 1_000_000;
+
+fn default_self_delegation() -> u64 {
+    DEFAULT_SELF_DELEGATION
+}
+
+/// Tendermint's maximum total voting power of a validator set.
+const MAX_TOTAL_VOTING_POWER: u64 = i64::MAX as u64 / 8;
+
+/// Checks that the validators' self-delegations make a validator set the
+/// chain can start from.
+///
+/// Every genesis validator starts in the active set, with voting power in
+/// proportion to its self-delegation, so each must have some, and there may
+/// be no more of them than the active validator limit.
+pub fn check_validator_stakes(
+    validators: &[TestnetValidator],
+    active_validator_limit: u64,
+) -> Result<()> {
+    if let Some(v) = validators.iter().find(|v| v.self_delegation == 0) {
+        return Err(anyhow!(
+            "validator {:?} has no self-delegation, so would have no voting power",
+            v.name
+        ));
+    }
+
+    if validators.len() as u64 > active_validator_limit {
+        let mut by_stake = validators.iter().collect::<Vec<_>>();
+        by_stake.sort_by_key(|v| std::cmp::Reverse(v.self_delegation));
+        let excluded = by_stake[active_validator_limit as usize..]
+            .iter()
+            .map(|v| format!("{:?}", v.name))
+            .collect::<Vec<_>>();
+        return Err(anyhow!(
+            "{} validators exceed the active validator limit of {}, which would leave out {}",
+            validators.len(),
+            active_validator_limit,
+            excluded.join(", ")
+        ));
+    }
+
+    let total = validators
+        .iter()
+        .try_fold(0u64, |total, v| total.checked_add(v.self_delegation))
+        .filter(|total| *total <= MAX_TOTAL_VOTING_POWER);
+    if total.is_none() {
+        return Err(anyhow!(
+            "the validators' total self-delegation exceeds Tendermint's maximum voting power of {}",
+            MAX_TOTAL_VOTING_POWER
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that no two validators import the same identity key or consensus
+/// key, which the chain would reject at genesis.
+pub fn check_imported_keys(validators: &[TestnetValidator]) -> Result<()> {
+    for (n, validator) in validators.iter().enumerate() {
+        for other in &validators[..n] {
+            let same_identity =
+                validator.identity_key.is_some() && validator.identity_key == other.identity_key;
+            let same_consensus =
+                validator.consensus_key.is_some() && validator.consensus_key == other.consensus_key;
+            if same_identity || same_consensus {
+                return Err(anyhow!(
+                    "validators {:?} and {:?} import the same {} key",
+                    other.name,
+                    validator.name,
+                    if same_identity {
+                        "identity"
+                    } else {
+                        "consensus"
+                    }
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The port Tendermint listens for peers on, in the generated configs.
+const P2P_PORT: u16 = 26656;
+
+/// How far apart the addresses of generated nodes are, to leave room for the
+/// other services run alongside each.
+const NODE_IP_STEP: u16 = 10;
+
+/// The host a generated node is reached at by its peers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeHost {
+    Ip(IpAddr),
+    Dns(String),
+}
+
+impl FromStr for NodeHost {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let unbracketed = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s);
+        if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+            return Ok(NodeHost::Ip(ip));
+        }
+        let is_label = |label: &str| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        };
+        // A name whose last label is numeric would be read as a malformed IPv4 address.
+        let numeric = s
+            .rsplit('.')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .all(|c| c.is_ascii_digit());
+        if s.len() <= 253 && s.split('.').all(is_label) && !numeric {
+            Ok(NodeHost::Dns(s.to_ascii_lowercase()))
+        } else {
+            Err(anyhow!("{:?} is neither an IP address nor a DNS name", s))
+        }
+    }
+}
+
+/// Formats the host as it is written in a peer address, with an IPv6 address
+/// in brackets.
+impl fmt::Display for NodeHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeHost::Ip(IpAddr::V6(ip)) => write!(f, "[{}]", ip),
+            NodeHost::Ip(ip) => write!(f, "{}", ip),
+            NodeHost::Dns(name) => f.write_str(name),
+        }
+    }
+}
+
+/// The host each validator's node is reached at, in order, followed by the
+/// host of each of `fullnodes` non-validator nodes.
+///
+/// A validator's node is at its `listen_address`, if it has one, and
+/// otherwise at the `n`th address from `starting_ip`, for the `n`th validator,
+/// counting up in the last octet (or, for IPv6, the last segment). The full
+/// nodes are at the addresses counted after the validators'. Running out of
+/// addresses there, or placing two nodes at the same host, is an error.
+pub fn node_hosts(
+    validators: &[TestnetValidator],
+    fullnodes: usize,
+    starting_ip: IpAddr,
+) -> Result<Vec<NodeHost>> {
+    let nodes = validators
+        .iter()
+        .map(|v| (format!("validator {:?}", v.name), v.listen_address.as_ref()))
+        .chain((0..fullnodes).map(|i| (format!("full node {}", i), None)))
+        .collect::<Vec<_>>();
+
+    let mut hosts: Vec<NodeHost> = Vec::with_capacity(nodes.len());
+    for (n, (node, listen_address)) in nodes.iter().enumerate() {
+        let host = match listen_address {
+            Some(address) => address
+                .parse()
+                .with_context(|| format!("invalid listen address for {}", node))?,
+            None => NodeHost::Ip(nth_ip(starting_ip, n).ok_or_else(|| {
+                anyhow!(
+                    "no address is left after --starting-ip {} for {}: \
+                     start from a lower address, or give validators a listen_address",
+                    starting_ip,
+                    node
+                )
+            })?),
+        };
+        if let Some(other) = hosts.iter().position(|other| *other == host) {
+            return Err(anyhow!(
+                "{} and {} would both listen at {}",
+                nodes[other].0,
+                node,
+                host
+            ));
+        }
+        hosts.push(host);
+    }
+    Ok(hosts)
+}
+
+/// The `n`th node address from `start`, if it does not overflow the last
+/// octet or segment.
+fn nth_ip(start: IpAddr, n: usize) -> Option<IpAddr> {
+    let offset = u16::try_from(n).ok()?.checked_mul(NODE_IP_STEP)?;
+    match start {
+        IpAddr::V4(ip) => {
+            let mut octets = ip.octets();
+            octets[3] = octets[3].checked_add(u8::try_from(offset).ok()?)?;
+            Some(Ipv4Addr::from(octets).into())
+        }
+        IpAddr::V6(ip) => {
+            let mut segments = ip.segments();
+            segments[7] = segments[7].checked_add(offset)?;
+            Some(Ipv6Addr::from(segments).into())
+        }
+    }
 }
 
 impl TryFrom<TestnetAllocation> for genesis::Allocation {
@@ -171,3 +435,33 @@ pub fn canonicalize_path(input: &str) -> PathBuf {
         PathBuf::from(format!("{}/{}", current_dir().unwrap().display(), input))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The value of `key` in the generated Tendermint config.
+    fn config_value(config: &str, key: &str) -> String {
+        let config: toml::Value = toml::from_str(config).unwrap();
+        config[key].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn tendermint_reaches_abci_on_a_unix_socket() {
+        let proxy_app = proxy_app(Some(Path::new("/run/pd/abci.sock")));
+        assert_eq!(proxy_app, "unix:///run/pd/abci.sock");
+
+        let config = generate_tm_config("node0", &proxy_app, &[]);
+        assert_eq!(
+            config_value(&config, "proxy-app"),
+            "unix:///run/pd/abci.sock"
+        );
+        assert_eq!(config_value(&config, "moniker"), "node0");
+    }
+
+    #[test]
+    fn tendermint_reaches_abci_on_the_default_port() {
+        let config = generate_tm_config("node0", &proxy_app(None), &[]);
+        assert_eq!(config_value(&config, "proxy-app"), "tcp://127.0.0.1:26658");
+    }
+}
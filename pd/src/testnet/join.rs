@@ -0,0 +1,202 @@
+//! Setting up a node to join a network which is already running, as
+//! `pd testnet join` does.
+//!
+//! Where `generate-testnet` writes the directories of every node of a new
+//! network, joining one only needs a directory for the local node: the
+//! network's genesis, some peers to dial, and keys of its own. The genesis and
+//! peers are fetched from the Tendermint RPC of a node already on the network,
+//! or the genesis from a URL publishing it, and the peers from a
+//! [`SignedPeerList`].
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use penumbra_crypto::keys::SeedPhrase;
+use rand_core::OsRng;
+use serde_json::Value;
+use tendermint_config::{NodeKey, PrivValidatorKey};
+
+use super::{generate_tm_config, get_validator_state};
+use crate::{
+    genesis,
+    keys::NodeKeys,
+    peers::{self, SignedPeerList},
+};
+
+/// The genesis of the network being joined, and the peers to dial.
+#[derive(Clone, Debug)]
+pub struct Bootstrap {
+    pub chain_id: String,
+    /// The network's Tendermint genesis file.
+    pub genesis_json: Vec<u8>,
+    /// The peers, each as `<node id>@<host>:<port>`.
+    pub peers: Vec<String>,
+}
+
+/// Where [`Bootstrap::fetch`] fetches the genesis and peers from.
+#[derive(Clone, Debug)]
+pub struct Sources {
+    /// The Tendermint RPC endpoint of a node on the network.
+    pub node: String,
+    /// Fetch the genesis file published at this URL, rather than the node's.
+    pub genesis_url: Option<String>,
+    /// Fetch the peer list published at this URL, signed with this key,
+    /// rather than dialing the node and its peers.
+    pub peer_list: Option<(String, ed25519_consensus::VerificationKey)>,
+}
+
+impl Bootstrap {
+    /// Fetches the network's genesis and peers, checking that the genesis is
+    /// one `pd` can start from.
+    pub async fn fetch(sources: &Sources) -> Result<Self> {
+        let client = reqwest::Client::new();
+        let node = sources.node.trim_end_matches('/');
+
+        let genesis = match &sources.genesis_url {
+            Some(url) => get_json(&client, url).await?,
+            None => rpc(&client, node, "genesis").await?["genesis"].take(),
+        };
+        let genesis_json = serde_json::to_vec_pretty(&genesis)?;
+        let (chain_id, _) = genesis::parse(&genesis_json).context("invalid genesis")?;
+
+        let peers = match &sources.peer_list {
+            Some((url, signer)) => {
+                let list: SignedPeerList = serde_json::from_value(get_json(&client, url).await?)
+                    .with_context(|| format!("invalid peer list at {}", url))?;
+                list.verify(signer, &chain_id)?;
+                list.peers
+            }
+            None => node_peers(&client, node, &chain_id).await?,
+        };
+
+        Ok(Bootstrap {
+            chain_id,
+            genesis_json,
+            peers,
+        })
+    }
+
+    /// Writes a directory for a new node joining the network, laid out as
+    /// those written by `generate-testnet`, with `pd`'s state in `pd` and
+    /// Tendermint's home in `tendermint`.
+    ///
+    /// The node's keys are derived from a fresh seed phrase, which is saved
+    /// with them, so that they can be restored with `pd keys derive`, and so
+    /// that the node can later be made a validator.
+    pub fn write_node_dir(&self, dir: &Path, moniker: &str, proxy_app: &str) -> Result<()> {
+        if dir.exists() {
+            return Err(anyhow!("{} already exists", dir.display()));
+        }
+        let config_dir = dir.join("tendermint").join("config");
+        let data_dir = dir.join("tendermint").join("data");
+        fs::create_dir_all(&config_dir)?;
+        fs::create_dir_all(&data_dir)?;
+        fs::create_dir_all(dir.join("pd"))?;
+
+        let config = generate_tm_config(moniker, proxy_app, &[]);
+        let config = peers::rewrite_config(&config, &self.peers)?;
+        write(config_dir.join("config.toml"), config)?;
+        write(config_dir.join("genesis.json"), &self.genesis_json)?;
+
+        let seed_phrase = SeedPhrase::generate(OsRng);
+        write(
+            config_dir.join("validator_seed_phrase.txt"),
+            seed_phrase.to_string(),
+        )?;
+        let keys = NodeKeys::derive(seed_phrase, 0);
+        write(
+            config_dir.join("node_key.json"),
+            serde_json::to_string_pretty(&NodeKey {
+                priv_key: keys.node_key,
+            })?,
+        )?;
+        let pub_key = keys.consensus_key.public_key();
+        write(
+            config_dir.join("priv_validator_key.json"),
+            serde_json::to_string_pretty(&PrivValidatorKey {
+                address: pub_key.into(),
+                pub_key,
+                priv_key: keys.consensus_key,
+            })?,
+        )?;
+        write(
+            data_dir.join("priv_validator_state.json"),
+            get_validator_state(),
+        )?;
+
+        Ok(())
+    }
+}
+
+fn write(path: PathBuf, contents: impl AsRef<[u8]>) -> Result<()> {
+    fs::write(&path, contents).with_context(|| format!("could not write {}", path.display()))
+}
+
+async fn get_json(client: &reqwest::Client, url: &str) -> Result<Value> {
+    client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("could not reach {}", url))?
+        .error_for_status()?
+        .json()
+        .await
+        .with_context(|| format!("invalid JSON at {}", url))
+}
+
+/// The result of calling `method` on the Tendermint RPC at `node`.
+async fn rpc(client: &reqwest::Client, node: &str, method: &str) -> Result<Value> {
+    let mut response = get_json(client, &format!("{}/{}", node, method)).await?;
+    if let Some(error) = response.get("error") {
+        return Err(anyhow!("{} failed on {}: {}", method, node, error));
+    }
+    Ok(response["result"].take())
+}
+
+/// The node at `node`, and the peers it is connected to, as peers to dial.
+///
+/// Nodes usually listen on an unspecified address, so each is dialed at the
+/// host it was reached at, on the port it listens on.
+async fn node_peers(client: &reqwest::Client, node: &str, chain_id: &str) -> Result<Vec<String>> {
+    let status = rpc(client, node, "status").await?;
+    let network = status["node_info"]["network"].as_str().unwrap_or_default();
+    if network != chain_id {
+        return Err(anyhow!(
+            "{} is on chain {:?}, not {:?}",
+            node,
+            network,
+            chain_id
+        ));
+    }
+    let host = reqwest::Url::parse(node)?
+        .host_str()
+        .ok_or_else(|| anyhow!("{} has no host", node))?
+        .to_string();
+    let mut peers = vec![peer(&status["node_info"], &host)?];
+
+    let net_info = rpc(client, node, "net_info").await?;
+    for info in net_info["peers"].as_array().into_iter().flatten() {
+        let remote_ip = info["remote_ip"].as_str().unwrap_or_default();
+        match peer(&info["node_info"], remote_ip) {
+            Ok(peer) => peers.push(peer),
+            Err(error) => tracing::warn!(?error, "skipping peer"),
+        }
+    }
+    Ok(peers)
+}
+
+/// A peer at `host`, on the port its node info says it listens on.
+fn peer(node_info: &Value, host: &str) -> Result<String> {
+    let id = node_info["id"].as_str().unwrap_or_default();
+    let listen_addr = node_info["listen_addr"].as_str().unwrap_or_default();
+    let port = listen_addr
+        .rsplit_once(':')
+        .map(|(_, port)| port)
+        .ok_or_else(|| anyhow!("peer {} has no listen port", id))?;
+    let peer = format!("{}@{}:{}", id, host, port);
+    peers::check_peer(&peer)?;
+    Ok(peer)
+}
@@ -0,0 +1,208 @@
+//! Coordinated upgrades of a running chain, keeping its state.
+//!
+//! Rather than starting every new version of a testnet from genesis, losing
+//! its balances and delegations, the validators can upgrade it in place:
+//!
+//! 1. They agree on a halt height, and restart their nodes with `pd start
+//!    --halt-height <height>`, so that each stops once it has committed the
+//!    block at that height.
+//! 2. They install the new version of `pd`, which registers in [`MIGRATIONS`]
+//!    the changes it needs made to the state of that chain at that height,
+//!    and run `pd migrate`. This executes the migrations in place of the next
+//!    block, commits them, and writes a Tendermint genesis which resumes the
+//!    chain from the block after that, with the migrated app hash.
+//! 3. They reset Tendermint's data, replace its genesis with the new one, and
+//!    start both again.
+//!
+//! Every node migrates the same state in the same way, so they all reach the
+//! app hash of the new genesis, which Tendermint checks on startup.
+
+use anyhow::{anyhow, Context, Result};
+use futures::future::BoxFuture;
+use jmt::Version;
+use tendermint::{abci::types::ValidatorUpdate, validator, Genesis, Time};
+
+use crate::{
+    components::{
+        app::View as _,
+        key_schema::{KeySchema, StateKey},
+    },
+    App, Component, Overlay, OverlayExt, Storage,
+};
+
+/// A change to the state of a particular chain, made while it is halted for
+/// an upgrade.
+pub struct Migration {
+    /// A unique name for the migration, under which it is recorded as applied.
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The chain to migrate.
+    pub chain_id: &'static str,
+    /// The halt height of the upgrade: the migration is made after the block
+    /// at this height.
+    pub height: u64,
+    pub run: for<'a> fn(&'a Overlay) -> BoxFuture<'a, Result<()>>,
+}
+
+/// Every migration this version of `pd` can make, in the order they are made.
+///
+/// No chain has been upgraded in place yet.
+pub const MIGRATIONS: &[Migration] = &[];
+
+/// The outcome of [`migrate`].
+#[derive(Clone, Debug)]
+pub struct Migrated {
+    pub chain_id: String,
+    /// The height of the block the migrations took the place of, one after
+    /// the halt height.
+    pub height: u64,
+    /// The version the migrated state was committed at.
+    pub version: Version,
+    pub app_hash: [u8; 32],
+    /// The time of the block at the halt height, if the chain had begun one.
+    pub time: Option<Time>,
+    /// The names of the migrations made.
+    pub migrations: Vec<&'static str>,
+    /// The validator set as of the migrated state.
+    pub validators: Vec<ValidatorUpdate>,
+}
+
+/// Makes the migrations of `migrations` registered for the stored chain at its
+/// latest height, committing them as the next block.
+///
+/// This refuses to run if there are no such migrations, so it cannot be run
+/// twice, nor before the node reached the halt height.
+pub async fn migrate(storage: &Storage, migrations: &[Migration]) -> Result<Migrated> {
+    if storage.latest_version().await?.is_none() {
+        return Err(anyhow!("the database is empty"));
+    }
+    let overlay = storage.overlay().await?;
+    let chain_id = overlay.get_chain_id().await?;
+    let halt_height = overlay.get_block_height().await?;
+    let time = if halt_height == 0 {
+        None
+    } else {
+        Some(overlay.get_block_timestamp().await?)
+    };
+
+    let due: Vec<_> = migrations
+        .iter()
+        .filter(|migration| migration.chain_id == chain_id && migration.height == halt_height)
+        .collect();
+    if due.is_empty() {
+        return Err(anyhow!(
+            "this version of pd has no migrations for chain {:?} at height {}; the node must \
+             have been halted at the upgrade's height with `pd start --halt-height`",
+            chain_id,
+            halt_height
+        ));
+    }
+
+    // The migrations take the place of a block, which, with block-count
+    // epochs, must not be the one ending the epoch, as nothing would end it.
+    let height = halt_height + 1;
+    let params = overlay.get_chain_params().await?;
+    if params.epoch_duration_seconds == 0
+        && overlay
+            .get_current_epoch()
+            .await?
+            .is_epoch_end(height, params.epoch_duration)
+    {
+        return Err(anyhow!(
+            "height {} ends an epoch, so the chain cannot be migrated after height {}; halt it \
+             at another height",
+            height,
+            halt_height
+        ));
+    }
+
+    overlay.put_block_height(height).await;
+    for migration in &due {
+        tracing::info!(
+            name = migration.name,
+            description = migration.description,
+            "migrating chain state"
+        );
+        (migration.run)(&overlay)
+            .await
+            .with_context(|| format!("migration {} failed", migration.name))?;
+        overlay
+            .put_proto(applied_key(migration.name).into(), height)
+            .await;
+    }
+    let (root_hash, version) = overlay.lock().await.commit(storage.clone()).await?;
+    storage.put_app_hash(version, root_hash).await?;
+
+    let app = App::new(storage.overlay().await?).await?;
+    Ok(Migrated {
+        chain_id,
+        height,
+        version,
+        app_hash: root_hash.0,
+        time,
+        migrations: due.iter().map(|migration| migration.name).collect(),
+        validators: app.tm_validator_updates().await?,
+    })
+}
+
+/// Rewrites the chain's original Tendermint genesis to resume the chain from
+/// the migrated state, from the block after the migrations.
+///
+/// The genesis is derived from the state alone, so every validator writes the
+/// same one.
+pub fn resume_genesis(
+    mut genesis: Genesis<serde_json::Value>,
+    migrated: &Migrated,
+) -> Result<Genesis<serde_json::Value>> {
+    if genesis.chain_id.as_str() != migrated.chain_id {
+        return Err(anyhow!(
+            "the genesis is for chain {:?}, but the migrated chain is {:?}",
+            genesis.chain_id.as_str(),
+            migrated.chain_id
+        ));
+    }
+
+    // Tendermint requires the first block to have the genesis time, and each
+    // block's time to be after the last's.
+    if let Some(time) = migrated.time {
+        genesis.genesis_time = time;
+    }
+    genesis.initial_height = (migrated.version + 1).try_into()?;
+    genesis.app_hash = migrated.app_hash.to_vec();
+    // Tendermint does not send `InitChain` to an application which already
+    // has state, so it takes the validator set from the genesis instead.
+    genesis.validators = migrated
+        .validators
+        .iter()
+        .map(|update| validator::Info::new(update.pub_key.clone(), update.power))
+        .collect();
+    Ok(genesis)
+}
+
+/// The height of the block in whose place the named migration was made, if it
+/// has been.
+pub async fn applied(overlay: &Overlay, name: &str) -> Result<Option<u64>> {
+    overlay.get_proto(applied_key(name).into()).await
+}
+
+fn applied_key(name: &str) -> String {
+    format!("upgrade/migrations/{}", name)
+}
+
+/// The keys recording the migrations made, for the
+/// [`key_schema`](crate::components::key_schema) registry.
+pub(crate) const KEY_SCHEMA: KeySchema = KeySchema {
+    component: "upgrade",
+    keys: state_keys,
+};
+
+fn state_keys(_overlay: &Overlay) -> BoxFuture<'_, Result<Vec<StateKey>>> {
+    Box::pin(async move {
+        Ok(MIGRATIONS
+            .iter()
+            .map(|migration| {
+                StateKey::new("upgrade/migrations/{name}", applied_key(migration.name))
+            })
+            .collect())
+    })
+}
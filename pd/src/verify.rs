@@ -0,0 +1,260 @@
+//! Consistency checks of the stored state, run by `pd start` before serving
+//! anything and on demand by `pd verify`.
+//!
+//! A node whose state is internally inconsistent (e.g., because the database
+//! was only partially restored from a backup, or a write was lost in a crash)
+//! would otherwise start and then fail consensus with an app hash mismatch at
+//! the next block, long after the damage was done. The checks are:
+//!
+//! - the root hash of the latest version of the tree matches the app hash
+//!   recorded when that version was committed;
+//! - the note commitment tree stored in the state has the anchor recorded for
+//!   the latest block;
+//! - the height markers kept by the components agree with the tree version.
+//!
+//! [`check_stake`] separately runs the staking invariants, which every block
+//! checks as it executes, over a range of past versions.
+
+use anyhow::Result;
+use jmt::JellyfishMerkleTree;
+use penumbra_crypto::merkle::{NoteCommitmentTree, TreeExt};
+
+use crate::{
+    components::{app::View as _, shielded_pool::View as _, staking::View as _},
+    OverlayExt, Storage,
+};
+
+/// The outcome of checking the stored state.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// The latest version of the tree, or `None` if the database is empty.
+    pub version: Option<u64>,
+    /// Descriptions of every inconsistency found.
+    pub problems: Vec<String>,
+    /// Descriptions of checks that could not be made, e.g., because the data
+    /// they need predates this version of `pd`.
+    pub skipped: Vec<String>,
+}
+
+impl Report {
+    /// Whether no inconsistencies were found.
+    pub fn is_consistent(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Checks that the latest version of the stored state is internally consistent.
+///
+/// An empty database is trivially consistent.
+pub async fn check(storage: &Storage) -> Result<Report> {
+    let version = match storage.latest_version().await? {
+        Some(version) => version,
+        None => return Ok(Report::default()),
+    };
+    let mut report = Report {
+        version: Some(version),
+        ..Default::default()
+    };
+
+    // The root hash of the tree must be the app hash that was committed.
+    let root_hash = JellyfishMerkleTree::new(storage)
+        .get_root_hash(version)
+        .await?;
+    match storage.app_hash(version).await? {
+        Some(app_hash) if app_hash.0 != root_hash.0 => report.problems.push(format!(
+            "root hash {} of version {} differs from the committed app hash {}",
+            hex::encode(root_hash.0),
+            version,
+            hex::encode(app_hash.0),
+        )),
+        Some(_) => {}
+        None => report
+            .skipped
+            .push(format!("no app hash was recorded for version {}", version)),
+    }
+
+    let overlay = storage.overlay().await?;
+
+    // The height markers must agree with the version, which is the height of
+    // the last committed block.
+    let height = overlay.get_block_height().await?;
+    if height != version {
+        report.problems.push(format!(
+            "block height {} differs from tree version {}",
+            height, version
+        ));
+    }
+    let epoch = overlay.get_current_epoch().await?;
+    if epoch.start_height > height {
+        report.problems.push(format!(
+            "epoch {} starts at height {}, after the current height {}",
+            epoch.index, epoch.start_height, height
+        ));
+    }
+    match overlay.compact_block(height).await? {
+        Some(compact_block) if compact_block.height != height => report.problems.push(format!(
+            "compact block stored for height {} is for height {}",
+            height, compact_block.height
+        )),
+        Some(_) => {}
+        None => report
+            .problems
+            .push(format!("no compact block is stored for height {}", height)),
+    }
+
+    // The stored note commitment tree must have the anchor recorded for the
+    // last block, or the next block would be built on a different tree.
+    let nct_data = overlay
+        .lock()
+        .await
+        .get(b"shielded_pool/nct_data".into())
+        .await?;
+    match nct_data {
+        Some(bytes) => {
+            let nct: NoteCommitmentTree = bincode::deserialize(&bytes)?;
+            let root = nct.root2();
+            match overlay.nct_anchor(height).await? {
+                Some(anchor) if anchor != root => report.problems.push(format!(
+                    "note commitment tree root {} differs from the anchor {} recorded for \
+                     height {}",
+                    root, anchor, height
+                )),
+                Some(_) => {}
+                None => report
+                    .skipped
+                    .push(format!("no anchor was recorded for height {}", height)),
+            }
+        }
+        None => report
+            .problems
+            .push("no note commitment tree is stored".to_string()),
+    }
+
+    Ok(report)
+}
+
+/// Checks the staking invariants at every version of the stored state from
+/// `from` to `to`, or to the latest version: each validator's delegation
+/// tokens, valued at its exchange rate, must be backed by its pool of staking
+/// tokens.
+///
+/// The report's version is the last version checked.
+pub async fn check_stake(storage: &Storage, from: u64, to: Option<u64>) -> Result<Report> {
+    let latest = match storage.latest_version().await? {
+        Some(version) => version,
+        None => return Ok(Report::default()),
+    };
+    let to = to.map_or(latest, |to| to.min(latest));
+    let mut report = Report {
+        version: Some(to),
+        ..Default::default()
+    };
+
+    let mut unrecorded = 0;
+    for version in from..=to {
+        let overlay = storage.overlay_at(version).await?;
+        for identity_key in overlay.validator_list().await? {
+            if overlay.pool_size(&identity_key).await?.is_none() {
+                unrecorded += 1;
+                continue;
+            }
+            if let Some(violation) = overlay.pool_invariant_violation(&identity_key).await? {
+                report
+                    .problems
+                    .push(format!("at height {}: {}", version, violation));
+            }
+        }
+    }
+    if unrecorded > 0 {
+        report.skipped.push(format!(
+            "{} validator pools had not been recorded yet at the heights checked",
+            unrecorded
+        ));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use jmt::RootHash;
+
+    use super::*;
+    use crate::{
+        genesis,
+        testing::{address, delegations, validator, Node},
+    };
+
+    /// Starts a node from a genesis state with one delegated validator.
+    async fn node() -> Result<Node> {
+        let validators = vec![validator("a")];
+        Node::start(genesis::AppState {
+            allocations: delegations(&validators, 1_000, address()),
+            validators,
+            ..Default::default()
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn empty_database_is_consistent() -> Result<()> {
+        let storage = Storage::in_memory().await?;
+        let report = check(&storage).await?;
+        assert_eq!(report.version, None);
+        assert!(report.is_consistent());
+        assert!(check_stake(&storage, 0, None).await?.is_consistent());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn committed_blocks_are_consistent() -> Result<()> {
+        let node = node().await?;
+        let version = node.storage().latest_version().await?;
+
+        let report = check(node.storage()).await?;
+        assert_eq!(report.version, version);
+        assert!(report.is_consistent(), "{:?}", report.problems);
+
+        let report = check_stake(node.storage(), 0, None).await?;
+        assert_eq!(report.version, version);
+        assert!(report.is_consistent(), "{:?}", report.problems);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn detects_tree_diverging_from_app_hash() -> Result<()> {
+        let node = node().await?;
+        let version = node.storage().latest_version().await?.unwrap();
+        node.storage()
+            .put_app_hash(version, RootHash([0; 32]))
+            .await?;
+
+        let report = check(node.storage()).await?;
+        assert_eq!(report.problems.len(), 1, "{:?}", report.problems);
+        assert!(report.problems[0].contains("differs from the committed app hash"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn detects_height_behind_tree_version() -> Result<()> {
+        let node = node().await?;
+
+        // Commit a version that was not produced by executing a block, so the
+        // height markers are left behind and no app hash is recorded.
+        let overlay = node.storage().overlay().await?;
+        overlay.put_proto("verify-test".into(), 1u64).await;
+        overlay.lock().await.commit(node.storage().clone()).await?;
+
+        let report = check(node.storage()).await?;
+        assert!(!report.is_consistent());
+        assert!(report
+            .problems
+            .iter()
+            .any(|problem| problem.contains("differs from tree version")));
+        assert_eq!(report.skipped.len(), 1, "{:?}", report.skipped);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use pd::testing::Node;
+use pd::Info;
+use penumbra_proto::{
+    proofs::{self, CommitmentProof},
+    Message,
+};
+use tendermint::abci::{request, response, InfoRequest, InfoResponse};
+use tower::{Service, ServiceExt};
+
+async fn query(info: &mut Info, path: &str, data: &[u8], prove: bool) -> response::Query {
+    let request = InfoRequest::Query(request::Query {
+        data: data.to_vec().into(),
+        path: path.to_string(),
+        height: 0u32.into(),
+        prove,
+    });
+    match info.ready().await.unwrap().call(request).await.unwrap() {
+        InfoResponse::Query(response) => response,
+        other => panic!("unexpected response {:?}", other),
+    }
+}
+
+fn proof(response: &response::Query) -> CommitmentProof {
+    let ops = &response.proof.as_ref().expect("a proof was requested").ops;
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].field_type, pd::ICS23_PROOF_OP);
+    CommitmentProof::decode(ops[0].data.as_slice()).unwrap()
+}
+
+#[tokio::test]
+async fn proves_state_against_the_app_hash() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    let mut info = Info::new(node.storage().clone());
+
+    let app_hash = query(&mut info, pd::APP_HASH_QUERY_PATH, &[], false).await;
+    assert_eq!(app_hash.code, 0);
+    let root = app_hash.value.to_vec();
+
+    // Keys can be read by their full path...
+    let chain_params = query(&mut info, pd::KEY_QUERY_PATH, b"chain_params", true).await;
+    assert_eq!(chain_params.code, 0);
+    assert!(!chain_params.value.is_empty());
+    assert!(proofs::verify_membership(
+        &proof(&chain_params),
+        &root,
+        b"chain_params",
+        &chain_params.value
+    ));
+
+    // ... or within a component's store, as relayers query them.
+    let key = b"ibc/ics02-client/client_counter";
+    let counter = query(
+        &mut info,
+        "/store/ibc/key",
+        b"ics02-client/client_counter",
+        true,
+    )
+    .await;
+    assert_eq!(counter.code, 0);
+    assert_eq!(counter.key.as_ref(), key);
+    assert!(proofs::verify_membership(
+        &proof(&counter),
+        &root,
+        key,
+        &counter.value
+    ));
+
+    // Missing keys are proven absent.
+    let missing = query(&mut info, pd::KEY_QUERY_PATH, b"no/such/key", true).await;
+    assert_eq!(missing.code, 0);
+    assert!(missing.value.is_empty());
+    assert!(proofs::verify_non_membership(
+        &proof(&missing),
+        &root,
+        b"no/such/key"
+    ));
+
+    // Proofs are only included when requested.
+    assert!(query(&mut info, pd::KEY_QUERY_PATH, b"chain_params", false)
+        .await
+        .proof
+        .is_none());
+
+    let version = query(&mut info, pd::APP_VERSION_QUERY_PATH, &[], false).await;
+    assert_eq!(version.code, 0);
+    assert!(!version.value.is_empty());
+
+    assert_ne!(query(&mut info, "store//key", b"x", false).await.code, 0);
+    assert_ne!(query(&mut info, "unknown", &[], false).await.code, 0);
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use pd::{
+    components::{app::View as _, shielded_pool::View as _},
+    genesis,
+    testing::{address, delegations, validator, Node},
+};
+use penumbra_proto::client::oblivious::ChainStatsRequest;
+
+#[tokio::test]
+async fn counts_genesis_and_later_blocks() -> anyhow::Result<()> {
+    let (a, b) = (validator("a"), validator("b"));
+    let allocations = delegations(&[a.clone(), b.clone()], 1_000_000, address());
+    let node = Node::start(genesis::AppState {
+        validators: vec![a, b],
+        allocations,
+        ..Default::default()
+    })
+    .await?;
+
+    // Each genesis allocation is a note.
+    let mut client = node.oblivious_client().await?;
+    let stats = client
+        .chain_stats(ChainStatsRequest::default())
+        .await?
+        .into_inner();
+    assert_eq!(stats.height, 0);
+    assert_eq!(stats.total_commitments, 2);
+    assert_eq!(stats.total_nullifiers, 0);
+    assert_eq!(stats.total_transactions, 0);
+    assert_eq!(stats.transactions_per_block, 0.0);
+    assert_eq!(stats.active_validators, 2);
+
+    // Totals are carried forward from block to block.
+    node.append_empty_blocks(4).await?;
+    let overlay = node.storage().overlay().await?;
+    overlay.put_transaction_count(6).await;
+    overlay.put_nullifier_count(3).await;
+    overlay.lock().await.commit(node.storage().clone()).await?;
+
+    let stats = client
+        .chain_stats(ChainStatsRequest::default())
+        .await?
+        .into_inner();
+    assert_eq!(stats.height, 4);
+    assert_eq!(stats.total_commitments, 2);
+    assert_eq!(stats.total_nullifiers, 3);
+    assert_eq!(stats.total_transactions, 6);
+    assert_eq!(stats.transactions_per_block, 1.5);
+
+    // The chain id is checked, as for other queries.
+    assert!(client
+        .chain_stats(ChainStatsRequest {
+            chain_id: "another-chain".to_string(),
+        })
+        .await
+        .is_err());
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use std::{future::Future, pin::Pin, time::Duration};
+
+use pd::testing::Node;
+use pd::{
+    components::app::View as _,
+    genesis,
+    upgrade::{self, Migration},
+    Overlay, OverlayExt,
+};
+use penumbra_chain::params::ChainParams;
+use tendermint::{public_key::Algorithm, Genesis, Time};
+
+const CHAIN_ID: &str = "upgrade-test";
+
+fn add_marker(overlay: &Overlay) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + '_>> {
+    Box::pin(async move {
+        overlay.put_proto(b"upgrade-test/marker".into(), 7u64).await;
+        Ok(())
+    })
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "add-marker",
+        description: "adds a marker to the state",
+        chain_id: CHAIN_ID,
+        height: 2,
+        run: add_marker,
+    },
+    Migration {
+        name: "at-epoch-end",
+        description: "would take the place of the last block of the epoch",
+        chain_id: CHAIN_ID,
+        height: 8,
+        run: add_marker,
+    },
+];
+
+/// Commits empty blocks up to the next `count` heights, giving the last one a
+/// timestamp.
+async fn advance(node: &Node, count: u64, time: Time) -> anyhow::Result<()> {
+    node.append_empty_blocks(count).await?;
+    let overlay = node.storage().overlay().await?;
+    overlay.put_block_timestamp(time).await;
+    overlay.lock().await.commit(node.storage().clone()).await?;
+    Ok(())
+}
+
+fn tendermint_genesis(chain_id: &str) -> Genesis<serde_json::Value> {
+    Genesis {
+        genesis_time: Time::from_unix_timestamp(0, 0).unwrap(),
+        chain_id: chain_id.parse().unwrap(),
+        initial_height: 0,
+        consensus_params: tendermint::consensus::Params {
+            block: tendermint::block::Size {
+                max_bytes: 22020096,
+                max_gas: -1,
+                time_iota_ms: 500,
+            },
+            evidence: tendermint::evidence::Params {
+                max_age_num_blocks: 100000,
+                max_age_duration: tendermint::evidence::Duration(Duration::new(86400, 0)),
+                max_bytes: 1048576,
+            },
+            validator: tendermint::consensus::params::ValidatorParams {
+                pub_key_types: vec![Algorithm::Ed25519],
+            },
+            version: None,
+        },
+        app_hash: vec![],
+        app_state: serde_json::Value::Null,
+        validators: vec![],
+    }
+}
+
+#[tokio::test]
+async fn migrates_at_the_halt_height_only() -> anyhow::Result<()> {
+    let node = Node::start(genesis::AppState {
+        chain_params: ChainParams {
+            chain_id: CHAIN_ID.to_string(),
+            epoch_duration: 10,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await?;
+    let storage = node.storage();
+    let halted_at = Time::from_unix_timestamp(1_650_000_000, 0).unwrap();
+
+    // Too early.
+    advance(&node, 1, halted_at).await?;
+    let error = upgrade::migrate(storage, MIGRATIONS).await.unwrap_err();
+    assert!(error.to_string().contains("at height 1"), "{}", error);
+
+    advance(&node, 1, halted_at).await?;
+    let migrated = upgrade::migrate(storage, MIGRATIONS).await?;
+    assert_eq!(migrated.height, 3);
+    assert_eq!(migrated.migrations, vec!["add-marker"]);
+    assert_eq!(storage.latest_version().await?, Some(migrated.version));
+
+    let overlay = storage.overlay().await?;
+    assert_eq!(overlay.get_block_height().await?, 3);
+    let marker: Option<u64> = overlay.get_proto(b"upgrade-test/marker".into()).await?;
+    assert_eq!(marker, Some(7));
+    assert_eq!(upgrade::applied(&overlay, "add-marker").await?, Some(3));
+    assert_eq!(upgrade::applied(&overlay, "at-epoch-end").await?, None);
+
+    // The migrations are not made twice.
+    assert!(upgrade::migrate(storage, MIGRATIONS).await.is_err());
+
+    // Height 9 ends the first epoch, so cannot be skipped.
+    advance(&node, 5, halted_at).await?;
+    let error = upgrade::migrate(storage, MIGRATIONS).await.unwrap_err();
+    assert!(error.to_string().contains("ends an epoch"), "{}", error);
+
+    // The chain resumes after the migrated state, from the halt block's time.
+    let genesis = upgrade::resume_genesis(tendermint_genesis(CHAIN_ID), &migrated)?;
+    assert_eq!(genesis.initial_height as u64, migrated.version + 1);
+    assert_eq!(genesis.app_hash, migrated.app_hash.to_vec());
+    assert_eq!(genesis.genesis_time, halted_at);
+    assert!(upgrade::resume_genesis(tendermint_genesis("other-chain"), &migrated).is_err());
+
+    Ok(())
+}
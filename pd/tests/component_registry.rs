@@ -0,0 +1,31 @@
+use pd::{
+    components::{registry, Faucet, Registry, Staking, REGISTRY},
+    genesis, App, Component, Storage,
+};
+
+#[test]
+fn full_build_registers_every_component() {
+    assert!(registry::COMPILED_OUT.is_empty());
+    let names: Vec<_> = REGISTRY.names().collect();
+    assert_eq!(names, vec!["staking", "ibc", "faucet", "shielded_pool"]);
+}
+
+#[tokio::test]
+async fn looks_up_components_by_type() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let storage = Storage::load(dir.path().join("rocksdb")).await?;
+
+    let mut app = App::new(storage.overlay().await?).await?;
+    app.init_chain(&genesis::AppState::default()).await?;
+    assert!(app.component::<Staking>().is_some());
+    assert!(app.component::<Faucet>().is_some());
+    assert!(app.component::<App>().is_none());
+
+    // A registry holds only what was registered in it.
+    let minimal = Registry::default().register::<Staking>("staking");
+    let components = minimal.instantiate(storage.overlay().await?).await?;
+    assert!(components.get::<Staking>().is_some());
+    assert!(components.get::<Faucet>().is_none());
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use pd::testing::{begin_block, Node};
+use pd::{
+    genesis,
+    replay::{self, ReplayLog},
+    Storage,
+};
+use tendermint::abci::{self, ConsensusRequest, ConsensusResponse};
+
+fn commit(app_hash: &[u8]) -> ConsensusResponse {
+    ConsensusResponse::Commit(abci::response::Commit {
+        data: app_hash.to_vec().into(),
+        retain_height: 0u32.into(),
+    })
+}
+
+#[tokio::test]
+async fn log_round_trips() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("consensus.log");
+    let entries = vec![
+        (
+            ConsensusRequest::EndBlock(abci::request::EndBlock { height: 1 }),
+            ConsensusResponse::EndBlock(Default::default()),
+        ),
+        (ConsensusRequest::Commit, commit(b"first")),
+        (ConsensusRequest::Commit, commit(b"second")),
+    ];
+
+    // The log is appended to across restarts.
+    let mut log = ReplayLog::open(&path)?;
+    for (req, rsp) in entries[..2].iter().cloned() {
+        log.record(req, rsp)?;
+    }
+    drop(log);
+    let (req, rsp) = entries[2].clone();
+    ReplayLog::open(&path)?.record(req, rsp)?;
+
+    let bytes = std::fs::read(&path)?;
+    let decoded = replay::decode_log(bytes.as_slice())?;
+    assert_eq!(format!("{:?}", decoded), format!("{:?}", entries));
+
+    // A log cut short by a crash is reported, rather than silently truncated.
+    let error = replay::decode_log(&bytes[..bytes.len() - 1]).unwrap_err();
+    assert!(format!("{:#}", error).contains("entry 2"), "{:#}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn replays_into_fresh_storage_only() -> anyhow::Result<()> {
+    let node = Node::start(genesis::AppState::default()).await?;
+    let error = replay::replay_log(node.storage().clone(), Vec::new())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("height 0"), "{}", error);
+
+    let dir = tempfile::tempdir()?;
+    let storage = Storage::load(dir.path().join("rocksdb")).await?;
+    let replay = replay::replay_log(storage, Vec::new()).await?;
+    assert_eq!(replay.entries, 0);
+    assert!(replay.mismatches.is_empty());
+    assert_eq!(replay.app_hash_divergence(), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn replays_logged_blocks_without_committing() -> anyhow::Result<()> {
+    let node = Node::start(genesis::AppState::default()).await?;
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("consensus.log");
+
+    // Two blocks, as `pd start --consensus-replay-log` would have logged
+    // them; the responses are not replayed, so their contents do not matter.
+    let mut log = ReplayLog::open(&path)?;
+    for height in 1..=2 {
+        let requests = [
+            (
+                ConsensusRequest::BeginBlock(begin_block(height, height as i64)),
+                ConsensusResponse::BeginBlock(Default::default()),
+            ),
+            (
+                ConsensusRequest::DeliverTx(abci::request::DeliverTx {
+                    tx: b"not a transaction".to_vec().into(),
+                }),
+                ConsensusResponse::DeliverTx(Default::default()),
+            ),
+            (
+                ConsensusRequest::EndBlock(abci::request::EndBlock {
+                    height: height as i64,
+                }),
+                ConsensusResponse::EndBlock(Default::default()),
+            ),
+            (ConsensusRequest::Commit, commit(b"")),
+        ];
+        for (req, rsp) in requests {
+            log.record(req, rsp)?;
+        }
+    }
+    drop(log);
+    let requests = replay::decode_log(std::fs::read(&path)?.as_slice())?
+        .into_iter()
+        .map(|(req, _)| req)
+        .collect::<Vec<_>>();
+
+    let blocks = replay::replay(node.storage().clone(), requests.clone(), 1, 2).await?;
+    assert_eq!(
+        blocks.iter().map(|block| block.height).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    for block in &blocks {
+        assert_eq!(block.txs.len(), 1);
+        assert!(!block.txs[0].valid);
+    }
+    // Nothing was committed, so the blocks can be replayed again.
+    assert_eq!(node.storage().latest_version().await?, Some(0));
+    let blocks = replay::replay(node.storage().clone(), requests.clone(), 2, 2).await?;
+    assert_eq!(blocks.len(), 1);
+
+    // Genesis cannot be replayed, nor blocks beyond the stored state.
+    assert!(
+        replay::replay(node.storage().clone(), requests.clone(), 0, 2)
+            .await
+            .is_err()
+    );
+    assert!(replay::replay(node.storage().clone(), requests, 3, 3)
+        .await
+        .is_err());
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use pd::testing::Node;
+use pd::{
+    components::{app::View as _, shielded_pool::View as _, ShieldedPool},
+    genesis::{self, Allocation},
+    Component,
+};
+use penumbra_chain::params::ChainParams;
+use penumbra_crypto::keys::{SeedPhrase, SpendKey, SpendSeed};
+use penumbra_tct::{Epoch, Eternity, Position};
+use rand_core::OsRng;
+use tendermint::abci;
+
+/// Ends the block at `height`, with no transactions, as far as the shielded pool is concerned,
+/// then starts the next epoch if this one is over, as the app would.
+async fn end_block(node: &Node, height: u64) -> anyhow::Result<()> {
+    let overlay = node.storage().overlay().await?;
+    overlay.put_block_height(height).await;
+    let mut shielded_pool = ShieldedPool::new(overlay.clone()).await?;
+    shielded_pool
+        .end_block(&abci::request::EndBlock {
+            height: height as i64,
+        })
+        .await?;
+    if overlay.is_end_of_epoch().await? {
+        let epoch = overlay.get_current_epoch().await?;
+        overlay.put_current_epoch(epoch.next(height + 1)).await;
+    }
+    overlay.lock().await.commit(node.storage().clone()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn publishes_block_and_epoch_roots() -> anyhow::Result<()> {
+    let spend_key = SpendKey::new(SpendSeed::from_seed_phrase(SeedPhrase::generate(OsRng), 0));
+    let address = spend_key
+        .incoming_viewing_key()
+        .payment_address(0u64.into())
+        .0;
+    // Genesis begins the first epoch, which ends at height 1.
+    let node = Node::start(genesis::AppState {
+        chain_params: ChainParams {
+            epoch_duration: 2,
+            ..Default::default()
+        },
+        allocations: vec![Allocation {
+            amount: 1_000,
+            denom: "upenumbra".to_string(),
+            address,
+        }],
+        ..Default::default()
+    })
+    .await?;
+    end_block(&node, 1).await?;
+    end_block(&node, 2).await?;
+
+    let overlay = node.storage().overlay().await?;
+    let mut blocks = Vec::new();
+    for height in 0..=2 {
+        blocks.push(overlay.compact_block(height).await?.unwrap());
+    }
+
+    // A client inserting every block by its root ends the epoch with the published root.
+    let mut tree = Eternity::new();
+    tree.insert_block_root(blocks[0].block_root.unwrap())?;
+    assert_eq!(blocks[0].epoch_root, None);
+    assert_eq!(
+        tree.position().block(),
+        Position::start_of_height(0, 0, 0)?.block()
+    );
+    tree.insert_block_root(blocks[1].block_root.unwrap())?;
+    assert_eq!(
+        tree.position().block(),
+        Position::start_of_height(1, 0, 0)?.block()
+    );
+    assert_eq!(tree.current_epoch_root(), blocks[1].epoch_root);
+    assert!(blocks[1].epoch_root.is_some());
+
+    // The next epoch starts with an empty block, the same as one of the last epoch's.
+    assert_eq!(blocks[2].epoch_root, None);
+    assert_eq!(blocks[2].block_root, blocks[1].block_root);
+
+    // A client skipping the epoch altogether reaches the same tree.
+    let mut skipped = Eternity::new();
+    skipped.insert_epoch_root(blocks[1].epoch_root.unwrap())?;
+    tree.insert_epoch(Epoch::new())?;
+    skipped.insert_epoch(Epoch::new())?;
+    tree.insert_block_root(blocks[2].block_root.unwrap())?;
+    skipped.insert_block_root(blocks[2].block_root.unwrap())?;
+    assert_eq!(tree.root(), skipped.root());
+
+    // Either way, the block sits where the epoch the chain recorded for it says.
+    let epoch = overlay.get_current_epoch().await?;
+    assert_eq!((epoch.index, epoch.start_height), (1, 2));
+    let expected = Position::start_of_height(2, epoch.start_height, epoch.index)?;
+    for tree in [&tree, &skipped] {
+        let position = tree.position();
+        assert_eq!(
+            (position.epoch(), position.block()),
+            (expected.epoch(), expected.block())
+        );
+        assert_eq!(position.height(epoch.start_height), 2);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use pd::{
+    components::app::View as _,
+    genesis,
+    testing::{begin_block, Node},
+    App, Component, Storage,
+};
+use penumbra_chain::params::ChainParams;
+use penumbra_stake::Epoch;
+use tendermint::abci;
+
+/// Starts a node with no validators, whose epochs are defined by `params`.
+async fn start(params: ChainParams) -> anyhow::Result<Node> {
+    Node::start(genesis::AppState {
+        chain_params: params,
+        ..Default::default()
+    })
+    .await
+}
+
+/// Executes and commits an empty block made `seconds` after the Unix epoch,
+/// returning the epoch the next block is in.
+async fn execute_block(storage: &Storage, height: u64, seconds: i64) -> anyhow::Result<Epoch> {
+    let mut app = App::new(storage.overlay().await?).await?;
+    app.begin_block(&begin_block(height, seconds)).await?;
+    app.end_block(&abci::request::EndBlock {
+        height: height as i64,
+    })
+    .await?;
+    app.commit(storage.clone()).await?;
+    storage.overlay().await?.get_current_epoch().await
+}
+
+#[tokio::test]
+async fn block_count_epochs_end_every_epoch_duration_blocks() -> anyhow::Result<()> {
+    let node = start(ChainParams {
+        epoch_duration: 3,
+        ..Default::default()
+    })
+    .await?;
+
+    // Epochs end at the same heights as when they were only derived from the
+    // height: the last block of epoch `i` is `(i + 1) * epoch_duration - 1`.
+    for height in 1..=10 {
+        let next_epoch = execute_block(node.storage(), height, height as i64).await?;
+        assert_eq!(
+            next_epoch,
+            Epoch::from_height(height + 1, 3),
+            "after block {}",
+            height
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn block_time_epochs_end_after_epoch_duration_seconds() -> anyhow::Result<()> {
+    let node = start(ChainParams {
+        epoch_duration: 3,
+        epoch_duration_seconds: 10,
+        ..Default::default()
+    })
+    .await?;
+    let storage = node.storage();
+
+    // Genesis has no timestamp, so the first epoch starts at the first block.
+    assert_eq!(execute_block(storage, 1, 100).await?.index, 0);
+    assert_eq!(execute_block(storage, 2, 105).await?.index, 0);
+    // The block count is ignored...
+    assert_eq!(execute_block(storage, 3, 109).await?.index, 0);
+    // ...and the epoch ends with the first block at least 10 seconds in.
+    assert_eq!(
+        execute_block(storage, 4, 110).await?,
+        Epoch {
+            index: 1,
+            start_height: 5,
+        }
+    );
+
+    // A block jumping over several durations ends only the current epoch,
+    // and the next one starts at the following block's time.
+    assert_eq!(execute_block(storage, 5, 112).await?.index, 1);
+    assert_eq!(
+        execute_block(storage, 6, 150).await?,
+        Epoch {
+            index: 2,
+            start_height: 7,
+        }
+    );
+    assert_eq!(execute_block(storage, 7, 151).await?.index, 2);
+    assert_eq!(execute_block(storage, 8, 160).await?.index, 2);
+    assert_eq!(
+        execute_block(storage, 9, 161).await?,
+        Epoch {
+            index: 3,
+            start_height: 10,
+        }
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn derives_the_epoch_from_the_height_on_legacy_chains() -> anyhow::Result<()> {
+    // Chains initialized before the current epoch was recorded have chain
+    // parameters and a block height, but no `epoch/index`.
+    let dir = tempfile::tempdir()?;
+    let storage = Storage::load(dir.path().join("rocksdb")).await?;
+    let overlay = storage.overlay().await?;
+    overlay.put_block_height(4).await;
+    overlay
+        .put_chain_params(ChainParams {
+            epoch_duration: 3,
+            ..Default::default()
+        })
+        .await?;
+
+    assert_eq!(
+        overlay.get_current_epoch().await?,
+        Epoch {
+            index: 1,
+            start_height: 3,
+        }
+    );
+    assert!(!overlay.is_end_of_epoch().await?);
+
+    overlay.put_block_height(5).await;
+    assert!(overlay.is_end_of_epoch().await?);
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use pd::{
+    components::faucet::View as _,
+    testing::{address, Node},
+};
+use penumbra_chain::{FaucetDisbursement, FaucetDisbursements};
+use penumbra_crypto::Address;
+use penumbra_proto::client::specific::FaucetDisbursementsRequest;
+
+#[tokio::test]
+async fn queries_disbursements_by_height_and_address() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    node.append_empty_blocks(3).await?;
+    let (alice, bob) = (address(), address());
+    let disbursement = |address, height| FaucetDisbursement {
+        address,
+        amount: 100,
+        height,
+    };
+
+    let overlay = node.storage().overlay().await?;
+    overlay
+        .put_faucet_disbursements(
+            1,
+            FaucetDisbursements(vec![disbursement(alice, 1), disbursement(bob, 1)]),
+        )
+        .await;
+    overlay
+        .put_faucet_disbursements(3, FaucetDisbursements(vec![disbursement(alice, 3)]))
+        .await;
+    overlay.lock().await.commit(node.storage().clone()).await?;
+
+    let mut client = node.specific_client().await?;
+    let query = |start_height, end_height, address: Option<Address>| FaucetDisbursementsRequest {
+        chain_id: String::new(),
+        start_height,
+        end_height,
+        address: address.map(Into::into),
+    };
+
+    let all: FaucetDisbursements = client
+        .faucet_disbursements(query(0, 3, None))
+        .await?
+        .into_inner()
+        .try_into()?;
+    assert_eq!(all.0.len(), 3);
+
+    let alices: FaucetDisbursements = client
+        .faucet_disbursements(query(0, 3, Some(alice)))
+        .await?
+        .into_inner()
+        .try_into()?;
+    assert_eq!(
+        alices.0,
+        vec![disbursement(alice, 1), disbursement(alice, 3)]
+    );
+
+    let later: FaucetDisbursements = client
+        .faucet_disbursements(query(2, 3, None))
+        .await?
+        .into_inner()
+        .try_into()?;
+    assert_eq!(later.0, vec![disbursement(alice, 3)]);
+
+    // Unbounded scans are refused.
+    assert!(client
+        .faucet_disbursements(query(0, 1_000_000, None))
+        .await
+        .is_err());
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use pd::{
+    components::shielded_pool::View as _,
+    flat_export::{self, Format, Table},
+    genesis,
+    testing::{address, delegations, validator, Node},
+};
+use penumbra_chain::sync::CompactBlock;
+use penumbra_crypto::{Fq, Nullifier};
+
+#[tokio::test]
+async fn exports_decoded_tables() -> anyhow::Result<()> {
+    let validators = vec![validator("alice"), validator("bob, and co")];
+    let node = Node::start(genesis::AppState {
+        validators: validators.clone(),
+        allocations: delegations(&validators, 1_000_000, address()),
+        ..Default::default()
+    })
+    .await?;
+
+    // A later block spends a note.
+    let height = node.append_empty_blocks(2).await?;
+    let nullifier = Nullifier(Fq::from(7u64));
+    let overlay = node.storage().overlay().await?;
+    overlay
+        .set_compact_block(CompactBlock {
+            height,
+            nullifiers: vec![nullifier.clone()],
+            ..Default::default()
+        })
+        .await;
+    overlay.lock().await.commit(node.storage().clone()).await?;
+
+    let dir = tempfile::tempdir()?;
+    let exported =
+        flat_export::export(node.storage(), height, &Table::ALL, Format::Csv, dir.path()).await?;
+    let rows: Vec<_> = exported.iter().map(|e| (e.table, e.rows)).collect();
+    assert_eq!(
+        rows,
+        vec![
+            (Table::Validators, 2),
+            (Table::Allocations, 2),
+            (Table::Nullifiers, 1)
+        ]
+    );
+
+    let validators_csv = std::fs::read_to_string(dir.path().join("validators.csv"))?;
+    let mut lines = validators_csv.lines();
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("identity_key,consensus_address,name,"));
+    for line in lines {
+        assert!(line.contains(",ACTIVE,"), "{}", line);
+    }
+    for validator in &validators {
+        assert!(validators_csv.contains(&validator.identity_key.to_string()));
+    }
+    // Text containing the separator is quoted.
+    assert!(validators_csv.contains("\"bob, and co\""));
+
+    let nullifiers_csv = std::fs::read_to_string(dir.path().join("nullifiers.csv"))?;
+    assert_eq!(
+        nullifiers_csv,
+        format!("height,nullifier\n{},{}\n", height, nullifier)
+    );
+
+    // Parquet files are complete, with the magic number at either end.
+    let exported = flat_export::export(
+        node.storage(),
+        height,
+        &[Table::Validators],
+        Format::Parquet,
+        dir.path(),
+    )
+    .await?;
+    let parquet = std::fs::read(&exported[0].path)?;
+    assert_eq!(exported[0].path, dir.path().join("validators.parquet"));
+    assert!(parquet.starts_with(b"PAR1") && parquet.ends_with(b"PAR1"));
+
+    Ok(())
+}
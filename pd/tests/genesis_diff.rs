@@ -0,0 +1,120 @@
+use pd::{
+    genesis::{self, Allocation, AllocationChange, EntryChange, FieldChange},
+    testing::{address, validator},
+};
+use penumbra_crypto::Address;
+use penumbra_stake::BaseRateData;
+
+fn allocation(amount: u64, address: Address) -> Allocation {
+    Allocation {
+        amount,
+        denom: "upenumbra".to_string(),
+        address,
+    }
+}
+
+#[test]
+fn ignores_order_and_split_allocations() -> anyhow::Result<()> {
+    let (alice, bob) = (validator("alice"), validator("bob"));
+    let address = address();
+    let old = genesis::AppState {
+        validators: vec![alice.clone(), bob.clone()],
+        allocations: vec![allocation(3, address)],
+        ..Default::default()
+    };
+    let new = genesis::AppState {
+        validators: vec![bob, alice],
+        allocations: vec![allocation(1, address), allocation(2, address)],
+        ..Default::default()
+    };
+
+    let diff = genesis::diff(&old, &new)?;
+    assert!(diff.is_empty(), "{}", diff);
+    Ok(())
+}
+
+#[test]
+fn reports_semantic_changes() -> anyhow::Result<()> {
+    let (alice, bob, carol) = (validator("alice"), validator("bob"), validator("carol"));
+    let (kept, dropped, added) = (address(), address(), address());
+    let old = genesis::AppState {
+        validators: vec![alice.clone(), bob.clone()],
+        allocations: vec![allocation(1, kept), allocation(5, dropped)],
+        ..Default::default()
+    };
+    let mut new = old.clone();
+    new.chain_params.epoch_duration = 100;
+    new.chain_params.chain_id = "penumbra-testnet-2".to_string();
+    new.validators[0].website = "https://alice.example.com".to_string();
+    new.validators.remove(1);
+    new.validators.push(carol.clone());
+    new.allocations = vec![allocation(2, kept), allocation(7, added)];
+    new.base_rate_data = Some(BaseRateData {
+        epoch_index: 0,
+        base_reward_rate: 0,
+        base_exchange_rate: 1_2000_0000,
+    });
+
+    let diff = genesis::diff(&old, &new)?;
+
+    let fields: Vec<_> = diff
+        .chain_params
+        .iter()
+        .map(|change| change.field.as_str())
+        .collect();
+    assert_eq!(fields, vec!["chain_id", "epoch_duration"]);
+    assert_eq!(
+        diff.chain_params[1],
+        FieldChange {
+            field: "epoch_duration".to_string(),
+            old: Some(old.chain_params.epoch_duration.to_string()),
+            new: Some("100".to_string()),
+        }
+    );
+
+    let label = |v: &Validator| format!("{} ({})", v.name, v.identity_key);
+    assert_eq!(diff.validators.len(), 3);
+    assert!(diff.validators.contains(&EntryChange::Added {
+        label: label(&carol)
+    }));
+    assert!(diff
+        .validators
+        .contains(&EntryChange::Removed { label: label(&bob) }));
+    assert!(diff.validators.contains(&EntryChange::Changed {
+        label: label(&alice),
+        fields: vec![FieldChange {
+            field: "website".to_string(),
+            old: Some(r#""""#.to_string()),
+            new: Some(r#""https://alice.example.com""#.to_string()),
+        }],
+    }));
+
+    let change = |address: Address, old, new| AllocationChange {
+        address: address.to_string(),
+        denom: "upenumbra".to_string(),
+        old,
+        new,
+    };
+    let mut expected = vec![
+        change(kept, 1, 2),
+        change(dropped, 5, 0),
+        change(added, 0, 7),
+    ];
+    expected.sort_by(|a, b| a.address.cmp(&b.address));
+    assert_eq!(diff.allocations, expected);
+
+    // Setting the base rate data is a change of the whole.
+    assert_eq!(diff.base_rate_data.len(), 1);
+    assert_eq!(diff.base_rate_data[0].old, None);
+    assert!(diff.validator_rates.is_empty());
+
+    let printed = diff.to_string();
+    assert!(printed.contains("epoch_duration: "), "{}", printed);
+    assert!(
+        printed.contains(&format!("- {}", label(&bob))),
+        "{}",
+        printed
+    );
+    assert!(!printed.contains("validator rates"), "{}", printed);
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use pd::health::{Checker, Readiness};
+use tendermint::block;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::watch,
+};
+
+// Nothing listens on the discard port, so every request fails to connect.
+const UNREACHABLE_RPC: &str = "http://127.0.0.1:9";
+
+/// Runs a minimal Tendermint RPC which reports the given sync status,
+/// returning its URL.
+async fn tendermint_rpc(latest_height: u64, catching_up: bool) -> anyhow::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let body = serde_json::json!({
+        "result": {
+            "sync_info": {
+                "latest_block_height": latest_height.to_string(),
+                "catching_up": catching_up,
+            }
+        }
+    })
+    .to_string();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let body = body.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut line = String::new();
+                while stream.read_line(&mut line).await? > 2 {
+                    line.clear();
+                }
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).await?;
+                stream.write_all(body.as_bytes()).await?;
+                anyhow::Ok(())
+            });
+        }
+    });
+
+    Ok(url)
+}
+
+#[tokio::test]
+async fn ready_once_caught_up_with_tendermint() -> anyhow::Result<()> {
+    let url = tendermint_rpc(10, false).await?;
+    let (heights, heights_rx) = watch::channel(block::Height::from(5u32));
+    let checker = Checker::new(Some(heights_rx), Some(url));
+
+    assert!(matches!(checker.check().await, Readiness::NotReady(_)));
+    heights.send(block::Height::from(9u32))?;
+    assert_eq!(checker.check().await, Readiness::Ready);
+    heights.send(block::Height::from(10u32))?;
+    assert_eq!(checker.check().await, Readiness::Ready);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn not_ready_while_tendermint_is_unsynced_or_unreachable() -> anyhow::Result<()> {
+    let url = tendermint_rpc(10, true).await?;
+    let (_heights, heights_rx) = watch::channel(block::Height::from(10u32));
+    let checker = Checker::new(Some(heights_rx.clone()), Some(url));
+    assert!(matches!(checker.check().await, Readiness::NotReady(_)));
+
+    let checker = Checker::new(Some(heights_rx), Some(UNREACHABLE_RPC.to_string()));
+    assert!(matches!(checker.check().await, Readiness::NotReady(_)));
+
+    // A node which is not following the chain serves fixed state.
+    let checker = Checker::new(None, Some(UNREACHABLE_RPC.to_string()));
+    assert_eq!(checker.check().await, Readiness::Ready);
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use pd::logging::{Format, Logging, Rotation};
+
+#[test]
+fn writes_json_logs_to_a_file() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("logs").join("pd.log");
+    let (reload_log_filter, guard) = Logging {
+        format: Format::Json,
+        file: Some(path.clone()),
+        rotation: Rotation::Never,
+    }
+    .init()?;
+
+    reload_log_filter("info")?;
+    tracing::debug!("filtered out");
+    tracing::info!(height = 42, "committed block");
+    // Dropping the guard writes out the buffered logs.
+    drop(guard);
+
+    let contents = std::fs::read_to_string(&path)?;
+    let lines = contents.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1);
+    let event: serde_json::Value = serde_json::from_str(lines[0])?;
+    assert_eq!(event["level"], "INFO");
+    assert_eq!(event["fields"]["message"], "committed block");
+    assert_eq!(event["fields"]["height"], 42);
+
+    assert!(reload_log_filter("pd=nonsense").is_err());
+    Ok(())
+}
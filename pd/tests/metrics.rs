@@ -0,0 +1,36 @@
+use pd::MetricsMode;
+
+#[tokio::test]
+async fn runs_without_the_metrics_endpoint() -> anyhow::Result<()> {
+    // Take the port the metrics would be served on.
+    let taken = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = taken.local_addr()?;
+
+    // Strictly, the node fails to start...
+    assert!(pd::install_metrics(MetricsMode::Strict, addr).is_err());
+    // ... but otherwise it carries on, still recording metrics.
+    let handle = pd::install_metrics(MetricsMode::Enabled, addr)?;
+    pd::register_all_metrics();
+    metrics::increment_counter!("node_transactions_total");
+    assert!(handle.render().contains("node_transactions_total 1"));
+
+    // Durations are exported as histograms, with buckets, not summaries.
+    metrics::histogram!("node_block_commit_duration_seconds", 0.003);
+    assert!(handle
+        .render()
+        .contains("node_block_commit_duration_seconds_bucket"));
+
+    Ok(())
+}
+
+#[test]
+fn parses_metrics_modes() {
+    for mode in [
+        MetricsMode::Enabled,
+        MetricsMode::Strict,
+        MetricsMode::Disabled,
+    ] {
+        assert_eq!(mode.to_string().parse::<MetricsMode>().unwrap(), mode);
+    }
+    assert!("off".parse::<MetricsMode>().is_err());
+}
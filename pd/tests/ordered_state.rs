@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use pd::{Overlay, OverlayExt, Storage};
+
+const SPACE: &str = "test/queue";
+
+fn keys(entries: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<Vec<u8>> {
+    entries.into_iter().map(|(key, _)| key).collect()
+}
+
+async fn put_all(overlay: &Overlay, keys: &[&[u8]]) -> anyhow::Result<()> {
+    for key in keys {
+        overlay
+            .put_ordered(SPACE, key.to_vec(), key.to_vec())
+            .await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn scans_in_key_order_across_pending_and_committed_writes() -> anyhow::Result<()> {
+    let storage = Storage::in_memory().await?;
+    let overlay = storage.overlay().await?;
+    put_all(&overlay, &[b"b/2", b"a/9", b"b/10", b"b/1"]).await?;
+
+    // Pending writes are scanned in order...
+    assert_eq!(
+        keys(overlay.prefix_range(SPACE, b"b/").await?),
+        vec![b"b/1".to_vec(), b"b/10".to_vec(), b"b/2".to_vec()]
+    );
+    let (_, version) = overlay.lock().await.commit(storage.clone()).await?;
+    assert_eq!(version, 0);
+
+    // ... and merged with committed ones, including deletions.
+    let overlay = storage.overlay().await?;
+    put_all(&overlay, &[b"b/0", b"c/1"]).await?;
+    overlay.delete_ordered(SPACE, b"b/10".to_vec()).await?;
+    let expected = vec![
+        (b"a/9".to_vec(), b"a/9".to_vec()),
+        (b"b/0".to_vec(), b"b/0".to_vec()),
+        (b"b/1".to_vec(), b"b/1".to_vec()),
+        (b"b/2".to_vec(), b"b/2".to_vec()),
+        (b"c/1".to_vec(), b"c/1".to_vec()),
+    ];
+    assert_eq!(overlay.prefix_range(SPACE, b"").await?, expected);
+    overlay.lock().await.commit(storage.clone()).await?;
+    assert_eq!(
+        storage.overlay().await?.prefix_range(SPACE, b"").await?,
+        expected
+    );
+
+    // Other keyspaces are unaffected.
+    assert!(storage
+        .overlay()
+        .await?
+        .prefix_range("test/other", b"")
+        .await?
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_order_does_not_change_the_state() -> anyhow::Result<()> {
+    let mut roots = Vec::new();
+    for keys in [[b"x", b"y", b"z"], [b"z", b"x", b"y"]] {
+        let storage = Storage::in_memory().await?;
+        let overlay = storage.overlay().await?;
+        put_all(&overlay, &keys.map(|key| &key[..])).await?;
+        let (root, _) = overlay.lock().await.commit(storage).await?;
+        roots.push(root);
+    }
+    assert_eq!(roots[0], roots[1]);
+
+    Ok(())
+}
+
+/// Checks that scans of `overlay` for each prefix match the same scans of
+/// `model`.
+async fn assert_scans_match(
+    overlay: &Overlay,
+    model: &BTreeMap<Vec<u8>, Vec<u8>>,
+) -> anyhow::Result<()> {
+    for prefix in ["", "1", "1/", "1/1", "1/10", "2/59", "3"] {
+        let expected: Vec<_> = model
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix.as_bytes()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        assert_eq!(
+            overlay.prefix_range(SPACE, prefix.as_bytes()).await?,
+            expected,
+            "prefix {:?}",
+            prefix
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn large_keyspaces_scan_like_sorted_maps() -> anyhow::Result<()> {
+    let storage = Storage::in_memory().await?;
+    let overlay = storage.overlay().await?;
+    let mut model = BTreeMap::new();
+
+    // Enough keys, sharing enough of their prefixes, to split the index
+    // several levels deep, including keys which are themselves the prefixes
+    // of split buckets.
+    let mut keys: Vec<Vec<u8>> = (0..600)
+        .map(|i| format!("{}/{}", i % 3, i).into_bytes())
+        .collect();
+    keys.extend([b"".to_vec(), b"1".to_vec(), b"1/".to_vec(), b"1/1".to_vec()]);
+    for (i, key) in keys.iter().enumerate() {
+        let value = i.to_be_bytes().to_vec();
+        overlay
+            .put_ordered(SPACE, key.clone(), value.clone())
+            .await?;
+        model.insert(key.clone(), value);
+    }
+    assert_scans_match(&overlay, &model).await?;
+
+    for key in keys.iter().step_by(4) {
+        overlay.delete_ordered(SPACE, key.clone()).await?;
+        model.remove(key);
+    }
+    // Deleting a key twice, or one never added, changes nothing.
+    overlay.delete_ordered(SPACE, keys[0].clone()).await?;
+    overlay.delete_ordered(SPACE, b"1/1000".to_vec()).await?;
+    assert_scans_match(&overlay, &model).await?;
+
+    overlay.lock().await.commit(storage.clone()).await?;
+    let overlay = storage.overlay().await?;
+    assert_scans_match(&overlay, &model).await?;
+
+    for key in &keys {
+        overlay.delete_ordered(SPACE, key.clone()).await?;
+    }
+    assert!(overlay.prefix_range(SPACE, b"").await?.is_empty());
+    // An emptied keyspace can be filled again.
+    put_all(&overlay, &[b"1/1", b"0"]).await?;
+    assert_eq!(
+        keys(overlay.prefix_range(SPACE, b"").await?),
+        vec![b"0".to_vec(), b"1/1".to_vec()]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use ed25519_consensus::SigningKey;
+use pd::peers::{refresh, rewrite_config, SignedPeerList};
+use rand_core::OsRng;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+const CHAIN_ID: &str = "penumbra-testnet";
+
+const CONFIG: &str = r#"[p2p]
+
+# Comma separated list of nodes to keep persistent connections to
+persistent-peers = "0000000000000000000000000000000000000000@10.0.0.1:26656"
+
+persistent-peers-max-dial-period = "0s"
+"#;
+
+fn peers() -> Vec<String> {
+    vec![
+        "1111111111111111111111111111111111111111@node1.example.com:26656".to_string(),
+        "2222222222222222222222222222222222222222@10.0.0.2:26656".to_string(),
+    ]
+}
+
+/// Serves `body` as the response to every HTTP request, returning its URL.
+async fn serve(body: String) -> anyhow::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/peers.json", listener.local_addr()?);
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let body = body.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut line = String::new();
+                while stream.read_line(&mut line).await? > 2 {
+                    line.clear();
+                }
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).await?;
+                stream.write_all(body.as_bytes()).await?;
+                anyhow::Ok(())
+            });
+        }
+    });
+
+    Ok(url)
+}
+
+#[test]
+fn verifies_signed_peer_lists() -> anyhow::Result<()> {
+    let key = SigningKey::new(OsRng);
+    let signer = key.verification_key();
+    let list = SignedPeerList::sign(&key, CHAIN_ID.to_string(), peers())?;
+    list.verify(&signer, CHAIN_ID)?;
+
+    // Lists for other chains, signed by other keys, or altered after signing
+    // are refused.
+    assert!(list.verify(&signer, "another-chain").is_err());
+    assert!(list
+        .verify(&SigningKey::new(OsRng).verification_key(), CHAIN_ID)
+        .is_err());
+    let mut altered = list.clone();
+    altered.peers.pop();
+    assert!(altered.verify(&signer, CHAIN_ID).is_err());
+
+    // Malformed peers are not signed.
+    assert!(SignedPeerList::sign(&key, CHAIN_ID.to_string(), vec!["node:26656".into()]).is_err());
+    assert!(SignedPeerList::sign(
+        &key,
+        CHAIN_ID.to_string(),
+        vec!["1111111111111111111111111111111111111111@node1.example.com".into()]
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn rewrites_only_the_persistent_peers() -> anyhow::Result<()> {
+    let rewritten = rewrite_config(CONFIG, &peers())?;
+    assert_eq!(
+        rewritten,
+        CONFIG.replace(
+            "0000000000000000000000000000000000000000@10.0.0.1:26656",
+            &peers().join(",")
+        )
+    );
+    // The setting must be present to be rewritten.
+    assert!(rewrite_config("[p2p]\n", &peers()).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn refreshes_the_tendermint_config() -> anyhow::Result<()> {
+    let key = SigningKey::new(OsRng);
+    let list = SignedPeerList::sign(&key, CHAIN_ID.to_string(), peers())?;
+    let url = serve(serde_json::to_string(&list)?).await?;
+
+    let home = tempfile::tempdir()?;
+    let config_dir = home.path().join("config");
+    std::fs::create_dir(&config_dir)?;
+    std::fs::write(config_dir.join("config.toml"), CONFIG)?;
+    std::fs::write(
+        config_dir.join("genesis.json"),
+        serde_json::json!({ "chain_id": CHAIN_ID }).to_string(),
+    )?;
+
+    // A list signed by another key leaves the config as it was.
+    let other = SigningKey::new(OsRng).verification_key();
+    assert!(refresh(&url, &other, home.path()).await.is_err());
+    assert_eq!(
+        std::fs::read_to_string(config_dir.join("config.toml"))?,
+        CONFIG
+    );
+
+    assert!(refresh(&url, &key.verification_key(), home.path()).await?);
+    assert_eq!(
+        std::fs::read_to_string(config_dir.join("config.toml"))?,
+        rewrite_config(CONFIG, &peers())?
+    );
+    // Refreshing again changes nothing.
+    assert!(!refresh(&url, &key.verification_key(), home.path()).await?);
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use pd::testing::Node;
+use pd::{
+    components::app::View as _,
+    pruning::{Pruning, Strategy},
+};
+
+#[tokio::test]
+async fn prunes_only_earlier_versions() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    for _ in 0..5 {
+        node.append_empty_blocks(1).await?;
+    }
+    let storage = node.storage();
+
+    let pruned = storage.prune(3).await?;
+    assert!(pruned.nodes > 0 && pruned.bytes > 0);
+
+    // The retained versions are intact...
+    for version in 3..=5 {
+        let overlay = storage.overlay_at(version).await?;
+        assert_eq!(overlay.get_block_height().await?, version);
+    }
+    assert!(pd::verify::check(storage).await?.is_consistent());
+
+    // ... but the pruned ones are gone.
+    assert!(storage
+        .overlay_at(1)
+        .await?
+        .get_block_height()
+        .await
+        .is_err());
+
+    // Pruning again deletes nothing more, and the chain carries on.
+    assert_eq!(storage.prune(3).await?.nodes, 0);
+    assert_eq!(node.append_empty_blocks(1).await?, 6);
+
+    Ok(())
+}
+
+#[test]
+fn resolves_strategies() -> anyhow::Result<()> {
+    assert_eq!(Pruning::new(Strategy::Nothing, None, None)?, None);
+    assert_eq!(
+        Pruning::new("everything".parse()?, None, None)?,
+        Some(Pruning {
+            keep_recent: 2,
+            interval: 10
+        })
+    );
+    assert_eq!(
+        Pruning::new(Strategy::Custom, Some(100), Some(5))?,
+        Some(Pruning {
+            keep_recent: 100,
+            interval: 5
+        })
+    );
+
+    // Custom settings are required for, and only for, the custom strategy.
+    assert!(Pruning::new(Strategy::Custom, Some(100), None).is_err());
+    assert!(Pruning::new(Strategy::Default, Some(100), Some(5)).is_err());
+    assert!(Pruning::new(Strategy::Custom, Some(1), Some(5)).is_err());
+    assert!("sometimes".parse::<Strategy>().is_err());
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use pd::rate_limit::{ApiKeys, RateLimitLayer, Usage};
+use pd::testing::Node;
+use penumbra_proto::client::specific::{
+    specific_query_client::SpecificQueryClient, specific_query_server::SpecificQueryServer,
+    ChainParamsAtHeightRequest,
+};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Code};
+
+fn request(api_key: Option<&str>) -> tonic::Request<ChainParamsAtHeightRequest> {
+    let mut request = tonic::Request::new(ChainParamsAtHeightRequest {
+        chain_id: String::new(),
+        height: 0,
+    });
+    if let Some(api_key) = api_key {
+        request
+            .metadata_mut()
+            .insert("x-api-key", api_key.parse().unwrap());
+    }
+    request
+}
+
+#[tokio::test]
+async fn limits_and_accounts_for_each_client() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    // Anonymous clients may make 2 requests at once, refilled at 1 per
+    // second, and the partner's requests are not limited.
+    let layer =
+        RateLimitLayer::new(1, 2).with_api_keys(ApiKeys::parse("# name key\npartner s3cret 0 0")?);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let server = tokio::spawn(
+        Server::builder()
+            .layer(layer.clone())
+            .add_service(SpecificQueryServer::new(node.storage().clone()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let mut client = SpecificQueryClient::connect(format!("http://{}", addr)).await?;
+
+    // Limited clients are told their remaining quota...
+    for remaining in ["1", "0"] {
+        let response = client.chain_params_at_height(request(None)).await?;
+        let metadata = response.metadata();
+        assert_eq!(metadata.get("x-ratelimit-limit").unwrap(), "2");
+        assert_eq!(metadata.get("x-ratelimit-remaining").unwrap(), remaining);
+    }
+
+    // ... and when to retry, once it is exhausted.
+    let status = client
+        .chain_params_at_height(request(None))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert_eq!(status.metadata().get("retry-after").unwrap(), "1");
+
+    // The partner is not limited, and so gets no quota.
+    let response = client
+        .chain_params_at_height(request(Some("s3cret")))
+        .await?;
+    assert!(response.metadata().get("x-ratelimit-limit").is_none());
+
+    // Unknown keys are refused.
+    let status = client
+        .chain_params_at_height(request(Some("guess")))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    let mut usage = layer.take_usage();
+    usage.sort_by(|a, b| a.client.cmp(&b.client));
+    assert_eq!(
+        usage,
+        vec![
+            Usage {
+                client: "127.0.0.1".to_string(),
+                requests: 3,
+                rejected: 1,
+            },
+            Usage {
+                client: "key:partner".to_string(),
+                requests: 1,
+                rejected: 0,
+            },
+        ]
+    );
+    // Usage is counted afresh after it is taken.
+    assert!(layer.take_usage().is_empty());
+
+    server.abort();
+    Ok(())
+}
+
+#[test]
+fn parses_api_keys() {
+    assert!(ApiKeys::parse("\n# comment\nalice key1\nbob key2 10 20\n").is_ok());
+    // Keys must be unique, and limits complete and numeric.
+    assert!(ApiKeys::parse("alice key\nbob key").is_err());
+    assert!(ApiKeys::parse("alice key 10").is_err());
+    assert!(ApiKeys::parse("alice key ten 20").is_err());
+}
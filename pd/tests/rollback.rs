@@ -0,0 +1,28 @@
+use pd::components::app::View as _;
+use pd::testing::Node;
+
+#[tokio::test]
+async fn rolls_back_to_an_earlier_height() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    node.append_empty_blocks(1).await?;
+    node.append_empty_blocks(2).await?;
+    let storage = node.storage();
+    assert_eq!(storage.latest_version().await?, Some(2));
+
+    let rollback = storage.rollback(1).await?;
+    assert_eq!((rollback.from, rollback.to), (2, 1));
+    assert!(rollback.nodes > 0);
+    assert_eq!(storage.latest_version().await?, Some(1));
+    assert_eq!(storage.overlay().await?.get_block_height().await?, 1);
+    assert!(pd::verify::check(storage).await?.is_consistent());
+
+    // Only earlier heights can be rolled back to...
+    assert!(storage.rollback(1).await.is_err());
+    assert!(storage.rollback(5).await.is_err());
+
+    // ... and the chain continues from the rolled back height.
+    assert_eq!(node.append_empty_blocks(1).await?, 2);
+    assert_eq!(storage.latest_version().await?, Some(2));
+
+    Ok(())
+}
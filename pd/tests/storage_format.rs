@@ -0,0 +1,61 @@
+use pd::{Storage, FORMAT_VERSION};
+use rocksdb::{Options, DB};
+
+#[tokio::test]
+async fn migrates_legacy_databases() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("rocksdb");
+
+    // Before the format was recorded, the database only had the default
+    // column family, which held the tree.
+    {
+        let db = DB::open_default(&path)?;
+        db.put(b"legacy", b"data")?;
+    }
+
+    // A legacy database cannot be opened read-only, since it cannot be
+    // migrated, nor read-write, since moving its tree is not automatic...
+    assert!(Storage::load_read_only(path.clone()).await.is_err());
+    assert!(Storage::load(path.clone()).await.is_err());
+
+    // ... so it must be migrated explicitly, after which it can be opened.
+    let (from, migrated) = Storage::migrate(path.clone()).await?;
+    assert_eq!(from, Some(0));
+    assert_eq!(migrated.len(), FORMAT_VERSION as usize);
+    assert_eq!(
+        Storage::migrate(path.clone()).await?,
+        (Some(FORMAT_VERSION), Vec::new())
+    );
+    drop(Storage::load(path.clone()).await?);
+    drop(Storage::load_read_only(path.clone()).await?);
+
+    // The tree's nodes were moved out of the default column family.
+    let column_families = DB::list_cf(&Options::default(), &path)?;
+    let db = DB::open_cf(&Options::default(), &path, column_families)?;
+    assert_eq!(db.get(b"legacy")?, None);
+    let jmt = db.cf_handle("jmt").unwrap();
+    assert_eq!(db.get_cf(jmt, b"legacy")?, Some(b"data".to_vec()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_newer_databases() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("rocksdb");
+    drop(Storage::load(path.clone()).await?);
+
+    // Every column family must be opened, not just the one being written.
+    {
+        let column_families = DB::list_cf(&Options::default(), &path)?;
+        let db = DB::open_cf(&Options::default(), &path, column_families)?;
+        let cf = db.cf_handle("meta").unwrap();
+        db.put_cf(cf, b"format_version", (FORMAT_VERSION + 1).to_be_bytes())?;
+    }
+
+    assert!(Storage::load(path.clone()).await.is_err());
+    assert!(Storage::load_read_only(path.clone()).await.is_err());
+    assert!(Storage::migrate(path).await.is_err());
+
+    Ok(())
+}
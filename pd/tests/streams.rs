@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use pd::testing::Node;
+use penumbra_proto::client::oblivious::{CompactBlockRangeRequest, SubscribeCompactBlocksRequest};
+
+/// Enough blocks that the node cannot send them all before the client reads
+/// them, so the stream is still being produced when the client goes away.
+const BLOCKS: u64 = 100_000;
+
+fn whole_chain() -> CompactBlockRangeRequest {
+    CompactBlockRangeRequest {
+        chain_id: String::new(),
+        start_height: 0,
+        end_height: 0,
+    }
+}
+
+/// Waits for the node to drop every stream, failing if it takes more than a
+/// second.
+async fn streams_released(node: &Node) -> anyhow::Result<()> {
+    for _ in 0..100 {
+        if node.open_streams() == 0 {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    Err(anyhow::anyhow!(
+        "{} streams still open a second after their clients went away",
+        node.open_streams()
+    ))
+}
+
+#[tokio::test]
+async fn dropped_client_releases_stream() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    node.append_empty_blocks(BLOCKS).await?;
+
+    let mut client = node.oblivious_client().await?;
+    let mut stream = client
+        .compact_block_range(whole_chain())
+        .await?
+        .into_inner();
+    assert_eq!(stream.message().await?.map(|block| block.height), Some(0));
+    assert_eq!(node.open_streams(), 1);
+
+    drop(stream);
+    drop(client);
+    streams_released(&node).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stream_ends_at_deadline() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    node.append_empty_blocks(BLOCKS).await?;
+
+    let mut client = node.oblivious_client().await?;
+    let mut request = tonic::Request::new(whole_chain());
+    request.set_timeout(Duration::from_millis(100));
+    let mut stream = client.compact_block_range(request).await?.into_inner();
+
+    let status = loop {
+        match stream.message().await {
+            Ok(Some(_)) => continue,
+            Ok(None) => panic!("stream of {} blocks finished before its deadline", BLOCKS),
+            Err(status) => break status,
+        }
+    };
+    assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+    streams_released(&node).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn subscription_sends_committed_blocks() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    node.append_empty_blocks(2).await?;
+
+    // A subscription from a past height catches up before following the chain...
+    let mut client = node.oblivious_client().await?;
+    let mut from_genesis = client
+        .subscribe_compact_blocks(SubscribeCompactBlocksRequest {
+            chain_id: String::new(),
+            start_height: 1,
+        })
+        .await?
+        .into_inner();
+    // ... while one from the next block waits for it.
+    let mut from_next = client
+        .subscribe_compact_blocks(SubscribeCompactBlocksRequest {
+            chain_id: String::new(),
+            start_height: 0,
+        })
+        .await?
+        .into_inner();
+
+    for height in 1..=2 {
+        assert_eq!(
+            from_genesis.message().await?.map(|b| b.height),
+            Some(height)
+        );
+    }
+    node.append_empty_blocks(1).await?;
+    assert_eq!(from_genesis.message().await?.map(|b| b.height), Some(3));
+    assert_eq!(from_next.message().await?.map(|b| b.height), Some(3));
+
+    // Nothing more is sent until another block is committed.
+    let next = tokio::time::timeout(Duration::from_millis(100), from_next.message()).await;
+    assert!(next.is_err());
+
+    drop(from_genesis);
+    drop(from_next);
+    drop(client);
+    streams_released(&node).await?;
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use pd::{
+    genesis,
+    testnet::join::{Bootstrap, Sources},
+};
+use penumbra_chain::params::ChainParams;
+use serde_json::json;
+use tendermint::{public_key::Algorithm, Genesis, Time};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+const CHAIN_ID: &str = "penumbra-testnet";
+const NODE_ID: &str = "1111111111111111111111111111111111111111";
+const PEER_ID: &str = "2222222222222222222222222222222222222222";
+
+fn genesis() -> serde_json::Value {
+    let genesis = Genesis {
+        genesis_time: Time::from_unix_timestamp(0, 0).unwrap(),
+        chain_id: CHAIN_ID.parse().unwrap(),
+        initial_height: 0,
+        consensus_params: tendermint::consensus::Params {
+            block: tendermint::block::Size {
+                max_bytes: 22020096,
+                max_gas: -1,
+                time_iota_ms: 500,
+            },
+            evidence: tendermint::evidence::Params {
+                max_age_num_blocks: 100000,
+                max_age_duration: tendermint::evidence::Duration(Duration::new(86400, 0)),
+                max_bytes: 1048576,
+            },
+            validator: tendermint::consensus::params::ValidatorParams {
+                pub_key_types: vec![Algorithm::Ed25519],
+            },
+            version: None,
+        },
+        app_hash: vec![],
+        app_state: genesis::AppState {
+            chain_params: ChainParams {
+                chain_id: CHAIN_ID.to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        validators: vec![],
+    };
+    serde_json::to_value(&genesis).unwrap()
+}
+
+/// Serves a Tendermint RPC on the chain `network`, whose node has one peer,
+/// returning its URL.
+async fn serve_rpc(network: &'static str) -> anyhow::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).await?;
+                let mut line = String::new();
+                while stream.read_line(&mut line).await? > 2 {
+                    line.clear();
+                }
+
+                let result = match request_line.split_whitespace().nth(1) {
+                    Some("/genesis") => json!({ "genesis": genesis() }),
+                    Some("/status") => json!({
+                        "node_info": {
+                            "id": NODE_ID,
+                            "listen_addr": "tcp://0.0.0.0:26656",
+                            "network": network,
+                        }
+                    }),
+                    Some("/net_info") => json!({
+                        "peers": [{
+                            "node_info": { "id": PEER_ID, "listen_addr": "tcp://0.0.0.0:26656" },
+                            "remote_ip": "10.0.0.2",
+                        }]
+                    }),
+                    _ => json!(null),
+                };
+                let body = json!({ "jsonrpc": "2.0", "id": -1, "result": result }).to_string();
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).await?;
+                stream.write_all(body.as_bytes()).await?;
+                anyhow::Ok(())
+            });
+        }
+    });
+
+    Ok(url)
+}
+
+#[tokio::test]
+async fn writes_a_node_directory_from_a_running_node() -> anyhow::Result<()> {
+    let node = serve_rpc(CHAIN_ID).await?;
+    let bootstrap = Bootstrap::fetch(&Sources {
+        node,
+        genesis_url: None,
+        peer_list: None,
+    })
+    .await?;
+    assert_eq!(bootstrap.chain_id, CHAIN_ID);
+    // The node is dialed where it was reached, and its peers where it sees them.
+    assert_eq!(
+        bootstrap.peers,
+        vec![
+            format!("{}@127.0.0.1:26656", NODE_ID),
+            format!("{}@10.0.0.2:26656", PEER_ID),
+        ]
+    );
+
+    let dir = tempfile::tempdir()?;
+    let node_dir = dir.path().join("node0");
+    bootstrap.write_node_dir(&node_dir, "joiner", "tcp://127.0.0.1:26658")?;
+
+    let config_dir = node_dir.join("tendermint").join("config");
+    let config = std::fs::read_to_string(config_dir.join("config.toml"))?;
+    assert!(config.contains(&format!(
+        "persistent-peers = \"{}\"",
+        bootstrap.peers.join(",")
+    )));
+    assert!(config.contains("moniker = \"joiner\""));
+    let (chain_id, _) = genesis::parse(&std::fs::read(config_dir.join("genesis.json"))?)?;
+    assert_eq!(chain_id, CHAIN_ID);
+    for file in [
+        "node_key.json",
+        "priv_validator_key.json",
+        "validator_seed_phrase.txt",
+    ] {
+        assert!(config_dir.join(file).exists(), "{} was not written", file);
+    }
+    assert!(node_dir
+        .join("tendermint/data/priv_validator_state.json")
+        .exists());
+
+    // An existing directory is left alone.
+    assert!(bootstrap
+        .write_node_dir(&node_dir, "joiner", "tcp://127.0.0.1:26658")
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_a_node_on_another_chain() -> anyhow::Result<()> {
+    let node = serve_rpc("another-chain").await?;
+    assert!(Bootstrap::fetch(&Sources {
+        node,
+        genesis_url: None,
+        peer_list: None,
+    })
+    .await
+    .is_err());
+    Ok(())
+}
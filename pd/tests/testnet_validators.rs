@@ -0,0 +1,198 @@
+use std::net::IpAddr;
+
+use pd::testnet::{
+    check_imported_keys, check_validator_stakes, node_hosts, parse_validators, NodeHost,
+    DEFAULT_SELF_DELEGATION,
+};
+
+fn validators(self_delegations: &[Option<&str>]) -> String {
+    validators_with("self_delegation", self_delegations)
+}
+
+fn validators_at(listen_addresses: &[Option<&str>]) -> String {
+    validators_with("listen_address", listen_addresses)
+}
+
+/// A validators file, with `field` set to each value given.
+fn validators_with(field: &str, values: &[Option<&str>]) -> String {
+    let entries = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let value = value
+                .map(|value| format!(r#", "{}": "{}""#, field, value))
+                .unwrap_or_default();
+            format!(
+                r#"{{ "name": "v{}", "website": "", "description": "", "funding_streams": [],
+                   "sequence_number": 0{} }}"#,
+                i, value
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", entries.join(","))
+}
+
+#[test]
+fn parses_self_delegations() -> anyhow::Result<()> {
+    let parsed = parse_validators(validators(&[Some("25_000_000"), None]).as_bytes())?;
+    assert_eq!(parsed[0].self_delegation, 25_000_000);
+    assert_eq!(parsed[1].self_delegation, DEFAULT_SELF_DELEGATION);
+    check_validator_stakes(&parsed, 2)?;
+    Ok(())
+}
+
+#[test]
+fn refuses_validators_outside_the_active_set() -> anyhow::Result<()> {
+    let parsed = parse_validators(validators(&[Some("3"), Some("1"), Some("2")]).as_bytes())?;
+    // The validator which would be left out is named.
+    let error = check_validator_stakes(&parsed, 2).unwrap_err().to_string();
+    assert!(error.contains("\"v1\""), "{}", error);
+    assert!(!error.contains("\"v2\""), "{}", error);
+
+    // A validator with no stake would have no voting power.
+    let parsed = parse_validators(validators(&[Some("1"), Some("0")]).as_bytes())?;
+    assert!(check_validator_stakes(&parsed, 2).is_err());
+
+    // Tendermint caps the total voting power.
+    let parsed = parse_validators(validators(&[Some(u64::MAX.to_string().as_str())]).as_bytes())?;
+    assert!(check_validator_stakes(&parsed, 1).is_err());
+    Ok(())
+}
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+#[test]
+fn places_nodes_at_explicit_and_counted_addresses() -> anyhow::Result<()> {
+    let parsed = parse_validators(
+        validators_at(&[None, Some("fd00::7"), Some("Node2.Example.com"), None]).as_bytes(),
+    )?;
+    let hosts = node_hosts(&parsed, 0, ip("192.167.10.11"))?;
+    assert_eq!(
+        hosts,
+        vec![
+            NodeHost::Ip(ip("192.167.10.11")),
+            NodeHost::Ip(ip("fd00::7")),
+            NodeHost::Dns("node2.example.com".to_string()),
+            NodeHost::Ip(ip("192.167.10.41")),
+        ]
+    );
+    // IPv6 addresses are bracketed in peer addresses.
+    assert_eq!(hosts[1].to_string(), "[fd00::7]");
+
+    let parsed = parse_validators(validators_at(&[None, None]).as_bytes())?;
+    assert_eq!(
+        node_hosts(&parsed, 0, ip("fd00::1"))?,
+        vec![NodeHost::Ip(ip("fd00::1")), NodeHost::Ip(ip("fd00::b"))]
+    );
+    Ok(())
+}
+
+#[test]
+fn places_full_nodes_after_validators() -> anyhow::Result<()> {
+    let parsed = parse_validators(validators_at(&[None, Some("fd00::7"), None]).as_bytes())?;
+    let hosts = node_hosts(&parsed, 2, ip("192.167.10.11"))?;
+    assert_eq!(
+        hosts[3..],
+        [
+            NodeHost::Ip(ip("192.167.10.41")),
+            NodeHost::Ip(ip("192.167.10.51")),
+        ]
+    );
+
+    // A full node may not take a validator's explicit address.
+    let parsed = parse_validators(validators_at(&[Some("192.167.10.21")]).as_bytes())?;
+    let error = node_hosts(&parsed, 1, ip("192.167.10.11"))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("full node 0"), "{}", error);
+    Ok(())
+}
+
+#[test]
+fn refuses_overflowing_or_clashing_addresses() -> anyhow::Result<()> {
+    // The fourth node would be past 192.167.10.255.
+    let parsed = parse_validators(validators_at(&[None, None, None, None]).as_bytes())?;
+    let error = node_hosts(&parsed, 0, ip("192.167.10.240"))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("\"v2\""), "{}", error);
+
+    // An explicit address may not clash with a counted one.
+    let parsed = parse_validators(validators_at(&[None, Some("192.167.10.11")]).as_bytes())?;
+    assert!(node_hosts(&parsed, 0, ip("192.167.10.11")).is_err());
+
+    for invalid in [
+        "not a host",
+        "-node.example.com",
+        "10.0.0.256",
+        "node0:26656",
+    ] {
+        let parsed = parse_validators(validators_at(&[Some(invalid)]).as_bytes())?;
+        assert!(
+            node_hosts(&parsed, 0, ip("192.167.10.11")).is_err(),
+            "{:?} was accepted",
+            invalid
+        );
+    }
+    Ok(())
+}
+
+const IDENTITY_KEY: &str =
+    "penumbravalid18caak577mn2k8aaswx8nk2ks0ajpmdmvmke6gt3cenfeaz22huqs0fj2j6";
+const CONSENSUS_KEY: &str = "NDLxa/YJZ+ZXiYhU3FkxEJY5nzzAE1m8eAaAhPfuxuU=";
+
+#[test]
+fn imports_existing_keys() -> anyhow::Result<()> {
+    let parsed =
+        parse_validators(validators_with("identity_key", &[Some(IDENTITY_KEY), None]).as_bytes())?;
+    assert_eq!(
+        parsed[0].identity_key.as_ref().unwrap().to_string(),
+        IDENTITY_KEY
+    );
+    assert_eq!(parsed[1].identity_key, None);
+    check_imported_keys(&parsed)?;
+
+    let parsed = parse_validators(
+        validators_with("consensus_key", &[None, Some(CONSENSUS_KEY)]).as_bytes(),
+    )?;
+    assert_eq!(parsed[0].consensus_key, None);
+    // Tendermint writes the key in its own files in the same base64 encoding.
+    let consensus_key = serde_json::to_value(parsed[1].consensus_key.unwrap())?;
+    assert_eq!(consensus_key["value"], CONSENSUS_KEY);
+    check_imported_keys(&parsed)?;
+    Ok(())
+}
+
+#[test]
+fn refuses_invalid_or_shared_keys() -> anyhow::Result<()> {
+    for (field, value) in [
+        ("identity_key", "penumbravalid1notakey"),
+        // Valid base64, but too short for an Ed25519 key.
+        ("consensus_key", "NDLxa/YJZ+ZX"),
+        ("consensus_key", "not base64!"),
+    ] {
+        assert!(
+            parse_validators(validators_with(field, &[Some(value)]).as_bytes()).is_err(),
+            "{} {:?} was accepted",
+            field,
+            value
+        );
+    }
+
+    for (field, value) in [
+        ("identity_key", IDENTITY_KEY),
+        ("consensus_key", CONSENSUS_KEY),
+    ] {
+        let parsed =
+            parse_validators(validators_with(field, &[Some(value), None, Some(value)]).as_bytes())?;
+        let error = check_imported_keys(&parsed).unwrap_err().to_string();
+        assert!(
+            error.contains("\"v0\"") && error.contains("\"v2\""),
+            "{}",
+            error
+        );
+    }
+    Ok(())
+}
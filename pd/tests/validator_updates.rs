@@ -0,0 +1,116 @@
+use pd::{
+    components::{
+        app::View as _,
+        staking::{validator_updates, View as _},
+        Staking,
+    },
+    genesis,
+    testing::{address, delegations, validator, Node},
+    Component,
+};
+use penumbra_stake::{Validator, ValidatorSet, ValidatorSetEntry, ValidatorState};
+use tendermint::{abci, PublicKey};
+
+fn entry(validator: &Validator, power: u64) -> ValidatorSetEntry {
+    ValidatorSetEntry {
+        identity_key: validator.identity_key.clone(),
+        consensus_key: validator.consensus_key,
+        power,
+    }
+}
+
+/// The updates from `previous` to `current`, as (consensus key, power)
+/// pairs, in a canonical order.
+fn updates(
+    previous: Vec<ValidatorSetEntry>,
+    current: Vec<ValidatorSetEntry>,
+) -> Vec<(PublicKey, u64)> {
+    sorted(
+        validator_updates(&ValidatorSet(previous), &ValidatorSet(current))
+            .unwrap()
+            .into_iter()
+            .map(|update| (update.pub_key, update.power.value()))
+            .collect(),
+    )
+}
+
+fn sorted(mut updates: Vec<(PublicKey, u64)>) -> Vec<(PublicKey, u64)> {
+    updates.sort_by_key(|(pub_key, _)| pub_key.to_bytes());
+    updates
+}
+
+#[test]
+fn reports_joins_exits_and_power_changes() {
+    let (a, b, c) = (validator("a"), validator("b"), validator("c"));
+
+    // An unchanged set needs no updates.
+    assert!(updates(vec![entry(&a, 10)], vec![entry(&a, 10)]).is_empty());
+
+    // Joining validators are reported with their power, changed powers are
+    // reported, and leaving validators are reported with no power.
+    assert_eq!(
+        updates(
+            vec![entry(&a, 10), entry(&b, 20)],
+            vec![entry(&a, 15), entry(&c, 30)]
+        ),
+        sorted(vec![
+            (a.consensus_key, 15),
+            (b.consensus_key, 0),
+            (c.consensus_key, 30)
+        ])
+    );
+
+    // Validators without power are not in Tendermint's set, so are neither
+    // added nor removed...
+    assert!(updates(vec![], vec![entry(&a, 0)]).is_empty());
+    assert!(updates(vec![entry(&a, 0)], vec![]).is_empty());
+    // ... but losing all power removes a validator.
+    assert_eq!(
+        updates(vec![entry(&a, 10)], vec![entry(&a, 0)]),
+        vec![(a.consensus_key, 0)]
+    );
+
+    // A validator changing its consensus key is replaced under the new key.
+    let rotated = Validator {
+        consensus_key: validator("a").consensus_key,
+        ..a.clone()
+    };
+    assert_eq!(
+        updates(vec![entry(&a, 10)], vec![entry(&rotated, 10)]),
+        sorted(vec![(a.consensus_key, 0), (rotated.consensus_key, 10)])
+    );
+}
+
+#[tokio::test]
+async fn removes_slashed_validators() -> anyhow::Result<()> {
+    let (a, b) = (validator("a"), validator("b"));
+    // Each validator's power comes from the delegation tokens allocated to it.
+    let allocations = delegations(&[a.clone(), b.clone()], 1_000_000, address());
+    let node = Node::start(genesis::AppState {
+        validators: vec![a.clone(), b.clone()],
+        allocations,
+        ..Default::default()
+    })
+    .await?;
+
+    // Slash `b` in the next block.
+    let overlay = node.storage().overlay().await?;
+    overlay.put_block_height(1).await;
+    overlay
+        .set_validator_state(&b.identity_key, ValidatorState::Slashed)
+        .await;
+
+    let mut staking = Staking::new(overlay).await?;
+    staking
+        .end_block(&abci::request::EndBlock { height: 1 })
+        .await?;
+    let updates: Vec<_> = staking
+        .validator_set_updates()
+        .await?
+        .into_iter()
+        .map(|update| (update.pub_key, update.power.value()))
+        .collect();
+    assert_eq!(updates, vec![(b.consensus_key, 0)]);
+
+    Ok(())
+}
@@ -57,12 +57,20 @@ fn main() -> Result<()> {
         &["proto/", "ibc-go-vendor/"],
     )?;
 
+    // Embed the descriptors of the RPC protocols, and of everything they
+    // import, so that programs can check that they speak the same protocol.
+    config.file_descriptor_set_path(
+        std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("file_descriptor_set.bin"),
+    );
+
     // For the client code, we also want to generate RPC instances, so compile via tonic:
     tonic_build::configure().compile_with_config(
         config,
         &[
             "proto/client/oblivious.proto",
             "proto/client/specific.proto",
+            "proto/admin.proto",
+            "proto/wallet_next.proto",
         ],
         &["proto/", "ibc-go-vendor/"],
     )?;
@@ -73,6 +81,8 @@ fn main() -> Result<()> {
 static SERIALIZE: &str = r#"#[derive(::serde::Deserialize, ::serde::Serialize)]"#;
 /// Serializes newtype structs as if the inner field were serialized on its own.
 static SERDE_TRANSPARENT: &str = r#"#[serde(transparent)]"#;
+/// Allows a field to be omitted, deserializing it as its default value.
+static SERDE_DEFAULT: &str = r#"#[serde(default)]"#;
 
 static AS_HEX: &str = r#"#[serde(with = "crate::serializers::hexstr")]"#;
 static AS_BASE64: &str = r#"#[serde(with = "crate::serializers::base64str")]"#;
@@ -91,6 +101,8 @@ static TYPE_ATTRIBUTES: &[(&str, &str)] = &[
     (".penumbra.stake.ValidatorStateEnum", SERIALIZE),
     (".penumbra.stake.ValidatorStateName", SERIALIZE),
     (".penumbra.stake.ValidatorStatus", SERIALIZE),
+    (".penumbra.stake.ValidatorSet", SERIALIZE),
+    (".penumbra.stake.ValidatorSetEntry", SERIALIZE),
     (".penumbra.stake.RateData", SERIALIZE),
     (".penumbra.stake.BaseRateData", SERIALIZE),
     (".penumbra.stake.IdentityKey", SERIALIZE),
@@ -124,6 +136,7 @@ static FIELD_ATTRIBUTES: &[(&str, &str)] = &[
     // Using base64 for the validator's consensus key means that
     // the format is the same as the Tendermint json config files.
     (".penumbra.stake.Validator.consensus_key", AS_BASE64),
+    (".penumbra.stake.ValidatorSetEntry.consensus_key", AS_BASE64),
     (".penumbra.stake.ValidatorDefinition.auth_sig", AS_HEX),
     (".penumbra.stake.IdentityKey.ik", AS_BECH32_IDENTITY_KEY),
     (".penumbra.crypto.Address.inner", AS_BECH32_ADDRESS),
@@ -131,4 +144,37 @@ static FIELD_ATTRIBUTES: &[(&str, &str)] = &[
     (".penumbra.crypto.NoteCommitment.inner", AS_HEX),
     (".penumbra.crypto.MerkleRoot.inner", AS_HEX),
     (".penumbra.chain.NoteSource.inner", AS_HEX),
+    (
+        ".penumbra.genesis.GenesisAppState.base_rate_data",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.genesis.GenesisAppState.validator_rates",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.epoch_duration_seconds",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.min_validator_bond_epochs",
+        SERDE_DEFAULT,
+    ),
+    (".penumbra.chain.ChainParams.features", SERDE_DEFAULT),
+    (
+        ".penumbra.chain.ChainParams.faucet_max_claim",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.faucet_claim_interval",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.faucet_claims_per_block",
+        SERDE_DEFAULT,
+    ),
+    (
+        ".penumbra.chain.ChainParams.max_validator_stake_share",
+        SERDE_DEFAULT,
+    ),
 ];
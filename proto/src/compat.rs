@@ -0,0 +1,283 @@
+//! Every program built from this crate embeds the [`FILE_DESCRIPTOR_SET`] it
+//! was compiled with, and `pd` serves its own over the oblivious query service.
+//! A client can then [`check`] that every method it calls, and every field of
+//! every message those methods exchange, exists with the same type on the
+//! other side, and refuse to run against an incompatible node with a clear
+//! message, rather than failing later with decode errors or silently missing
+//! data.
+//!
+//! Additions on the other side are compatible: unknown methods are never
+//! called, and unknown fields are skipped when decoding.
+//!
+//! [`FILE_DESCRIPTOR_SET`]: crate::FILE_DESCRIPTOR_SET
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+use prost::Message;
+pub use prost_types::FileDescriptorSet;
+use prost_types::{
+    field_descriptor_proto::Label, DescriptorProto, FieldDescriptorProto, MethodDescriptorProto,
+};
+
+/// The descriptors compiled into this crate.
+pub fn file_descriptor_set() -> FileDescriptorSet {
+    FileDescriptorSet::decode(crate::FILE_DESCRIPTOR_SET)
+        .expect("compiled file descriptor set is valid")
+}
+
+/// A difference which prevents one program from using another's protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Incompatibility {
+    /// The method does not exist on the other side.
+    MissingMethod { method: String },
+    /// The method exists, but takes or returns different messages.
+    MethodSignature {
+        method: String,
+        expected: String,
+        found: String,
+    },
+    /// A message used by the methods does not exist on the other side.
+    MissingMessage { message: String },
+    /// A field of a message does not exist on the other side.
+    MissingField { message: String, field: String },
+    /// A field of a message has a different type on the other side.
+    FieldType {
+        message: String,
+        field: String,
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Incompatibility::MissingMethod { method } => write!(f, "missing method {}", method),
+            Incompatibility::MethodSignature {
+                method,
+                expected,
+                found,
+            } => write!(f, "method {} is {}, expected {}", method, found, expected),
+            Incompatibility::MissingMessage { message } => {
+                write!(f, "missing message {}", message)
+            }
+            Incompatibility::MissingField { message, field } => {
+                write!(f, "missing field {} of message {}", field, message)
+            }
+            Incompatibility::FieldType {
+                message,
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "field {} of message {} is {}, expected {}",
+                field, message, found, expected
+            ),
+        }
+    }
+}
+
+/// Checks that `provided` has everything `required` uses to call `methods`.
+///
+/// Methods are named by their gRPC paths, e.g.
+/// `/penumbra.client.specific.SpecificQuery/NctAnchor`; fields are compared by
+/// number, since that is all that appears on the wire.
+pub fn check(
+    required: &FileDescriptorSet,
+    provided: &FileDescriptorSet,
+    methods: &[&str],
+) -> Vec<Incompatibility> {
+    let required = Index::new(required);
+    let provided = Index::new(provided);
+    let mut incompatibilities = Vec::new();
+
+    // The messages to compare, starting with those the methods exchange.
+    let mut pending = Vec::new();
+    for &method in methods {
+        let required_method = match required.methods.get(method) {
+            Some(required_method) => required_method,
+            // Methods unknown to the caller are never called.
+            None => continue,
+        };
+        match provided.methods.get(method) {
+            Some(provided_method) => {
+                if signature(required_method) != signature(provided_method) {
+                    incompatibilities.push(Incompatibility::MethodSignature {
+                        method: method.to_string(),
+                        expected: signature(required_method),
+                        found: signature(provided_method),
+                    });
+                }
+            }
+            None => incompatibilities.push(Incompatibility::MissingMethod {
+                method: method.to_string(),
+            }),
+        }
+        pending.push(required_method.input_type().to_string());
+        pending.push(required_method.output_type().to_string());
+    }
+
+    let mut visited = BTreeSet::new();
+    while let Some(message) = pending.pop() {
+        if !visited.insert(message.clone()) {
+            continue;
+        }
+        let required_message = match required.messages.get(&message) {
+            Some(required_message) => required_message,
+            None => continue,
+        };
+        let provided_message = match provided.messages.get(&message) {
+            Some(provided_message) => provided_message,
+            None => {
+                incompatibilities.push(Incompatibility::MissingMessage {
+                    message: message.trim_start_matches('.').to_string(),
+                });
+                continue;
+            }
+        };
+
+        for required_field in &required_message.field {
+            match provided_message
+                .field
+                .iter()
+                .find(|field| field.number() == required_field.number())
+            {
+                Some(provided_field) => {
+                    if field_type(required_field) != field_type(provided_field) {
+                        incompatibilities.push(Incompatibility::FieldType {
+                            message: message.trim_start_matches('.').to_string(),
+                            field: required_field.name().to_string(),
+                            expected: field_type(required_field),
+                            found: field_type(provided_field),
+                        });
+                    }
+                }
+                None => incompatibilities.push(Incompatibility::MissingField {
+                    message: message.trim_start_matches('.').to_string(),
+                    field: required_field.name().to_string(),
+                }),
+            }
+            if !required_field.type_name().is_empty() {
+                pending.push(required_field.type_name().to_string());
+            }
+        }
+    }
+
+    incompatibilities
+}
+
+/// The methods and messages of a descriptor set, by fully qualified name.
+struct Index<'a> {
+    /// Keyed by gRPC path, e.g. `/penumbra.client.specific.SpecificQuery/NctAnchor`.
+    methods: HashMap<String, &'a MethodDescriptorProto>,
+    /// Keyed by type name, e.g. `.penumbra.crypto.MerkleRoot`.
+    messages: HashMap<String, &'a DescriptorProto>,
+}
+
+impl<'a> Index<'a> {
+    fn new(set: &'a FileDescriptorSet) -> Self {
+        let mut index = Index {
+            methods: HashMap::new(),
+            messages: HashMap::new(),
+        };
+        for file in &set.file {
+            let package = match file.package() {
+                "" => String::new(),
+                package => format!(".{}", package),
+            };
+            for service in &file.service {
+                for method in &service.method {
+                    index.methods.insert(
+                        format!(
+                            "/{}.{}/{}",
+                            package.trim_start_matches('.'),
+                            service.name(),
+                            method.name()
+                        ),
+                        method,
+                    );
+                }
+            }
+            for message in &file.message_type {
+                index.insert_message(&package, message);
+            }
+        }
+        index
+    }
+
+    fn insert_message(&mut self, scope: &str, message: &'a DescriptorProto) {
+        let name = format!("{}.{}", scope, message.name());
+        for nested in &message.nested_type {
+            self.insert_message(&name, nested);
+        }
+        self.messages.insert(name, message);
+    }
+}
+
+fn signature(method: &MethodDescriptorProto) -> String {
+    let stream = |streaming| if streaming { "stream " } else { "" };
+    format!(
+        "({}{}) returns ({}{})",
+        stream(method.client_streaming()),
+        method.input_type().trim_start_matches('.'),
+        stream(method.server_streaming()),
+        method.output_type().trim_start_matches('.'),
+    )
+}
+
+fn field_type(field: &FieldDescriptorProto) -> String {
+    let label = match field.label() {
+        Label::Repeated => "repeated ",
+        Label::Optional | Label::Required => "",
+    };
+    match field.type_name() {
+        "" => format!("{}{:?}", label, field.r#type()).to_lowercase(),
+        type_name => format!("{}{}", label, type_name.trim_start_matches('.')),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NCT_ANCHOR: &str = "/penumbra.client.specific.SpecificQuery/NctAnchor";
+
+    #[test]
+    fn compatible_with_itself() {
+        let set = file_descriptor_set();
+        assert_eq!(check(&set, &set, &[NCT_ANCHOR]), Vec::new());
+    }
+
+    #[test]
+    fn detects_missing_fields_and_methods() {
+        let required = file_descriptor_set();
+        let mut provided = required.clone();
+        for file in &mut provided.file {
+            for message in &mut file.message_type {
+                if message.name() == "MerkleRoot" {
+                    message.field.clear();
+                }
+            }
+            for service in &mut file.service {
+                service.method.retain(|method| method.name() != "NctAnchor");
+            }
+        }
+
+        assert_eq!(
+            check(&required, &provided, &[NCT_ANCHOR]),
+            vec![
+                Incompatibility::MissingMethod {
+                    method: NCT_ANCHOR.to_string()
+                },
+                Incompatibility::MissingField {
+                    message: "penumbra.crypto.MerkleRoot".to_string(),
+                    field: "inner".to_string(),
+                },
+            ]
+        );
+    }
+}
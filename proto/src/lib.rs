@@ -14,6 +14,11 @@
 //!
 //! The [`Protobuf`] marker trait can be implemented on a domain type to ensure
 //! these conversions exist.
+//!
+//! The exceptions are [`proofs`], which verifies the ICS23 proofs of chain
+//! state returned by `pd`, so that clients can check them without depending
+//! on any of its internals, and [`compat`], which checks that two programs
+//! built from different versions of this crate can talk to each other.
 
 pub use prost::Message;
 
@@ -23,6 +28,17 @@ pub mod serializers;
 mod protobuf;
 pub use protobuf::Protobuf;
 
+/// Verification of ICS23 proofs of the chain state.
+pub mod proofs;
+
+/// Compatibility checks between the protocols compiled into different programs.
+pub mod compat;
+
+/// The encoded `FileDescriptorSet` of the RPC protocols and everything they
+/// import, as compiled into this crate.
+pub const FILE_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/file_descriptor_set.bin"));
+
 /// Crypto structures.
 pub mod crypto {
     include!(concat!(env!("OUT_DIR"), "/penumbra.crypto.rs"));
@@ -58,6 +74,16 @@ pub mod client {
     }
 }
 
+/// Node administration structures.
+pub mod admin {
+    tonic::include_proto!("penumbra.admin");
+}
+
+/// The `pwalletd` RPC protocol.
+pub mod wallet_next {
+    tonic::include_proto!("penumbra.wallet_next");
+}
+
 /// IBC protocol structures.
 pub mod ibc {
     tonic::include_proto!("penumbra.ibc");
@@ -87,6 +113,7 @@ pub mod sighash {
                     ..
                 })) => Some(SHAction::Spend(spend_body)),
                 Some(TxAction::IbcAction(i)) => Some(SHAction::IbcAction(i)),
+                Some(TxAction::FaucetClaim(c)) => Some(SHAction::FaucetClaim(c)),
                 None => None,
             };
             Self { action }
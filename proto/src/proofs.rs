@@ -0,0 +1,65 @@
+//! Verification of ICS23 proofs of Penumbra's state.
+//!
+//! The state is a Jellyfish Merkle tree (JMT), keyed by the SHA-256 hash of
+//! each key, so proofs are [`CommitmentProof`]s over the *hashed* key. The
+//! [`ProofSpec`] describing the JMT's hashing is given by [`spec`]; the helpers
+//! here hash the key before verifying, so they take the key as it was written.
+
+pub use ics23::CommitmentProof;
+use ics23::{HashOp, InnerSpec, LeafOp, LengthOp, ProofSpec};
+use sha2::{Digest, Sha256};
+
+/// The domain separator prepended to leaf nodes before hashing.
+pub const LEAF_PREFIX: &[u8] = b"JMT::LeafNode";
+
+/// The domain separator prepended to internal nodes before hashing.
+pub const INTERNAL_PREFIX: &[u8] = b"JMT::IntrnalNode";
+
+/// The hash of an empty subtree.
+pub const PLACEHOLDER_HASH: &[u8; 32] = b"SPARSE_MERKLE_PLACEHOLDER_HASH__";
+
+/// The hash under which `key` is stored in the state tree.
+pub fn key_hash(key: &[u8]) -> [u8; 32] {
+    Sha256::digest(key).into()
+}
+
+/// The operation which hashes a leaf of the state tree, given its key hash and
+/// value.
+pub fn leaf_op() -> LeafOp {
+    LeafOp {
+        hash: HashOp::Sha256.into(),
+        prehash_key: HashOp::NoHash.into(),
+        prehash_value: HashOp::Sha256.into(),
+        length: LengthOp::NoPrefix.into(),
+        prefix: LEAF_PREFIX.to_vec(),
+    }
+}
+
+/// The ICS23 specification of proofs of the state tree.
+pub fn spec() -> ProofSpec {
+    ProofSpec {
+        leaf_spec: Some(leaf_op()),
+        inner_spec: Some(InnerSpec {
+            hash: HashOp::Sha256.into(),
+            child_order: vec![0, 1],
+            min_prefix_length: INTERNAL_PREFIX.len() as i32,
+            max_prefix_length: INTERNAL_PREFIX.len() as i32,
+            child_size: 32,
+            empty_child: PLACEHOLDER_HASH.to_vec(),
+        }),
+        min_depth: 0,
+        max_depth: 0,
+    }
+}
+
+/// Verifies that `proof` proves that `key` is set to `value` in the state with
+/// the given root hash.
+pub fn verify_membership(proof: &CommitmentProof, root: &[u8], key: &[u8], value: &[u8]) -> bool {
+    ics23::verify_membership(proof, &spec(), &root.to_vec(), &key_hash(key), value)
+}
+
+/// Verifies that `proof` proves that `key` is not set in the state with the
+/// given root hash.
+pub fn verify_non_membership(proof: &CommitmentProof, root: &[u8], key: &[u8]) -> bool {
+    ics23::verify_non_membership(proof, &spec(), &root.to_vec(), &key_hash(key))
+}
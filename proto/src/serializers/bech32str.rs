@@ -117,6 +117,28 @@ pub mod address {
     }
 }
 
+pub mod address_viewing_key {
+    use super::*;
+
+    /// The Bech32 prefix used for viewing keys scoped to a single address.
+    pub const BECH32_PREFIX: &str = "penumbraavk";
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_bech32(deserializer, BECH32_PREFIX, Variant::Bech32m)
+    }
+
+    pub fn serialize<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]>,
+    {
+        serialize_bech32(value, serializer, BECH32_PREFIX, Variant::Bech32m)
+    }
+}
+
 pub mod asset_id {
     use super::*;
 
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use penumbra_crypto::Address;
+use penumbra_proto::{stake as pb, Protobuf};
+use serde::{Deserialize, Serialize};
+
+use crate::IdentityKey;
+
+/// The commission paid to each of a validator's funding streams at the end of an epoch.
+///
+/// This is recorded so that validators can reconcile their commission income,
+/// and delegators can audit that it matched the advertised rates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommissionPayouts {
+    pub identity_key: IdentityKey,
+    /// The index of the epoch whose rewards were paid out.
+    pub epoch_index: u64,
+    pub payouts: Vec<FundingStreamPayout>,
+}
+
+/// The amount paid to a single funding stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FundingStreamPayout {
+    pub address: Address,
+    /// The funding stream's advertised rate, in basis points.
+    pub rate_bps: u16,
+    pub amount: u64,
+}
+
+impl Protobuf<pb::CommissionPayouts> for CommissionPayouts {}
+
+impl From<CommissionPayouts> for pb::CommissionPayouts {
+    fn from(payouts: CommissionPayouts) -> pb::CommissionPayouts {
+        pb::CommissionPayouts {
+            identity_key: Some(payouts.identity_key.into()),
+            epoch_index: payouts.epoch_index,
+            payouts: payouts.payouts.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TryFrom<pb::CommissionPayouts> for CommissionPayouts {
+    type Error = anyhow::Error;
+    fn try_from(payouts: pb::CommissionPayouts) -> Result<CommissionPayouts> {
+        Ok(CommissionPayouts {
+            identity_key: payouts
+                .identity_key
+                .ok_or_else(|| anyhow::anyhow!("missing identity key"))?
+                .try_into()?,
+            epoch_index: payouts.epoch_index,
+            payouts: payouts
+                .payouts
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl Protobuf<pb::FundingStreamPayout> for FundingStreamPayout {}
+
+impl From<FundingStreamPayout> for pb::FundingStreamPayout {
+    fn from(payout: FundingStreamPayout) -> pb::FundingStreamPayout {
+        pb::FundingStreamPayout {
+            address: Some(payout.address.into()),
+            rate_bps: payout.rate_bps.into(),
+            amount: payout.amount,
+        }
+    }
+}
+
+impl TryFrom<pb::FundingStreamPayout> for FundingStreamPayout {
+    type Error = anyhow::Error;
+    fn try_from(payout: pb::FundingStreamPayout) -> Result<FundingStreamPayout> {
+        Ok(FundingStreamPayout {
+            address: payout
+                .address
+                .ok_or_else(|| anyhow::anyhow!("missing address"))?
+                .try_into()?,
+            rate_bps: payout.rate_bps.try_into()?,
+            amount: payout.amount,
+        })
+    }
+}
@@ -1,50 +1,39 @@
-use tendermint::block;
-
 /// Epoch represents a given epoch for Penumbra and is used
 /// for calculation of staking exchange rates.
+///
+/// Epochs are either a fixed number of blocks long, or end with the first
+/// block at least a fixed amount of block time after they started, depending
+/// on the chain parameters; in either case, an epoch is identified by its index
+/// and the height of its first block.
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub struct Epoch {
     pub index: u64,
-    pub duration: u64,
+    /// The height of the first block in this epoch.
+    pub start_height: u64,
 }
 
 impl Epoch {
-    /// Instantiates a new `Epoch` from a given block height and epoch duration.
+    /// Instantiates a new `Epoch` from a given block height and epoch duration,
+    /// for chains whose epochs are a fixed number of blocks long.
     pub fn from_height(height: u64, epoch_duration: u64) -> Epoch {
+        let index = height / epoch_duration;
         Epoch {
-            index: height / epoch_duration,
-            duration: epoch_duration,
+            index,
+            start_height: index * epoch_duration,
         }
     }
 
-    /// Indicates the starting block height for this epoch (inclusive)
-    pub fn start_height(&self) -> block::Height {
-        block::Height::try_from(self.index * self.duration).expect("able to parse block height")
-    }
-
-    /// Indicates the ending block height for this epoch (inclusive)
-    pub fn end_height(&self) -> block::Height {
-        block::Height::try_from((self.index + 1) * self.duration - 1)
-            .expect("able to parse block height")
-    }
-
-    pub fn is_epoch_end(&self, height: u64) -> bool {
-        self.end_height().value() == height
+    /// Indicates whether `height` is the last block of this epoch, for chains
+    /// whose epochs are a fixed number of blocks long.
+    pub fn is_epoch_end(&self, height: u64, epoch_duration: u64) -> bool {
+        height + 1 == self.start_height + epoch_duration
     }
 
-    /// Returns the epoch following this one.
-    pub fn next(&self) -> Self {
+    /// Returns the epoch following this one, given the height of its first block.
+    pub fn next(&self, start_height: u64) -> Self {
         Epoch {
             index: self.index + 1,
-            duration: self.duration,
-        }
-    }
-
-    /// Returns the epoch preceding this one.
-    pub fn prev(&self) -> Self {
-        Epoch {
-            index: self.index - 1,
-            duration: self.duration,
+            start_height,
         }
     }
 }
@@ -0,0 +1,197 @@
+/// The schedule by which the base reward rate declines from epoch to epoch.
+///
+/// The base reward rate of epoch $e$ is $r_e = \max(r_{min}, r_0 (1 - d)^e)$, for the initial
+/// rate $r_0$ and the decay $d$ per epoch, so that issuance decays exponentially from genesis
+/// until it reaches the floor $r_{min}$. With no decay, the rate is the constant $r_0$.
+///
+/// All three parameters are fixed-point, with an implicit denominator of $10^8$, like the rates
+/// themselves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IssuanceSchedule {
+    /// The base reward rate at epoch 0.
+    pub initial_base_reward_rate: u64,
+    /// The fraction by which the base reward rate declines each epoch.
+    pub base_reward_rate_decay: u64,
+    /// The base reward rate below which it does not decline.
+    pub min_base_reward_rate: u64,
+}
+
+impl IssuanceSchedule {
+    /// A schedule with the same base reward rate in every epoch.
+    pub fn constant(base_reward_rate: u64) -> Self {
+        IssuanceSchedule {
+            initial_base_reward_rate: base_reward_rate,
+            base_reward_rate_decay: 0,
+            min_base_reward_rate: 0,
+        }
+    }
+
+    /// Computes the base reward rate of the epoch with the given index.
+    ///
+    /// The rate is computed directly from the index, rather than from the previous epoch's rate,
+    /// so that rounding errors do not accumulate from epoch to epoch: $(1 - d)^e$ is computed by
+    /// repeated squaring, rounding down after each fixed-point product. A decay of $10^8$ or more
+    /// sends the rate straight to the floor.
+    pub fn base_reward_rate(&self, epoch_index: u64) -> u64 {
+        let retained = 1_0000_0000u128.saturating_sub(self.base_reward_rate_decay as u128);
+
+        let mut factor = 1_0000_0000u128;
+        let mut square = retained;
+        let mut exponent = epoch_index;
+        while exponent > 0 && factor > 0 {
+            if exponent & 1 == 1 {
+                factor = (factor * square) / 1_0000_0000;
+            }
+            square = (square * square) / 1_0000_0000;
+            exponent >>= 1;
+        }
+
+        let rate = (self.initial_base_reward_rate as u128 * factor) / 1_0000_0000;
+        // The rate never exceeds the initial rate, so it fits back into 64 bits.
+        (rate as u64).max(self.min_base_reward_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BaseRateData;
+
+    const EPOCHS: u64 = 3650;
+    const SUPPLY: u64 = 1_000_000_000_000;
+
+    /// The supply after each epoch, starting at `SUPPLY`, as the base exchange rate revalues it.
+    fn supplies(schedule: &IssuanceSchedule) -> Vec<f64> {
+        let mut base_rate = BaseRateData {
+            epoch_index: 0,
+            base_reward_rate: 0,
+            base_exchange_rate: 1_0000_0000,
+        };
+        (0..EPOCHS)
+            .map(|_| {
+                base_rate = base_rate.next(schedule);
+                SUPPLY as f64 * base_rate.base_exchange_rate as f64 / 1e8
+            })
+            .collect()
+    }
+
+    /// How far the supply may fall short of its exact value after the given number of epochs.
+    ///
+    /// Rounding down the exchange rate and the reward rate each lose at most one unit of the
+    /// exchange rate per epoch, which later epochs compound by less than the supply grows here,
+    /// a factor of at most 3.
+    fn rounding_allowance(epochs: u64) -> f64 {
+        SUPPLY as f64 * 3.0 * 2.0 * epochs as f64 / 1e8
+    }
+
+    #[test]
+    fn constant_schedule_compounds() {
+        let r = 3_0000;
+        let supplies = supplies(&IssuanceSchedule::constant(r));
+        for (i, supply) in supplies.into_iter().enumerate() {
+            let epoch = i as u64 + 1;
+            let expected = SUPPLY as f64 * (1.0 + r as f64 / 1e8).powi(epoch as i32);
+            assert!(supply <= expected * (1.0 + 1e-12), "epoch {}", epoch);
+            assert!(
+                expected - supply <= rounding_allowance(epoch),
+                "epoch {}: supply {} but expected {}",
+                epoch,
+                supply,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn rate_decays_exponentially() {
+        let schedule = IssuanceSchedule {
+            initial_base_reward_rate: 3_0000,
+            base_reward_rate_decay: 10_0000,
+            min_base_reward_rate: 0,
+        };
+        for epoch in 0..EPOCHS {
+            let expected = 3e4 * (1.0 - 1e-3f64).powi(epoch as i32);
+            let rate = schedule.base_reward_rate(epoch) as f64;
+            // Each of the O(log e) fixed-point products rounds down by at most 10^-8, and the
+            // rate itself by less than one unit.
+            assert!(rate <= expected + 1e-6, "epoch {}", epoch);
+            assert!(
+                expected - rate <= 2.0,
+                "epoch {}: {} not {}",
+                epoch,
+                rate,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn decaying_schedule_bounds_supply() {
+        let (r0, d) = (3_0000u64, 10_0000u64);
+        let schedule = IssuanceSchedule {
+            initial_base_reward_rate: r0,
+            base_reward_rate_decay: d,
+            min_base_reward_rate: 0,
+        };
+        let (r0, q) = (r0 as f64 / 1e8, 1.0 - d as f64 / 1e8);
+
+        // The supply after epoch e is the product of (1 + r_k) over epochs 1 through e, where
+        // r_k = r0 q^k. Since x - x^2/2 <= ln(1 + x) <= x, its logarithm lies between the
+        // closed-form geometric sums S1 - S2/2 and S1, for S1 = sum r_k and S2 = sum r_k^2.
+        let supplies = supplies(&schedule);
+        for (i, supply) in supplies.iter().enumerate() {
+            let epoch = i as i32 + 1;
+            let s1 = r0 * q * (1.0 - q.powi(epoch)) / (1.0 - q);
+            let s2 = r0 * r0 * q * q * (1.0 - q.powi(2 * epoch)) / (1.0 - q * q);
+            let upper = SUPPLY as f64 * s1.exp();
+            let lower = SUPPLY as f64 * (s1 - s2 / 2.0).exp();
+            assert!(*supply <= upper * (1.0 + 1e-12), "epoch {}", epoch);
+            assert!(
+                lower - supply <= rounding_allowance(epoch as u64),
+                "epoch {}: supply {} below {}",
+                epoch,
+                supply,
+                lower
+            );
+        }
+
+        // Issuance converges: the supply never exceeds the limit of the upper bound, and the
+        // supply grows by less over the last year than over the first.
+        let limit = SUPPLY as f64 * (r0 * q / (1.0 - q)).exp();
+        let last = supplies[supplies.len() - 1];
+        assert!(last <= limit);
+        let first_year = supplies[364] - SUPPLY as f64;
+        let last_year = last - supplies[supplies.len() - 366];
+        assert!(last_year < first_year / 10.0);
+    }
+
+    #[test]
+    fn rate_stops_at_floor() {
+        let schedule = IssuanceSchedule {
+            initial_base_reward_rate: 3_0000,
+            base_reward_rate_decay: 100_0000,
+            min_base_reward_rate: 1_0000,
+        };
+        // 3 (0.99)^e falls below 1 between epochs 109 and 110.
+        assert!(schedule.base_reward_rate(109) > 1_0000);
+        assert_eq!(schedule.base_reward_rate(110), 1_0000);
+        assert_eq!(schedule.base_reward_rate(u64::MAX), 1_0000);
+
+        let supplies = supplies(&schedule);
+        let (before, after) = (supplies[999], supplies[EPOCHS as usize - 1]);
+        let expected = before * (1.0 + 1e-4f64).powi(EPOCHS as i32 - 1000);
+        assert!(after <= expected * (1.0 + 1e-12));
+        assert!(expected - after <= rounding_allowance(EPOCHS - 1000));
+    }
+
+    #[test]
+    fn total_decay_goes_to_floor() {
+        let schedule = IssuanceSchedule {
+            initial_base_reward_rate: 3_0000,
+            base_reward_rate_decay: 2_0000_0000,
+            min_base_reward_rate: 5,
+        };
+        assert_eq!(schedule.base_reward_rate(0), 3_0000);
+        assert_eq!(schedule.base_reward_rate(1), 5);
+    }
+}
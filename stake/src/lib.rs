@@ -3,24 +3,29 @@ use once_cell::sync::Lazy;
 use penumbra_crypto::asset;
 
 mod changes;
+mod commission;
 mod delegate;
 mod epoch;
 mod funding_stream;
 mod identity_key;
 mod info;
+mod issuance;
 mod rate;
 mod status;
 mod token;
 mod undelegate;
 mod validator;
+mod validator_set;
 mod validator_state;
 
 pub use changes::{DelegationChanges, PendingRewardNote, RewardNotes};
+pub use commission::{CommissionPayouts, FundingStreamPayout};
 pub use delegate::Delegate;
 pub use epoch::Epoch;
 pub use funding_stream::FundingStream;
 pub use identity_key::IdentityKey;
 pub use info::ValidatorInfo;
+pub use issuance::IssuanceSchedule;
 pub use rate::{BaseRateData, RateData, RateDataById};
 pub use status::ValidatorStatus;
 pub use token::DelegationToken;
@@ -28,6 +33,7 @@ pub use undelegate::Undelegate;
 pub use validator::{
     FundingStreams, Validator, ValidatorDefinition, ValidatorList, VerifiedValidatorDefinition,
 };
+pub use validator_set::{ValidatorSet, ValidatorSetEntry};
 pub use validator_state::{ValidatorState, ValidatorStateName};
 
 /// The Bech32 prefix used for validator consensus pubkeys.
@@ -6,7 +6,7 @@ use penumbra_proto::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{FundingStream, IdentityKey, ValidatorState};
+use crate::{FundingStream, IdentityKey, IssuanceSchedule, ValidatorState};
 
 pub type RateDataById = BTreeMap<IdentityKey, RateData>;
 
@@ -170,8 +170,9 @@ pub struct BaseRateData {
 
 impl BaseRateData {
     /// Compute the base rate data for the epoch following the current one,
-    /// given the next epoch's base reward rate.
-    pub fn next(&self, base_reward_rate: u64) -> BaseRateData {
+    /// whose base reward rate is set by the issuance schedule.
+    pub fn next(&self, schedule: &IssuanceSchedule) -> BaseRateData {
+        let base_reward_rate = schedule.base_reward_rate(self.epoch_index + 1);
         let base_exchange_rate =
             (self.base_exchange_rate * (base_reward_rate + 1_0000_0000)) / 1_0000_0000;
         BaseRateData {
@@ -0,0 +1,73 @@
+use penumbra_proto::{stake as pb, Protobuf};
+use serde::{Deserialize, Serialize};
+
+use crate::IdentityKey;
+
+/// The active validator set as of the end of some block, i.e., the validators
+/// whose voting power was reported to Tendermint at that height.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "pb::ValidatorSet", into = "pb::ValidatorSet")]
+pub struct ValidatorSet(pub Vec<ValidatorSetEntry>);
+
+/// A member of a [`ValidatorSet`].
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(try_from = "pb::ValidatorSetEntry", into = "pb::ValidatorSetEntry")]
+pub struct ValidatorSetEntry {
+    /// The validator's identity.
+    pub identity_key: IdentityKey,
+    /// The key the validator signs blocks with.
+    pub consensus_key: tendermint::PublicKey,
+    /// The validator's voting power.
+    pub power: u64,
+}
+
+impl Protobuf<pb::ValidatorSet> for ValidatorSet {}
+
+impl From<ValidatorSet> for pb::ValidatorSet {
+    fn from(set: ValidatorSet) -> Self {
+        pb::ValidatorSet {
+            validators: set.0.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TryFrom<pb::ValidatorSet> for ValidatorSet {
+    type Error = anyhow::Error;
+
+    fn try_from(msg: pb::ValidatorSet) -> Result<Self, Self::Error> {
+        Ok(ValidatorSet(
+            msg.validators
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        ))
+    }
+}
+
+impl Protobuf<pb::ValidatorSetEntry> for ValidatorSetEntry {}
+
+impl From<ValidatorSetEntry> for pb::ValidatorSetEntry {
+    fn from(entry: ValidatorSetEntry) -> Self {
+        pb::ValidatorSetEntry {
+            identity_key: Some(entry.identity_key.into()),
+            consensus_key: entry.consensus_key.to_bytes(),
+            power: entry.power,
+        }
+    }
+}
+
+impl TryFrom<pb::ValidatorSetEntry> for ValidatorSetEntry {
+    type Error = anyhow::Error;
+
+    fn try_from(msg: pb::ValidatorSetEntry) -> Result<Self, Self::Error> {
+        Ok(ValidatorSetEntry {
+            identity_key: msg
+                .identity_key
+                .ok_or_else(|| anyhow::anyhow!("missing identity key field in proto"))?
+                .try_into()?,
+            consensus_key: tendermint::PublicKey::from_raw_ed25519(&msg.consensus_key)
+                .ok_or_else(|| anyhow::anyhow!("invalid ed25519 consensus pubkey"))?,
+            power: msg.power,
+        })
+    }
+}
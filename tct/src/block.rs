@@ -145,6 +145,31 @@ impl Block {
         Self::default()
     }
 
+    /// Create a new [`Block`] containing all the given [`Commitment`]s, in order.
+    ///
+    /// This is equivalent to [`insert`](Block::insert)ing each commitment in turn into a new
+    /// [`Block`], but allocates the block's index once, up front.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InsertError`] if there are more commitments than fit in a single block.
+    pub fn from_commitments(commitments: &[(Commitment, Witness)]) -> Result<Self, InsertError> {
+        let kept = commitments
+            .iter()
+            .filter(|(_, witness)| *witness == Keep)
+            .count();
+
+        let mut block = Block {
+            index: HashedMap::with_capacity_and_hasher(kept, Default::default()),
+            ..Default::default()
+        };
+        for &(commitment, witness) in commitments {
+            block.insert(witness, commitment)?;
+        }
+
+        Ok(block)
+    }
+
     /// Get a [`BlockMut`] from this [`Block`].
     pub(super) fn as_mut(&mut self) -> BlockMut {
         BlockMut {
@@ -246,6 +271,14 @@ impl Block {
         self.index.get(&commitment).map(|index| Position(*index))
     }
 
+    /// The witnessed [`Commitment`]s in this [`Block`], with their positions, in no particular
+    /// order.
+    pub(crate) fn witnessed(&self) -> impl Iterator<Item = (Commitment, Position)> + '_ {
+        self.index
+            .iter()
+            .map(|(&commitment, &index)| (commitment, Position(index)))
+    }
+
     /// The number of [`Commitment`]s currently witnessed in this [`Block`].
     ///
     /// Note that [`forget`](Block::forget)ting a commitment decreases this count, but does not
@@ -386,4 +419,26 @@ mod test {
     fn insert_error_sync_send() {
         static_assertions::assert_impl_all!(InsertError: Sync, Send);
     }
+
+    #[test]
+    fn from_commitments_matches_insert() {
+        let commitments: Vec<_> = (0u64..10)
+            .map(|i| {
+                let witness = if i % 3 == 0 { Forget } else { Keep };
+                (Commitment(i.into()), witness)
+            })
+            .collect();
+
+        let mut expected = Block::new();
+        for &(commitment, witness) in &commitments {
+            expected.insert(witness, commitment).unwrap();
+        }
+
+        let block = Block::from_commitments(&commitments).unwrap();
+        assert_eq!(block.root(), expected.root());
+        assert_eq!(block.position(), expected.position());
+        assert_eq!(block.witnessed_count(), 6);
+        assert!(block.witness(Commitment(1u64.into())).is_some());
+        assert!(block.witness(Commitment(3u64.into())).is_none());
+    }
 }
@@ -139,6 +139,32 @@ impl Epoch {
         Self::default()
     }
 
+    /// Create a new [`Epoch`] containing all the given [`Block`]s, in order.
+    ///
+    /// This is equivalent to [`insert_block`](Epoch::insert_block)ing each block in turn into a
+    /// new [`Epoch`], but allocates the epoch's index once, up front, rather than growing it with
+    /// each block. Together with [`Block::from_commitments`], this builds an entire epoch in one
+    /// call.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InsertBlockError`] containing the first block which did not fit if there are
+    /// more blocks than fit in a single epoch.
+    pub fn from_blocks(blocks: impl IntoIterator<Item = Block>) -> Result<Self, InsertBlockError> {
+        let blocks: Vec<Block> = blocks.into_iter().collect();
+        let witnessed = blocks.iter().map(Block::witnessed_count).sum();
+
+        let mut epoch = Epoch {
+            index: HashedMap::with_capacity_and_hasher(witnessed, Default::default()),
+            ..Default::default()
+        };
+        for block in blocks {
+            epoch.insert_block(block)?;
+        }
+
+        Ok(epoch)
+    }
+
     /// Get an [`EpochMut`] referring to this [`Epoch`].
     pub(super) fn as_mut(&mut self) -> EpochMut {
         EpochMut {
@@ -521,3 +547,34 @@ impl EpochMut<'_> {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_blocks_matches_insert_block() {
+        let blocks: Vec<_> = (0u64..4)
+            .map(|b| {
+                let commitments: Vec<_> = (0u64..5)
+                    .map(|i| (Commitment((b * 5 + i).into()), Keep))
+                    .collect();
+                Block::from_commitments(&commitments).unwrap()
+            })
+            .collect();
+
+        let mut expected = Epoch::new();
+        for block in blocks.clone() {
+            expected.insert_block(block).unwrap();
+        }
+
+        let epoch = Epoch::from_blocks(blocks).unwrap();
+        assert_eq!(epoch.root(), expected.root());
+        assert_eq!(epoch.position(), expected.position());
+        assert_eq!(epoch.witnessed_count(), 20);
+        assert_eq!(
+            epoch.position_of(Commitment(7u64.into())),
+            expected.position_of(Commitment(7u64.into()))
+        );
+    }
+}
@@ -13,12 +13,26 @@ use crate::*;
 pub(crate) mod epoch;
 use epoch::{block, block::Block, Epoch, EpochMut};
 
+mod codec;
 mod proof;
+#[cfg(feature = "async")]
+mod stream;
 pub use proof::Proof;
 
+mod retention;
+pub use retention::RetentionPolicy;
+
+mod observer;
+use observer::Observer;
+pub use observer::TreeObserver;
+
+mod shared;
+pub use shared::{Reader, SharedEternity};
+
 pub mod error;
 pub use error::{
-    InsertBlockError, InsertBlockRootError, InsertEpochError, InsertEpochRootError, InsertError,
+    DecodeError, InsertBlockError, InsertBlockRootError, InsertCompactBlockError, InsertEpochError,
+    InsertEpochRootError, InsertError, PositionError,
 };
 
 /// A sparse merkle tree to witness up to 65,536 [`Epoch`]s, each witnessing up to 65,536
@@ -28,6 +42,8 @@ pub struct Eternity {
     position: index::within::Eternity,
     index: HashedMap<Commitment, index::within::Eternity>,
     inner: Tier<Tier<Tier<Item>>>,
+    #[serde(skip)]
+    observer: Observer,
 }
 
 /// The root hash of an [`Eternity`].
@@ -92,6 +108,71 @@ impl Position {
     pub fn epoch(&self) -> u16 {
         self.0.epoch.into()
     }
+
+    /// The [`Position`] of the given commitment index within the given block of the given epoch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the indices is not representable in 16 bits.
+    pub fn new(epoch: u64, block: u64, commitment: u64) -> Result<Position, PositionError> {
+        let epoch = u16::try_from(epoch).map_err(|_| PositionError::EpochOutOfRange(epoch))?;
+        let block = u16::try_from(block).map_err(|_| PositionError::BlockOutOfRange(block))?;
+        let commitment = u16::try_from(commitment)
+            .map_err(|_| PositionError::CommitmentOutOfRange(commitment))?;
+
+        Ok(Position(index::within::Eternity {
+            epoch: epoch.into(),
+            block: block.into(),
+            commitment: commitment.into(),
+        }))
+    }
+
+    /// The [`Position`] of the first commitment in the given epoch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the epoch index is not representable in 16 bits.
+    pub fn start_of_epoch(epoch: u64) -> Result<Position, PositionError> {
+        Position::new(epoch, 0, 0)
+    }
+
+    /// The [`Position`] of the first commitment in the given block of the given epoch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either index is not representable in 16 bits.
+    pub fn start_of_block(epoch: u64, block: u64) -> Result<Position, PositionError> {
+        Position::new(epoch, block, 0)
+    }
+
+    /// The [`Position`] of the first commitment in the block at the given chain height, which
+    /// falls within the epoch with the given index that began at `epoch_start_height`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the height is before the start of the epoch, or if the epoch or block
+    /// index is not representable in 16 bits.
+    pub fn start_of_height(
+        height: u64,
+        epoch_start_height: u64,
+        epoch_index: u64,
+    ) -> Result<Position, PositionError> {
+        let block = height.checked_sub(epoch_start_height).ok_or(
+            PositionError::HeightBeforeEpochStart {
+                height,
+                epoch_start_height,
+            },
+        )?;
+        Position::start_of_block(epoch_index, block)
+    }
+
+    /// The chain height of the block to which this [`Position`] refers, given the height at which
+    /// its epoch began.
+    ///
+    /// This is the inverse of [`Position::start_of_height`], ignoring the commitment index.
+    pub fn height(&self, epoch_start_height: u64) -> u64 {
+        epoch_start_height + self.block() as u64
+    }
 }
 
 impl From<Position> for u64 {
@@ -147,10 +228,14 @@ impl Eternity {
         witness: Witness,
         commitment: impl Into<Commitment>,
     ) -> Result<Position, InsertError> {
-        self.insert_commitment_or_hash(match witness {
-            Keep => Insert::Keep(commitment.into()),
-            Forget => Insert::Hash(Hash::of(commitment.into())),
-        })
+        let commitment = commitment.into();
+        let position = self.insert_commitment_or_hash(match witness {
+            Keep => Insert::Keep(commitment),
+            Forget => Insert::Hash(Hash::of(commitment)),
+        })?;
+        self.observer
+            .notify(|observer| observer.inserted(position, commitment, witness));
+        Ok(position)
     }
 
     /// Get a [`Proof`] of inclusion for the commitment at this index in the eternity.
@@ -194,6 +279,8 @@ impl Eternity {
             debug_assert!(forgotten);
             // Remove this entry from the index
             self.index.remove(&commitment);
+            self.observer
+                .notify(|observer| observer.forgotten(Position(within_epoch), commitment));
         }
 
         forgotten
@@ -207,6 +294,12 @@ impl Eternity {
         // The position at which we will insert the commitment
         let position = self.position();
 
+        // The commitment, if it is witnessed, which may replace an earlier witness of itself
+        let kept = match &commitment {
+            Insert::Keep(commitment) => Some(*commitment),
+            Insert::Hash(_) => None,
+        };
+
         // If the eternity is empty, we need to create a new epoch to insert the commitment into
         if self.inner.is_empty() && self.insert_epoch(Epoch::new()).is_err() {
             return Err(InsertError::Full);
@@ -229,6 +322,10 @@ impl Eternity {
                 // If inserting this commitment replaced some other commitment, forget the replaced index
                 let forgotten = self.inner.forget(replaced);
                 debug_assert!(forgotten);
+                if let Some(commitment) = kept {
+                    self.observer
+                        .notify(|observer| observer.forgotten(Position(replaced), commitment));
+                }
                 Ok(position)
             }
         }
@@ -249,6 +346,9 @@ impl Eternity {
     /// [`Eternity`] if the [`Eternity`] is full, or the most recently inserted [`Epoch`] is full or
     /// was inserted by [`Insert::Hash`].
     pub fn insert_block(&mut self, block: Block) -> Result<(), InsertBlockError> {
+        let finalized = self.finalizing_block();
+        let inserting = self.inserting(block.witnessed().map(|(commitment, _)| commitment));
+
         // If the eternity is empty, we need to create a new epoch to insert the block into
         if self.inner.is_empty() && self.insert_epoch(Epoch::new()).is_err() {
             return Err(InsertBlockError::Full(block));
@@ -279,6 +379,7 @@ impl Eternity {
                     let forgotten = self.inner.forget(replaced);
                     debug_assert!(forgotten);
                 }
+                self.notify_inserted_all(finalized, None, inserting);
                 Ok(())
             }
         }
@@ -295,6 +396,8 @@ impl Eternity {
         &mut self,
         block_root: block::Root,
     ) -> Result<(), InsertBlockRootError> {
+        let finalized = self.finalizing_block();
+
         // If the eternity is empty, we need to create a new epoch to insert the block into
         if self.inner.is_empty() && self.insert_epoch(Epoch::new()).is_err() {
             return Err(InsertBlockRootError::Full);
@@ -317,11 +420,62 @@ impl Eternity {
                     let forgotten = self.inner.forget(replaced);
                     debug_assert!(forgotten);
                 }
+                self.notify_inserted_all(finalized, None, Vec::new());
                 Ok(())
             }
         }
     }
 
+    /// Add a whole block to the most recently inserted [`Epoch`] of this [`Eternity`], given only
+    /// the data a client receives in a compact block, so that the tree advances exactly as the
+    /// chain's did.
+    ///
+    /// The `block_root` is the root of the block as the chain computed it, `total_commitments` is
+    /// the number of commitments in the block, and `commitments` are those commitments in order,
+    /// each marked with whether to [`Keep`] it.
+    ///
+    /// If no commitment is to be kept, `commitments` may be empty: the block is inserted by its
+    /// root alone, as a single forgotten subtree, without hashing anything. Otherwise, every
+    /// commitment in the block must be given, so that the kept ones can be witnessed, and the block
+    /// built from them must have the given root.
+    ///
+    /// Either way, the next block starts at the next block index, even if this one was empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InsertCompactBlockError`] without changing the [`Eternity`] if the [`Eternity`] is
+    /// full, the most recently inserted [`Epoch`] is full or was forgotten, or the commitments
+    /// given do not make up a block with the given root.
+    pub fn insert_compact_block(
+        &mut self,
+        block_root: block::Root,
+        commitments: &[(Commitment, Witness)],
+        total_commitments: usize,
+    ) -> Result<(), InsertCompactBlockError> {
+        let keeping = commitments.iter().any(|(_, witness)| *witness == Keep);
+        if !keeping {
+            return Ok(self.insert_block_root(block_root)?);
+        }
+
+        if commitments.len() != total_commitments {
+            return Err(InsertCompactBlockError::MissingCommitments {
+                total: total_commitments,
+                given: commitments.len(),
+            });
+        }
+        let block = Block::from_commitments(commitments)
+            .map_err(|_| InsertCompactBlockError::TooManyCommitments(commitments.len()))?;
+        let actual = block.root();
+        if actual != block_root {
+            return Err(InsertCompactBlockError::RootMismatch {
+                expected: block_root,
+                actual,
+            });
+        }
+
+        Ok(self.insert_block(block)?)
+    }
+
     /// Get the root hash of the most recent [`Block`] in the most recent [`Epoch`] of this
     /// [`Eternity`].
     ///
@@ -380,6 +534,13 @@ impl Eternity {
         // increment the epoch index
         let was_empty = self.inner.is_empty();
 
+        let finalized_block = self.finalizing_block();
+        let finalized_epoch = self.finalizing_epoch();
+        let inserting = match &epoch {
+            Insert::Keep(epoch) => self.inserting(epoch.index.keys().copied()),
+            Insert::Hash(_) => Vec::new(),
+        };
+
         // Decompose the block into its components
         let (position, epoch, epoch_index) = match epoch {
             Insert::Hash(hash) => (
@@ -437,6 +598,7 @@ impl Eternity {
                 }
             }
 
+            self.notify_inserted_all(finalized_block, finalized_epoch, inserting);
             Ok(())
         }
     }
@@ -504,3 +666,145 @@ impl Eternity {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn height_round_trip(epoch_start_height in 0u64..=1 << 32, epoch in 0u64..=u16::MAX as u64, block in 0u64..=u16::MAX as u64) {
+            let height = epoch_start_height + block;
+            let position = Position::start_of_height(height, epoch_start_height, epoch).unwrap();
+            prop_assert_eq!(position.epoch() as u64, epoch);
+            prop_assert_eq!(position.commitment(), 0);
+            prop_assert_eq!(position.height(epoch_start_height), height);
+        }
+    }
+
+    fn commitment(i: u64) -> Commitment {
+        Commitment::from(Fq::from(i))
+    }
+
+    #[test]
+    fn compact_blocks_advance_like_the_chain() {
+        let blocks: Vec<Vec<Commitment>> = vec![
+            (0..3).map(commitment).collect(),
+            vec![],
+            (3..7).map(commitment).collect(),
+        ];
+        let mine = commitment(5);
+
+        let mut chain = Eternity::new();
+        let mut wallet = Eternity::new();
+        for commitments in &blocks {
+            let all: Vec<_> = commitments.iter().map(|c| (*c, Keep)).collect();
+            let block = Block::from_commitments(&all).unwrap();
+            let root = block.root();
+            chain.insert_block(block).unwrap();
+
+            let kept: Vec<_> = commitments
+                .iter()
+                .map(|c| (*c, if *c == mine { Keep } else { Forget }))
+                .collect();
+            let given = if commitments.contains(&mine) {
+                &kept[..]
+            } else {
+                &[]
+            };
+            wallet
+                .insert_compact_block(root, given, commitments.len())
+                .unwrap();
+            assert_eq!(wallet.root(), chain.root());
+            assert_eq!(wallet.position(), chain.position());
+        }
+
+        assert_eq!(wallet.witnessed_count(), 1);
+        assert_eq!(wallet.position_of(mine), chain.position_of(mine));
+        assert!(wallet.witness(mine).is_some());
+    }
+
+    #[test]
+    fn compact_block_must_match_its_root() {
+        let mut wallet = Eternity::new();
+        let given = [(commitment(0), Keep), (commitment(1), Forget)];
+        let root = Block::from_commitments(&given).unwrap().root();
+
+        assert_eq!(
+            wallet.insert_compact_block(root, &given[..1], 2),
+            Err(InsertCompactBlockError::MissingCommitments { total: 2, given: 1 })
+        );
+        let other = Block::from_commitments(&given[..1]).unwrap().root();
+        assert_eq!(
+            wallet.insert_compact_block(other, &given, 2),
+            Err(InsertCompactBlockError::RootMismatch {
+                expected: other,
+                actual: root
+            })
+        );
+        assert!(wallet.is_empty());
+    }
+
+    #[test]
+    fn compact_block_keeping_nothing_is_inserted_by_root() {
+        let all: Vec<_> = (0..4).map(|i| (commitment(i), Keep)).collect();
+        let block = Block::from_commitments(&all).unwrap();
+        let root = block.root();
+        let mut chain = Eternity::new();
+        chain.insert_block(block).unwrap();
+
+        // Commitments which are all forgotten are not checked against the root, even if some are
+        // missing, since the block is inserted by its root alone.
+        let forgotten: Vec<_> = (0..4).map(|i| (commitment(i), Forget)).collect();
+        let mut wallet = Eternity::new();
+        wallet
+            .insert_compact_block(root, &forgotten[..2], 4)
+            .unwrap();
+        assert_eq!(wallet.root(), chain.root());
+        assert_eq!(wallet.witnessed_count(), 0);
+    }
+
+    #[test]
+    fn compact_block_needs_a_current_epoch() {
+        let given = [(commitment(0), Keep)];
+        let root = Block::from_commitments(&given).unwrap().root();
+
+        let mut wallet = Eternity::new();
+        wallet.insert_epoch_root(Epoch::new().root()).unwrap();
+        let before = wallet.root();
+        assert_eq!(
+            wallet.insert_compact_block(root, &given, 1),
+            Err(InsertCompactBlockError::EpochForgotten)
+        );
+        assert_eq!(wallet.root(), before);
+        assert_eq!(wallet.witnessed_count(), 0);
+    }
+
+    #[test]
+    fn out_of_range_indices() {
+        assert_eq!(
+            Position::start_of_epoch(1 << 16),
+            Err(PositionError::EpochOutOfRange(1 << 16))
+        );
+        assert_eq!(
+            Position::start_of_block(0, 1 << 16),
+            Err(PositionError::BlockOutOfRange(1 << 16))
+        );
+        assert_eq!(
+            Position::start_of_height(9, 10, 1),
+            Err(PositionError::HeightBeforeEpochStart {
+                height: 9,
+                epoch_start_height: 10,
+            })
+        );
+        assert_eq!(
+            Position::start_of_height(10 + (1 << 16), 10, 1),
+            Err(PositionError::BlockOutOfRange(1 << 16))
+        );
+        assert_eq!(
+            Position::start_of_height(10, 10, 1 << 16),
+            Err(PositionError::EpochOutOfRange(1 << 16))
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! A compact binary encoding for [`Eternity`]s, with a choice of how much to trust the input.
+
+use super::{error::DecodeError, Eternity, Position, Root};
+use crate::{
+    internal::{
+        encode::{self, Encode, Pieces},
+        path::Witness as _,
+    },
+    Commitment, Hash,
+};
+
+impl Encode for Eternity {
+    fn pieces(&self) -> Pieces<'_> {
+        let index = encode::seq(self.index.iter().map(|entry| encode::one(&entry)));
+        Box::new(
+            encode::one(&self.position)
+                .chain(index)
+                .chain(self.inner.pieces()),
+        )
+    }
+}
+
+impl Eternity {
+    /// Encode this [`Eternity`] as bytes, suitable for decoding with
+    /// [`from_bytes_trusted`](Eternity::from_bytes_trusted) or
+    /// [`from_bytes_verified`](Eternity::from_bytes_verified).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("serializing an eternity cannot fail")
+    }
+
+    /// Decode an [`Eternity`] from bytes without checking its internal consistency.
+    ///
+    /// This is fast, but should only be used for bytes produced by [`to_bytes`](Eternity::to_bytes)
+    /// from a trusted source (such as the node's own storage): a malicious encoding can produce a
+    /// tree which panics or returns incorrect witnesses.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DecodeError::Malformed`] if the bytes are not the encoding of any [`Eternity`].
+    pub fn from_bytes_trusted(bytes: &[u8]) -> Result<Self, DecodeError> {
+        bincode::deserialize(bytes).map_err(|e| DecodeError::Malformed(e.to_string()))
+    }
+
+    /// Decode an [`Eternity`] from bytes, checking that it is internally consistent and that its
+    /// root hash is `expected_root`.
+    ///
+    /// Every indexed commitment is checked to be witnessed at its indexed position, which requires
+    /// computing every internal hash of the tree, so this is considerably slower than
+    /// [`from_bytes_trusted`](Eternity::from_bytes_trusted).
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DecodeError`] describing the first inconsistency found.
+    pub fn from_bytes_verified(bytes: &[u8], expected_root: Root) -> Result<Self, DecodeError> {
+        let eternity = Self::from_bytes_trusted(bytes)?;
+
+        let end = u64::from(eternity.position);
+        for (&commitment, &index) in eternity.index.iter() {
+            let position = Position(index);
+            if u64::from(index) >= end {
+                return Err(DecodeError::PositionOutOfBounds {
+                    commitment,
+                    position,
+                });
+            }
+            match eternity.inner.witness(index) {
+                None => {
+                    return Err(DecodeError::NotWitnessed {
+                        commitment,
+                        position,
+                    })
+                }
+                Some((_, leaf)) if leaf != Hash::of(commitment) => {
+                    return Err(DecodeError::WrongLeaf {
+                        commitment,
+                        position,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        let actual = eternity.root();
+        if actual != expected_root {
+            return Err(DecodeError::RootMismatch {
+                expected: expected_root,
+                actual,
+            });
+        }
+
+        Ok(eternity)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{internal::index, Block, Epoch, Witness};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn round_trip_verified(commitments in prop::collection::vec(any::<Commitment>(), 0..32)) {
+            let mut eternity = Eternity::new();
+            for (i, commitment) in commitments.into_iter().enumerate() {
+                let witness = if i % 2 == 0 { Witness::Keep } else { Witness::Forget };
+                let _ = eternity.insert(witness, commitment);
+            }
+
+            let bytes = eternity.to_bytes();
+            assert_eq!(Eternity::from_bytes_trusted(&bytes).unwrap(), eternity);
+            assert_eq!(Eternity::from_bytes_verified(&bytes, eternity.root()).unwrap(), eternity);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn pieces_are_the_whole_encoding(
+            commitments in prop::collection::vec(any::<Commitment>(), 0..32)
+        ) {
+            let mut eternity = Eternity::new();
+            for (i, commitment) in commitments.into_iter().enumerate() {
+                let witness = if i % 3 == 0 { Witness::Forget } else { Witness::Keep };
+                let _ = eternity.insert(witness, commitment);
+                if i % 5 == 4 {
+                    let _ = eternity.insert_block(Block::new());
+                }
+                if i % 13 == 12 {
+                    let _ = eternity.insert_epoch(Epoch::new());
+                }
+            }
+
+            assert_eq!(eternity.pieces().flatten().collect::<Vec<_>>(), eternity.to_bytes());
+        }
+    }
+
+    /// An eternity witnessing two commitments, with a forgotten one between them.
+    fn witnessed() -> (Eternity, [Commitment; 2]) {
+        let kept = [Commitment(1u64.into()), Commitment(3u64.into())];
+        let mut eternity = Eternity::new();
+        eternity.insert(Witness::Keep, kept[0]).unwrap();
+        eternity
+            .insert(Witness::Forget, Commitment(2u64.into()))
+            .unwrap();
+        eternity.insert(Witness::Keep, kept[1]).unwrap();
+        (eternity, kept)
+    }
+
+    /// Re-encodes `eternity` with `commitment` indexed at `index`, as a
+    /// tampered encoding would.
+    fn reindexed(
+        eternity: &Eternity,
+        commitment: Commitment,
+        index: index::within::Eternity,
+    ) -> Vec<u8> {
+        let mut tampered = eternity.clone();
+        tampered.index.insert(commitment, index);
+        bincode::serialize(&tampered).unwrap()
+    }
+
+    #[test]
+    fn verified_rejects_flipped_commitment() {
+        let (eternity, [first, _]) = witnessed();
+        let mut tampered = eternity.clone();
+        let index = tampered.index.remove(&first).unwrap();
+        let flipped = Commitment(4u64.into());
+        tampered.index.insert(flipped, index);
+
+        assert!(matches!(
+            Eternity::from_bytes_verified(&bincode::serialize(&tampered).unwrap(), eternity.root()),
+            Err(DecodeError::WrongLeaf { commitment, .. }) if commitment == flipped
+        ));
+    }
+
+    #[test]
+    fn verified_rejects_moved_index() {
+        let (eternity, [first, second]) = witnessed();
+        let root = eternity.root();
+
+        // Moved onto another witnessed commitment...
+        let other = eternity.index[&second];
+        assert!(matches!(
+            Eternity::from_bytes_verified(&reindexed(&eternity, first, other), root),
+            Err(DecodeError::WrongLeaf { .. })
+        ));
+        // ... onto the forgotten commitment...
+        let forgotten = index::within::Eternity::from(u64::from(eternity.index[&first]) + 1);
+        assert!(matches!(
+            Eternity::from_bytes_verified(&reindexed(&eternity, first, forgotten), root),
+            Err(DecodeError::NotWitnessed { .. })
+        ));
+        // ... or past the end of the tree.
+        assert!(matches!(
+            Eternity::from_bytes_verified(&reindexed(&eternity, first, eternity.position), root),
+            Err(DecodeError::PositionOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn verified_rejects_truncated_structure() {
+        let (eternity, _) = witnessed();
+        let bytes = eternity.to_bytes();
+        for len in 0..bytes.len() {
+            assert!(
+                Eternity::from_bytes_verified(&bytes[..len], eternity.root()).is_err(),
+                "accepted the first {} of {} bytes",
+                len,
+                bytes.len()
+            );
+        }
+    }
+
+    #[test]
+    fn verified_never_panics_on_corrupted_bytes() {
+        let (eternity, _) = witnessed();
+        let bytes = eternity.to_bytes();
+        for i in 0..bytes.len() {
+            for flip in [0x01, 0x80, 0xff] {
+                let mut corrupted = bytes.clone();
+                corrupted[i] ^= flip;
+                // Whether or not the corruption is detected, decoding must
+                // return rather than panic.
+                let _ = Eternity::from_bytes_verified(&corrupted, eternity.root());
+            }
+        }
+    }
+
+    #[test]
+    fn verified_rejects_wrong_root() {
+        let eternity = Eternity::new();
+        let wrong = Root(Hash::of(Commitment(1u64.into())));
+        assert!(matches!(
+            Eternity::from_bytes_verified(&eternity.to_bytes(), wrong),
+            Err(DecodeError::RootMismatch { .. })
+        ));
+    }
+}
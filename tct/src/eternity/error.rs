@@ -1,10 +1,11 @@
-//! Errors that can occur when inserting into an [`Eternity`].
+//! Errors that can occur when inserting into or decoding an [`Eternity`].
 
 use thiserror::Error;
 
 #[cfg(doc)]
 use super::Eternity;
-use super::{Block, Epoch};
+use super::{block, Block, Epoch, Position, Root};
+use crate::Commitment;
 
 /// An error occurred when trying to insert an commitment into an [`Eternity`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
@@ -70,6 +71,59 @@ pub enum InsertBlockRootError {
     EpochForgotten,
 }
 
+/// An error occurred when trying to insert a compact block into the [`Eternity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum InsertCompactBlockError {
+    /// The [`Eternity`] was full.
+    #[error("eternity is full")]
+    Full,
+    /// The most recent [`Epoch`] of the [`Eternity`] was full.
+    #[error("most recent epoch is full")]
+    EpochFull,
+    /// The most recent [`Epoch`] of the [`Eternity`] was forgotten.
+    #[error("most recent epoch was forgotten")]
+    EpochForgotten,
+    /// There were more commitments than fit in a single [`Block`].
+    #[error("{0} commitments do not fit in a single block")]
+    TooManyCommitments(usize),
+    /// Some commitments were to be kept, but not every commitment in the block was given.
+    #[error("block has {total} commitments, but {given} were given")]
+    MissingCommitments {
+        /// The number of commitments in the block.
+        total: usize,
+        /// The number of commitments given.
+        given: usize,
+    },
+    /// The root of the block built from the given commitments was not the expected root.
+    #[error("block built from the given commitments has root {actual}, but expected {expected}")]
+    RootMismatch {
+        /// The root of the block, as the chain computed it.
+        expected: block::Root,
+        /// The root of the block built from the given commitments.
+        actual: block::Root,
+    },
+}
+
+impl From<InsertBlockError> for InsertCompactBlockError {
+    fn from(error: InsertBlockError) -> Self {
+        match error {
+            InsertBlockError::Full(_) => InsertCompactBlockError::Full,
+            InsertBlockError::EpochFull(_) => InsertCompactBlockError::EpochFull,
+            InsertBlockError::EpochForgotten(_) => InsertCompactBlockError::EpochForgotten,
+        }
+    }
+}
+
+impl From<InsertBlockRootError> for InsertCompactBlockError {
+    fn from(error: InsertBlockRootError) -> Self {
+        match error {
+            InsertBlockRootError::Full => InsertCompactBlockError::Full,
+            InsertBlockRootError::EpochFull => InsertCompactBlockError::EpochFull,
+            InsertBlockRootError::EpochForgotten => InsertCompactBlockError::EpochForgotten,
+        }
+    }
+}
+
 /// The [`Eternity`] was full when trying to insert an [`Epoch`].
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
 #[error("eternity is full")]
@@ -88,6 +142,73 @@ impl From<InsertEpochError> for Epoch {
 #[non_exhaustive]
 pub struct InsertEpochRootError;
 
+/// An error occurred when decoding an [`Eternity`] from bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DecodeError {
+    /// The bytes were not a valid encoding of any [`Eternity`].
+    #[error("malformed eternity encoding: {0}")]
+    Malformed(String),
+    /// The encoding could not be read.
+    #[error("failed to read eternity encoding: {0}")]
+    Io(String),
+    /// The index referred to a position at or after the next position to be inserted.
+    #[error("commitment {commitment:?} is indexed at {position:?}, past the end of the eternity")]
+    PositionOutOfBounds {
+        /// The commitment whose index entry was invalid.
+        commitment: Commitment,
+        /// The position it was indexed at.
+        position: Position,
+    },
+    /// The index referred to a position which is not witnessed in the tree.
+    #[error("commitment {commitment:?} is indexed at {position:?}, which is not witnessed")]
+    NotWitnessed {
+        /// The commitment whose index entry was invalid.
+        commitment: Commitment,
+        /// The position it was indexed at.
+        position: Position,
+    },
+    /// The leaf witnessed at an indexed position did not match the indexed commitment.
+    #[error(
+        "commitment {commitment:?} is indexed at {position:?}, which witnesses a different leaf"
+    )]
+    WrongLeaf {
+        /// The commitment whose index entry was invalid.
+        commitment: Commitment,
+        /// The position it was indexed at.
+        position: Position,
+    },
+    /// The root of the decoded tree was not the expected root.
+    #[error("decoded eternity has root {actual}, but expected {expected}")]
+    RootMismatch {
+        /// The root the caller expected.
+        expected: Root,
+        /// The root of the decoded tree.
+        actual: Root,
+    },
+}
+
+/// A [`Position`] could not be constructed because an index was out of range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum PositionError {
+    /// The epoch index was greater than the maximum of 65,535.
+    #[error("epoch index {0} is out of range")]
+    EpochOutOfRange(u64),
+    /// The block index was greater than the maximum of 65,535.
+    #[error("block index {0} is out of range")]
+    BlockOutOfRange(u64),
+    /// The commitment index was greater than the maximum of 65,535.
+    #[error("commitment index {0} is out of range")]
+    CommitmentOutOfRange(u64),
+    /// The height was before the start of the epoch it was said to fall within.
+    #[error("height {height} is before the start of its epoch at height {epoch_start_height}")]
+    HeightBeforeEpochStart {
+        /// The height of the block.
+        height: u64,
+        /// The height at which the epoch began.
+        epoch_start_height: u64,
+    },
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -97,7 +218,10 @@ mod test {
         static_assertions::assert_impl_all!(InsertError: Sync, Send);
         static_assertions::assert_impl_all!(InsertBlockError: Sync, Send);
         static_assertions::assert_impl_all!(InsertBlockRootError: Sync, Send);
+        static_assertions::assert_impl_all!(InsertCompactBlockError: Sync, Send);
         static_assertions::assert_impl_all!(InsertEpochError: Sync, Send);
         static_assertions::assert_impl_all!(InsertEpochRootError: Sync, Send);
+        static_assertions::assert_impl_all!(DecodeError: Sync, Send);
+        static_assertions::assert_impl_all!(PositionError: Sync, Send);
     }
 }
@@ -0,0 +1,305 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{block, epoch, Commitment, Eternity, Keep, Position, Witness};
+
+/// An observer of changes to an [`Eternity`], to be installed with [`Eternity::set_observer`].
+///
+/// This lets an external index (for instance, of the commitments in each block) be kept in
+/// lockstep with the tree, without duplicating the tree's own bookkeeping of positions. Every
+/// method does nothing by default, so an observer need only implement the events it cares about.
+///
+/// Observer methods are called synchronously, after the change they describe has been made, so
+/// they should be cheap.
+pub trait TreeObserver: Send + Sync {
+    /// A [`Commitment`] was inserted at the given [`Position`].
+    ///
+    /// This is called for commitments inserted one at a time, whether or not they are witnessed,
+    /// and for each witnessed commitment of a [`Block`](crate::Block) or
+    /// [`Epoch`](crate::Epoch) inserted all at once, in order of position. Commitments which
+    /// were forgotten before their block or epoch was inserted are not reported, since the tree
+    /// only holds their hashes.
+    fn inserted(&self, position: Position, commitment: Commitment, witness: Witness) {
+        let _ = (position, commitment, witness);
+    }
+
+    /// The [`Commitment`] witnessed at the given [`Position`] was forgotten.
+    ///
+    /// This is called both when a commitment is explicitly forgotten, and when it is implicitly
+    /// forgotten because it was inserted again at a later position.
+    fn forgotten(&self, position: Position, commitment: Commitment) {
+        let _ = (position, commitment);
+    }
+
+    /// The block starting at the given [`Position`] was finalized with the given root, because
+    /// a later block or epoch was inserted after it.
+    ///
+    /// The blocks within an [`Epoch`](crate::Epoch) inserted all at once are not reported
+    /// individually.
+    fn block_finalized(&self, position: Position, root: block::Root) {
+        let _ = (position, root);
+    }
+
+    /// The epoch starting at the given [`Position`] was finalized with the given root, because a
+    /// later epoch was inserted after it.
+    ///
+    /// The last block of the epoch is reported as finalized first.
+    fn epoch_finalized(&self, position: Position, root: epoch::Root) {
+        let _ = (position, root);
+    }
+}
+
+/// The [`TreeObserver`] installed on an [`Eternity`], if any.
+///
+/// This is not part of the state of the tree: it is not serialized, it is not cloned, and it is
+/// ignored when comparing trees.
+#[derive(Default)]
+pub(super) struct Observer(Option<Arc<dyn TreeObserver>>);
+
+impl Observer {
+    /// Notify the observer, if there is one.
+    pub(super) fn notify(&self, f: impl FnOnce(&dyn TreeObserver)) {
+        if let Some(observer) = &self.0 {
+            f(observer.as_ref())
+        }
+    }
+
+    /// Whether there is an observer to notify.
+    pub(super) fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl Clone for Observer {
+    fn clone(&self) -> Self {
+        Observer(None)
+    }
+}
+
+impl PartialEq for Observer {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Observer {}
+
+impl Debug for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Observer(Some(..))"),
+            None => f.write_str("Observer(None)"),
+        }
+    }
+}
+
+impl Eternity {
+    /// Install a [`TreeObserver`] to be notified of every subsequent change to this [`Eternity`],
+    /// replacing any previously installed observer.
+    ///
+    /// The observer is not carried over to clones of this [`Eternity`], nor to its serialization,
+    /// so that speculative copies of the tree do not notify it.
+    pub fn set_observer(&mut self, observer: impl TreeObserver + 'static) {
+        self.observer = Observer(Some(Arc::new(observer)));
+    }
+
+    /// Remove the installed [`TreeObserver`], if any, returning whether there was one.
+    pub fn clear_observer(&mut self) -> bool {
+        self.observer.0.take().is_some()
+    }
+
+    /// The start and root of the current block, which will be finalized by inserting another
+    /// block or epoch, if there is an observer to report that to.
+    pub(super) fn finalizing_block(&self) -> Option<(Position, block::Root)> {
+        if !self.observer.is_set() {
+            return None;
+        }
+        let root = self.current_block_root()?;
+        let start = Position::new(
+            self.position().epoch().into(),
+            self.position().block().into(),
+            0,
+        )
+        .expect("indices of the current block are in range");
+        Some((start, root))
+    }
+
+    /// The start and root of the current epoch, which will be finalized by inserting another
+    /// epoch, if there is an observer to report that to.
+    pub(super) fn finalizing_epoch(&self) -> Option<(Position, epoch::Root)> {
+        if !self.observer.is_set() {
+            return None;
+        }
+        let root = self.current_epoch_root()?;
+        let start = Position::start_of_epoch(self.position().epoch().into())
+            .expect("index of the current epoch is in range");
+        Some((start, root))
+    }
+
+    /// The witnessed commitments about to be inserted all at once, with the positions at which
+    /// they are currently witnessed, if there is an observer to report them to.
+    pub(super) fn inserting(
+        &self,
+        commitments: impl Iterator<Item = Commitment>,
+    ) -> Vec<(Commitment, Option<Position>)> {
+        if !self.observer.is_set() {
+            return Vec::new();
+        }
+        commitments
+            .map(|commitment| (commitment, self.position_of(commitment)))
+            .collect()
+    }
+
+    /// Report the finalization of blocks and epochs, and the insertion of commitments all at once,
+    /// after a block or epoch was inserted.
+    pub(super) fn notify_inserted_all(
+        &self,
+        block: Option<(Position, block::Root)>,
+        epoch: Option<(Position, epoch::Root)>,
+        commitments: Vec<(Commitment, Option<Position>)>,
+    ) {
+        self.observer.notify(|observer| {
+            if let Some((start, root)) = block {
+                observer.block_finalized(start, root);
+            }
+            if let Some((start, root)) = epoch {
+                observer.epoch_finalized(start, root);
+            }
+
+            for &(commitment, replaced) in &commitments {
+                if let Some(replaced) = replaced {
+                    observer.forgotten(replaced, commitment);
+                }
+            }
+
+            let mut inserted: Vec<(Position, Commitment)> = commitments
+                .into_iter()
+                .filter_map(|(commitment, _)| Some((self.position_of(commitment)?, commitment)))
+                .collect();
+            inserted.sort_by_key(|&(position, _)| u64::from(position));
+            for (position, commitment) in inserted {
+                observer.inserted(position, commitment, Keep);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{Block, Epoch, Forget};
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Event {
+        Inserted(u64, Commitment, Witness),
+        Forgotten(u64, Commitment),
+        BlockFinalized(u64, block::Root),
+        EpochFinalized(u64, epoch::Root),
+    }
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Event>>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<Event> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl TreeObserver for Recorder {
+        fn inserted(&self, position: Position, commitment: Commitment, witness: Witness) {
+            let event = Event::Inserted(position.into(), commitment, witness);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn forgotten(&self, position: Position, commitment: Commitment) {
+            let event = Event::Forgotten(position.into(), commitment);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn block_finalized(&self, position: Position, root: block::Root) {
+            let event = Event::BlockFinalized(position.into(), root);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn epoch_finalized(&self, position: Position, root: epoch::Root) {
+            let event = Event::EpochFinalized(position.into(), root);
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    fn commitment(i: u64) -> Commitment {
+        Commitment(i.into())
+    }
+
+    fn position(epoch: u64, block: u64, commitment: u64) -> u64 {
+        Position::new(epoch, block, commitment).unwrap().into()
+    }
+
+    #[test]
+    fn reports_inserts_forgets_and_finalization() {
+        let recorder = Recorder::default();
+        let mut eternity = Eternity::new();
+        eternity.set_observer(recorder.clone());
+
+        eternity.insert(Keep, commitment(0)).unwrap();
+        eternity.insert(Forget, commitment(1)).unwrap();
+        assert_eq!(
+            recorder.take(),
+            vec![
+                Event::Inserted(position(0, 0, 0), commitment(0), Keep),
+                Event::Inserted(position(0, 0, 1), commitment(1), Forget),
+            ]
+        );
+
+        let first_block = eternity.current_block_root().unwrap();
+        let block =
+            Block::from_commitments(&[(commitment(2), Keep), (commitment(3), Forget)]).unwrap();
+        eternity.insert_block(block).unwrap();
+        assert_eq!(
+            recorder.take(),
+            vec![
+                Event::BlockFinalized(position(0, 0, 0), first_block),
+                Event::Inserted(position(0, 1, 0), commitment(2), Keep),
+            ]
+        );
+
+        assert!(eternity.forget(commitment(0)));
+        assert!(!eternity.forget(commitment(0)));
+        assert_eq!(
+            recorder.take(),
+            vec![Event::Forgotten(position(0, 0, 0), commitment(0))]
+        );
+
+        let last_block = eternity.current_block_root().unwrap();
+        let first_epoch = eternity.current_epoch_root().unwrap();
+        eternity.insert_epoch(Epoch::new()).unwrap();
+        eternity.insert(Keep, commitment(2)).unwrap();
+        assert_eq!(
+            recorder.take(),
+            vec![
+                Event::BlockFinalized(position(0, 1, 0), last_block),
+                Event::EpochFinalized(position(0, 0, 0), first_epoch),
+                Event::Forgotten(position(0, 1, 0), commitment(2)),
+                Event::Inserted(position(1, 0, 0), commitment(2), Keep),
+            ]
+        );
+    }
+
+    #[test]
+    fn observer_is_not_cloned() {
+        let recorder = Recorder::default();
+        let mut eternity = Eternity::new();
+        eternity.set_observer(recorder.clone());
+
+        let mut clone = eternity.clone();
+        assert_eq!(clone, eternity);
+        clone.insert(Keep, commitment(0)).unwrap();
+        assert!(recorder.take().is_empty());
+
+        assert!(eternity.clear_observer());
+        eternity.insert(Keep, commitment(0)).unwrap();
+        assert!(recorder.take().is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+
+use crate::{Commitment, Eternity, Position};
+
+/// A policy for which witnessed [`Commitment`]s an [`Eternity`] should retain, to be applied with
+/// [`Eternity::apply_policy`].
+///
+/// By default, everything is retained. A policy can be narrowed to retain only the commitments of
+/// the most recent epochs with [`keep_last_epochs`](RetentionPolicy::keep_last_epochs), while
+/// still retaining particular commitments regardless of their age with
+/// [`never_forget`](RetentionPolicy::never_forget).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RetentionPolicy {
+    last_epochs: Option<u16>,
+    pinned: HashSet<Commitment>,
+}
+
+impl RetentionPolicy {
+    /// Create a new [`RetentionPolicy`] which retains everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retain only the commitments in the most recent `epochs` epochs, counting the current epoch
+    /// (the one into which the next commitment would be inserted).
+    ///
+    /// If `epochs` is zero, no commitments are retained unless they are pinned by
+    /// [`never_forget`](RetentionPolicy::never_forget).
+    pub fn keep_last_epochs(mut self, epochs: u16) -> Self {
+        self.last_epochs = Some(epochs);
+        self
+    }
+
+    /// Always retain the given commitment, regardless of any other rule.
+    pub fn never_forget(mut self, commitment: impl Into<Commitment>) -> Self {
+        self.pinned.insert(commitment.into());
+        self
+    }
+
+    /// Check whether a commitment witnessed at `position` should be retained, when the current
+    /// epoch is `current_epoch`.
+    pub fn retains(&self, current_epoch: u16, position: Position, commitment: Commitment) -> bool {
+        if self.pinned.contains(&commitment) {
+            return true;
+        }
+        match self.last_epochs {
+            Some(epochs) => current_epoch.saturating_sub(position.epoch()) < epochs,
+            None => true,
+        }
+    }
+}
+
+impl Eternity {
+    /// Forget every witnessed [`Commitment`] which the given [`RetentionPolicy`] does not retain.
+    ///
+    /// This does not change the root of the [`Eternity`]. Returns the number of commitments which
+    /// were forgotten.
+    pub fn apply_policy(&mut self, policy: &RetentionPolicy) -> usize {
+        let current_epoch = self.position().epoch();
+
+        let forgettable: Vec<Commitment> = self
+            .index
+            .iter()
+            .filter(|(commitment, index)| {
+                !policy.retains(current_epoch, Position(**index), **commitment)
+            })
+            .map(|(&commitment, _)| commitment)
+            .collect();
+
+        for &commitment in &forgettable {
+            let forgotten = self.forget(commitment);
+            debug_assert!(forgotten);
+        }
+
+        forgettable.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Epoch, Keep};
+
+    #[test]
+    fn keeps_recent_epochs_and_pinned_commitments() {
+        let mut eternity = Eternity::new();
+        for epoch in 0u64..3 {
+            if epoch > 0 {
+                eternity.insert_epoch(Epoch::new()).unwrap();
+            }
+            for i in 0u64..2 {
+                eternity
+                    .insert(Keep, Commitment((epoch * 2 + i).into()))
+                    .unwrap();
+            }
+        }
+        let root = eternity.root();
+
+        let policy = RetentionPolicy::new()
+            .keep_last_epochs(2)
+            .never_forget(Commitment(0u64.into()));
+        assert_eq!(eternity.apply_policy(&policy), 1);
+        assert_eq!(eternity.root(), root);
+        assert!(eternity.witness(Commitment(0u64.into())).is_some());
+        assert!(eternity.witness(Commitment(1u64.into())).is_none());
+        for i in 2u64..6 {
+            assert!(eternity.witness(Commitment(i.into())).is_some());
+        }
+
+        // Applying the same policy again forgets nothing more.
+        assert_eq!(eternity.apply_policy(&policy), 0);
+        assert_eq!(eternity.witnessed_count(), 5);
+    }
+}
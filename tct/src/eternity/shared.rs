@@ -0,0 +1,275 @@
+use std::sync::{mpsc, Arc};
+
+use left_right::{Absorb, ReadHandle, ReadHandleFactory, WriteHandle};
+use parking_lot::Mutex;
+
+use crate::{
+    block, epoch, Block, Commitment, Epoch, Eternity, Position, Proof, RetentionPolicy, Root,
+    Witness,
+};
+
+use super::error::{
+    InsertBlockError, InsertBlockRootError, InsertCompactBlockError, InsertEpochError,
+    InsertEpochRootError, InsertError,
+};
+
+/// An [`Eternity`] which can be read concurrently with being written, from many tasks at once.
+///
+/// Reads never take a lock: readers see the tree as of the last completed write, and are not
+/// blocked by a write in progress. Writes are serialized with each other, and each is visible to
+/// readers as soon as it returns.
+///
+/// This is done by keeping two copies of the tree, one for readers and one for the writer, and
+/// applying each change to both, so it takes twice the memory of a single [`Eternity`], but never
+/// clones the whole tree after it is shared. A write waits for readers still reading the copy it
+/// is about to change, so reads should be brief.
+///
+/// Cloning a [`SharedEternity`] is cheap, and the clone refers to the same tree.
+///
+/// Any [`TreeObserver`](crate::TreeObserver) installed on the shared tree is removed, since it
+/// would otherwise be notified of each change twice.
+#[derive(Clone)]
+pub struct SharedEternity {
+    writer: Arc<Mutex<WriteHandle<Eternity, Operation>>>,
+    readers: ReadHandleFactory<Eternity>,
+}
+
+/// A handle for reading a [`SharedEternity`] without taking any lock, from a single task.
+///
+/// A [`Reader`] can be sent between threads, but not shared between them: each concurrent reader
+/// should have its own, from [`SharedEternity::reader`].
+pub struct Reader {
+    handle: ReadHandle<Eternity>,
+    // Readers can only read while the writer exists.
+    _writer: Arc<Mutex<WriteHandle<Eternity, Operation>>>,
+}
+
+/// A change to be made to both copies of a [`SharedEternity`].
+///
+/// The flag is set for the first copy changed, which is the one whose outcome is reported.
+struct Operation(Box<dyn Fn(&mut Eternity, bool) + Send>);
+
+impl Absorb<Operation> for Eternity {
+    fn absorb_first(&mut self, operation: &mut Operation, _other: &Self) {
+        (operation.0)(self, true)
+    }
+
+    fn absorb_second(&mut self, operation: Operation, _other: &Self) {
+        (operation.0)(self, false)
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        *self = first.clone();
+    }
+}
+
+impl From<Eternity> for SharedEternity {
+    fn from(eternity: Eternity) -> Self {
+        SharedEternity::new(eternity)
+    }
+}
+
+impl Default for SharedEternity {
+    fn default() -> Self {
+        SharedEternity::new(Eternity::new())
+    }
+}
+
+impl SharedEternity {
+    /// Share an [`Eternity`], so that it can be read concurrently with being written.
+    pub fn new(mut eternity: Eternity) -> Self {
+        eternity.clear_observer();
+        let (mut writer, reader) = left_right::new_from_empty(eternity);
+        // Until the first publish, changes are made to the writer's copy directly rather than
+        // logged, so publish now so that every write reports its outcome in the same way.
+        writer.publish();
+        SharedEternity {
+            writer: Arc::new(Mutex::new(writer)),
+            readers: reader.factory(),
+        }
+    }
+
+    /// Get a new [`Reader`] of this tree, for reading from a single task without taking a lock.
+    pub fn reader(&self) -> Reader {
+        Reader {
+            handle: self.readers.handle(),
+            _writer: self.writer.clone(),
+        }
+    }
+
+    /// Read the tree as of the last completed write.
+    ///
+    /// This registers a new reader, which takes a brief lock: tasks reading repeatedly should
+    /// keep their own [`Reader`] instead.
+    pub fn read<R>(&self, f: impl FnOnce(&Eternity) -> R) -> R {
+        self.reader().read(f)
+    }
+
+    /// Get the root hash of the tree, as in [`Eternity::root`].
+    pub fn root(&self) -> Root {
+        self.read(Eternity::root)
+    }
+
+    /// Get a [`Proof`] of inclusion for a commitment, as in [`Eternity::witness`].
+    pub fn witness(&self, commitment: impl Into<Commitment>) -> Option<Proof> {
+        let commitment = commitment.into();
+        self.read(|eternity| eternity.witness(commitment))
+    }
+
+    /// Get the position of a witnessed commitment, as in [`Eternity::position_of`].
+    pub fn position_of(&self, commitment: impl Into<Commitment>) -> Option<Position> {
+        let commitment = commitment.into();
+        self.read(|eternity| eternity.position_of(commitment))
+    }
+
+    /// Add a new [`Commitment`] to the tree, as in [`Eternity::insert`].
+    pub fn insert(
+        &self,
+        witness: Witness,
+        commitment: impl Into<Commitment>,
+    ) -> Result<Position, InsertError> {
+        let commitment = commitment.into();
+        self.write(move |eternity| eternity.insert(witness, commitment))
+    }
+
+    /// Forget about the witness for a commitment, as in [`Eternity::forget`].
+    pub fn forget(&self, commitment: impl Into<Commitment>) -> bool {
+        let commitment = commitment.into();
+        self.write(move |eternity| eternity.forget(commitment))
+    }
+
+    /// Add a new [`Block`] all at once, as in [`Eternity::insert_block`].
+    pub fn insert_block(&self, block: Block) -> Result<(), InsertBlockError> {
+        self.write(move |eternity| eternity.insert_block(block.clone()))
+    }
+
+    /// Add the root of a [`Block`], as in [`Eternity::insert_block_root`].
+    pub fn insert_block_root(&self, block_root: block::Root) -> Result<(), InsertBlockRootError> {
+        self.write(move |eternity| eternity.insert_block_root(block_root))
+    }
+
+    /// Add a [`Block`] from a compact block, as in [`Eternity::insert_compact_block`].
+    pub fn insert_compact_block(
+        &self,
+        block_root: block::Root,
+        commitments: &[(Commitment, Witness)],
+        total_commitments: usize,
+    ) -> Result<(), InsertCompactBlockError> {
+        let commitments = commitments.to_vec();
+        self.write(move |eternity| {
+            eternity.insert_compact_block(block_root, &commitments, total_commitments)
+        })
+    }
+
+    /// Add a new [`Epoch`] all at once, as in [`Eternity::insert_epoch`].
+    pub fn insert_epoch(&self, epoch: Epoch) -> Result<(), InsertEpochError> {
+        self.write(move |eternity| eternity.insert_epoch(epoch.clone()))
+    }
+
+    /// Add the root of an [`Epoch`], as in [`Eternity::insert_epoch_root`].
+    pub fn insert_epoch_root(&self, epoch_root: epoch::Root) -> Result<(), InsertEpochRootError> {
+        self.write(move |eternity| eternity.insert_epoch_root(epoch_root))
+    }
+
+    /// Forget the witnesses a [`RetentionPolicy`] no longer retains, as in
+    /// [`Eternity::apply_policy`].
+    pub fn apply_policy(&self, policy: &RetentionPolicy) -> usize {
+        let policy = policy.clone();
+        self.write(move |eternity| eternity.apply_policy(&policy))
+    }
+
+    /// Make a change to both copies of the tree, and publish it to readers, returning its outcome.
+    ///
+    /// The change must be deterministic, so that the copies remain the same.
+    fn write<R: Send + 'static>(&self, change: impl Fn(&mut Eternity) -> R + Send + 'static) -> R {
+        let (outcome_tx, outcome_rx) = mpsc::channel();
+        let mut writer = self.writer.lock();
+        writer.append(Operation(Box::new(move |eternity, first| {
+            let outcome = change(eternity);
+            if first {
+                let _ = outcome_tx.send(outcome);
+            }
+        })));
+        writer.publish();
+        outcome_rx
+            .try_recv()
+            .expect("a change is made to the first copy when it is published")
+    }
+}
+
+impl Reader {
+    /// Read the tree as of the last completed write.
+    pub fn read<R>(&self, f: impl FnOnce(&Eternity) -> R) -> R {
+        let eternity = self
+            .handle
+            .enter()
+            .expect("the writer outlives its readers");
+        f(&*eternity)
+    }
+
+    /// Get the root hash of the tree, as in [`Eternity::root`].
+    pub fn root(&self) -> Root {
+        self.read(Eternity::root)
+    }
+
+    /// Get a [`Proof`] of inclusion for a commitment, as in [`Eternity::witness`].
+    pub fn witness(&self, commitment: impl Into<Commitment>) -> Option<Proof> {
+        let commitment = commitment.into();
+        self.read(|eternity| eternity.witness(commitment))
+    }
+
+    /// Get the position of a witnessed commitment, as in [`Eternity::position_of`].
+    pub fn position_of(&self, commitment: impl Into<Commitment>) -> Option<Position> {
+        let commitment = commitment.into();
+        self.read(|eternity| eternity.position_of(commitment))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Forget, Keep};
+    use proptest::prelude::*;
+
+    #[test]
+    fn shared_eternity_sync_send() {
+        static_assertions::assert_impl_all!(SharedEternity: Sync, Send);
+        static_assertions::assert_impl_all!(Reader: Send);
+    }
+
+    proptest! {
+        #[test]
+        fn matches_unshared(commitments in prop::collection::vec(any::<Commitment>(), 1..32)) {
+            let mut eternity = Eternity::new();
+            let shared = SharedEternity::default();
+            let reader = shared.reader();
+
+            for (i, commitment) in commitments.iter().enumerate() {
+                let witness = if i % 2 == 0 { Keep } else { Forget };
+                prop_assert_eq!(
+                    shared.insert(witness, *commitment),
+                    eternity.insert(witness, *commitment)
+                );
+                if i % 5 == 4 {
+                    prop_assert_eq!(
+                        shared.insert_block_root(Block::new().root()),
+                        eternity.insert_block_root(Block::new().root())
+                    );
+                }
+                // Each write is visible to readers as soon as it returns.
+                prop_assert_eq!(reader.root(), eternity.root());
+            }
+
+            prop_assert_eq!(shared.forget(commitments[0]), eternity.forget(commitments[0]));
+            for commitment in &commitments {
+                prop_assert_eq!(reader.witness(*commitment), eternity.witness(*commitment));
+                prop_assert_eq!(shared.position_of(*commitment), eternity.position_of(*commitment));
+            }
+            // Both copies are kept the same.
+            prop_assert!(shared.read(|shared| shared == &eternity));
+            shared.insert(Keep, commitments[0]).unwrap();
+            eternity.insert(Keep, commitments[0]).unwrap();
+            prop_assert!(shared.read(|shared| shared == &eternity));
+        }
+    }
+}
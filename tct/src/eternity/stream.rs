@@ -0,0 +1,160 @@
+//! Streaming the [`Eternity`] encoding to and from asynchronous I/O, without ever holding the whole
+//! encoding in memory.
+
+use std::io::{self, Read};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+use super::{error::DecodeError, Eternity};
+use crate::internal::encode::Encode;
+
+/// The size of the chunks the encoding is written and read in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The number of chunks which may be read ahead of the decoder.
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+impl Eternity {
+    /// Write the encoding of this [`Eternity`] to `writer`, in the same format as
+    /// [`to_bytes`](Eternity::to_bytes).
+    ///
+    /// The encoding is produced as it is written, so at most a few chunks of it are in memory at
+    /// once, however large the tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from `writer`, at which point only part of the encoding may have
+    /// been written.
+    pub async fn serialize_to(&self, mut writer: impl AsyncWrite + Unpin) -> io::Result<()> {
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        for piece in self.pieces() {
+            chunk.extend_from_slice(&piece);
+            if chunk.len() >= CHUNK_SIZE {
+                writer.write_all(&chunk).await?;
+                chunk.clear();
+            }
+        }
+        writer.write_all(&chunk).await?;
+        writer.flush().await
+    }
+
+    /// Read an [`Eternity`] from the encoding written to `reader` by
+    /// [`serialize_to`](Eternity::serialize_to), or by [`to_bytes`](Eternity::to_bytes), without
+    /// checking its internal consistency.
+    ///
+    /// The tree is decoded on the blocking thread pool as its encoding is read, so only a few
+    /// chunks of the encoding are in memory at once. As with
+    /// [`from_bytes_trusted`](Eternity::from_bytes_trusted), this should only be used for
+    /// encodings from a trusted source.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DecodeError::Io`] if reading from `reader` fails, or [`DecodeError::Malformed`] if
+    /// what was read is not the encoding of any [`Eternity`].
+    pub async fn deserialize_from(mut reader: impl AsyncRead + Unpin) -> Result<Self, DecodeError> {
+        let (chunks, receiver) = mpsc::channel(CHUNKS_IN_FLIGHT);
+        let decoding = tokio::task::spawn_blocking(move || {
+            bincode::deserialize_from(Chunks {
+                receiver,
+                chunk: Vec::new(),
+                read: 0,
+            })
+        });
+
+        loop {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let read = reader
+                .read(&mut chunk)
+                .await
+                .map_err(|e| DecodeError::Io(e.to_string()))?;
+            if read == 0 {
+                break;
+            }
+            chunk.truncate(read);
+            // The decoder stops receiving once it has decoded a whole tree, or failed to.
+            if chunks.send(chunk).await.is_err() {
+                break;
+            }
+        }
+        // Closing the channel marks the end of the encoding.
+        drop(chunks);
+
+        match decoding.await {
+            Ok(decoded) => decoded.map_err(|e| DecodeError::Malformed(e.to_string())),
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(DecodeError::Io(e.to_string())),
+        }
+    }
+}
+
+/// The chunks of an encoding as they are read, for the decoder.
+struct Chunks {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    read: usize,
+}
+
+impl Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.chunk.len() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.read = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.read);
+        buf[..len].copy_from_slice(&self.chunk[self.read..self.read + len]);
+        self.read += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Witness;
+
+    /// A tree whose encoding spans several chunks.
+    fn eternity() -> Eternity {
+        let mut eternity = Eternity::new();
+        for i in 0..4096u64 {
+            let witness = if i % 3 == 0 {
+                Witness::Forget
+            } else {
+                Witness::Keep
+            };
+            eternity
+                .insert(witness, crate::Commitment(i.into()))
+                .unwrap();
+        }
+        eternity
+    }
+
+    #[tokio::test]
+    async fn streams_the_whole_encoding() {
+        let eternity = eternity();
+        let mut bytes = Vec::new();
+        eternity.serialize_to(&mut bytes).await.unwrap();
+        assert!(bytes.len() > CHUNK_SIZE);
+        assert_eq!(bytes, eternity.to_bytes());
+
+        let decoded = Eternity::deserialize_from(bytes.as_slice()).await.unwrap();
+        assert_eq!(decoded, eternity);
+        assert_eq!(decoded.root(), eternity.root());
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_encodings() {
+        let bytes = eternity().to_bytes();
+        assert!(matches!(
+            Eternity::deserialize_from(&bytes[..bytes.len() - 1]).await,
+            Err(DecodeError::Malformed(_))
+        ));
+    }
+}
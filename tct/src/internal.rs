@@ -2,6 +2,7 @@
 //!
 //! This module and its submodules should not be expected to follow semantic versioning.
 
+pub mod encode;
 pub mod hash;
 pub mod height;
 pub mod index;
@@ -4,6 +4,7 @@ use crate::{
     internal::{
         active::Forget,
         complete,
+        encode::{self, Encode, Pieces},
         height::Zero,
         path::{self, Witness},
     },
@@ -83,3 +84,9 @@ impl Forget for Item {
         unreachable!("active items can not be forgotten directly")
     }
 }
+
+impl Encode for Item {
+    fn pieces(&self) -> Pieces<'_> {
+        encode::one(self)
+    }
+}
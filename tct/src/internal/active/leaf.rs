@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     internal::{
         active::{Forget, Full},
+        encode::{Encode, Pieces},
         height::IsHeight,
         path::Witness,
     },
@@ -129,3 +130,9 @@ impl<Item: GetHash + Forget> Forget for Leaf<Item> {
         }
     }
 }
+
+impl<Item: Encode> Encode for Leaf<Item> {
+    fn pieces(&self) -> Pieces<'_> {
+        self.item.pieces()
+    }
+}
@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     internal::{
         active::{Forget, Full},
+        encode::{self, Encode, Pieces},
         hash::CachedHash,
         height::{IsHeight, Succ},
         path::{self, WhichWay, Witness},
@@ -355,3 +356,13 @@ where
         }
     }
 }
+
+impl<Child: Focus + Encode> Encode for Node<Child>
+where
+    Child::Complete: Encode,
+{
+    fn pieces(&self) -> Pieces<'_> {
+        let siblings = encode::seq(self.siblings.iter().map(Encode::pieces));
+        Box::new(siblings.chain(self.focus.pieces()))
+    }
+}
@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     internal::{
         active::{Forget, Full},
+        encode::{self, Encode, Pieces},
         path::Witness,
     },
     Active, AuthPath, Focus, ForgetOwned, GetHash, Hash, Height, Insert,
@@ -312,6 +313,21 @@ where
     }
 }
 
+impl<Item: Focus + Encode> Encode for Tier<Item>
+where
+    Item::Complete: Encode,
+{
+    fn pieces(&self) -> Pieces<'_> {
+        match &self.inner {
+            Inner::Active(active) => {
+                encode::variant(0, encode::option((**active).as_ref().map(Encode::pieces)))
+            }
+            Inner::Complete(complete) => encode::variant(1, complete.pieces()),
+            Inner::Hash(hash) => encode::variant(2, encode::one(hash)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     internal::{
         active,
+        encode::{self, Encode, Pieces},
         height::Zero,
         path::{self, Witness},
     },
@@ -56,3 +57,9 @@ impl ForgetOwned for Item {
         (Insert::Hash(self.0), true)
     }
 }
+
+impl Encode for Item {
+    fn pieces(&self) -> Pieces<'_> {
+        encode::one(self)
+    }
+}
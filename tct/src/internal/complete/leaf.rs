@@ -1,6 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{internal::path::Witness, Complete, ForgetOwned, GetHash, Hash, Height, Insert};
+use crate::{
+    internal::{
+        encode::{Encode, Pieces},
+        path::Witness,
+    },
+    Complete, ForgetOwned, GetHash, Hash, Height, Insert,
+};
 
 use super::super::active;
 
@@ -59,3 +65,9 @@ impl<Item: ForgetOwned> ForgetOwned for Leaf<Item> {
         (item.map(Leaf), forgotten)
     }
 }
+
+impl<Item: Encode> Encode for Leaf<Item> {
+    fn pieces(&self) -> Pieces<'_> {
+        self.0.pieces()
+    }
+}
@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     internal::{
+        encode::{Encode, Pieces},
         hash::CachedHash,
         height::{IsHeight, Succ},
         path::{self, AuthPath, WhichWay, Witness},
@@ -192,6 +193,12 @@ impl<Child: GetHash + ForgetOwned> ForgetOwned for Node<Child> {
     }
 }
 
+impl<Child: Encode> Encode for Node<Child> {
+    fn pieces(&self) -> Pieces<'_> {
+        self.children.pieces()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
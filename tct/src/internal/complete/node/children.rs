@@ -12,7 +12,10 @@ use serde::{Deserialize, Serialize};
 mod shape;
 pub use shape::*;
 
-use crate::{Hash, Height, Insert};
+use crate::{
+    internal::encode::{self, Encode, Pieces},
+    Hash, Height, Insert,
+};
 
 /// The children of a [`Node`](super::Node).
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -142,3 +145,19 @@ impl<Child> From<Children<Child>> for [Insert<Child>; 4] {
         }
     }
 }
+
+impl<Child: Encode> Encode for Children<Child> {
+    fn pieces(&self) -> Pieces<'_> {
+        let children = self.children();
+        // The variants are in the order of the shapes read as binary numbers, with a 1 for each
+        // child kept, starting from the shape with only the last child kept.
+        let shape = children
+            .iter()
+            .fold(0, |shape, child| (shape << 1) | u32::from(child.is_keep()));
+        let children = children.into_iter().flat_map(|child| match child {
+            Insert::Keep(child) => child.pieces(),
+            Insert::Hash(hash) => encode::one(&hash),
+        });
+        encode::variant(shape - 1, Box::new(children))
+    }
+}
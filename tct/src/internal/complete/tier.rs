@@ -1,6 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{internal::path::Witness, AuthPath, Complete, ForgetOwned, GetHash, Hash, Height};
+use crate::{
+    internal::{
+        encode::{Encode, Pieces},
+        path::Witness,
+    },
+    AuthPath, Complete, ForgetOwned, GetHash, Hash, Height,
+};
 
 use super::super::active;
 
@@ -55,3 +61,9 @@ impl<Item: GetHash + ForgetOwned> ForgetOwned for Tier<Item> {
         (inner.map(|inner| Tier { inner }), forgotten)
     }
 }
+
+impl<Item: Encode> Encode for Tier<Item> {
+    fn pieces(&self) -> Pieces<'_> {
+        self.inner.pieces()
+    }
+}
@@ -0,0 +1,52 @@
+//! Encoding of trees piece by piece, so that the whole encoding need never be in memory at once.
+//!
+//! The concatenated pieces of a tree are exactly its [`bincode`] encoding, so a tree encoded
+//! piece by piece can be decoded like any other.
+
+use std::iter;
+
+use serde::Serialize;
+
+/// A lazily produced sequence of pieces of an encoding.
+pub type Pieces<'a> = Box<dyn Iterator<Item = Vec<u8>> + Send + 'a>;
+
+/// A tree which can be encoded piece by piece.
+///
+/// Each piece is the encoding of a hash, a tag, or a length, and the pieces of each subtree are
+/// only produced once those before it have been consumed, so the memory used while encoding is
+/// bounded by the height of the tree rather than its size.
+pub trait Encode: Sync {
+    /// The pieces of the [`bincode`] encoding of this tree, in order.
+    fn pieces(&self) -> Pieces<'_>;
+}
+
+/// The encoding of a value small enough to be encoded at once.
+pub fn whole<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    bincode::serialize(value).expect("serializing a tree cannot fail")
+}
+
+/// A single piece, being the whole encoding of the value.
+pub fn one<'a, T: Serialize + ?Sized>(value: &T) -> Pieces<'a> {
+    Box::new(iter::once(whole(value)))
+}
+
+/// The pieces of a variant of an enum: its index, followed by the pieces of its contents.
+pub fn variant(index: u32, contents: Pieces<'_>) -> Pieces<'_> {
+    Box::new(iter::once(whole(&index)).chain(contents))
+}
+
+/// The pieces of an optional value.
+pub fn option(value: Option<Pieces<'_>>) -> Pieces<'_> {
+    match value {
+        None => one(&0u8),
+        Some(value) => Box::new(iter::once(whole(&1u8)).chain(value)),
+    }
+}
+
+/// The pieces of a sequence: its length, followed by the pieces of each of its elements.
+pub fn seq<'a, I>(elems: I) -> Pieces<'a>
+where
+    I: ExactSizeIterator<Item = Pieces<'a>> + Send + 'a,
+{
+    Box::new(iter::once(whole(&(elems.len() as u64))).chain(elems.flatten()))
+}
@@ -125,13 +125,50 @@ impl Hash {
 
     /// Construct a hash for an internal node of the tree, given its height and the hashes of its
     /// four children.
+    ///
+    /// Nodes whose children all have the zero hash are common in sparse trees (for instance, when
+    /// an empty block or epoch is finalized), so their hashes are looked up in a precomputed table
+    /// rather than recomputed.
     #[inline]
-    pub fn node(height: u8, Hash(a): Hash, Hash(b): Hash, Hash(c): Hash, Hash(d): Hash) -> Hash {
+    pub fn node(height: u8, a: Hash, b: Hash, c: Hash, d: Hash) -> Hash {
+        let zero = Hash::default();
+        if a == zero && b == zero && c == zero && d == zero {
+            if let Some(hash) = EMPTY_NODES.get(height as usize) {
+                return *hash;
+            }
+        }
+        Hash::node_uncached(height, a, b, c, d)
+    }
+
+    /// Compute the hash of an internal node, without consulting the table of empty node hashes.
+    #[inline]
+    fn node_uncached(
+        height: u8,
+        Hash(a): Hash,
+        Hash(b): Hash,
+        Hash(c): Hash,
+        Hash(d): Hash,
+    ) -> Hash {
         let height = Fq::from_le_bytes_mod_order(&height.to_le_bytes());
         Hash(hash_4(&(*DOMAIN_SEPARATOR + height), (a, b, c, d)))
     }
 }
 
+/// The height of the tallest node in the tree, the root of an [`Eternity`](crate::Eternity).
+const MAX_NODE_HEIGHT: u8 = 24;
+
+/// The hash of a node whose children all have the zero hash, indexed by the height of the node.
+///
+/// Index 0 is unused, since nodes have height at least 1.
+static EMPTY_NODES: Lazy<[Hash; MAX_NODE_HEIGHT as usize + 1]> = Lazy::new(|| {
+    let zero = Hash::default();
+    let mut table = [zero; MAX_NODE_HEIGHT as usize + 1];
+    for (height, hash) in table.iter_mut().enumerate().skip(1) {
+        *hash = Hash::node_uncached(height as u8, zero, zero, zero, zero);
+    }
+    table
+});
+
 #[cfg(any(test, feature = "arbitrary"))]
 mod arbitrary {
     use super::Hash;
@@ -172,3 +209,25 @@ mod arbitrary {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn empty_nodes_match_uncached() {
+        let zero = Hash::default();
+        for height in 1..=MAX_NODE_HEIGHT {
+            assert_eq!(
+                Hash::node(height, zero, zero, zero, zero),
+                Hash::node_uncached(height, zero, zero, zero, zero)
+            );
+        }
+    }
+
+    #[test]
+    fn max_node_height_is_eternity_height() {
+        use crate::{internal::height::IsHeight, Height};
+        assert_eq!(MAX_NODE_HEIGHT, <crate::Eternity as Height>::Height::HEIGHT);
+    }
+}
@@ -2,7 +2,13 @@ use std::mem;
 
 use serde::{Deserialize, Serialize};
 
-use crate::{internal::active::Forget, ForgetOwned, GetHash, Hash, Height};
+use crate::{
+    internal::{
+        active::Forget,
+        encode::{self, Encode, Pieces},
+    },
+    ForgetOwned, GetHash, Hash, Height,
+};
 
 /// Either an item or just its hash, to be used when inserting into a tree.
 ///
@@ -113,3 +119,12 @@ impl<T: Height + ForgetOwned> Forget for Insert<T> {
         forgotten
     }
 }
+
+impl<T: Encode> Encode for Insert<T> {
+    fn pieces(&self) -> Pieces<'_> {
+        match self {
+            Insert::Keep(item) => encode::variant(0, item.pieces()),
+            Insert::Hash(hash) => encode::variant(1, encode::one(hash)),
+        }
+    }
+}
@@ -53,6 +53,11 @@ impl<T> Three<T> {
         }
     }
 
+    /// Iterate over the elements of this [`Three`] by reference.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.elems.iter()
+    }
+
     /// Get an enumeration of the elements of this [`Three`] by mutable reference.
     pub fn elems_mut(&mut self) -> ElemsMut<T> {
         match self.elems.as_mut_slice() {
@@ -175,7 +180,7 @@ impl<'de, T: Deserialize<'de>> Visitor<'de> for ThreeVisitor<T> {
         A: serde::de::SeqAccess<'de>,
     {
         let mut elems = Vec::with_capacity(4);
-        for _ in 0..=3 {
+        for _ in 0..3 {
             if let Some(elem) = seq.next_element()? {
                 elems.push(elem);
             } else {
@@ -197,3 +202,19 @@ impl<'de, T: Deserialize<'de>> Deserialize<'de> for Three<T> {
         deserializer.deserialize_seq(ThreeVisitor(PhantomData))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deserialize_rejects_more_than_three_elements() {
+        for len in 0..=3 {
+            let bytes = bincode::serialize(&vec![0u8; len]).unwrap();
+            let three: Three<u8> = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(three.iter().count(), len);
+        }
+        let bytes = bincode::serialize(&vec![0u8; 4]).unwrap();
+        assert!(bincode::deserialize::<Three<u8>>(&bytes).is_err());
+    }
+}
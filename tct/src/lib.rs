@@ -54,6 +54,9 @@ mod internal;
 #[cfg(any(doc, test, feature = "spec"))]
 pub mod spec;
 
+#[cfg(any(doc, test, feature = "vectors"))]
+pub mod vectors;
+
 use internal::{
     active::{Active, Focus, Insert, Item, Tier},
     complete::{Complete, ForgetOwned},
@@ -102,7 +105,7 @@ impl From<poseidon377::Fq> for Commitment {
 mod eternity;
 pub use eternity::{
     epoch::{block::Block, Epoch},
-    error, Eternity, Position, Proof, Root,
+    error, Eternity, Position, Proof, Reader, RetentionPolicy, Root, SharedEternity, TreeObserver,
 };
 
 pub mod epoch {
@@ -142,7 +145,7 @@ mod test {
 
     #[test]
     fn check_eternity_size() {
-        static_assertions::assert_eq_size!(Eternity, [u8; 104]);
+        static_assertions::assert_eq_size!(Eternity, [u8; 120]);
     }
 
     #[test]
@@ -0,0 +1,378 @@
+//! Canonical test vectors, for checking that other implementations of the tiered commitment tree
+//! compute the same roots and authentication paths as this crate, hash for hash.
+//!
+//! Each [`TestVector`] is a sequence of [`Action`]s on an empty [`Eternity`], with the root of the
+//! tree after each action, and a [`ProofVector`] for each commitment still witnessed at the end.
+//! The vectors generated by [`generate`] are checked in as JSON at `tct/vectors/vectors.json`,
+//! where implementations in other languages can read them; its format is described alongside it.
+//!
+//! Field elements, whether commitments or hashes, are written as the hex of their 32-byte
+//! little-endian encoding, as in the protobuf encoding of a [`Root`](crate::Root).
+
+use ark_ff::PrimeField;
+use decaf377::FieldExt;
+use poseidon377::Fq;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{Block, Commitment, Epoch, Eternity, Forget, Hash, Keep, Position, Proof};
+
+/// A sequence of actions on an empty [`Eternity`], and the results they should have.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVector {
+    /// A short description of what the vector exercises.
+    pub name: String,
+    /// The actions, in order, each with the root of the tree after it.
+    pub steps: Vec<Step>,
+    /// Proofs of inclusion for the commitments witnessed at the end, against the last root.
+    pub proofs: Vec<ProofVector>,
+}
+
+/// An action on the tree, and the root of the tree after it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Step {
+    /// The action.
+    pub action: Action,
+    /// The root of the tree after the action.
+    pub root: String,
+}
+
+/// An action on an [`Eternity`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Insert a commitment into the current block, with [`Eternity::insert`], keeping it to be
+    /// witnessed later or forgetting it.
+    Insert {
+        /// The commitment.
+        commitment: String,
+        /// Whether the commitment is kept.
+        keep: bool,
+    },
+    /// Start a new, empty block, with [`Eternity::insert_block`].
+    NewBlock,
+    /// Start a new, empty epoch, with [`Eternity::insert_epoch`].
+    NewEpoch,
+}
+
+/// A proof of inclusion of a commitment.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofVector {
+    /// The commitment.
+    pub commitment: String,
+    /// The index of the commitment's epoch within the eternity.
+    pub epoch: u16,
+    /// The index of the commitment's block within its epoch.
+    pub block: u16,
+    /// The index of the commitment within its block.
+    pub index: u16,
+    /// The siblings of each node on the path from the root to the commitment, root first.
+    pub auth_path: Vec<[String; 3]>,
+}
+
+/// A test vector did not match this implementation.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum VectorError {
+    /// A field element could not be decoded.
+    #[error("invalid field element {0:?}")]
+    InvalidElement(String),
+    /// An action could not be applied to the tree.
+    #[error("could not apply step {step}")]
+    InvalidAction {
+        /// The index of the step.
+        step: usize,
+    },
+    /// The root after a step differed.
+    #[error("root after step {step} is {actual}, not {expected}")]
+    Root {
+        /// The index of the step.
+        step: usize,
+        /// The root in the vector.
+        expected: String,
+        /// The root computed by this implementation.
+        actual: String,
+    },
+    /// A proof differed, or did not verify.
+    #[error("proof of commitment {commitment} does not match")]
+    Proof {
+        /// The commitment whose proof differed.
+        commitment: String,
+    },
+    /// A different set of commitments was witnessed.
+    #[error("{actual} commitments are witnessed, not {expected}")]
+    Witnessed {
+        /// The number of proofs in the vector.
+        expected: usize,
+        /// The number of commitments witnessed by this implementation.
+        actual: usize,
+    },
+}
+
+/// Generates the canonical test vectors.
+pub fn generate() -> Vec<TestVector> {
+    let mut next = 0;
+    let mut commitment = |keep| {
+        next += 1;
+        Action::insert(commit(next), keep)
+    };
+    vec![
+        TestVector::generate("empty block", vec![Action::NewBlock]),
+        TestVector::generate("one commitment", vec![commitment(true)]),
+        TestVector::generate(
+            "forgotten commitments",
+            vec![commitment(false), commitment(true), commitment(false)],
+        ),
+        TestVector::generate(
+            "blocks",
+            [
+                (0..5).map(|_| commitment(true)).collect::<Vec<_>>(),
+                vec![Action::NewBlock],
+                (0..3).map(|_| commitment(true)).collect(),
+            ]
+            .concat(),
+        ),
+        TestVector::generate(
+            "empty blocks and epochs",
+            vec![
+                Action::NewBlock,
+                Action::NewBlock,
+                Action::NewEpoch,
+                commitment(true),
+                Action::NewEpoch,
+                Action::NewBlock,
+            ],
+        ),
+        TestVector::generate(
+            "epochs",
+            [
+                vec![commitment(true), Action::NewBlock, commitment(false)],
+                vec![Action::NewEpoch, commitment(true), commitment(true)],
+                vec![Action::NewBlock, Action::NewEpoch, commitment(true)],
+            ]
+            .concat(),
+        ),
+        // More commitments than fit in the lowest four levels of a block, keeping a few of them.
+        TestVector::generate(
+            "full subtrees",
+            (0..300).map(|i| commitment(i % 64 == 0)).collect(),
+        ),
+    ]
+}
+
+impl Action {
+    fn insert(commitment: Commitment, keep: bool) -> Action {
+        Action::Insert {
+            commitment: encode(commitment.0),
+            keep,
+        }
+    }
+
+    fn apply(&self, tree: &mut Eternity) -> Result<(), ()> {
+        match self {
+            Action::Insert { commitment, keep } => {
+                let commitment = Commitment(decode(commitment).map_err(|_| ())?);
+                let witness = if *keep { Keep } else { Forget };
+                tree.insert(witness, commitment).map(|_| ()).map_err(|_| ())
+            }
+            Action::NewBlock => tree.insert_block(Block::new()).map_err(|_| ()),
+            Action::NewEpoch => tree.insert_epoch(Epoch::new()).map_err(|_| ()),
+        }
+    }
+}
+
+impl TestVector {
+    /// Applies the actions to an empty tree, recording its roots and final proofs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an action cannot be applied.
+    pub fn generate(name: &str, actions: Vec<Action>) -> TestVector {
+        let mut tree = Eternity::new();
+        let mut kept = Vec::new();
+        let mut steps = Vec::new();
+        for action in actions {
+            action
+                .apply(&mut tree)
+                .unwrap_or_else(|_| panic!("could not apply {:?} in {:?}", action, name));
+            if let Action::Insert {
+                commitment,
+                keep: true,
+            } = &action
+            {
+                kept.push(Commitment(decode(commitment).unwrap()));
+            }
+            steps.push(Step {
+                action,
+                root: tree.root().to_string(),
+            });
+        }
+
+        let proofs = kept
+            .into_iter()
+            .map(|commitment| {
+                let proof = tree
+                    .witness(commitment)
+                    .expect("kept commitment is witnessed");
+                ProofVector::from(&proof)
+            })
+            .collect();
+
+        TestVector {
+            name: name.to_string(),
+            steps,
+            proofs,
+        }
+    }
+
+    /// Checks that this implementation computes the vector's roots after each step, that it
+    /// witnesses exactly the vector's proofs at the end, and that they verify against its root.
+    pub fn check(&self) -> Result<(), VectorError> {
+        let mut tree = Eternity::new();
+        for (step, Step { action, root }) in self.steps.iter().enumerate() {
+            action
+                .apply(&mut tree)
+                .map_err(|_| VectorError::InvalidAction { step })?;
+            let actual = tree.root().to_string();
+            if actual != *root {
+                return Err(VectorError::Root {
+                    step,
+                    expected: root.clone(),
+                    actual,
+                });
+            }
+        }
+
+        if self.proofs.len() != tree.witnessed_count() {
+            return Err(VectorError::Witnessed {
+                expected: self.proofs.len(),
+                actual: tree.witnessed_count(),
+            });
+        }
+        let root = tree.root();
+        for vector in &self.proofs {
+            let mismatch = || VectorError::Proof {
+                commitment: vector.commitment.clone(),
+            };
+            let proof = vector.to_proof()?;
+            proof.verify(root).map_err(|_| mismatch())?;
+            if tree.witness(proof.commitment()) != Some(proof) {
+                return Err(mismatch());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&Proof> for ProofVector {
+    fn from(proof: &Proof) -> Self {
+        let position = proof.position();
+        ProofVector {
+            commitment: encode(proof.commitment().0),
+            epoch: position.epoch(),
+            block: position.block(),
+            index: position.commitment(),
+            auth_path: proof
+                .auth_path()
+                .into_iter()
+                .map(|siblings| (*siblings).map(|hash| encode(hash.into())))
+                .collect(),
+        }
+    }
+}
+
+impl ProofVector {
+    /// Decodes the proof.
+    pub fn to_proof(&self) -> Result<Proof, VectorError> {
+        let invalid = || VectorError::Proof {
+            commitment: self.commitment.clone(),
+        };
+        let commitment = Commitment(decode(&self.commitment)?);
+        let position = Position::new(self.epoch.into(), self.block.into(), self.index.into())
+            .map_err(|_| invalid())?;
+        let auth_path: [[Hash; 3]; 24] = self
+            .auth_path
+            .iter()
+            .map(|siblings| -> Result<[Hash; 3], VectorError> {
+                let [a, b, c] = siblings;
+                Ok([
+                    Hash::new(decode(a)?),
+                    Hash::new(decode(b)?),
+                    Hash::new(decode(c)?),
+                ])
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .try_into()
+            .map_err(|_| invalid())?;
+        Ok(Proof::new(commitment, position, auth_path))
+    }
+}
+
+/// The `n`th commitment of the vectors.
+fn commit(n: u64) -> Commitment {
+    Commitment(Fq::from_le_bytes_mod_order(&n.to_le_bytes()))
+}
+
+fn encode(fq: Fq) -> String {
+    hex::encode(fq.to_bytes())
+}
+
+fn decode(hex: &str) -> Result<Fq, VectorError> {
+    let invalid = || VectorError::InvalidElement(hex.to_string());
+    let bytes: [u8; 32] = hex::decode(hex)
+        .map_err(|_| invalid())?
+        .try_into()
+        .map_err(|_| invalid())?;
+    Fq::from_bytes(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Set to regenerate the checked-in vectors, after a deliberate change to the tree's hashing.
+    const UPDATE_VECTORS: &str = "PENUMBRA_TCT_UPDATE_VECTORS";
+
+    const VECTORS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/vectors/vectors.json");
+
+    #[test]
+    fn checked_in_vectors_are_current() {
+        let generated = generate();
+        if std::env::var_os(UPDATE_VECTORS).is_some() {
+            let json = serde_json::to_string_pretty(&generated).unwrap();
+            std::fs::write(VECTORS_PATH, json + "\n").unwrap();
+        }
+
+        let json = std::fs::read_to_string(VECTORS_PATH).unwrap_or_else(|_| {
+            panic!(
+                "{} is missing: run the tests with {} set to write it",
+                VECTORS_PATH, UPDATE_VECTORS
+            )
+        });
+        let checked_in: Vec<TestVector> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            checked_in, generated,
+            "the tree no longer matches the checked-in vectors"
+        );
+    }
+
+    #[test]
+    fn generated_vectors_check() {
+        for vector in generate() {
+            if let Err(error) = vector.check() {
+                panic!("vector {:?}: {}", vector.name, error);
+            }
+        }
+    }
+
+    #[test]
+    fn check_detects_a_wrong_root() {
+        let mut vector =
+            TestVector::generate("one commitment", vec![Action::insert(commit(1), true)]);
+        vector.steps[0].root = vector.steps[0].root.replace(|c| c != '0', "0");
+        assert!(matches!(
+            vector.check(),
+            Err(VectorError::Root { step: 0, .. })
+        ));
+    }
+}
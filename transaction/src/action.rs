@@ -1,13 +1,18 @@
 use std::convert::{TryFrom, TryInto};
 
 use penumbra_crypto::value;
+#[cfg(feature = "ibc")]
 use penumbra_ibc as ibc;
 use penumbra_proto::{transaction as pb, Protobuf};
 use penumbra_stake as stake;
 
+pub mod faucet_claim;
+#[cfg(not(feature = "ibc"))]
+pub mod ibc;
 pub mod output;
 pub mod spend;
 
+pub use faucet_claim::FaucetClaim;
 pub use output::Output;
 pub use spend::Spend;
 
@@ -20,6 +25,7 @@ pub enum Action {
     Undelegate(stake::Undelegate),
     ValidatorDefinition(stake::ValidatorDefinition),
     IBCAction(ibc::IBCAction),
+    FaucetClaim(faucet_claim::FaucetClaim),
 }
 
 impl Action {
@@ -34,6 +40,8 @@ impl Action {
             Action::ValidatorDefinition(_) => value::Commitment::default(),
             // TODO: should IBC actions have value commitments?
             Action::IBCAction(_) => value::Commitment::default(),
+            // Claimed tokens are minted by the faucet, outside the transaction.
+            Action::FaucetClaim(_) => value::Commitment::default(),
         }
     }
 }
@@ -61,6 +69,9 @@ impl From<Action> for pb::Action {
             Action::IBCAction(inner) => pb::Action {
                 action: Some(pb::action::Action::IbcAction(inner.into())),
             },
+            Action::FaucetClaim(inner) => pb::Action {
+                action: Some(pb::action::Action::FaucetClaim(inner.into())),
+            },
         }
     }
 }
@@ -83,6 +94,7 @@ impl TryFrom<pb::Action> for Action {
                 Ok(Action::ValidatorDefinition(inner.try_into()?))
             }
             pb::action::Action::IbcAction(inner) => Ok(Action::IBCAction(inner.try_into()?)),
+            pb::action::Action::FaucetClaim(inner) => Ok(Action::FaucetClaim(inner.try_into()?)),
         }
     }
 }
@@ -0,0 +1,41 @@
+use std::convert::{TryFrom, TryInto};
+
+use anyhow::Error;
+use penumbra_crypto::Address;
+use penumbra_proto::{transaction as pb, Protobuf};
+
+/// A claim of tokens from the chain's faucet.
+///
+/// The faucet mints the claimed tokens as a note to the address, so a claim
+/// does not change the transaction's value balance.
+#[derive(Clone, Debug)]
+pub struct FaucetClaim {
+    pub address: Address,
+    /// The amount of the staking token claimed.
+    pub amount: u64,
+}
+
+impl Protobuf<pb::FaucetClaim> for FaucetClaim {}
+
+impl From<FaucetClaim> for pb::FaucetClaim {
+    fn from(msg: FaucetClaim) -> Self {
+        pb::FaucetClaim {
+            address: Some(msg.address.into()),
+            amount: msg.amount,
+        }
+    }
+}
+
+impl TryFrom<pb::FaucetClaim> for FaucetClaim {
+    type Error = Error;
+
+    fn try_from(proto: pb::FaucetClaim) -> anyhow::Result<Self, Self::Error> {
+        Ok(FaucetClaim {
+            address: proto
+                .address
+                .ok_or_else(|| anyhow::anyhow!("missing faucet claim address"))?
+                .try_into()?,
+            amount: proto.amount,
+        })
+    }
+}
@@ -0,0 +1,34 @@
+//! IBC actions, in builds without the `ibc` feature.
+//!
+//! Such builds cannot execute IBC actions, but must still decode and re-encode
+//! the transactions carrying them exactly as full builds do, so they keep the
+//! action's protobuf as is rather than depending on `penumbra-ibc`.
+
+use penumbra_proto::{ibc as pb, Protobuf};
+
+/// An IBC action, kept as its protobuf.
+#[derive(Clone, Debug)]
+pub struct IBCAction {
+    pub action: pb::ibc_action::Action,
+}
+
+impl Protobuf<pb::IbcAction> for IBCAction {}
+
+impl From<IBCAction> for pb::IbcAction {
+    fn from(i: IBCAction) -> Self {
+        pb::IbcAction {
+            action: Some(i.action),
+        }
+    }
+}
+
+impl TryFrom<pb::IbcAction> for IBCAction {
+    type Error = anyhow::Error;
+    fn try_from(d: pb::IbcAction) -> Result<Self, Self::Error> {
+        Ok(Self {
+            action: d
+                .action
+                .ok_or_else(|| anyhow::anyhow!("IBCAction.action is missing"))?,
+        })
+    }
+}
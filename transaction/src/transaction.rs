@@ -17,7 +17,10 @@ use penumbra_proto::{
 };
 use penumbra_stake::{Delegate, Undelegate, ValidatorDefinition, STAKING_TOKEN_ASSET_ID};
 
-use crate::{action::output, Action};
+use crate::{
+    action::{output, FaucetClaim},
+    Action,
+};
 
 mod builder;
 pub use builder::Builder;
@@ -63,6 +66,7 @@ impl Transaction {
             delegations: Vec::new(),
             undelegations: Vec::new(),
             validator_definitions: Vec::new(),
+            faucet_claims: Vec::new(),
             fee: None,
             synthetic_blinding_factor: Fr::zero(),
             value_balance: decaf377::Element::default(),
@@ -107,6 +111,16 @@ impl Transaction {
         })
     }
 
+    pub fn faucet_claims(&self) -> impl Iterator<Item = &FaucetClaim> {
+        self.actions().filter_map(|action| {
+            if let Action::FaucetClaim(c) = action {
+                Some(c)
+            } else {
+                None
+            }
+        })
+    }
+
     pub fn output_bodies(&self) -> Vec<output::Body> {
         self.transaction_body
             .actions
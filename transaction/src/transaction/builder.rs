@@ -15,7 +15,7 @@ use rand::seq::SliceRandom;
 use rand_core::{CryptoRng, RngCore};
 
 use crate::{
-    action::{spend, Action, Output, Spend},
+    action::{spend, Action, FaucetClaim, Output, Spend},
     Error, Fee, Transaction, TransactionBody,
 };
 
@@ -32,6 +32,8 @@ pub struct Builder {
     pub undelegations: Vec<Undelegate>,
     /// List of validator (re-)definitions in the transaction.
     pub validator_definitions: Vec<ValidatorDefinition>,
+    /// List of faucet claims in the transaction.
+    pub faucet_claims: Vec<FaucetClaim>,
     /// Transaction fee. None if unset.
     pub fee: Option<Fee>,
     /// Sum of blinding factors for each value commitment.
@@ -181,6 +183,16 @@ impl Builder {
         self
     }
 
+    /// Claim `amount` of the staking token from the faucet, to be minted to
+    /// `address`.
+    ///
+    /// The claimed tokens are minted by the chain rather than spent by the
+    /// transaction, so this does not change its value balance.
+    pub fn add_faucet_claim(&mut self, address: Address, amount: u64) -> &mut Self {
+        self.faucet_claims.push(FaucetClaim { address, amount });
+        self
+    }
+
     /// Set the transaction fee in PEN.
     ///
     /// Note that we're using the lower case `pen` in the code.
@@ -290,6 +302,9 @@ impl Builder {
                 .expect("expected identity key within validator definition to have signed validator definition");
             actions.push(Action::ValidatorDefinition(vd.clone()));
         }
+        for claim in self.faucet_claims.drain(..) {
+            actions.push(Action::FaucetClaim(claim));
+        }
 
         let mut transaction_body = TransactionBody {
             actions,
@@ -0,0 +1,23 @@
+-- Secret material encrypted under a key derived from the wallet passphrase.
+--
+-- The `pending_*` columns hold the re-encrypted values during a passphrase
+-- rotation: they are written in one transaction and swapped into place in a
+-- second, so an interrupted rotation can always be rolled forward.
+
+CREATE TABLE passphrase (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    salt BLOB NOT NULL,
+    check_nonce BLOB NOT NULL,
+    check_ciphertext BLOB NOT NULL,
+    pending_salt BLOB,
+    pending_check_nonce BLOB,
+    pending_check_ciphertext BLOB
+);
+
+CREATE TABLE secrets (
+    name TEXT PRIMARY KEY NOT NULL,
+    nonce BLOB NOT NULL,
+    ciphertext BLOB NOT NULL,
+    pending_nonce BLOB,
+    pending_ciphertext BLOB
+);
@@ -0,0 +1,19 @@
+-- The node's note commitment tree anchor at each recently synced height, along
+-- with the serialized state of the wallet's own tree at that height, so that
+-- local state can be rewound if the chain is reset or rolled back.
+
+CREATE TABLE sync_checkpoints (
+    height INTEGER PRIMARY KEY NOT NULL,
+    anchor BLOB NOT NULL,
+    nct BLOB NOT NULL
+);
+
+-- The most recently detected divergence between the wallet and the node, if
+-- any. A null `last_common_height` means no checkpoint matches the node's
+-- chain, so the wallet must resync from scratch.
+
+CREATE TABLE sync_divergence (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    last_common_height INTEGER,
+    divergent_height INTEGER NOT NULL
+);
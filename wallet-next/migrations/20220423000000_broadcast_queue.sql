@@ -0,0 +1,17 @@
+-- Transactions submitted to the wallet for broadcast, together with their
+-- broadcast status. Transactions are queued until the node accepts them, and
+-- are retried with exponential backoff while the node is unreachable, until
+-- the chain passes their expiry height.
+
+CREATE TABLE broadcast_queue (
+    tx_hash BLOB PRIMARY KEY NOT NULL,
+    tx BLOB NOT NULL,
+    expiry_height INTEGER NOT NULL,
+    -- One of `queued`, `broadcast`, `rejected`, or `expired`.
+    status TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    -- Unix timestamps, in milliseconds.
+    submitted_at INTEGER NOT NULL,
+    next_attempt_at INTEGER NOT NULL,
+    last_error TEXT
+);
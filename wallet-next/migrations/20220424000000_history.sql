@@ -0,0 +1,23 @@
+-- The wallet's transaction history, as categorized ledger entries. A single
+-- transaction may produce several entries, e.g. a send and its fee, or the
+-- debit of the staking token and the credit of the delegation token when
+-- delegating.
+
+CREATE TABLE history (
+    id INTEGER PRIMARY KEY NOT NULL,
+    height INTEGER NOT NULL,
+    -- The block time, as a Unix timestamp in seconds.
+    block_time INTEGER NOT NULL,
+    tx_hash BLOB NOT NULL,
+    -- One of `receive`, `send`, `fee`, `staking_reward`, `delegate`, or `undelegate`.
+    category TEXT NOT NULL,
+    -- The base denomination of the amount.
+    denom TEXT NOT NULL,
+    -- In base units.
+    amount INTEGER NOT NULL,
+    -- Whether the amount was credited to (rather than debited from) the wallet.
+    credit BOOLEAN NOT NULL,
+    memo TEXT NOT NULL
+);
+
+CREATE INDEX history_height ON history (height);
@@ -0,0 +1,49 @@
+-- Spending limits and approval rules, evaluated before the wallet signs a
+-- spend. Rules with a NULL account apply to the wallet as a whole, in addition
+-- to any rules for the spending account.
+
+CREATE TABLE spending_limits (
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- The account the limits apply to, or NULL for the whole wallet.
+    account INTEGER,
+    -- The base denomination the limits are expressed in.
+    denom TEXT NOT NULL,
+    -- All in base units; NULL when there is no such limit.
+    max_per_tx INTEGER,
+    max_per_day INTEGER,
+    -- Spends of more than this amount need manual approval.
+    approval_threshold INTEGER
+);
+
+-- If a scope has any allowed destinations, spends from it may only go to them.
+CREATE TABLE allowed_destinations (
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- The account the entry applies to, or NULL for the whole wallet.
+    account INTEGER,
+    address TEXT NOT NULL
+);
+
+-- Every spend the policy has authorized, counted against daily limits.
+CREATE TABLE authorized_spends (
+    id INTEGER PRIMARY KEY NOT NULL,
+    account INTEGER NOT NULL,
+    denom TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    -- Unix timestamp in milliseconds.
+    authorized_at INTEGER NOT NULL
+);
+
+CREATE INDEX authorized_spends_denom ON authorized_spends (denom, authorized_at);
+
+-- Spends held for manual approval.
+CREATE TABLE pending_spends (
+    id INTEGER PRIMARY KEY NOT NULL,
+    account INTEGER NOT NULL,
+    destination TEXT NOT NULL,
+    denom TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    -- Why the spend needs approval.
+    reason TEXT NOT NULL,
+    -- Unix timestamp in milliseconds.
+    requested_at INTEGER NOT NULL
+);
@@ -0,0 +1,15 @@
+-- Addresses of other wallets, watched through imported address viewing keys.
+-- The wallet can detect and decrypt notes sent to these addresses, but cannot
+-- spend them.
+
+CREATE TABLE watched_addresses (
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- A name for the address, chosen by whoever imported the key.
+    label TEXT NOT NULL,
+    -- The Bech32m-encoded address viewing key.
+    viewing_key TEXT NOT NULL UNIQUE,
+    -- The watched address, derived from the key.
+    address TEXT NOT NULL,
+    -- Unix timestamp in milliseconds.
+    imported_at INTEGER NOT NULL
+);
@@ -0,0 +1,37 @@
+-- Notifications of payment events, queued for delivery to the webhooks
+-- pwalletd is configured with. Each event is recorded once per webhook URL,
+-- and retried with exponential backoff until the URL accepts it.
+
+CREATE TABLE webhook_deliveries (
+    id INTEGER PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL,
+    -- One of `payment.detected`, `payment.confirmed`, or `payment.spent`.
+    event TEXT NOT NULL,
+    -- The JSON body to POST.
+    payload TEXT NOT NULL,
+    -- One of `pending`, `delivered`, or `failed`.
+    status TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    -- Unix timestamps, in milliseconds.
+    created_at INTEGER NOT NULL,
+    next_attempt_at INTEGER NOT NULL,
+    last_error TEXT
+);
+
+CREATE INDEX webhook_deliveries_due ON webhook_deliveries (status, next_attempt_at);
+
+-- How far through the history webhook events have been generated: entries up
+-- to `last_history_id` have been reported as detected or spent, and receipts
+-- at heights up to `confirmed_height` have been reported as confirmed.
+--
+-- The cursor starts at the end of the existing history, so that enabling
+-- webhooks does not replay payments received before this migration.
+
+CREATE TABLE webhook_cursor (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+    last_history_id INTEGER NOT NULL,
+    confirmed_height INTEGER NOT NULL
+);
+
+INSERT INTO webhook_cursor ( id, last_history_id, confirmed_height )
+SELECT 0, COALESCE(MAX(id), 0), COALESCE(MAX(height), -1) FROM history;
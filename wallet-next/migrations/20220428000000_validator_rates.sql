@@ -0,0 +1,11 @@
+-- The latest known exchange and reward rates of each validator, e.g. as
+-- imported from a wallet checkpoint, so that a wallet which did not sync the
+-- epochs before its checkpoint can still value its delegations.
+
+CREATE TABLE validator_rates (
+    -- The Bech32-encoded identity key of the validator.
+    identity_key TEXT PRIMARY KEY NOT NULL,
+    epoch_index INTEGER NOT NULL,
+    validator_reward_rate INTEGER NOT NULL,
+    validator_exchange_rate INTEGER NOT NULL
+);
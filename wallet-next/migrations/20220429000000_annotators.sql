@@ -0,0 +1,23 @@
+-- Plugins registered to annotate transactions before they are recorded in the
+-- history, each serving the annotator RPC on a loopback endpoint.
+
+CREATE TABLE annotators (
+    name TEXT PRIMARY KEY NOT NULL,
+    endpoint TEXT NOT NULL,
+    -- Calls failed since the last success; the annotator is no longer called
+    -- once too many have failed in a row, until it registers again.
+    consecutive_failures INTEGER NOT NULL,
+    last_error TEXT
+);
+
+-- The notes attached by annotators to the transactions in the history.
+
+CREATE TABLE history_annotations (
+    id INTEGER PRIMARY KEY NOT NULL,
+    tx_hash BLOB NOT NULL,
+    annotator TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+
+CREATE INDEX history_annotations_tx_hash ON history_annotations (tx_hash);
@@ -0,0 +1,14 @@
+-- Transactions being composed, shared between the frontends connected to the
+-- wallet, so that a draft started in one can be finished in another.
+
+CREATE TABLE drafts (
+    id INTEGER PRIMARY KEY NOT NULL,
+    label TEXT NOT NULL,
+    -- The draft as encoded by the frontend; opaque to the wallet.
+    contents BLOB NOT NULL,
+    -- Incremented by each update, so that a frontend editing a stale copy of
+    -- the draft cannot overwrite another's changes.
+    version INTEGER NOT NULL,
+    -- Unix timestamp in milliseconds.
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,249 @@
+//! Denomination-aware conversion between base-unit amounts and their display form.
+//!
+//! Every frontend (and the CLI) should go through a [`Formatter`] when displaying or accepting
+//! amounts, so that they all agree on which display unit is chosen and how fractional amounts are
+//! rounded.
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use penumbra_crypto::{asset, Value};
+
+/// Formats and parses [`Value`]s using the display units from asset metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Formatter {
+    /// The character inserted between groups of three integer digits, if any.
+    pub thousands_separator: Option<char>,
+    /// The character separating the integer and fractional parts of an amount.
+    pub decimal_point: char,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self {
+            thousands_separator: None,
+            decimal_point: '.',
+        }
+    }
+}
+
+impl Formatter {
+    /// A formatter grouping thousands with `separator`, e.g. `1,000.5penumbra` for `,`.
+    pub fn with_thousands_separator(separator: char) -> Self {
+        Self {
+            thousands_separator: Some(separator),
+            ..Default::default()
+        }
+    }
+
+    /// Format a [`Value`] in the best display unit for its amount.
+    ///
+    /// Returns `None` if the asset is not in the provided [`asset::Cache`].
+    pub fn format_value(&self, value: &Value, cache: &asset::Cache) -> Option<String> {
+        let denom = cache.get(&value.asset_id)?;
+        Some(self.format_in_unit(value.amount, &denom.best_unit_for(value.amount)))
+    }
+
+    /// Format an amount of base units in a specific display unit.
+    pub fn format_in_unit(&self, amount: u64, unit: &asset::Unit) -> String {
+        format!("{}{}", self.format_amount(amount, unit), unit)
+    }
+
+    /// Format an amount of base units in a specific display unit, without the unit suffix.
+    pub fn format_amount(&self, amount: u64, unit: &asset::Unit) -> String {
+        let formatted = unit.format_value(amount);
+        let (integer, fraction) = match formatted.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (formatted.as_str(), None),
+        };
+
+        let mut output = self.group_thousands(integer);
+        if let Some(fraction) = fraction {
+            output.push(self.decimal_point);
+            output.push_str(fraction);
+        }
+        output
+    }
+
+    /// Parse a string like `1.5penumbra` into a [`Value`] denominated in base units.
+    ///
+    /// Thousands separators are ignored, and amounts with more fractional digits than the display
+    /// unit can represent are rejected rather than rounded.
+    pub fn parse_value(&self, input: &str) -> anyhow::Result<Value> {
+        Value::from_str(&self.normalize(input.trim())?)
+    }
+
+    /// Parse an amount in a specific display unit into a number of base units.
+    pub fn parse_amount(&self, input: &str, unit: &asset::Unit) -> anyhow::Result<u64> {
+        unit.parse_value(&self.normalize(input.trim())?)
+    }
+
+    /// Parse a string like `1.5penumbra` into a number of base units of the denomination it is
+    /// expressed in.
+    pub fn parse_denominated(&self, input: &str) -> anyhow::Result<(u64, asset::Denom)> {
+        let normalized = self.normalize(input.trim())?;
+        let split = normalized
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|&split| split > 0)
+            .ok_or_else(|| {
+                anyhow!(
+                    "could not parse {:?} as an amount; provide both a number and a unit, e.g. \
+                     1penumbra",
+                    input
+                )
+            })?;
+        let (amount, unit) = normalized.split_at(split);
+        let unit = asset::REGISTRY.parse_unit(unit);
+        Ok((unit.parse_value(amount)?, unit.base()))
+    }
+
+    /// Insert thousands separators into a string of integer digits.
+    fn group_thousands(&self, digits: &str) -> String {
+        let separator = match self.thousands_separator {
+            Some(separator) => separator,
+            None => return digits.to_string(),
+        };
+
+        let mut output = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                output.push(separator);
+            }
+            output.push(digit);
+        }
+        output
+    }
+
+    /// Strip thousands separators and convert the decimal point into the `.` expected by the
+    /// underlying parser.
+    fn normalize(&self, input: &str) -> anyhow::Result<String> {
+        let mut output = String::with_capacity(input.len());
+        for c in input.chars() {
+            if Some(c) == self.thousands_separator {
+                continue;
+            } else if c == self.decimal_point {
+                output.push('.');
+            } else if c == '.' {
+                return Err(anyhow!(
+                    "unexpected '.' in {:?}; the decimal point is {:?}",
+                    input,
+                    self.decimal_point
+                ));
+            } else {
+                output.push(c);
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::asset::REGISTRY;
+
+    use super::*;
+
+    fn upenumbra() -> asset::Denom {
+        REGISTRY.parse_denom("upenumbra").unwrap()
+    }
+
+    fn unit(unit: &str) -> asset::Unit {
+        REGISTRY.parse_unit(unit)
+    }
+
+    #[test]
+    fn formats_in_the_best_unit_without_rounding() {
+        let formatter = Formatter::default();
+        let format = |amount| formatter.format_in_unit(amount, &upenumbra().best_unit_for(amount));
+
+        assert_eq!(format(2_000_000), "2penumbra");
+        assert_eq!(format(1_500_000), "1.5penumbra");
+        // Every base unit is shown, however small a fraction of the unit.
+        assert_eq!(format(1_000_001), "1.000001penumbra");
+        assert_eq!(format(1_500), "1.5mpenumbra");
+        assert_eq!(format(999), "999upenumbra");
+        assert_eq!(format(0), "0upenumbra");
+    }
+
+    #[test]
+    fn formats_with_localized_separators() {
+        let formatter = Formatter::with_thousands_separator(',');
+        assert_eq!(
+            formatter.format_in_unit(1_234_567_500_000, &unit("penumbra")),
+            "1,234,567.5penumbra"
+        );
+        assert_eq!(
+            formatter.format_amount(999_000_000, &unit("penumbra")),
+            "999"
+        );
+
+        let european = Formatter {
+            thousands_separator: Some('.'),
+            decimal_point: ',',
+        };
+        assert_eq!(
+            european.format_in_unit(1_234_500_000, &unit("penumbra")),
+            "1.234,5penumbra"
+        );
+    }
+
+    #[test]
+    fn parses_amounts_in_any_unit() {
+        let formatter = Formatter::with_thousands_separator(',');
+        assert_eq!(
+            formatter.parse_denominated("1,000.5penumbra").unwrap(),
+            (1_000_500_000, upenumbra())
+        );
+        assert_eq!(
+            formatter.parse_denominated(" 1.5mpenumbra ").unwrap(),
+            (1_500, upenumbra())
+        );
+        assert_eq!(
+            formatter.parse_amount("2.25", &unit("penumbra")).unwrap(),
+            2_250_000
+        );
+        let value = formatter.parse_value("2penumbra").unwrap();
+        assert_eq!(value.amount, 2_000_000);
+        assert_eq!(value.asset_id, upenumbra().id());
+
+        let european = Formatter {
+            thousands_separator: Some('.'),
+            decimal_point: ',',
+        };
+        assert_eq!(
+            european.parse_denominated("1.000,5penumbra").unwrap(),
+            (1_000_500_000, upenumbra())
+        );
+    }
+
+    #[test]
+    fn rejects_amounts_it_would_have_to_round() {
+        let formatter = Formatter::default();
+        // Digits finer than the base unit are rejected rather than rounded.
+        assert!(formatter.parse_denominated("1.0000001penumbra").is_err());
+        assert!(formatter.parse_amount("1.5", &unit("upenumbra")).is_err());
+        // Both a number and a unit are required.
+        assert!(formatter.parse_denominated("penumbra").is_err());
+        assert!(formatter.parse_denominated("15").is_err());
+        // A '.' is not taken for a decimal point when another one is in use.
+        let comma = Formatter {
+            thousands_separator: None,
+            decimal_point: ',',
+        };
+        assert!(comma.parse_amount("1.5", &unit("penumbra")).is_err());
+    }
+
+    #[test]
+    fn parses_what_it_formats() {
+        let formatter = Formatter::with_thousands_separator(',');
+        for amount in [0, 1, 999, 1_000, 1_500_001, 1_234_567_890, u64::MAX] {
+            let formatted = formatter.format_in_unit(amount, &upenumbra().best_unit_for(amount));
+            assert_eq!(
+                formatter.parse_denominated(&formatted).unwrap(),
+                (amount, upenumbra()),
+                "{}",
+                formatted
+            );
+        }
+    }
+}
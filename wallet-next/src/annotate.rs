@@ -0,0 +1,318 @@
+//! Plugins which annotate transactions before they are recorded in the history.
+//!
+//! Tooling built around the wallet, such as invoice matching or tagging
+//! payments with exchange rates, needs to attach its own notes to
+//! transactions, and should not need a fork of the wallet daemon to do so. An
+//! annotator is a separate process serving the `AnnotatorService` RPC on a
+//! loopback endpoint, which it [`register`]s with the wallet. As each
+//! transaction is [`record`]ed in the [`history`](crate::history), every
+//! registered annotator is asked for [`Annotation`]s, which are stored with it.
+//!
+//! Annotators are isolated from the wallet and from each other: they are shown
+//! the transaction but cannot change it, each call is bounded by
+//! [`ANNOTATE_TIMEOUT`] and by how much it may return, and a failed call only
+//! loses that annotator's notes. An annotator which fails
+//! [`MAX_CONSECUTIVE_FAILURES`] calls in a row is suspended, and no longer
+//! called, until it registers again.
+
+use std::{net::IpAddr, time::Duration};
+
+use anyhow::anyhow;
+use penumbra_proto::wallet_next::{self as pb, annotator_service_client::AnnotatorServiceClient};
+use sqlx::sqlite::SqlitePool;
+
+use crate::history::{self, Transaction};
+
+/// How long to wait for an annotator to connect and respond before counting
+/// the call as failed. Annotators are called in turn, so recording a
+/// transaction waits at most this long for each.
+pub const ANNOTATE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The number of calls in a row an annotator may fail before it is suspended.
+pub const MAX_CONSECUTIVE_FAILURES: u64 = 5;
+
+/// The most annotations a single call may return.
+pub const MAX_ANNOTATIONS: usize = 16;
+
+/// The longest key of an annotation, in bytes.
+pub const MAX_KEY_LEN: usize = 64;
+
+/// The longest value of an annotation, in bytes.
+pub const MAX_VALUE_LEN: usize = 1024;
+
+/// A registered annotation plugin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotator {
+    pub name: String,
+    /// The URL of the plugin's `AnnotatorService`.
+    pub endpoint: String,
+    /// The number of calls which have failed since the last one succeeded.
+    pub consecutive_failures: u64,
+    /// The reason for the last failed call.
+    pub last_error: Option<String>,
+}
+
+impl Annotator {
+    /// Whether the annotator has failed too many calls in a row to be called.
+    pub fn suspended(&self) -> bool {
+        self.consecutive_failures >= MAX_CONSECUTIVE_FAILURES
+    }
+}
+
+/// A note attached to a transaction by an annotator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    /// The name of the annotator which attached the note.
+    pub annotator: String,
+    pub key: String,
+    pub value: String,
+}
+
+impl From<Annotation> for pb::Annotation {
+    fn from(annotation: Annotation) -> Self {
+        pb::Annotation {
+            annotator: annotation.annotator,
+            key: annotation.key,
+            value: annotation.value,
+        }
+    }
+}
+
+/// Checks that an annotator is named, and that its endpoint is an HTTP URL on
+/// the loopback interface, so that transactions are only ever shown to
+/// processes on the wallet's own host.
+pub fn check_registration(name: &str, endpoint: &str) -> anyhow::Result<()> {
+    if name.is_empty() {
+        return Err(anyhow!("annotator name must not be empty"));
+    }
+    let url = reqwest::Url::parse(endpoint)
+        .map_err(|e| anyhow!("invalid annotator endpoint {:?}: {}", endpoint, e))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(anyhow!(
+            "annotator endpoint {:?} is not an HTTP URL",
+            endpoint
+        ));
+    }
+    let loopback = match url.host_str() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map_or(false, |ip| ip.is_loopback()),
+        None => false,
+    };
+    if !loopback {
+        return Err(anyhow!(
+            "annotator endpoint {:?} is not on the loopback interface",
+            endpoint
+        ));
+    }
+    Ok(())
+}
+
+/// Register an annotator, replacing any registered under the same name, and
+/// lifting its suspension if it had been suspended.
+pub async fn register(pool: &SqlitePool, name: &str, endpoint: &str) -> anyhow::Result<()> {
+    check_registration(name, endpoint)?;
+    sqlx::query!(
+        r#"
+INSERT INTO annotators ( name, endpoint, consecutive_failures )
+VALUES ( ?1, ?2, 0 )
+ON CONFLICT ( name ) DO UPDATE
+SET endpoint = excluded.endpoint, consecutive_failures = 0, last_error = NULL
+        "#,
+        name,
+        endpoint
+    )
+    .execute(pool)
+    .await?;
+
+    tracing::info!(%name, %endpoint, "registered annotator");
+    Ok(())
+}
+
+/// Stop calling an annotator, returning whether it was registered. The
+/// annotations it has already attached are kept.
+pub async fn unregister(pool: &SqlitePool, name: &str) -> anyhow::Result<bool> {
+    let removed = sqlx::query!(
+        r#"
+DELETE FROM annotators
+WHERE name = ?1
+        "#,
+        name
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(removed > 0)
+}
+
+/// Every registered annotator, by name.
+pub async fn annotators(pool: &SqlitePool) -> anyhow::Result<Vec<Annotator>> {
+    let rows = sqlx::query!(
+        r#"
+SELECT name, endpoint, consecutive_failures, last_error
+FROM annotators
+ORDER BY name
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Annotator {
+            name: row.name,
+            endpoint: row.endpoint,
+            consecutive_failures: row.consecutive_failures as u64,
+            last_error: row.last_error,
+        })
+        .collect())
+}
+
+/// The annotations attached to a transaction, in the order they were recorded.
+pub async fn annotations(pool: &SqlitePool, tx_hash: &[u8]) -> anyhow::Result<Vec<Annotation>> {
+    let rows = sqlx::query!(
+        r#"
+SELECT annotator, key, value
+FROM history_annotations
+WHERE tx_hash = ?1
+ORDER BY id
+        "#,
+        tx_hash
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Annotation {
+            annotator: row.annotator,
+            key: row.key,
+            value: row.value,
+        })
+        .collect())
+}
+
+/// Record the line items of a transaction in the history, together with the
+/// annotations every registered annotator which is not suspended attaches to
+/// it, returning the annotations.
+///
+/// A failed call to an annotator is logged and counted against it, but the
+/// transaction is recorded regardless.
+pub async fn record(
+    pool: &SqlitePool,
+    transaction: &Transaction,
+) -> anyhow::Result<Vec<Annotation>> {
+    let request = pb::AnnotateRequest {
+        transaction: Some(transaction.clone().into()),
+    };
+
+    let mut annotations = Vec::new();
+    for annotator in annotators(pool).await? {
+        if annotator.suspended() {
+            continue;
+        }
+        let result = match tokio::time::timeout(
+            ANNOTATE_TIMEOUT,
+            call(&annotator.endpoint, request.clone()),
+        )
+        .await
+        {
+            Ok(result) => result.and_then(check_annotations),
+            Err(_) => Err(anyhow!("timed out after {:?}", ANNOTATE_TIMEOUT)),
+        };
+
+        let (consecutive_failures, last_error) = match result {
+            Ok(returned) => {
+                annotations.extend(returned.into_iter().map(|(key, value)| Annotation {
+                    annotator: annotator.name.clone(),
+                    key,
+                    value,
+                }));
+                (0, None)
+            }
+            Err(e) => {
+                tracing::warn!(name = %annotator.name, ?e, "annotator failed");
+                (
+                    annotator.consecutive_failures as i64 + 1,
+                    Some(e.to_string()),
+                )
+            }
+        };
+        sqlx::query!(
+            r#"
+UPDATE annotators
+SET consecutive_failures = ?1, last_error = ?2
+WHERE name = ?3
+            "#,
+            consecutive_failures,
+            last_error,
+            annotator.name
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    let mut tx = pool.begin().await?;
+    for entry in &transaction.line_items {
+        history::insert(&mut tx, entry).await?;
+    }
+    for annotation in &annotations {
+        sqlx::query!(
+            r#"
+INSERT INTO history_annotations ( tx_hash, annotator, key, value )
+VALUES ( ?1, ?2, ?3, ?4 )
+            "#,
+            transaction.tx_hash,
+            annotation.annotator,
+            annotation.key,
+            annotation.value
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(annotations)
+}
+
+/// Ask the annotator at `endpoint` to annotate a transaction.
+async fn call(endpoint: &str, request: pb::AnnotateRequest) -> anyhow::Result<Vec<pb::Annotation>> {
+    let mut client = AnnotatorServiceClient::connect(endpoint.to_string()).await?;
+    Ok(client.annotate(request).await?.into_inner().annotations)
+}
+
+/// The keys and values of the annotations an annotator returned, if it stayed
+/// within the limits on how many it may return, and how large they may be.
+fn check_annotations(annotations: Vec<pb::Annotation>) -> anyhow::Result<Vec<(String, String)>> {
+    if annotations.len() > MAX_ANNOTATIONS {
+        return Err(anyhow!(
+            "returned {} annotations, more than the {} allowed",
+            annotations.len(),
+            MAX_ANNOTATIONS
+        ));
+    }
+    annotations
+        .into_iter()
+        .map(|annotation| {
+            if annotation.key.is_empty() || annotation.key.len() > MAX_KEY_LEN {
+                return Err(anyhow!(
+                    "returned an annotation key of {} bytes, not between 1 and {}",
+                    annotation.key.len(),
+                    MAX_KEY_LEN
+                ));
+            }
+            if annotation.value.len() > MAX_VALUE_LEN {
+                return Err(anyhow!(
+                    "returned an annotation value of {} bytes, more than the {} allowed",
+                    annotation.value.len(),
+                    MAX_VALUE_LEN
+                ));
+            }
+            Ok((annotation.key, annotation.value))
+        })
+        .collect()
+}
@@ -0,0 +1,277 @@
+//! Passphrase-encrypted archives of the whole wallet, for moving it between
+//! devices.
+//!
+//! An archive holds every row of every wallet table: the keystore (whose
+//! secrets stay encrypted under the wallet passphrase), the synced state and
+//! sync checkpoints, the transaction history, and the wallet's settings, such
+//! as its spending policy and watched addresses. Restoring one on another
+//! device therefore needs no resync. The rows are serialized canonically, so
+//! the same wallet state always produces the same plaintext.
+//!
+//! The plaintext is encrypted with ChaCha20-Poly1305 under a key derived from
+//! the wallet passphrase. The archive's header, which carries the format
+//! version and key derivation salt, is authenticated along with it, so any
+//! modification of the archive is detected on import.
+
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Context};
+use serde_json::{json, Value};
+use sqlx::{
+    sqlite::{SqliteConnection, SqlitePool},
+    Row,
+};
+
+use crate::keystore::{self, PassphraseKey};
+
+/// The bytes every archive starts with.
+const MAGIC: &[u8] = b"PWALLET";
+
+/// The version of the archive format produced by [`export`].
+pub const FORMAT_VERSION: u8 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// What an [`import`] restored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub tables: u64,
+    pub rows: u64,
+}
+
+/// A value as rendered by SQLite's `quote()` function.
+enum Literal {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// Export the whole wallet as an archive encrypted under the wallet passphrase.
+pub async fn export(pool: &SqlitePool, passphrase: &str) -> anyhow::Result<Vec<u8>> {
+    keystore::check_passphrase(pool, passphrase).await?;
+
+    let mut conn = pool.acquire().await?;
+    let schema_version = schema_version(&mut conn).await?;
+    let mut tables = serde_json::Map::new();
+    for table in table_names(&mut conn).await? {
+        let columns = column_names(&mut conn, &table).await?;
+        let select = format!(
+            "SELECT {} FROM \"{}\" ORDER BY rowid",
+            columns
+                .iter()
+                .map(|column| format!("quote(\"{}\")", column))
+                .collect::<Vec<_>>()
+                .join(", "),
+            table
+        );
+        let rows = sqlx::query(&select)
+            .fetch_all(&mut *conn)
+            .await?
+            .iter()
+            .map(|row| {
+                (0..columns.len())
+                    .map(|i| row.try_get::<String, _>(i))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        tables.insert(table, json!({ "columns": columns, "rows": rows }));
+    }
+
+    // Object keys are sorted, so the serialization is canonical.
+    let plaintext = serde_json::to_vec(&json!({
+        "schema_version": schema_version,
+        "tables": tables,
+    }))?;
+
+    let salt = keystore::new_salt();
+    let header = [MAGIC, &[FORMAT_VERSION], &salt].concat();
+    let (nonce, ciphertext) =
+        PassphraseKey::derive(passphrase, &salt).encrypt_with_aad(&plaintext, &header);
+
+    tracing::info!(bytes = plaintext.len(), "exported wallet");
+    Ok([header, nonce, ciphertext].concat())
+}
+
+/// Replace the contents of the wallet with those of an archive.
+///
+/// The passphrase is the one the archive was exported with, which becomes the
+/// wallet passphrase. Archives exported by older versions of the wallet can
+/// be imported, as long as the wallet's migrations since then have only added
+/// tables and columns with defaults; archives exported by newer versions
+/// cannot.
+pub async fn import(
+    pool: &SqlitePool,
+    passphrase: &str,
+    archive: &[u8],
+) -> anyhow::Result<Summary> {
+    let header_len = MAGIC.len() + 1 + SALT_LEN;
+    if archive.len() < header_len + NONCE_LEN || !archive.starts_with(MAGIC) {
+        return Err(anyhow!("not a wallet archive"));
+    }
+    let (header, rest) = archive.split_at(header_len);
+    let format_version = header[MAGIC.len()];
+    if format_version != FORMAT_VERSION {
+        return Err(anyhow!(
+            "unsupported archive format version {}, expected {}",
+            format_version,
+            FORMAT_VERSION
+        ));
+    }
+    let salt = &header[MAGIC.len() + 1..];
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let plaintext = PassphraseKey::derive(passphrase, salt)
+        .decrypt_with_aad(nonce, ciphertext, header)
+        .map_err(|_| anyhow!("incorrect passphrase, or the archive is corrupt"))?;
+    let contents: Value = serde_json::from_slice(&plaintext).context("invalid archive contents")?;
+
+    let mut tx = pool.begin().await?;
+
+    let archive_version = contents["schema_version"].as_i64().unwrap_or_default();
+    let wallet_version = schema_version(&mut tx).await?;
+    if archive_version > wallet_version {
+        return Err(anyhow!(
+            "archive is from a newer wallet (schema version {}, this wallet has {})",
+            archive_version,
+            wallet_version
+        ));
+    }
+
+    let wallet_tables = table_names(&mut tx).await?;
+    for table in &wallet_tables {
+        sqlx::query(&format!("DELETE FROM \"{}\"", table))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let mut summary = Summary::default();
+    let tables = contents["tables"]
+        .as_object()
+        .ok_or_else(|| anyhow!("invalid archive contents"))?;
+    for (table, table_contents) in tables {
+        if !wallet_tables.contains(table) {
+            return Err(anyhow!("archive has unknown table {:?}", table));
+        }
+        let wallet_columns = column_names(&mut tx, table)
+            .await?
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        let columns = string_array(&table_contents["columns"])?;
+        if let Some(column) = columns.iter().find(|c| !wallet_columns.contains(*c)) {
+            return Err(anyhow!(
+                "archive has unknown column {:?} of table {:?}",
+                column,
+                table
+            ));
+        }
+
+        let insert = format!(
+            "INSERT INTO \"{}\" ( {} ) VALUES ( {} )",
+            table,
+            columns
+                .iter()
+                .map(|column| format!("\"{}\"", column))
+                .collect::<Vec<_>>()
+                .join(", "),
+            (1..=columns.len())
+                .map(|i| format!("?{}", i))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let rows = table_contents["rows"]
+            .as_array()
+            .ok_or_else(|| anyhow!("invalid rows of table {:?}", table))?;
+        for row in rows {
+            let row = string_array(row)?;
+            if row.len() != columns.len() {
+                return Err(anyhow!("row of table {:?} has the wrong length", table));
+            }
+            let mut query = sqlx::query(&insert);
+            for literal in &row {
+                query = match parse_literal(literal)? {
+                    Literal::Null => query.bind(None::<i64>),
+                    Literal::Integer(value) => query.bind(value),
+                    Literal::Real(value) => query.bind(value),
+                    Literal::Text(value) => query.bind(value),
+                    Literal::Blob(value) => query.bind(value),
+                };
+            }
+            query.execute(&mut *tx).await?;
+            summary.rows += 1;
+        }
+        summary.tables += 1;
+    }
+
+    tx.commit().await?;
+
+    tracing::info!(?summary, "imported wallet");
+    Ok(summary)
+}
+
+/// The version of the last migration applied to the wallet database.
+async fn schema_version(conn: &mut SqliteConnection) -> anyhow::Result<i64> {
+    Ok(
+        sqlx::query_scalar::<_, Option<i64>>("SELECT MAX(version) FROM _sqlx_migrations")
+            .fetch_one(&mut *conn)
+            .await?
+            .unwrap_or_default(),
+    )
+}
+
+/// The names of the wallet's tables, in order.
+async fn table_names(conn: &mut SqliteConnection) -> anyhow::Result<Vec<String>> {
+    Ok(sqlx::query_scalar::<_, String>(
+        "SELECT name FROM sqlite_master \
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != '_sqlx_migrations' \
+         ORDER BY name",
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// The names of a table's columns, in order.
+async fn column_names(conn: &mut SqliteConnection, table: &str) -> anyhow::Result<Vec<String>> {
+    Ok(
+        sqlx::query_scalar::<_, String>("SELECT name FROM pragma_table_info(?1) ORDER BY cid")
+            .bind(table)
+            .fetch_all(&mut *conn)
+            .await?,
+    )
+}
+
+fn string_array(value: &Value) -> anyhow::Result<Vec<String>> {
+    value
+        .as_array()
+        .and_then(|values| {
+            values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string))
+                .collect()
+        })
+        .ok_or_else(|| anyhow!("invalid archive contents"))
+}
+
+fn parse_literal(literal: &str) -> anyhow::Result<Literal> {
+    if literal == "NULL" {
+        Ok(Literal::Null)
+    } else if let Some(hex) = literal
+        .strip_prefix("X'")
+        .and_then(|rest| rest.strip_suffix('\''))
+    {
+        Ok(Literal::Blob(hex::decode(hex)?))
+    } else if let Some(text) = literal
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''))
+    {
+        Ok(Literal::Text(text.replace("''", "'")))
+    } else if let Ok(value) = literal.parse::<i64>() {
+        Ok(Literal::Integer(value))
+    } else if let Ok(value) = literal.parse::<f64>() {
+        Ok(Literal::Real(value))
+    } else {
+        Err(anyhow!("invalid value {:?} in archive", literal))
+    }
+}
@@ -1,21 +1,382 @@
-use anyhow::Result;
+use std::{env, net::SocketAddr, path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Result};
+use penumbra_proto::{
+    client::{
+        oblivious::{oblivious_query_client::ObliviousQueryClient, ProtoDescriptorRequest},
+        specific::specific_query_client::SpecificQueryClient,
+    },
+    compat::{self, FileDescriptorSet},
+    wallet_next::wallet_service_server::WalletServiceServer,
+    Message,
+};
+use penumbra_wallet_next::{
+    broadcast, checkpoint, keystore, maintenance,
+    progress::ProgressTracker,
+    proxy::{self, Proxy},
+    reorg, sync,
+    tls::{EndpointPin, Tls},
+    webhook, WalletService, MIGRATOR,
+};
 use sqlx::sqlite::SqlitePool;
-use std::env;
+use structopt::StructOpt;
+use tonic::{transport::Server, Code};
 
-use penumbra_wallet_next::{insert_table, read_table};
+#[derive(Debug, StructOpt)]
+#[structopt(name = "pwalletd", about = "The Penumbra wallet daemon.")]
+struct Opt {
+    /// Bind the wallet RPC service to this address.
+    #[structopt(long, default_value = "127.0.0.1:8081")]
+    bind: SocketAddr,
+    /// The URL of pd's query services, which the wallet syncs from, and checks
+    /// its synced state against.
+    ///
+    /// This may be a Tor onion service, if `--proxy` points at Tor.
+    #[structopt(long)]
+    node: Option<String>,
+    /// The chain ID the wallet expects the node to serve.
+    #[structopt(long, default_value = "")]
+    chain_id: String,
+    /// How often, in seconds, to sync blocks committed since the last sync.
+    #[structopt(long, default_value = "5")]
+    sync_interval: u64,
+    /// How often, in seconds, to check for chain resets and rollbacks.
+    #[structopt(long, default_value = "60")]
+    divergence_check_interval: u64,
+    /// The URL of the node's Tendermint RPC endpoint, used to broadcast
+    /// submitted transactions.
+    ///
+    /// This may be a Tor onion service, if `--proxy` points at Tor.
+    #[structopt(long)]
+    tendermint_rpc: Option<String>,
+    /// How often, in seconds, to broadcast queued transactions whose next
+    /// attempt is due.
+    #[structopt(long, default_value = "1")]
+    broadcast_interval: u64,
+    /// How often, in seconds, to check the integrity of the wallet database
+    /// and reclaim its free space.
+    #[structopt(long, default_value = "86400")]
+    maintenance_interval: u64,
+    /// Make all connections to the node through this SOCKS5 proxy, e.g.
+    /// `socks5://127.0.0.1:9050` for a local Tor daemon.
+    ///
+    /// Host names are resolved by the proxy, not locally.
+    #[structopt(long)]
+    proxy: Option<Proxy>,
+    /// Trust the PEM-encoded CA certificates in this file, besides the web
+    /// PKI roots, for `https` endpoints: the node, its Tendermint RPC, and
+    /// the checkpoint provider.
+    #[structopt(long, parse(from_os_str))]
+    tls_ca_file: Option<PathBuf>,
+    /// Accept only certificates matching a pin from an endpoint, as
+    /// `<host>:<port>=spki-sha256:<hex>` or `<host>:<port>=cert-sha256:<hex>`.
+    /// May be given more than once, including for the same endpoint.
+    ///
+    /// Pinned endpoints must use `https`, and their certificates need not be
+    /// issued by a trusted CA.
+    #[structopt(long = "tls-pin")]
+    tls_pins: Vec<EndpointPin>,
+    /// Refuse to connect to any endpoint over plaintext `http`, except for
+    /// onion services, which Tor already encrypts.
+    #[structopt(long)]
+    require_tls: bool,
+    /// POST signed notifications of detected, confirmed, and spent payments to
+    /// this URL. May be given more than once.
+    #[structopt(long = "webhook")]
+    webhooks: Vec<String>,
+    /// A file holding the secret with which webhook notifications are signed.
+    /// Required if any `--webhook` is given.
+    #[structopt(long, parse(from_os_str))]
+    webhook_secret_file: Option<PathBuf>,
+    /// The number of blocks the wallet must sync past a received payment
+    /// before it is reported to webhooks as confirmed.
+    #[structopt(long, default_value = "10")]
+    webhook_confirmations: u64,
+    /// How often, in seconds, to queue webhook notifications of new payments,
+    /// and deliver those whose next attempt is due.
+    #[structopt(long, default_value = "1")]
+    webhook_interval: u64,
+    /// Start a new wallet's sync from the signed checkpoint served at this
+    /// URL, rather than from genesis.
+    ///
+    /// The checkpoint's anchor is checked against `--node` and every
+    /// `--checkpoint-node`. Ignored once the wallet has synced anything.
+    #[structopt(long)]
+    checkpoint_provider: Option<String>,
+    /// The hex-encoded ed25519 key with which the checkpoint provider signs
+    /// its checkpoints. Required if `--checkpoint-provider` is given.
+    #[structopt(long)]
+    checkpoint_key: Option<String>,
+    /// The URL of another node's specific query service, which must agree with
+    /// the checkpoint's anchor. May be given more than once.
+    #[structopt(long = "checkpoint-node")]
+    checkpoint_nodes: Vec<String>,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let opt = Opt::from_args();
+
     let pool = SqlitePool::connect(&env::var("DATABASE_URL")?).await?;
 
     // TODO: weird chicken & egg problem w/ database existing or not
-    sqlx::migrate!().run(&pool).await?;
-    let row = insert_table(&pool).await?;
-    let x = read_table(&pool).await?;
-
-    println!(
-        "Hello, pwalletd! I got stuff from sqlite: row {} value {}",
-        row, x
-    );
+    MIGRATOR.run(&pool).await?;
+
+    // Finish any passphrase rotation interrupted by a previous shutdown.
+    keystore::complete_rotation(&pool).await?;
+
+    if let Some(proxy) = &opt.proxy {
+        tracing::info!(?proxy, "connecting to the node through a SOCKS proxy");
+    }
+
+    let mut tls = Tls::default();
+    if let Some(path) = &opt.tls_ca_file {
+        tls.add_ca_file(path)?;
+    }
+    for EndpointPin { authority, pin } in &opt.tls_pins {
+        tls.add_pin(authority, *pin);
+    }
+    if opt.require_tls {
+        tls.require();
+    }
+
+    if let Some(provider) = &opt.checkpoint_provider {
+        let key = opt
+            .checkpoint_key
+            .as_deref()
+            .ok_or_else(|| anyhow!("--checkpoint-provider requires --checkpoint-key"))?;
+        let nodes = opt.node.iter().chain(&opt.checkpoint_nodes);
+        start_from_checkpoint(
+            &pool,
+            opt.proxy.as_ref(),
+            &tls,
+            provider,
+            key,
+            nodes,
+            &opt.chain_id,
+        )
+        .await?;
+    }
+
+    let progress = ProgressTracker::default();
+    if let Some(node) = opt.node.clone() {
+        proxy::check_route(opt.proxy.as_ref(), &node)?;
+        check_compatibility(opt.proxy.as_ref(), &tls, &node).await?;
+
+        {
+            let pool = pool.clone();
+            let proxy = opt.proxy.clone();
+            let tls = tls.clone();
+            let node = node.clone();
+            let chain_id = opt.chain_id.clone();
+            let progress = progress.clone();
+            let interval = Duration::from_secs(opt.sync_interval);
+            tokio::spawn(async move {
+                loop {
+                    if let Err(e) =
+                        sync_blocks(&pool, proxy.as_ref(), &tls, &node, &chain_id, &progress).await
+                    {
+                        tracing::warn!(?e, "could not sync with the node");
+                    }
+                    tokio::time::sleep(interval).await;
+                }
+            });
+        }
+
+        let pool = pool.clone();
+        let proxy = opt.proxy.clone();
+        let tls = tls.clone();
+        let chain_id = opt.chain_id.clone();
+        let interval = Duration::from_secs(opt.divergence_check_interval);
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) =
+                    check_divergence(&pool, proxy.as_ref(), &tls, &node, &chain_id).await
+                {
+                    tracing::warn!(?e, "could not check for chain divergence");
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    if let Some(rpc_url) = opt.tendermint_rpc.clone() {
+        let pool = pool.clone();
+        let client = proxy::http_client(opt.proxy.as_ref(), &tls, &rpc_url)?;
+        let interval = Duration::from_secs(opt.broadcast_interval);
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = process_broadcasts(&pool, &client, &rpc_url).await {
+                    tracing::warn!(?e, "could not process broadcast queue");
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    {
+        let secret = match &opt.webhook_secret_file {
+            Some(path) => std::fs::read_to_string(path)?.trim().as_bytes().to_vec(),
+            None if opt.webhooks.is_empty() => Vec::new(),
+            None => return Err(anyhow!("--webhook requires --webhook-secret-file")),
+        };
+        let pool = pool.clone();
+        let urls = opt.webhooks.clone();
+        let confirmations = opt.webhook_confirmations;
+        let client = reqwest::Client::new();
+        let interval = Duration::from_secs(opt.webhook_interval);
+        // Events are collected even with no webhooks configured, so that
+        // adding one later does not replay old payments.
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = notify_webhooks(&pool, &client, &urls, &secret, confirmations).await
+                {
+                    tracing::warn!(?e, "could not notify webhooks");
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    {
+        let pool = pool.clone();
+        let interval = Duration::from_secs(opt.maintenance_interval);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = maintenance::run(&pool).await {
+                    tracing::warn!(?e, "could not maintain wallet database");
+                }
+            }
+        });
+    }
+
+    tracing::info!(bind = ?opt.bind, "starting pwalletd");
+    Server::builder()
+        .add_service(WalletServiceServer::new(
+            WalletService::new(pool).with_sync_progress(progress),
+        ))
+        .serve(opt.bind)
+        .await?;
+
+    Ok(())
+}
+
+/// The node's methods which pwalletd calls.
+const NODE_METHODS: &[&str] = &[
+    "/penumbra.client.oblivious.ObliviousQuery/ChainStats",
+    "/penumbra.client.oblivious.ObliviousQuery/CompactBlockRange",
+    "/penumbra.client.specific.SpecificQuery/NctAnchor",
+];
+
+/// Checks that the node speaks the protocol pwalletd was compiled with, so that
+/// a version mismatch fails fast rather than with decode errors mid-sync.
+async fn check_compatibility(proxy: Option<&Proxy>, tls: &Tls, node: &str) -> Result<()> {
+    let mut client = ObliviousQueryClient::new(proxy::connect(proxy, tls, node).await?);
+    let response = match client.proto_descriptor(ProtoDescriptorRequest {}).await {
+        Ok(response) => response.into_inner(),
+        Err(status) if status.code() == Code::Unimplemented => {
+            tracing::warn!("node does not report its protocol; skipping compatibility check");
+            return Ok(());
+        }
+        Err(status) => return Err(status.into()),
+    };
+    let provided = FileDescriptorSet::decode(response.file_descriptor_set.as_slice())?;
+
+    let incompatibilities = compat::check(&compat::file_descriptor_set(), &provided, NODE_METHODS);
+    if !incompatibilities.is_empty() {
+        for incompatibility in &incompatibilities {
+            tracing::error!(%incompatibility, "node protocol is incompatible");
+        }
+        return Err(anyhow!(
+            "the node at {} speaks an incompatible protocol ({}); upgrade whichever of the \
+             wallet and the node is older",
+            node,
+            incompatibilities[0]
+        ));
+    }
+
     Ok(())
 }
+
+async fn start_from_checkpoint(
+    pool: &SqlitePool,
+    proxy: Option<&Proxy>,
+    tls: &Tls,
+    provider: &str,
+    key: &str,
+    nodes: impl Iterator<Item = &String>,
+    chain_id: &str,
+) -> Result<()> {
+    if let Some(height) = reorg::synced_height(pool).await? {
+        tracing::info!(
+            height,
+            "wallet has already synced; not fetching a checkpoint"
+        );
+        return Ok(());
+    }
+
+    let key: [u8; 32] = hex::decode(key)?
+        .try_into()
+        .map_err(|_| anyhow!("--checkpoint-key must be 32 hex-encoded bytes"))?;
+    let key = ed25519_consensus::VerificationKey::try_from(key)?;
+    let client = proxy::http_client(proxy, tls, provider)?;
+    let checkpoint = checkpoint::fetch(&client, provider, &key).await?;
+
+    let mut clients = Vec::new();
+    for node in nodes {
+        clients.push(SpecificQueryClient::new(
+            proxy::connect(proxy, tls, node).await?,
+        ));
+    }
+    checkpoint::verify(&checkpoint, &mut clients, chain_id).await?;
+    checkpoint::import(pool, &checkpoint).await
+}
+
+async fn sync_blocks(
+    pool: &SqlitePool,
+    proxy: Option<&Proxy>,
+    tls: &Tls,
+    node: &str,
+    chain_id: &str,
+    progress: &ProgressTracker,
+) -> Result<()> {
+    let mut client = ObliviousQueryClient::new(proxy::connect(proxy, tls, node).await?);
+    sync::sync(pool, &mut client, chain_id, progress).await
+}
+
+async fn check_divergence(
+    pool: &SqlitePool,
+    proxy: Option<&Proxy>,
+    tls: &Tls,
+    node: &str,
+    chain_id: &str,
+) -> Result<()> {
+    let mut client = SpecificQueryClient::new(proxy::connect(proxy, tls, node).await?);
+    reorg::detect(pool, &mut client, chain_id).await?;
+    Ok(())
+}
+
+async fn process_broadcasts(
+    pool: &SqlitePool,
+    client: &reqwest::Client,
+    rpc_url: &str,
+) -> Result<()> {
+    // Expiry is judged against the wallet's own view of the chain, so that a
+    // transaction is only expired once the wallet has synced past it.
+    let height = reorg::synced_height(pool).await?.unwrap_or_default();
+    broadcast::process(pool, client, rpc_url, height).await
+}
+
+async fn notify_webhooks(
+    pool: &SqlitePool,
+    client: &reqwest::Client,
+    urls: &[String],
+    secret: &[u8],
+    confirmations: u64,
+) -> Result<()> {
+    let height = reorg::synced_height(pool).await?;
+    webhook::collect(pool, urls, height, confirmations).await?;
+    webhook::deliver(pool, client, urls, secret).await
+}
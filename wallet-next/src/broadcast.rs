@@ -0,0 +1,328 @@
+//! Queueing and retrying of transaction broadcasts.
+//!
+//! Submitted transactions are first recorded in the wallet database, and only
+//! then broadcast to the node, so that a wallet on a flaky network never loses
+//! a transaction it was asked to send. [`process`] broadcasts every queued
+//! transaction whose next attempt is due: while the node is unreachable, each
+//! attempt pushes the next one further out with exponential [`backoff`], until
+//! either the node accepts or rejects the transaction, or the chain passes the
+//! transaction's expiry height and it can no longer be included.
+
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use penumbra_proto::{
+    transaction::Transaction,
+    wallet_next::{self as pb, submitted_transaction},
+    Message,
+};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqlitePool;
+
+/// The delay before the first retry of a broadcast that could not reach the node.
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest delay between two attempts to broadcast the same transaction.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// The broadcast status of a submitted transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// Waiting to be broadcast, or to be retried.
+    Queued,
+    /// Accepted by the node's mempool.
+    Broadcast,
+    /// Rejected by the node; it will not be retried.
+    Rejected,
+    /// Not broadcast before the chain passed its expiry height.
+    Expired,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Broadcast => "broadcast",
+            Status::Rejected => "rejected",
+            Status::Expired => "expired",
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "queued" => Ok(Status::Queued),
+            "broadcast" => Ok(Status::Broadcast),
+            "rejected" => Ok(Status::Rejected),
+            "expired" => Ok(Status::Expired),
+            _ => Err(anyhow!("unknown broadcast status {:?}", s)),
+        }
+    }
+}
+
+/// A submitted transaction, as listed in the wallet's transaction history.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubmittedTx {
+    /// The SHA-256 hash of the encoded transaction, as used by Tendermint.
+    pub tx_hash: Vec<u8>,
+    /// The height after which the transaction can no longer be included, or 0
+    /// if it never expires.
+    pub expiry_height: u64,
+    pub status: Status,
+    /// The number of failed attempts to reach the node with this transaction.
+    pub attempts: u64,
+    pub submitted_at: SystemTime,
+    /// The reason for the last failed attempt, or for the node's rejection.
+    pub last_error: Option<String>,
+}
+
+impl From<SubmittedTx> for pb::SubmittedTransaction {
+    fn from(tx: SubmittedTx) -> Self {
+        let status = match tx.status {
+            Status::Queued => submitted_transaction::Status::Queued,
+            Status::Broadcast => submitted_transaction::Status::Broadcast,
+            Status::Rejected => submitted_transaction::Status::Rejected,
+            Status::Expired => submitted_transaction::Status::Expired,
+        };
+        pb::SubmittedTransaction {
+            tx_hash: tx.tx_hash,
+            expiry_height: tx.expiry_height,
+            status: status as i32,
+            attempts: tx.attempts,
+            submitted_at_unix_ms: tx
+                .submitted_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            last_error: tx.last_error.unwrap_or_default(),
+        }
+    }
+}
+
+/// The delay before retrying a broadcast that has failed `attempts` times.
+pub fn backoff(attempts: u64) -> Duration {
+    let exponent = attempts.saturating_sub(1).min(31) as u32;
+    INITIAL_BACKOFF
+        .checked_mul(1u32 << exponent)
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF)
+}
+
+/// Queue an encoded transaction for broadcast, returning its hash.
+///
+/// Submitting a transaction which is already queued has no effect.
+pub async fn submit(pool: &SqlitePool, tx: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let expiry_height = Transaction::decode(tx)
+        .context("could not decode transaction")?
+        .body
+        .ok_or_else(|| anyhow!("transaction is missing a body"))?
+        .expiry_height as i64;
+    let tx_hash = Sha256::digest(tx).to_vec();
+    let now = unix_ms(SystemTime::now());
+    let status = Status::Queued.as_str();
+
+    sqlx::query!(
+        r#"
+INSERT OR IGNORE INTO broadcast_queue
+    ( tx_hash, tx, expiry_height, status, attempts, submitted_at, next_attempt_at )
+VALUES ( ?1, ?2, ?3, ?4, 0, ?5, ?5 )
+        "#,
+        tx_hash,
+        tx,
+        expiry_height,
+        status,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(tx_hash)
+}
+
+/// Every submitted transaction, oldest first.
+pub async fn history(pool: &SqlitePool) -> anyhow::Result<Vec<SubmittedTx>> {
+    let rows = sqlx::query!(
+        r#"
+SELECT tx_hash, expiry_height, status, attempts, submitted_at, last_error
+FROM broadcast_queue
+ORDER BY submitted_at
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(SubmittedTx {
+                tx_hash: row.tx_hash,
+                expiry_height: row.expiry_height as u64,
+                status: row.status.parse()?,
+                attempts: row.attempts as u64,
+                submitted_at: UNIX_EPOCH + Duration::from_millis(row.submitted_at as u64),
+                last_error: row.last_error,
+            })
+        })
+        .collect()
+}
+
+/// The submitted transaction with the given hash, if there is one.
+pub async fn submitted(pool: &SqlitePool, tx_hash: &[u8]) -> anyhow::Result<Option<SubmittedTx>> {
+    let row = sqlx::query!(
+        r#"
+SELECT tx_hash, expiry_height, status, attempts, submitted_at, last_error
+FROM broadcast_queue
+WHERE tx_hash = ?1
+        "#,
+        tx_hash
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| {
+        Ok(SubmittedTx {
+            tx_hash: row.tx_hash,
+            expiry_height: row.expiry_height as u64,
+            status: row.status.parse()?,
+            attempts: row.attempts as u64,
+            submitted_at: UNIX_EPOCH + Duration::from_millis(row.submitted_at as u64),
+            last_error: row.last_error,
+        })
+    })
+    .transpose()
+}
+
+/// Expire every queued transaction which can no longer be included after
+/// `height`, then broadcast every queued transaction whose next attempt is due
+/// to the Tendermint RPC endpoint at `rpc_url`, using `client`.
+///
+/// If the node cannot be reached, the remaining due transactions are not
+/// attempted, but all of them are backed off.
+pub async fn process(
+    pool: &SqlitePool,
+    client: &reqwest::Client,
+    rpc_url: &str,
+    height: u64,
+) -> anyhow::Result<()> {
+    let height = height as i64;
+    let queued = Status::Queued.as_str();
+    let expired = Status::Expired.as_str();
+    sqlx::query!(
+        r#"
+UPDATE broadcast_queue
+SET status = ?1
+WHERE status = ?2 AND expiry_height != 0 AND expiry_height <= ?3
+        "#,
+        expired,
+        queued,
+        height
+    )
+    .execute(pool)
+    .await?;
+
+    let now = SystemTime::now();
+    let now_ms = unix_ms(now);
+    let due = sqlx::query!(
+        r#"
+SELECT tx_hash, tx, attempts
+FROM broadcast_queue
+WHERE status = ?1 AND next_attempt_at <= ?2
+ORDER BY submitted_at
+        "#,
+        queued,
+        now_ms
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut unreachable: Option<String> = None;
+    for row in due {
+        let (status, error) = match &unreachable {
+            Some(error) => (Status::Queued, error.clone()),
+            None => match broadcast_tx_sync(client, rpc_url, &row.tx).await {
+                Ok(Ok(())) => (Status::Broadcast, String::new()),
+                Ok(Err(log)) => (Status::Rejected, log),
+                Err(e) => {
+                    tracing::debug!(?e, "could not reach node, deferring broadcasts");
+                    unreachable = Some(e.to_string());
+                    (Status::Queued, e.to_string())
+                }
+            },
+        };
+
+        let (attempts, next_attempt_at) = if status == Status::Queued {
+            let attempts = row.attempts + 1;
+            (attempts, unix_ms(now + backoff(attempts as u64)))
+        } else {
+            (row.attempts, now_ms)
+        };
+        let status = status.as_str();
+        let last_error = if error.is_empty() { None } else { Some(error) };
+        sqlx::query!(
+            r#"
+UPDATE broadcast_queue
+SET status = ?1, attempts = ?2, next_attempt_at = ?3, last_error = ?4
+WHERE tx_hash = ?5
+            "#,
+            status,
+            attempts,
+            next_attempt_at,
+            last_error,
+            row.tx_hash
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Broadcast a transaction with Tendermint's `broadcast_tx_sync`, returning
+/// `Ok(Err(log))` if the node was reached but rejected the transaction.
+async fn broadcast_tx_sync(
+    client: &reqwest::Client,
+    rpc_url: &str,
+    tx: &[u8],
+) -> anyhow::Result<Result<(), String>> {
+    let rsp: serde_json::Value = client
+        .post(rpc_url)
+        .json(&serde_json::json!(
+            {
+                "method": "broadcast_tx_sync",
+                "params": [tx],
+                "id": 0,
+            }
+        ))
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    // Sometimes the result is in a result key, and sometimes it's bare.
+    let result = rsp.get("result").unwrap_or(&rsp);
+    let code = result
+        .get("code")
+        .and_then(|c| c.as_i64())
+        .ok_or_else(|| anyhow!("could not parse JSON response"))?;
+
+    if code == 0 {
+        Ok(Ok(()))
+    } else {
+        let log = result
+            .get("log")
+            .and_then(|l| l.as_str())
+            .unwrap_or_default();
+        Ok(Err(format!("code {}: {}", code, log)))
+    }
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .expect("time travels linearly in a forward direction")
+        .as_millis() as i64
+}
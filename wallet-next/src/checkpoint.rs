@@ -0,0 +1,440 @@
+//! Cold-start sync from a signed wallet checkpoint.
+//!
+//! A wallet created today cannot have notes in any block before today, yet
+//! syncing from genesis means scanning all of them. A checkpoint provider
+//! instead serves a [`WalletCheckpoint`]: the serialized note commitment tree
+//! frontier and its anchor as of some height, together with the validators'
+//! rates, signed with the provider's ed25519 key. A new wallet [`fetch`]es it,
+//! [`verify`]s the anchor and the exchange rates against at least
+//! [`MIN_VERIFYING_NODES`] nodes, so that neither the provider nor a single
+//! node can feed it a false tree, and [`import`]s it as its first sync
+//! checkpoint, from which sync resumes. The reward rates cannot be checked
+//! against the nodes, which only serve exchange rates by epoch, so they are
+//! trusted from the provider.
+//!
+//! The provider serves the checkpoint as a JSON document, with the hex-encoded
+//! signature over the exact bytes of the body in a
+//! `Penumbra-Checkpoint-Signature` header.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use ed25519_consensus::{Signature, VerificationKey};
+use penumbra_crypto::merkle::{self, NoteCommitmentTree, TreeExt};
+use penumbra_proto::{
+    client::specific::{specific_query_client::SpecificQueryClient, ExchangeRateChangeRequest},
+    crypto,
+    serializers::bech32str::{self, validator_identity_key::BECH32_PREFIX},
+    stake,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use tonic::{transport::Channel, Code};
+
+use crate::reorg;
+
+/// The number of nodes which must agree with a checkpoint's anchor before it
+/// is imported.
+pub const MIN_VERIFYING_NODES: usize = 2;
+
+/// The header carrying the provider's signature over the checkpoint.
+pub const SIGNATURE_HEADER: &str = "Penumbra-Checkpoint-Signature";
+
+/// How long to wait for the provider to respond.
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The state a wallet needs to start syncing after `height`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletCheckpoint {
+    pub chain_id: String,
+    pub height: u64,
+    /// The hex-encoded note commitment tree anchor at the end of `height`.
+    pub anchor: String,
+    /// The hex-encoded note commitment tree frontier at the end of `height`,
+    /// serialized as the wallet records it in its own checkpoints.
+    pub nct: String,
+    /// The rates of every validator, as of the epoch containing `height`.
+    ///
+    /// Only the exchange rates are [`verify`]'d; the reward rates are trusted
+    /// from the provider.
+    pub rates: Vec<RateData>,
+}
+
+/// A validator's rates in some epoch.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateData {
+    /// The Bech32-encoded identity key of the validator.
+    pub identity_key: String,
+    pub epoch_index: u64,
+    pub validator_reward_rate: u64,
+    pub validator_exchange_rate: u64,
+}
+
+impl WalletCheckpoint {
+    /// Parse a checkpoint from the body served by a provider, checking the
+    /// provider's hex-encoded `signature` over it.
+    pub fn from_signed(
+        body: &[u8],
+        signature: &str,
+        provider_key: &VerificationKey,
+    ) -> anyhow::Result<Self> {
+        let signature: [u8; 64] = hex::decode(signature)?
+            .try_into()
+            .map_err(|_| anyhow!("checkpoint signature has the wrong length"))?;
+        provider_key
+            .verify(&Signature::from(signature), body)
+            .map_err(|_| anyhow!("checkpoint is not signed by the provider's key"))?;
+        Ok(serde_json::from_slice(body)?)
+    }
+
+    /// The decoded note commitment tree anchor.
+    pub fn anchor(&self) -> anyhow::Result<merkle::Root> {
+        let bytes = hex::decode(&self.anchor).context("invalid checkpoint anchor")?;
+        merkle::Root::try_from(bytes.as_slice()).context("invalid checkpoint anchor")
+    }
+
+    /// The decoded note commitment tree, checked against the anchor.
+    pub fn nct(&self) -> anyhow::Result<NoteCommitmentTree> {
+        let bytes = hex::decode(&self.nct).context("invalid checkpoint tree")?;
+        let nct: NoteCommitmentTree =
+            bincode::deserialize(&bytes).context("invalid checkpoint tree")?;
+        if nct.root2() != self.anchor()? {
+            return Err(anyhow!("checkpoint tree does not match its anchor"));
+        }
+        Ok(nct)
+    }
+}
+
+/// Fetch the checkpoint served at `url` using `client`, checking that it is
+/// signed by `provider_key`.
+pub async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    provider_key: &VerificationKey,
+) -> anyhow::Result<WalletCheckpoint> {
+    let response = client
+        .get(url)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?;
+    let signature = response
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .ok_or_else(|| anyhow!("checkpoint provider did not sign its response"))?
+        .to_str()?
+        .to_string();
+    let body = response.bytes().await?;
+    WalletCheckpoint::from_signed(&body, &signature, provider_key)
+}
+
+/// Check that the checkpoint's tree matches its anchor, and that every one of
+/// `nodes`, of which there must be at least [`MIN_VERIFYING_NODES`], reports
+/// that anchor at its height on the chain `chain_id`, and the checkpoint's
+/// exchange rate for every validator in its epoch.
+pub async fn verify(
+    checkpoint: &WalletCheckpoint,
+    nodes: &mut [SpecificQueryClient<Channel>],
+    chain_id: &str,
+) -> anyhow::Result<()> {
+    if checkpoint.chain_id != chain_id {
+        return Err(anyhow!(
+            "checkpoint is for chain {:?}, not {:?}",
+            checkpoint.chain_id,
+            chain_id
+        ));
+    }
+    checkpoint.nct()?;
+    if nodes.len() < MIN_VERIFYING_NODES {
+        return Err(anyhow!(
+            "a checkpoint must be verified against at least {} nodes, but only {} were given",
+            MIN_VERIFYING_NODES,
+            nodes.len()
+        ));
+    }
+
+    let anchor = crypto::MerkleRoot::from(checkpoint.anchor()?).inner;
+    for (i, node) in nodes.iter_mut().enumerate() {
+        if !reorg::node_agrees(node, chain_id, checkpoint.height, &anchor).await? {
+            return Err(anyhow!(
+                "node {} does not agree with the checkpoint's anchor at height {}",
+                i,
+                checkpoint.height
+            ));
+        }
+        for rate in &checkpoint.rates {
+            if !node_agrees_on_rate(node, chain_id, rate).await? {
+                return Err(anyhow!(
+                    "node {} does not agree with the checkpoint's exchange rate for {} in \
+                     epoch {}",
+                    i,
+                    rate.identity_key,
+                    rate.epoch_index
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `node` reports `rate`'s exchange rate for its validator and epoch.
+async fn node_agrees_on_rate(
+    node: &mut SpecificQueryClient<Channel>,
+    chain_id: &str,
+    rate: &RateData,
+) -> anyhow::Result<bool> {
+    let ik = bech32str::decode(&rate.identity_key, BECH32_PREFIX, bech32str::Bech32m)
+        .context("invalid checkpoint identity key")?;
+    let request = ExchangeRateChangeRequest {
+        chain_id: chain_id.to_string(),
+        identity_key: Some(stake::IdentityKey { ik }),
+        start_epoch: rate.epoch_index,
+        end_epoch: rate.epoch_index,
+    };
+    match node.exchange_rate_change(request).await {
+        Ok(response) => {
+            Ok(response.into_inner().start_exchange_rate == rate.validator_exchange_rate)
+        }
+        Err(status) if status.code() == Code::NotFound => Ok(false),
+        Err(status) => Err(anyhow!(
+            "could not fetch the exchange rate of {}: {}",
+            rate.identity_key,
+            status
+        )),
+    }
+}
+
+/// Start the wallet's sync from a verified checkpoint, recording its tree as
+/// the first sync checkpoint and its validator rates.
+///
+/// # Errors
+///
+/// Returns an error if the wallet has already synced anything, since skipping
+/// to the checkpoint could then miss notes, or if the checkpoint's tree does
+/// not match its anchor. Nothing is written in either case.
+pub async fn import(pool: &SqlitePool, checkpoint: &WalletCheckpoint) -> anyhow::Result<()> {
+    if let Some(height) = reorg::synced_height(pool).await? {
+        return Err(anyhow!(
+            "cannot import a checkpoint into a wallet already synced to height {}",
+            height
+        ));
+    }
+
+    let anchor = checkpoint.anchor()?;
+    let nct = bincode::serialize(&checkpoint.nct()?)?;
+
+    let mut tx = pool.begin().await?;
+    for rate in &checkpoint.rates {
+        let epoch_index = rate.epoch_index as i64;
+        let reward_rate = rate.validator_reward_rate as i64;
+        let exchange_rate = rate.validator_exchange_rate as i64;
+        sqlx::query!(
+            r#"
+INSERT OR REPLACE INTO validator_rates
+    ( identity_key, epoch_index, validator_reward_rate, validator_exchange_rate )
+VALUES ( ?1, ?2, ?3, ?4 )
+            "#,
+            rate.identity_key,
+            epoch_index,
+            reward_rate,
+            exchange_rate
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    reorg::record_checkpoint(pool, checkpoint.height, anchor, &nct).await?;
+
+    tracing::info!(
+        height = checkpoint.height,
+        validators = checkpoint.rates.len(),
+        "imported wallet checkpoint"
+    );
+    Ok(())
+}
+
+/// The latest known rates of every validator, ordered by identity key.
+pub async fn validator_rates(pool: &SqlitePool) -> anyhow::Result<Vec<RateData>> {
+    let rows = sqlx::query!(
+        r#"
+SELECT identity_key, epoch_index, validator_reward_rate, validator_exchange_rate
+FROM validator_rates
+ORDER BY identity_key
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| RateData {
+            identity_key: row.identity_key,
+            epoch_index: row.epoch_index as u64,
+            validator_reward_rate: row.validator_reward_rate as u64,
+            validator_exchange_rate: row.validator_exchange_rate as u64,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_consensus::SigningKey;
+    use penumbra_crypto::{merkle::Frontier, note};
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::testing::wallet_pool;
+
+    fn rate(identity_key: &str, epoch_index: u64) -> RateData {
+        RateData {
+            identity_key: identity_key.to_string(),
+            epoch_index,
+            validator_reward_rate: 1_000,
+            validator_exchange_rate: 100_000_000 + epoch_index,
+        }
+    }
+
+    /// A tree with one note commitment in it.
+    fn nct() -> NoteCommitmentTree {
+        let mut nct = NoteCommitmentTree::new(0);
+        nct.append(&note::Commitment(1u64.into()));
+        nct
+    }
+
+    fn checkpoint() -> WalletCheckpoint {
+        WalletCheckpoint {
+            chain_id: "penumbra-testnet".to_string(),
+            height: 100,
+            anchor: hex::encode(nct().root2().to_bytes()),
+            nct: hex::encode(bincode::serialize(&nct()).unwrap()),
+            rates: vec![rate("penumbravalid1a", 9), rate("penumbravalid1b", 10)],
+        }
+    }
+
+    #[test]
+    fn only_checkpoints_signed_by_the_provider_are_accepted() -> anyhow::Result<()> {
+        let key = SigningKey::new(OsRng);
+        let body = serde_json::to_vec(&checkpoint())?;
+        let signature = hex::encode(key.sign(&body).to_bytes());
+
+        let parsed = WalletCheckpoint::from_signed(&body, &signature, &key.verification_key())?;
+        assert_eq!(parsed, checkpoint());
+
+        // The signature covers the exact bytes of the body...
+        let mut tampered = body.clone();
+        tampered.push(b'\n');
+        assert!(
+            WalletCheckpoint::from_signed(&tampered, &signature, &key.verification_key()).is_err()
+        );
+        // ... and must be made by the provider's key...
+        let other = SigningKey::new(OsRng).verification_key();
+        assert!(WalletCheckpoint::from_signed(&body, &signature, &other).is_err());
+        // ... and be a whole signature.
+        let error = WalletCheckpoint::from_signed(
+            &body,
+            &signature[..signature.len() - 2],
+            &key.verification_key(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("wrong length"), "{}", error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn anchors_are_decoded_from_hex() -> anyhow::Result<()> {
+        assert_eq!(checkpoint().anchor()?, nct().root2());
+        for anchor in ["not hex", "00"] {
+            let checkpoint = WalletCheckpoint {
+                anchor: anchor.to_string(),
+                ..checkpoint()
+            };
+            assert!(checkpoint.anchor().is_err(), "{:?}", anchor);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn trees_must_match_their_anchor() -> anyhow::Result<()> {
+        assert_eq!(checkpoint().nct()?.root2(), nct().root2());
+
+        let empty = WalletCheckpoint {
+            nct: hex::encode(bincode::serialize(&NoteCommitmentTree::new(0))?),
+            ..checkpoint()
+        };
+        let error = empty.nct().unwrap_err();
+        assert!(error.to_string().contains("does not match"), "{}", error);
+
+        let garbage = WalletCheckpoint {
+            nct: hex::encode(b"frontier"),
+            ..checkpoint()
+        };
+        assert!(garbage.nct().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verification_needs_enough_nodes_of_the_same_chain() -> anyhow::Result<()> {
+        let error = verify(&checkpoint(), &mut [], "penumbra-mainnet")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("penumbra-mainnet"), "{}", error);
+
+        let error = verify(&checkpoint(), &mut [], "penumbra-testnet")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("at least 2 nodes"), "{}", error);
+
+        // The tree is checked before any node is asked about its anchor.
+        let forged = WalletCheckpoint {
+            anchor: hex::encode(merkle::Root(Default::default()).to_bytes()),
+            ..checkpoint()
+        };
+        let error = verify(&forged, &mut [], "penumbra-testnet")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("does not match"), "{}", error);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn import_starts_sync_at_the_checkpoint() -> anyhow::Result<()> {
+        let pool = wallet_pool().await?;
+        let checkpoint = checkpoint();
+
+        // A tree which does not match the anchor is rejected before anything
+        // is written.
+        let forged = WalletCheckpoint {
+            nct: hex::encode(bincode::serialize(&NoteCommitmentTree::new(0))?),
+            ..checkpoint.clone()
+        };
+        assert!(import(&pool, &forged).await.is_err());
+        assert_eq!(reorg::synced_height(&pool).await?, None);
+        assert!(validator_rates(&pool).await?.is_empty());
+
+        import(&pool, &checkpoint).await?;
+        assert_eq!(reorg::synced_height(&pool).await?, Some(100));
+        let latest = reorg::latest_checkpoint(&pool).await?.unwrap();
+        assert_eq!(latest.height, 100);
+        let imported: NoteCommitmentTree = bincode::deserialize(&latest.nct)?;
+        assert_eq!(imported.root2(), nct().root2());
+        assert_eq!(validator_rates(&pool).await?, checkpoint.rates);
+
+        // Skipping ahead once sync has started could miss notes.
+        let later = WalletCheckpoint {
+            height: 200,
+            rates: vec![rate("penumbravalid1a", 19)],
+            ..checkpoint.clone()
+        };
+        let error = import(&pool, &later).await.unwrap_err();
+        assert!(error.to_string().contains("height 100"), "{}", error);
+        assert_eq!(validator_rates(&pool).await?, checkpoint.rates);
+
+        Ok(())
+    }
+}
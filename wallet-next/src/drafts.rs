@@ -0,0 +1,210 @@
+//! Transaction drafts shared between the frontends connected to the wallet.
+//!
+//! Several frontends (say, a GUI, the CLI and a browser extension) may edit
+//! the same draft at once. Rather than locking a draft while one of them has
+//! it open, each draft carries a version, which every [`update`] and
+//! [`delete`] must name: if another frontend changed the draft in the
+//! meantime, the versions no longer match, and the write is refused with the
+//! current draft as a [`Outcome::Conflict`], so the frontend can merge or
+//! retry instead of silently overwriting the other's changes.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use penumbra_proto::wallet_next as pb;
+use sqlx::sqlite::SqlitePool;
+
+/// A transaction being composed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Draft {
+    pub id: i64,
+    pub label: String,
+    /// The draft as encoded by the frontend; opaque to the wallet.
+    pub contents: Vec<u8>,
+    /// The number of times the draft has been written, starting at 1.
+    pub version: u64,
+    pub updated_at: SystemTime,
+}
+
+impl From<Draft> for pb::Draft {
+    fn from(draft: Draft) -> Self {
+        pb::Draft {
+            id: draft.id as u64,
+            label: draft.label,
+            contents: draft.contents,
+            version: draft.version,
+            updated_at_unix_ms: draft
+                .updated_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        }
+    }
+}
+
+/// The outcome of a write to a draft, given the version it was expected to be.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome<T> {
+    /// The draft was at the expected version, and was written.
+    Done(T),
+    /// The draft was written by someone else since the expected version, and
+    /// was left as it is now.
+    Conflict(Draft),
+    /// There is no such draft.
+    NotFound,
+}
+
+/// Save a new draft, at version 1.
+pub async fn create(pool: &SqlitePool, label: &str, contents: &[u8]) -> anyhow::Result<Draft> {
+    let updated_at = SystemTime::now();
+    let now = unix_ms(updated_at);
+
+    let id = sqlx::query!(
+        r#"
+INSERT INTO drafts ( label, contents, version, updated_at )
+VALUES ( ?1, ?2, 1, ?3 )
+        "#,
+        label,
+        contents,
+        now
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    Ok(Draft {
+        id,
+        label: label.to_string(),
+        contents: contents.to_vec(),
+        version: 1,
+        updated_at,
+    })
+}
+
+/// The draft with the given id, if there is one.
+pub async fn get(pool: &SqlitePool, id: i64) -> anyhow::Result<Option<Draft>> {
+    let row = sqlx::query!(
+        r#"
+SELECT id, label, contents, version, updated_at
+FROM drafts
+WHERE id = ?1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| Draft {
+        id: row.id,
+        label: row.label,
+        contents: row.contents,
+        version: row.version as u64,
+        updated_at: UNIX_EPOCH + Duration::from_millis(row.updated_at as u64),
+    }))
+}
+
+/// Every draft, in the order they were created.
+pub async fn list(pool: &SqlitePool) -> anyhow::Result<Vec<Draft>> {
+    let rows = sqlx::query!(
+        r#"
+SELECT id, label, contents, version, updated_at
+FROM drafts
+ORDER BY id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Draft {
+            id: row.id,
+            label: row.label,
+            contents: row.contents,
+            version: row.version as u64,
+            updated_at: UNIX_EPOCH + Duration::from_millis(row.updated_at as u64),
+        })
+        .collect())
+}
+
+/// Replace the label and contents of a draft, if it is still at
+/// `expected_version`, returning the draft at its next version.
+pub async fn update(
+    pool: &SqlitePool,
+    id: i64,
+    expected_version: u64,
+    label: &str,
+    contents: &[u8],
+) -> anyhow::Result<Outcome<Draft>> {
+    let updated_at = SystemTime::now();
+    let now = unix_ms(updated_at);
+    let version = expected_version as i64;
+
+    // The version check and the write are a single statement, so no other
+    // write can come between them.
+    let updated = sqlx::query!(
+        r#"
+UPDATE drafts
+SET label = ?1, contents = ?2, version = version + 1, updated_at = ?3
+WHERE id = ?4 AND version = ?5
+        "#,
+        label,
+        contents,
+        now,
+        id,
+        version
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if updated == 0 {
+        return conflict(pool, id).await;
+    }
+    Ok(Outcome::Done(Draft {
+        id,
+        label: label.to_string(),
+        contents: contents.to_vec(),
+        version: expected_version + 1,
+        updated_at,
+    }))
+}
+
+/// Discard a draft, if it is still at `expected_version`.
+pub async fn delete(
+    pool: &SqlitePool,
+    id: i64,
+    expected_version: u64,
+) -> anyhow::Result<Outcome<()>> {
+    let version = expected_version as i64;
+
+    let deleted = sqlx::query!(
+        r#"
+DELETE FROM drafts
+WHERE id = ?1 AND version = ?2
+        "#,
+        id,
+        version
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if deleted == 0 {
+        return conflict(pool, id).await;
+    }
+    Ok(Outcome::Done(()))
+}
+
+/// Why a write to the draft with the given id matched no row.
+async fn conflict<T>(pool: &SqlitePool, id: i64) -> anyhow::Result<Outcome<T>> {
+    Ok(match get(pool, id).await? {
+        Some(draft) => Outcome::Conflict(draft),
+        None => Outcome::NotFound,
+    })
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .expect("time travels linearly in a forward direction")
+        .as_millis() as i64
+}
@@ -0,0 +1,124 @@
+//! Notification of the frontends connected to the wallet of each other's
+//! changes.
+//!
+//! Several frontends may be connected to one wallet at once, e.g. a GUI, the
+//! CLI and a browser extension. When one of them submits a transaction or
+//! saves a draft, the service publishes an [`Event`] to the [`EventBus`], and
+//! every frontend following the `WatchHistory` RPC receives it straight away,
+//! rather than noticing the change the next time it polls.
+//!
+//! Each subscriber has its own queue of events, so a slow frontend does not
+//! hold up the others: if it falls more than the bus's capacity behind, the
+//! oldest events are dropped from its queue, and it is told how many it
+//! missed, so that it can list the wallet's state afresh.
+
+use std::sync::{Arc, Mutex};
+
+use penumbra_proto::wallet_next::{self as pb, wallet_event};
+use tokio::sync::broadcast;
+use tonic::Request;
+
+use crate::{broadcast::SubmittedTx, drafts::Draft};
+
+/// The request metadata header in which a frontend names itself, so that the
+/// events it causes can be told apart from those caused by other frontends.
+pub const CLIENT_HEADER: &str = "x-wallet-client";
+
+/// The number of events a subscriber may fall behind by before it misses some.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// A change to the wallet made through one of its frontends.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// The position of the event among all the events published to the bus,
+    /// starting at 1.
+    pub sequence: u64,
+    /// The name of the frontend which caused the event, from its
+    /// [`CLIENT_HEADER`], or empty if it did not name itself.
+    pub origin: String,
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// A transaction was queued for broadcast.
+    TransactionSubmitted(SubmittedTx),
+    /// A draft was created or updated.
+    DraftSaved(Draft),
+    /// A draft was deleted.
+    DraftDeleted { id: i64 },
+}
+
+/// Publishes [`Event`]s to every subscriber.
+///
+/// Clones publish to the same subscribers.
+#[derive(Clone, Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    // Held while sending, so that events are queued in sequence order.
+    sequence: Arc<Mutex<u64>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    /// A bus whose subscribers may fall `capacity` events behind before they
+    /// miss some.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::channel(capacity).0,
+            sequence: Default::default(),
+        }
+    }
+
+    /// Publish an event caused by the frontend named `origin`, returning its
+    /// sequence number.
+    pub fn publish(&self, origin: &str, kind: EventKind) -> u64 {
+        let mut sequence = self.sequence.lock().unwrap();
+        *sequence += 1;
+        // Sending only fails if no frontend is subscribed, to miss the event.
+        let _ = self.sender.send(Event {
+            sequence: *sequence,
+            origin: origin.to_string(),
+            kind,
+        });
+        *sequence
+    }
+
+    /// Receive every event published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+/// The name a frontend gave itself in the [`CLIENT_HEADER`] of a request, or
+/// empty if it did not.
+pub fn origin<T>(request: &Request<T>) -> String {
+    request
+        .metadata()
+        .get(CLIENT_HEADER)
+        .and_then(|name| name.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+impl From<Event> for pb::WalletEvent {
+    fn from(event: Event) -> Self {
+        let event_kind = match event.kind {
+            EventKind::TransactionSubmitted(tx) => {
+                wallet_event::Event::TransactionSubmitted(tx.into())
+            }
+            EventKind::DraftSaved(draft) => wallet_event::Event::DraftSaved(draft.into()),
+            EventKind::DraftDeleted { id } => wallet_event::Event::DraftDeleted(id as u64),
+        };
+        pb::WalletEvent {
+            sequence: event.sequence,
+            origin: event.origin,
+            event: Some(event_kind),
+        }
+    }
+}
@@ -0,0 +1,473 @@
+//! The wallet's categorized transaction history, and its export for bookkeeping.
+//!
+//! Explorers cannot show the contents of shielded transactions, so the wallet
+//! is the only place a business can reconstruct its books from. As the wallet
+//! syncs, it records each effect of a transaction on its balance as an
+//! [`Entry`], with fees, realized staking rewards and slashing losses broken
+//! out into their own [`Category`]s. [`export`] renders a range of entries as
+//! CSV, OFX, or JSON, with amounts in each asset's display denomination, and
+//! [`transactions`] and [`totals`] group them by transaction and by category.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    ops::RangeInclusive,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use penumbra_crypto::asset::{self, REGISTRY};
+use penumbra_proto::wallet_next as pb;
+use serde_json::json;
+use sqlx::sqlite::{SqliteConnection, SqlitePool};
+
+use crate::Formatter;
+
+/// What kind of effect an [`Entry`] had on the wallet's balance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    /// Funds received from another party.
+    Receive,
+    /// Funds sent to another party.
+    Send,
+    /// A transaction fee paid by the wallet.
+    Fee,
+    /// Staking rewards realized by undelegating.
+    StakingReward,
+    /// Either side of a delegation.
+    Delegate,
+    /// Either side of an undelegation, excluding the realized reward and any
+    /// slashing loss.
+    Undelegate,
+    /// Value lost to a validator's slashing, realized by undelegating.
+    SlashLoss,
+}
+
+impl Category {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Receive => "receive",
+            Category::Send => "send",
+            Category::Fee => "fee",
+            Category::StakingReward => "staking_reward",
+            Category::Delegate => "delegate",
+            Category::Undelegate => "undelegate",
+            Category::SlashLoss => "slash_loss",
+        }
+    }
+
+    /// The OFX transaction type for entries of this category.
+    fn ofx_type(&self, credit: bool) -> &'static str {
+        match (self, credit) {
+            (Category::Fee, _) => "FEE",
+            (Category::StakingReward, _) => "INT",
+            (Category::Delegate | Category::Undelegate, _) => "XFER",
+            (_, true) => "CREDIT",
+            (_, false) => "DEBIT",
+        }
+    }
+}
+
+impl FromStr for Category {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "receive" => Ok(Category::Receive),
+            "send" => Ok(Category::Send),
+            "fee" => Ok(Category::Fee),
+            "staking_reward" => Ok(Category::StakingReward),
+            "delegate" => Ok(Category::Delegate),
+            "undelegate" => Ok(Category::Undelegate),
+            "slash_loss" => Ok(Category::SlashLoss),
+            _ => Err(anyhow!("unknown history category {:?}", s)),
+        }
+    }
+}
+
+/// A single effect of a transaction on the wallet's balance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub height: u64,
+    pub block_time: SystemTime,
+    pub tx_hash: Vec<u8>,
+    pub category: Category,
+    pub denom: asset::Denom,
+    /// The amount, in base units.
+    pub amount: u64,
+    /// Whether the amount was credited to (rather than debited from) the wallet.
+    pub credit: bool,
+    pub memo: String,
+}
+
+/// The entries of a single transaction, as line items.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    pub height: u64,
+    pub block_time: SystemTime,
+    pub tx_hash: Vec<u8>,
+    pub line_items: Vec<Entry>,
+}
+
+/// The amounts credited and debited by the entries of one category and
+/// denomination.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Total {
+    pub category: Category,
+    pub denom: asset::Denom,
+    /// The sum of the credited amounts, in base units.
+    pub credited: u64,
+    /// The sum of the debited amounts, in base units.
+    pub debited: u64,
+}
+
+/// The format of a history export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// One row per entry, with a header row.
+    Csv,
+    /// An OFX 2 bank statement, as imported by most accounting software.
+    Ofx,
+    /// A JSON array of entries.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "ofx" => Ok(Format::Ofx),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow!(
+                "invalid export format {:?}, expected csv, ofx, or json",
+                s
+            )),
+        }
+    }
+}
+
+/// Record an entry in the wallet's history.
+pub async fn record(pool: &SqlitePool, entry: &Entry) -> anyhow::Result<()> {
+    let mut conn = pool.acquire().await?;
+    insert(&mut conn, entry).await
+}
+
+/// Record an entry in the wallet's history, on a connection which may be
+/// partway through a database transaction.
+pub(crate) async fn insert(conn: &mut SqliteConnection, entry: &Entry) -> anyhow::Result<()> {
+    let height = entry.height as i64;
+    let block_time = unix_secs(entry.block_time);
+    let category = entry.category.as_str();
+    let denom = entry.denom.to_string();
+    let amount = entry.amount as i64;
+
+    sqlx::query!(
+        r#"
+INSERT INTO history ( height, block_time, tx_hash, category, denom, amount, credit, memo )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8 )
+        "#,
+        height,
+        block_time,
+        entry.tx_hash,
+        category,
+        denom,
+        amount,
+        entry.credit,
+        entry.memo
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// The entries recorded for blocks with heights in `range`, in the order they
+/// were recorded.
+pub async fn entries(pool: &SqlitePool, range: RangeInclusive<u64>) -> anyhow::Result<Vec<Entry>> {
+    let start = *range.start() as i64;
+    let end = (*range.end()).min(i64::MAX as u64) as i64;
+
+    let rows = sqlx::query!(
+        r#"
+SELECT height, block_time, tx_hash, category, denom, amount, credit, memo
+FROM history
+WHERE height >= ?1 AND height <= ?2
+ORDER BY id
+        "#,
+        start,
+        end
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(Entry {
+                height: row.height as u64,
+                block_time: UNIX_EPOCH + Duration::from_secs(row.block_time as u64),
+                tx_hash: row.tx_hash,
+                category: row.category.parse()?,
+                denom: REGISTRY
+                    .parse_denom(&row.denom)
+                    .ok_or_else(|| anyhow!("invalid denom {:?} in history", row.denom))?,
+                amount: row.amount as u64,
+                credit: row.credit,
+                memo: row.memo,
+            })
+        })
+        .collect()
+}
+
+/// Groups entries by the transaction they were recorded for, in the order
+/// each transaction was first recorded.
+pub fn transactions(entries: Vec<Entry>) -> Vec<Transaction> {
+    let mut transactions: Vec<Transaction> = Vec::new();
+    let mut index = BTreeMap::new();
+    for entry in entries {
+        match index.get(&(entry.height, entry.tx_hash.clone())) {
+            Some(&i) => transactions[i].line_items.push(entry),
+            None => {
+                index.insert((entry.height, entry.tx_hash.clone()), transactions.len());
+                transactions.push(Transaction {
+                    height: entry.height,
+                    block_time: entry.block_time,
+                    tx_hash: entry.tx_hash.clone(),
+                    line_items: vec![entry],
+                });
+            }
+        }
+    }
+    transactions
+}
+
+/// The transaction, without annotations.
+impl From<Transaction> for pb::HistoryTransaction {
+    fn from(transaction: Transaction) -> Self {
+        pb::HistoryTransaction {
+            height: transaction.height,
+            block_time: unix_secs(transaction.block_time) as u64,
+            tx_hash: transaction.tx_hash,
+            line_items: transaction
+                .line_items
+                .into_iter()
+                .map(|entry| pb::HistoryLineItem {
+                    category: entry.category.as_str().to_string(),
+                    denom: entry.denom.to_string(),
+                    amount: entry.amount,
+                    credit: entry.credit,
+                    memo: entry.memo,
+                })
+                .collect(),
+            annotations: Vec::new(),
+        }
+    }
+}
+
+/// Totals the entries of each category and denomination, ordered by category
+/// and then by denomination.
+pub fn totals(entries: &[Entry]) -> anyhow::Result<Vec<Total>> {
+    let mut totals = BTreeMap::<(&'static str, String), Total>::new();
+    for entry in entries {
+        let total = totals
+            .entry((entry.category.as_str(), entry.denom.to_string()))
+            .or_insert_with(|| Total {
+                category: entry.category,
+                denom: entry.denom.clone(),
+                credited: 0,
+                debited: 0,
+            });
+        let sum = if entry.credit {
+            &mut total.credited
+        } else {
+            &mut total.debited
+        };
+        *sum = sum
+            .checked_add(entry.amount)
+            .ok_or_else(|| anyhow!("history total of {} overflows", entry.denom))?;
+    }
+    Ok(totals.into_values().collect())
+}
+
+/// The wallet's balance of each denomination, as the sum of its history, in
+/// base units. Denominations with no balance are omitted.
+pub async fn balances(pool: &SqlitePool) -> anyhow::Result<Vec<(asset::Denom, u64)>> {
+    let rows = sqlx::query!(
+        r#"
+SELECT denom, SUM(CASE WHEN credit THEN amount ELSE -amount END) AS "balance!: i64"
+FROM history
+GROUP BY denom
+ORDER BY denom
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut balances = Vec::new();
+    for row in rows {
+        let denom = REGISTRY
+            .parse_denom(&row.denom)
+            .ok_or_else(|| anyhow!("invalid denom {:?} in history", row.denom))?;
+        let balance = u64::try_from(row.balance)
+            .map_err(|_| anyhow!("history debits more {} than it credits", denom))?;
+        if balance > 0 {
+            balances.push((denom, balance));
+        }
+    }
+    Ok(balances)
+}
+
+/// Render entries in the given format, with amounts in the default display
+/// unit of their denomination.
+///
+/// Debits are rendered as negative amounts.
+pub fn export(entries: &[Entry], format: Format, formatter: &Formatter) -> String {
+    let amount = |entry: &Entry, formatter: &Formatter| {
+        let unit = entry.denom.default_unit();
+        let sign = if entry.credit { "" } else { "-" };
+        (
+            format!("{}{}", sign, formatter.format_amount(entry.amount, &unit)),
+            unit.to_string(),
+        )
+    };
+
+    match format {
+        Format::Csv => {
+            let mut csv = String::from("height,date,tx_hash,category,amount,unit,memo\n");
+            for entry in entries {
+                let (amount, unit) = amount(entry, formatter);
+                let fields = [
+                    entry.height.to_string(),
+                    rfc3339(entry.block_time),
+                    hex::encode(&entry.tx_hash),
+                    entry.category.as_str().to_string(),
+                    amount,
+                    unit,
+                    entry.memo.clone(),
+                ];
+                let fields: Vec<_> = fields.iter().map(|field| csv_field(field)).collect();
+                writeln!(csv, "{}", fields.join(",")).expect("can write to string");
+            }
+            csv
+        }
+        Format::Json => {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|entry| {
+                    let (amount, unit) = amount(entry, formatter);
+                    json!({
+                        "height": entry.height,
+                        "date": rfc3339(entry.block_time),
+                        "tx_hash": hex::encode(&entry.tx_hash),
+                        "category": entry.category.as_str(),
+                        "amount": amount,
+                        "unit": unit,
+                        "memo": entry.memo,
+                    })
+                })
+                .collect();
+            serde_json::to_string_pretty(&entries).expect("can serialize JSON values")
+        }
+        Format::Ofx => {
+            let mut transactions = String::new();
+            for (index, entry) in entries.iter().enumerate() {
+                // OFX amounts are always formatted the same way, regardless of locale.
+                let (amount, unit) = amount(entry, &Formatter::default());
+                // OFX statements are in a single currency, so the unit goes in the memo.
+                let memo = if entry.memo.is_empty() {
+                    unit
+                } else {
+                    format!("{} ({})", entry.memo, unit)
+                };
+                write!(
+                    transactions,
+                    "<STMTTRN><TRNTYPE>{}</TRNTYPE><DTPOSTED>{}</DTPOSTED>\
+                     <TRNAMT>{}</TRNAMT><FITID>{}-{}</FITID><NAME>{}</NAME>\
+                     <MEMO>{}</MEMO></STMTTRN>\n",
+                    entry.category.ofx_type(entry.credit),
+                    ofx_date(entry.block_time),
+                    amount,
+                    hex::encode(&entry.tx_hash),
+                    index,
+                    entry.category.as_str(),
+                    xml_escape(&memo),
+                )
+                .expect("can write to string");
+            }
+
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n\
+                 <?OFX OFXHEADER=\"200\" VERSION=\"220\" SECURITY=\"NONE\" \
+                 OLDFILEUID=\"NONE\" NEWFILEUID=\"NONE\"?>\n\
+                 <OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>\n\
+                 {}</BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>\n",
+                transactions
+            )
+        }
+    }
+}
+
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .expect("time travels linearly in a forward direction")
+        .as_secs() as i64
+}
+
+/// Splits a time into its UTC calendar date and time of day.
+fn civil(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = unix_secs(time);
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400) as u32);
+
+    // Howard Hinnant's `civil_from_days`.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+    )
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = civil(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+fn ofx_date(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = civil(time);
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}[0:GMT]",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// Quotes a CSV field if it contains a separator, quote, or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
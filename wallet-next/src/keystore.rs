@@ -0,0 +1,426 @@
+//! Passphrase-encrypted storage for the wallet's secret material.
+//!
+//! Each secret is encrypted with ChaCha20-Poly1305 under a key derived from the
+//! wallet passphrase with PBKDF2. Changing the passphrase re-encrypts every
+//! secret in two phases: the new ciphertexts are first written alongside the old
+//! ones, then swapped into place, each phase in its own sqlite transaction. If
+//! the process dies between the phases, [`complete_rotation`] rolls the rotation
+//! forward the next time the keystore is used, so the wallet is never left with
+//! some secrets under the old passphrase and some under the new one.
+//!
+//! Every keystore access holds the database's write lock from its first read,
+//! so that no secret can be stored between a rotation reading the secrets and
+//! marking them as re-encrypted.
+
+use anyhow::{anyhow, Context};
+use chacha20poly1305::{
+    aead::{Aead, NewAead, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hmac::Hmac;
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+use sqlx::{
+    sqlite::{Sqlite, SqliteConnection, SqlitePool},
+    Transaction,
+};
+
+/// The number of PBKDF2 rounds used to derive an encryption key from a passphrase.
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// A known plaintext, encrypted under the passphrase key so that a passphrase
+/// can be checked even when no secrets are stored.
+const CHECK_PLAINTEXT: &[u8] = b"penumbra wallet passphrase check";
+
+/// An encryption key derived from a passphrase.
+pub(crate) struct PassphraseKey([u8; 32]);
+
+impl PassphraseKey {
+    pub(crate) fn derive(passphrase: &str, salt: &[u8]) -> Self {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+        Self(key)
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&self.0))
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .expect("encryption cannot fail");
+        (nonce.to_vec(), ciphertext)
+    }
+
+    fn decrypt(&self, nonce: &[u8], ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if nonce.len() != 12 {
+            return Err(anyhow!("invalid nonce length {}", nonce.len()));
+        }
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("decryption failed"))
+    }
+
+    /// Like [`Self::encrypt`], but also authenticating `aad`, which is not encrypted.
+    pub(crate) fn encrypt_with_aad(&self, plaintext: &[u8], aad: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&self.0))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .expect("encryption cannot fail");
+        (nonce.to_vec(), ciphertext)
+    }
+
+    /// Like [`Self::decrypt`], but also checking the authenticity of `aad`.
+    pub(crate) fn decrypt_with_aad(
+        &self,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        if nonce.len() != 12 {
+            return Err(anyhow!("invalid nonce length {}", nonce.len()));
+        }
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| anyhow!("decryption failed"))
+    }
+}
+
+pub(crate) fn new_salt() -> Vec<u8> {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    salt.to_vec()
+}
+
+/// Begins a transaction holding the database's write lock from the start, as
+/// `BEGIN IMMEDIATE` does.
+///
+/// sqlx only begins deferred transactions, which take the write lock at their
+/// first write, so this starts with a write which changes nothing.
+async fn begin_write(pool: &SqlitePool) -> anyhow::Result<Transaction<'static, Sqlite>> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE passphrase SET id = id WHERE 0")
+        .execute(&mut tx)
+        .await?;
+    Ok(tx)
+}
+
+/// Derive the current passphrase key, checking that the passphrase is correct.
+///
+/// Any pending rotation is completed first, within the caller's transaction,
+/// so a rotation is never in progress while the key is in use: a secret
+/// stored under it cannot be left out of a rotation.
+async fn unlock(conn: &mut SqliteConnection, passphrase: &str) -> anyhow::Result<PassphraseKey> {
+    roll_forward(&mut *conn).await?;
+
+    let row = sqlx::query!(
+        r#"
+SELECT salt, check_nonce, check_ciphertext
+FROM passphrase
+WHERE id = 0
+        "#
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| anyhow!("keystore is not initialized"))?;
+
+    let key = PassphraseKey::derive(passphrase, &row.salt);
+    key.decrypt(&row.check_nonce, &row.check_ciphertext)
+        .map_err(|_| anyhow!("incorrect passphrase"))?;
+    Ok(key)
+}
+
+/// Check that `passphrase` is the wallet's passphrase.
+pub async fn check_passphrase(pool: &SqlitePool, passphrase: &str) -> anyhow::Result<()> {
+    let mut tx = begin_write(pool).await?;
+    unlock(&mut tx, passphrase).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Set the passphrase for a newly created wallet.
+pub async fn initialize(pool: &SqlitePool, passphrase: &str) -> anyhow::Result<()> {
+    let salt = new_salt();
+    let (check_nonce, check_ciphertext) =
+        PassphraseKey::derive(passphrase, &salt).encrypt(CHECK_PLAINTEXT);
+
+    sqlx::query!(
+        r#"
+INSERT INTO passphrase ( id, salt, check_nonce, check_ciphertext )
+VALUES ( 0, ?1, ?2, ?3 )
+        "#,
+        salt,
+        check_nonce,
+        check_ciphertext
+    )
+    .execute(pool)
+    .await
+    .context("keystore is already initialized")?;
+
+    Ok(())
+}
+
+/// Encrypt and store a secret under the given name, replacing any previous value.
+pub async fn store_secret(
+    pool: &SqlitePool,
+    passphrase: &str,
+    name: &str,
+    plaintext: &[u8],
+) -> anyhow::Result<()> {
+    let mut tx = begin_write(pool).await?;
+    let (nonce, ciphertext) = unlock(&mut tx, passphrase).await?.encrypt(plaintext);
+
+    sqlx::query!(
+        r#"
+INSERT OR REPLACE INTO secrets ( name, nonce, ciphertext )
+VALUES ( ?1, ?2, ?3 )
+        "#,
+        name,
+        nonce,
+        ciphertext
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Load and decrypt the secret with the given name, if it exists.
+pub async fn load_secret(
+    pool: &SqlitePool,
+    passphrase: &str,
+    name: &str,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut tx = begin_write(pool).await?;
+    let key = unlock(&mut tx, passphrase).await?;
+
+    let row = sqlx::query!(
+        r#"
+SELECT nonce, ciphertext
+FROM secrets
+WHERE name = ?1
+        "#,
+        name
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+    tx.commit().await?;
+
+    row.map(|row| key.decrypt(&row.nonce, &row.ciphertext))
+        .transpose()
+        .with_context(|| format!("could not decrypt secret {:?}", name))
+}
+
+/// Re-encrypt every stored secret under a new passphrase.
+///
+/// Returns the number of secrets re-encrypted.
+pub async fn change_passphrase(
+    pool: &SqlitePool,
+    old_passphrase: &str,
+    new_passphrase: &str,
+) -> anyhow::Result<u64> {
+    let reencrypted = begin_rotation(pool, old_passphrase, new_passphrase).await?;
+    complete_rotation(pool).await?;
+
+    tracing::info!(reencrypted, "changed wallet passphrase");
+    Ok(reencrypted)
+}
+
+/// The first phase of [`change_passphrase`]: writes the secrets re-encrypted
+/// under the new passphrase, and its check, into the pending columns.
+async fn begin_rotation(
+    pool: &SqlitePool,
+    old_passphrase: &str,
+    new_passphrase: &str,
+) -> anyhow::Result<u64> {
+    let mut tx = begin_write(pool).await?;
+    let old_key = unlock(&mut tx, old_passphrase).await?;
+    let new_salt = new_salt();
+    let new_key = PassphraseKey::derive(new_passphrase, &new_salt);
+
+    let secrets = sqlx::query!(
+        r#"
+SELECT name, nonce, ciphertext
+FROM secrets
+        "#
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    let reencrypted = secrets.len() as u64;
+    for secret in secrets {
+        let plaintext = old_key
+            .decrypt(&secret.nonce, &secret.ciphertext)
+            .with_context(|| format!("could not decrypt secret {:?}", secret.name))?;
+        let (nonce, ciphertext) = new_key.encrypt(&plaintext);
+
+        sqlx::query!(
+            r#"
+UPDATE secrets
+SET pending_nonce = ?1, pending_ciphertext = ?2
+WHERE name = ?3
+            "#,
+            nonce,
+            ciphertext,
+            secret.name
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    let (check_nonce, check_ciphertext) = new_key.encrypt(CHECK_PLAINTEXT);
+    sqlx::query!(
+        r#"
+UPDATE passphrase
+SET pending_salt = ?1, pending_check_nonce = ?2, pending_check_ciphertext = ?3
+WHERE id = 0
+        "#,
+        new_salt,
+        check_nonce,
+        check_ciphertext
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(reencrypted)
+}
+
+/// Finish a passphrase rotation whose first phase was committed, if there is one.
+///
+/// This needs no passphrase, since the pending values were already encrypted
+/// under the new key. It is called automatically before every keystore access.
+pub async fn complete_rotation(pool: &SqlitePool) -> anyhow::Result<()> {
+    let mut tx = begin_write(pool).await?;
+    roll_forward(&mut tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Swaps the pending values of a rotation into place, if there are any.
+async fn roll_forward(conn: &mut SqliteConnection) -> anyhow::Result<()> {
+    let pending = sqlx::query!(
+        r#"
+SELECT pending_salt
+FROM passphrase
+WHERE id = 0
+        "#
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .and_then(|row| row.pending_salt)
+    .is_some();
+
+    if !pending {
+        return Ok(());
+    }
+
+    sqlx::query!(
+        r#"
+UPDATE secrets
+SET nonce = pending_nonce, ciphertext = pending_ciphertext,
+    pending_nonce = NULL, pending_ciphertext = NULL
+WHERE pending_ciphertext IS NOT NULL
+        "#
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+UPDATE passphrase
+SET salt = pending_salt, check_nonce = pending_check_nonce,
+    check_ciphertext = pending_check_ciphertext,
+    pending_salt = NULL, pending_check_nonce = NULL, pending_check_ciphertext = NULL
+WHERE id = 0 AND pending_salt IS NOT NULL
+        "#
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    tracing::info!("completed pending passphrase rotation");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::wallet_pool;
+
+    const SECRETS: [(&str, &[u8]); 2] = [("a", b"alpha"), ("b", b"beta")];
+
+    /// A keystore holding [`SECRETS`] under the passphrase `old`.
+    async fn keystore() -> anyhow::Result<SqlitePool> {
+        let pool = wallet_pool().await?;
+        initialize(&pool, "old").await?;
+        for (name, plaintext) in SECRETS {
+            store_secret(&pool, "old", name, plaintext).await?;
+        }
+        Ok(pool)
+    }
+
+    async fn assert_secrets(pool: &SqlitePool, passphrase: &str) -> anyhow::Result<()> {
+        for (name, plaintext) in SECRETS {
+            assert_eq!(
+                load_secret(pool, passphrase, name).await?.as_deref(),
+                Some(plaintext)
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn changes_passphrase() -> anyhow::Result<()> {
+        let pool = keystore().await?;
+        assert!(change_passphrase(&pool, "wrong", "new").await.is_err());
+
+        assert_eq!(change_passphrase(&pool, "old", "new").await?, 2);
+        assert!(check_passphrase(&pool, "old").await.is_err());
+        assert_secrets(&pool, "new").await
+    }
+
+    #[tokio::test]
+    async fn completes_interrupted_rotations() -> anyhow::Result<()> {
+        let pool = keystore().await?;
+
+        // The process dies after the first phase of the rotation...
+        assert_eq!(begin_rotation(&pool, "old", "new").await?, 2);
+        // ... so the rotation is completed on restart, without the passphrase.
+        complete_rotation(&pool).await?;
+        assert!(check_passphrase(&pool, "old").await.is_err());
+        assert_secrets(&pool, "new").await?;
+
+        // With no rotation pending, completing one changes nothing.
+        complete_rotation(&pool).await?;
+        assert_secrets(&pool, "new").await
+    }
+
+    #[tokio::test]
+    async fn stores_secrets_under_the_pending_passphrase() -> anyhow::Result<()> {
+        let pool = keystore().await?;
+        begin_rotation(&pool, "old", "new").await?;
+
+        // Storing a secret mid-rotation completes the rotation first, so the
+        // secret cannot be left under the old passphrase.
+        assert!(store_secret(&pool, "old", "c", b"gamma").await.is_err());
+        store_secret(&pool, "new", "c", b"gamma").await?;
+        assert_eq!(
+            load_secret(&pool, "new", "c").await?.as_deref(),
+            Some(&b"gamma"[..])
+        );
+        assert_secrets(&pool, "new").await
+    }
+}
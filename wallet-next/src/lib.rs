@@ -1,4 +1,33 @@
-use sqlx::sqlite::SqlitePool;
+use sqlx::{migrate::Migrator, sqlite::SqlitePool};
+
+pub mod amount;
+pub mod annotate;
+pub mod archive;
+pub mod broadcast;
+pub mod checkpoint;
+pub mod drafts;
+pub mod events;
+pub mod history;
+pub mod keystore;
+pub mod maintenance;
+pub mod policy;
+pub mod progress;
+pub mod proxy;
+pub mod reorg;
+mod service;
+pub mod sync;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tls;
+pub mod watch;
+pub mod webhook;
+
+pub use amount::Formatter;
+pub use service::WalletService;
+
+/// The migrations which bring a wallet database up to date, for every program
+/// which opens one.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
 
 // Stub code -- note that whatever code works with SQL has to be in the library,
 // not in the binary, so that we can run `cargo sqlx prepare` against one crate.
@@ -0,0 +1,112 @@
+//! Periodic maintenance of the wallet database.
+//!
+//! A long-lived wallet database accumulates free pages as notes, memos and
+//! checkpoints are pruned, and its query planner statistics go stale as the
+//! tables grow. [`run`] checks the database's integrity, returns free pages to
+//! the filesystem, and refreshes the statistics.
+//!
+//! Free pages can only be reclaimed incrementally if the database was created
+//! in incremental auto-vacuum mode, which the wallet's migrations predate; the
+//! first run converts the database with a full `VACUUM`, which rewrites it.
+
+use std::time::{Duration, Instant};
+
+use sqlx::sqlite::{SqliteConnection, SqlitePool};
+
+/// The value of `PRAGMA auto_vacuum` for incremental auto-vacuum mode.
+const AUTO_VACUUM_INCREMENTAL: i64 = 2;
+
+/// The outcome of a maintenance run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The problems found by the integrity check, or empty if there were none.
+    pub integrity_errors: Vec<String>,
+    /// The size of the database before maintenance, in bytes.
+    pub size_before: u64,
+    /// The size of the database after maintenance, in bytes.
+    pub size_after: u64,
+    /// How long maintenance took.
+    pub duration: Duration,
+}
+
+impl Report {
+    /// Whether the integrity check found no problems.
+    pub fn integrity_ok(&self) -> bool {
+        self.integrity_errors.is_empty()
+    }
+
+    /// The space returned to the filesystem, in bytes.
+    pub fn reclaimed(&self) -> u64 {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+/// Check the integrity of the wallet database, reclaim its free pages, and
+/// refresh its query planner statistics.
+///
+/// Free pages are not reclaimed if the integrity check fails, since rewriting
+/// a corrupt database can make matters worse.
+pub async fn run(pool: &SqlitePool) -> anyhow::Result<Report> {
+    let start = Instant::now();
+    let mut conn = pool.acquire().await?;
+    let size_before = size(&mut conn).await?;
+
+    // The check reports the single row "ok" if no problems were found.
+    let integrity_errors = sqlx::query_scalar::<_, String>("PRAGMA integrity_check")
+        .fetch_all(&mut conn)
+        .await?
+        .into_iter()
+        .filter(|row| row != "ok")
+        .collect::<Vec<_>>();
+
+    if integrity_errors.is_empty() {
+        let auto_vacuum = sqlx::query_scalar::<_, i64>("PRAGMA auto_vacuum")
+            .fetch_one(&mut conn)
+            .await?;
+        if auto_vacuum == AUTO_VACUUM_INCREMENTAL {
+            sqlx::query("PRAGMA incremental_vacuum")
+                .execute(&mut conn)
+                .await?;
+        } else {
+            tracing::info!("converting wallet database to incremental auto-vacuum");
+            sqlx::query("PRAGMA auto_vacuum = INCREMENTAL")
+                .execute(&mut conn)
+                .await?;
+            sqlx::query("VACUUM").execute(&mut conn).await?;
+        }
+    }
+    sqlx::query("ANALYZE").execute(&mut conn).await?;
+
+    let report = Report {
+        integrity_errors,
+        size_before,
+        size_after: size(&mut conn).await?,
+        duration: start.elapsed(),
+    };
+
+    if report.integrity_ok() {
+        tracing::info!(
+            reclaimed = report.reclaimed(),
+            size = report.size_after,
+            duration = ?report.duration,
+            "maintained wallet database"
+        );
+    } else {
+        for error in &report.integrity_errors {
+            tracing::error!(%error, "wallet database failed integrity check");
+        }
+    }
+
+    Ok(report)
+}
+
+/// The size of the database, in bytes.
+async fn size(conn: &mut SqliteConnection) -> anyhow::Result<u64> {
+    let page_count = sqlx::query_scalar::<_, i64>("PRAGMA page_count")
+        .fetch_one(&mut *conn)
+        .await?;
+    let page_size = sqlx::query_scalar::<_, i64>("PRAGMA page_size")
+        .fetch_one(&mut *conn)
+        .await?;
+    Ok((page_count * page_size) as u64)
+}
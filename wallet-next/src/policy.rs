@@ -0,0 +1,473 @@
+//! Spending limits and manual approval of spends.
+//!
+//! Before the wallet signs a spend, it asks the policy to [`authorize`] it.
+//! Rules are set per denomination at two levels: for the wallet as a whole,
+//! and for individual accounts. A spend must satisfy the rules at both levels:
+//!
+//! - if a level has any allowed destinations, the spend must go to one of them;
+//! - the spend must not exceed that level's per-transaction limit;
+//! - together with the spends authorized in the last 24 hours (across the
+//!   whole wallet, or within the account), it must not exceed the daily limit;
+//! - if it exceeds that level's approval threshold, it is held as pending until
+//!   an operator [`approve`]s or [`reject`]s it.
+//!
+//! This lets a team run a treasury wallet where routine payments go through
+//! unattended, while large or unusual ones need a second pair of eyes.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use penumbra_crypto::asset::{self, REGISTRY};
+use sqlx::sqlite::SqlitePool;
+
+use crate::Formatter;
+
+/// The window over which daily limits are enforced.
+pub const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The limits on spends of one denomination, in base units.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The most that may be spent in a single transaction.
+    pub max_per_tx: Option<u64>,
+    /// The most that may be spent in any 24-hour window.
+    pub max_per_day: Option<u64>,
+    /// Spends of more than this need manual approval.
+    pub approval_threshold: Option<u64>,
+}
+
+/// A spend the wallet has been asked to sign.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpendRequest {
+    /// The account the funds are spent from.
+    pub account: u64,
+    /// The address the funds are sent to.
+    pub destination: String,
+    pub denom: asset::Denom,
+    /// The amount, in base units.
+    pub amount: u64,
+}
+
+/// The outcome of asking the policy to authorize a spend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Authorization {
+    /// The spend may be signed.
+    Approved,
+    /// The spend must not be signed until it is [`approve`]d.
+    Pending { id: i64, reason: String },
+    /// The spend violates the policy, and must not be signed.
+    Denied { reason: String },
+}
+
+/// A spend held for manual approval.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingSpend {
+    pub id: i64,
+    pub request: SpendRequest,
+    /// Why the spend needs approval.
+    pub reason: String,
+    pub requested_at: SystemTime,
+}
+
+/// The policy's verdict on a spend, before any of its effects are recorded.
+enum Decision {
+    Allow,
+    NeedsApproval(String),
+    Deny(String),
+}
+
+/// Replace the limits on spends of `denom` from `account`, or from the whole
+/// wallet if `account` is `None`.
+///
+/// Setting the default (empty) [`Limits`] removes all limits at that level.
+pub async fn set_limits(
+    pool: &SqlitePool,
+    account: Option<u64>,
+    denom: &asset::Denom,
+    limits: Limits,
+) -> anyhow::Result<()> {
+    let account = account.map(|account| account as i64);
+    let denom = denom.to_string();
+    let max_per_tx = limits.max_per_tx.map(|amount| amount as i64);
+    let max_per_day = limits.max_per_day.map(|amount| amount as i64);
+    let approval_threshold = limits.approval_threshold.map(|amount| amount as i64);
+
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        r#"
+DELETE FROM spending_limits
+WHERE account IS ?1 AND denom = ?2
+        "#,
+        account,
+        denom
+    )
+    .execute(&mut tx)
+    .await?;
+
+    if limits != Limits::default() {
+        sqlx::query!(
+            r#"
+INSERT INTO spending_limits ( account, denom, max_per_tx, max_per_day, approval_threshold )
+VALUES ( ?1, ?2, ?3, ?4, ?5 )
+            "#,
+            account,
+            denom,
+            max_per_tx,
+            max_per_day,
+            approval_threshold
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// The limits on spends of `denom` from `account`, or from the whole wallet if
+/// `account` is `None`.
+pub async fn limits(
+    pool: &SqlitePool,
+    account: Option<u64>,
+    denom: &asset::Denom,
+) -> anyhow::Result<Limits> {
+    let account = account.map(|account| account as i64);
+    let denom = denom.to_string();
+
+    let row = sqlx::query!(
+        r#"
+SELECT max_per_tx, max_per_day, approval_threshold
+FROM spending_limits
+WHERE account IS ?1 AND denom = ?2
+        "#,
+        account,
+        denom
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row
+        .map(|row| Limits {
+            max_per_tx: row.max_per_tx.map(|amount| amount as u64),
+            max_per_day: row.max_per_day.map(|amount| amount as u64),
+            approval_threshold: row.approval_threshold.map(|amount| amount as u64),
+        })
+        .unwrap_or_default())
+}
+
+/// Replace the destinations spends from `account`, or from the whole wallet if
+/// `account` is `None`, are restricted to.
+///
+/// An empty list lifts the restriction at that level.
+pub async fn set_allowed_destinations(
+    pool: &SqlitePool,
+    account: Option<u64>,
+    addresses: &[String],
+) -> anyhow::Result<()> {
+    let account = account.map(|account| account as i64);
+
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        r#"
+DELETE FROM allowed_destinations
+WHERE account IS ?1
+        "#,
+        account
+    )
+    .execute(&mut tx)
+    .await?;
+
+    for address in addresses {
+        sqlx::query!(
+            r#"
+INSERT INTO allowed_destinations ( account, address )
+VALUES ( ?1, ?2 )
+            "#,
+            account,
+            address
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// The destinations spends from `account`, or from the whole wallet if
+/// `account` is `None`, are restricted to; empty if they are unrestricted.
+pub async fn allowed_destinations(
+    pool: &SqlitePool,
+    account: Option<u64>,
+) -> anyhow::Result<Vec<String>> {
+    let account = account.map(|account| account as i64);
+
+    let rows = sqlx::query!(
+        r#"
+SELECT address
+FROM allowed_destinations
+WHERE account IS ?1
+        "#,
+        account
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.address).collect())
+}
+
+/// Check a spend against the policy, recording it against the daily limits if
+/// it is approved, and holding it for manual approval if it needs it.
+pub async fn authorize(pool: &SqlitePool, request: &SpendRequest) -> anyhow::Result<Authorization> {
+    let now = SystemTime::now();
+
+    match evaluate(pool, request, now).await? {
+        Decision::Allow => {
+            let mut tx = pool.begin().await?;
+            record_spend(&mut tx, request, now).await?;
+            tx.commit().await?;
+            Ok(Authorization::Approved)
+        }
+        Decision::Deny(reason) => Ok(Authorization::Denied { reason }),
+        Decision::NeedsApproval(reason) => {
+            let account = request.account as i64;
+            let denom = request.denom.to_string();
+            let amount = request.amount as i64;
+            let requested_at = unix_ms(now);
+
+            let id = sqlx::query!(
+                r#"
+INSERT INTO pending_spends ( account, destination, denom, amount, reason, requested_at )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6 )
+                "#,
+                account,
+                request.destination,
+                denom,
+                amount,
+                reason,
+                requested_at
+            )
+            .execute(pool)
+            .await?
+            .last_insert_rowid();
+
+            tracing::info!(id, ?request, %reason, "holding spend for approval");
+            Ok(Authorization::Pending { id, reason })
+        }
+    }
+}
+
+/// Every spend held for manual approval, oldest first.
+pub async fn pending(pool: &SqlitePool) -> anyhow::Result<Vec<PendingSpend>> {
+    let rows = sqlx::query!(
+        r#"
+SELECT id, account, destination, denom, amount, reason, requested_at
+FROM pending_spends
+ORDER BY id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(PendingSpend {
+                id: row.id,
+                request: SpendRequest {
+                    account: row.account as u64,
+                    destination: row.destination,
+                    denom: REGISTRY
+                        .parse_denom(&row.denom)
+                        .ok_or_else(|| anyhow!("invalid denom {:?} in pending spend", row.denom))?,
+                    amount: row.amount as u64,
+                },
+                reason: row.reason,
+                requested_at: UNIX_EPOCH + Duration::from_millis(row.requested_at as u64),
+            })
+        })
+        .collect()
+}
+
+/// Approve a pending spend, returning it so that it can be signed.
+///
+/// Approval overrides approval thresholds, but not the other rules, which are
+/// checked again: if the spend now violates one (e.g., because other spends
+/// have since used up the daily limit), it stays pending.
+pub async fn approve(pool: &SqlitePool, id: i64) -> anyhow::Result<SpendRequest> {
+    let spend = pending(pool)
+        .await?
+        .into_iter()
+        .find(|spend| spend.id == id)
+        .ok_or_else(|| anyhow!("no pending spend with id {}", id))?;
+
+    let now = SystemTime::now();
+    if let Decision::Deny(reason) = evaluate(pool, &spend.request, now).await? {
+        return Err(anyhow!("cannot approve spend {}: {}", id, reason));
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        r#"
+DELETE FROM pending_spends
+WHERE id = ?1
+        "#,
+        id
+    )
+    .execute(&mut tx)
+    .await?;
+    record_spend(&mut tx, &spend.request, now).await?;
+    tx.commit().await?;
+
+    tracing::info!(id, request = ?spend.request, "approved pending spend");
+    Ok(spend.request)
+}
+
+/// Reject a pending spend, discarding it.
+pub async fn reject(pool: &SqlitePool, id: i64) -> anyhow::Result<()> {
+    let rejected = sqlx::query!(
+        r#"
+DELETE FROM pending_spends
+WHERE id = ?1
+        "#,
+        id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if rejected == 0 {
+        return Err(anyhow!("no pending spend with id {}", id));
+    }
+
+    tracing::info!(id, "rejected pending spend");
+    Ok(())
+}
+
+/// Check a spend against the rules for the whole wallet, then for its account.
+async fn evaluate(
+    pool: &SqlitePool,
+    request: &SpendRequest,
+    now: SystemTime,
+) -> anyhow::Result<Decision> {
+    let mut approval = None;
+    let display = |amount: u64| {
+        Formatter::default().format_in_unit(amount, &request.denom.best_unit_for(amount))
+    };
+
+    for scope in [None, Some(request.account)] {
+        let level = match scope {
+            None => "wallet".to_string(),
+            Some(account) => format!("account {}", account),
+        };
+
+        let allowed = allowed_destinations(pool, scope).await?;
+        if !allowed.is_empty() && !allowed.contains(&request.destination) {
+            return Ok(Decision::Deny(format!(
+                "{} is not an allowed destination for the {}",
+                request.destination, level
+            )));
+        }
+
+        let limits = limits(pool, scope, &request.denom).await?;
+        if let Some(max) = limits.max_per_tx {
+            if request.amount > max {
+                return Ok(Decision::Deny(format!(
+                    "spend of {} exceeds the {}'s limit of {} per transaction",
+                    display(request.amount),
+                    level,
+                    display(max)
+                )));
+            }
+        }
+        if let Some(max) = limits.max_per_day {
+            let spent = spent_since(pool, scope, &request.denom, now - DAY).await?;
+            if spent.saturating_add(request.amount) > max {
+                return Ok(Decision::Deny(format!(
+                    "spend of {} would exceed the {}'s limit of {} per day, \
+                     of which {} has already been spent",
+                    display(request.amount),
+                    level,
+                    display(max),
+                    display(spent)
+                )));
+            }
+        }
+        if let Some(threshold) = limits.approval_threshold {
+            if request.amount > threshold && approval.is_none() {
+                approval = Some(format!(
+                    "spend of {} exceeds the {}'s approval threshold of {}",
+                    display(request.amount),
+                    level,
+                    display(threshold)
+                ));
+            }
+        }
+    }
+
+    Ok(match approval {
+        Some(reason) => Decision::NeedsApproval(reason),
+        None => Decision::Allow,
+    })
+}
+
+/// The total amount of `denom` authorized for spending from `account`, or from
+/// the whole wallet if `account` is `None`, after `since`.
+async fn spent_since(
+    pool: &SqlitePool,
+    account: Option<u64>,
+    denom: &asset::Denom,
+    since: SystemTime,
+) -> anyhow::Result<u64> {
+    let account = account.map(|account| account as i64);
+    let denom = denom.to_string();
+    let since = unix_ms(since);
+
+    let total = sqlx::query!(
+        r#"
+SELECT COALESCE(SUM(amount), 0) AS "total!: i64"
+FROM authorized_spends
+WHERE (?1 IS NULL OR account = ?1) AND denom = ?2 AND authorized_at > ?3
+        "#,
+        account,
+        denom,
+        since
+    )
+    .fetch_one(pool)
+    .await?
+    .total;
+
+    Ok(total as u64)
+}
+
+/// Count an authorized spend against the daily limits.
+async fn record_spend(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    request: &SpendRequest,
+    now: SystemTime,
+) -> anyhow::Result<()> {
+    let account = request.account as i64;
+    let denom = request.denom.to_string();
+    let amount = request.amount as i64;
+    let authorized_at = unix_ms(now);
+
+    sqlx::query!(
+        r#"
+INSERT INTO authorized_spends ( account, denom, amount, authorized_at )
+VALUES ( ?1, ?2, ?3, ?4 )
+        "#,
+        account,
+        denom,
+        amount,
+        authorized_at
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .expect("time travels linearly in a forward direction")
+        .as_millis() as i64
+}
@@ -0,0 +1,258 @@
+//! Progress reporting for the wallet's sync, so that frontends can show how
+//! far it has caught up, and how long it has left, rather than a spinner.
+//!
+//! The sync task reports to a [`ProgressTracker`] when it starts catching up,
+//! the chain height it is catching up to, and each block it scans. The
+//! tracker computes the recent scanning rate from these, and from it the time
+//! left, and publishes the result to frontends following the
+//! `WatchSyncProgress` RPC.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use penumbra_proto::wallet_next as pb;
+use tokio::sync::watch;
+
+/// How far back the scanning rate is averaged over, so that the estimate
+/// follows changes in the rate without jumping around from block to block.
+const RATE_WINDOW: Duration = Duration::from_secs(30);
+
+/// How far the wallet's sync has caught up with the chain.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Progress {
+    /// Whether the wallet is currently catching up.
+    pub syncing: bool,
+    /// The most recently scanned height.
+    pub current_height: u64,
+    /// The height of the chain being caught up with.
+    pub target_height: u64,
+    /// The rate at which blocks were scanned over the last [`RATE_WINDOW`].
+    pub blocks_per_second: f64,
+    /// The estimated time left until the wallet has caught up, once blocks
+    /// have been scanned for long enough to know the rate.
+    pub eta: Option<Duration>,
+    /// The size of the compact blocks downloaded since sync started.
+    pub bytes_downloaded: u64,
+}
+
+impl From<Progress> for pb::SyncProgress {
+    fn from(progress: Progress) -> Self {
+        pb::SyncProgress {
+            syncing: progress.syncing,
+            current_height: progress.current_height,
+            target_height: progress.target_height,
+            blocks_per_second: progress.blocks_per_second,
+            has_eta: progress.eta.is_some(),
+            eta_seconds: progress.eta.map(|eta| eta.as_secs()).unwrap_or_default(),
+            bytes_downloaded: progress.bytes_downloaded,
+        }
+    }
+}
+
+/// Computes the sync's [`Progress`] from the sync task's reports, and
+/// publishes it to subscribers.
+///
+/// Clones report to, and publish from, the same progress.
+#[derive(Clone, Debug)]
+pub struct ProgressTracker {
+    state: Arc<Mutex<State>>,
+    progress_tx: Arc<watch::Sender<Progress>>,
+    // Held so that progress is published even while no frontend is following it.
+    progress_rx: watch::Receiver<Progress>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    progress: Progress,
+    /// When each recent height was scanned, oldest first, spanning at least
+    /// the last [`RATE_WINDOW`].
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Default for ProgressTracker {
+    fn default() -> Self {
+        let (progress_tx, progress_rx) = watch::channel(Progress::default());
+        Self {
+            state: Default::default(),
+            progress_tx: Arc::new(progress_tx),
+            progress_rx,
+        }
+    }
+}
+
+impl ProgressTracker {
+    /// The current progress, and then each change to it.
+    pub fn subscribe(&self) -> watch::Receiver<Progress> {
+        self.progress_rx.clone()
+    }
+
+    /// The current progress.
+    pub fn progress(&self) -> Progress {
+        self.progress_rx.borrow().clone()
+    }
+
+    /// Reports that the sync task is catching up from `current_height` to
+    /// `target_height`, restarting the rate and download counts.
+    pub fn start(&self, current_height: u64, target_height: u64) {
+        self.update(|state| {
+            state.samples.clear();
+            state.samples.push_back((Instant::now(), current_height));
+            state.progress = Progress {
+                syncing: true,
+                current_height,
+                target_height,
+                ..Default::default()
+            };
+        });
+    }
+
+    /// Reports that the chain being caught up with has grown to `target_height`.
+    pub fn set_target(&self, target_height: u64) {
+        self.update(|state| state.progress.target_height = target_height);
+    }
+
+    /// Reports that the block at `height` has been scanned, after downloading
+    /// `bytes` of it.
+    pub fn record_block(&self, height: u64, bytes: u64) {
+        self.update(|state| {
+            let now = Instant::now();
+            state.samples.push_back((now, height));
+            while state.samples.len() > 2 && now - state.samples[1].0 >= RATE_WINDOW {
+                state.samples.pop_front();
+            }
+
+            let progress = &mut state.progress;
+            progress.current_height = height;
+            progress.target_height = progress.target_height.max(height);
+            progress.bytes_downloaded += bytes;
+
+            let (started, start_height) = state.samples[0];
+            let elapsed = (now - started).as_secs_f64();
+            progress.blocks_per_second = if elapsed > 0.0 {
+                height.saturating_sub(start_height) as f64 / elapsed
+            } else {
+                0.0
+            };
+            let remaining = progress.target_height - height;
+            progress.eta = if remaining == 0 {
+                Some(Duration::ZERO)
+            } else if progress.blocks_per_second > 0.0 {
+                Some(Duration::from_secs_f64(
+                    remaining as f64 / progress.blocks_per_second,
+                ))
+            } else {
+                None
+            };
+        });
+    }
+
+    /// Reports that the sync task has caught up, or stopped.
+    pub fn finish(&self) {
+        self.update(|state| {
+            state.samples.clear();
+            state.progress.syncing = false;
+            state.progress.blocks_per_second = 0.0;
+            state.progress.eta = None;
+        });
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        let mut state = self.state.lock().unwrap();
+        f(&mut state);
+        // There is always a receiver, so this cannot fail.
+        let _ = self.progress_tx.send(state.progress.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replaces the tracker's samples with heights scanned the given number
+    /// of seconds ago.
+    fn scanned(tracker: &ProgressTracker, samples: &[(u64, u64)]) {
+        let now = Instant::now();
+        tracker.state.lock().unwrap().samples = samples
+            .iter()
+            .map(|&(seconds_ago, height)| (now - Duration::from_secs(seconds_ago), height))
+            .collect();
+    }
+
+    #[test]
+    fn estimates_the_time_left_from_the_rate() {
+        let tracker = ProgressTracker::default();
+        tracker.start(100, 1100);
+        scanned(&tracker, &[(10, 100)]);
+        tracker.record_block(200, 0);
+
+        // 100 blocks in (just over) 10 seconds leaves (just over) 90 seconds
+        // for the other 900.
+        let progress = tracker.progress();
+        assert!(
+            (9.9..=10.0).contains(&progress.blocks_per_second),
+            "{:?}",
+            progress
+        );
+        let eta = progress.eta.unwrap().as_secs_f64();
+        assert!((90.0..91.0).contains(&eta), "{:?}", progress);
+    }
+
+    #[test]
+    fn averages_the_rate_over_the_recent_window() {
+        let tracker = ProgressTracker::default();
+        tracker.start(0, 1000);
+        // The sample from 40 seconds ago is the newest one spanning the
+        // window, so the rate is measured from it rather than from the start.
+        scanned(&tracker, &[(100, 0), (40, 100), (20, 700)]);
+        tracker.record_block(800, 0);
+
+        let progress = tracker.progress();
+        assert!(
+            (17.4..=17.5).contains(&progress.blocks_per_second),
+            "{:?}",
+            progress
+        );
+        assert_eq!(tracker.state.lock().unwrap().samples.len(), 3);
+    }
+
+    #[test]
+    fn restarting_resets_the_counts() {
+        let tracker = ProgressTracker::default();
+        tracker.start(0, 10);
+        tracker.record_block(5, 512);
+        assert_eq!(tracker.progress().bytes_downloaded, 512);
+
+        tracker.start(5, 20);
+        assert_eq!(
+            tracker.progress(),
+            Progress {
+                syncing: true,
+                current_height: 5,
+                target_height: 20,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn eta_is_sent_in_whole_seconds_when_known() {
+        let progress = Progress {
+            syncing: true,
+            eta: Some(Duration::from_millis(90_700)),
+            ..Default::default()
+        };
+        let proto = pb::SyncProgress::from(progress.clone());
+        assert!(proto.has_eta);
+        assert_eq!(proto.eta_seconds, 90);
+
+        let proto = pb::SyncProgress::from(Progress {
+            eta: None,
+            ..progress
+        });
+        assert!(!proto.has_eta);
+        assert_eq!(proto.eta_seconds, 0);
+    }
+}
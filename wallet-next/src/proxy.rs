@@ -0,0 +1,211 @@
+//! Routing of the wallet's outbound connections through a SOCKS5 proxy.
+//!
+//! Every connection pwalletd makes to a node reveals the wallet's IP address
+//! to that node, and syncing reveals when the wallet is online. Running the
+//! connections through a SOCKS5 proxy such as Tor hides both. Host names are
+//! always passed to the proxy unresolved, so that DNS lookups for the node
+//! don't leak either, and so that Tor onion services can be used as endpoints.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Context};
+use reqwest::Url;
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+use tonic::transport::{Channel, Endpoint, Uri};
+
+use crate::tls::Tls;
+
+/// A SOCKS5 proxy, as given by a `socks5://[user:password@]host:port` URL.
+///
+/// Proxy credentials are useful with Tor, which isolates connections made
+/// with different credentials onto different circuits.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Proxy {
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+}
+
+impl FromStr for Proxy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let url = Url::parse(s).with_context(|| format!("invalid proxy URL {:?}", s))?;
+        // Host names are resolved by the proxy either way.
+        if !matches!(url.scheme(), "socks5" | "socks5h") {
+            return Err(anyhow!(
+                "unsupported proxy scheme {:?}, expected socks5",
+                url.scheme()
+            ));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("proxy URL {:?} is missing a host", s))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = url
+            .port()
+            .ok_or_else(|| anyhow!("proxy URL {:?} is missing a port", s))?;
+        let credentials = if url.username().is_empty() {
+            None
+        } else {
+            Some((
+                url.username().to_string(),
+                url.password().unwrap_or_default().to_string(),
+            ))
+        };
+
+        Ok(Self {
+            host,
+            port,
+            credentials,
+        })
+    }
+}
+
+impl fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never log the proxy password.
+        f.debug_struct("Proxy")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("authenticated", &self.credentials.is_some())
+            .finish()
+    }
+}
+
+impl Proxy {
+    /// Open a TCP connection to `host:port` through the proxy, without
+    /// resolving `host` locally.
+    pub async fn tunnel(&self, host: &str, port: u16) -> anyhow::Result<TcpStream> {
+        let proxy = (self.host.as_str(), self.port);
+        let target = (host, port);
+        let stream = match &self.credentials {
+            None => Socks5Stream::connect(proxy, target).await,
+            Some((username, password)) => {
+                Socks5Stream::connect_with_password(proxy, target, username, password).await
+            }
+        }
+        .with_context(|| format!("could not connect to {}:{} through proxy", host, port))?;
+
+        Ok(stream.into_inner())
+    }
+
+    /// The proxy URL, in the form understood by `reqwest`, which resolves host
+    /// names through the proxy only for the `socks5h` scheme.
+    fn reqwest_url(&self) -> String {
+        let credentials = match &self.credentials {
+            None => String::new(),
+            Some((username, password)) => format!("{}:{}@", username, password),
+        };
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        format!("socks5h://{}{}:{}", credentials, host, self.port)
+    }
+}
+
+/// Whether `url` points at a Tor onion service.
+pub fn is_onion(url: &str) -> bool {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.ends_with(".onion")))
+        .unwrap_or(false)
+}
+
+/// Connect a gRPC channel to `url`, through `proxy` if one is given, and over
+/// TLS as configured by `tls` if `url` is `https`.
+///
+/// # Errors
+///
+/// Returns an error if `url` is an onion service and no proxy is given, since
+/// onion services can only be reached through Tor (see [`check_route`]), or
+/// if `tls` does not allow connecting to `url` (see [`Tls::handshake`]).
+pub async fn connect(proxy: Option<&Proxy>, tls: &Tls, url: &str) -> anyhow::Result<Channel> {
+    let endpoint = Endpoint::from_shared(url.to_string())
+        .with_context(|| format!("invalid endpoint URL {:?}", url))?;
+
+    check_route(proxy, url)?;
+    let handshake = tls.handshake(url)?;
+    let proxy = proxy.cloned();
+    if proxy.is_none() && handshake.is_none() {
+        return Ok(endpoint.connect().await?);
+    }
+
+    let open = move |uri: Uri| {
+        let proxy = proxy.clone();
+        async move {
+            let host = uri
+                .host()
+                .ok_or_else(|| anyhow!("endpoint {} is missing a host", uri))?
+                .trim_start_matches('[')
+                .trim_end_matches(']');
+            let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+                Some("https") => 443,
+                _ => 80,
+            });
+            match proxy {
+                Some(proxy) => proxy.tunnel(host, port).await,
+                None => Ok(TcpStream::connect((host, port)).await?),
+            }
+        }
+    };
+
+    let channel = match handshake {
+        None => {
+            endpoint
+                .connect_with_connector(tower::service_fn(open))
+                .await?
+        }
+        Some(handshake) => {
+            endpoint
+                .connect_with_connector(tower::service_fn(move |uri: Uri| {
+                    let (open, handshake) = (open.clone(), handshake.clone());
+                    async move { handshake.secure(open(uri).await?).await }
+                }))
+                .await?
+        }
+    };
+
+    Ok(channel)
+}
+
+/// An HTTP client for requests to `url`, such as the node's Tendermint RPC
+/// endpoint, which connects through `proxy` if one is given, and secures its
+/// connections as `tls` says.
+///
+/// The client never follows redirects from `https` to plaintext `http`.
+///
+/// # Errors
+///
+/// Returns an error if `url` is an onion service and no proxy is given, or
+/// if `tls` refuses a plaintext connection to it.
+pub fn http_client(proxy: Option<&Proxy>, tls: &Tls, url: &str) -> anyhow::Result<reqwest::Client> {
+    check_route(proxy, url)?;
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy.reqwest_url())?);
+    }
+    if let Some(config) = tls.client_config(url)? {
+        builder = builder.use_preconfigured_tls(config).https_only(true);
+    }
+    Ok(builder.build()?)
+}
+
+/// Check that `url` can be reached with the given proxy configuration, i.e.,
+/// that it is not an onion service unless there is a proxy.
+pub fn check_route(proxy: Option<&Proxy>, url: &str) -> anyhow::Result<()> {
+    if proxy.is_none() && is_onion(url) {
+        Err(anyhow!(
+            "{} is an onion service, which can only be reached through a Tor SOCKS proxy \
+             (e.g. --proxy socks5://127.0.0.1:9050)",
+            url
+        ))
+    } else {
+        Ok(())
+    }
+}
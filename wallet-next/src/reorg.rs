@@ -0,0 +1,311 @@
+//! Detection of, and recovery from, chain resets and rollbacks.
+//!
+//! As the wallet [syncs](crate::sync), it records a checkpoint for each synced
+//! height: the anchor of its note commitment tree at that height, together
+//! with the serialized tree itself. If the node later reports a different
+//! anchor for a checkpointed height (for instance, because a testnet was
+//! reset), the wallet's local state no longer describes the node's chain.
+//! [`detect`] finds the last checkpoint both still agree on and records the
+//! divergence, which stays in place until [`recover`] discards every checkpoint
+//! past that point, so that sync resumes from the restored tree.
+
+use anyhow::anyhow;
+use penumbra_crypto::merkle;
+use penumbra_proto::{
+    client::specific::{specific_query_client::SpecificQueryClient, NctAnchorRequest},
+    crypto::MerkleRoot,
+};
+use sqlx::sqlite::SqlitePool;
+use tonic::{transport::Channel, Code};
+
+/// The number of most recent checkpoints retained by [`record_checkpoint`].
+///
+/// A divergence older than this cannot be rewound to, and requires a full resync.
+pub const MAX_CHECKPOINTS: u64 = 100;
+
+/// A point at which the wallet's synced state and the node's chain disagree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The highest checkpointed height whose anchor still matches the node, or
+    /// `None` if no checkpoint matches and the wallet must resync from scratch.
+    pub last_common_height: Option<u64>,
+    /// The lowest checkpointed height whose anchor does not match the node.
+    pub divergent_height: u64,
+}
+
+/// A checkpoint restored by [`recover`].
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    /// The height sync should resume after.
+    pub height: u64,
+    /// The serialized note commitment tree as of the end of `height`.
+    pub nct: Vec<u8>,
+}
+
+/// Record the anchor and local note commitment tree after syncing `height`,
+/// pruning all but the most recent [`MAX_CHECKPOINTS`] checkpoints.
+pub async fn record_checkpoint(
+    pool: &SqlitePool,
+    height: u64,
+    anchor: merkle::Root,
+    nct: &[u8],
+) -> anyhow::Result<()> {
+    let height = height as i64;
+    let anchor = MerkleRoot::from(anchor).inner;
+    let oldest_retained = height - MAX_CHECKPOINTS as i64 + 1;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+INSERT OR REPLACE INTO sync_checkpoints ( height, anchor, nct )
+VALUES ( ?1, ?2, ?3 )
+        "#,
+        height,
+        anchor,
+        nct
+    )
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+DELETE FROM sync_checkpoints
+WHERE height < ?1
+        "#,
+        oldest_retained
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// The most recently synced height, if the wallet has synced anything.
+pub async fn synced_height(pool: &SqlitePool) -> anyhow::Result<Option<u64>> {
+    let row = sqlx::query!(
+        r#"
+SELECT MAX(height) AS "height?: i64"
+FROM sync_checkpoints
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.height.map(|height| height as u64))
+}
+
+/// The checkpoint of the most recently synced height, which sync resumes
+/// after, if the wallet has synced anything.
+pub async fn latest_checkpoint(pool: &SqlitePool) -> anyhow::Result<Option<Checkpoint>> {
+    let row = sqlx::query!(
+        r#"
+SELECT height, nct
+FROM sync_checkpoints
+ORDER BY height DESC
+LIMIT 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| Checkpoint {
+        height: row.height as u64,
+        nct: row.nct,
+    }))
+}
+
+/// The divergence recorded by [`detect`] and not yet resolved by [`recover`], if any.
+pub async fn divergence(pool: &SqlitePool) -> anyhow::Result<Option<Divergence>> {
+    let row = sqlx::query!(
+        r#"
+SELECT last_common_height, divergent_height
+FROM sync_divergence
+WHERE id = 0
+        "#
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| Divergence {
+        last_common_height: row.last_common_height.map(|height| height as u64),
+        divergent_height: row.divergent_height as u64,
+    }))
+}
+
+/// Compare the wallet's checkpoints against the anchors reported by the node,
+/// recording and returning a divergence if they disagree.
+///
+/// A previously recorded divergence is returned as-is, without contacting the
+/// node, until it is resolved with [`recover`].
+pub async fn detect(
+    pool: &SqlitePool,
+    client: &mut SpecificQueryClient<Channel>,
+    chain_id: &str,
+) -> anyhow::Result<Option<Divergence>> {
+    if let Some(divergence) = divergence(pool).await? {
+        return Ok(Some(divergence));
+    }
+
+    let checkpoints = sqlx::query!(
+        r#"
+SELECT height, anchor
+FROM sync_checkpoints
+ORDER BY height
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    // Check the common case, that the latest checkpoint still matches, first.
+    match checkpoints.last() {
+        None => return Ok(None),
+        Some(latest) => {
+            if node_agrees(client, chain_id, latest.height as u64, &latest.anchor).await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    // Otherwise, binary search for the first checkpoint that disagrees.
+    //
+    // This assumes that once the checkpoints diverge from the node's chain,
+    // every later one does too. That holds when the chains differ in their
+    // notes, since the tree is append-only, but not when they contain the
+    // same notes in different blocks, as a later checkpoint may then agree
+    // again. The search then finds some disagreeing checkpoint right after an
+    // agreeing one, rather than the first, which is still safe to recover
+    // to, since the agreeing checkpoint's tree matches the node's.
+    let (mut lo, mut hi) = (0, checkpoints.len() - 1);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let checkpoint = &checkpoints[mid];
+        if node_agrees(
+            client,
+            chain_id,
+            checkpoint.height as u64,
+            &checkpoint.anchor,
+        )
+        .await?
+        {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+
+    let divergence = Divergence {
+        last_common_height: lo.checked_sub(1).map(|i| checkpoints[i].height as u64),
+        divergent_height: checkpoints[lo].height as u64,
+    };
+    tracing::warn!(
+        ?divergence,
+        "wallet state has diverged from the node's chain"
+    );
+
+    let last_common_height = divergence.last_common_height.map(|height| height as i64);
+    let divergent_height = divergence.divergent_height as i64;
+    sqlx::query!(
+        r#"
+INSERT OR REPLACE INTO sync_divergence ( id, last_common_height, divergent_height )
+VALUES ( 0, ?1, ?2 )
+        "#,
+        last_common_height,
+        divergent_height
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(Some(divergence))
+}
+
+/// Whether the node's anchor at `height` matches the wallet's recorded anchor.
+///
+/// A node that has no anchor at that height (e.g., after a reset to a shorter
+/// chain) does not agree.
+pub(crate) async fn node_agrees(
+    client: &mut SpecificQueryClient<Channel>,
+    chain_id: &str,
+    height: u64,
+    anchor: &[u8],
+) -> anyhow::Result<bool> {
+    let request = NctAnchorRequest {
+        chain_id: chain_id.to_string(),
+        height,
+    };
+    match client.nct_anchor(request).await {
+        Ok(response) => Ok(response.into_inner().inner == anchor),
+        Err(status) if status.code() == Code::NotFound => Ok(false),
+        Err(status) => Err(anyhow!(
+            "could not fetch anchor at height {}: {}",
+            height,
+            status
+        )),
+    }
+}
+
+/// Discard all local sync state past the recorded divergence, clearing it.
+///
+/// Returns the checkpoint sync resumes from, which is then the
+/// [latest](latest_checkpoint), or `None` if there is no common checkpoint and
+/// the wallet resyncs from scratch.
+///
+/// # Errors
+///
+/// Returns an error if no divergence has been recorded.
+pub async fn recover(pool: &SqlitePool) -> anyhow::Result<Option<Checkpoint>> {
+    let divergence = divergence(pool)
+        .await?
+        .ok_or_else(|| anyhow!("no divergence to recover from"))?;
+
+    // With no common checkpoint, every checkpoint is discarded.
+    let last_common_height = divergence
+        .last_common_height
+        .map(|height| height as i64)
+        .unwrap_or(-1);
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+DELETE FROM sync_checkpoints
+WHERE height > ?1
+        "#,
+        last_common_height
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let checkpoint = sqlx::query!(
+        r#"
+SELECT height, nct
+FROM sync_checkpoints
+WHERE height = ?1
+        "#,
+        last_common_height
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .map(|row| Checkpoint {
+        height: row.height as u64,
+        nct: row.nct,
+    });
+
+    sqlx::query!(
+        r#"
+DELETE FROM sync_divergence
+        "#
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    tracing::info!(
+        resume_height = ?checkpoint.as_ref().map(|c| c.height),
+        "recovered from chain divergence"
+    );
+    Ok(checkpoint)
+}
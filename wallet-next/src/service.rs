@@ -0,0 +1,788 @@
+//! The `pwalletd` gRPC service.
+
+use std::{convert::TryInto, pin::Pin, time::UNIX_EPOCH};
+
+use penumbra_crypto::asset::{self, REGISTRY};
+use penumbra_proto::wallet_next::{
+    self as pb, authorize_spend_response, export_history_request, wallet_event,
+    wallet_service_server::WalletService as WalletServiceRpc, AddressesRequest, AddressesResponse,
+    ApprovePendingRequest, ApprovePendingResponse, AuthorizeSpendRequest, AuthorizeSpendResponse,
+    BalanceRequest, BalanceResponse, ChangePassphraseRequest, ChangePassphraseResponse,
+    CreateDraftRequest, CreateDraftResponse, DeleteDraftRequest, DeleteDraftResponse,
+    ExportAddressViewingKeyRequest, ExportAddressViewingKeyResponse, ExportHistoryRequest,
+    ExportHistoryResponse, ExportWalletRequest, ExportWalletResponse, GetDraftRequest,
+    GetDraftResponse, ImportAddressViewingKeyRequest, ImportAddressViewingKeyResponse,
+    ImportWalletRequest, ImportWalletResponse, ListAnnotatorsRequest, ListAnnotatorsResponse,
+    ListDraftsRequest, ListDraftsResponse, ListPendingRequest, ListPendingResponse,
+    ListTransactionsRequest, ListTransactionsResponse, ListWatchedAddressesRequest,
+    ListWatchedAddressesResponse, MaintainNowRequest, MaintainNowResponse,
+    RecoverFromDivergenceRequest, RecoverFromDivergenceResponse, RegisterAnnotatorRequest,
+    RegisterAnnotatorResponse, RejectPendingRequest, RejectPendingResponse,
+    SetAllowedDestinationsRequest, SetAllowedDestinationsResponse, SetSpendingLimitsRequest,
+    SetSpendingLimitsResponse, SubmitTransactionRequest, SubmitTransactionResponse,
+    SyncStatusRequest, SyncStatusResponse, TransactionHistoryRequest, TransactionHistoryResponse,
+    UnregisterAnnotatorRequest, UnregisterAnnotatorResponse, UnwatchAddressRequest,
+    UnwatchAddressResponse, UpdateDraftRequest, UpdateDraftResponse, WatchHistoryRequest,
+    WatchSyncProgressRequest,
+};
+use sqlx::sqlite::SqlitePool;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream, WatchStream},
+    Stream, StreamExt,
+};
+use tonic::{Request, Response, Status};
+use tracing::instrument;
+
+use crate::{
+    annotate, archive, broadcast,
+    drafts::{self, Outcome},
+    events::{self, EventBus, EventKind},
+    history, keystore, maintenance,
+    policy::{self, Authorization},
+    progress::ProgressTracker,
+    reorg,
+    watch::{self, AddressViewingKey},
+    Formatter,
+};
+
+/// The most addresses which may be listed by a single request.
+const MAX_ADDRESSES: u64 = 1000;
+
+/// The wallet daemon's RPC service, backed by the wallet database.
+#[derive(Clone, Debug)]
+pub struct WalletService {
+    pool: SqlitePool,
+    progress: ProgressTracker,
+    events: EventBus,
+}
+
+impl WalletService {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            progress: ProgressTracker::default(),
+            events: EventBus::default(),
+        }
+    }
+
+    /// Reports the progress of the sync task reporting to `progress`.
+    pub fn with_sync_progress(self, progress: ProgressTracker) -> Self {
+        Self { progress, ..self }
+    }
+
+    /// Publishes the changes made through the service to `events`, rather
+    /// than to a bus of its own.
+    pub fn with_events(self, events: EventBus) -> Self {
+        Self { events, ..self }
+    }
+}
+
+#[tonic::async_trait]
+impl WalletServiceRpc for WalletService {
+    type WatchSyncProgressStream =
+        Pin<Box<dyn Stream<Item = Result<pb::SyncProgress, Status>> + Send>>;
+    type WatchHistoryStream = Pin<Box<dyn Stream<Item = Result<pb::WalletEvent, Status>> + Send>>;
+
+    #[instrument(skip(self, request))]
+    async fn change_passphrase(
+        &self,
+        request: Request<ChangePassphraseRequest>,
+    ) -> Result<Response<ChangePassphraseResponse>, Status> {
+        let ChangePassphraseRequest {
+            old_passphrase,
+            new_passphrase,
+        } = request.into_inner();
+        if new_passphrase.is_empty() {
+            return Err(Status::invalid_argument("new passphrase must not be empty"));
+        }
+
+        let reencrypted = keystore::change_passphrase(&self.pool, &old_passphrase, &new_passphrase)
+            .await
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        Ok(Response::new(ChangePassphraseResponse { reencrypted }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn sync_status(
+        &self,
+        _request: Request<SyncStatusRequest>,
+    ) -> Result<Response<SyncStatusResponse>, Status> {
+        let synced_height = reorg::synced_height(&self.pool)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+        let divergence = reorg::divergence(&self.pool)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+
+        Ok(Response::new(SyncStatusResponse {
+            synced: synced_height.is_some(),
+            synced_height: synced_height.unwrap_or_default(),
+            divergence: divergence.map(|divergence| pb::Divergence {
+                divergent_height: divergence.divergent_height,
+                has_common_height: divergence.last_common_height.is_some(),
+                last_common_height: divergence.last_common_height.unwrap_or_default(),
+            }),
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn watch_sync_progress(
+        &self,
+        _request: Request<WatchSyncProgressRequest>,
+    ) -> Result<Response<Self::WatchSyncProgressStream>, Status> {
+        let updates =
+            WatchStream::new(self.progress.subscribe()).map(|progress| Ok(progress.into()));
+        Ok(Response::new(Box::pin(updates)))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn recover_from_divergence(
+        &self,
+        _request: Request<RecoverFromDivergenceRequest>,
+    ) -> Result<Response<RecoverFromDivergenceResponse>, Status> {
+        let checkpoint = reorg::recover(&self.pool)
+            .await
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        Ok(Response::new(RecoverFromDivergenceResponse {
+            resumed: checkpoint.is_some(),
+            resume_height: checkpoint.map(|c| c.height).unwrap_or_default(),
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn submit_transaction(
+        &self,
+        request: Request<SubmitTransactionRequest>,
+    ) -> Result<Response<SubmitTransactionResponse>, Status> {
+        let origin = events::origin(&request);
+        let tx_hash = broadcast::submit(&self.pool, &request.into_inner().transaction)
+            .await
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let submitted = broadcast::submitted(&self.pool, &tx_hash)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?
+            .ok_or_else(|| Status::internal("submitted transaction is missing"))?;
+        self.events
+            .publish(&origin, EventKind::TransactionSubmitted(submitted));
+
+        Ok(Response::new(SubmitTransactionResponse { tx_hash }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn transaction_history(
+        &self,
+        _request: Request<TransactionHistoryRequest>,
+    ) -> Result<Response<TransactionHistoryResponse>, Status> {
+        let history = broadcast::history(&self.pool)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+
+        let transactions = history.into_iter().map(Into::into).collect();
+
+        Ok(Response::new(TransactionHistoryResponse { transactions }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn export_history(
+        &self,
+        request: Request<ExportHistoryRequest>,
+    ) -> Result<Response<ExportHistoryResponse>, Status> {
+        let request = request.into_inner();
+        let format = match export_history_request::Format::from_i32(request.format) {
+            Some(export_history_request::Format::Csv) => history::Format::Csv,
+            Some(export_history_request::Format::Ofx) => history::Format::Ofx,
+            Some(export_history_request::Format::Json) => history::Format::Json,
+            None => return Err(Status::invalid_argument("unknown export format")),
+        };
+        let end_height = match request.end_height {
+            0 => u64::MAX,
+            end_height => end_height,
+        };
+
+        let entries = history::entries(&self.pool, request.start_height..=end_height)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+        let contents = history::export(&entries, format, &Formatter::default());
+
+        Ok(Response::new(ExportHistoryResponse { contents }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn list_transactions(
+        &self,
+        request: Request<ListTransactionsRequest>,
+    ) -> Result<Response<ListTransactionsResponse>, Status> {
+        let request = request.into_inner();
+        let end_height = match request.end_height {
+            0 => u64::MAX,
+            end_height => end_height,
+        };
+
+        let entries = history::entries(&self.pool, request.start_height..=end_height)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+        let totals = history::totals(&entries)
+            .map_err(|e| Status::data_loss(e.to_string()))?
+            .into_iter()
+            .map(|total| pb::HistoryTotal {
+                category: total.category.as_str().to_string(),
+                denom: total.denom.to_string(),
+                credited: total.credited,
+                debited: total.debited,
+            })
+            .collect();
+        let mut transactions = Vec::new();
+        for transaction in history::transactions(entries) {
+            let annotations = annotate::annotations(&self.pool, &transaction.tx_hash)
+                .await
+                .map_err(|_| Status::unavailable("database error"))?;
+            transactions.push(pb::HistoryTransaction {
+                annotations: annotations.into_iter().map(Into::into).collect(),
+                ..transaction.into()
+            });
+        }
+
+        Ok(Response::new(ListTransactionsResponse {
+            transactions,
+            totals,
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn set_spending_limits(
+        &self,
+        request: Request<SetSpendingLimitsRequest>,
+    ) -> Result<Response<SetSpendingLimitsResponse>, Status> {
+        let request = request.into_inner();
+        let account = (!request.wallet_wide).then(|| request.account);
+        let denom = parse_denom(&request.denom)?;
+        // Zero means no limit.
+        let limits = policy::Limits {
+            max_per_tx: Some(request.max_per_tx).filter(|&max| max != 0),
+            max_per_day: Some(request.max_per_day).filter(|&max| max != 0),
+            approval_threshold: Some(request.approval_threshold).filter(|&max| max != 0),
+        };
+
+        policy::set_limits(&self.pool, account, &denom, limits)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+
+        Ok(Response::new(SetSpendingLimitsResponse {}))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn set_allowed_destinations(
+        &self,
+        request: Request<SetAllowedDestinationsRequest>,
+    ) -> Result<Response<SetAllowedDestinationsResponse>, Status> {
+        let request = request.into_inner();
+        let account = (!request.wallet_wide).then(|| request.account);
+
+        policy::set_allowed_destinations(&self.pool, account, &request.addresses)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+
+        Ok(Response::new(SetAllowedDestinationsResponse {}))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn authorize_spend(
+        &self,
+        request: Request<AuthorizeSpendRequest>,
+    ) -> Result<Response<AuthorizeSpendResponse>, Status> {
+        let request = request.into_inner();
+        let spend = request
+            .spend
+            .ok_or_else(|| Status::invalid_argument("missing spend"))?;
+        let (amount, denom) = if request.display_amount.is_empty() {
+            (spend.amount, parse_denom(&spend.denom)?)
+        } else {
+            Formatter::default()
+                .parse_denominated(&request.display_amount)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?
+        };
+        let spend = policy::SpendRequest {
+            account: spend.account,
+            denom,
+            destination: spend.destination,
+            amount,
+        };
+
+        let authorization = policy::authorize(&self.pool, &spend)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+
+        let (status, pending_id, reason) = match authorization {
+            Authorization::Approved => {
+                (authorize_spend_response::Status::Approved, 0, String::new())
+            }
+            Authorization::Pending { id, reason } => {
+                (authorize_spend_response::Status::Pending, id as u64, reason)
+            }
+            Authorization::Denied { reason } => {
+                (authorize_spend_response::Status::Denied, 0, reason)
+            }
+        };
+
+        Ok(Response::new(AuthorizeSpendResponse {
+            status: status as i32,
+            pending_id,
+            reason,
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn list_pending(
+        &self,
+        _request: Request<ListPendingRequest>,
+    ) -> Result<Response<ListPendingResponse>, Status> {
+        let pending = policy::pending(&self.pool)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+
+        let pending = pending
+            .into_iter()
+            .map(|spend| pb::PendingSpend {
+                id: spend.id as u64,
+                spend: Some(spend_request_to_proto(spend.request)),
+                reason: spend.reason,
+                requested_at_unix_ms: spend
+                    .requested_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+            })
+            .collect();
+
+        Ok(Response::new(ListPendingResponse { pending }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn approve_pending(
+        &self,
+        request: Request<ApprovePendingRequest>,
+    ) -> Result<Response<ApprovePendingResponse>, Status> {
+        let spend = policy::approve(&self.pool, request.into_inner().id as i64)
+            .await
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        Ok(Response::new(ApprovePendingResponse {
+            spend: Some(spend_request_to_proto(spend)),
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn reject_pending(
+        &self,
+        request: Request<RejectPendingRequest>,
+    ) -> Result<Response<RejectPendingResponse>, Status> {
+        policy::reject(&self.pool, request.into_inner().id as i64)
+            .await
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        Ok(Response::new(RejectPendingResponse {}))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn maintain_now(
+        &self,
+        _request: Request<MaintainNowRequest>,
+    ) -> Result<Response<MaintainNowResponse>, Status> {
+        let report = maintenance::run(&self.pool)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+
+        Ok(Response::new(MaintainNowResponse {
+            integrity_errors: report.integrity_errors,
+            size_before: report.size_before,
+            size_after: report.size_after,
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn export_address_viewing_key(
+        &self,
+        request: Request<ExportAddressViewingKeyRequest>,
+    ) -> Result<Response<ExportAddressViewingKeyResponse>, Status> {
+        let request = request.into_inner();
+
+        let key = watch::export(
+            &self.pool,
+            &request.passphrase,
+            request.address_index.into(),
+        )
+        .await
+        .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        Ok(Response::new(ExportAddressViewingKeyResponse {
+            viewing_key: key.to_string(),
+            address: key.address().to_string(),
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn import_address_viewing_key(
+        &self,
+        request: Request<ImportAddressViewingKeyRequest>,
+    ) -> Result<Response<ImportAddressViewingKeyResponse>, Status> {
+        let request = request.into_inner();
+        let key = request
+            .viewing_key
+            .parse::<AddressViewingKey>()
+            .map_err(|e| Status::invalid_argument(format!("invalid viewing key: {}", e)))?;
+
+        let id = watch::import(&self.pool, &request.label, &key)
+            .await
+            .map_err(|e| Status::already_exists(e.to_string()))?;
+
+        Ok(Response::new(ImportAddressViewingKeyResponse {
+            id: id as u64,
+            address: key.address().to_string(),
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn list_watched_addresses(
+        &self,
+        _request: Request<ListWatchedAddressesRequest>,
+    ) -> Result<Response<ListWatchedAddressesResponse>, Status> {
+        let watched = watch::watched(&self.pool)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+
+        let watched = watched
+            .into_iter()
+            .map(|watched| pb::WatchedAddress {
+                id: watched.id as u64,
+                label: watched.label,
+                address: watched.key.address().to_string(),
+                imported_at_unix_ms: watched
+                    .imported_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+            })
+            .collect();
+
+        Ok(Response::new(ListWatchedAddressesResponse { watched }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn unwatch_address(
+        &self,
+        request: Request<UnwatchAddressRequest>,
+    ) -> Result<Response<UnwatchAddressResponse>, Status> {
+        let id = request.into_inner().id;
+
+        let removed = watch::unwatch(&self.pool, id as i64)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+        if !removed {
+            return Err(Status::not_found(format!(
+                "no watched address with id {}",
+                id
+            )));
+        }
+
+        Ok(Response::new(UnwatchAddressResponse {}))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn export_wallet(
+        &self,
+        request: Request<ExportWalletRequest>,
+    ) -> Result<Response<ExportWalletResponse>, Status> {
+        let archive = archive::export(&self.pool, &request.into_inner().passphrase)
+            .await
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        Ok(Response::new(ExportWalletResponse { archive }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn import_wallet(
+        &self,
+        request: Request<ImportWalletRequest>,
+    ) -> Result<Response<ImportWalletResponse>, Status> {
+        let ImportWalletRequest {
+            passphrase,
+            archive,
+        } = request.into_inner();
+
+        let summary = archive::import(&self.pool, &passphrase, &archive)
+            .await
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        Ok(Response::new(ImportWalletResponse {
+            tables: summary.tables,
+            rows: summary.rows,
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn balance(
+        &self,
+        _request: Request<BalanceRequest>,
+    ) -> Result<Response<BalanceResponse>, Status> {
+        let balances = history::balances(&self.pool)
+            .await
+            .map_err(|e| Status::data_loss(e.to_string()))?;
+
+        let balances = balances
+            .into_iter()
+            .map(|(denom, amount)| pb::AssetBalance {
+                denom: denom.to_string(),
+                amount,
+            })
+            .collect();
+
+        Ok(Response::new(BalanceResponse { balances }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn addresses(
+        &self,
+        request: Request<AddressesRequest>,
+    ) -> Result<Response<AddressesResponse>, Status> {
+        let request = request.into_inner();
+        if request.count > MAX_ADDRESSES {
+            return Err(Status::invalid_argument(format!(
+                "at most {} addresses may be listed at once",
+                MAX_ADDRESSES
+            )));
+        }
+        let end_index = request
+            .start_index
+            .checked_add(request.count)
+            .ok_or_else(|| Status::invalid_argument("address index out of range"))?;
+
+        let spend_key = watch::spend_key(&self.pool, &request.passphrase)
+            .await
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+        let ivk = spend_key.incoming_viewing_key();
+
+        let addresses = (request.start_index..end_index)
+            .map(|index| pb::WalletAddress {
+                index,
+                address: ivk.payment_address(index.into()).0.to_string(),
+            })
+            .collect();
+
+        Ok(Response::new(AddressesResponse { addresses }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn register_annotator(
+        &self,
+        request: Request<RegisterAnnotatorRequest>,
+    ) -> Result<Response<RegisterAnnotatorResponse>, Status> {
+        let RegisterAnnotatorRequest { name, endpoint } = request.into_inner();
+        annotate::check_registration(&name, &endpoint)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        annotate::register(&self.pool, &name, &endpoint)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+
+        Ok(Response::new(RegisterAnnotatorResponse {}))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn unregister_annotator(
+        &self,
+        request: Request<UnregisterAnnotatorRequest>,
+    ) -> Result<Response<UnregisterAnnotatorResponse>, Status> {
+        let name = request.into_inner().name;
+
+        let removed = annotate::unregister(&self.pool, &name)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+        if !removed {
+            return Err(Status::not_found(format!("no annotator named {:?}", name)));
+        }
+
+        Ok(Response::new(UnregisterAnnotatorResponse {}))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn list_annotators(
+        &self,
+        _request: Request<ListAnnotatorsRequest>,
+    ) -> Result<Response<ListAnnotatorsResponse>, Status> {
+        let annotators = annotate::annotators(&self.pool)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?
+            .into_iter()
+            .map(|annotator| pb::Annotator {
+                suspended: annotator.suspended(),
+                name: annotator.name,
+                endpoint: annotator.endpoint,
+                consecutive_failures: annotator.consecutive_failures,
+                last_error: annotator.last_error.unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(Response::new(ListAnnotatorsResponse { annotators }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn watch_history(
+        &self,
+        request: Request<WatchHistoryRequest>,
+    ) -> Result<Response<Self::WatchHistoryStream>, Status> {
+        let own = events::origin(&request);
+        // A frontend which has not named itself cannot tell its own changes apart.
+        let exclude_own = request.into_inner().exclude_own && !own.is_empty();
+
+        let events =
+            BroadcastStream::new(self.events.subscribe()).filter_map(move |event| match event {
+                Ok(event) if exclude_own && event.origin == own => None,
+                Ok(event) => Some(Ok(event.into())),
+                Err(BroadcastStreamRecvError::Lagged(missed)) => Some(Ok(pb::WalletEvent {
+                    sequence: 0,
+                    origin: String::new(),
+                    event: Some(wallet_event::Event::Missed(missed)),
+                })),
+            });
+        Ok(Response::new(Box::pin(events)))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn create_draft(
+        &self,
+        request: Request<CreateDraftRequest>,
+    ) -> Result<Response<CreateDraftResponse>, Status> {
+        let origin = events::origin(&request);
+        let CreateDraftRequest { label, contents } = request.into_inner();
+
+        let draft = drafts::create(&self.pool, &label, &contents)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+        self.events
+            .publish(&origin, EventKind::DraftSaved(draft.clone()));
+
+        Ok(Response::new(CreateDraftResponse {
+            draft: Some(draft.into()),
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn get_draft(
+        &self,
+        request: Request<GetDraftRequest>,
+    ) -> Result<Response<GetDraftResponse>, Status> {
+        let id = request.into_inner().id;
+
+        let draft = drafts::get(&self.pool, draft_id(id)?)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?
+            .ok_or_else(|| Status::not_found(format!("no draft with id {}", id)))?;
+
+        Ok(Response::new(GetDraftResponse {
+            draft: Some(draft.into()),
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn list_drafts(
+        &self,
+        _request: Request<ListDraftsRequest>,
+    ) -> Result<Response<ListDraftsResponse>, Status> {
+        let drafts = drafts::list(&self.pool)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        Ok(Response::new(ListDraftsResponse { drafts }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn update_draft(
+        &self,
+        request: Request<UpdateDraftRequest>,
+    ) -> Result<Response<UpdateDraftResponse>, Status> {
+        let origin = events::origin(&request);
+        let UpdateDraftRequest {
+            id,
+            expected_version,
+            label,
+            contents,
+        } = request.into_inner();
+
+        let outcome = drafts::update(
+            &self.pool,
+            draft_id(id)?,
+            expected_version,
+            &label,
+            &contents,
+        )
+        .await
+        .map_err(|_| Status::unavailable("database error"))?;
+        let draft = written(outcome, id, expected_version)?;
+        self.events
+            .publish(&origin, EventKind::DraftSaved(draft.clone()));
+
+        Ok(Response::new(UpdateDraftResponse {
+            draft: Some(draft.into()),
+        }))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn delete_draft(
+        &self,
+        request: Request<DeleteDraftRequest>,
+    ) -> Result<Response<DeleteDraftResponse>, Status> {
+        let origin = events::origin(&request);
+        let DeleteDraftRequest {
+            id,
+            expected_version,
+        } = request.into_inner();
+
+        let outcome = drafts::delete(&self.pool, draft_id(id)?, expected_version)
+            .await
+            .map_err(|_| Status::unavailable("database error"))?;
+        written(outcome, id, expected_version)?;
+        self.events
+            .publish(&origin, EventKind::DraftDeleted { id: id as i64 });
+
+        Ok(Response::new(DeleteDraftResponse {}))
+    }
+}
+
+fn parse_denom(denom: &str) -> Result<asset::Denom, Status> {
+    REGISTRY
+        .parse_denom(denom)
+        .ok_or_else(|| Status::invalid_argument(format!("invalid denom {:?}", denom)))
+}
+
+fn spend_request_to_proto(spend: policy::SpendRequest) -> pb::SpendRequest {
+    pb::SpendRequest {
+        account: spend.account,
+        destination: spend.destination,
+        denom: spend.denom.to_string(),
+        amount: spend.amount,
+    }
+}
+
+fn draft_id(id: u64) -> Result<i64, Status> {
+    id.try_into()
+        .map_err(|_| Status::not_found(format!("no draft with id {}", id)))
+}
+
+/// The result of a write to the draft with the given id, expected to be at
+/// `expected_version`.
+fn written<T>(outcome: Outcome<T>, id: u64, expected_version: u64) -> Result<T, Status> {
+    match outcome {
+        Outcome::Done(result) => Ok(result),
+        Outcome::Conflict(draft) => Err(Status::aborted(format!(
+            "draft {} is at version {}, not {}; it was changed by another frontend",
+            id, draft.version, expected_version
+        ))),
+        Outcome::NotFound => Err(Status::not_found(format!("no draft with id {}", id))),
+    }
+}
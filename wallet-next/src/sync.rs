@@ -0,0 +1,128 @@
+//! Syncing the wallet with the node's chain.
+//!
+//! Each pass of [`sync`] downloads the compact blocks the wallet has not yet
+//! scanned, appends their note commitments to the wallet's note commitment
+//! tree, and records a [`reorg`] checkpoint of the tree after each block. The
+//! tree is restored from the latest checkpoint at the start of every pass, so
+//! once [`reorg::recover`] has discarded the checkpoints past a divergence,
+//! the next pass resumes from the tree as it was at the last common height.
+
+use std::convert::TryFrom;
+
+use anyhow::Context;
+use penumbra_chain::sync::CompactBlock;
+use penumbra_crypto::merkle::{Frontier, NoteCommitmentTree, TreeExt};
+use penumbra_proto::{
+    client::oblivious::{
+        oblivious_query_client::ObliviousQueryClient, ChainStatsRequest,
+        CompactBlockRangeInterrupted, CompactBlockRangeRequest,
+    },
+    Message,
+};
+use sqlx::sqlite::SqlitePool;
+use tonic::{transport::Channel, Code, Status};
+
+use crate::{progress::ProgressTracker, reorg};
+
+/// Scans the blocks the node has committed since the wallet last synced,
+/// reporting to `progress` as it goes.
+///
+/// Nothing is scanned while a divergence is recorded, since the blocks would
+/// extend a tree the node's chain no longer contains; sync resumes once the
+/// divergence is resolved with [`reorg::recover`].
+///
+/// If the node interrupts the stream, e.g. because it is draining connections
+/// before a restart, the pass ends early, and the next resumes from the last
+/// block scanned.
+pub async fn sync(
+    pool: &SqlitePool,
+    client: &mut ObliviousQueryClient<Channel>,
+    chain_id: &str,
+    progress: &ProgressTracker,
+) -> anyhow::Result<()> {
+    if reorg::divergence(pool).await?.is_some() {
+        tracing::debug!("not syncing until the wallet recovers from the divergence");
+        return Ok(());
+    }
+
+    let (start_height, mut nct) = match reorg::latest_checkpoint(pool).await? {
+        Some(checkpoint) => (
+            checkpoint.height + 1,
+            bincode::deserialize(&checkpoint.nct).context("invalid checkpointed tree")?,
+        ),
+        None => (0, NoteCommitmentTree::new(0)),
+    };
+
+    // The node's compact block ranges exclude the latest block, so it is
+    // scanned on a later pass, once another block is committed after it.
+    let latest_height = client
+        .chain_stats(ChainStatsRequest {
+            chain_id: chain_id.to_string(),
+        })
+        .await?
+        .into_inner()
+        .height;
+    if start_height >= latest_height {
+        return Ok(());
+    }
+
+    progress.start(start_height.saturating_sub(1), latest_height - 1);
+    let scanned = scan(
+        pool,
+        client,
+        CompactBlockRangeRequest {
+            chain_id: chain_id.to_string(),
+            start_height,
+            end_height: latest_height,
+        },
+        &mut nct,
+        progress,
+    )
+    .await;
+    progress.finish();
+    scanned
+}
+
+async fn scan(
+    pool: &SqlitePool,
+    client: &mut ObliviousQueryClient<Channel>,
+    range: CompactBlockRangeRequest,
+    nct: &mut NoteCommitmentTree,
+    progress: &ProgressTracker,
+) -> anyhow::Result<()> {
+    let mut stream = client.compact_block_range(range).await?.into_inner();
+    loop {
+        let block = match stream.message().await {
+            Ok(Some(block)) => block,
+            Ok(None) => return Ok(()),
+            Err(status) => match interruption(&status) {
+                Some(interrupted) => {
+                    tracing::info!(
+                        resume_height = interrupted.resume_height,
+                        "node interrupted sync; resuming on the next pass"
+                    );
+                    return Ok(());
+                }
+                None => return Err(status.into()),
+            },
+        };
+        let bytes = block.encoded_len() as u64;
+        let block = CompactBlock::try_from(block)?;
+
+        for output in &block.outputs {
+            nct.append(&output.note_commitment);
+        }
+        reorg::record_checkpoint(pool, block.height, nct.root2(), &bincode::serialize(nct)?)
+            .await?;
+        progress.record_block(block.height, bytes);
+    }
+}
+
+/// Whether `status` ended the stream early but resumably, and if so, where to
+/// resume from.
+fn interruption(status: &Status) -> Option<CompactBlockRangeInterrupted> {
+    if status.code() != Code::Unavailable || status.details().is_empty() {
+        return None;
+    }
+    CompactBlockRangeInterrupted::decode(status.details()).ok()
+}
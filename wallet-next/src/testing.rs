@@ -0,0 +1,46 @@
+//! Helpers for testing the wallet end-to-end against an in-process `pd`.
+//!
+//! The in-process node is pd's own test [`Node`], which runs genesis on a
+//! fresh temporary database, serves pd's query services on an ephemeral local
+//! port, and stands in for Tendermint's RPC with pd's mempool, so tests can
+//! drive the wallet's sync and broadcast pipelines against real chain state
+//! without a running testnet.
+//!
+//! Only [`wallet_pool`] is available to the wallet's own unit tests; the rest
+//! needs the `testing` feature, which brings in pd's test node.
+
+#[cfg(feature = "testing")]
+pub use pd::testing::Node;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+
+#[cfg(feature = "testing")]
+use crate::{broadcast, progress::ProgressTracker, reorg, sync};
+
+/// Creates an empty, fully migrated in-memory wallet database.
+pub async fn wallet_pool() -> anyhow::Result<SqlitePool> {
+    // Every connection to `sqlite::memory:` is a separate database, so the
+    // pool must hold exactly one connection, which is never recycled.
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await?;
+    crate::MIGRATOR.run(&pool).await?;
+    Ok(pool)
+}
+
+/// Runs one pass of pwalletd's sync pipeline against `node`.
+#[cfg(feature = "testing")]
+pub async fn sync(pool: &SqlitePool, node: &Node) -> anyhow::Result<()> {
+    let mut client = node.oblivious_client().await?;
+    sync::sync(pool, &mut client, "", &ProgressTracker::default()).await
+}
+
+/// Runs one pass of pwalletd's broadcast pipeline against `node`, expiring
+/// transactions against the wallet's synced height, as pwalletd does.
+#[cfg(feature = "testing")]
+pub async fn broadcast(pool: &SqlitePool, node: &Node) -> anyhow::Result<()> {
+    let height = reorg::synced_height(pool).await?.unwrap_or_default();
+    broadcast::process(pool, &reqwest::Client::new(), &node.rpc_url(), height).await
+}
@@ -0,0 +1,311 @@
+//! TLS for the wallet's connections to nodes, with optional pinning.
+//!
+//! A hosted pwalletd talks to a node, its Tendermint RPC and a checkpoint
+//! provider over networks it does not control, and the requests it makes are
+//! relevant to the wallets it serves. Connections to `https` endpoints are
+//! encrypted and authenticated: by default, the endpoint's certificate must
+//! chain to one of the web PKI roots or to a CA given with
+//! [`Tls::add_ca_file`]. Alternatively, an endpoint can be pinned to one or
+//! more [`Pin`]s, in which case its certificate is accepted if and only if it
+//! matches one of them. Pinning works with self-signed certificates, and
+//! protects against a compromised or coerced CA.
+//!
+//! Onion services are already encrypted and authenticated end-to-end by Tor,
+//! so they may be reached over plaintext `http` even when TLS is required.
+
+use std::{
+    collections::BTreeMap, fmt, fs::File, io::BufReader, path::Path, str::FromStr, sync::Arc,
+};
+
+use anyhow::{anyhow, Context};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{
+        client::{ServerCertVerified, ServerCertVerifier},
+        Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+    },
+    TlsConnector,
+};
+
+use crate::proxy::is_onion;
+
+/// An acceptable certificate for a pinned endpoint, as given by
+/// `spki-sha256:<hex>` or `cert-sha256:<hex>`.
+///
+/// Pinning the public key rather than the whole certificate lets the node
+/// renew its certificate without changing its pin, so long as it keeps its key.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Pin {
+    /// The SHA-256 hash of the certificate's DER-encoded SubjectPublicKeyInfo.
+    Spki([u8; 32]),
+    /// The SHA-256 hash of the whole DER-encoded certificate.
+    Certificate([u8; 32]),
+}
+
+impl Pin {
+    /// The public key pin of a DER-encoded certificate.
+    pub fn spki(certificate: &[u8]) -> anyhow::Result<Self> {
+        let (_, certificate) = x509_parser::parse_x509_certificate(certificate)
+            .map_err(|e| anyhow!("could not parse certificate: {}", e))?;
+        Ok(Pin::Spki(
+            Sha256::digest(certificate.public_key().raw).into(),
+        ))
+    }
+
+    /// The pin of the whole of a DER-encoded certificate.
+    pub fn certificate(certificate: &[u8]) -> Self {
+        Pin::Certificate(Sha256::digest(certificate).into())
+    }
+
+    fn matches(&self, certificate: &[u8]) -> bool {
+        match self {
+            Pin::Spki(_) => Pin::spki(certificate).map_or(false, |pin| pin == *self),
+            Pin::Certificate(_) => Pin::certificate(certificate) == *self,
+        }
+    }
+}
+
+impl FromStr for Pin {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (kind, hash) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid pin {:?}, expected spki-sha256:<hex>", s))?;
+        let hash: [u8; 32] = hex::decode(hash)
+            .ok()
+            .and_then(|hash| hash.try_into().ok())
+            .ok_or_else(|| anyhow!("pin {:?} is not a hex-encoded SHA-256 hash", s))?;
+        match kind {
+            "spki-sha256" => Ok(Pin::Spki(hash)),
+            "cert-sha256" => Ok(Pin::Certificate(hash)),
+            _ => Err(anyhow!(
+                "unsupported pin type {:?}, expected spki-sha256 or cert-sha256",
+                kind
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pin::Spki(hash) => write!(f, "spki-sha256:{}", hex::encode(hash)),
+            Pin::Certificate(hash) => write!(f, "cert-sha256:{}", hex::encode(hash)),
+        }
+    }
+}
+
+impl fmt::Debug for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// A pin for one endpoint, as given by `<host>:<port>=<pin>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EndpointPin {
+    pub authority: String,
+    pub pin: Pin,
+}
+
+impl FromStr for EndpointPin {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (authority, pin) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid endpoint pin {:?}, expected <host>:<port>=<pin>", s))?;
+        Ok(Self {
+            authority: authority.to_ascii_lowercase(),
+            pin: pin.parse()?,
+        })
+    }
+}
+
+/// How to secure connections to nodes.
+#[derive(Clone, Debug, Default)]
+pub struct Tls {
+    /// DER-encoded CA certificates to trust besides the web PKI roots.
+    cas: Vec<Vec<u8>>,
+    /// The pins of each pinned endpoint, by `host:port`.
+    pins: BTreeMap<String, Vec<Pin>>,
+    /// Whether to refuse plaintext connections to anything but onion services.
+    required: bool,
+}
+
+impl Tls {
+    /// Trusts the PEM-encoded CA certificates in the file at `path`, besides
+    /// the web PKI roots.
+    pub fn add_ca_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let file = File::open(path).with_context(|| format!("could not open {:?}", path))?;
+        let cas = rustls_pemfile::certs(&mut BufReader::new(file))?;
+        if cas.is_empty() {
+            return Err(anyhow!("{:?} holds no PEM-encoded certificates", path));
+        }
+        self.cas.extend(cas);
+        Ok(())
+    }
+
+    /// Accepts only certificates matching `pin`, or any other pin added for
+    /// it, from the endpoint at `authority` (`host:port`).
+    pub fn add_pin(&mut self, authority: &str, pin: Pin) {
+        self.pins
+            .entry(authority.to_ascii_lowercase())
+            .or_default()
+            .push(pin);
+    }
+
+    /// Refuses plaintext connections to endpoints other than onion services.
+    pub fn require(&mut self) {
+        self.required = true;
+    }
+
+    /// Decides how to secure a gRPC connection to `url`: returns the TLS
+    /// handshake to perform for `https` URLs, and `None` for plaintext ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a connection to `url` would be plaintext, but the
+    /// endpoint is pinned or TLS is required.
+    pub fn handshake(&self, url: &str) -> anyhow::Result<Option<Handshake>> {
+        let mut config = match self.client_config(url)? {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+        // The node serves gRPC, which is HTTP/2 only.
+        config.alpn_protocols = vec![b"h2".to_vec()];
+
+        let (_, host, _) = parse_endpoint(url)?;
+        Ok(Some(Handshake {
+            connector: TlsConnector::from(Arc::new(config)),
+            server_name: ServerName::try_from(host.as_str())
+                .map_err(|_| anyhow!("{:?} is not a valid TLS server name", host))?,
+        }))
+    }
+
+    /// Decides how to secure connections to `url`: returns the TLS
+    /// configuration to use for `https` URLs, and `None` for plaintext ones.
+    ///
+    /// The configuration offers no ALPN protocols, so suits HTTP/1.1 clients
+    /// such as [`proxy::http_client`](crate::proxy::http_client).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a connection to `url` would be plaintext, but the
+    /// endpoint is pinned or TLS is required.
+    pub fn client_config(&self, url: &str) -> anyhow::Result<Option<ClientConfig>> {
+        let (parsed, host, port) = parse_endpoint(url)?;
+        let pins = self
+            .pins
+            .get(&format!("{}:{}", host, port).to_ascii_lowercase());
+
+        match parsed.scheme() {
+            "https" => {}
+            "http" if pins.is_some() => {
+                return Err(anyhow!("{} is pinned, so must use https", url));
+            }
+            "http" if self.required && !is_onion(url) => {
+                return Err(anyhow!(
+                    "{} is not an onion service, so must use https when TLS is required",
+                    url
+                ));
+            }
+            "http" => return Ok(None),
+            scheme => return Err(anyhow!("unsupported endpoint scheme {:?}", scheme)),
+        }
+
+        let config = ClientConfig::builder().with_safe_defaults();
+        Ok(Some(match pins {
+            Some(pins) => config
+                .with_custom_certificate_verifier(Arc::new(PinVerifier(pins.clone())))
+                .with_no_client_auth(),
+            None => {
+                let mut roots = RootCertStore::empty();
+                roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
+                    |root| {
+                        OwnedTrustAnchor::from_subject_spki_name_constraints(
+                            root.subject,
+                            root.spki,
+                            root.name_constraints,
+                        )
+                    },
+                ));
+                for ca in &self.cas {
+                    roots
+                        .add(&Certificate(ca.clone()))
+                        .map_err(|e| anyhow!("invalid CA certificate: {:?}", e))?;
+                }
+                config.with_root_certificates(roots).with_no_client_auth()
+            }
+        }))
+    }
+}
+
+/// Parses the endpoint URL `url`, returning it with its host, without
+/// brackets, and its port.
+fn parse_endpoint(url: &str) -> anyhow::Result<(Url, String, u16)> {
+    let parsed = Url::parse(url).with_context(|| format!("invalid endpoint URL {:?}", url))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| anyhow!("endpoint URL {:?} is missing a host", url))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("endpoint URL {:?} is missing a port", url))?;
+    Ok((parsed, host, port))
+}
+
+/// A TLS handshake with one endpoint.
+#[derive(Clone)]
+pub struct Handshake {
+    connector: TlsConnector,
+    server_name: ServerName,
+}
+
+impl Handshake {
+    /// Secures `stream`, which must be connected to the endpoint.
+    pub async fn secure(&self, stream: TcpStream) -> anyhow::Result<TlsStream<TcpStream>> {
+        self.connector
+            .connect(self.server_name.clone(), stream)
+            .await
+            .with_context(|| format!("TLS handshake with {:?} failed", self.server_name))
+    }
+}
+
+/// Accepts exactly the certificates matching one of its pins, whoever issued
+/// them, and whatever names they are for.
+///
+/// The server must still prove that it holds the certificate's key, which
+/// rustls checks separately.
+struct PinVerifier(Vec<Pin>);
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        if self.0.iter().any(|pin| pin.matches(&end_entity.0)) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            tracing::warn!(
+                presented = ?Pin::spki(&end_entity.0).ok(),
+                expected = ?self.0,
+                "node presented an unpinned certificate"
+            );
+            Err(tokio_rustls::rustls::Error::General(
+                "certificate does not match any pin".to_string(),
+            ))
+        }
+    }
+}
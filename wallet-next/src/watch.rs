@@ -0,0 +1,230 @@
+//! Viewing keys scoped to a single address, for watch-only auditing.
+//!
+//! An [`AddressViewingKey`] lets its holder see the notes sent to one
+//! diversified address of a wallet, e.g. so that a business can let its
+//! accountant monitor a receiving address. It carries no spending authority
+//! and no outgoing viewing key, so the wallet's spends stay private.
+//!
+//! Note that the scope is enforced by the watching wallet, not by the key
+//! itself: every address of a wallet shares one incoming viewing key, so a
+//! holder who extracts it from an address viewing key can detect incoming
+//! notes to the wallet's other addresses too. Share a dedicated account's
+//! address if that matters.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use penumbra_crypto::{
+    ka,
+    keys::{
+        DiversifierIndex, IncomingViewingKey, SpendKey, SpendSeed, DIVERSIFIER_LEN_BYTES,
+        IVK_LEN_BYTES,
+    },
+    Address, Note,
+};
+use penumbra_proto::serializers::bech32str::{self, address_viewing_key::BECH32_PREFIX};
+use sqlx::sqlite::SqlitePool;
+
+use crate::keystore;
+
+/// The name of the keystore secret holding the wallet's spend seed.
+pub const SPEND_SEED_SECRET: &str = "spend_seed";
+
+/// Incoming viewing capability for a single diversified address.
+#[derive(Clone, Debug)]
+pub struct AddressViewingKey {
+    ivk: IncomingViewingKey,
+    index: DiversifierIndex,
+}
+
+impl AddressViewingKey {
+    /// Scope `ivk` to the address with the given diversifier index.
+    pub fn new(ivk: &IncomingViewingKey, index: DiversifierIndex) -> Self {
+        Self {
+            ivk: ivk.clone(),
+            index,
+        }
+    }
+
+    /// The address this key can view.
+    pub fn address(&self) -> Address {
+        self.ivk.payment_address(self.index).0
+    }
+
+    /// Decrypt a note ciphertext, if the note was sent to this key's address.
+    ///
+    /// Notes sent to other addresses of the same wallet are not returned, even
+    /// though they could be decrypted.
+    pub fn decrypt(&self, ciphertext: &[u8], epk: &ka::Public) -> Option<Note> {
+        let note = Note::decrypt(ciphertext, &self.ivk, epk).ok()?;
+        if &note.diversifier() == self.address().diversifier() {
+            Some(note)
+        } else {
+            None
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.ivk.to_bytes().to_vec();
+        bytes.extend_from_slice(&self.index.0);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for AddressViewingKey {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != IVK_LEN_BYTES + DIVERSIFIER_LEN_BYTES {
+            return Err(anyhow!(
+                "address viewing key must be {} bytes, got {}",
+                IVK_LEN_BYTES + DIVERSIFIER_LEN_BYTES,
+                bytes.len()
+            ));
+        }
+        Ok(Self {
+            ivk: bytes[..IVK_LEN_BYTES].try_into()?,
+            index: DiversifierIndex(
+                bytes[IVK_LEN_BYTES..]
+                    .try_into()
+                    .expect("remaining bytes are a diversifier index"),
+            ),
+        })
+    }
+}
+
+impl fmt::Display for AddressViewingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&bech32str::encode(
+            &self.to_bytes(),
+            BECH32_PREFIX,
+            bech32str::Bech32m,
+        ))
+    }
+}
+
+impl FromStr for AddressViewingKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        bech32str::decode(s, BECH32_PREFIX, bech32str::Bech32m)?
+            .as_slice()
+            .try_into()
+    }
+}
+
+/// An address watched through an imported [`AddressViewingKey`].
+#[derive(Clone, Debug)]
+pub struct WatchedAddress {
+    pub id: i64,
+    pub label: String,
+    pub key: AddressViewingKey,
+    pub imported_at: SystemTime,
+}
+
+/// Export a viewing key for the wallet's address with the given index.
+///
+/// This needs the passphrase, since the key is derived from the wallet's
+/// encrypted spend seed.
+pub async fn export(
+    pool: &SqlitePool,
+    passphrase: &str,
+    index: DiversifierIndex,
+) -> anyhow::Result<AddressViewingKey> {
+    let spend_key = spend_key(pool, passphrase).await?;
+
+    Ok(AddressViewingKey::new(
+        spend_key.incoming_viewing_key(),
+        index,
+    ))
+}
+
+/// The wallet's spend key, derived from its encrypted spend seed.
+pub async fn spend_key(pool: &SqlitePool, passphrase: &str) -> anyhow::Result<SpendKey> {
+    let seed = keystore::load_secret(pool, passphrase, SPEND_SEED_SECRET)
+        .await?
+        .ok_or_else(|| anyhow!("wallet has no spend seed"))?;
+    let seed = SpendSeed::try_from(seed.as_slice()).context("invalid spend seed")?;
+    Ok(SpendKey::new(seed))
+}
+
+/// Start watching the address of an imported key, returning its id.
+///
+/// Importing a key that is already watched is an error.
+pub async fn import(
+    pool: &SqlitePool,
+    label: &str,
+    key: &AddressViewingKey,
+) -> anyhow::Result<i64> {
+    let viewing_key = key.to_string();
+    let address = key.address().to_string();
+    let imported_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time travels linearly in a forward direction")
+        .as_millis() as i64;
+
+    let id = sqlx::query!(
+        r#"
+INSERT INTO watched_addresses ( label, viewing_key, address, imported_at )
+VALUES ( ?1, ?2, ?3, ?4 )
+        "#,
+        label,
+        viewing_key,
+        address,
+        imported_at
+    )
+    .execute(pool)
+    .await
+    .context("address is already watched")?
+    .last_insert_rowid();
+
+    tracing::info!(id, %address, "watching address");
+    Ok(id)
+}
+
+/// Every watched address, in the order they were imported.
+pub async fn watched(pool: &SqlitePool) -> anyhow::Result<Vec<WatchedAddress>> {
+    let rows = sqlx::query!(
+        r#"
+SELECT id, label, viewing_key, imported_at
+FROM watched_addresses
+ORDER BY id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(WatchedAddress {
+                id: row.id,
+                label: row.label,
+                key: row.viewing_key.parse().with_context(|| {
+                    format!("invalid viewing key for watched address {}", row.id)
+                })?,
+                imported_at: UNIX_EPOCH + Duration::from_millis(row.imported_at as u64),
+            })
+        })
+        .collect()
+}
+
+/// Stop watching an address, returning whether it was watched.
+pub async fn unwatch(pool: &SqlitePool, id: i64) -> anyhow::Result<bool> {
+    let removed = sqlx::query!(
+        r#"
+DELETE FROM watched_addresses
+WHERE id = ?1
+        "#,
+        id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(removed > 0)
+}
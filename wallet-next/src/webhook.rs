@@ -0,0 +1,504 @@
+//! Signed webhook notifications of payment events.
+//!
+//! Merchants usually want to hear about payments in the systems they already
+//! run, rather than by holding a streaming RPC connection open to the wallet.
+//! [`collect`] turns new [`history`](crate::history) entries into [`Event`]s
+//! (a payment detected, confirmed, or spent) and queues one delivery of each
+//! event per configured webhook URL. [`deliver`] POSTs every due delivery as
+//! JSON, retrying with the same exponential [`backoff`] as broadcasts until
+//! the URL answers with a success status, or [`MAX_ATTEMPTS`] have failed.
+//!
+//! Each request carries the event in a `Penumbra-Event` header, and an
+//! HMAC-SHA256 [`signature`] in a `Penumbra-Signature` header, computed with
+//! the webhook secret over the `Penumbra-Timestamp` header and the body. The
+//! body's `event_id` is stable across retries, so receivers can discard
+//! duplicates.
+
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::sqlite::SqlitePool;
+
+use crate::{broadcast::backoff, history::Category};
+
+/// The number of failed attempts after which a delivery is abandoned: about
+/// eight hours of retries.
+pub const MAX_ATTEMPTS: u64 = 100;
+
+/// How long to wait for a webhook to respond before counting the attempt as
+/// failed.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A payment event reported to webhooks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The wallet detected a payment it received.
+    Detected,
+    /// A received payment was buried under the configured number of blocks.
+    Confirmed,
+    /// The wallet spent funds to pay another party.
+    Spent,
+}
+
+impl Event {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Event::Detected => "payment.detected",
+            Event::Confirmed => "payment.confirmed",
+            Event::Spent => "payment.spent",
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "payment.detected" => Ok(Event::Detected),
+            "payment.confirmed" => Ok(Event::Confirmed),
+            "payment.spent" => Ok(Event::Spent),
+            _ => Err(anyhow!("unknown webhook event {:?}", s)),
+        }
+    }
+}
+
+/// The delivery status of a webhook notification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// Waiting to be delivered, or to be retried.
+    Pending,
+    /// Accepted by the webhook.
+    Delivered,
+    /// Abandoned, either after [`MAX_ATTEMPTS`] or because its URL is no longer
+    /// configured.
+    Failed,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Delivered => "delivered",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "pending" => Ok(Status::Pending),
+            "delivered" => Ok(Status::Delivered),
+            "failed" => Ok(Status::Failed),
+            _ => Err(anyhow!("unknown webhook delivery status {:?}", s)),
+        }
+    }
+}
+
+/// A notification of an event to one webhook URL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delivery {
+    pub url: String,
+    pub event: Event,
+    /// The JSON body POSTed to the URL.
+    pub payload: String,
+    pub status: Status,
+    /// The number of failed attempts to deliver the notification.
+    pub attempts: u64,
+    /// The reason for the last failed attempt.
+    pub last_error: Option<String>,
+}
+
+/// The hex-encoded HMAC-SHA256 signature, under `secret`, of a webhook request
+/// sent at `timestamp` (in Unix seconds) with the given body.
+///
+/// The signed message is the decimal timestamp, a `.`, and the body, so that a
+/// captured request cannot be replayed with a fresh timestamp.
+pub fn signature(secret: &[u8], timestamp: u64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Queue notifications, to each of `urls`, of the payments recorded in the
+/// history since the last call, and of the received payments which are now
+/// `confirmations` blocks below `synced_height`.
+///
+/// Events are generated even when no URLs are configured, so that configuring
+/// a webhook later does not replay old payments.
+pub async fn collect(
+    pool: &SqlitePool,
+    urls: &[String],
+    synced_height: Option<u64>,
+    confirmations: u64,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let cursor = sqlx::query!(
+        r#"
+SELECT last_history_id, confirmed_height
+FROM webhook_cursor
+WHERE id = 0
+        "#
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    let mut events = Vec::new();
+    let mut last_history_id = cursor.last_history_id;
+    let new_entries = sqlx::query!(
+        r#"
+SELECT id, height, block_time, tx_hash, category, denom, amount, memo
+FROM history
+WHERE id > ?1
+ORDER BY id
+        "#,
+        cursor.last_history_id
+    )
+    .fetch_all(&mut tx)
+    .await?;
+    for row in new_entries {
+        last_history_id = row.id;
+        let event = match row.category.parse()? {
+            Category::Receive => Event::Detected,
+            Category::Send => Event::Spent,
+            _ => continue,
+        };
+        events.push((
+            event,
+            json!({
+                "event_id": format!("{}:{}", row.id, event.as_str()),
+                "event": event.as_str(),
+                "height": row.height,
+                "block_time": row.block_time,
+                "tx_hash": hex::encode(&row.tx_hash),
+                "denom": row.denom,
+                "amount": row.amount.to_string(),
+                "memo": row.memo,
+            }),
+        ));
+    }
+
+    // Receipts are confirmed once the wallet has synced `confirmations` blocks
+    // past them.
+    let mut confirmed_height = cursor.confirmed_height;
+    let confirmed_through = synced_height
+        .and_then(|height| height.checked_sub(confirmations))
+        .map(|height| height as i64);
+    if let Some(through) = confirmed_through.filter(|&through| through > confirmed_height) {
+        let receive = Category::Receive.as_str();
+        let confirmed = sqlx::query!(
+            r#"
+SELECT id, height, block_time, tx_hash, denom, amount, memo
+FROM history
+WHERE category = ?1 AND height > ?2 AND height <= ?3 AND id <= ?4
+ORDER BY id
+            "#,
+            receive,
+            confirmed_height,
+            through,
+            last_history_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        for row in confirmed {
+            let event = Event::Confirmed;
+            events.push((
+                event,
+                json!({
+                    "event_id": format!("{}:{}", row.id, event.as_str()),
+                    "event": event.as_str(),
+                    "height": row.height,
+                    "block_time": row.block_time,
+                    "tx_hash": hex::encode(&row.tx_hash),
+                    "denom": row.denom,
+                    "amount": row.amount.to_string(),
+                    "memo": row.memo,
+                    "confirmations": confirmations,
+                }),
+            ));
+        }
+        confirmed_height = through;
+    }
+
+    let now = unix_ms(SystemTime::now());
+    let pending = Status::Pending.as_str();
+    for (event, payload) in &events {
+        let event = event.as_str();
+        let payload = payload.to_string();
+        for url in urls {
+            sqlx::query!(
+                r#"
+INSERT INTO webhook_deliveries
+    ( url, event, payload, status, attempts, created_at, next_attempt_at )
+VALUES ( ?1, ?2, ?3, ?4, 0, ?5, ?5 )
+                "#,
+                url,
+                event,
+                payload,
+                pending,
+                now
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+    }
+
+    sqlx::query!(
+        r#"
+UPDATE webhook_cursor
+SET last_history_id = ?1, confirmed_height = ?2
+WHERE id = 0
+        "#,
+        last_history_id,
+        confirmed_height
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    if !events.is_empty() {
+        tracing::debug!(
+            events = events.len(),
+            urls = urls.len(),
+            "queued webhook events"
+        );
+    }
+    Ok(())
+}
+
+/// Every queued notification, oldest first.
+pub async fn deliveries(pool: &SqlitePool) -> anyhow::Result<Vec<Delivery>> {
+    let rows = sqlx::query!(
+        r#"
+SELECT url, event, payload, status, attempts, last_error
+FROM webhook_deliveries
+ORDER BY id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(Delivery {
+                url: row.url,
+                event: row.event.parse()?,
+                payload: row.payload,
+                status: row.status.parse()?,
+                attempts: row.attempts as u64,
+                last_error: row.last_error,
+            })
+        })
+        .collect()
+}
+
+/// POST every notification whose next attempt is due to its URL, using
+/// `client`, signed with `secret`.
+///
+/// Notifications to URLs which are no longer among `urls` are abandoned.
+pub async fn deliver(
+    pool: &SqlitePool,
+    client: &reqwest::Client,
+    urls: &[String],
+    secret: &[u8],
+) -> anyhow::Result<()> {
+    let now = SystemTime::now();
+    let now_ms = unix_ms(now);
+    let pending = Status::Pending.as_str();
+    let due = sqlx::query!(
+        r#"
+SELECT id, url, event, payload, attempts
+FROM webhook_deliveries
+WHERE status = ?1 AND next_attempt_at <= ?2
+ORDER BY id
+        "#,
+        pending,
+        now_ms
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for row in due {
+        let configured = urls.contains(&row.url);
+        let result = if configured {
+            post(client, &row.url, &row.event, &row.payload, secret).await
+        } else {
+            Err(anyhow!("webhook is no longer configured"))
+        };
+
+        let (status, attempts, next_attempt_at, last_error) = match result {
+            Ok(()) => (Status::Delivered, row.attempts, now_ms, None),
+            Err(e) => {
+                tracing::debug!(url = %row.url, ?e, "could not deliver webhook");
+                let attempts = row.attempts + 1;
+                let status = if attempts as u64 >= MAX_ATTEMPTS || !configured {
+                    Status::Failed
+                } else {
+                    Status::Pending
+                };
+                let next_attempt_at = unix_ms(now + backoff(attempts as u64));
+                (status, attempts, next_attempt_at, Some(e.to_string()))
+            }
+        };
+        let status = status.as_str();
+        sqlx::query!(
+            r#"
+UPDATE webhook_deliveries
+SET status = ?1, attempts = ?2, next_attempt_at = ?3, last_error = ?4
+WHERE id = ?5
+            "#,
+            status,
+            attempts,
+            next_attempt_at,
+            last_error,
+            row.id
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// POST a signed notification, failing unless the webhook answers with a
+/// success status.
+async fn post(
+    client: &reqwest::Client,
+    url: &str,
+    event: &str,
+    payload: &str,
+    secret: &[u8],
+) -> anyhow::Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time travels linearly in a forward direction")
+        .as_secs();
+    client
+        .post(url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header("Content-Type", "application/json")
+        .header("Penumbra-Event", event)
+        .header("Penumbra-Timestamp", timestamp.to_string())
+        .header("Penumbra-Signature", signature(secret, timestamp, payload))
+        .body(payload.to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .expect("time travels linearly in a forward direction")
+        .as_millis() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::wallet_pool;
+
+    /// A URL at which nothing is listening.
+    const UNREACHABLE: &str = "http://127.0.0.1:1/";
+
+    /// Queues a delivery to `url` which is due now, after `attempts` failed
+    /// attempts.
+    async fn queue(pool: &SqlitePool, url: &str, attempts: u64) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO webhook_deliveries \
+             (url, event, payload, status, attempts, created_at, next_attempt_at) \
+             VALUES (?1, 'payment.spent', '{}', 'pending', ?2, 0, 0)",
+        )
+        .bind(url)
+        .bind(attempts as i64)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn signs_the_timestamp_and_body() {
+        let body = r#"{"event":"payment.spent"}"#;
+        assert_eq!(
+            signature(b"secret", 1650000000, body),
+            "af636c3521d8cc96e4dc88bf9dd915a032f41c06d7824469d0588df754302dbe"
+        );
+        assert_ne!(
+            signature(b"secret", 1650000001, body),
+            signature(b"secret", 1650000000, body)
+        );
+        assert_ne!(
+            signature(b"other", 1650000000, body),
+            signature(b"secret", 1650000000, body)
+        );
+    }
+
+    #[test]
+    fn events_and_statuses_round_trip_through_strings() {
+        for event in [Event::Detected, Event::Confirmed, Event::Spent] {
+            assert_eq!(event.as_str().parse::<Event>().unwrap(), event);
+        }
+        for status in [Status::Pending, Status::Delivered, Status::Failed] {
+            assert_eq!(status.as_str().parse::<Status>().unwrap(), status);
+        }
+        assert!("payment.refunded".parse::<Event>().is_err());
+        assert!("lost".parse::<Status>().is_err());
+    }
+
+    #[tokio::test]
+    async fn retries_failed_deliveries_until_the_last_attempt() -> anyhow::Result<()> {
+        let pool = wallet_pool().await?;
+        queue(&pool, UNREACHABLE, 0).await?;
+        queue(&pool, UNREACHABLE, MAX_ATTEMPTS - 1).await?;
+        let urls = [UNREACHABLE.to_string()];
+
+        deliver(&pool, &reqwest::Client::new(), &urls, b"secret").await?;
+        let deliveries = deliveries(&pool).await?;
+        assert_eq!(
+            deliveries
+                .iter()
+                .map(|delivery| (delivery.status, delivery.attempts))
+                .collect::<Vec<_>>(),
+            vec![(Status::Pending, 1), (Status::Failed, MAX_ATTEMPTS)]
+        );
+        assert!(deliveries
+            .iter()
+            .all(|delivery| delivery.last_error.is_some()));
+
+        // The retry waits out its backoff.
+        deliver(&pool, &reqwest::Client::new(), &urls, b"secret").await?;
+        assert_eq!(deliveries(&pool).await?[0].attempts, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn abandons_deliveries_to_unconfigured_urls() -> anyhow::Result<()> {
+        let pool = wallet_pool().await?;
+        queue(&pool, UNREACHABLE, 0).await?;
+
+        deliver(&pool, &reqwest::Client::new(), &[], b"secret").await?;
+        let delivery = deliveries(&pool).await?.remove(0);
+        assert_eq!(delivery.status, Status::Failed);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(
+            delivery.last_error.as_deref(),
+            Some("webhook is no longer configured")
+        );
+
+        Ok(())
+    }
+}
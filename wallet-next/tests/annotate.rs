@@ -0,0 +1,187 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use penumbra_crypto::asset::REGISTRY;
+use penumbra_proto::wallet_next::{
+    annotator_service_server::{AnnotatorService, AnnotatorServiceServer},
+    wallet_service_server::WalletService as _,
+    AnnotateRequest, AnnotateResponse, Annotation as ProtoAnnotation, ListTransactionsRequest,
+    RegisterAnnotatorRequest,
+};
+use penumbra_wallet_next::{
+    annotate::{self, Annotation, MAX_ANNOTATIONS, MAX_CONSECUTIVE_FAILURES},
+    history::{self, Category, Entry, Transaction},
+    testing::wallet_pool,
+    WalletService,
+};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+// Nothing listens on the discard port, so every call fails to connect.
+const UNREACHABLE_ANNOTATOR: &str = "http://127.0.0.1:9";
+
+/// A plugin which matches payments to invoices by their memo, or which
+/// misbehaves as told.
+#[derive(Clone, Copy)]
+enum Plugin {
+    Invoices,
+    Failing,
+    Verbose,
+}
+
+#[tonic::async_trait]
+impl AnnotatorService for Plugin {
+    async fn annotate(
+        &self,
+        request: Request<AnnotateRequest>,
+    ) -> Result<Response<AnnotateResponse>, Status> {
+        let transaction = request.into_inner().transaction.unwrap_or_default();
+        let annotation = |key: &str, value: &str| ProtoAnnotation {
+            annotator: String::new(),
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        let annotations = match self {
+            Plugin::Invoices => transaction
+                .line_items
+                .iter()
+                .filter_map(|item| item.memo.strip_prefix("invoice "))
+                .map(|invoice| annotation("invoice", invoice))
+                .collect(),
+            Plugin::Failing => return Err(Status::internal("exchange rates unavailable")),
+            Plugin::Verbose => (0..=MAX_ANNOTATIONS)
+                .map(|i| annotation(&i.to_string(), ""))
+                .collect(),
+        };
+        Ok(Response::new(AnnotateResponse { annotations }))
+    }
+}
+
+/// Serves the plugin on an ephemeral local port, returning its endpoint.
+async fn serve(plugin: Plugin) -> anyhow::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(
+        Server::builder()
+            .add_service(AnnotatorServiceServer::new(plugin))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    Ok(endpoint)
+}
+
+fn transaction(tx_hash: u8, memo: &str) -> Transaction {
+    let entry = Entry {
+        height: 1,
+        block_time: UNIX_EPOCH + Duration::from_secs(1_650_000_000),
+        tx_hash: vec![tx_hash; 32],
+        category: Category::Receive,
+        denom: REGISTRY.parse_denom("upenumbra").unwrap(),
+        amount: 1_000,
+        credit: true,
+        memo: memo.to_string(),
+    };
+    Transaction {
+        height: entry.height,
+        block_time: entry.block_time,
+        tx_hash: entry.tx_hash.clone(),
+        line_items: vec![entry],
+    }
+}
+
+#[tokio::test]
+async fn records_annotations_despite_failing_plugins() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    annotate::register(&pool, "invoices", &serve(Plugin::Invoices).await?).await?;
+    annotate::register(&pool, "rates", &serve(Plugin::Failing).await?).await?;
+    annotate::register(&pool, "verbose", &serve(Plugin::Verbose).await?).await?;
+    annotate::register(&pool, "gone", UNREACHABLE_ANNOTATOR).await?;
+
+    let invoice = Annotation {
+        annotator: "invoices".to_string(),
+        key: "invoice".to_string(),
+        value: "1042".to_string(),
+    };
+    let annotations = annotate::record(&pool, &transaction(1, "invoice 1042")).await?;
+    assert_eq!(annotations, vec![invoice.clone()]);
+
+    // The transaction is recorded, whatever the plugins did.
+    assert_eq!(history::entries(&pool, 0..=u64::MAX).await?.len(), 1);
+    assert_eq!(
+        annotate::annotations(&pool, &[1; 32]).await?,
+        vec![invoice.clone()]
+    );
+
+    // Each failure is counted against its plugin.
+    for annotator in annotate::annotators(&pool).await? {
+        let failed = annotator.name != "invoices";
+        assert_eq!(annotator.consecutive_failures, failed as u64);
+        assert_eq!(annotator.last_error.is_some(), failed, "{}", annotator.name);
+    }
+
+    // Annotations are listed with the wallet's transactions.
+    let service = WalletService::new(pool.clone());
+    let listed = service
+        .list_transactions(Request::new(ListTransactionsRequest::default()))
+        .await?
+        .into_inner();
+    assert_eq!(listed.transactions[0].annotations, vec![invoice.into()]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn suspends_plugins_until_they_register_again() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    annotate::register(&pool, "rates", UNREACHABLE_ANNOTATOR).await?;
+
+    for tx_hash in 0..MAX_CONSECUTIVE_FAILURES as u8 + 1 {
+        annotate::record(&pool, &transaction(tx_hash, "")).await?;
+    }
+    let annotators = annotate::annotators(&pool).await?;
+    assert!(annotators[0].suspended());
+    // Once suspended, the plugin is no longer called.
+    assert_eq!(annotators[0].consecutive_failures, MAX_CONSECUTIVE_FAILURES);
+
+    annotate::register(&pool, "rates", &serve(Plugin::Invoices).await?).await?;
+    let annotators = annotate::annotators(&pool).await?;
+    assert!(!annotators[0].suspended());
+    assert_eq!(annotators[0].last_error, None);
+
+    assert!(annotate::unregister(&pool, "rates").await?);
+    assert!(annotate::annotators(&pool).await?.is_empty());
+    assert!(!annotate::unregister(&pool, "rates").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_registers_local_plugins() -> anyhow::Result<()> {
+    let service = WalletService::new(wallet_pool().await?);
+    let register = |name: &str, endpoint: &str| {
+        let request = Request::new(RegisterAnnotatorRequest {
+            name: name.to_string(),
+            endpoint: endpoint.to_string(),
+        });
+        let service = service.clone();
+        async move { service.register_annotator(request).await }
+    };
+
+    for endpoint in [
+        "http://127.0.0.1:9000",
+        "http://[::1]:9000",
+        "http://localhost:9000",
+    ] {
+        register("local", endpoint).await?;
+    }
+    for (name, endpoint) in [
+        ("remote", "http://203.0.113.7:9000"),
+        ("remote", "http://plugins.example.com:9000"),
+        ("socket", "unix:///run/plugin.sock"),
+        ("", "http://127.0.0.1:9000"),
+    ] {
+        let status = register(name, endpoint).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument, "{}", endpoint);
+    }
+
+    Ok(())
+}
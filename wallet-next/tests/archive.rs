@@ -0,0 +1,70 @@
+use penumbra_crypto::asset::REGISTRY;
+use penumbra_wallet_next::{
+    archive, keystore,
+    policy::{self, Limits},
+    testing::wallet_pool,
+};
+
+#[tokio::test]
+async fn archive_restores_wallet_on_another_device() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let upenumbra = REGISTRY.parse_denom("upenumbra").unwrap();
+    let limits = Limits {
+        max_per_tx: Some(100),
+        max_per_day: None,
+        approval_threshold: Some(50),
+    };
+    keystore::initialize(&pool, "hunter2").await?;
+    keystore::store_secret(&pool, "hunter2", "seed", b"it's a secret").await?;
+    policy::set_limits(&pool, Some(0), &upenumbra, limits).await?;
+
+    assert!(archive::export(&pool, "wrong").await.is_err());
+    let exported = archive::export(&pool, "hunter2").await?;
+
+    let other = wallet_pool().await?;
+    keystore::initialize(&other, "other").await?;
+    assert!(archive::import(&other, "wrong", &exported).await.is_err());
+    let summary = archive::import(&other, "hunter2", &exported).await?;
+    assert!(summary.rows > 0);
+
+    // The imported wallet uses the exported wallet's passphrase.
+    assert_eq!(
+        keystore::load_secret(&other, "hunter2", "seed").await?,
+        Some(b"it's a secret".to_vec())
+    );
+    assert!(keystore::load_secret(&other, "other", "seed")
+        .await
+        .is_err());
+    assert_eq!(policy::limits(&other, Some(0), &upenumbra).await?, limits);
+
+    // Exports of the same wallet state only differ in their encryption.
+    let reexported = archive::export(&other, "hunter2").await?;
+    let reimported = wallet_pool().await?;
+    assert_eq!(
+        archive::import(&reimported, "hunter2", &reexported).await?,
+        summary
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn tampered_archive_is_rejected() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    keystore::initialize(&pool, "hunter2").await?;
+    let exported = archive::export(&pool, "hunter2").await?;
+
+    for i in [0, 7, 8, exported.len() - 1] {
+        let mut tampered = exported.clone();
+        tampered[i] ^= 1;
+        assert!(archive::import(&pool, "hunter2", &tampered).await.is_err());
+    }
+    assert!(archive::import(&pool, "hunter2", &exported[..20])
+        .await
+        .is_err());
+
+    // A rejected import leaves the wallet untouched.
+    keystore::check_passphrase(&pool, "hunter2").await?;
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use penumbra_proto::{
+    transaction::{Transaction, TransactionBody},
+    Message,
+};
+use penumbra_wallet_next::{
+    broadcast::{self, Status},
+    testing::wallet_pool,
+};
+
+// Nothing listens on the discard port, so every broadcast fails to connect.
+const UNREACHABLE_RPC: &str = "http://127.0.0.1:9";
+
+#[tokio::test]
+async fn queues_while_offline_until_expiry() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let tx = Transaction {
+        body: Some(TransactionBody {
+            expiry_height: 10,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+    .encode_to_vec();
+
+    let tx_hash = broadcast::submit(&pool, &tx).await?;
+    // Resubmitting is idempotent.
+    assert_eq!(broadcast::submit(&pool, &tx).await?, tx_hash);
+
+    // A failed attempt backs the transaction off, so an immediate second pass
+    // does not retry it.
+    let client = reqwest::Client::new();
+    broadcast::process(&pool, &client, UNREACHABLE_RPC, 9).await?;
+    broadcast::process(&pool, &client, UNREACHABLE_RPC, 9).await?;
+    let history = broadcast::history(&pool).await?;
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].tx_hash, tx_hash);
+    assert_eq!(history[0].status, Status::Queued);
+    assert_eq!(history[0].attempts, 1);
+    assert!(history[0].last_error.is_some());
+
+    // Once the wallet has synced the expiry height, the transaction expires.
+    broadcast::process(&pool, &client, UNREACHABLE_RPC, 10).await?;
+    let history = broadcast::history(&pool).await?;
+    assert_eq!(history[0].status, Status::Expired);
+
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use ed25519_consensus::SigningKey;
+use pd::{
+    genesis,
+    testing::{address, delegations, validator},
+};
+use penumbra_crypto::merkle::{self, NoteCommitmentTree, TreeExt};
+use penumbra_proto::client::specific::NctAnchorRequest;
+use penumbra_wallet_next::{
+    checkpoint::{self, RateData, WalletCheckpoint, SIGNATURE_HEADER},
+    reorg,
+    testing::{self, wallet_pool, Node},
+};
+use rand_core::OsRng;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+/// Runs a minimal HTTP server which answers every request with `body`, signed
+/// by `key`, returning its URL.
+async fn provider(key: SigningKey, body: Vec<u8>) -> anyhow::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/checkpoint", listener.local_addr()?);
+    let signature = hex::encode(key.sign(&body).to_bytes());
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let (body, signature) = (body.clone(), signature.clone());
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut line = String::new();
+                while stream.read_line(&mut line).await? > 2 {
+                    line.clear();
+                }
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n{}: {}\r\nconnection: close\r\n\r\n",
+                    body.len(),
+                    SIGNATURE_HEADER,
+                    signature
+                );
+                stream.write_all(head.as_bytes()).await?;
+                stream.write_all(&body).await?;
+                anyhow::Ok(())
+            });
+        }
+    });
+
+    Ok(url)
+}
+
+/// Two nodes with the same genesis, which has one validator with delegations,
+/// so that its note commitment tree is not empty, and the validator's
+/// identity key.
+async fn nodes() -> anyhow::Result<(Node, Node, String)> {
+    let validator = validator("a");
+    let app_state = genesis::AppState {
+        validators: vec![validator.clone()],
+        allocations: delegations(&[validator.clone()], 1_000, address()),
+        ..Default::default()
+    };
+    let (first, second) = (
+        Node::start(app_state.clone()).await?,
+        Node::start(app_state).await?,
+    );
+    Ok((first, second, validator.identity_key.to_string()))
+}
+
+/// A checkpoint of the nodes' genesis, with the tree a wallet syncing it
+/// records.
+async fn genesis_checkpoint(node: &Node, identity_key: &str) -> anyhow::Result<WalletCheckpoint> {
+    let anchor: merkle::Root = node
+        .specific_client()
+        .await?
+        .nct_anchor(NctAnchorRequest {
+            chain_id: String::new(),
+            height: 0,
+        })
+        .await?
+        .into_inner()
+        .try_into()?;
+
+    // Sync leaves the latest block for the next pass, so it stops at genesis.
+    node.append_empty_blocks(1).await?;
+    let scratch = wallet_pool().await?;
+    testing::sync(&scratch, node).await?;
+    let synced = reorg::latest_checkpoint(&scratch).await?.unwrap();
+    assert_eq!(synced.height, 0);
+
+    Ok(WalletCheckpoint {
+        chain_id: String::new(),
+        height: 0,
+        anchor: hex::encode(anchor.to_bytes()),
+        nct: hex::encode(synced.nct),
+        rates: vec![RateData {
+            identity_key: identity_key.to_string(),
+            epoch_index: 0,
+            validator_reward_rate: 0,
+            validator_exchange_rate: 100_000_000,
+        }],
+    })
+}
+
+#[tokio::test]
+async fn starts_sync_from_verified_checkpoint() -> anyhow::Result<()> {
+    let (first, second, validator) = nodes().await?;
+    let key = SigningKey::new(OsRng);
+    let checkpoint = genesis_checkpoint(&first, &validator).await?;
+    let url = provider(key.clone(), serde_json::to_vec(&checkpoint)?).await?;
+
+    let fetched = checkpoint::fetch(&reqwest::Client::new(), &url, &key.verification_key()).await?;
+    assert_eq!(fetched, checkpoint);
+
+    // One node is not enough to trust the anchor.
+    let mut nodes = vec![first.specific_client().await?];
+    assert!(checkpoint::verify(&fetched, &mut nodes, "").await.is_err());
+    nodes.push(second.specific_client().await?);
+    checkpoint::verify(&fetched, &mut nodes, "").await?;
+
+    let pool = wallet_pool().await?;
+    checkpoint::import(&pool, &fetched).await?;
+    assert_eq!(reorg::synced_height(&pool).await?, Some(0));
+    assert_eq!(checkpoint::validator_rates(&pool).await?, checkpoint.rates);
+    assert_eq!(reorg::detect(&pool, &mut nodes[0], "").await?, None);
+
+    // A wallet which has synced anything cannot skip ahead.
+    assert!(checkpoint::import(&pool, &fetched).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_forged_checkpoints() -> anyhow::Result<()> {
+    let (first, second, validator) = nodes().await?;
+    let key = SigningKey::new(OsRng);
+    let checkpoint = genesis_checkpoint(&first, &validator).await?;
+
+    // A checkpoint signed by some other key is rejected...
+    let url = provider(SigningKey::new(OsRng), serde_json::to_vec(&checkpoint)?).await?;
+    let client = reqwest::Client::new();
+    assert!(checkpoint::fetch(&client, &url, &key.verification_key())
+        .await
+        .is_err());
+
+    // ... as is a correctly signed one whose tree does not match its anchor,
+    // or whose anchor or exchange rates the nodes do not report.
+    let mut nodes = vec![
+        first.specific_client().await?,
+        second.specific_client().await?,
+    ];
+    let empty = NoteCommitmentTree::new(0);
+    let forgeries = [
+        WalletCheckpoint {
+            anchor: hex::encode(merkle::Root(Default::default()).to_bytes()),
+            ..checkpoint.clone()
+        },
+        WalletCheckpoint {
+            anchor: hex::encode(empty.root2().to_bytes()),
+            nct: hex::encode(bincode::serialize(&empty)?),
+            ..checkpoint.clone()
+        },
+        WalletCheckpoint {
+            rates: vec![RateData {
+                validator_exchange_rate: 200_000_000,
+                ..checkpoint.rates[0].clone()
+            }],
+            ..checkpoint.clone()
+        },
+        WalletCheckpoint {
+            rates: vec![RateData {
+                epoch_index: 1,
+                ..checkpoint.rates[0].clone()
+            }],
+            ..checkpoint.clone()
+        },
+    ];
+    for forged in forgeries {
+        let url = provider(key.clone(), serde_json::to_vec(&forged)?).await?;
+        let fetched = checkpoint::fetch(&client, &url, &key.verification_key()).await?;
+        assert!(checkpoint::verify(&fetched, &mut nodes, "").await.is_err());
+    }
+
+    Ok(())
+}
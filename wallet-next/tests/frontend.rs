@@ -0,0 +1,107 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use penumbra_crypto::{
+    asset::REGISTRY,
+    keys::{SeedPhrase, SpendKey, SpendSeed},
+};
+use penumbra_proto::wallet_next::{
+    wallet_service_server::WalletService as _, AddressesRequest, AssetBalance, BalanceRequest,
+};
+use penumbra_wallet_next::{
+    history::{self, Category, Entry},
+    keystore,
+    testing::wallet_pool,
+    watch::SPEND_SEED_SECRET,
+    Formatter, WalletService,
+};
+use rand_core::OsRng;
+use tonic::{Code, Request};
+
+#[tokio::test]
+async fn reports_balances_from_history() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let entry = |denom: &str, category, amount, credit| Entry {
+        height: 1,
+        block_time: UNIX_EPOCH + Duration::from_secs(1_650_000_000),
+        tx_hash: vec![0xab; 32],
+        category,
+        denom: REGISTRY.parse_denom(denom).unwrap(),
+        amount,
+        credit,
+        memo: String::new(),
+    };
+    history::record(&pool, &entry("upenumbra", Category::Receive, 1_500, true)).await?;
+    history::record(&pool, &entry("upenumbra", Category::Send, 1_000, false)).await?;
+    history::record(&pool, &entry("upenumbra", Category::Fee, 10, false)).await?;
+    history::record(&pool, &entry("ugm", Category::Receive, 7, true)).await?;
+    history::record(&pool, &entry("ugm", Category::Send, 7, false)).await?;
+
+    let service = WalletService::new(pool);
+    let balances = service
+        .balance(Request::new(BalanceRequest {}))
+        .await?
+        .into_inner()
+        .balances;
+    // Spent-down denominations are omitted.
+    assert_eq!(
+        balances,
+        vec![AssetBalance {
+            denom: "upenumbra".to_string(),
+            amount: 490,
+        }]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lists_addresses() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let seed = SpendSeed::from_seed_phrase(SeedPhrase::generate(OsRng), 0);
+    keystore::initialize(&pool, "hunter2").await?;
+    keystore::store_secret(&pool, "hunter2", SPEND_SEED_SECRET, &seed.0).await?;
+    let ivk = SpendKey::new(seed).incoming_viewing_key().clone();
+    let service = WalletService::new(pool);
+
+    let request = |passphrase: &str, count| {
+        Request::new(AddressesRequest {
+            passphrase: passphrase.to_string(),
+            start_index: 3,
+            count,
+        })
+    };
+    let addresses = service.addresses(request("hunter2", 2)).await?.into_inner();
+    let addresses: Vec<_> = addresses
+        .addresses
+        .into_iter()
+        .map(|address| (address.index, address.address))
+        .collect();
+    assert_eq!(
+        addresses,
+        vec![
+            (3, ivk.payment_address(3u64.into()).0.to_string()),
+            (4, ivk.payment_address(4u64.into()).0.to_string()),
+        ]
+    );
+
+    let status = service.addresses(request("wrong", 2)).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let status = service
+        .addresses(request("hunter2", 1_000_000))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    Ok(())
+}
+
+#[test]
+fn parses_denominated_amounts() {
+    let formatter = Formatter::with_thousands_separator(',');
+    let (amount, denom) = formatter.parse_denominated("1,000.5penumbra").unwrap();
+    assert_eq!(amount, 1_000_500_000);
+    assert_eq!(denom, REGISTRY.parse_denom("upenumbra").unwrap());
+
+    assert!(formatter.parse_denominated("penumbra").is_err());
+    assert!(formatter.parse_denominated("1.5").is_err());
+}
@@ -0,0 +1,102 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use penumbra_crypto::asset::REGISTRY;
+use penumbra_wallet_next::{
+    history::{self, Category, Entry, Format},
+    testing::wallet_pool,
+    Formatter,
+};
+
+#[tokio::test]
+async fn exports_csv_in_display_units() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let upenumbra = REGISTRY.parse_denom("upenumbra").unwrap();
+    let entry = |height, category, amount, credit| Entry {
+        height,
+        block_time: UNIX_EPOCH + Duration::from_secs(1_650_000_000),
+        tx_hash: vec![0xab; 32],
+        category,
+        denom: upenumbra.clone(),
+        amount,
+        credit,
+        memo: "rent, april".to_string(),
+    };
+
+    history::record(&pool, &entry(1, Category::Receive, 1_500_000, true)).await?;
+    history::record(&pool, &entry(2, Category::Send, 1_000_000, false)).await?;
+    history::record(&pool, &entry(2, Category::Fee, 10, false)).await?;
+
+    // Entries outside the range are not exported.
+    let entries = history::entries(&pool, 2..=2).await?;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1], entry(2, Category::Fee, 10, false));
+
+    let csv = history::export(&entries, Format::Csv, &Formatter::default());
+    let tx_hash = "ab".repeat(32);
+    assert_eq!(
+        csv,
+        format!(
+            "height,date,tx_hash,category,amount,unit,memo\n\
+             2,2022-04-15T05:20:00Z,{0},send,-1,penumbra,\"rent, april\"\n\
+             2,2022-04-15T05:20:00Z,{0},fee,-0.00001,penumbra,\"rent, april\"\n",
+            tx_hash
+        )
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn attributes_fees_rewards_and_slashing_as_line_items() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let upenumbra = REGISTRY.parse_denom("upenumbra").unwrap();
+    let entry = |tx: u8, category, amount, credit| Entry {
+        height: 7,
+        block_time: UNIX_EPOCH + Duration::from_secs(1_650_000_000),
+        tx_hash: vec![tx; 32],
+        category,
+        denom: upenumbra.clone(),
+        amount,
+        credit,
+        memo: String::new(),
+    };
+
+    // An undelegation returning 1000 of the 1100 delegated, after a reward of
+    // 50 and a slashing loss of 150, and paying a fee, then another fee.
+    let undelegation = vec![
+        entry(1, Category::Undelegate, 1100, true),
+        entry(1, Category::StakingReward, 50, true),
+        entry(1, Category::SlashLoss, 150, false),
+        entry(1, Category::Fee, 10, false),
+    ];
+    for entry in &undelegation {
+        history::record(&pool, entry).await?;
+    }
+    history::record(&pool, &entry(2, Category::Fee, 5, false)).await?;
+
+    let entries = history::entries(&pool, 0..=u64::MAX).await?;
+    let transactions = history::transactions(entries.clone());
+    assert_eq!(transactions.len(), 2);
+    assert_eq!(transactions[0].tx_hash, vec![1; 32]);
+    assert_eq!(transactions[0].line_items, undelegation);
+    assert_eq!(
+        transactions[1].line_items,
+        vec![entry(2, Category::Fee, 5, false)]
+    );
+
+    let totals: Vec<_> = history::totals(&entries)?
+        .into_iter()
+        .map(|total| (total.category, total.credited, total.debited))
+        .collect();
+    assert_eq!(
+        totals,
+        vec![
+            (Category::Fee, 0, 15),
+            (Category::SlashLoss, 0, 150),
+            (Category::StakingReward, 50, 0),
+            (Category::Undelegate, 1100, 0),
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use penumbra_wallet_next::{maintenance, testing::wallet_pool};
+
+#[tokio::test]
+async fn reclaims_free_pages() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+
+    // Fill a table, then drop it, leaving its pages on the free list.
+    sqlx::query("CREATE TABLE scratch ( data BLOB NOT NULL )")
+        .execute(&pool)
+        .await?;
+    for _ in 0..64 {
+        sqlx::query("INSERT INTO scratch ( data ) VALUES ( zeroblob(4096) )")
+            .execute(&pool)
+            .await?;
+    }
+    sqlx::query("DROP TABLE scratch").execute(&pool).await?;
+
+    // The first run converts the database to incremental auto-vacuum...
+    let report = maintenance::run(&pool).await?;
+    assert!(report.integrity_ok());
+    assert!(report.reclaimed() >= 64 * 4096);
+
+    // ... after which there is nothing left to reclaim.
+    let report = maintenance::run(&pool).await?;
+    assert!(report.integrity_ok());
+    assert_eq!(report.reclaimed(), 0);
+
+    Ok(())
+}
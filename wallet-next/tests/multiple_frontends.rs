@@ -0,0 +1,206 @@
+use penumbra_proto::{
+    transaction::{Transaction, TransactionBody},
+    wallet_next::{
+        wallet_event::Event, wallet_service_server::WalletService as _, CreateDraftRequest,
+        DeleteDraftRequest, GetDraftRequest, ListDraftsRequest, SubmitTransactionRequest,
+        UpdateDraftRequest, WatchHistoryRequest,
+    },
+    Message,
+};
+use penumbra_wallet_next::{
+    drafts::{self, Outcome},
+    events::{EventBus, CLIENT_HEADER},
+    testing::wallet_pool,
+    WalletService,
+};
+use tokio_stream::StreamExt;
+use tonic::{Code, Request};
+
+/// A request from the frontend with the given name.
+fn from<T>(client: &'static str, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert(CLIENT_HEADER, client.parse().unwrap());
+    request
+}
+
+#[tokio::test]
+async fn changes_reach_every_frontend() -> anyhow::Result<()> {
+    let service = WalletService::new(wallet_pool().await?);
+    let mut gui = service
+        .watch_history(from("gui", WatchHistoryRequest { exclude_own: false }))
+        .await?
+        .into_inner();
+    let mut cli = service
+        .watch_history(from("cli", WatchHistoryRequest { exclude_own: true }))
+        .await?
+        .into_inner();
+
+    let tx = Transaction {
+        body: Some(TransactionBody {
+            expiry_height: 10,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    let tx_hash = service
+        .submit_transaction(from("cli", SubmitTransactionRequest { transaction: tx }))
+        .await?
+        .into_inner()
+        .tx_hash;
+
+    // The transaction sent from the CLI shows up in the GUI...
+    let event = gui.next().await.unwrap()?;
+    assert_eq!((event.sequence, event.origin.as_str()), (1, "cli"));
+    match event.event {
+        Some(Event::TransactionSubmitted(submitted)) => {
+            assert_eq!(submitted.tx_hash, tx_hash);
+            assert_eq!(submitted.expiry_height, 10);
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+
+    // ... while the CLI, having asked to leave out its own changes, only sees
+    // the GUI's draft.
+    service
+        .create_draft(from(
+            "gui",
+            CreateDraftRequest {
+                label: "rent".to_string(),
+                contents: b"draft".to_vec(),
+            },
+        ))
+        .await?;
+    for stream in [&mut gui, &mut cli] {
+        let event = stream.next().await.unwrap()?;
+        assert_eq!((event.sequence, event.origin.as_str()), (2, "gui"));
+        match event.event {
+            Some(Event::DraftSaved(draft)) => assert_eq!(draft.label, "rent"),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_writes_to_stale_drafts() -> anyhow::Result<()> {
+    let service = WalletService::new(wallet_pool().await?);
+    let draft = service
+        .create_draft(from(
+            "gui",
+            CreateDraftRequest {
+                label: "rent".to_string(),
+                contents: b"100".to_vec(),
+            },
+        ))
+        .await?
+        .into_inner()
+        .draft
+        .unwrap();
+    assert_eq!(draft.version, 1);
+    let update = |client, expected_version, contents: &[u8]| {
+        service.update_draft(from(
+            client,
+            UpdateDraftRequest {
+                id: draft.id,
+                expected_version,
+                label: "rent".to_string(),
+                contents: contents.to_vec(),
+            },
+        ))
+    };
+
+    // Both frontends opened version 1, but the GUI saves first.
+    let updated = update("gui", 1, b"120").await?.into_inner().draft.unwrap();
+    assert_eq!(updated.version, 2);
+    let status = update("cli", 1, b"90").await.unwrap_err();
+    assert_eq!(status.code(), Code::Aborted);
+
+    let delete = |expected_version| {
+        service.delete_draft(from(
+            "cli",
+            DeleteDraftRequest {
+                id: draft.id,
+                expected_version,
+            },
+        ))
+    };
+    assert_eq!(delete(1).await.unwrap_err().code(), Code::Aborted);
+
+    // The GUI's changes were kept.
+    let get = || service.get_draft(Request::new(GetDraftRequest { id: draft.id }));
+    let current = get().await?.into_inner().draft.unwrap();
+    assert_eq!((current.version, current.contents), (2, b"120".to_vec()));
+
+    delete(2).await?;
+    assert_eq!(get().await.unwrap_err().code(), Code::NotFound);
+    assert_eq!(
+        update("gui", 2, b"120").await.unwrap_err().code(),
+        Code::NotFound
+    );
+    let listed = service
+        .list_drafts(Request::new(ListDraftsRequest {}))
+        .await?
+        .into_inner();
+    assert!(listed.drafts.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_one_concurrent_update_wins() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let id = drafts::create(&pool, "rent", b"100").await?.id;
+
+    let updates = (0..8u8).map(|i| {
+        let pool = pool.clone();
+        tokio::spawn(async move { drafts::update(&pool, id, 1, "rent", &[i]).await })
+    });
+    let mut done = Vec::new();
+    for update in updates.collect::<Vec<_>>() {
+        match update.await?? {
+            Outcome::Done(draft) => done.push(draft),
+            Outcome::Conflict(current) => assert_eq!(current.version, 2),
+            Outcome::NotFound => panic!("draft disappeared"),
+        }
+    }
+    assert_eq!(done.len(), 1);
+    let current = drafts::get(&pool, id).await?.unwrap();
+    assert_eq!(
+        (current.version, current.contents),
+        (2, done[0].contents.clone())
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn tells_lagging_frontends_what_they_missed() -> anyhow::Result<()> {
+    let service = WalletService::new(wallet_pool().await?).with_events(EventBus::with_capacity(2));
+    let mut slow = service
+        .watch_history(Request::new(WatchHistoryRequest::default()))
+        .await?
+        .into_inner();
+
+    for i in 0..5 {
+        service
+            .create_draft(Request::new(CreateDraftRequest {
+                label: i.to_string(),
+                contents: Vec::new(),
+            }))
+            .await?;
+    }
+
+    let event = slow.next().await.unwrap()?;
+    assert_eq!(event.event, Some(Event::Missed(3)));
+    for sequence in 4..=5 {
+        let event = slow.next().await.unwrap()?;
+        assert_eq!(event.sequence, sequence);
+        assert!(matches!(event.event, Some(Event::DraftSaved(_))));
+    }
+
+    Ok(())
+}
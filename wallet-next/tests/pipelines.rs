@@ -0,0 +1,60 @@
+use penumbra_proto::{
+    transaction::{Transaction, TransactionBody},
+    Message,
+};
+use penumbra_wallet_next::{
+    broadcast::{self, Status},
+    reorg,
+    testing::{self, wallet_pool, Node},
+};
+
+fn transaction(expiry_height: u64) -> Vec<u8> {
+    Transaction {
+        body: Some(TransactionBody {
+            expiry_height,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+    .encode_to_vec()
+}
+
+#[tokio::test]
+async fn syncs_and_broadcasts_against_the_node() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    let pool = wallet_pool().await?;
+
+    // The wallet syncs every block but the latest.
+    node.append_empty_blocks(3).await?;
+    testing::sync(&pool, &node).await?;
+    assert_eq!(reorg::synced_height(&pool).await?, Some(2));
+
+    // A transaction which expired by the synced height is never broadcast,
+    // and one the node's mempool rejects is not retried.
+    let expired = broadcast::submit(&pool, &transaction(2)).await?;
+    let rejected = broadcast::submit(&pool, &transaction(0)).await?;
+    testing::broadcast(&pool, &node).await?;
+
+    let expired = broadcast::submitted(&pool, &expired).await?.unwrap();
+    assert_eq!(expired.status, Status::Expired);
+    assert_eq!(expired.attempts, 0);
+    let rejected = broadcast::submitted(&pool, &rejected).await?.unwrap();
+    assert_eq!(rejected.status, Status::Rejected);
+    assert!(
+        rejected
+            .last_error
+            .as_deref()
+            .unwrap()
+            .starts_with("code 1"),
+        "{:?}",
+        rejected.last_error
+    );
+    assert!(node.pending_txs().is_empty());
+
+    // The next pass picks up where the last one stopped.
+    node.append_empty_blocks(2).await?;
+    testing::sync(&pool, &node).await?;
+    assert_eq!(reorg::synced_height(&pool).await?, Some(4));
+
+    Ok(())
+}
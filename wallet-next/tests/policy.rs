@@ -0,0 +1,148 @@
+use penumbra_crypto::asset::REGISTRY;
+use penumbra_wallet_next::{
+    policy::{self, Authorization, Limits, SpendRequest},
+    testing::wallet_pool,
+};
+
+fn spend(account: u64, destination: &str, amount: u64) -> SpendRequest {
+    SpendRequest {
+        account,
+        destination: destination.to_string(),
+        denom: REGISTRY.parse_denom("upenumbra").unwrap(),
+        amount,
+    }
+}
+
+#[tokio::test]
+async fn enforces_per_tx_and_daily_limits() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let upenumbra = REGISTRY.parse_denom("upenumbra").unwrap();
+
+    policy::set_limits(
+        &pool,
+        Some(0),
+        &upenumbra,
+        Limits {
+            max_per_tx: Some(100),
+            max_per_day: Some(250),
+            approval_threshold: None,
+        },
+    )
+    .await?;
+
+    assert!(matches!(
+        policy::authorize(&pool, &spend(0, "alice", 101)).await?,
+        Authorization::Denied { .. }
+    ));
+    assert_eq!(
+        policy::authorize(&pool, &spend(0, "alice", 100)).await?,
+        Authorization::Approved
+    );
+    assert_eq!(
+        policy::authorize(&pool, &spend(0, "alice", 100)).await?,
+        Authorization::Approved
+    );
+    // Only 50 of the daily limit remains.
+    assert!(matches!(
+        policy::authorize(&pool, &spend(0, "alice", 51)).await?,
+        Authorization::Denied { .. }
+    ));
+    assert_eq!(
+        policy::authorize(&pool, &spend(0, "alice", 50)).await?,
+        Authorization::Approved
+    );
+
+    // Other accounts are not limited, but the wallet-wide limit counts them all.
+    assert_eq!(
+        policy::authorize(&pool, &spend(1, "alice", 500)).await?,
+        Authorization::Approved
+    );
+    policy::set_limits(
+        &pool,
+        None,
+        &upenumbra,
+        Limits {
+            max_per_day: Some(1000),
+            ..Default::default()
+        },
+    )
+    .await?;
+    assert!(matches!(
+        policy::authorize(&pool, &spend(1, "alice", 251)).await?,
+        Authorization::Denied { .. }
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn restricts_destinations() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+
+    policy::set_allowed_destinations(&pool, Some(0), &["alice".to_string()]).await?;
+    assert_eq!(
+        policy::authorize(&pool, &spend(0, "alice", 1)).await?,
+        Authorization::Approved
+    );
+    assert!(matches!(
+        policy::authorize(&pool, &spend(0, "bob", 1)).await?,
+        Authorization::Denied { .. }
+    ));
+    assert_eq!(
+        policy::authorize(&pool, &spend(1, "bob", 1)).await?,
+        Authorization::Approved
+    );
+
+    // Lifting the restriction allows any destination again.
+    policy::set_allowed_destinations(&pool, Some(0), &[]).await?;
+    assert_eq!(
+        policy::authorize(&pool, &spend(0, "bob", 1)).await?,
+        Authorization::Approved
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn holds_large_spends_for_approval() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let upenumbra = REGISTRY.parse_denom("upenumbra").unwrap();
+
+    policy::set_limits(
+        &pool,
+        None,
+        &upenumbra,
+        Limits {
+            approval_threshold: Some(10),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    assert_eq!(
+        policy::authorize(&pool, &spend(0, "alice", 10)).await?,
+        Authorization::Approved
+    );
+    let first = match policy::authorize(&pool, &spend(0, "alice", 11)).await? {
+        Authorization::Pending { id, .. } => id,
+        other => panic!("expected a pending spend, got {:?}", other),
+    };
+    let second = match policy::authorize(&pool, &spend(0, "bob", 12)).await? {
+        Authorization::Pending { id, .. } => id,
+        other => panic!("expected a pending spend, got {:?}", other),
+    };
+
+    let pending = policy::pending(&pool).await?;
+    assert_eq!(pending.len(), 2);
+    assert_eq!(pending[0].request, spend(0, "alice", 11));
+
+    assert_eq!(policy::approve(&pool, first).await?, spend(0, "alice", 11));
+    policy::reject(&pool, second).await?;
+    assert!(policy::pending(&pool).await?.is_empty());
+
+    // Neither can be approved or rejected twice.
+    assert!(policy::approve(&pool, first).await.is_err());
+    assert!(policy::reject(&pool, second).await.is_err());
+
+    Ok(())
+}
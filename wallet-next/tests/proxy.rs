@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use penumbra_proto::client::specific::{
+    specific_query_client::SpecificQueryClient, NctAnchorRequest,
+};
+use penumbra_wallet_next::{
+    proxy::{self, Proxy},
+    testing::Node,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Runs a minimal unauthenticated SOCKS5 proxy which only supports `CONNECT`
+/// to domain names, recording each requested domain.
+async fn socks5_proxy() -> anyhow::Result<(String, Arc<Mutex<Vec<String>>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("socks5://{}", listener.local_addr()?);
+    let requested = Arc::new(Mutex::new(Vec::new()));
+
+    let log = requested.clone();
+    tokio::spawn(async move {
+        while let Ok((mut client, _)) = listener.accept().await {
+            let log = log.clone();
+            tokio::spawn(async move {
+                // Greeting: accept "no authentication".
+                let mut header = [0u8; 2];
+                client.read_exact(&mut header).await?;
+                let mut methods = vec![0u8; header[1] as usize];
+                client.read_exact(&mut methods).await?;
+                client.write_all(&[5, 0]).await?;
+
+                // Request: VER CMD RSV ATYP=domain LEN DOMAIN PORT.
+                let mut request = [0u8; 5];
+                client.read_exact(&mut request).await?;
+                assert_eq!(request[3], 3, "host names must not be resolved locally");
+                let mut domain = vec![0u8; request[4] as usize];
+                client.read_exact(&mut domain).await?;
+                let port = client.read_u16().await?;
+                let domain = String::from_utf8(domain)?;
+                log.lock().unwrap().push(domain.clone());
+
+                let mut upstream = TcpStream::connect((domain.as_str(), port)).await?;
+                client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+                tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+                anyhow::Ok(())
+            });
+        }
+    });
+
+    Ok((url, requested))
+}
+
+#[tokio::test]
+async fn connects_through_socks_proxy() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    let (proxy_url, requested) = socks5_proxy().await?;
+    let proxy: Proxy = proxy_url.parse()?;
+
+    // Use a host name, so that we can check it reaches the proxy unresolved.
+    let url = node.url().replace("127.0.0.1", "localhost");
+    let mut client =
+        SpecificQueryClient::new(proxy::connect(Some(&proxy), &Default::default(), &url).await?);
+    client
+        .nct_anchor(NctAnchorRequest {
+            chain_id: String::new(),
+            height: 0,
+        })
+        .await?;
+
+    assert_eq!(*requested.lock().unwrap(), vec!["localhost".to_string()]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn onion_endpoints_require_a_proxy() -> anyhow::Result<()> {
+    let onion = "http://penumbraexampleonionaddressxxxxxxxxxxxxxxxxxxxxxxx.onion:8080";
+    assert!(proxy::is_onion(onion));
+    assert!(proxy::connect(None, &Default::default(), onion)
+        .await
+        .is_err());
+    assert!(proxy::http_client(None, &Default::default(), onion).is_err());
+
+    let proxy: Proxy = "socks5://127.0.0.1:9050".parse()?;
+    assert!(proxy::http_client(Some(&proxy), &Default::default(), onion).is_ok());
+    assert!("http://127.0.0.1:9050".parse::<Proxy>().is_err());
+
+    Ok(())
+}
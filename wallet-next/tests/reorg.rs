@@ -0,0 +1,99 @@
+use pd::{
+    genesis::{self, Allocation},
+    testing::address,
+};
+use penumbra_crypto::merkle;
+use penumbra_proto::client::specific::NctAnchorRequest;
+use penumbra_wallet_next::{
+    progress::ProgressTracker,
+    reorg::{self, Divergence},
+    sync,
+    testing::{wallet_pool, Node},
+};
+
+#[tokio::test]
+async fn recovers_from_rollback() -> anyhow::Result<()> {
+    let node = Node::start(Default::default()).await?;
+    let mut client = node.specific_client().await?;
+    let pool = wallet_pool().await?;
+
+    // Sync genesis for real...
+    let genesis_anchor: merkle::Root = client
+        .nct_anchor(NctAnchorRequest {
+            chain_id: String::new(),
+            height: 0,
+        })
+        .await?
+        .into_inner()
+        .try_into()?;
+    reorg::record_checkpoint(&pool, 0, genesis_anchor, b"genesis").await?;
+    assert_eq!(reorg::detect(&pool, &mut client, "").await?, None);
+
+    // ... then pretend the wallet synced a block the node has since rolled back.
+    reorg::record_checkpoint(&pool, 1, merkle::Root(Default::default()), b"orphaned").await?;
+    let expected = Divergence {
+        last_common_height: Some(0),
+        divergent_height: 1,
+    };
+    assert_eq!(reorg::detect(&pool, &mut client, "").await?, Some(expected));
+    assert_eq!(reorg::divergence(&pool).await?, Some(expected));
+
+    let checkpoint = reorg::recover(&pool).await?.expect("genesis is retained");
+    assert_eq!(checkpoint.height, 0);
+    assert_eq!(checkpoint.nct, b"genesis");
+    assert_eq!(reorg::divergence(&pool).await?, None);
+    assert_eq!(reorg::synced_height(&pool).await?, Some(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn resumes_sync_from_the_restored_tree() -> anyhow::Result<()> {
+    // Genesis notes make the tree's anchors depend on its contents, so only a
+    // correctly restored tree matches the node's again.
+    let node = Node::start(genesis::AppState {
+        allocations: vec![Allocation {
+            amount: 1_000,
+            denom: "upenumbra".to_string(),
+            address: address(),
+        }],
+        ..Default::default()
+    })
+    .await?;
+    node.append_empty_blocks(3).await?;
+    let mut oblivious = node.oblivious_client().await?;
+    let mut specific = node.specific_client().await?;
+    let pool = wallet_pool().await?;
+    let progress = ProgressTracker::default();
+
+    // Sync leaves the latest block for the next pass.
+    sync::sync(&pool, &mut oblivious, "", &progress).await?;
+    assert_eq!(reorg::synced_height(&pool).await?, Some(2));
+    assert_eq!(progress.progress().current_height, 2);
+    assert!(!progress.progress().syncing);
+    assert_eq!(reorg::detect(&pool, &mut specific, "").await?, None);
+
+    // Pretend the wallet synced a block the node has since replaced...
+    reorg::record_checkpoint(&pool, 3, merkle::Root(Default::default()), b"orphaned").await?;
+    let expected = Divergence {
+        last_common_height: Some(2),
+        divergent_height: 3,
+    };
+    assert_eq!(
+        reorg::detect(&pool, &mut specific, "").await?,
+        Some(expected)
+    );
+
+    // ... which stops sync until the wallet recovers.
+    node.append_empty_blocks(2).await?;
+    sync::sync(&pool, &mut oblivious, "", &progress).await?;
+    assert_eq!(reorg::synced_height(&pool).await?, Some(3));
+
+    let checkpoint = reorg::recover(&pool).await?.expect("height 2 is retained");
+    assert_eq!(checkpoint.height, 2);
+    sync::sync(&pool, &mut oblivious, "", &progress).await?;
+    assert_eq!(reorg::synced_height(&pool).await?, Some(4));
+    assert_eq!(reorg::detect(&pool, &mut specific, "").await?, None);
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use penumbra_proto::wallet_next::{
+    wallet_service_server::WalletService as _, WatchSyncProgressRequest,
+};
+use penumbra_wallet_next::{progress::ProgressTracker, testing::wallet_pool, WalletService};
+use tokio_stream::StreamExt;
+use tonic::Request;
+
+#[tokio::test]
+async fn estimates_time_left() -> anyhow::Result<()> {
+    let tracker = ProgressTracker::default();
+    tracker.start(100, 1100);
+    for height in 101..=110 {
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        tracker.record_block(height, 1000);
+    }
+
+    let progress = tracker.progress();
+    assert!(progress.syncing);
+    assert_eq!(progress.current_height, 110);
+    assert_eq!(progress.target_height, 1100);
+    assert_eq!(progress.bytes_downloaded, 10_000);
+    assert!(progress.blocks_per_second > 0.0);
+    assert!(progress.eta.is_some());
+
+    // The chain grows while the wallet catches up.
+    tracker.set_target(1200);
+    tracker.record_block(1200, 1000);
+    assert_eq!(tracker.progress().eta, Some(Duration::ZERO));
+
+    tracker.finish();
+    let progress = tracker.progress();
+    assert!(!progress.syncing);
+    assert_eq!(progress.current_height, 1200);
+    assert_eq!(progress.eta, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn streams_progress_to_frontends() -> anyhow::Result<()> {
+    let tracker = ProgressTracker::default();
+    let service = WalletService::new(wallet_pool().await?).with_sync_progress(tracker.clone());
+    let mut updates = service
+        .watch_sync_progress(Request::new(WatchSyncProgressRequest {}))
+        .await?
+        .into_inner();
+
+    // The current progress is sent straight away...
+    let update = updates.next().await.unwrap()?;
+    assert!(!update.syncing);
+
+    // ... and then each change.
+    tracker.start(0, 10);
+    let update = updates.next().await.unwrap()?;
+    assert!(update.syncing);
+    assert_eq!(update.target_height, 10);
+    assert!(!update.has_eta);
+
+    tracker.record_block(10, 512);
+    let update = updates.next().await.unwrap()?;
+    assert_eq!(update.current_height, 10);
+    assert_eq!(update.bytes_downloaded, 512);
+    assert!(update.has_eta);
+    assert_eq!(update.eta_seconds, 0);
+
+    Ok(())
+}
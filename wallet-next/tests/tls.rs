@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use penumbra_proto::client::specific::{
+    specific_query_client::SpecificQueryClient, specific_query_server::SpecificQueryServer,
+    NctAnchorRequest,
+};
+use penumbra_wallet_next::{
+    proxy,
+    testing::Node,
+    tls::{Pin, Tls},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+
+/// A test CA, not among the web PKI roots.
+const CA_PEM: &str = include_str!("fixtures/tls/ca.pem");
+/// A certificate for `localhost` issued by the test CA, and its key.
+const LEAF_PEM: &str = include_str!("fixtures/tls/localhost.pem");
+const LEAF_KEY_PEM: &str = include_str!("fixtures/tls/localhost.key");
+
+/// A node's specific query service, served over TLS with a certificate for
+/// `localhost` issued by the test CA.
+struct TlsNode {
+    url: String,
+    ca_pem: String,
+    certificate: Vec<u8>,
+    _node: Node,
+}
+
+impl TlsNode {
+    async fn start() -> anyhow::Result<Self> {
+        let node = Node::start(Default::default()).await?;
+        let certificate = rustls_pemfile::certs(&mut LEAF_PEM.as_bytes())?.remove(0);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("https://localhost:{}", listener.local_addr()?.port());
+        let identity = Identity::from_pem(LEAF_PEM, LEAF_KEY_PEM);
+        tokio::spawn(
+            Server::builder()
+                .tls_config(ServerTlsConfig::new().identity(identity))?
+                .add_service(SpecificQueryServer::new(node.storage().clone()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        Ok(Self {
+            url,
+            ca_pem: CA_PEM.to_string(),
+            certificate,
+            _node: node,
+        })
+    }
+
+    fn authority(&self) -> &str {
+        self.url.trim_start_matches("https://")
+    }
+
+    async fn query(&self, tls: &Tls) -> anyhow::Result<()> {
+        let mut client = SpecificQueryClient::new(proxy::connect(None, tls, &self.url).await?);
+        client
+            .nct_anchor(NctAnchorRequest {
+                chain_id: String::new(),
+                height: 0,
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// Serves bare HTTP/1.1 over TLS, with the same certificate as [`TlsNode`],
+/// answering every request with `ok`, as the Tendermint RPC or a checkpoint
+/// provider would. Returns its URL.
+async fn https_server() -> anyhow::Result<String> {
+    let certificates = rustls_pemfile::certs(&mut LEAF_PEM.as_bytes())?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let key = rustls_pemfile::pkcs8_private_keys(&mut LEAF_KEY_PEM.as_bytes())?.remove(0);
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certificates, PrivateKey(key))?;
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("https://localhost:{}/", listener.local_addr()?.port());
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let mut stream = acceptor.accept(stream).await?;
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    request.push(stream.read_u8().await?);
+                }
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok",
+                    )
+                    .await?;
+                stream.shutdown().await
+            });
+        }
+    });
+    Ok(url)
+}
+
+async fn get(tls: &Tls, url: &str) -> anyhow::Result<String> {
+    let client = proxy::http_client(None, tls, url)?;
+    Ok(client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?)
+}
+
+#[tokio::test]
+async fn verifies_node_certificates() -> anyhow::Result<()> {
+    let node = TlsNode::start().await?;
+
+    // The node's CA is not one of the web PKI roots...
+    assert!(node.query(&Tls::default()).await.is_err());
+
+    // ... but it can be trusted explicitly.
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("ca.pem");
+    std::fs::write(&path, &node.ca_pem)?;
+    let mut tls = Tls::default();
+    tls.add_ca_file(&path)?;
+    node.query(&tls).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn accepts_only_pinned_certificates() -> anyhow::Result<()> {
+    let node = TlsNode::start().await?;
+
+    // A pinned certificate is accepted even though its CA is not trusted.
+    for pin in [
+        Pin::spki(&node.certificate)?,
+        Pin::certificate(&node.certificate),
+    ] {
+        let mut tls = Tls::default();
+        tls.add_pin(node.authority(), pin.to_string().parse()?);
+        node.query(&tls).await?;
+    }
+
+    // Any other certificate is refused, even from a trusted CA.
+    let other = Pin::Spki([0; 32]);
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("ca.pem");
+    std::fs::write(&path, &node.ca_pem)?;
+    let mut tls = Tls::default();
+    tls.add_ca_file(&path)?;
+    tls.add_pin(node.authority(), other);
+    assert!(node.query(&tls).await.is_err());
+
+    // Adding the node's pin alongside allows rotating between them.
+    tls.add_pin(node.authority(), Pin::spki(&node.certificate)?);
+    node.query(&tls).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_plaintext_when_secured() -> anyhow::Result<()> {
+    let mut tls = Tls::default();
+    assert!(tls.handshake("http://node.example:8080")?.is_none());
+
+    tls.add_pin("node.example:8080", Pin::Spki([0; 32]));
+    assert!(tls.handshake("http://node.example:8080").is_err());
+    assert!(tls.handshake("http://other.example:8080")?.is_none());
+
+    // Onion services are encrypted by Tor, so are allowed even when TLS is
+    // required.
+    tls.require();
+    assert!(tls.handshake("http://other.example:8080").is_err());
+    let onion = "http://penumbraexampleonionaddressxxxxxxxxxxxxxxxxxxxxxxx.onion:8080";
+    assert!(tls.handshake(onion)?.is_none());
+    assert!(tls.handshake("https://other.example:8080")?.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn http_clients_are_secured_likewise() -> anyhow::Result<()> {
+    let url = https_server().await?;
+    let authority = url.trim_start_matches("https://").trim_end_matches('/');
+    let certificate = rustls_pemfile::certs(&mut LEAF_PEM.as_bytes())?.remove(0);
+
+    // The server's CA is not one of the web PKI roots...
+    assert!(get(&Tls::default(), &url).await.is_err());
+
+    // ... but it can be trusted explicitly.
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("ca.pem");
+    std::fs::write(&path, CA_PEM)?;
+    let mut tls = Tls::default();
+    tls.add_ca_file(&path)?;
+    assert_eq!(get(&tls, &url).await?, "ok");
+
+    // Pins override the trusted CAs.
+    tls.add_pin(authority, Pin::Spki([0; 32]));
+    assert!(get(&tls, &url).await.is_err());
+    let mut tls = Tls::default();
+    tls.add_pin(authority, Pin::spki(&certificate)?);
+    assert_eq!(get(&tls, &url).await?, "ok");
+
+    // Pinned endpoints, and any endpoint when TLS is required, refuse
+    // plaintext.
+    let plaintext = url.replace("https://", "http://");
+    assert!(proxy::http_client(None, &tls, &plaintext).is_err());
+    let mut tls = Tls::default();
+    tls.require();
+    assert!(proxy::http_client(None, &tls, "http://other.example:26657").is_err());
+    assert!(proxy::http_client(None, &tls, "https://other.example:26657").is_ok());
+
+    Ok(())
+}
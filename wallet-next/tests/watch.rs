@@ -0,0 +1,79 @@
+use penumbra_crypto::{
+    asset::REGISTRY,
+    ka,
+    keys::{SeedPhrase, SpendKey, SpendSeed},
+    Note, Value,
+};
+use penumbra_wallet_next::{
+    keystore,
+    testing::wallet_pool,
+    watch::{self, AddressViewingKey, SPEND_SEED_SECRET},
+};
+use rand_core::OsRng;
+
+/// Encrypt a new note to the address of `spend_key` with the given index.
+fn note_to(spend_key: &SpendKey, address_index: u64) -> (Note, Vec<u8>, ka::Public) {
+    let (address, _) = spend_key
+        .incoming_viewing_key()
+        .payment_address(address_index.into());
+    let value = Value {
+        amount: 10,
+        asset_id: REGISTRY.parse_denom("upenumbra").unwrap().id(),
+    };
+    let note = Note::generate(&mut OsRng, &address, value);
+    let esk = ka::Secret::new(&mut OsRng);
+    let epk = esk.diversified_public(&note.diversified_generator());
+    let ciphertext = note.encrypt(&esk).to_vec();
+    (note, ciphertext, epk)
+}
+
+#[tokio::test]
+async fn exported_key_views_only_its_address() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let seed = SpendSeed::from_seed_phrase(SeedPhrase::generate(OsRng), 0);
+    keystore::initialize(&pool, "hunter2").await?;
+    keystore::store_secret(&pool, "hunter2", SPEND_SEED_SECRET, &seed.0).await?;
+    let spend_key = SpendKey::new(seed);
+
+    let key = watch::export(&pool, "hunter2", 1u64.into()).await?;
+    assert_eq!(
+        key.address(),
+        spend_key
+            .incoming_viewing_key()
+            .payment_address(1u64.into())
+            .0
+    );
+    assert!(watch::export(&pool, "wrong", 1u64.into()).await.is_err());
+
+    // The key survives its string encoding.
+    let key = key.to_string().parse::<AddressViewingKey>()?;
+
+    let (note, ciphertext, epk) = note_to(&spend_key, 1);
+    assert_eq!(key.decrypt(&ciphertext, &epk), Some(note));
+    let (_, ciphertext, epk) = note_to(&spend_key, 2);
+    assert_eq!(key.decrypt(&ciphertext, &epk), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn imports_and_unwatches_addresses() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let spend_key = SpendKey::new(SpendSeed::from_seed_phrase(SeedPhrase::generate(OsRng), 0));
+    let key = AddressViewingKey::new(spend_key.incoming_viewing_key(), 7u64.into());
+
+    let id = watch::import(&pool, "receivables", &key).await?;
+    assert!(watch::import(&pool, "again", &key).await.is_err());
+
+    let watched = watch::watched(&pool).await?;
+    assert_eq!(watched.len(), 1);
+    assert_eq!(watched[0].id, id);
+    assert_eq!(watched[0].label, "receivables");
+    assert_eq!(watched[0].key.address(), key.address());
+
+    assert!(watch::unwatch(&pool, id).await?);
+    assert!(!watch::unwatch(&pool, id).await?);
+    assert!(watch::watched(&pool).await?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use penumbra_crypto::asset::REGISTRY;
+use penumbra_wallet_next::{
+    broadcast,
+    history::{self, Category, Entry},
+    testing::wallet_pool,
+    webhook::{self, Event, Status},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+// Nothing listens on the discard port, so every delivery fails to connect.
+const UNREACHABLE_WEBHOOK: &str = "http://127.0.0.1:9/";
+
+const SECRET: &[u8] = b"merchant secret";
+
+/// A request received by the webhook: its headers, lowercased, and its body.
+#[derive(Debug)]
+struct Received {
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Received {
+    fn header(&self, name: &str) -> &str {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    }
+}
+
+/// Runs a minimal HTTP server which answers every request with `200 OK`,
+/// recording each request.
+async fn webhook_server() -> anyhow::Result<(String, Arc<Mutex<Vec<Received>>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/payments", listener.local_addr()?);
+    let received = Arc::new(Mutex::new(Vec::new()));
+
+    let log = received.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let log = log.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut line = String::new();
+                stream.read_line(&mut line).await?;
+                let mut headers = Vec::new();
+                loop {
+                    line.clear();
+                    stream.read_line(&mut line).await?;
+                    match line.trim_end().split_once(": ") {
+                        Some((name, value)) => {
+                            headers.push((name.to_lowercase(), value.to_string()))
+                        }
+                        None => break,
+                    }
+                }
+                let length = headers
+                    .iter()
+                    .find(|(name, _)| name == "content-length")
+                    .map_or(Ok(0), |(_, value)| value.parse())?;
+                let mut body = vec![0u8; length];
+                stream.read_exact(&mut body).await?;
+                log.lock().unwrap().push(Received {
+                    headers,
+                    body: String::from_utf8(body)?,
+                });
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await?;
+                anyhow::Ok(())
+            });
+        }
+    });
+
+    Ok((url, received))
+}
+
+fn entry(height: u64, category: Category, credit: bool) -> Entry {
+    Entry {
+        height,
+        block_time: UNIX_EPOCH + Duration::from_secs(1_650_000_000),
+        tx_hash: vec![height as u8; 32],
+        category,
+        denom: REGISTRY.parse_denom("upenumbra").unwrap(),
+        amount: 1_500_000,
+        credit,
+        memo: "invoice 42".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn delivers_signed_payment_events() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let (url, received) = webhook_server().await?;
+    let urls = vec![url.clone()];
+    let client = reqwest::Client::new();
+
+    // Payments received and confirmed before any webhook is configured are
+    // not replayed.
+    history::record(&pool, &entry(1, Category::Receive, true)).await?;
+    webhook::collect(&pool, &[], Some(3), 2).await?;
+
+    history::record(&pool, &entry(5, Category::Receive, true)).await?;
+    history::record(&pool, &entry(6, Category::Send, false)).await?;
+    history::record(&pool, &entry(6, Category::Fee, false)).await?;
+    webhook::collect(&pool, &urls, Some(6), 2).await?;
+    webhook::deliver(&pool, &client, &urls, SECRET).await?;
+
+    // The receipt at height 5 is confirmed once the wallet syncs height 7.
+    webhook::collect(&pool, &urls, Some(7), 2).await?;
+    webhook::deliver(&pool, &client, &urls, SECRET).await?;
+
+    let deliveries = webhook::deliveries(&pool).await?;
+    assert_eq!(
+        deliveries.iter().map(|d| d.event).collect::<Vec<_>>(),
+        vec![Event::Detected, Event::Spent, Event::Confirmed]
+    );
+    assert!(deliveries.iter().all(|d| d.status == Status::Delivered));
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 3);
+    for (request, delivery) in received.iter().zip(&deliveries) {
+        assert_eq!(request.body, delivery.payload);
+        assert_eq!(request.header("penumbra-event"), delivery.event.as_str());
+        let timestamp = request.header("penumbra-timestamp").parse()?;
+        assert_eq!(
+            request.header("penumbra-signature"),
+            webhook::signature(SECRET, timestamp, &request.body)
+        );
+    }
+
+    let confirmed: serde_json::Value = serde_json::from_str(&received[2].body)?;
+    assert_eq!(confirmed["height"], 5);
+    assert_eq!(confirmed["amount"], "1500000");
+    assert_eq!(confirmed["tx_hash"], hex::encode([5u8; 32]));
+    assert_eq!(confirmed["event_id"], "2:payment.confirmed");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn retries_unreachable_webhooks() -> anyhow::Result<()> {
+    let pool = wallet_pool().await?;
+    let urls = vec![UNREACHABLE_WEBHOOK.to_string()];
+    let client = reqwest::Client::new();
+
+    history::record(&pool, &entry(1, Category::Receive, true)).await?;
+    webhook::collect(&pool, &urls, None, 2).await?;
+
+    // A failed attempt backs the delivery off, so an immediate second pass
+    // does not retry it.
+    webhook::deliver(&pool, &client, &urls, SECRET).await?;
+    webhook::deliver(&pool, &client, &urls, SECRET).await?;
+    let deliveries = webhook::deliveries(&pool).await?;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].status, Status::Pending);
+    assert_eq!(deliveries[0].attempts, 1);
+    assert!(deliveries[0].last_error.is_some());
+
+    // Once the webhook is no longer configured, its deliveries are abandoned.
+    tokio::time::sleep(broadcast::backoff(1) + Duration::from_millis(100)).await;
+    webhook::deliver(&pool, &client, &[], SECRET).await?;
+    let deliveries = webhook::deliveries(&pool).await?;
+    assert_eq!(deliveries[0].status, Status::Failed);
+
+    Ok(())
+}
@@ -737,6 +737,7 @@ impl ClientState {
             height,
             outputs,
             nullifiers,
+            ..
         }: CompactBlock,
     ) -> Result<(), anyhow::Error> {
         // We have to do a bit of a dance to use None as "-1" and handle genesis notes.